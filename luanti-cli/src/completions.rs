@@ -0,0 +1,52 @@
+//! Generation of shell completion scripts from the command line definition
+
+use std::io::Write;
+
+use clap::{Command, ValueEnum};
+
+/// Shells completion scripts can be generated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Shell {
+    /// The `bash` shell
+    Bash,
+    /// The `zsh` shell
+    Zsh,
+    /// The `fish` shell
+    Fish,
+}
+
+impl From<Shell> for clap_complete::Shell {
+    fn from(shell: Shell) -> Self {
+        match shell {
+            Shell::Bash => Self::Bash,
+            Shell::Zsh => Self::Zsh,
+            Shell::Fish => Self::Fish,
+        }
+    }
+}
+
+/// Write a completion script for the given command and all of its subcommands.
+pub(crate) fn generate(shell: Shell, command: &mut Command, output: &mut dyn Write) {
+    let name = command.get_name().to_owned();
+    clap_complete::generate(clap_complete::Shell::from(shell), command, name, output);
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, ValueEnum};
+
+    use super::{Shell, generate};
+    use crate::Args;
+
+    #[test]
+    fn scripts_complete_all_subcommands() {
+        for &shell in Shell::value_variants() {
+            let mut script = Vec::new();
+            generate(shell, &mut Args::command(), &mut script);
+            let script = String::from_utf8_lossy(&script);
+            for subcommand in ["init", "test-vectors", "completions"] {
+                assert!(script.contains(subcommand), "{shell:?} lacks {subcommand}");
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Scaffolding of new world directories
+//!
+//! The generated layout is understood by both this server and the C++ engine.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use log::info;
+
+const WORLD_MT_FILE_NAME: &str = "world.mt";
+const AUTH_FILE_NAME: &str = "auth.txt";
+
+/// Game used if none has been specified
+pub(crate) const DEFAULT_GAME_ID: &str = "devtest";
+/// Port used if none has been specified
+pub(crate) const DEFAULT_PORT: u16 = 30000;
+
+/// Settings for a world to be created
+pub(crate) struct WorldScaffold {
+    /// Directory of the world; must not exist or be empty
+    pub(crate) path: PathBuf,
+    /// Id of the game the world is based on
+    pub(crate) game_id: String,
+    /// Name of the world as shown in the main menu
+    pub(crate) world_name: String,
+    /// Port the server shall listen on
+    pub(crate) port: u16,
+    /// Replace existing files
+    pub(crate) force: bool,
+}
+
+impl WorldScaffold {
+    /// Create all files of the world directory.
+    pub(crate) fn create(&self) -> Result<()> {
+        self.check_target()?;
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+
+        self.write_file(WORLD_MT_FILE_NAME, &self.world_mt())?;
+        self.write_file(super::CONFIG_FILE_NAME, &self.minetest_conf())?;
+        // the plain text auth backend is supported by all engine versions; an empty file
+        // doesn't contain any accounts
+        self.write_file(AUTH_FILE_NAME, "")?;
+
+        info!(
+            "created world '{}' at {}",
+            self.world_name,
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    fn check_target(&self) -> Result<()> {
+        if self.force {
+            return Ok(());
+        }
+        match fs::read_dir(&self.path) {
+            Ok(mut entries) => {
+                if entries.next().is_some() {
+                    bail!(
+                        "{} is not empty (use --force to overwrite)",
+                        self.path.display()
+                    );
+                }
+                Ok(())
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => {
+                Err(error).with_context(|| format!("failed to read {}", self.path.display()))
+            }
+        }
+    }
+
+    fn write_file(&self, name: &str, content: &str) -> Result<()> {
+        let path = self.path.join(name);
+        fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The map backend is left out, as there is no map yet; both this server and the engine create
+    /// an `SQLite` map on first start.
+    fn world_mt(&self) -> String {
+        format!(
+            "gameid = {game_id}\n\
+             world_name = {world_name}\n\
+             player_backend = sqlite3\n\
+             auth_backend = files\n\
+             mod_storage_backend = sqlite3\n\
+             creative_mode = false\n\
+             enable_damage = true\n",
+            game_id = self.game_id,
+            world_name = self.world_name,
+        )
+    }
+
+    fn minetest_conf(&self) -> String {
+        format!(
+            "# generated by luanti-cli\n\
+             server_name = {world_name}\n\
+             default_game = {game_id}\n\
+             port = {port}\n\
+             max_users = 15\n\
+             enable_damage = true\n\
+             creative_mode = false\n\
+             disallow_empty_password = false\n",
+            world_name = self.world_name,
+            game_id = self.game_id,
+            port = self.port,
+        )
+    }
+}
+
+/// Derive a world name from the last component of the path.
+pub(crate) fn world_name_from_path(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || "world".into(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{env, fs};
+
+    use super::{AUTH_FILE_NAME, WORLD_MT_FILE_NAME, WorldScaffold, world_name_from_path};
+
+    #[test]
+    fn worlds_are_created_in_empty_directories_only() {
+        let path = env::temp_dir().join(format!("luanti-cli-init-{}", std::process::id()));
+        let _ignore = fs::remove_dir_all(&path);
+        let mut scaffold = WorldScaffold {
+            path: path.clone(),
+            game_id: "minetest".into(),
+            world_name: world_name_from_path(&path),
+            port: 30001,
+            force: false,
+        };
+        scaffold.create().unwrap();
+
+        let world_mt = fs::read_to_string(path.join(WORLD_MT_FILE_NAME)).unwrap();
+        assert!(world_mt.contains("gameid = minetest\n"));
+        assert!(!world_mt.contains("\nbackend ="));
+        let config = fs::read_to_string(path.join(crate::CONFIG_FILE_NAME)).unwrap();
+        assert!(config.contains("port = 30001\n"));
+        assert_eq!(fs::read_to_string(path.join(AUTH_FILE_NAME)).unwrap(), "");
+
+        scaffold.create().unwrap_err();
+        scaffold.force = true;
+        scaffold.create().unwrap();
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -2,14 +2,24 @@
 //!
 //! The current implementation is an incomplete stub at the moment.
 
-mod config_file;
+mod completions;
+mod init;
+mod test_vectors;
+mod world;
 
 use std::{
-    env,
+    env, io,
     path::{Path, PathBuf},
 };
 
+use clap::{CommandFactory, Parser, Subcommand};
+use completions::Shell;
+use glam::I16Vec3;
+use init::WorldScaffold;
 use log::{LevelFilter, debug, error};
+use luanti_core::MapNodePos;
+use luanti_server::config::StorageBackend;
+use world::Conversion;
 
 const CONFIG_FILE_NAME: &str = "minetest.conf";
 const GAME_CONFIG_FILE_NAME: &str = "game.conf";
@@ -19,11 +29,217 @@ const WORLDS_DIR_NAME: &str = "worlds";
 // further reading:
 // Look into `subgames.cpp/findSubgame` for the search algorithm used by Luanti to find the game.
 
-fn main() {
+/// luanti-cli - command line tools for administering a Luanti server
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a new world directory usable by this server and the C++ engine
+    Init {
+        /// Directory of the new world
+        path: PathBuf,
+
+        /// Id of the game the world is based on
+        #[arg(short, long, default_value = init::DEFAULT_GAME_ID)]
+        game: String,
+
+        /// Name of the world (defaults to the directory name)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Port the server shall listen on
+        #[arg(short, long, default_value_t = init::DEFAULT_PORT)]
+        port: u16,
+
+        /// Overwrite files in a non-empty directory
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+    },
+    /// Maintain an existing world
+    World {
+        #[command(subcommand)]
+        command: WorldCommand,
+    },
+    /// Export canonical serializations of representative protocol commands
+    TestVectors {
+        /// Write one binary file per vector into this directory instead of printing hex lines
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print a shell completion script to stdout
+    Completions {
+        /// The shell to generate the script for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WorldCommand {
+    /// Copy all map blocks of a world into another storage backend
+    Convert {
+        /// Directory of the world to read from
+        source: PathBuf,
+
+        /// Directory of the world to write to
+        target: PathBuf,
+
+        /// Storage backend of the source world (`sqlite3`, `minetestworld` or `dummy`)
+        #[arg(long)]
+        from: StorageBackend,
+
+        /// Storage backend of the target world (`sqlite3` or `dummy`)
+        #[arg(long)]
+        to: StorageBackend,
+
+        /// Don't load every block back from the target to compare it with the source
+        #[arg(long, default_value_t = false)]
+        no_verify: bool,
+    },
+    /// Copy the map of a world into a backup directory; works while a server is using the world
+    Backup {
+        /// Directory of the world
+        world: PathBuf,
+
+        /// Directory to store the backup in
+        target: PathBuf,
+
+        /// Storage backend of the world
+        #[arg(long, default_value_t = StorageBackend::default())]
+        backend: StorageBackend,
+    },
+    /// Write a cuboid region of a world into a `WorldEdit` schematic file (`.we`)
+    Export {
+        /// Directory of the world
+        world: PathBuf,
+
+        /// Schematic file to create
+        file: PathBuf,
+
+        /// One corner of the region (`x,y,z`)
+        #[arg(long, value_parser = parse_node_pos, allow_hyphen_values = true)]
+        min: MapNodePos,
+
+        /// The opposite corner of the region (`x,y,z`)
+        #[arg(long, value_parser = parse_node_pos, allow_hyphen_values = true)]
+        max: MapNodePos,
+
+        /// Storage backend of the world
+        #[arg(long, default_value_t = StorageBackend::default())]
+        backend: StorageBackend,
+    },
+    /// Place the content of a `WorldEdit` schematic file (`.we`) into a world
+    Import {
+        /// Directory of the world
+        world: PathBuf,
+
+        /// Schematic file to read
+        file: PathBuf,
+
+        /// Position of the schematic's origin within the world (`x,y,z`)
+        #[arg(long, value_parser = parse_node_pos, allow_hyphen_values = true)]
+        pos: MapNodePos,
+
+        /// Storage backend of the world (`sqlite3` or `dummy`)
+        #[arg(long, default_value_t = StorageBackend::default())]
+        backend: StorageBackend,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
     env_logger::Builder::from_default_env()
         .filter_level(LevelFilter::Trace)
         .init();
 
+    let args = Args::parse();
+    match args.command {
+        Some(Command::Init {
+            path,
+            game,
+            name,
+            port,
+            force,
+        }) => {
+            let world_name = name.unwrap_or_else(|| init::world_name_from_path(&path));
+            WorldScaffold {
+                path,
+                game_id: game,
+                world_name,
+                port,
+                force,
+            }
+            .create()?;
+        }
+        Some(Command::World {
+            command:
+                WorldCommand::Convert {
+                    source,
+                    target,
+                    from,
+                    to,
+                    no_verify,
+                },
+        }) => {
+            Conversion {
+                source,
+                target,
+                from,
+                to,
+                verify: !no_verify,
+            }
+            .run()?;
+        }
+        Some(Command::World {
+            command:
+                WorldCommand::Backup {
+                    world,
+                    target,
+                    backend,
+                },
+        }) => {
+            world::backup(&world, backend, &target)?;
+        }
+        Some(Command::World {
+            command:
+                WorldCommand::Export {
+                    world,
+                    file,
+                    min,
+                    max,
+                    backend,
+                },
+        }) => {
+            world::export_region(&world, backend, min, max, &file)?;
+        }
+        Some(Command::World {
+            command:
+                WorldCommand::Import {
+                    world,
+                    file,
+                    pos,
+                    backend,
+                },
+        }) => {
+            world::import_region(&world, backend, &file, pos)?;
+        }
+        Some(Command::TestVectors { output }) => {
+            test_vectors::export(output.as_deref())?;
+        }
+        Some(Command::Completions { shell }) => {
+            completions::generate(shell, &mut Args::command(), &mut io::stdout().lock());
+        }
+        None => show_installation(),
+    }
+
+    Ok(())
+}
+
+fn show_installation() {
     // minetest.conf
     // server.conf
     // mod.conf
@@ -59,6 +275,21 @@ fn find_install_config() -> Option<PathBuf> {
     }
 }
 
+/// Parses a node position given as `x,y,z`.
+fn parse_node_pos(text: &str) -> Result<MapNodePos, String> {
+    let coordinates = text
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<i16>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| format!("invalid coordinate in `{text}`: {error}"))?;
+    let [x, y, z] = coordinates.as_slice() else {
+        return Err(format!(
+            "expected three coordinates (`x,y,z`) but got `{text}`"
+        ));
+    };
+    Ok(MapNodePos(I16Vec3::new(*x, *y, *z)))
+}
+
 fn check_installation_root(path: &Path) -> Result<PathBuf, String> {
     let config_path = path.join(CONFIG_FILE_NAME);
     if !config_path.is_file() {
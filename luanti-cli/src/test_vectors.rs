@@ -0,0 +1,43 @@
+//! Export of protocol test vectors
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use log::info;
+use luanti_protocol::test_vectors;
+
+/// Write all test vectors either as binary files into `output` or as hex lines to stdout.
+///
+/// Files are named `<output>/<protocol version>/<direction>/<name>.bin`.
+pub(crate) fn export(output: Option<&Path>) -> Result<()> {
+    let vectors = test_vectors::generate()?;
+
+    let Some(output) = output else {
+        let mut stdout = io::stdout().lock();
+        for vector in &vectors {
+            writeln!(stdout, "{vector}")?;
+        }
+        return Ok(());
+    };
+
+    for vector in &vectors {
+        let dir = output
+            .join(vector.protocol_version.to_string())
+            .join(vector.direction_name());
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let path = dir.join(format!("{}.bin", vector.name));
+        fs::write(&path, &vector.bytes)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    info!(
+        "exported {} test vectors to {}",
+        vectors.len(),
+        output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! Maintenance of existing worlds
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use luanti_core::MapNodePos;
+use luanti_server::{
+    config::StorageBackend,
+    world::{
+        content_id_mapper::ContentIdMapper, editor::WorldEditor, storage::WorldStorage,
+        worldedit::Schematic,
+    },
+};
+use tokio::runtime::Runtime;
+
+/// Progress is being reported this often
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings for copying the map of a world into another storage backend
+pub(crate) struct Conversion {
+    /// Directory of the world to read from
+    pub(crate) source: PathBuf,
+    /// Directory of the world to write to
+    pub(crate) target: PathBuf,
+    /// Storage backend of the source world
+    pub(crate) from: StorageBackend,
+    /// Storage backend of the target world
+    pub(crate) to: StorageBackend,
+    /// Load every block back from the target and compare its data to the source
+    pub(crate) verify: bool,
+}
+
+impl Conversion {
+    /// Streams all map blocks from the source into the target.
+    pub(crate) fn run(&self) -> Result<()> {
+        if self.to == StorageBackend::Minetestworld {
+            bail!("the {} backend cannot store blocks", self.to);
+        }
+        fs::create_dir_all(&self.target)
+            .with_context(|| format!("failed to create {}", self.target.display()))?;
+        if fs::canonicalize(&self.source)? == fs::canonicalize(&self.target)? {
+            bail!("source and target must be different directories");
+        }
+
+        // the storages need to be opened within a runtime but run their own one afterwards
+        let runtime = Runtime::new()?;
+
+        // the target keeps the ids being assigned while reading the source
+        let content_ids = Arc::new(ContentIdMapper::open(&self.target)?);
+        let source = runtime.block_on(self.from.open(&self.source, Arc::clone(&content_ids)))?;
+        let mut target = runtime.block_on(self.to.open(&self.target, content_ids))?;
+        self.copy_blocks(source.as_ref(), target.as_mut())
+    }
+
+    fn copy_blocks(&self, source: &dyn WorldStorage, target: &mut dyn WorldStorage) -> Result<()> {
+        let positions = source.block_positions()?;
+        let total = positions.len();
+        info!(
+            "converting {total} blocks from {} ({}) to {} ({})",
+            self.source.display(),
+            self.from,
+            self.target.display(),
+            self.to
+        );
+
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut mismatches = 0_usize;
+        for (done, pos) in positions.into_iter().enumerate() {
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                info!("converted {done}/{total} blocks");
+                last_report = Instant::now();
+            }
+
+            // all backends store blocks in the engine's format, so they are copied as-is to keep
+            // e.g. static objects and node timers which a `WorldBlock` doesn't know about
+            let Some(data) = source.load_serialized_block(pos)? else {
+                warn!("map block {pos} disappeared during the conversion");
+                continue;
+            };
+            target.store_serialized_block(pos, &data)?;
+
+            if self.verify && target.load_serialized_block(pos)?.as_ref() != Some(&data) {
+                warn!("map block {pos} differs after the conversion");
+                mismatches += 1;
+            }
+        }
+
+        let elapsed = start.elapsed();
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "the rate is only an estimation for humans"
+        )]
+        let rate = total as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        info!(
+            "converted {total} blocks in {:.1}s ({rate:.0} blocks/s)",
+            elapsed.as_secs_f64()
+        );
+
+        if mismatches > 0 {
+            bail!("{mismatches} of {total} blocks differ after the conversion");
+        }
+        if self.verify {
+            info!("all blocks have been verified");
+        }
+        Ok(())
+    }
+}
+
+/// Writes the cuboid between `min` and `max` into a `WorldEdit` schematic file.
+pub(crate) fn export_region(
+    world: &Path,
+    backend: StorageBackend,
+    min: MapNodePos,
+    max: MapNodePos,
+    file: &Path,
+) -> Result<()> {
+    // the storages need to be opened within a runtime but run their own one afterwards
+    let runtime = Runtime::new()?;
+    let content_ids = Arc::new(ContentIdMapper::open(world)?);
+    let mut storage = runtime.block_on(backend.open(world, Arc::clone(&content_ids)))?;
+    let mut editor = WorldEditor::new(storage.as_mut(), content_ids);
+
+    let schematic = Schematic::read_region(&mut editor, min, max)?;
+    fs::write(file, schematic.to_string())
+        .with_context(|| format!("failed to write {}", file.display()))?;
+    info!(
+        "exported {} nodes into {}",
+        schematic.nodes.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+/// Places the content of a `WorldEdit` schematic file into a world with its origin at `origin`.
+pub(crate) fn import_region(
+    world: &Path,
+    backend: StorageBackend,
+    file: &Path,
+    origin: MapNodePos,
+) -> Result<()> {
+    if backend == StorageBackend::Minetestworld {
+        bail!("the {backend} backend cannot store blocks");
+    }
+    let text =
+        fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?;
+    let schematic =
+        Schematic::parse(&text).with_context(|| format!("failed to parse {}", file.display()))?;
+
+    let runtime = Runtime::new()?;
+    let content_ids = Arc::new(ContentIdMapper::open(world)?);
+    let mut storage = runtime.block_on(backend.open(world, Arc::clone(&content_ids)))?;
+    let mut editor = WorldEditor::new(storage.as_mut(), content_ids);
+
+    schematic.write_region(&mut editor, origin)?;
+    let blocks = editor.flush()?;
+    info!(
+        "imported {} nodes into {blocks} map blocks of {}",
+        schematic.nodes.len(),
+        world.display()
+    );
+    Ok(())
+}
+
+/// Creates a snapshot of the map of a world in `target`.
+///
+/// A running server may still hold modified map blocks in memory which aren't part of the
+/// snapshot; the periodic backups of the server don't have this limitation.
+pub(crate) fn backup(world: &Path, backend: StorageBackend, target: &Path) -> Result<()> {
+    // the storages need to be opened within a runtime but run their own one afterwards
+    let runtime = Runtime::new()?;
+    // the snapshot contains the content ids of the world as well
+    let content_ids = Arc::new(ContentIdMapper::open(world)?);
+    let storage = runtime.block_on(backend.open(world, content_ids))?;
+    let start = Instant::now();
+    storage.snapshot(target)?;
+    info!(
+        "created a backup of {} in {} within {:.1}s",
+        world.display(),
+        target.display(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
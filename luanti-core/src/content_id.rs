@@ -34,6 +34,24 @@ impl ContentId {
     /// Doesn't create faces with anything and is considered being
     /// out-of-map in the game map.
     pub const IGNORE: Self = Self(127);
+
+    /// Returns `true` if this is [`Self::AIR`].
+    #[must_use]
+    pub const fn is_air(self) -> bool {
+        self.0 == Self::AIR.0
+    }
+
+    /// Returns `true` if this is [`Self::IGNORE`], i.e. the node hasn't been generated or loaded.
+    #[must_use]
+    pub const fn is_ignore(self) -> bool {
+        self.0 == Self::IGNORE.0
+    }
+
+    /// Returns `true` if this is [`Self::UNKNOWN`].
+    #[must_use]
+    pub const fn is_unknown(self) -> bool {
+        self.0 == Self::UNKNOWN.0
+    }
 }
 
 impl From<ContentId> for usize {
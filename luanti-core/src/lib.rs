@@ -3,7 +3,11 @@
 mod content_id;
 mod map_block;
 mod map_node;
+mod param2;
+mod region;
 
 pub use content_id::*;
 pub use map_block::*;
 pub use map_node::*;
+pub use param2::*;
+pub use region::*;
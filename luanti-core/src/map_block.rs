@@ -8,9 +8,10 @@ use std::{
 use glam::{I16Vec3, UVec3};
 
 use crate::map_node::{MapNode, MapNodeIndex, MapNodePos};
+use crate::region::Region;
 
 /// Contains all `MapNodes` of a single map block.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct MapBlockNodes(pub [MapNode; MapBlockPos::NODE_COUNT as usize]);
 
 impl Index<MapNodeIndex> for MapBlockNodes {
@@ -119,6 +120,36 @@ impl MapBlockPos {
     pub fn node_pos(self, index: MapNodeIndex) -> MapNodePos {
         MapNodePos(MapNodePos::from(self).0 + UVec3::from(index).as_i16vec3())
     }
+
+    /// Returns the region of all map nodes within this map block.
+    #[must_use]
+    pub fn nodes(self) -> Region {
+        Region::new(
+            self.node_pos(MapNodeIndex::MIN).0,
+            self.node_pos(MapNodeIndex::MAX).0,
+        )
+    }
+
+    /// Returns all map blocks reaching `radius` blocks into each direction of this one, including
+    /// itself. Map blocks outside of the world are skipped.
+    pub fn blocks_in_radius(self, radius: u16) -> impl Iterator<Item = Self> {
+        Self::blocks_within(Region::around(self.0, radius))
+    }
+
+    /// Returns all map blocks containing at least one node of the given region of nodes.
+    pub fn blocks_in_region(nodes: Region) -> impl Iterator<Item = Self> {
+        nodes.blocks().into_iter().map(Self)
+    }
+
+    /// Returns all map blocks within the given region of map block positions. Map blocks outside
+    /// of the world are skipped.
+    pub fn blocks_within(blocks: Region) -> impl Iterator<Item = Self> {
+        blocks
+            .intersection(&Region::new(Self::MIN.0, Self::MAX.0))
+            .into_iter()
+            .flatten()
+            .map(Self)
+    }
 }
 
 impl Display for MapBlockPos {
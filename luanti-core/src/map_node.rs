@@ -1,12 +1,16 @@
 //! Contains a single `MapNode` which is the fundamental building block (voxel, cube) of a Luanti
 //! world.
 
-use crate::{content_id::ContentId, map_block::MapBlockPos};
+use crate::{
+    content_id::ContentId,
+    map_block::MapBlockPos,
+    param2::{FACEDIR_MASK, FaceDir, WALLMOUNTED_MASK, WallMounted},
+};
 use glam::{I16Vec3, U8Vec3, U16Vec3, UVec3};
 
 /// Masks for MapNode.param2 of flowing liquids
 pub(crate) const LIQUID_LEVEL_MASK: u8 = 0x07;
-// pub(crate) const LIQUID_FLOW_DOWN_MASK: u8 = 0x08;
+pub(crate) const LIQUID_FLOW_DOWN_MASK: u8 = 0x08;
 
 /// maximum amount of liquid in a block
 pub(crate) const LIQUID_LEVEL_MAX: u8 = LIQUID_LEVEL_MASK;
@@ -20,6 +24,37 @@ pub(crate) const LEVELED_MASK: u8 = 0x7F;
 /// maximum value for the level parameter of a node
 pub const LEVELED_MAX: u8 = LEVELED_MASK;
 
+/// Number of bits occupied by the light level of a single bank within param1
+const LIGHT_BITS: u32 = 4;
+/// mask for a single light bank after it has been aligned towards the least significant bit
+const LIGHT_MASK: u8 = (1 << LIGHT_BITS) - 1;
+/// the brightest light level a light source may emit
+pub const LIGHT_MAX: u8 = 14;
+/// light level of direct sunlight; only ever found in the day bank
+pub const LIGHT_SUN: u8 = LIGHT_MASK;
+
+/// One of the two light levels stored in `MapNode::param1` of light-propagating nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LightBank {
+    /// light level at full daylight; stored in the lower nibble
+    Day,
+    /// light level at night, i.e. only caused by light sources; stored in the upper nibble
+    Night,
+}
+
+impl LightBank {
+    /// Both banks in storage order
+    pub const ALL: [Self; 2] = [Self::Day, Self::Night];
+
+    /// Bit index of the bank within param1
+    const fn shift(self) -> u32 {
+        match self {
+            Self::Day => 0,
+            Self::Night => LIGHT_BITS,
+        }
+    }
+}
+
 /// A single map node with its parameters.
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct MapNode {
@@ -31,6 +66,107 @@ pub struct MapNode {
     pub param2: u8,
 }
 
+impl MapNode {
+    /// Combines the light levels of both banks into a value suitable for param1.
+    ///
+    /// Excessive bits of either level get discarded.
+    #[must_use]
+    pub const fn pack_light(day: u8, night: u8) -> u8 {
+        ((day & LIGHT_MASK) << LightBank::Day.shift())
+            | ((night & LIGHT_MASK) << LightBank::Night.shift())
+    }
+
+    /// Returns the light level of the given bank.
+    #[must_use]
+    pub const fn light(&self, bank: LightBank) -> u8 {
+        (self.param1 >> bank.shift()) & LIGHT_MASK
+    }
+
+    /// Replaces the light level of the given bank, leaving the other bank untouched.
+    pub fn set_light(&mut self, bank: LightBank, level: u8) {
+        let shift = bank.shift();
+        self.param1 = (self.param1 & !(LIGHT_MASK << shift)) | ((level & LIGHT_MASK) << shift);
+    }
+
+    /// Returns the light levels of the day and night bank.
+    #[must_use]
+    pub const fn lights(&self) -> (u8, u8) {
+        (self.light(LightBank::Day), self.light(LightBank::Night))
+    }
+
+    /// Replaces the light levels of both banks.
+    pub fn set_lights(&mut self, day: u8, night: u8) {
+        self.param1 = Self::pack_light(day, night);
+    }
+
+    /// Returns whether the node is lit differently at day and at night.
+    #[must_use]
+    pub const fn day_night_differs(&self) -> bool {
+        self.light(LightBank::Day) != self.light(LightBank::Night)
+    }
+
+    /// Merges the light of `other` into this node by keeping the brighter level of each bank.
+    pub fn merge_light(&mut self, other: &Self) {
+        for bank in LightBank::ALL {
+            let level = self.light(bank).max(other.light(bank));
+            self.set_light(bank, level);
+        }
+    }
+
+    /// Interpolates between the night and the day bank.
+    ///
+    /// `daylight` ranges from `0` (night) to `1000` (full day), matching the day-night ratio
+    /// used by Luanti. Larger values are clamped.
+    #[must_use]
+    pub fn blend_light(&self, daylight: u16) -> u8 {
+        let daylight = daylight.min(1000);
+        let (day, night) = self.lights();
+        let blended = (u16::from(day) * daylight + u16::from(night) * (1000 - daylight)) / 1000;
+        // the blended value lies between two 4-bit values
+        u8::try_from(blended).unwrap_or(LIGHT_MASK)
+    }
+
+    /// Returns the orientation of a node using one of the `facedir` or `4dir` param2 types.
+    #[must_use]
+    pub const fn facedir(&self) -> FaceDir {
+        FaceDir::from_param2(self.param2)
+    }
+
+    /// Replaces the orientation of a `facedir` node, keeping the color of `colorfacedir`.
+    pub fn set_facedir(&mut self, facedir: FaceDir) {
+        self.param2 = (self.param2 & !FACEDIR_MASK) | facedir.value();
+    }
+
+    /// Returns the direction a node using one of the `wallmounted` param2 types is attached to.
+    #[must_use]
+    pub const fn wallmounted(&self) -> WallMounted {
+        WallMounted::from_param2(self.param2)
+    }
+
+    /// Replaces the direction of a `wallmounted` node, keeping the color of `colorwallmounted`.
+    pub fn set_wallmounted(&mut self, wallmounted: WallMounted) {
+        self.param2 = (self.param2 & !WALLMOUNTED_MASK) | wallmounted.value();
+    }
+
+    /// Returns the amount of liquid of a flowing liquid; `0` to [`LIQUID_LEVEL_SOURCE`]` - 1`.
+    #[must_use]
+    pub const fn liquid_level(&self) -> u8 {
+        self.param2 & LIQUID_LEVEL_MASK
+    }
+
+    /// Returns `true` if a flowing liquid is falling down.
+    #[must_use]
+    pub const fn is_flowing_down(&self) -> bool {
+        self.param2 & LIQUID_FLOW_DOWN_MASK != 0
+    }
+
+    /// Returns the level of a `leveled` node; `0` to [`LEVELED_MAX`].
+    #[must_use]
+    pub const fn leveled(&self) -> u8 {
+        self.param2 & LEVELED_MASK
+    }
+}
+
 /// The coordinates of a single node within the world
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct MapNodePos(pub I16Vec3);
@@ -137,3 +273,44 @@ impl From<u16> for MapNodeIndex {
         Self(value & MapBlockPos::NODE_COUNT_MASK)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LIGHT_MAX, LIGHT_SUN, LightBank, MapNode};
+
+    #[test]
+    fn light_banks() {
+        let mut node = MapNode::default();
+        node.set_light(LightBank::Day, LIGHT_SUN);
+        node.set_light(LightBank::Night, 3);
+        assert_eq!(node.param1, 0x3f);
+        assert_eq!(node.lights(), (LIGHT_SUN, 3));
+        assert!(node.day_night_differs());
+
+        // excessive bits must not leak into the other bank
+        node.set_light(LightBank::Day, 0xf0 | 2);
+        assert_eq!(node.lights(), (2, 3));
+
+        node.set_lights(LIGHT_MAX, LIGHT_MAX);
+        assert_eq!(node.param1, MapNode::pack_light(LIGHT_MAX, LIGHT_MAX));
+        assert!(!node.day_night_differs());
+    }
+
+    #[test]
+    fn merge_and_blend() {
+        let mut node = MapNode {
+            param1: MapNode::pack_light(LIGHT_SUN, 2),
+            ..MapNode::default()
+        };
+        node.merge_light(&MapNode {
+            param1: MapNode::pack_light(4, 9),
+            ..MapNode::default()
+        });
+        assert_eq!(node.lights(), (LIGHT_SUN, 9));
+
+        assert_eq!(node.blend_light(0), 9);
+        assert_eq!(node.blend_light(500), 12);
+        assert_eq!(node.blend_light(1000), LIGHT_SUN);
+        assert_eq!(node.blend_light(u16::MAX), LIGHT_SUN);
+    }
+}
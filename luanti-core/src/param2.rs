@@ -0,0 +1,211 @@
+//! Interpretations of `MapNode::param2` which describe the orientation of a node
+//!
+//! Which interpretation applies depends on the `param_type_2` of the node's definition.
+
+use glam::{I16Vec3, Mat3};
+
+/// mask for the facedir within param2; the remaining bits hold the color of `colorfacedir`
+pub(crate) const FACEDIR_MASK: u8 = 0x1F;
+/// number of valid facedir values
+const FACEDIR_COUNT: u8 = 24;
+/// mask for the wallmounted direction within param2; the remaining bits hold the color of
+/// `colorwallmounted`
+pub(crate) const WALLMOUNTED_MASK: u8 = 0x07;
+
+/// The direction the top of a node points to, indexed by [`FaceDir::axis`]
+const FACEDIR_AXES: [I16Vec3; 6] = [
+    I16Vec3::Y,
+    I16Vec3::Z,
+    I16Vec3::NEG_Z,
+    I16Vec3::X,
+    I16Vec3::NEG_X,
+    I16Vec3::NEG_Y,
+];
+
+/// The direction the front of a node points to, matching `facedir_dirs` of Luanti
+const FACEDIR_FACING: [I16Vec3; FACEDIR_COUNT as usize] = [
+    // top pointing up
+    I16Vec3::Z,
+    I16Vec3::X,
+    I16Vec3::NEG_Z,
+    I16Vec3::NEG_X,
+    // top pointing towards +Z
+    I16Vec3::NEG_Y,
+    I16Vec3::X,
+    I16Vec3::Y,
+    I16Vec3::NEG_X,
+    // top pointing towards -Z
+    I16Vec3::Y,
+    I16Vec3::X,
+    I16Vec3::NEG_Y,
+    I16Vec3::NEG_X,
+    // top pointing towards +X
+    I16Vec3::Z,
+    I16Vec3::NEG_Y,
+    I16Vec3::NEG_Z,
+    I16Vec3::Y,
+    // top pointing towards -X
+    I16Vec3::Z,
+    I16Vec3::Y,
+    I16Vec3::NEG_Z,
+    I16Vec3::NEG_Y,
+    // top pointing down
+    I16Vec3::Z,
+    I16Vec3::NEG_X,
+    I16Vec3::NEG_Z,
+    I16Vec3::X,
+];
+
+/// The direction a node is attached to, matching `wallmounted_dirs` of Luanti
+const WALLMOUNTED_DIRS: [I16Vec3; 8] = [
+    I16Vec3::Y,
+    I16Vec3::NEG_Y,
+    I16Vec3::X,
+    I16Vec3::NEG_X,
+    I16Vec3::Z,
+    I16Vec3::NEG_Z,
+    // ceiling and floor, but rotated by 90°
+    I16Vec3::Y,
+    I16Vec3::NEG_Y,
+];
+
+/// One of the 24 orientations of a `facedir` node
+///
+/// The value combines the direction the top of the node points to ([`Self::axis`]) with one of
+/// four rotations around it ([`Self::rotation`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FaceDir(u8);
+
+impl FaceDir {
+    /// Interprets the lower five bits of param2 just like Luanti; out-of-range values wrap.
+    #[must_use]
+    pub const fn from_param2(param2: u8) -> Self {
+        Self((param2 & FACEDIR_MASK) % FACEDIR_COUNT)
+    }
+
+    /// Combines an axis (`0..6`) with a rotation around it (`0..4`); excessive values wrap.
+    #[must_use]
+    pub const fn new(axis: u8, rotation: u8) -> Self {
+        Self((axis % 6) * 4 + rotation % 4)
+    }
+
+    /// Returns the value as stored in param2.
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the index of the direction the top of the node points to: `+Y`, `+Z`, `-Z`, `+X`,
+    /// `-X` or `-Y`.
+    #[must_use]
+    pub const fn axis(self) -> u8 {
+        self.0 / 4
+    }
+
+    /// Returns the number of clockwise quarter turns around the [`Self::axis`].
+    #[must_use]
+    pub const fn rotation(self) -> u8 {
+        self.0 % 4
+    }
+
+    /// Returns the direction the top of the node points to.
+    #[must_use]
+    pub const fn top(self) -> I16Vec3 {
+        #[expect(clippy::indexing_slicing, reason = "the axis is always below 6")]
+        FACEDIR_AXES[self.axis() as usize]
+    }
+
+    /// Returns the direction the front of the node points to, like `core.facedir_to_dir`.
+    #[must_use]
+    pub const fn facing(self) -> I16Vec3 {
+        #[expect(clippy::indexing_slicing, reason = "the value is always below 24")]
+        FACEDIR_FACING[self.0 as usize]
+    }
+
+    /// Rotates a vector from the node's local coordinates into world coordinates.
+    #[must_use]
+    pub fn rotate(self, vec: I16Vec3) -> I16Vec3 {
+        let top = self.top();
+        let facing = self.facing();
+        let side = top.cross(facing);
+        side * vec.x + top * vec.y + facing * vec.z
+    }
+
+    /// Returns the matrix rotating the node's local coordinates into world coordinates.
+    #[must_use]
+    pub fn matrix(self) -> Mat3 {
+        Mat3::from_cols(
+            self.rotate(I16Vec3::X).as_vec3(),
+            self.top().as_vec3(),
+            self.facing().as_vec3(),
+        )
+    }
+}
+
+/// The direction a `wallmounted` node is attached to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WallMounted(u8);
+
+impl WallMounted {
+    /// Interprets the lower three bits of param2.
+    #[must_use]
+    pub const fn from_param2(param2: u8) -> Self {
+        Self(param2 & WALLMOUNTED_MASK)
+    }
+
+    /// Returns the value as stored in param2.
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the direction the node is attached to, like `core.wallmounted_to_dir`.
+    #[must_use]
+    pub const fn dir(self) -> I16Vec3 {
+        #[expect(clippy::indexing_slicing, reason = "the value is always below 8")]
+        WALLMOUNTED_DIRS[self.0 as usize]
+    }
+
+    /// Returns `true` if the node is attached to the ceiling or the floor with the rotation
+    /// introduced in Luanti 5.9.
+    #[must_use]
+    pub const fn is_rotated(self) -> bool {
+        self.0 >= 6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{I16Vec3, Vec3};
+
+    use super::{FaceDir, WallMounted};
+
+    #[test]
+    fn facedir_rotations_are_proper() {
+        assert_eq!(FaceDir::from_param2(0xE1), FaceDir::new(0, 1));
+        assert_eq!(FaceDir::from_param2(25), FaceDir::new(0, 1));
+        for value in 0..24 {
+            let facedir = FaceDir::from_param2(value);
+            assert_eq!(facedir.rotate(I16Vec3::Y), facedir.top());
+            assert_eq!(facedir.rotate(I16Vec3::Z), facedir.facing());
+            assert_eq!(facedir.top().dot(facedir.facing()), 0, "{facedir:?}");
+            let matrix = facedir.matrix();
+            assert!((matrix.determinant() - 1.0).abs() < f32::EPSILON);
+            assert_eq!(
+                matrix * Vec3::new(1.0, 2.0, 3.0),
+                facedir.rotate(I16Vec3::new(1, 2, 3)).as_vec3()
+            );
+        }
+        // a quarter turn around the vertical axis
+        assert_eq!(FaceDir::new(0, 1).rotate(I16Vec3::X), I16Vec3::NEG_Z);
+        assert_eq!(FaceDir::new(5, 0).top(), I16Vec3::NEG_Y);
+    }
+
+    #[test]
+    fn wallmounted_directions() {
+        assert_eq!(WallMounted::from_param2(0x09).dir(), I16Vec3::NEG_Y);
+        assert_eq!(WallMounted::from_param2(3).dir(), I16Vec3::NEG_X);
+        assert!(WallMounted::from_param2(7).is_rotated());
+        assert!(!WallMounted::from_param2(5).is_rotated());
+    }
+}
@@ -0,0 +1,262 @@
+//! Contains `Region`, an axis-aligned cuboid of positions, and the iterator over its positions.
+//!
+//! Regions work on raw vectors, so they can describe both nodes and map blocks. See
+//! [`MapBlockPos::blocks_in_radius`] and [`MapBlockPos::blocks_in_region`] for typed iterators.
+
+use glam::{I16Vec3, IVec3};
+
+use crate::map_block::MapBlockPos;
+
+/// An axis-aligned cuboid between two positions; both corners are inclusive, so a region is never
+/// empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Region {
+    min: I16Vec3,
+    max: I16Vec3,
+}
+
+impl Region {
+    /// Creates the region spanned by two arbitrary corners.
+    #[must_use]
+    pub fn new(corner: I16Vec3, opposite: I16Vec3) -> Self {
+        Self {
+            min: corner.min(opposite),
+            max: corner.max(opposite),
+        }
+    }
+
+    /// Creates the region containing a single position.
+    #[must_use]
+    pub const fn single(pos: I16Vec3) -> Self {
+        Self { min: pos, max: pos }
+    }
+
+    /// Creates the cube reaching `radius` steps into each direction of `center`. Parts of the
+    /// cube exceeding the range of `i16` are cut off.
+    #[must_use]
+    pub fn around(center: I16Vec3, radius: u16) -> Self {
+        let center = center.as_ivec3();
+        let radius = IVec3::splat(i32::from(radius));
+        let (lower, upper) = (IVec3::splat(i16::MIN.into()), IVec3::splat(i16::MAX.into()));
+        Self {
+            min: (center - radius).max(lower).as_i16vec3(),
+            max: (center + radius).min(upper).as_i16vec3(),
+        }
+    }
+
+    /// Returns the corner with the lowest coordinates.
+    #[must_use]
+    pub const fn min(&self) -> I16Vec3 {
+        self.min
+    }
+
+    /// Returns the corner with the highest coordinates.
+    #[must_use]
+    pub const fn max(&self) -> I16Vec3 {
+        self.max
+    }
+
+    /// Returns the number of positions along each axis.
+    #[must_use]
+    pub fn size(&self) -> [u32; 3] {
+        let size = self.max.as_ivec3() - self.min.as_ivec3() + 1;
+        size.as_uvec3().to_array()
+    }
+
+    /// Returns the number of positions within this region.
+    #[must_use]
+    pub fn volume(&self) -> u64 {
+        self.size().iter().copied().map(u64::from).product()
+    }
+
+    /// Checks whether the given position lies within this region.
+    #[must_use]
+    pub fn contains(&self, pos: I16Vec3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmple(self.max).all()
+    }
+
+    /// Returns the positions contained in both regions or `None` if they don't overlap.
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        min.cmple(max).all().then_some(Self { min, max })
+    }
+
+    /// Returns the position of `pos` in the order of [`Self::iter`], e.g. to address a buffer
+    /// holding a value for every position of this region. Returns `None` if `pos` lies outside.
+    #[must_use]
+    pub fn index(&self, pos: I16Vec3) -> Option<usize> {
+        if !self.contains(pos) {
+            return None;
+        }
+        usize::try_from(self.offset(pos)).ok()
+    }
+
+    /// Returns the region of map blocks containing all nodes of this region.
+    #[must_use]
+    pub const fn blocks(&self) -> Self {
+        Self {
+            min: MapBlockPos::for_vec(self.min).vec(),
+            max: MapBlockPos::for_vec(self.max).vec(),
+        }
+    }
+
+    /// Iterates over all positions; `x` changes fastest, `z` slowest.
+    #[must_use]
+    pub const fn iter(&self) -> RegionIter {
+        RegionIter {
+            region: *self,
+            next: Some(self.min),
+        }
+    }
+
+    /// Returns the position following `pos` in the order of [`Self::iter`].
+    fn successor(&self, pos: I16Vec3) -> Option<I16Vec3> {
+        if pos.x < self.max.x {
+            return Some(I16Vec3::new(pos.x + 1, pos.y, pos.z));
+        }
+        if pos.y < self.max.y {
+            return Some(I16Vec3::new(self.min.x, pos.y + 1, pos.z));
+        }
+        if pos.z < self.max.z {
+            return Some(I16Vec3::new(self.min.x, self.min.y, pos.z + 1));
+        }
+        None
+    }
+
+    /// Returns the number of positions preceding `pos` in the order of [`Self::iter`].
+    fn offset(&self, pos: I16Vec3) -> u64 {
+        let [size_x, size_y, _] = self.size().map(u64::from);
+        let delta = (pos.as_ivec3() - self.min.as_ivec3()).as_uvec3();
+        (u64::from(delta.z) * size_y + u64::from(delta.y)) * size_x + u64::from(delta.x)
+    }
+}
+
+impl IntoIterator for Region {
+    type Item = I16Vec3;
+    type IntoIter = RegionIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &Region {
+    type Item = I16Vec3;
+    type IntoIter = RegionIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterates over all positions of a [`Region`]; `x` changes fastest, `z` slowest.
+#[derive(Clone, Debug)]
+pub struct RegionIter {
+    region: Region,
+    next: Option<I16Vec3>,
+}
+
+impl Iterator for RegionIter {
+    type Item = I16Vec3;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.next?;
+        self.next = self.region.successor(pos);
+        Some(pos)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Some(next) = self.next else {
+            return (0, Some(0));
+        };
+        let remaining = self.region.volume() - self.region.offset(next);
+        usize::try_from(remaining)
+            .map_or((usize::MAX, None), |remaining| (remaining, Some(remaining)))
+    }
+}
+
+impl std::iter::FusedIterator for RegionIter {}
+
+#[cfg(test)]
+mod tests {
+    use glam::I16Vec3;
+
+    use super::Region;
+    use crate::{MapBlockPos, MapNodePos};
+
+    #[test]
+    fn regions_are_iterated_x_first() {
+        let region = Region::new(I16Vec3::new(1, 1, 1), I16Vec3::new(0, 0, 0));
+        assert_eq!(region.volume(), 8);
+        let positions: Vec<_> = region.iter().map(|pos| pos.to_array()).collect();
+        assert_eq!(
+            positions,
+            [
+                [0, 0, 0],
+                [1, 0, 0],
+                [0, 1, 0],
+                [1, 1, 0],
+                [0, 0, 1],
+                [1, 0, 1],
+                [0, 1, 1],
+                [1, 1, 1]
+            ]
+        );
+
+        let mut iter = region.iter();
+        assert_eq!(iter.size_hint(), (8, Some(8)));
+        iter.nth(4);
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.next(), None);
+
+        assert!(
+            region
+                .iter()
+                .enumerate()
+                .all(|(index, pos)| region.index(pos) == Some(index))
+        );
+        assert_eq!(region.index(I16Vec3::new(2, 0, 0)), None);
+
+        assert_eq!(Region::single(I16Vec3::MAX).iter().count(), 1);
+        let full = Region::new(I16Vec3::MIN, I16Vec3::MAX);
+        assert_eq!(full.volume(), 1 << 48);
+        assert_eq!(Region::around(I16Vec3::ZERO, u16::MAX), full);
+    }
+
+    #[test]
+    fn regions_are_clamped() {
+        let region = Region::around(I16Vec3::new(i16::MAX, 0, i16::MIN), 2);
+        assert_eq!(region.min(), I16Vec3::new(i16::MAX - 2, -2, i16::MIN));
+        assert_eq!(region.max(), I16Vec3::new(i16::MAX, 2, i16::MIN + 2));
+        assert_eq!(region.volume(), 3 * 5 * 3);
+        assert!(region.contains(I16Vec3::new(i16::MAX, 2, i16::MIN)));
+        assert!(!region.contains(I16Vec3::new(i16::MAX, 3, i16::MIN)));
+
+        let other = Region::new(I16Vec3::ZERO, I16Vec3::MAX);
+        assert_eq!(region.intersection(&other), None);
+        assert_eq!(
+            Region::single(I16Vec3::ZERO).intersection(&other),
+            Some(Region::single(I16Vec3::ZERO))
+        );
+
+        let blocks: Vec<_> = MapBlockPos::MAX.blocks_in_radius(1).collect();
+        assert_eq!(blocks.len(), 8);
+        assert!(blocks.contains(&MapBlockPos::MAX));
+        assert_eq!(MapBlockPos::ZERO.blocks_in_radius(2).count(), 125);
+
+        let nodes = Region::new(I16Vec3::new(-1, 0, 15), I16Vec3::new(16, 0, 16));
+        let covering: Vec<_> = MapBlockPos::blocks_in_region(nodes).collect();
+        assert_eq!(covering.len(), 3 * 2);
+        assert_eq!(
+            covering.first().copied(),
+            Some(MapBlockPos::for_node(MapNodePos(I16Vec3::new(-1, 0, 15))))
+        );
+        assert_eq!(
+            MapBlockPos::ZERO.nodes(),
+            Region::new(I16Vec3::ZERO, I16Vec3::splat(15))
+        );
+    }
+}
@@ -5,7 +5,6 @@
     // clippy::missing_panics_doc,
     // clippy::missing_errors_doc,
     clippy::expect_used,
-    clippy::unwrap_used,
     clippy::unimplemented,
     reason = "//TODO add documentation and improve error handling"
 )]
@@ -14,24 +13,36 @@ use proc_macro2::Ident;
 use proc_macro2::Literal;
 use proc_macro2::TokenStream;
 use quote::ToTokens;
+use quote::format_ident;
 use quote::quote;
 use quote::quote_spanned;
+use syn::Attribute;
 use syn::Data;
 use syn::DeriveInput;
+use syn::Expr;
 use syn::Field;
+use syn::FieldsNamed;
 use syn::Generics;
 use syn::Index;
+use syn::Meta;
+use syn::Path;
 use syn::Type;
 use syn::TypeParam;
+use syn::ext::IdentExt;
 use syn::parse_macro_input;
+use syn::parse_quote;
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 
-#[proc_macro_derive(LuantiSerialize, attributes(wrap))]
+#[proc_macro_derive(LuantiSerialize, attributes(wrap, default, skip_serializing_if, since))]
 pub fn luanti_serialize(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    let to_json = make_to_json_impl(&input);
     let name = input.ident;
-    let serialize_body = make_serialize_body(&name, &input.data);
+    let serialize_body = match make_serialize_body(&name, &input.data) {
+        Ok(serialize_body) => serialize_body,
+        Err(error) => return error.to_compile_error().into(),
+    };
 
     // The struct must include Serialize in the bounds of any type
     // that need to be serializable.
@@ -39,6 +50,11 @@ pub fn luanti_serialize(input: proc_macro::TokenStream) -> proc_macro::TokenStre
     let name_generic = strip_generic_bounds(&input.generics).to_token_stream();
     let where_generic = input.generics.where_clause;
 
+    let schema = match make_schema(&input.data) {
+        Ok(schema) => schema,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
     let expanded = quote! {
         impl #impl_generic Serialize for #name #name_generic #where_generic {
             type Input = Self;
@@ -47,15 +63,27 @@ pub fn luanti_serialize(input: proc_macro::TokenStream) -> proc_macro::TokenStre
                 Ok(())
             }
         }
+
+        impl #impl_generic crate::schema::Describe for #name #name_generic #where_generic {
+            const SCHEMA: crate::schema::TypeSchema = #schema;
+        }
+
+        #to_json
     };
     proc_macro::TokenStream::from(expanded)
 }
 
-#[proc_macro_derive(LuantiDeserialize, attributes(wrap))]
+#[proc_macro_derive(
+    LuantiDeserialize,
+    attributes(wrap, default, skip_serializing_if, since)
+)]
 pub fn luanti_deserialize(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = input.ident;
-    let deserialize_body = make_deserialize_body(&name, &input.data);
+    let deserialize_body = match make_deserialize_body(&name, &input.data) {
+        Ok(deserialize_body) => deserialize_body,
+        Err(error) => return error.to_compile_error().into(),
+    };
 
     // The struct must include Deserialize in the bounds of any type
     // that need to be serializable.
@@ -74,41 +102,198 @@ pub fn luanti_deserialize(input: proc_macro::TokenStream) -> proc_macro::TokenSt
     proc_macro::TokenStream::from(expanded)
 }
 
-fn get_wrapped_type(field: &Field) -> Type {
+/// Renders the type as JSON; this is implied by `LuantiSerialize` and meant for types with
+/// hand-written serializers.
+#[proc_macro_derive(ToJson)]
+pub fn to_json(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    proc_macro::TokenStream::from(make_to_json_impl(&input))
+}
+
+fn make_to_json_impl(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let impl_generic = input.generics.to_token_stream();
+    let name_generic = strip_generic_bounds(&input.generics).to_token_stream();
+    let where_generic = add_to_json_bounds(&input.generics).where_clause;
+    let body = make_to_json_body(&input.data);
+    quote! {
+        impl #impl_generic crate::schema::ToJson for #name #name_generic #where_generic {
+            fn write_json(&self, json: &mut String) {
+                #body
+            }
+        }
+    }
+}
+
+fn get_wrapped_type(field: &Field) -> syn::Result<Type> {
     let mut ty = field.ty.clone();
     for attr in &field.attrs {
         if attr.path().is_ident("wrap") {
-            ty = attr.parse_args::<Type>().unwrap();
+            ty = attr.parse_args::<Type>()?;
         }
     }
-    ty
+    Ok(ty)
+}
+
+/// Returns the value of a field which is absent because the stream has ended; `None` if the
+/// field is mandatory.
+///
+/// `#[default]` uses `Default::default()`, `#[default(expr)]` or `#[default = expr]` use the
+/// given expression.
+fn get_default(field: &Field) -> syn::Result<Option<TokenStream>> {
+    let Some(attr) = get_default_attr(field) else {
+        return Ok(None);
+    };
+    Ok(Some(match &attr.meta {
+        Meta::Path(_) => quote_spanned! {attr.span() => ::core::default::Default::default() },
+        Meta::List(list) => list.parse_args::<Expr>()?.to_token_stream(),
+        Meta::NameValue(name_value) => name_value.value.to_token_stream(),
+    }))
+}
+
+fn get_default_attr(field: &Field) -> Option<&Attribute> {
+    field
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("default"))
+}
+
+/// Returns the predicate of `#[skip_serializing_if(predicate)]`, if any.
+fn get_skip_condition(field: &Field) -> syn::Result<Option<Path>> {
+    field
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("skip_serializing_if"))
+        .map(Attribute::parse_args::<Path>)
+        .transpose()
+}
+
+/// Returns the protocol version of `#[since(version)]`, if any.
+fn get_since(field: &Field) -> syn::Result<Option<Expr>> {
+    field
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("since"))
+        .map(Attribute::parse_args::<Expr>)
+        .transpose()
+}
+
+fn is_skippable(field: &Field) -> bool {
+    field
+        .attrs
+        .iter()
+        .any(|attr| attr.path().is_ident("skip_serializing_if") || attr.path().is_ident("since"))
+}
+
+/// Serializes named fields in order.
+///
+/// Fields with `#[skip_serializing_if(predicate)]` or `#[since(version)]` need to be at the end.
+/// They are omitted from the end of the stream as long as the predicate holds for them and all
+/// following fields, so the receiver can tell which ones are absent. Fields the peer's protocol
+/// version doesn't support are never written, and neither are the ones following them.
+fn make_serialize_named(fields: &FieldsNamed) -> syn::Result<TokenStream> {
+    let first_skippable = fields
+        .named
+        .iter()
+        .position(is_skippable)
+        .unwrap_or(fields.named.len());
+    if let Some(field) = fields
+        .named
+        .iter()
+        .skip(first_skippable)
+        .find(|field| !is_skippable(field))
+    {
+        return Ok(quote_spanned! {field.span() =>
+            compile_error!("fields following a field with `skip_serializing_if` or `since` need to be skippable as well");
+        });
+    }
+
+    let conditions = fields
+        .named
+        .iter()
+        .skip(first_skippable)
+        .map(|field| {
+            let name = &field.ident;
+            Ok(get_skip_condition(field)?.map_or_else(
+                || quote! { true },
+                |condition| quote_spanned! {field.span() => !#condition(&value.#name) },
+            ))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let supported = fields
+        .named
+        .iter()
+        .skip(first_skippable)
+        .map(|field| {
+            Ok(get_since(field)?.map_or_else(
+                || quote! { true },
+                |since| quote_spanned! {field.span() => ser.context().protocol_version >= #since },
+            ))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let prelude = if first_skippable < fields.named.len() {
+        quote! {
+            // number of trailing fields which need to be written
+            let present = [#(#conditions),*];
+            let supported = [#(#supported),*];
+            let supported = supported.iter().position(|is_supported| !*is_supported).unwrap_or(supported.len());
+            let written = present.iter().take(supported).rposition(|is_present| *is_present).map_or(0, |last| last + 1);
+        }
+    } else {
+        quote! {}
+    };
+
+    let recurse = fields
+        .named
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let name = &field.ident;
+            let ty = get_wrapped_type(field)?;
+            let serialize = quote_spanned! {field.span() =>
+                <#ty as Serialize>::serialize(&value.#name, ser)?;
+            };
+            Ok(if let Some(trailing) = index.checked_sub(first_skippable) {
+                quote_spanned! {field.span() =>
+                    if written > #trailing {
+                        #serialize
+                    }
+                }
+            } else {
+                serialize
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        #prelude
+        #(#recurse)*
+    })
 }
 
 /// For struct, fields are serialized/deserialized in order.
 /// For enum, tags are assumed u8, consecutive, starting with 0.
-fn make_serialize_body(input_name: &Ident, data: &Data) -> TokenStream {
-    match *data {
+fn make_serialize_body(input_name: &Ident, data: &Data) -> syn::Result<TokenStream> {
+    Ok(match *data {
         Data::Struct(ref data) => match data.fields {
-            syn::Fields::Named(ref fields) => {
-                let recurse = fields.named.iter().map(|field| {
-                    let name = &field.ident;
-                    let ty = get_wrapped_type(field);
-                    quote_spanned! {field.span() =>
-                        <#ty as Serialize>::serialize(&value.#name, ser)?;
-                    }
-                });
-                quote! {
-                    #(#recurse)*
-                }
-            }
+            syn::Fields::Named(ref fields) => make_serialize_named(fields)?,
             syn::Fields::Unnamed(ref fields) => {
-                let recurse = fields.unnamed.iter().enumerate().map(|(index, field)| {
-                    let index = Index::from(index);
-                    let ty = get_wrapped_type(field);
-                    quote_spanned! {field.span() =>
-                        <#ty as Serialize>::serialize(&value.#index, ser)?;
-                    }
-                });
+                let recurse = fields
+                    .unnamed
+                    .iter()
+                    .enumerate()
+                    .map(|(index, field)| {
+                        let index = Index::from(index);
+                        let ty = get_wrapped_type(field)?;
+                        if is_skippable(field) {
+                            return Ok(quote_spanned! {field.span() =>
+                                compile_error!("`skip_serializing_if` and `since` are only supported for named fields");
+                            });
+                        }
+                        Ok(quote_spanned! {field.span() =>
+                            <#ty as Serialize>::serialize(&value.#index, ser)?;
+                        })
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
                 quote! {
                     #(#recurse)*
                 }
@@ -146,43 +331,69 @@ fn make_serialize_body(input_name: &Ident, data: &Data) -> TokenStream {
             }
         }
         Data::Union(_) => unimplemented!(),
-    }
+    })
 }
 
-fn make_deserialize_body(input_name: &Ident, data: &Data) -> TokenStream {
-    match *data {
-        Data::Struct(ref data) => match data.fields {
-            syn::Fields::Named(ref fields) => {
-                let assignments = fields.named.iter().map(|field| {
-                    let name = &field.ident;
-                    let ty = get_wrapped_type(field);
-                    quote_spanned! {field.span() =>
-        // very noisy; re-enable if there are protocol errors to be debugged
-                        // log::trace!(stringify!("deserializing field", #input_name, #name));
-                        #[allow(unused_qualifications)]
-                        let #name = anyhow::Context::context(<#ty as Deserialize>::deserialize(deser), stringify!("failed to deserialize field", #input_name, #name))?;
-
-        // very noisy; re-enable if there are protocol errors to be debugged
-                        // log::trace!("result: {:?} - {} bytes left", #name, deser.remaining());
-                    }
-                });
-                let fields = fields.named.iter().map(|field| {
-                    let name = &field.ident;
-                    quote_spanned! { field.span() => #name, }
-                });
-                quote! {
-                    #(#assignments)*
-                    Ok(Self { #(#fields)* })
+/// Deserializes named fields in order.
+///
+/// Fields with `#[default]` take their default value if the stream has already ended.
+fn make_deserialize_named(input_name: &Ident, fields: &FieldsNamed) -> syn::Result<TokenStream> {
+    let assignments = fields
+        .named
+        .iter()
+        .map(|field| {
+            let name = &field.ident;
+            let ty = get_wrapped_type(field)?;
+            let deserialize = quote_spanned! {field.span() =>
+                deser.deserialize_field::<#ty>(stringify!(#input_name), stringify!(#name))?
+            };
+            Ok(if let Some(default) = get_default(field)? {
+                quote_spanned! {field.span() =>
+                    let #name = if deser.has_remaining() {
+                        #deserialize
+                    } else {
+                        #default
+                    };
                 }
-            }
+            } else {
+                quote_spanned! {field.span() =>
+                    let #name = #deserialize;
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let fields = fields.named.iter().map(|field| {
+        let name = &field.ident;
+        quote_spanned! { field.span() => #name, }
+    });
+    Ok(quote! {
+        #(#assignments)*
+        Ok(Self { #(#fields)* })
+    })
+}
+
+fn make_deserialize_body(input_name: &Ident, data: &Data) -> syn::Result<TokenStream> {
+    Ok(match *data {
+        Data::Struct(ref data) => match data.fields {
+            syn::Fields::Named(ref fields) => make_deserialize_named(input_name, fields)?,
             syn::Fields::Unnamed(ref fields) => {
-                let recurse = fields.unnamed.iter().enumerate().map(|(index, field)| {
-                    let index = Index::from(index);
-                    let ty = get_wrapped_type(field);
-                    quote_spanned! {field.span() =>
-                        #index: <#ty as Deserialize>::deserialize(deser)?,
-                    }
-                });
+                let recurse = fields
+                    .unnamed
+                    .iter()
+                    .enumerate()
+                    .map(|(index, field)| {
+                        let index = Index::from(index);
+                        let ty = get_wrapped_type(field)?;
+                        if get_default_attr(field).is_some() {
+                            return Ok(quote_spanned! {field.span() =>
+                                compile_error!("`default` is only supported for named fields");
+                            });
+                        }
+                        Ok(quote_spanned! {field.span() =>
+                            #index: <#ty as Deserialize>::deserialize(deser)?,
+                        })
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
                 let inner = quote! {
                     #(#recurse)*
                 };
@@ -234,7 +445,225 @@ fn make_deserialize_body(input_name: &Ident, data: &Data) -> TokenStream {
             }
         }
         Data::Union(_) => unimplemented!(),
+    })
+}
+
+/// Renders types and expressions for the schema, e.g. `Array16<Wrapped16<ItemDef>>`.
+///
+/// `to_string()` puts spaces between all tokens, which are dropped except after separators.
+fn source_text(tokens: &impl ToTokens) -> String {
+    tokens
+        .to_token_stream()
+        .to_string()
+        .split_whitespace()
+        .collect::<String>()
+        .replace(',', ", ")
+        .replace(';', "; ")
+}
+
+fn make_field_schema(name: &str, field: &Field) -> syn::Result<TokenStream> {
+    let ty = source_text(&field.ty);
+    let wire_ty = if field.attrs.iter().any(|attr| attr.path().is_ident("wrap")) {
+        let wire_ty = source_text(&get_wrapped_type(field)?);
+        quote! { Some(#wire_ty) }
+    } else {
+        quote! { None }
+    };
+    let default = match get_default_attr(field).map(|attr| &attr.meta) {
+        None => quote! { None },
+        Some(Meta::Path(_)) => quote! { Some("Default::default()") },
+        Some(Meta::List(list)) => {
+            let default = source_text(&list.parse_args::<Expr>()?);
+            quote! { Some(#default) }
+        }
+        Some(Meta::NameValue(name_value)) => {
+            let default = source_text(&name_value.value);
+            quote! { Some(#default) }
+        }
+    };
+    let skip_if = if let Some(condition) = get_skip_condition(field)? {
+        let condition = source_text(&condition);
+        quote! { Some(#condition) }
+    } else {
+        quote! { None }
+    };
+    let since = if let Some(since) = get_since(field)? {
+        let since = source_text(&since);
+        quote! { Some(#since) }
+    } else {
+        quote! { None }
+    };
+    Ok(quote! {
+        crate::schema::FieldSchema {
+            name: #name,
+            ty: #ty,
+            wire_ty: #wire_ty,
+            default: #default,
+            skip_if: #skip_if,
+            since: #since,
+        }
+    })
+}
+
+/// Describes the serialized layout of a type; see `luanti_protocol::schema`.
+fn make_schema(data: &Data) -> syn::Result<TokenStream> {
+    Ok(match *data {
+        Data::Struct(ref data) => match data.fields {
+            syn::Fields::Named(ref fields) => {
+                let fields = fields
+                    .named
+                    .iter()
+                    .map(|field| {
+                        let name = field
+                            .ident
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default();
+                        make_field_schema(&name, field)
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
+                quote! { crate::schema::TypeSchema::Struct(&[#(#fields),*]) }
+            }
+            syn::Fields::Unnamed(ref fields) => {
+                let fields = fields
+                    .unnamed
+                    .iter()
+                    .enumerate()
+                    .map(|(index, field)| make_field_schema(&index.to_string(), field))
+                    .collect::<syn::Result<Vec<_>>>()?;
+                quote! { crate::schema::TypeSchema::Tuple(&[#(#fields),*]) }
+            }
+            syn::Fields::Unit => quote! { crate::schema::TypeSchema::Struct(&[]) },
+        },
+        Data::Enum(ref body) => {
+            let variants = body
+                .variants
+                .iter()
+                .map(|variant| variant.ident.to_string());
+            quote! { crate::schema::TypeSchema::Enum(&[#(#variants),*]) }
+        }
+        Data::Union(_) => unimplemented!(),
+    })
+}
+
+/// Renders a value as JSON, using the same field and variant names as [`make_schema`].
+///
+/// Newtypes are rendered as their only field and enum variants carrying fields become an object
+/// with the name of the variant as the only key.
+fn make_to_json_body(data: &Data) -> TokenStream {
+    match *data {
+        Data::Struct(ref data) => {
+            let bindings = data
+                .fields
+                .members()
+                .map(|member| quote! { &self.#member })
+                .collect::<Vec<_>>();
+            make_fields_to_json(&data.fields, &bindings)
+        }
+        Data::Enum(ref body) => {
+            let variants = body.variants.iter().map(|variant| {
+                let id = &variant.ident;
+                let name = id.unraw().to_string();
+                let bindings = variant
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(index, field)| {
+                        field
+                            .ident
+                            .clone()
+                            .unwrap_or_else(|| format_ident!("field_{index}"))
+                    })
+                    .collect::<Vec<_>>();
+                let pattern = match variant.fields {
+                    syn::Fields::Named(_) => quote! { Self::#id { #(#bindings),* } },
+                    syn::Fields::Unnamed(_) => quote! { Self::#id ( #(#bindings),* ) },
+                    syn::Fields::Unit => quote! { Self::#id },
+                };
+                if variant.fields.is_empty() {
+                    return quote! {
+                        #pattern => crate::schema::write_json_string(json, #name),
+                    };
+                }
+                let bindings = bindings
+                    .iter()
+                    .map(ToTokens::to_token_stream)
+                    .collect::<Vec<_>>();
+                let fields = make_fields_to_json(&variant.fields, &bindings);
+                quote! {
+                    #pattern => {
+                        json.push('{');
+                        crate::schema::write_json_string(json, #name);
+                        json.push(':');
+                        #fields
+                        json.push('}');
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#variants)*
+                }
+            }
+        }
+        Data::Union(_) => unimplemented!(),
+    }
+}
+
+/// Renders the fields of a struct or an enum variant, which are available as `bindings`.
+fn make_fields_to_json(fields: &syn::Fields, bindings: &[TokenStream]) -> TokenStream {
+    if let (syn::Fields::Unnamed(_), [binding]) = (fields, bindings) {
+        return quote! { crate::schema::ToJson::write_json(#binding, json); };
+    }
+    let members = fields
+        .iter()
+        .zip(bindings)
+        .enumerate()
+        .map(|(index, (field, binding))| {
+            let separator = if index == 0 {
+                quote! {}
+            } else {
+                quote! { json.push(','); }
+            };
+            let key = field.ident.as_ref().map(|name| {
+                let name = name.unraw().to_string();
+                quote! {
+                    crate::schema::write_json_string(json, #name);
+                    json.push(':');
+                }
+            });
+            quote! {
+                #separator
+                #key
+                crate::schema::ToJson::write_json(#binding, json);
+            }
+        });
+    let (open, close) = if matches!(fields, syn::Fields::Unnamed(_)) {
+        ('[', ']')
+    } else {
+        ('{', '}')
+    };
+    quote! {
+        json.push(#open);
+        #(#members)*
+        json.push(#close);
+    }
+}
+
+/// Adds a `ToJson` bound to every type parameter.
+fn add_to_json_bounds(input: &Generics) -> Generics {
+    let mut generics = input.clone();
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(parse_quote! { #param: crate::schema::ToJson });
     }
+    generics
 }
 
 /// Converts <T: Trait, S: Trait2> into <T, S>
@@ -0,0 +1,276 @@
+//! Benchmarks of the serialization hot paths
+//!
+//! Run with `cargo bench -p luanti-protocol`.
+
+#![allow(
+    missing_docs,
+    reason = "the criterion macros generate undocumented items"
+)]
+#![allow(
+    unused_crate_dependencies,
+    reason = "benchmarks see all dependencies of the library"
+)]
+#![expect(clippy::unwrap_used, reason = "benchmarks abort on any error")]
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use glam::{I16Vec3, U8Vec4, Vec3};
+use luanti_core::{ContentId, MapNode};
+use luanti_protocol::commands::Command;
+use luanti_protocol::commands::server_to_client::{
+    BlockdataSpec, ItemDef, ItemType, ItemdefList, ToClientCommand,
+};
+use luanti_protocol::types::{
+    ContentFeatures, MapNodesBulk, NodeDefManager, NodeMetadataList, Option16, ProtocolContext,
+    SColor, SoundSpec, TransferrableMapBlock,
+};
+use luanti_protocol::wire::channel_id::ChannelId;
+use luanti_protocol::wire::compression::CompressionConfig;
+use luanti_protocol::wire::deser::{Deserialize, Deserializer};
+use luanti_protocol::wire::packet::{InnerBody, OriginalBody, Packet};
+use luanti_protocol::wire::peer_id::PeerId;
+use luanti_protocol::wire::ser::{Serialize, VecSerializer};
+use luanti_protocol::wire::util::{compress_zlib, decompress_zlib, zstd_compress, zstd_decompress};
+
+/// context of the server sending to a client
+fn send_context() -> ProtocolContext {
+    ProtocolContext::latest_for_send(false)
+}
+
+/// context of a client receiving from the server
+fn receive_context() -> ProtocolContext {
+    ProtocolContext::latest_for_receive(true)
+}
+
+fn serialize<T: Serialize<Input = T>>(value: &T) -> Vec<u8> {
+    let mut serializer = VecSerializer::new(send_context(), 0x0001_0000);
+    T::serialize(value, &mut serializer).unwrap();
+    serializer.take()
+}
+
+fn deserialize<T: Deserialize>(data: &[u8]) -> T::Output {
+    T::deserialize(&mut Deserializer::new(receive_context(), data)).unwrap()
+}
+
+/// A map block with some variation, so compression has something to do
+fn map_nodes() -> MapNodesBulk {
+    MapNodesBulk {
+        nodes: std::array::from_fn(|index| {
+            let [low, high] = u16::try_from(index).unwrap().to_le_bytes();
+            MapNode {
+                content_id: ContentId(u16::from(low % 7)),
+                param1: high,
+                param2: low % 4,
+            }
+        }),
+    }
+}
+
+fn node_defs() -> NodeDefManager {
+    NodeDefManager {
+        content_features: (0..500)
+            .map(|index| {
+                (
+                    index,
+                    ContentFeatures::new_unknown(format!("benchmark:node_{index}")),
+                )
+            })
+            .collect(),
+    }
+}
+
+fn item_defs() -> ItemdefList {
+    ItemdefList {
+        itemdef_manager_version: 0,
+        defs: (0..500)
+            .map(|index| ItemDef {
+                version: 6,
+                item_type: ItemType::Craft,
+                name: format!("benchmark:item_{index}"),
+                description: format!("Item {index}").into(),
+                inventory_image: format!("benchmark_item_{index}.png"),
+                wield_image: String::new(),
+                wield_scale: Vec3::ONE,
+                stack_max: 99,
+                usable: false,
+                liquids_pointable: false,
+                tool_capabilities: Option16::None,
+                groups: [("benchmark", 1)].into_iter().collect(),
+                node_placement_prediction: String::new(),
+                sound_place: SoundSpec::new(String::new()),
+                sound_place_failed: SoundSpec::new(String::new()),
+                range: 4.0,
+                palette_image: String::new(),
+                color: SColor(U8Vec4::MAX),
+                inventory_overlay: String::new(),
+                wield_overlay: String::new(),
+                short_description: Some(format!("Item {index}").into()),
+                sound_use: Some(SoundSpec::new(String::new())),
+                sound_use_air: Some(SoundSpec::new(String::new())),
+                place_param2: Some(0),
+            })
+            .collect(),
+        aliases: Vec::new(),
+    }
+}
+
+fn map_block() -> TransferrableMapBlock {
+    TransferrableMapBlock {
+        is_underground: true,
+        day_night_differs: false,
+        generated: true,
+        lighting_complete: Some(0xFFFF),
+        nodes: map_nodes(),
+        node_metadata: NodeMetadataList {
+            metadata: Vec::new(),
+        },
+    }
+}
+
+/// A reliable packet carrying a map block from the server
+fn blockdata_packet() -> Packet {
+    let server = PeerId::deserialize(&mut Deserializer::new(receive_context(), &[0, 1])).unwrap();
+    let command = Command::ToClient(ToClientCommand::Blockdata(Box::new(BlockdataSpec {
+        pos: I16Vec3::new(1, -2, 3),
+        block: map_block(),
+        network_specific_version: 2,
+    })));
+    let body = InnerBody::Original(OriginalBody {
+        command: Some(command),
+    });
+    Packet::new(server, ChannelId::Default, body.into_reliable(65500.into()))
+}
+
+fn map_nodes_bulk(criterion: &mut Criterion) {
+    let nodes = map_nodes();
+    let data = serialize(&nodes);
+    let mut group = criterion.benchmark_group("MapNodesBulk");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("serialize", |bencher| {
+        bencher.iter(|| serialize(black_box(&nodes)));
+    });
+    group.bench_function("deserialize", |bencher| {
+        bencher.iter(|| deserialize::<MapNodesBulk>(black_box(&data)));
+    });
+    group.finish();
+}
+
+fn node_def_manager(criterion: &mut Criterion) {
+    let node_defs = node_defs();
+    let data = serialize(&node_defs);
+    let mut group = criterion.benchmark_group("NodeDefManager");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("serialize", |bencher| {
+        bencher.iter(|| serialize(black_box(&node_defs)));
+    });
+    group.bench_function("deserialize", |bencher| {
+        bencher.iter(|| deserialize::<NodeDefManager>(black_box(&data)));
+    });
+    group.finish();
+}
+
+fn itemdef_list(criterion: &mut Criterion) {
+    let item_defs = item_defs();
+    let data = serialize(&item_defs);
+    let mut group = criterion.benchmark_group("ItemdefList");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("serialize", |bencher| {
+        bencher.iter(|| serialize(black_box(&item_defs)));
+    });
+    group.bench_function("deserialize", |bencher| {
+        bencher.iter(|| deserialize::<ItemdefList>(black_box(&data)));
+    });
+    group.finish();
+}
+
+fn compression(criterion: &mut Criterion) {
+    let uncompressed = serialize(&map_nodes());
+    let zlib = compress_zlib(&uncompressed);
+    let mut zstd = Vec::new();
+    zstd_compress(&uncompressed, |chunk| {
+        zstd.extend_from_slice(chunk);
+        Ok(())
+    })
+    .unwrap();
+
+    let mut group = criterion.benchmark_group("compression");
+    group.throughput(Throughput::Bytes(uncompressed.len() as u64));
+    group.bench_function("zlib compress", |bencher| {
+        bencher.iter(|| compress_zlib(black_box(&uncompressed)));
+    });
+    group.bench_function("zlib decompress", |bencher| {
+        bencher.iter(|| decompress_zlib(black_box(&zlib)).unwrap());
+    });
+    group.bench_function("zstd compress", |bencher| {
+        bencher.iter(|| {
+            let mut compressed = Vec::new();
+            zstd_compress(black_box(&uncompressed), |chunk| {
+                compressed.extend_from_slice(chunk);
+                Ok(())
+            })
+            .unwrap();
+            compressed
+        });
+    });
+    group.bench_function("zstd decompress", |bencher| {
+        bencher.iter(|| {
+            let mut decompressed = Vec::with_capacity(uncompressed.len());
+            zstd_decompress(black_box(&zstd), |chunk| {
+                decompressed.extend_from_slice(chunk);
+                Ok(())
+            })
+            .unwrap();
+            decompressed
+        });
+    });
+    group.finish();
+}
+
+/// Compares the zstd levels of map blocks; the compressed size is part of the name of each
+/// benchmark
+fn map_block_levels(criterion: &mut Criterion) {
+    let block = map_block();
+    let mut group = criterion.benchmark_group("map block");
+    for map_block_level in [-5, -1, 0, 1, 3, 9, 19] {
+        let context = send_context().with_compression(CompressionConfig {
+            map_block_level,
+            ..CompressionConfig::default()
+        });
+        let serialize_block = |value: &TransferrableMapBlock| {
+            let mut serializer = VecSerializer::new(context, 0x4000);
+            TransferrableMapBlock::serialize(value, &mut serializer).unwrap();
+            serializer.take()
+        };
+        let size = serialize_block(&block).len();
+        group.bench_function(
+            format!("level {map_block_level} ({size} bytes)"),
+            |bencher| {
+                bencher.iter(|| serialize_block(black_box(&block)));
+            },
+        );
+    }
+    group.finish();
+}
+
+fn packet_round_trip(criterion: &mut Criterion) {
+    let packet = blockdata_packet();
+    let data = serialize(&packet);
+    let mut group = criterion.benchmark_group("Packet");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("blockdata round trip", |bencher| {
+        bencher.iter(|| deserialize::<Packet>(&serialize(black_box(&packet))));
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    map_nodes_bulk,
+    node_def_manager,
+    itemdef_list,
+    compression,
+    map_block_levels,
+    packet_round_trip
+);
+criterion_main!(benches);
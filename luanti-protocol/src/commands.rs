@@ -9,20 +9,67 @@ pub mod client_to_server;
 pub mod server_to_client;
 
 use crate::CommandDirection;
+use crate::schema;
+use crate::types::CommandId;
+use crate::types::ProtocolContext;
 use crate::wire::channel_id::ChannelId;
 use crate::wire::deser::Deserialize;
 use crate::wire::deser::DeserializeResult;
 use crate::wire::deser::Deserializer;
+use crate::wire::ser::MockSerializer;
 use crate::wire::ser::Serialize;
 use crate::wire::ser::SerializeResult;
 use crate::wire::ser::Serializer;
 use client_to_server::ToServerCommand;
+use luanti_protocol_derive::ToJson;
 use server_to_client::ToClientCommand;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Command {
     ToServer(ToServerCommand),
     ToClient(ToClientCommand),
+    /// A command whose id isn't known to this crate, e.g. one added by a newer protocol version
+    Unknown(RawCommand),
+}
+
+/// A command being passed on without interpreting its payload
+///
+/// Received commands with an unknown id are represented this way instead of failing
+/// deserialization, so proxies keep working when new commands are being added to the protocol.
+#[derive(Debug, PartialEq, Clone, ToJson)]
+pub struct RawCommand {
+    pub direction: CommandDirection,
+    /// id preceding the payload on the wire
+    pub id: CommandId,
+    /// everything following the id
+    pub payload: Vec<u8>,
+}
+
+impl RawCommand {
+    /// Returns the number of bytes the command occupies on the wire.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        size_of::<CommandId>() + self.payload.len()
+    }
+
+    /// Returns `true` if the command consists of its id only.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+impl Command {
+    /// Returns the number of bytes the command occupies when being serialized with the given
+    /// context, without actually allocating them.
+    ///
+    /// Commands exceeding [`MAX_ORIGINAL_BODY_SIZE`](crate::wire::packet::MAX_ORIGINAL_BODY_SIZE)
+    /// are split into several packets.
+    pub fn estimated_size(&self, context: ProtocolContext) -> anyhow::Result<usize> {
+        let mut ser = MockSerializer::new(context);
+        Self::serialize(self, &mut ser)?;
+        Ok(ser.len())
+    }
 }
 
 pub trait CommandProperties {
@@ -38,6 +85,9 @@ pub trait CommandProperties {
 pub trait CommandRef: CommandProperties + std::fmt::Debug {
     fn toserver_ref(&self) -> Option<&ToServerCommand>;
     fn toclient_ref(&self) -> Option<&ToClientCommand>;
+    fn raw_ref(&self) -> Option<&RawCommand> {
+        None
+    }
 }
 
 pub fn serialize_commandref<Cmd: CommandRef, S: Serializer>(
@@ -50,6 +100,9 @@ pub fn serialize_commandref<Cmd: CommandRef, S: Serializer>(
     if let Some(command) = cmd.toclient_ref() {
         ToClientCommand::serialize(command, ser)?;
     }
+    if let Some(command) = cmd.raw_ref() {
+        RawCommand::serialize(command, ser)?;
+    }
     Ok(())
 }
 
@@ -58,6 +111,7 @@ impl CommandProperties for Command {
         match self {
             Command::ToServer(_) => CommandDirection::ToServer,
             Command::ToClient(_) => CommandDirection::ToClient,
+            Command::Unknown(command) => command.direction,
         }
     }
 
@@ -65,6 +119,7 @@ impl CommandProperties for Command {
         match self {
             Command::ToServer(command) => command.default_channel(),
             Command::ToClient(command) => command.default_channel(),
+            Command::Unknown(_) => ChannelId::Default,
         }
     }
 
@@ -72,6 +127,7 @@ impl CommandProperties for Command {
         match self {
             Command::ToServer(command) => command.default_reliability(),
             Command::ToClient(command) => command.default_reliability(),
+            Command::Unknown(_) => true,
         }
     }
 
@@ -79,6 +135,7 @@ impl CommandProperties for Command {
         match self {
             Command::ToServer(command) => command.command_name(),
             Command::ToClient(command) => command.command_name(),
+            Command::Unknown(_) => "Unknown",
         }
     }
 }
@@ -87,16 +144,23 @@ impl CommandRef for Command {
     fn toserver_ref(&self) -> Option<&ToServerCommand> {
         match self {
             Command::ToServer(command) => Some(command),
-            Command::ToClient(_) => None,
+            Command::ToClient(_) | Command::Unknown(_) => None,
         }
     }
 
     fn toclient_ref(&self) -> Option<&ToClientCommand> {
         match self {
-            Command::ToServer(_) => None,
+            Command::ToServer(_) | Command::Unknown(_) => None,
             Command::ToClient(command) => Some(command),
         }
     }
+
+    fn raw_ref(&self) -> Option<&RawCommand> {
+        match self {
+            Command::ToServer(_) | Command::ToClient(_) => None,
+            Command::Unknown(command) => Some(command),
+        }
+    }
 }
 
 impl CommandRef for ToClientCommand {
@@ -125,16 +189,70 @@ impl Serialize for Command {
         match value {
             Command::ToServer(command) => ToServerCommand::serialize(command, ser),
             Command::ToClient(command) => ToClientCommand::serialize(command, ser),
+            Command::Unknown(command) => RawCommand::serialize(command, ser),
         }
     }
 }
 
+impl Serialize for RawCommand {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        CommandId::serialize(&value.id, ser)?;
+        ser.write_bytes(&value.payload)
+    }
+}
+
 impl Deserialize for Command {
     type Output = Option<Self>;
     fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self::Output> {
-        Ok(match deser.direction() {
+        let direction = deser.direction();
+        if let Ok(id) = deser.peek(size_of::<CommandId>()) {
+            let id = CommandId::from_be_bytes([id[0], id[1]]);
+            if schema::find(direction, id).is_none() {
+                log::debug!("passing on unknown command {direction:?} {id}");
+                let payload = deser.take_all()[size_of::<CommandId>()..].to_vec();
+                return Ok(Some(Self::Unknown(RawCommand {
+                    direction,
+                    id,
+                    payload,
+                })));
+            }
+        }
+        Ok(match direction {
             CommandDirection::ToClient => ToClientCommand::deserialize(deser)?.map(Self::ToClient),
             CommandDirection::ToServer => ToServerCommand::deserialize(deser)?.map(Self::ToServer),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use super::{Command, RawCommand};
+    use crate::types::{CommandDirection, ProtocolContext};
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn unknown_commands_round_trip() {
+        let context = ProtocolContext::latest_for_receive(false);
+        let bytes = [0x00, 0xfe, 1, 2, 3];
+        let mut deser = Deserializer::new(context, &bytes);
+        let command = Command::deserialize(&mut deser).unwrap().unwrap();
+        assert!(!deser.has_remaining());
+        assert_eq!(
+            command,
+            Command::Unknown(RawCommand {
+                direction: CommandDirection::ToServer,
+                id: 0xfe,
+                payload: vec![1, 2, 3],
+            })
+        );
+        assert_eq!(command.estimated_size(context).unwrap(), bytes.len());
+
+        let mut ser = VecSerializer::new(context, bytes.len());
+        Command::serialize(&command, &mut ser).unwrap();
+        assert_eq!(ser.take(), bytes);
+    }
+}
@@ -45,7 +45,8 @@ define_protocol!(41, 0x4f457403, ToServer, ToServerCommand => {
     FirstSrp, 0x50, Init, true => FirstSrpSpec,
     SrpBytesA, 0x51, Init, true => SrpBytesASpec,
     SrpBytesM, 0x52, Init, true => SrpBytesMSpec,
-    UpdateClientInfo, 0x53, Init, true => UpdateClientInfoSpec
+    UpdateClientInfo, 0x53, Init, true => UpdateClientInfoSpec,
+    GotFarBlocks, 0x54, Response, true => GotFarBlocksSpec
 });
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -98,6 +99,14 @@ pub struct GotBlocksSpec {
     pub blocks: Vec<I16Vec3>,
 }
 
+/// Acknowledges the reception of far blocks of a single level.
+#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+pub struct GotFarBlocksSpec {
+    pub level: u8,
+    #[wrap(Array8<I16Vec3>)]
+    pub blocks: Vec<I16Vec3>,
+}
+
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct DeletedblocksSpec {
     #[wrap(Array8<I16Vec3>)]
@@ -111,7 +120,7 @@ pub struct InventoryActionSpec {
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct TSChatMessageSpec {
-    #[wrap(WString)]
+    #[wrap(LossyWString)]
     pub message: String,
 }
 
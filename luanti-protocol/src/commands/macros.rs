@@ -70,7 +70,7 @@ macro_rules! define_protocol {
      $protocol_id: literal,
      $dir: ident,
      $command_ty: ident => {
-         $($name: ident, $id: literal, $channel: ident, $reliable: literal => $spec_ty: ident),*
+         $($name: ident, $id: literal, $channel: ident, $reliable: literal $(, since $since: expr)? => $spec_ty: ident),*
     }) => {
         $crate::as_item! {
             #[derive(Debug, PartialEq, Clone)]
@@ -105,6 +105,35 @@ macro_rules! define_protocol {
             }
         }
 
+        $crate::as_item! {
+            impl $command_ty {
+                /// Description of all commands of this direction; see [`crate::schema`]
+                pub const SCHEMA: &'static [$crate::schema::CommandSchema] = &[
+                    $($crate::schema::CommandSchema {
+                        name: stringify!($name),
+                        id: $id,
+                        direction: CommandDirection::$dir,
+                        channel: ChannelId::$channel,
+                        reliable: $reliable,
+                        since: $crate::schema::since(&[$($since)?]),
+                        body_type: stringify!($spec_ty),
+                        body: <$spec_ty as $crate::schema::Describe>::SCHEMA,
+                    }),*
+                ];
+            }
+        }
+
+        $crate::as_item! {
+            /// Renders the command's fields; the name of the command isn't included.
+            impl $crate::schema::ToJson for $command_ty {
+                fn write_json(&self, json: &mut String) {
+                    match self {
+                        $($command_ty::$name(spec) => $crate::schema::ToJson::write_json(spec, json)),*,
+                    }
+                }
+            }
+        }
+
         $crate::as_item! {
             impl Serialize for $command_ty {
                 type Input = Self;
@@ -135,7 +164,11 @@ macro_rules! define_protocol {
                     let command_id = u16::deserialize(deserializer)?;
                     let dir = deserializer.direction();
                     let result = match (dir, command_id) {
-                        $( (CommandDirection::$dir, $id) => $command_ty::$name(Box::new(<$spec_ty as Deserialize>::deserialize(deserializer)?)) ),*,
+                        $( (CommandDirection::$dir, $id) => {
+                            #[cfg(feature = "tracing")]
+                            let _span = tracing::debug_span!("command", name = stringify!($name), id = $id).entered();
+                            $command_ty::$name(Box::new(<$spec_ty as Deserialize>::deserialize(deserializer)?))
+                        } ),*,
                         _ => bail!(DeserializeError::BadPacketId(dir, command_id)),
                     };
                     // there might be more bytes to read if new fields have been added to the protocol
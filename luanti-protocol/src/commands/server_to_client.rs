@@ -1,12 +1,15 @@
 mod access_denied;
 mod active_object_messages;
+mod csm_restrictions;
 mod hud_change;
 mod item_def;
 mod particle_spawner;
 mod set_sky;
+mod sound;
 
 pub use access_denied::*;
 pub use active_object_messages::*;
+pub use csm_restrictions::*;
 use glam::I16Vec3;
 use glam::IVec2;
 use glam::Vec2;
@@ -16,16 +19,20 @@ pub use item_def::*;
 use luanti_core::MapNode;
 pub use particle_spawner::*;
 pub use set_sky::*;
+pub use sound::*;
 
 use super::CommandProperties;
 #[allow(clippy::wildcard_imports, reason = "greatly simplifies macros")]
 use crate::types::*;
+use crate::versions::EPHEMERAL_SOUND_PROTOCOL_VERSION;
+use crate::versions::SOUND_START_TIME_PROTOCOL_VERSION;
 use crate::wire::audit::audit_command;
 use crate::wire::channel_id::ChannelId;
 use crate::wire::deser::Deserialize;
 use crate::wire::deser::DeserializeError;
 use crate::wire::deser::DeserializeResult;
 use crate::wire::deser::Deserializer;
+use crate::wire::packet::FAR_BLOCKS_PROTOCOL_VERSION;
 use crate::wire::ser::Serialize;
 use crate::wire::ser::SerializeResult;
 use crate::wire::ser::Serializer;
@@ -93,7 +100,8 @@ define_protocol!(41, 0x4f457403, ToClient, ToClientCommand => {
     SrpBytesSB, 0x60, Default, true => SrpBytesSBSpec,
     FormspecPrepend, 0x61, Default, true => FormspecPrependSpec,
     MinimapModes, 0x62, Default, true => MinimapModesSpec,
-    SetLighting, 0x63, Default, true => SetLightingSpec
+    SetLighting, 0x63, Default, true => SetLightingSpec,
+    FarBlocks, 0x64, Response, true, since FAR_BLOCKS_PROTOCOL_VERSION => FarBlocksSpec
 });
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -126,6 +134,19 @@ pub struct BlockdataSpec {
     pub network_specific_version: u8,
 }
 
+/// A low-detail block covering `2^level` map blocks along each axis.
+///
+/// Every node of the contained block represents a cube of `2^level` nodes along each axis. Only
+/// clients announcing at least `FAR_BLOCKS_PROTOCOL_VERSION` may receive this command.
+#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+pub struct FarBlocksSpec {
+    /// position of the map block with the lowest coordinates covered by this block
+    pub pos: I16Vec3,
+    /// the downsampling level; `0` would be identical to a regular map block
+    pub level: u8,
+    pub block: TransferrableMapBlock,
+}
+
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AddnodeSpec {
     pub pos: I16Vec3,
@@ -147,8 +168,11 @@ pub struct InventorySpec {
 pub struct TimeOfDaySpec {
     pub time_of_day: u16,
     pub time_speed: Option<f32>,
+    /// fields of newer protocol versions; see [`TrailingBytes`]
+    pub extra: TrailingBytes,
 }
 
+/// The restrictions of client-side mods; see [`CsmRestrictions`] for a typed representation
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct CsmRestrictionFlagsSpec {
     pub csm_restriction_flags: u64,
@@ -172,10 +196,10 @@ pub struct MediaPushSpec {
 pub struct TCChatMessageSpec {
     pub version: u8,
     pub message_type: u8,
-    #[wrap(WString)]
+    #[wrap(LossyWString)]
     pub sender: String,
-    #[wrap(WString)]
-    pub message: String,
+    #[wrap(LossyWString<RichText>)]
+    pub message: RichText,
     pub timestamp: u64,
 }
 
@@ -191,6 +215,8 @@ pub struct ActiveObjectRemoveAddSpec {
 pub struct HpSpec {
     pub hp: u16,
     pub damage_effect: Option<bool>,
+    /// fields of newer protocol versions; see [`TrailingBytes`]
+    pub extra: TrailingBytes,
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -211,6 +237,8 @@ pub struct FovSpec {
     pub fov: f32,
     pub is_multiplier: bool,
     pub transition_time: Option<f32>,
+    /// fields of newer protocol versions; see [`TrailingBytes`]
+    pub extra: TrailingBytes,
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -240,19 +268,33 @@ pub struct AnnounceMediaSpec {
     pub remote_servers: String,
 }
 
+/// Starts playing a sound; the layout of `Server::playSound` of the engine
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct PlaySoundSpec {
+    /// refers to the sound in later commands; [`EPHEMERAL_SOUND_ID`] for ephemeral sounds
     pub server_id: i32,
     pub spec_name: String,
     pub spec_gain: f32,
-    pub typ: u8,
+    pub typ: SoundLocationType,
+    /// the position of positional sounds and the current position of the object of object
+    /// sounds, in world units (10 per node); ignored for local sounds
     pub pos: Vec3,
+    /// the object an object sound follows; ignored for other sounds
     pub object_id: u16,
     pub spec_loop: bool,
-    pub spec_fade: Option<f32>,
-    pub spec_pitch: Option<f32>,
-    pub ephemeral: Option<bool>,
-    pub start_type: f32,
+    /// the gain per second the sound fades in with; `0.0` plays it at full gain right away
+    #[default]
+    pub spec_fade: f32,
+    #[default(1.0)]
+    pub spec_pitch: f32,
+    /// ephemeral sounds can't be referred to, so the client doesn't report when they ended
+    #[default]
+    #[since(EPHEMERAL_SOUND_PROTOCOL_VERSION)]
+    pub ephemeral: bool,
+    /// the offset into the sound to start playing at, in seconds
+    #[default]
+    #[since(SOUND_START_TIME_PROTOCOL_VERSION)]
+    pub start_time: f32,
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -321,6 +363,8 @@ pub struct HudaddSpec {
     pub z_index: Option<i16>,
     pub text2: Option<String>,
     pub style: Option<u32>,
+    /// fields of newer protocol versions; see [`TrailingBytes`]
+    pub extra: TrailingBytes,
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -398,15 +442,39 @@ pub struct UpdatePlayerListSpec {
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct TCModchannelMsgSpec {
     pub channel_name: String,
+    /// name of the sending player; empty for messages of the server
     pub sender: String,
     pub channel_msg: String,
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct ModchannelSignalSpec {
-    pub signal_tmp: u8,
+    pub signal: ModChannelSignal,
     pub channel: String,
-    pub state: Option<u8>,
+    /// the new state; only sent along with [`ModChannelSignal::SetState`]
+    pub state: Option<ModChannelState>,
+}
+
+impl ModchannelSignalSpec {
+    /// Creates a signal which doesn't change the state of the channel.
+    #[must_use]
+    pub fn new(signal: ModChannelSignal, channel: String) -> Self {
+        Self {
+            signal,
+            channel,
+            state: None,
+        }
+    }
+
+    /// Creates a signal changing the state of the channel.
+    #[must_use]
+    pub fn set_state(channel: String, state: ModChannelState) -> Self {
+        Self {
+            signal: ModChannelSignal::SetState,
+            channel,
+            state: Some(state),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -2,16 +2,20 @@ use crate::wire::{
     deser::{Deserialize, DeserializeResult, Deserializer},
     ser::{Serialize, SerializeResult, Serializer},
 };
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AccessDeniedCommand {
     pub code: AccessDeniedCode,
+    /// message shown instead of the default text of the code; may be empty
+    #[default]
     pub reason: String,
+    /// whether the client is asked to reconnect
+    #[default]
     pub reconnect: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToJson)]
 pub enum AccessDeniedCode {
     WrongPassword,
     UnexpectedData,
@@ -23,9 +27,10 @@ pub enum AccessDeniedCode {
     EmptyPassword,
     AlreadyConnected,
     ServerFail,
-    CustomString(String),
-    Shutdown(String, bool), // custom message (or blank), should_reconnect
-    Crash(String, bool),    // custom message (or blank), should_reconnect
+    /// the reason is given by the message of the command
+    CustomString,
+    Shutdown,
+    Crash,
 }
 
 impl Serialize for AccessDeniedCode {
@@ -44,23 +49,9 @@ impl Serialize for AccessDeniedCode {
             EmptyPassword => u8::serialize(&7, ser),
             AlreadyConnected => u8::serialize(&8, ser),
             ServerFail => u8::serialize(&9, ser),
-            CustomString(msg) => {
-                u8::serialize(&10, ser)?;
-                String::serialize(msg, ser)?;
-                Ok(())
-            }
-            Shutdown(msg, reconnect) => {
-                u8::serialize(&11, ser)?;
-                String::serialize(msg, ser)?;
-                bool::serialize(reconnect, ser)?;
-                Ok(())
-            }
-            Crash(msg, reconnect) => {
-                u8::serialize(&12, ser)?;
-                String::serialize(msg, ser)?;
-                bool::serialize(reconnect, ser)?;
-                Ok(())
-            }
+            CustomString => u8::serialize(&10, ser),
+            Shutdown => u8::serialize(&11, ser),
+            Crash => u8::serialize(&12, ser),
         }
     }
 }
@@ -83,23 +74,19 @@ impl Deserialize for AccessDeniedCode {
             7 => Ok(EmptyPassword),
             8 => Ok(AlreadyConnected),
             9 => Ok(ServerFail),
-            10 => Ok(CustomString(String::deserialize(deser)?)),
-            11 => Ok(Shutdown(
-                String::deserialize(deser)?,
-                (u8::deserialize(deser)? & 1) != 0,
-            )),
-            12 => Ok(Crash(
-                String::deserialize(deser)?,
-                (u8::deserialize(deser)? & 1) != 0,
-            )),
-            _ => Ok(CustomString(String::deserialize(deser)?)),
+            10 => Ok(CustomString),
+            11 => Ok(Shutdown),
+            12 => Ok(Crash),
+            // like the engine, treat unknown codes as if they had a custom message
+            _ => Ok(CustomString),
         }
     }
 }
 
 impl AccessDeniedCode {
+    /// Returns the default message of the code, as shown by the engine if no message is given.
     #[must_use]
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         #![allow(clippy::enum_glob_use, reason = "improves readability")]
         use AccessDeniedCode::*;
         match self {
@@ -121,27 +108,132 @@ impl AccessDeniedCode {
                 "Another client is connected with this name.  If your client closed unexpectedly, try again in a minute."
             }
             ServerFail => "Internal server error",
-            CustomString(msg) => {
-                if msg.is_empty() {
-                    "unknown"
-                } else {
-                    msg
-                }
-            }
-            Shutdown(msg, _) => {
-                if msg.is_empty() {
-                    "Server shutting down"
-                } else {
-                    msg
-                }
+            CustomString => "unknown",
+            Shutdown => "Server shutting down",
+            Crash => "The server has experienced an internal error.  You will now be disconnected.",
+        }
+    }
+}
+
+/// Why the server disconnects a client
+///
+/// This covers all [`AccessDeniedCode`]s; the ones which may come with a message carry it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    WrongPassword,
+    UnexpectedData,
+    Singleplayer,
+    WrongVersion,
+    WrongCharsInName,
+    WrongName,
+    TooManyUsers,
+    EmptyPassword,
+    AlreadyConnected,
+    ServerFail,
+    /// a message shown to the player, e.g. why they have been kicked
+    Custom(String),
+    /// the server is shutting down; an empty message shows the default text
+    Shutdown(String),
+    /// the server has crashed; an empty message shows the default text
+    Crash(String),
+}
+
+impl DisconnectReason {
+    #[must_use]
+    pub fn code(&self) -> AccessDeniedCode {
+        match self {
+            Self::WrongPassword => AccessDeniedCode::WrongPassword,
+            Self::UnexpectedData => AccessDeniedCode::UnexpectedData,
+            Self::Singleplayer => AccessDeniedCode::Singleplayer,
+            Self::WrongVersion => AccessDeniedCode::WrongVersion,
+            Self::WrongCharsInName => AccessDeniedCode::WrongCharsInName,
+            Self::WrongName => AccessDeniedCode::WrongName,
+            Self::TooManyUsers => AccessDeniedCode::TooManyUsers,
+            Self::EmptyPassword => AccessDeniedCode::EmptyPassword,
+            Self::AlreadyConnected => AccessDeniedCode::AlreadyConnected,
+            Self::ServerFail => AccessDeniedCode::ServerFail,
+            Self::Custom(_) => AccessDeniedCode::CustomString,
+            Self::Shutdown(_) => AccessDeniedCode::Shutdown,
+            Self::Crash(_) => AccessDeniedCode::Crash,
+        }
+    }
+
+    /// Returns the message the player is going to see.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Custom(message) | Self::Shutdown(message) | Self::Crash(message)
+                if !message.is_empty() =>
+            {
+                message
             }
-            Crash(msg, _) => {
-                if msg.is_empty() {
-                    "The server has experienced an internal error.  You will now be disconnected."
-                } else {
-                    msg
-                }
+            _ => self.code().to_str(),
+        }
+    }
+
+    /// Creates the command telling the client about the disconnect.
+    #[must_use]
+    pub fn to_command(&self, reconnect: bool) -> AccessDeniedCommand {
+        let reason = match self {
+            Self::Custom(message) | Self::Shutdown(message) | Self::Crash(message) => {
+                message.clone()
             }
+            // the client shows a translated text instead
+            _ => String::new(),
+        };
+        AccessDeniedCommand {
+            code: self.code(),
+            reason,
+            reconnect,
+        }
+    }
+}
+
+impl From<&AccessDeniedCommand> for DisconnectReason {
+    fn from(command: &AccessDeniedCommand) -> Self {
+        let AccessDeniedCommand { code, reason, .. } = command;
+        match code {
+            AccessDeniedCode::WrongPassword => Self::WrongPassword,
+            AccessDeniedCode::UnexpectedData => Self::UnexpectedData,
+            AccessDeniedCode::Singleplayer => Self::Singleplayer,
+            AccessDeniedCode::WrongVersion => Self::WrongVersion,
+            AccessDeniedCode::WrongCharsInName => Self::WrongCharsInName,
+            AccessDeniedCode::WrongName => Self::WrongName,
+            AccessDeniedCode::TooManyUsers => Self::TooManyUsers,
+            AccessDeniedCode::EmptyPassword => Self::EmptyPassword,
+            AccessDeniedCode::AlreadyConnected => Self::AlreadyConnected,
+            AccessDeniedCode::ServerFail => Self::ServerFail,
+            AccessDeniedCode::CustomString => Self::Custom(reason.clone()),
+            AccessDeniedCode::Shutdown => Self::Shutdown(reason.clone()),
+            AccessDeniedCode::Crash => Self::Crash(reason.clone()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessDeniedCode, AccessDeniedCommand, DisconnectReason};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn custom_messages_are_sent_once() {
+        let reason = DisconnectReason::Custom("griefing".into());
+        let command = reason.to_command(false);
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(true), 16);
+        AccessDeniedCommand::serialize(&command, &mut ser).unwrap();
+        let data = ser.take();
+        // code, message, reconnect
+        assert_eq!(data, b"\x0a\x00\x08griefing\x00");
+
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(false), &data);
+        let received = AccessDeniedCommand::deserialize(&mut deser).unwrap();
+        assert_eq!(DisconnectReason::from(&received), reason);
+        assert_eq!(reason.message(), "griefing");
+        assert_eq!(
+            DisconnectReason::Shutdown(String::new()).message(),
+            AccessDeniedCode::Shutdown.to_str()
+        );
+    }
+}
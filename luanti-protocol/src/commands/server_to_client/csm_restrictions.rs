@@ -0,0 +1,108 @@
+use super::CsmRestrictionFlagsSpec;
+
+bitflags::bitflags! {
+    /// The client-side modding features a server may disable; `CSMRestrictionFlags` of the engine
+    ///
+    /// Bits unknown to this crate are being retained, so restrictions of newer servers aren't lost.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct CsmRestrictionFlags: u64 {
+        /// don't load client-provided mods (nor `builtin`)
+        const LOAD_CLIENT_MODS = 1 << 0;
+        /// don't let client mods send chat messages
+        const CHAT_MESSAGES = 1 << 1;
+        /// don't let client mods look up item definitions
+        const READ_ITEMDEFS = 1 << 2;
+        /// don't let client mods look up node definitions
+        const READ_NODEDEFS = 1 << 3;
+        /// limit node lookups of client mods to the `noderange` around the player
+        const LOOKUP_NODES = 1 << 4;
+        /// don't let client mods look up information about players
+        const READ_PLAYERINFO = 1 << 5;
+    }
+}
+
+/// The restrictions of client-side mods as sent by [`CsmRestrictionFlagsSpec`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CsmRestrictions {
+    pub flags: CsmRestrictionFlags,
+    /// the distance in nodes around the player client mods may look up nodes within; only
+    /// meaningful with [`CsmRestrictionFlags::LOOKUP_NODES`]
+    pub noderange: u32,
+}
+
+impl CsmRestrictions {
+    /// Allows client mods to do everything; what clients assume until told otherwise
+    pub const UNRESTRICTED: Self = Self {
+        flags: CsmRestrictionFlags::empty(),
+        noderange: 0,
+    };
+
+    /// Returns `true` if the given features are disabled.
+    #[must_use]
+    pub fn restricts(&self, flags: CsmRestrictionFlags) -> bool {
+        self.flags.contains(flags)
+    }
+
+    /// Returns `true` if client mods may look up a node at the given distance from the player.
+    #[must_use]
+    pub fn allows_node_lookup(&self, distance: u32) -> bool {
+        !self.restricts(CsmRestrictionFlags::LOOKUP_NODES) || distance <= self.noderange
+    }
+}
+
+impl Default for CsmRestrictions {
+    /// The defaults of the engine's `csm_restriction_flags` and `csm_restriction_noderange`:
+    /// client mods may be loaded but nothing else
+    fn default() -> Self {
+        Self {
+            flags: CsmRestrictionFlags::all().difference(CsmRestrictionFlags::LOAD_CLIENT_MODS),
+            noderange: 0,
+        }
+    }
+}
+
+impl From<CsmRestrictions> for CsmRestrictionFlagsSpec {
+    fn from(restrictions: CsmRestrictions) -> Self {
+        Self {
+            csm_restriction_flags: restrictions.flags.bits(),
+            csm_restriction_noderange: restrictions.noderange,
+        }
+    }
+}
+
+impl From<&CsmRestrictionFlagsSpec> for CsmRestrictions {
+    fn from(spec: &CsmRestrictionFlagsSpec) -> Self {
+        Self {
+            flags: CsmRestrictionFlags::from_bits_retain(spec.csm_restriction_flags),
+            noderange: spec.csm_restriction_noderange,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsmRestrictionFlags, CsmRestrictions};
+    use crate::commands::server_to_client::CsmRestrictionFlagsSpec;
+
+    #[test]
+    fn restrictions_match_the_engine_flags() {
+        let spec = CsmRestrictionFlagsSpec::from(CsmRestrictions::default());
+        assert_eq!(spec.csm_restriction_flags, 62);
+        assert_eq!(spec.csm_restriction_noderange, 0);
+
+        let restrictions = CsmRestrictions::from(&CsmRestrictionFlagsSpec {
+            csm_restriction_flags: 0x8000_0011,
+            csm_restriction_noderange: 8,
+        });
+        assert!(restrictions.restricts(CsmRestrictionFlags::LOAD_CLIENT_MODS));
+        assert!(!restrictions.restricts(CsmRestrictionFlags::CHAT_MESSAGES));
+        assert!(restrictions.allows_node_lookup(8));
+        assert!(!restrictions.allows_node_lookup(9));
+        // unknown bits survive a round trip
+        assert_eq!(
+            CsmRestrictionFlagsSpec::from(restrictions).csm_restriction_flags,
+            0x8000_0011
+        );
+        assert!(CsmRestrictions::UNRESTRICTED.allows_node_lookup(u32::MAX));
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::bail;
 use glam::{IVec2, Vec2, Vec3};
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
 use crate::wire::{
     deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
@@ -13,7 +13,12 @@ pub struct HudchangeCommand {
     pub stat: HudStat,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A single property of a HUD element along with its new value, corresponding to
+/// `HudElementStat` in C++ land
+///
+/// The type of the value depends on the property, so each variant carries the type being sent by
+/// Luanti's `sendHUDChange`.
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum HudStat {
     Pos(Vec2),
     Name(String),
@@ -26,75 +31,65 @@ pub enum HudStat {
     Offset(Vec2),
     WorldPos(Vec3),
     Size(IVec2),
-    ZIndex(u32),
+    /// sent as 32 bits but clamped to 16 bits by the client
+    ZIndex(i32),
     Text2(String),
     Style(u32),
 }
 
+impl HudStat {
+    // HudElementStat
+    const POS: u8 = 0;
+    const NAME: u8 = 1;
+    const SCALE: u8 = 2;
+    const TEXT: u8 = 3;
+    const NUMBER: u8 = 4;
+    const ITEM: u8 = 5;
+    const DIR: u8 = 6;
+    const ALIGN: u8 = 7;
+    const OFFSET: u8 = 8;
+    const WORLD_POS: u8 = 9;
+    const SIZE: u8 = 10;
+    const Z_INDEX: u8 = 11;
+    const TEXT2: u8 = 12;
+    const STYLE: u8 = 13;
+
+    /// Returns the number identifying the property on the wire.
+    #[must_use]
+    pub fn stat(&self) -> u8 {
+        match self {
+            HudStat::Pos(_) => Self::POS,
+            HudStat::Name(_) => Self::NAME,
+            HudStat::Scale(_) => Self::SCALE,
+            HudStat::Text(_) => Self::TEXT,
+            HudStat::Number(_) => Self::NUMBER,
+            HudStat::Item(_) => Self::ITEM,
+            HudStat::Dir(_) => Self::DIR,
+            HudStat::Align(_) => Self::ALIGN,
+            HudStat::Offset(_) => Self::OFFSET,
+            HudStat::WorldPos(_) => Self::WORLD_POS,
+            HudStat::Size(_) => Self::SIZE,
+            HudStat::ZIndex(_) => Self::Z_INDEX,
+            HudStat::Text2(_) => Self::TEXT2,
+            HudStat::Style(_) => Self::STYLE,
+        }
+    }
+}
+
 impl Serialize for HudStat {
     type Input = Self;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
         #![allow(clippy::enum_glob_use, reason = "improves readability")]
         use HudStat::*;
+        u8::serialize(&value.stat(), ser)?;
         match value {
-            Pos(value) => {
-                u8::serialize(&0, ser)?;
-                Vec2::serialize(value, ser)?;
-            }
-            Name(value) => {
-                u8::serialize(&1, ser)?;
-                String::serialize(value, ser)?;
-            }
-            Scale(value) => {
-                u8::serialize(&2, ser)?;
-                Vec2::serialize(value, ser)?;
-            }
-            Text(value) => {
-                u8::serialize(&3, ser)?;
-                String::serialize(value, ser)?;
-            }
-            Number(value) => {
-                u8::serialize(&4, ser)?;
-                u32::serialize(value, ser)?;
-            }
-            Item(value) => {
-                u8::serialize(&5, ser)?;
-                u32::serialize(value, ser)?;
-            }
-            Dir(value) => {
-                u8::serialize(&6, ser)?;
-                u32::serialize(value, ser)?;
-            }
-            Align(value) => {
-                u8::serialize(&7, ser)?;
-                Vec2::serialize(value, ser)?;
-            }
-            Offset(value) => {
-                u8::serialize(&8, ser)?;
-                Vec2::serialize(value, ser)?;
-            }
-            WorldPos(value) => {
-                u8::serialize(&9, ser)?;
-                Vec3::serialize(value, ser)?;
-            }
-            Size(value) => {
-                u8::serialize(&10, ser)?;
-                IVec2::serialize(value, ser)?;
-            }
-            ZIndex(value) => {
-                u8::serialize(&11, ser)?;
-                u32::serialize(value, ser)?;
-            }
-            Text2(value) => {
-                u8::serialize(&12, ser)?;
-                String::serialize(value, ser)?;
-            }
-            Style(value) => {
-                u8::serialize(&13, ser)?;
-                u32::serialize(value, ser)?;
-            }
+            Pos(value) | Scale(value) | Align(value) | Offset(value) => Vec2::serialize(value, ser),
+            Name(value) | Text(value) | Text2(value) => String::serialize(value, ser),
+            Number(value) | Item(value) | Dir(value) | Style(value) => u32::serialize(value, ser),
+            WorldPos(value) => Vec3::serialize(value, ser),
+            Size(value) => IVec2::serialize(value, ser),
+            ZIndex(value) => i32::serialize(value, ser),
         }
-        Ok(())
     }
 }
 
@@ -105,23 +100,78 @@ impl Deserialize for HudStat {
         use HudStat::*;
         let stat = u8::deserialize(deser)?;
         match stat {
-            0 => Ok(Pos(Vec2::deserialize(deser)?)),
-            1 => Ok(Name(String::deserialize(deser)?)),
-            2 => Ok(Scale(Vec2::deserialize(deser)?)),
-            3 => Ok(Text(String::deserialize(deser)?)),
-            4 => Ok(Number(u32::deserialize(deser)?)),
-            5 => Ok(Item(u32::deserialize(deser)?)),
-            6 => Ok(Dir(u32::deserialize(deser)?)),
-            7 => Ok(Align(Vec2::deserialize(deser)?)),
-            8 => Ok(Offset(Vec2::deserialize(deser)?)),
-            9 => Ok(WorldPos(Vec3::deserialize(deser)?)),
-            10 => Ok(Size(IVec2::deserialize(deser)?)),
-            11 => Ok(ZIndex(u32::deserialize(deser)?)),
-            12 => Ok(Text2(String::deserialize(deser)?)),
-            13 => Ok(Style(u32::deserialize(deser)?)),
-            _ => bail!(DeserializeError::InvalidValue(String::from(
-                "HudStat invalid stat",
+            Self::POS => Ok(Pos(Vec2::deserialize(deser)?)),
+            Self::NAME => Ok(Name(String::deserialize(deser)?)),
+            Self::SCALE => Ok(Scale(Vec2::deserialize(deser)?)),
+            Self::TEXT => Ok(Text(String::deserialize(deser)?)),
+            Self::NUMBER => Ok(Number(u32::deserialize(deser)?)),
+            Self::ITEM => Ok(Item(u32::deserialize(deser)?)),
+            Self::DIR => Ok(Dir(u32::deserialize(deser)?)),
+            Self::ALIGN => Ok(Align(Vec2::deserialize(deser)?)),
+            Self::OFFSET => Ok(Offset(Vec2::deserialize(deser)?)),
+            Self::WORLD_POS => Ok(WorldPos(Vec3::deserialize(deser)?)),
+            Self::SIZE => Ok(Size(IVec2::deserialize(deser)?)),
+            Self::Z_INDEX => Ok(ZIndex(i32::deserialize(deser)?)),
+            Self::TEXT2 => Ok(Text2(String::deserialize(deser)?)),
+            Self::STYLE => Ok(Style(u32::deserialize(deser)?)),
+            _ => bail!(DeserializeError::InvalidValue(format!(
+                "HudStat invalid stat {stat}"
             ))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec2, Vec2, Vec3};
+
+    use super::{HudStat, HudchangeCommand};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    fn serialize(stat: HudStat) -> Vec<u8> {
+        let command = HudchangeCommand { server_id: 7, stat };
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(true), 32);
+        HudchangeCommand::serialize(&command, &mut ser).unwrap();
+        let data = ser.take();
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(false), &data);
+        assert_eq!(HudchangeCommand::deserialize(&mut deser).unwrap(), command);
+        data
+    }
+
+    #[test]
+    fn every_stat_has_its_own_value_type() {
+        let vec2 = Vec2::new(0.5, -1.0);
+        let stats = [
+            (HudStat::Pos(vec2), 8),
+            (HudStat::Name("hp".into()), 4),
+            (HudStat::Scale(vec2), 8),
+            (HudStat::Text("heart.png".into()), 11),
+            (HudStat::Number(20), 4),
+            (HudStat::Item(3), 4),
+            (HudStat::Dir(1), 4),
+            (HudStat::Align(vec2), 8),
+            (HudStat::Offset(vec2), 8),
+            (HudStat::WorldPos(Vec3::new(1.0, 2.0, 3.0)), 12),
+            (HudStat::Size(IVec2::new(24, -24)), 8),
+            (HudStat::ZIndex(-1), 4),
+            (HudStat::Text2("bg.png".into()), 8),
+            (HudStat::Style(2), 4),
+        ];
+        for (expected_stat, (stat, value_len)) in (0..).zip(stats) {
+            assert_eq!(stat.stat(), expected_stat);
+            let data = serialize(stat);
+            // server id, stat, value
+            assert_eq!(data[..5], [0, 0, 0, 7, expected_stat]);
+            assert_eq!(data.len(), 5 + value_len, "stat {expected_stat}");
+        }
+
+        assert_eq!(serialize(HudStat::ZIndex(-1))[5..], [0xff; 4]);
+        let mut deser = Deserializer::new(
+            ProtocolContext::latest_for_receive(false),
+            &[0, 0, 0, 7, 14, 0, 0, 0, 0],
+        );
+        HudchangeCommand::deserialize(&mut deser).unwrap_err();
+    }
+}
@@ -1,5 +1,6 @@
 use crate::types::{
-    Array16, Array32, Option16, Pair, SColor, SoundSpec, Wrapped16, ZLibCompressed,
+    Array16, Array32, ItemGroups, Option16, Pair, RichText, SColor, SoundSpec, Wrapped16,
+    ZLibCompressed,
 };
 use crate::wire::{
     deser::{Deserialize, DeserializeResult, Deserializer},
@@ -29,7 +30,7 @@ pub struct ItemDef {
     pub version: u8,
     pub item_type: ItemType,
     pub name: String,
-    pub description: String,
+    pub description: RichText,
     pub inventory_image: String,
     pub wield_image: String,
     pub wield_scale: Vec3,
@@ -37,8 +38,7 @@ pub struct ItemDef {
     pub usable: bool,
     pub liquids_pointable: bool,
     pub tool_capabilities: Option16<ToolCapabilities>,
-    #[wrap(Array16<Pair<String, i16>>)]
-    pub groups: Vec<(String, i16)>,
+    pub groups: ItemGroups,
     pub node_placement_prediction: String,
     pub sound_place: SoundSpec,
     pub sound_place_failed: SoundSpec,
@@ -47,7 +47,7 @@ pub struct ItemDef {
     pub color: SColor,
     pub inventory_overlay: String,
     pub wield_overlay: String,
-    pub short_description: Option<String>,
+    pub short_description: Option<RichText>,
     pub sound_use: Option<SoundSpec>,
     pub sound_use_air: Option<SoundSpec>,
     pub place_param2: Option<u8>,
@@ -82,6 +82,112 @@ pub struct ToolGroupCap {
     pub times: Vec<(i16, f32)>,
 }
 
+/// The highest wear of a tool; it breaks when reaching it
+pub const MAX_WEAR: u16 = u16::MAX;
+
+/// The outcome of digging a node with a tool, see [`ToolCapabilities::dig_params`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigParams {
+    /// the time it takes to dig the node in seconds
+    pub time: f32,
+    /// the wear added to the tool
+    pub wear: u16,
+    /// the group of the tool capability being used
+    pub main_group: String,
+}
+
+impl ToolCapabilities {
+    /// Calculates how long it takes to dig a node of the given groups and how much this wears the
+    /// tool, like Luanti's `getDigParams`. Returns `None` if the tool can't dig the node at all.
+    ///
+    /// `initial_wear` is the wear of the tool before digging. Clients use this to predict the
+    /// digging time.
+    #[must_use]
+    pub fn dig_params(&self, groups: &ItemGroups, initial_wear: u16) -> Option<DigParams> {
+        // nodes in the group `dig_immediate` can be dug quickly by any tool without wearing it
+        if !self.has_group_cap("dig_immediate") {
+            let time = match groups.get("dig_immediate") {
+                2 => Some(0.5),
+                3 => Some(0.0),
+                _ => None,
+            };
+            if let Some(time) = time {
+                return Some(DigParams {
+                    time,
+                    wear: 0,
+                    main_group: "dig_immediate".into(),
+                });
+            }
+        }
+
+        let level = groups.get("level");
+        let mut result: Option<DigParams> = None;
+        for (group, cap) in &self.group_caps {
+            let level_diff = i32::from(cap.maxlevel) - i32::from(level);
+            if level_diff < 0 {
+                continue;
+            }
+            let Some(mut time) = cap.time(groups.get(group)) else {
+                continue;
+            };
+            if level_diff > 1 {
+                #[expect(clippy::cast_precision_loss, reason = "levels are small numbers")]
+                let level_diff = level_diff as f32;
+                time /= level_diff;
+            }
+            if result.as_ref().is_some_and(|result| result.time <= time) {
+                continue;
+            }
+            // tools of a higher level than the node last exponentially longer
+            let uses = i64::from(cap.uses.max(0))
+                .saturating_mul(3_i64.saturating_pow(level_diff.unsigned_abs()));
+            let uses = u32::try_from(uses).unwrap_or(u32::MAX);
+            result = Some(DigParams {
+                time,
+                wear: wear_per_use(uses, initial_wear),
+                main_group: group.clone(),
+            });
+        }
+        result
+    }
+
+    /// Returns `true` if the tool has a capability for the given group.
+    #[must_use]
+    pub fn has_group_cap(&self, group: &str) -> bool {
+        self.group_caps.iter().any(|(name, _)| name == group)
+    }
+}
+
+impl ToolGroupCap {
+    /// Returns the time it takes to dig a node with the given rating of the group in seconds or
+    /// `None` if the rating isn't supported.
+    #[must_use]
+    pub fn time(&self, rating: i16) -> Option<f32> {
+        self.times
+            .iter()
+            .find_map(|(level, time)| (*level == rating).then_some(*time))
+    }
+}
+
+/// Returns the wear a single use adds to a tool which can be used `uses` times; `0` means
+/// infinitely.
+///
+/// Like Luanti's `calculateResultWear`, the remainder of spreading the wear over all uses is being
+/// added towards the end of the tool's lifetime, so it breaks after exactly `uses` uses.
+#[must_use]
+pub fn wear_per_use(uses: u32, initial_wear: u16) -> u16 {
+    if uses == 0 {
+        return 0;
+    }
+    let range = u32::from(MAX_WEAR) + 1;
+    let wear_normal = range / uses;
+    let blocks_oversize = range % uses;
+    let wear_extra = u32::from(
+        blocks_oversize > 0 && u32::from(initial_wear) >= (uses - blocks_oversize) * wear_normal,
+    );
+    u16::try_from(wear_normal + wear_extra).unwrap_or(MAX_WEAR)
+}
+
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub enum ItemType {
     None,
@@ -89,3 +195,72 @@ pub enum ItemType {
     Craft,
     Tool,
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use crate::types::ItemGroups;
+
+    use super::{ToolCapabilities, ToolGroupCap, wear_per_use};
+
+    fn pickaxe() -> ToolCapabilities {
+        ToolCapabilities {
+            version: 5,
+            full_punch_interval: 1.0,
+            max_drop_level: 1,
+            group_caps: vec![(
+                "cracky".into(),
+                ToolGroupCap {
+                    uses: 20,
+                    maxlevel: 2,
+                    times: vec![(1, 4.0), (2, 1.6), (3, 0.8)],
+                },
+            )],
+            damage_groups: vec![("fleshy".into(), 2)],
+            punch_attack_uses: None,
+        }
+    }
+
+    #[test]
+    fn dig_time_depends_on_the_groups() {
+        let pickaxe = pickaxe();
+
+        let stone: ItemGroups = [("cracky", 3)].into_iter().collect();
+        let params = pickaxe.dig_params(&stone, 0).unwrap();
+        // the tool is two levels above the node
+        assert!((params.time - 0.4).abs() < f32::EPSILON);
+        assert_eq!(params.wear, wear_per_use(180, 0));
+        assert_eq!(params.main_group, "cracky");
+
+        let obsidian: ItemGroups = [("cracky", 1), ("level", 2)].into_iter().collect();
+        let obsidian_params = pickaxe.dig_params(&obsidian, 0).unwrap();
+        assert!((obsidian_params.time - 4.0).abs() < f32::EPSILON);
+        assert_eq!(obsidian_params.wear, wear_per_use(20, 0));
+
+        let too_hard: ItemGroups = [("cracky", 1), ("level", 3)].into_iter().collect();
+        assert_eq!(pickaxe.dig_params(&too_hard, 0), None);
+        let wood: ItemGroups = [("choppy", 2)].into_iter().collect();
+        assert_eq!(pickaxe.dig_params(&wood, 0), None);
+
+        let torch: ItemGroups = [("dig_immediate", 3)].into_iter().collect();
+        let torch_params = pickaxe.dig_params(&torch, 0).unwrap();
+        assert!(torch_params.time.abs() < f32::EPSILON);
+        assert_eq!(torch_params.wear, 0);
+    }
+
+    #[test]
+    fn tools_break_after_all_uses() {
+        assert_eq!(wear_per_use(0, 0), 0);
+        assert_eq!(wear_per_use(128, 0), 512);
+
+        // 65536 isn't divisible by 130; the last uses wear the tool a bit more
+        let mut wear = 0_u32;
+        for _ in 0..129 {
+            wear += u32::from(wear_per_use(130, u16::try_from(wear).unwrap()));
+        }
+        assert!(wear < 0x1_0000);
+        wear += u32::from(wear_per_use(130, u16::try_from(wear).unwrap()));
+        assert!(wear >= 0x1_0000);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::schema::{Describe, TypeSchema};
 use crate::types::{Array16, LongString, RangedParameter, TileAnimationParams};
 use crate::wire::{
     deser::{Deserialize, DeserializeResult, Deserializer},
@@ -7,9 +8,9 @@ use anyhow::{Context, bail};
 use glam::{Vec2, Vec3};
 use luanti_core::ContentId;
 use luanti_core::MapNode;
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct AddParticlespawnerCommand {
     /// from base class
     pub base: CommonParticleParams,
@@ -37,6 +38,10 @@ pub struct AddParticlespawnerCommand {
     attached_id: u16,
 }
 
+impl Describe for AddParticlespawnerCommand {
+    const SCHEMA: TypeSchema = TypeSchema::Custom;
+}
+
 impl Deserialize for AddParticlespawnerCommand {
     type Output = Self;
 
@@ -215,7 +220,7 @@ pub struct SpawnParticleCommand {
 
 /// This is the send format used by `SendSpawnParticle`
 /// See `ParticleParameters::serialize`
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct ParticleParameters {
     pub pos: Vec3,
     pub vel: Vec3,
@@ -324,7 +329,7 @@ impl Deserialize for ParticleParameters {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 #[expect(clippy::struct_excessive_bools, reason = "this is mandated by the API")]
 pub struct CommonParticleParams {
     pub(crate) collision_detection: bool,
@@ -338,7 +343,7 @@ pub struct CommonParticleParams {
     pub(crate) texture: ServerParticleTexture,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum Attractor {
     None,
     Point(PointAttractor),
@@ -408,7 +413,7 @@ pub struct PlaneAttractor {
     pub direction_attachment: u16,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct ParticleTexture {
     pub blend_mode: BlendMode,
     pub alpha: TweenedParameter<f32>,
@@ -427,7 +432,7 @@ impl Default for ParticleTexture {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct ServerParticleTexture {
     // inherited from base class
     pub base: ParticleTexture,
@@ -533,7 +538,7 @@ impl ParticleTextureFlags {
 /// This is serialized as part of a combined 'flags' field on
 /// `ServerParticleTexture`, so it doesn't implement the methods
 /// on its own.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, ToJson)]
 #[repr(u8)]
 pub enum BlendMode {
     Alpha = Self::ALPHA,
@@ -6,14 +6,14 @@ use crate::{
     },
 };
 use anyhow::bail;
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct SetSkyCommand {
     pub params: SkyboxParams,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct SkyboxParams {
     pub bgcolor: SColor,
     pub r#type: String,
@@ -28,7 +28,7 @@ pub struct SkyboxParams {
     pub fog_color: SColor,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum SkyboxData {
     /// If `skybox_type == "plain"`
     None,
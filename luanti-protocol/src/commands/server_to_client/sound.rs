@@ -0,0 +1,98 @@
+use anyhow::bail;
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+
+use crate::wire::{
+    deser::{Deserialize, DeserializeResult, Deserializer},
+    ser::{Serialize, SerializeResult, Serializer},
+};
+
+/// The id of ephemeral sounds; older clients don't know about ephemeral sounds and report them
+/// as ended by this id, so it must not be used by any other sound
+pub const EPHEMERAL_SOUND_ID: i32 = -1;
+
+/// Where a sound of a [`PlaySoundSpec`] is being played; `SoundLocation` of the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, LuantiSerialize, LuantiDeserialize)]
+pub enum SoundLocationType {
+    /// the sound can be heard everywhere at the same volume
+    Local,
+    /// the sound is emitted at a fixed position
+    Position,
+    /// the sound follows an active object
+    Object,
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{EPHEMERAL_SOUND_ID, SoundLocationType};
+    use crate::commands::server_to_client::PlaySoundSpec;
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    fn context(protocol_version: u16) -> ProtocolContext {
+        ProtocolContext {
+            protocol_version,
+            ..ProtocolContext::latest_for_send(false)
+        }
+    }
+
+    fn serialize(spec: &PlaySoundSpec, protocol_version: u16) -> Vec<u8> {
+        let mut ser = VecSerializer::new(context(protocol_version), 64);
+        PlaySoundSpec::serialize(spec, &mut ser).unwrap();
+        ser.take()
+    }
+
+    fn deserialize(data: &[u8], protocol_version: u16) -> PlaySoundSpec {
+        let mut deser = Deserializer::new(context(protocol_version), data);
+        let spec = PlaySoundSpec::deserialize(&mut deser).unwrap();
+        assert!(!deser.has_remaining());
+        spec
+    }
+
+    #[test]
+    fn fields_follow_the_protocol_version() {
+        let spec = PlaySoundSpec {
+            server_id: 5,
+            spec_name: "default_dig".into(),
+            spec_gain: 1.0,
+            typ: SoundLocationType::Position,
+            pos: Vec3::new(10.0, 20.0, -5.0),
+            object_id: 0,
+            spec_loop: false,
+            spec_fade: 0.0,
+            spec_pitch: 1.0,
+            ephemeral: false,
+            start_time: 0.5,
+        };
+        // the layout of the engine: id, name, gain, type, position, object, loop, fade, pitch,
+        // ephemeral, start time
+        let latest: &[u8] = b"\x00\x00\x00\x05\x00\x0bdefault_dig\x3f\x80\x00\x00\x01\
+            \x41\x20\x00\x00\x41\xa0\x00\x00\xc0\xa0\x00\x00\x00\x00\x00\
+            \x00\x00\x00\x00\x3f\x80\x00\x00\x00\x3f\x00\x00\x00";
+        assert_eq!(serialize(&spec, 47), latest);
+        assert_eq!(deserialize(latest, 47), spec);
+
+        // 5.0.0 neither knows ephemeral sounds nor start times
+        let oldest = serialize(&spec, 37);
+        assert_eq!(oldest.as_slice(), latest.split_at(latest.len() - 5).0);
+        let received = deserialize(&oldest, 37);
+        assert!(received.start_time.abs() < f32::EPSILON);
+        assert_eq!(serialize(&spec, 42).len(), latest.len() - 4);
+
+        // the optional fields of a packet from an outdated sender
+        let legacy = deserialize(latest.split_at(latest.len() - 13).0, 37);
+        assert!(legacy.spec_fade.abs() < f32::EPSILON);
+        assert!((legacy.spec_pitch - 1.0).abs() < f32::EPSILON);
+
+        let ephemeral = PlaySoundSpec {
+            server_id: EPHEMERAL_SOUND_ID,
+            typ: SoundLocationType::Object,
+            object_id: 40000,
+            ephemeral: true,
+            ..spec
+        };
+        assert_eq!(deserialize(&serialize(&ephemeral, 47), 47), ephemeral);
+    }
+}
@@ -20,10 +20,17 @@
     reason = "//TODO there's some unidiomatic code left"
 )]
 
+// only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
+
 pub mod commands;
 pub mod peer;
+pub mod schema;
 pub mod services;
+pub mod test_vectors;
 pub mod types;
+pub mod versions;
 pub mod wire;
 
 pub use commands::CommandRef;
@@ -31,4 +38,3 @@ pub use services::client::LuantiClient;
 pub use services::conn::LuantiConnection;
 pub use services::server::LuantiServer;
 pub use types::CommandDirection;
-pub use wire::audit::audit_on;
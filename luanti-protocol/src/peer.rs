@@ -14,9 +14,11 @@
 mod channel;
 mod reliable_receiver;
 mod reliable_sender;
+pub mod send_queue;
 mod sequence_number;
 mod split_receiver;
 mod split_sender;
+pub mod stats;
 
 use anyhow::Result;
 use anyhow::bail;
@@ -26,19 +28,36 @@ use log::error;
 use log::info;
 use log::trace;
 use log::warn;
+use send_queue::DropPolicies;
+use send_queue::DropPolicy;
+use send_queue::QueueStats;
+use send_queue::ReliableConfig;
+use stats::PeerStats;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::watch;
 
 use crate::commands::Command;
 use crate::commands::CommandProperties;
+use crate::commands::RawCommand;
+use crate::commands::server_to_client::DisconnectReason;
 use crate::commands::server_to_client::ToClientCommand;
+use crate::schema;
+use crate::types::CommandDirection;
+use crate::types::CommandId;
 use crate::types::ProtocolContext;
+use crate::wire::buf_pool::BufPool;
+use crate::wire::buf_pool::BufPoolStats;
+use crate::wire::buf_pool::PooledBuf;
 use crate::wire::channel_id::ChannelId;
+use crate::wire::compression::CompressionConfig;
 use crate::wire::deser::Deserialize;
 use crate::wire::deser::Deserializer;
 use crate::wire::packet::AckBody;
 use crate::wire::packet::ControlBody;
+use crate::wire::packet::InnerBody;
+use crate::wire::packet::MAX_COMMAND_SIZE;
 use crate::wire::packet::Packet;
 use crate::wire::packet::PacketBody;
 use crate::wire::packet::ReliableBody;
@@ -52,6 +71,7 @@ use reliable_sender::ReliableSender;
 use split_receiver::SplitReceiver;
 use split_sender::SplitSender;
 
+use std::fmt;
 use std::net::SocketAddr;
 use std::time::Duration;
 use std::time::Instant;
@@ -61,8 +81,6 @@ const INEXISTENT_PEER_ID_GRACE: Duration = Duration::from_secs(20);
 
 #[derive(thiserror::Error, Debug)]
 pub enum PeerError {
-    #[error("Peer sent disconnect packet")]
-    PeerSentDisconnect,
     #[error("Socket Closed")]
     SocketClosed,
     #[error("Controller Closed")]
@@ -71,15 +89,71 @@ pub enum PeerError {
     InternalPeerError,
 }
 
+/// One of the two ends of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// this end, i.e. the owner of the [`Peer`]
+    Local,
+    /// the other end
+    Remote,
+}
+
+/// The connection has been closed in an orderly manner
+///
+/// This is the error [`Peer::recv`] fails with after the remote side sent a disconnect packet.
+/// Use `downcast_ref` to tell it apart from network failures.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub struct Disconnect {
+    /// the reason of the `AccessDenied` command preceding the disconnect; `None` if the
+    /// connection was closed without giving a reason, e.g. by a player leaving the game
+    pub reason: Option<DisconnectReason>,
+    /// the side which ended the connection; the one having sent `AccessDenied` if there was one
+    pub initiated_by: Side,
+}
+
+impl fmt::Display for Disconnect {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = match self.initiated_by {
+            Side::Local => "local",
+            Side::Remote => "remote",
+        };
+        write!(formatter, "disconnected by the {side} side")?;
+        if let Some(reason) = &self.reason {
+            write!(formatter, ": {}", reason.message())?;
+        }
+        Ok(())
+    }
+}
+
 pub type FullSeqNum = u64;
 
+/// Describes how a received command has been transferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    /// the channel the command arrived on
+    pub channel: ChannelId,
+    /// whether the command arrived in a reliable packet
+    pub reliable: bool,
+}
+
 // This is held by the driver that interfaces with the LuantiSocket
 pub struct Peer {
     remote_addr: SocketAddr,
     remote_is_server: bool,
     /// TODO(paradust): Add back-pressure
-    send: UnboundedSender<Command>,
-    recv: UnboundedReceiver<Result<Command>>,
+    send: UnboundedSender<ControllerToPeer>,
+    recv: UnboundedReceiver<Result<(Transfer, Command)>>,
+    /// how the most recently received command has been transferred
+    last_transfer: Option<Transfer>,
+    stats: watch::Receiver<PeerStats>,
+    drop_policies: watch::Sender<DropPolicies>,
+    reliable_config: watch::Sender<ReliableConfig>,
+    audit: watch::Sender<bool>,
+    compression: watch::Sender<CompressionConfig>,
+    max_command_size: watch::Sender<Option<usize>>,
+    /// whether the peer ids have been exchanged
+    established: watch::Receiver<bool>,
+    buf_pool: BufPool,
 }
 
 impl Peer {
@@ -97,43 +171,248 @@ impl Peer {
     /// Send command to peer
     /// If this fails, the peer has disconnected.
     pub fn send(&self, command: Command) -> Result<()> {
-        self.send.send(command)?;
+        self.send.send(ControllerToPeer::Command(command))?;
+        Ok(())
+    }
+
+    /// Send a command this crate doesn't know about; see [`RawCommand`].
+    ///
+    /// Fails if `id` belongs to a known command of the direction being sent to, as the remote side
+    /// would interpret the payload as that command; use [`Self::send`] instead. Also fails if the
+    /// command is too large to be sent at all.
+    /// If this fails otherwise, the peer has disconnected.
+    pub fn send_raw_command(&self, id: CommandId, payload: Vec<u8>) -> Result<()> {
+        let command = RawCommand {
+            direction: CommandDirection::for_send(self.remote_is_server),
+            id,
+            payload,
+        };
+        if let Some(known) = schema::find(command.direction, id) {
+            bail!(
+                "command id {id} belongs to {} and cannot be sent raw",
+                known.name
+            );
+        }
+        if command.len() > MAX_COMMAND_SIZE {
+            bail!(
+                "raw command {id} is too large to be sent: {} bytes exceed the limit of {MAX_COMMAND_SIZE} bytes",
+                command.len()
+            );
+        }
+        self.send(Command::Unknown(command))
+    }
+
+    /// Send command to peer on the given channel with the given reliability instead of the
+    /// command's defaults.
+    /// The drop policies don't apply to commands sent this way.
+    /// If this fails, the peer has disconnected.
+    pub fn send_on(&self, channel: ChannelId, reliable: bool, command: Command) -> Result<()> {
+        self.send.send(ControllerToPeer::CommandOn {
+            channel,
+            reliable,
+            command,
+        })?;
+        Ok(())
+    }
+
+    /// Send a raw body to the peer.
+    /// Reliable bodies are assigned the next sequence number of the channel and get retransmitted
+    /// until acknowledged, just like commands.
+    /// If this fails, the peer has disconnected.
+    pub fn send_inner(&self, channel: ChannelId, reliable: bool, body: InnerBody) -> Result<()> {
+        self.send.send(ControllerToPeer::Inner {
+            channel,
+            reliable,
+            body,
+        })?;
+        Ok(())
+    }
+
+    /// Send a fully assembled body to the peer as-is.
+    /// This bypasses the channel entirely: reliable bodies keep their sequence number, won't be
+    /// retransmitted, and acknowledgements for them are ignored. Useful for fuzzing.
+    /// If this fails, the peer has disconnected.
+    pub fn send_packet(&self, channel: ChannelId, body: PacketBody) -> Result<()> {
+        self.send.send(ControllerToPeer::Packet { channel, body })?;
+        Ok(())
+    }
+
+    /// Returns the current state of the outgoing queues.
+    #[must_use]
+    pub fn queue_stats(&self) -> QueueStats {
+        self.stats.borrow().queues
+    }
+
+    /// Returns the current state of the outgoing queues and the counters of the incoming reliable
+    /// streams.
+    #[must_use]
+    pub fn stats(&self) -> PeerStats {
+        *self.stats.borrow()
+    }
+
+    /// Returns the counters of the buffer pool shared by all peers of the socket.
+    #[must_use]
+    pub fn buf_pool_stats(&self) -> BufPoolStats {
+        self.buf_pool.stats()
+    }
+
+    /// Returns the policies currently applied when the remote side falls behind.
+    #[must_use]
+    pub fn drop_policies(&self) -> DropPolicies {
+        *self.drop_policies.borrow()
+    }
+
+    /// Replace the policies applied when the remote side falls behind.
+    pub fn set_drop_policies(&self, drop_policies: DropPolicies) {
+        self.drop_policies.send_replace(drop_policies);
+    }
+
+    /// Returns the send window and resend timeout of reliable packets.
+    #[must_use]
+    pub fn reliable_config(&self) -> ReliableConfig {
+        *self.reliable_config.borrow()
+    }
+
+    /// Changes the send window and resend timeout of reliable packets on all channels. Values
+    /// outside the supported ranges are clamped; see [`ReliableConfig::clamped`].
+    pub fn set_reliable_config(&self, reliable_config: ReliableConfig) {
+        self.reliable_config.send_replace(reliable_config.clamped());
+    }
+
+    /// Returns whether received commands are being audited; see [`crate::wire::audit`].
+    #[must_use]
+    pub fn audit(&self) -> bool {
+        *self.audit.borrow()
+    }
+
+    /// Switch auditing of received commands on or off for this peer only.
+    pub fn set_audit(&self, audit: bool) {
+        self.audit.send_replace(audit);
+    }
+
+    /// Returns how compressed payloads of sent commands are being compressed.
+    #[must_use]
+    pub fn compression(&self) -> CompressionConfig {
+        *self.compression.borrow()
+    }
+
+    /// Changes how compressed payloads of commands sent from now on are being compressed; see
+    /// [`crate::wire::compression`].
+    pub fn set_compression(&self, compression: CompressionConfig) {
+        self.compression.send_replace(compression);
+    }
+
+    /// Returns the size above which sent commands are reported; see [`Self::set_max_command_size`].
+    #[must_use]
+    pub fn max_command_size(&self) -> Option<usize> {
+        *self.max_command_size.borrow()
+    }
+
+    /// Logs a warning for every sent command which serializes to more than `max_command_size`
+    /// bytes and counts them in [`ChannelQueueStats::oversized`]; `None` disables this (default).
+    ///
+    /// Such commands are still being sent, split into as many packets as needed.
+    ///
+    /// [`ChannelQueueStats::oversized`]: send_queue::ChannelQueueStats::oversized
+    pub fn set_max_command_size(&self, max_command_size: Option<usize>) {
+        self.max_command_size.send_replace(max_command_size);
+    }
+
+    /// Waits until the peer ids have been exchanged. For a server this is the case as soon as the
+    /// client sent its first packet; a client needs to wait for the server's `SetPeerId`.
+    /// If this fails, the peer has disconnected.
+    pub async fn established(&mut self) -> Result<()> {
+        if self
+            .established
+            .wait_for(|established| *established)
+            .await
+            .is_err()
+        {
+            bail!("disconnected before the connection has been established");
+        }
         Ok(())
     }
 
+    /// Closes the connection, sending a disconnect packet to the remote side. Commands which
+    /// haven't been sent yet are discarded.
+    pub fn close(&self) {
+        // the runner might have exited already
+        self.send
+            .send(ControllerToPeer::Close)
+            .unwrap_or_else(|error| {
+                debug!("peer runner is no longer available: {error}");
+            });
+    }
+
     /// Receive command from the peer
-    /// Returns (channel, reliable flag, Command)
     /// If this fails, the peer is disconnected.
     pub async fn recv(&mut self) -> Result<Command> {
         match self.recv.recv().await {
-            Some(result) => result,
+            Some(Ok((transfer, command))) => {
+                self.last_transfer = Some(transfer);
+                Ok(command)
+            }
+            Some(Err(error)) => Err(error),
             None => bail!(PeerError::InternalPeerError),
         }
     }
+
+    /// Returns how the command most recently returned by [`Self::recv`] has been transferred.
+    #[must_use]
+    pub fn last_transfer(&self) -> Option<Transfer> {
+        self.last_transfer
+    }
 }
 
 // This is owned by the LuantiSocket
 pub struct PeerIO {
     relay: UnboundedSender<SocketToPeer>,
+    buf_pool: BufPool,
 }
 
+/// Creates a peer and the handle the socket relays its datagrams through.
+///
+/// `audit` switches on auditing right from the first received command; see [`Peer::set_audit`].
 #[must_use]
 pub fn new_peer(
     remote_addr: SocketAddr,
     remote_is_server: bool,
     peer_to_socket: UnboundedSender<PeerToSocket>,
+    buf_pool: BufPool,
+    audit: bool,
 ) -> (Peer, PeerIO) {
     let (peer_send_tx, peer_send_rx) = unbounded_channel();
     let (peer_recv_tx, peer_recv_rx) = unbounded_channel();
     let (relay_tx, relay_rx) = unbounded_channel();
+    let (stats_tx, stats_rx) = watch::channel(PeerStats::default());
+    let (drop_policies_tx, drop_policies_rx) = watch::channel(DropPolicies::default());
+    let (reliable_config_tx, reliable_config_rx) = watch::channel(ReliableConfig::default());
+    let (audit_tx, mut audit_rx) = watch::channel(audit);
+    // have the runner pick up the initial value like any later change
+    audit_rx.mark_changed();
+    let (compression_tx, compression_rx) = watch::channel(CompressionConfig::default());
+    let (max_command_size_tx, max_command_size_rx) = watch::channel(None);
+    let (established_tx, established_rx) = watch::channel(false);
 
     let socket_peer = Peer {
         remote_addr,
         remote_is_server,
         send: peer_send_tx,
         recv: peer_recv_rx,
+        last_transfer: None,
+        stats: stats_rx,
+        drop_policies: drop_policies_tx,
+        reliable_config: reliable_config_tx,
+        audit: audit_tx,
+        compression: compression_tx,
+        max_command_size: max_command_size_tx,
+        established: established_rx,
+        buf_pool: buf_pool.clone(),
+    };
+    let socket_peer_io = PeerIO {
+        relay: relay_tx,
+        buf_pool: buf_pool.clone(),
     };
-    let socket_peer_io = PeerIO { relay: relay_tx };
     let socket_peer_runner = PeerRunner {
         remote_addr,
         remote_is_server,
@@ -146,13 +425,20 @@ pub fn new_peer(
         from_controller: peer_send_rx,
         to_controller: peer_recv_tx.clone(),
         to_socket: peer_to_socket,
-        channels: vec![
-            Channel::new(remote_is_server, peer_recv_tx.clone()),
-            Channel::new(remote_is_server, peer_recv_tx.clone()),
-            Channel::new(remote_is_server, peer_recv_tx.clone()),
-        ],
+        channels: ChannelId::all()
+            .map(|id| Channel::new(id, remote_is_server, peer_recv_tx.clone(), buf_pool.clone()))
+            .into(),
         now: Instant::now(),
         last_received: Instant::now(),
+        access_denied: None,
+        stats: stats_tx,
+        drop_policies: drop_policies_rx,
+        reliable_config: reliable_config_rx,
+        audit: audit_rx,
+        compression: compression_rx,
+        max_command_size: max_command_size_rx,
+        established: established_tx,
+        buf_pool,
     };
     tokio::spawn(socket_peer_runner.run());
     (socket_peer, socket_peer_io)
@@ -163,9 +449,11 @@ impl PeerIO {
     /// Called by the `LuantiSocket` when a packet arrives for us
     ///
     pub fn send(&mut self, data: &[u8]) {
+        let mut buf = self.buf_pool.take(data.len());
+        buf.extend_from_slice(data);
         //TODO Add back-pressure
         self.relay
-            .send(SocketToPeer::Received(data.to_vec()))
+            .send(SocketToPeer::Received(buf))
             .unwrap_or_else(|error| {
                 // TODO clarify error condition and handling
                 error!("failed to relay packet: {error}");
@@ -173,20 +461,82 @@ impl PeerIO {
     }
 }
 
+/// Requests of the controller (the owner of the [`Peer`]) to the runner
+#[derive(Debug)]
+pub enum ControllerToPeer {
+    /// A command sent on its default channel with its default reliability
+    Command(Command),
+    /// A command sent on the given channel with the given reliability
+    CommandOn {
+        channel: ChannelId,
+        reliable: bool,
+        command: Command,
+    },
+    /// A raw body to be sequenced by the channel if reliable
+    Inner {
+        channel: ChannelId,
+        reliable: bool,
+        body: InnerBody,
+    },
+    /// A body to be sent without any processing
+    Packet {
+        channel: ChannelId,
+        body: PacketBody,
+    },
+    /// A request to disconnect
+    Close,
+}
+
 #[derive(Debug)]
 pub enum SocketToPeer {
-    /// TODO(paradust): Use buffer pool
-    Received(Vec<u8>),
+    Received(PooledBuf),
 }
 
 #[derive(Debug)]
 pub enum PeerToSocket {
-    // Acks are sent with higher priority
-    SendImmediate(SocketAddr, Vec<u8>),
-    Send(SocketAddr, Vec<u8>),
+    Send(SocketAddr, SendPriority, PooledBuf),
     PeerIsDisconnected(SocketAddr),
 }
 
+/// The order in which the socket sends the datagrams queued by its peers
+///
+/// Datagrams of a higher priority are always sent first, so acknowledgements and time-critical
+/// commands like player positions aren't stuck behind bulk data like map blocks. Datagrams of the
+/// same priority are sent in turns for each peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SendPriority {
+    /// acknowledgements and other control packets
+    Control,
+    /// data of [`ChannelId::Default`]
+    Default,
+    /// data of [`ChannelId::Init`]
+    Init,
+    /// data of [`ChannelId::Response`]
+    Response,
+}
+
+impl SendPriority {
+    /// All priorities, highest first
+    pub const ALL: [Self; 4] = [Self::Control, Self::Default, Self::Init, Self::Response];
+
+    /// Returns the priority of a packet being sent on the given channel.
+    #[must_use]
+    pub fn of(channel: ChannelId, body: &PacketBody) -> Self {
+        let inner = match body {
+            PacketBody::Reliable(body) => &body.inner,
+            PacketBody::Inner(body) => body,
+        };
+        if matches!(inner, InnerBody::Control(_)) {
+            return Self::Control;
+        }
+        match channel {
+            ChannelId::Default => Self::Default,
+            ChannelId::Init => Self::Init,
+            ChannelId::Response => Self::Response,
+        }
+    }
+}
+
 pub struct PeerRunner {
     remote_addr: SocketAddr,
     remote_is_server: bool,
@@ -199,8 +549,8 @@ pub struct PeerRunner {
     to_socket: UnboundedSender<PeerToSocket>,
 
     // TODO(paradust): These should have back-pressure
-    from_controller: UnboundedReceiver<Command>,
-    to_controller: UnboundedSender<Result<Command>>,
+    from_controller: UnboundedReceiver<ControllerToPeer>,
+    to_controller: UnboundedSender<Result<(Transfer, Command)>>,
 
     // This is the peer id in the Luanti protocol
     // Luanti's server uses these to keep track of clients, but we use the remote_addr.
@@ -216,6 +566,23 @@ pub struct PeerRunner {
 
     // Time last packet was received. Used to timeout connection.
     last_received: Instant,
+
+    // The reason of the first `AccessDenied` command and the side which has sent it
+    access_denied: Option<(DisconnectReason, Side)>,
+
+    // Published after every round of sending
+    stats: watch::Sender<PeerStats>,
+    drop_policies: watch::Receiver<DropPolicies>,
+    reliable_config: watch::Receiver<ReliableConfig>,
+    audit: watch::Receiver<bool>,
+    compression: watch::Receiver<CompressionConfig>,
+    max_command_size: watch::Receiver<Option<usize>>,
+
+    // Set once the peer ids have been exchanged
+    established: watch::Sender<bool>,
+
+    // Provides the buffers of outgoing datagrams
+    buf_pool: BufPool,
 }
 
 impl PeerRunner {
@@ -226,38 +593,40 @@ impl PeerRunner {
             .for_each(|channel| channel.update_now(&self.now));
     }
 
-    pub fn serialize_for_send(&mut self, channel: ChannelId, body: PacketBody) -> Result<Vec<u8>> {
+    pub fn serialize_for_send(
+        &mut self,
+        channel: ChannelId,
+        body: PacketBody,
+    ) -> Result<PooledBuf> {
         let pkt = Packet::new(self.local_peer_id, channel, body);
-        let mut serializer = VecSerializer::new(self.send_context, 512);
+        let mut serializer = VecSerializer::pooled(self.send_context, &self.buf_pool, 512);
         Packet::serialize(&pkt, &mut serializer)?;
-        Ok(serializer.take())
+        Ok(serializer.take_pooled())
     }
 
     pub fn send_raw(&mut self, channel: ChannelId, body: PacketBody) -> Result<()> {
+        let priority = SendPriority::of(channel, &body);
         let raw = self.serialize_for_send(channel, body)?;
         self.to_socket
-            .send(PeerToSocket::Send(self.remote_addr, raw))?;
-        Ok(())
-    }
-
-    pub fn send_raw_priority(&mut self, channel: ChannelId, body: PacketBody) -> Result<()> {
-        let raw = self.serialize_for_send(channel, body)?;
-        self.to_socket
-            .send(PeerToSocket::SendImmediate(self.remote_addr, raw))?;
+            .send(PeerToSocket::Send(self.remote_addr, priority, raw))?;
         Ok(())
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "peer",
+            skip_all,
+            fields(remote = %self.remote_addr, peer_id = tracing::field::Empty)
+        )
+    )]
     pub async fn run(mut self) {
         if let Err(err) = self.run_inner().await {
             // Top-level error handling for a peer.
             // If an error gets to this point, the peer is toast.
             // Send a disconnect packet, and a remove peer request to the socket
             // These channels might already be dead, so ignore any errors.
-            let disconnected_cleanly = if let Some(error) = err.downcast_ref::<PeerError>() {
-                matches!(error, PeerError::PeerSentDisconnect)
-            } else {
-                false
-            };
+            let disconnected_cleanly = err.downcast_ref::<Disconnect>().is_some();
             if !disconnected_cleanly {
                 // Send a disconnect packet
                 #[expect(
@@ -294,6 +663,8 @@ impl PeerRunner {
         let never = self.now + Duration::from_secs(315_576_000);
 
         loop {
+            self.refresh_reliable_config();
+
             // Before select, make sure everything ready to send has been sent,
             // and compute a resend timeout.
             let mut next_wakeup = never;
@@ -309,11 +680,12 @@ impl PeerRunner {
                     next_wakeup = std::cmp::min(next_wakeup, timeout);
                 }
             }
+            self.publish_stats();
 
             // rust-analyzer chokes on code inside select!, so keep it to a minimum.
             tokio::select! {
                 msg = self.from_socket.recv() => self.handle_from_socket(msg)?,
-                command = self.from_controller.recv() => self.handle_from_controller(command)?,
+                message = self.from_controller.recv() => self.handle_from_controller(message)?,
                 () = tokio::time::sleep_until(next_wakeup.into()) => self.handle_timeout()?,
             }
         }
@@ -332,6 +704,7 @@ impl PeerRunner {
                 //     buf.len(),
                 //     &buf[0..buf.len().min(64)]
                 // );
+                self.refresh_audit();
                 let mut deser = Deserializer::new(self.recv_context, &buf);
                 let pkt = Packet::deserialize(&mut deser)?;
                 self.last_received = self.now;
@@ -341,16 +714,38 @@ impl PeerRunner {
         Ok(())
     }
 
-    fn handle_from_controller(&mut self, command: Option<Command>) -> Result<()> {
-        trace!("received command from controller: {command:?}",);
+    fn handle_from_controller(&mut self, message: Option<ControllerToPeer>) -> Result<()> {
+        trace!("received message from controller: {message:?}");
 
         self.update_now();
-        let Some(command) = command else {
+        let Some(message) = message else {
             bail!(PeerError::ControllerClosed);
         };
-        self.sniff_hello(&command);
-
-        self.send_command(command)?;
+        self.refresh_compression();
+        match message {
+            ControllerToPeer::Command(command) => {
+                self.sniff_hello(&command);
+                self.sniff_access_denied(&command, Side::Local);
+                self.send_command(command)?;
+            }
+            ControllerToPeer::CommandOn {
+                channel,
+                reliable,
+                command,
+            } => {
+                self.sniff_hello(&command);
+                self.sniff_access_denied(&command, Side::Local);
+                let max_size = *self.max_command_size.borrow();
+                self.channels[usize::from(channel)].send(reliable, command, max_size)?;
+            }
+            ControllerToPeer::Inner {
+                channel,
+                reliable,
+                body,
+            } => self.channels[usize::from(channel)].send_inner(reliable, body),
+            ControllerToPeer::Packet { channel, body } => self.send_raw(channel, body)?,
+            ControllerToPeer::Close => bail!(PeerError::ControllerClosed),
+        }
         Ok(())
     }
 
@@ -374,10 +769,14 @@ impl PeerRunner {
                 self.local_peer_id = PeerId::SERVER;
                 // FIXME this may hand out peer ids that are already in use
                 self.remote_peer_id = PeerId::random();
+                #[cfg(feature = "tracing")]
+                tracing::Span::current()
+                    .record("peer_id", tracing::field::display(self.remote_peer_id));
 
                 // Tell the client about it
                 let set_peer_id = SetPeerIdBody::new(self.remote_peer_id).into_inner();
                 self.channels[0].send_inner(true, set_peer_id);
+                self.established.send_replace(true);
             }
             if pkt.sender_peer_id.is_none() {
                 if self.now > self.connect_time + INEXISTENT_PEER_ID_GRACE {
@@ -412,6 +811,10 @@ impl PeerRunner {
                     if self.remote_is_server {
                         if self.local_peer_id.is_none() {
                             self.local_peer_id = set_peer_id.peer_id;
+                            self.established.send_replace(true);
+                            #[cfg(feature = "tracing")]
+                            tracing::Span::current()
+                                .record("peer_id", tracing::field::display(self.local_peer_id));
                         } else if self.local_peer_id != set_peer_id.peer_id {
                             bail!("Peer id mismatch in duplicate SetPeerId");
                         }
@@ -422,12 +825,13 @@ impl PeerRunner {
                 ControlBody::Ping => {
                     // no-op. Packet already updated timeout
                 }
-                ControlBody::Disconnect => bail!(PeerError::PeerSentDisconnect),
+                ControlBody::Disconnect => bail!(self.disconnect()),
             }
         }
         // If this is a HELLO packet, sniff it to set our protocol context.
         if let Some(command) = pkt.body.command() {
             self.sniff_hello(command);
+            self.sniff_access_denied(command, Side::Remote);
         }
 
         self.channels[usize::from(pkt.channel)].process(pkt.body)
@@ -443,29 +847,128 @@ impl PeerRunner {
         }
     }
 
+    /// Remembers why the connection is about to be closed, so the reason can be reported along
+    /// with the disconnect.
+    fn sniff_access_denied(&mut self, command: &Command, side: Side) {
+        if self.access_denied.is_some() {
+            return;
+        }
+        if let Command::ToClient(ToClientCommand::AccessDenied(access_denied)) = command {
+            self.access_denied = Some((DisconnectReason::from(access_denied.as_ref()), side));
+        }
+    }
+
+    /// Describes the disconnect after the remote side sent a disconnect packet.
+    fn disconnect(&self) -> Disconnect {
+        match &self.access_denied {
+            Some((reason, side)) => Disconnect {
+                reason: Some(reason.clone()),
+                initiated_by: *side,
+            },
+            None => Disconnect {
+                reason: None,
+                initiated_by: Side::Remote,
+            },
+        }
+    }
+
     fn update_context(&mut self, ser_fmt: u8, protocol_version: u16) {
         self.recv_context.protocol_version = protocol_version;
         self.recv_context.ser_fmt = ser_fmt;
         self.send_context.protocol_version = protocol_version;
         self.send_context.ser_fmt = ser_fmt;
+        self.propagate_context();
+    }
+
+    /// Picks up changes made through [`Peer::set_audit`].
+    fn refresh_audit(&mut self) {
+        if !self.audit.has_changed().unwrap_or(false) {
+            return;
+        }
+        let audit = *self.audit.borrow_and_update();
+        self.recv_context.audit = audit;
+        self.send_context.audit = audit;
+        self.propagate_context();
+    }
+
+    /// Picks up changes made through [`Peer::set_compression`].
+    fn refresh_compression(&mut self) {
+        if !self.compression.has_changed().unwrap_or(false) {
+            return;
+        }
+        self.send_context.compression = *self.compression.borrow_and_update();
+        self.propagate_context();
+    }
+
+    fn refresh_reliable_config(&mut self) {
+        if !self.reliable_config.has_changed().unwrap_or(false) {
+            return;
+        }
+        let config = *self.reliable_config.borrow_and_update();
+        self.channels
+            .iter_mut()
+            .for_each(|channel| channel.set_reliable_config(config));
+    }
+
+    fn propagate_context(&mut self) {
         self.channels
             .iter_mut()
             .for_each(|channel| channel.update_context(self.recv_context, self.send_context));
     }
 
-    /// If this is a reliable packet, send an ack right away
-    /// using a higher-priority out-of-band channel.
+    /// If this is a reliable packet, send an ack right away.
+    /// Being a control packet, it overtakes any queued data; see [`SendPriority`].
     fn send_ack(&mut self, channel: ChannelId, rb: &ReliableBody) -> Result<()> {
         let ack = AckBody::new(rb.seqnum).into_inner().into_unreliable();
-        self.send_raw_priority(channel, ack)?;
+        self.send_raw(channel, ack)?;
         Ok(())
     }
 
     /// Send command to remote
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(command = command.command_name()))
+    )]
     fn send_command(&mut self, command: Command) -> Result<()> {
         let channel = command.default_channel();
-        let reliable = command.default_reliability();
-        self.channels[usize::from(channel)].send(reliable, command)
+        let mut reliable = command.default_reliability();
+        if reliable {
+            let backlog = self.channels[usize::from(channel)].backlog();
+            let policy = self.drop_policies.borrow().decide(&command, backlog);
+            match policy {
+                DropPolicy::Keep => {}
+                DropPolicy::SendUnreliable => {
+                    trace!("remote is behind; sending {command:?} unreliably");
+                    self.stats.send_modify(|stats| {
+                        stats.queues.channels[usize::from(channel)].downgraded += 1;
+                    });
+                    reliable = false;
+                }
+                DropPolicy::Drop => {
+                    trace!("remote is behind; dropping {command:?}");
+                    self.stats.send_modify(|stats| {
+                        stats.queues.channels[usize::from(channel)].dropped += 1;
+                    });
+                    return Ok(());
+                }
+            }
+        }
+        let max_size = *self.max_command_size.borrow();
+        self.channels[usize::from(channel)].send(reliable, command, max_size)
+    }
+
+    /// Update the queue lengths and receive counters visible to the controller.
+    fn publish_stats(&self) {
+        self.stats.send_if_modified(|stats| {
+            let previous = *stats;
+            for (channel, channel_stats) in self.channels.iter().zip(&mut stats.queues.channels) {
+                channel.update_stats(channel_stats);
+            }
+            for (channel, received) in self.channels.iter().zip(&mut stats.received) {
+                channel.update_receive_stats(received);
+            }
+            *stats != previous
+        });
     }
 
     #[expect(
@@ -477,3 +980,196 @@ impl PeerRunner {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::Vec3;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::{Disconnect, PeerToSocket, Side, Transfer, new_peer};
+    use crate::commands::Command;
+    use crate::commands::server_to_client::{
+        DisconnectReason, HelloSpec, PlaySoundSpec, SoundLocationType, ToClientCommand,
+    };
+    use crate::types::AuthMechsBitset;
+    use crate::types::ProtocolContext;
+    use crate::wire::buf_pool::BufPool;
+    use crate::wire::channel_id::ChannelId;
+    use crate::wire::packet::{
+        ControlBody, InnerBody, MAX_COMMAND_SIZE, OriginalBody, Packet, PacketBody,
+    };
+    use crate::wire::peer_id::PeerId;
+    use crate::wire::sequence_number::WrappingSequenceNumber;
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    /// Serializes a packet as sent by a server.
+    fn datagram(channel: ChannelId, body: PacketBody) -> Vec<u8> {
+        let packet = Packet::new(PeerId::SERVER, channel, body);
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 64);
+        Packet::serialize(&packet, &mut ser).unwrap();
+        ser.take()
+    }
+
+    #[tokio::test]
+    async fn disconnects_carry_the_reason() {
+        let (to_socket, _from_peer) = unbounded_channel();
+        let (mut peer, mut peer_io) = new_peer(
+            "127.0.0.1:30000".parse().unwrap(),
+            true,
+            to_socket,
+            BufPool::default(),
+            false,
+        );
+
+        let reason = DisconnectReason::Shutdown("maintenance".into());
+        let access_denied = InnerBody::Original(OriginalBody {
+            command: Some(Command::ToClient(reason.to_command(true).into())),
+        });
+        peer_io.send(&datagram(
+            ChannelId::Default,
+            access_denied.into_reliable(WrappingSequenceNumber::INITIAL),
+        ));
+        peer_io.send(&datagram(
+            ChannelId::Default,
+            ControlBody::Disconnect.into_inner().into_unreliable(),
+        ));
+
+        assert!(matches!(
+            peer.recv().await.unwrap(),
+            Command::ToClient(ToClientCommand::AccessDenied(_))
+        ));
+        let error = peer.recv().await.unwrap_err();
+        let disconnect = error.downcast_ref::<Disconnect>().unwrap();
+        assert_eq!(
+            disconnect,
+            &Disconnect {
+                reason: Some(reason),
+                initiated_by: Side::Remote,
+            }
+        );
+        assert_eq!(
+            disconnect.to_string(),
+            "disconnected by the remote side: maintenance"
+        );
+    }
+
+    #[tokio::test]
+    async fn received_commands_report_their_transfer() {
+        let (to_socket, _from_peer) = unbounded_channel();
+        let (mut peer, mut peer_io) = new_peer(
+            "127.0.0.1:30000".parse().unwrap(),
+            true,
+            to_socket,
+            BufPool::default(),
+            false,
+        );
+        let command = || {
+            InnerBody::Original(OriginalBody {
+                command: Some(Command::ToClient(
+                    DisconnectReason::Shutdown(String::new())
+                        .to_command(false)
+                        .into(),
+                )),
+            })
+        };
+
+        assert_eq!(peer.last_transfer(), None);
+        peer_io.send(&datagram(
+            ChannelId::Response,
+            command().into_reliable(WrappingSequenceNumber::INITIAL),
+        ));
+        peer.recv().await.unwrap();
+        assert_eq!(
+            peer.last_transfer(),
+            Some(Transfer {
+                channel: ChannelId::Response,
+                reliable: true,
+            })
+        );
+
+        peer_io.send(&datagram(ChannelId::Init, command().into_unreliable()));
+        peer.recv().await.unwrap();
+        assert_eq!(
+            peer.last_transfer(),
+            Some(Transfer {
+                channel: ChannelId::Init,
+                reliable: false,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn sent_hello_switches_the_protocol_version() {
+        let (to_socket, mut from_peer) = unbounded_channel();
+        let (peer, _peer_io) = new_peer(
+            "127.0.0.1:30000".parse().unwrap(),
+            false,
+            to_socket,
+            BufPool::default(),
+            false,
+        );
+        let play_sound = || {
+            Command::ToClient(
+                PlaySoundSpec {
+                    server_id: 1,
+                    spec_name: "click".into(),
+                    spec_gain: 1.0,
+                    typ: SoundLocationType::Local,
+                    pos: Vec3::ZERO,
+                    object_id: 0,
+                    spec_loop: false,
+                    spec_fade: 0.0,
+                    spec_pitch: 1.0,
+                    ephemeral: false,
+                    start_time: 0.0,
+                }
+                .into(),
+            )
+        };
+        let mut sent_len = async || match from_peer.recv().await.unwrap() {
+            PeerToSocket::Send(_, _, datagram) => datagram.len(),
+            PeerToSocket::PeerIsDisconnected(_) => 0,
+        };
+
+        peer.send(play_sound()).unwrap();
+        let latest = sent_len().await;
+        // a client of Luanti 5.0.0 is answered in its version
+        peer.send(Command::ToClient(
+            HelloSpec {
+                serialization_version: 29,
+                compression_mode: 0,
+                protocol_version: 37,
+                auth_mechs: AuthMechsBitset::default(),
+                username_legacy: String::new(),
+            }
+            .into(),
+        ))
+        .unwrap();
+        sent_len().await;
+        peer.send(play_sound()).unwrap();
+        // neither `ephemeral` nor `start_time` are known to protocol version 37
+        assert_eq!(sent_len().await, latest - 5);
+    }
+
+    #[tokio::test]
+    async fn raw_commands_are_validated() {
+        let (to_socket, _from_peer) = unbounded_channel();
+        let (peer, _peer_io) = new_peer(
+            "127.0.0.1:30000".parse().unwrap(),
+            true,
+            to_socket,
+            BufPool::default(),
+            false,
+        );
+
+        // 0x02 is `Init` when being sent to a server
+        assert!(peer.send_raw_command(0x02, vec![]).is_err());
+        assert!(
+            peer.send_raw_command(0xfe, vec![0; MAX_COMMAND_SIZE])
+                .is_err()
+        );
+        peer.send_raw_command(0xfe, vec![1, 2, 3]).unwrap();
+    }
+}
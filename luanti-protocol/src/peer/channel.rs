@@ -1,20 +1,26 @@
 use std::{collections::VecDeque, time::Instant};
 
 use anyhow::Result;
+use log::{trace, warn};
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
-    commands::Command,
+    commands::{Command, CommandProperties},
     types::ProtocolContext,
     wire::{
+        buf_pool::BufPool,
+        channel_id::ChannelId,
         deser::{Deserialize, Deserializer},
         packet::{ControlBody, InnerBody, PacketBody, ReliableBody},
     },
 };
 
-use super::{ReliableReceiver, ReliableSender, SplitReceiver, SplitSender};
+use super::send_queue::{ChannelQueueStats, ReliableConfig};
+use super::stats::ChannelReceiveStats;
+use super::{ReliableReceiver, ReliableSender, SplitReceiver, SplitSender, Transfer};
 
 pub(crate) struct Channel {
+    id: ChannelId,
     unreliable_out: VecDeque<InnerBody>,
 
     reliable_in: ReliableReceiver,
@@ -23,27 +29,37 @@ pub(crate) struct Channel {
     split_in: SplitReceiver,
     split_out: SplitSender,
 
-    to_controller: UnboundedSender<Result<Command>>,
+    to_controller: UnboundedSender<Result<(Transfer, Command)>>,
     now: Instant,
     recv_context: ProtocolContext,
     send_context: ProtocolContext,
+
+    /// number of commands which have been split into several packets
+    split_count: u64,
+    /// number of commands exceeding the configured maximum size
+    oversized_count: u64,
 }
 
 impl Channel {
     pub(crate) fn new(
+        id: ChannelId,
         remote_is_server: bool,
-        to_controller: UnboundedSender<Result<Command>>,
+        to_controller: UnboundedSender<Result<(Transfer, Command)>>,
+        buf_pool: BufPool,
     ) -> Self {
         Self {
+            id,
             unreliable_out: VecDeque::new(),
             reliable_in: ReliableReceiver::new(),
             reliable_out: ReliableSender::new(),
-            split_in: SplitReceiver::new(),
-            split_out: SplitSender::new(),
+            split_in: SplitReceiver::new(buf_pool.clone()),
+            split_out: SplitSender::new(buf_pool),
             to_controller,
             now: Instant::now(),
             recv_context: ProtocolContext::latest_for_receive(remote_is_server),
             send_context: ProtocolContext::latest_for_send(remote_is_server),
+            split_count: 0,
+            oversized_count: 0,
         }
     }
 
@@ -65,7 +81,7 @@ impl Channel {
     pub(crate) fn process(&mut self, body: PacketBody) -> Result<()> {
         match body {
             PacketBody::Reliable(rb) => self.process_reliable(rb)?,
-            PacketBody::Inner(ib) => self.process_inner(ib)?,
+            PacketBody::Inner(ib) => self.process_inner(ib, false)?,
         }
         Ok(())
     }
@@ -73,24 +89,24 @@ impl Channel {
     pub(crate) fn process_reliable(&mut self, body: ReliableBody) -> Result<()> {
         self.reliable_in.push(body);
         while let Some(inner) = self.reliable_in.pop() {
-            self.process_inner(inner)?;
+            self.process_inner(inner, true)?;
         }
         Ok(())
     }
 
-    pub(crate) fn process_inner(&mut self, body: InnerBody) -> Result<()> {
+    pub(crate) fn process_inner(&mut self, body: InnerBody, reliable: bool) -> Result<()> {
         match body {
             InnerBody::Control(body) => self.process_control(body),
             InnerBody::Original(body) => {
                 if let Some(command) = body.command {
-                    self.process_command(command);
+                    self.process_command(command, reliable);
                 }
             }
             InnerBody::Split(body) => {
                 if let Some(payload) = self.split_in.push(self.now, body)? {
                     let mut buf = Deserializer::new(self.recv_context, &payload);
                     if let Some(command) = Command::deserialize(&mut buf)? {
-                        self.process_command(command);
+                        self.process_command(command, reliable);
                     }
                 }
             }
@@ -100,22 +116,48 @@ impl Channel {
 
     pub(crate) fn process_control(&mut self, body: ControlBody) {
         if let ControlBody::Ack(ack) = body {
-            self.reliable_out.process_ack(&ack);
+            self.reliable_out.process_ack(&ack, self.now);
         } else {
             // Everything else is handled one level up
         }
     }
 
-    pub(crate) fn process_command(&mut self, command: Command) {
-        match self.to_controller.send(Ok(command)) {
+    pub(crate) fn process_command(&mut self, command: Command, reliable: bool) {
+        let transfer = Transfer {
+            channel: self.id,
+            reliable,
+        };
+        match self.to_controller.send(Ok((transfer, command))) {
             Ok(()) => (),
             Err(error) => panic!("Unexpected command channel shutdown: {error:?}"),
         }
     }
 
     /// Send command to remote
-    pub(crate) fn send(&mut self, reliable: bool, command: Command) -> Result<()> {
-        let bodies = self.split_out.push(self.send_context, command)?;
+    ///
+    /// Commands larger than `max_size` are still sent, but a warning is logged.
+    pub(crate) fn send(
+        &mut self,
+        reliable: bool,
+        command: Command,
+        max_size: Option<usize>,
+    ) -> Result<()> {
+        let size = command.estimated_size(self.send_context)?;
+        if max_size.is_some_and(|max_size| size > max_size) {
+            warn!(
+                "sending {name} with {size} bytes which exceeds the maximum of {max_size:?} bytes",
+                name = command.command_name()
+            );
+            self.oversized_count += 1;
+        }
+        let bodies = self.split_out.push(self.send_context, command, size)?;
+        if bodies.len() > 1 {
+            trace!(
+                "split command of {size} bytes into {} packets",
+                bodies.len()
+            );
+            self.split_count += 1;
+        }
         for body in bodies {
             self.send_inner(reliable, body);
         }
@@ -141,6 +183,34 @@ impl Channel {
         None
     }
 
+    /// Number of reliable packets waiting for the send window
+    pub(crate) fn backlog(&self) -> usize {
+        self.reliable_out.queued_len()
+    }
+
+    /// Fill in the current queue lengths.
+    pub(crate) fn update_stats(&self, stats: &mut ChannelQueueStats) {
+        stats.unreliable = self.unreliable_out.len();
+        stats.reliable_queued = self.reliable_out.queued_len();
+        stats.reliable_in_flight = self.reliable_out.in_flight_len();
+        stats.retransmitted = self.reliable_out.resent_count();
+        stats.rtt = self.reliable_out.rtt();
+        stats.split = self.split_count;
+        stats.oversized = self.oversized_count;
+    }
+
+    /// Fill in the counters of the incoming reliable stream.
+    pub(crate) fn update_receive_stats(&self, stats: &mut ChannelReceiveStats) {
+        stats.duplicates = self.reliable_in.duplicate_count();
+        stats.out_of_order = self.reliable_in.out_of_order_count();
+        stats.buffered = self.reliable_in.buffered_len();
+    }
+
+    /// Changes the send window and resend timeout of reliable packets.
+    pub(crate) fn set_reliable_config(&mut self, config: ReliableConfig) {
+        self.reliable_out.set_config(config);
+    }
+
     /// Only call after exhausting `next_send()`
     pub(crate) fn next_timeout(&mut self) -> Option<Instant> {
         self.reliable_out.next_timeout()
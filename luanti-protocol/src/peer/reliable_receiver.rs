@@ -1,6 +1,7 @@
 use crate::wire::packet::InnerBody;
 use crate::wire::packet::ReliableBody;
 use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
 
 use super::sequence_number::SequenceNumber;
 
@@ -13,6 +14,12 @@ pub(super) struct ReliableReceiver {
     // It must always be true that: smallest key in buffer > next_seqnum
     // TODO documentation doesn't match the implementation. After a `push`, `buffer` may equal `next_seqnum`
     buffer: BTreeMap<SequenceNumber, InnerBody>,
+
+    // Number of packets which have been received before, usually because our ack got lost
+    duplicates: u64,
+
+    // Number of packets which arrived before one of their predecessors
+    out_of_order: u64,
 }
 
 impl ReliableReceiver {
@@ -20,6 +27,8 @@ impl ReliableReceiver {
         ReliableReceiver {
             next_seqnum: SequenceNumber::init(),
             buffer: BTreeMap::new(),
+            duplicates: 0,
+            out_of_order: 0,
         }
     }
 
@@ -29,12 +38,36 @@ impl ReliableReceiver {
         if seqnum >= self.next_seqnum {
             // Future packet. Put it in the buffer.
             // Don't override it if it's already there.
-            self.buffer.entry(seqnum).or_insert(body.inner);
+            match self.buffer.entry(seqnum) {
+                Entry::Vacant(entry) => {
+                    if seqnum > self.next_seqnum {
+                        self.out_of_order += 1;
+                    }
+                    entry.insert(body.inner);
+                }
+                Entry::Occupied(_) => self.duplicates += 1,
+            }
         } else {
             // Packet was already received and processed. Ignore
+            self.duplicates += 1;
         }
     }
 
+    /// Number of packets which have been received more than once
+    pub(super) fn duplicate_count(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Number of packets which arrived before one of their predecessors
+    pub(super) fn out_of_order_count(&self) -> u64 {
+        self.out_of_order
+    }
+
+    /// Number of packets waiting for one of their predecessors
+    pub(super) fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
     // Pull a single body to be processed, from the reliable stream.
     // These are guaranteed to be in the same order as they were sent.
     // This should be called until exhaustion, after a push.
@@ -124,4 +157,27 @@ mod tests {
             offset += CHUNK_LEN;
         }
     }
+
+    #[test]
+    fn duplicates_and_reordering_are_counted() {
+        let reliable = |index: u16| match make_inner(u32::from(index))
+            .into_reliable(WrappingSequenceNumber::INITIAL + index)
+        {
+            PacketBody::Reliable(body) => body,
+            PacketBody::Inner(_) => panic!(),
+        };
+
+        let mut receiver = ReliableReceiver::new();
+        receiver.push(reliable(1));
+        receiver.push(reliable(1));
+        assert!(receiver.pop().is_none());
+        assert_eq!(receiver.buffered_len(), 1);
+
+        receiver.push(reliable(0));
+        while receiver.pop().is_some() {}
+        receiver.push(reliable(0));
+        assert_eq!(receiver.buffered_len(), 0);
+        assert_eq!(receiver.out_of_order_count(), 1);
+        assert_eq!(receiver.duplicate_count(), 2);
+    }
 }
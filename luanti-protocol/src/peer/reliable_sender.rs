@@ -8,17 +8,9 @@ use crate::wire::packet::AckBody;
 use crate::wire::packet::InnerBody;
 use crate::wire::packet::PacketBody;
 
+use super::send_queue::ReliableConfig;
 use super::sequence_number::SequenceNumber;
 
-//const MIN_RELIABLE_WINDOW_SIZE: u16 = 0x40; // 64
-const START_RELIABLE_WINDOW_SIZE: u16 = 0x400; // 1024
-
-#[cfg(test)]
-const MAX_RELIABLE_WINDOW_SIZE: u16 = 0x8000; // 32768
-
-//const RESEND_TIMEOUT_MIN_MS: u64 = 100;
-const RESEND_TIMEOUT_START_MS: u64 = 500;
-//const RESEND_TIMEOUT_MAX_MS: u64 = 3000;
 const RESEND_RESOLUTION: Duration = Duration::from_millis(20);
 
 pub(super) struct ReliableSender {
@@ -37,26 +29,52 @@ pub(super) struct ReliableSender {
     // TODO(paradust): Use a better data structure for this
     timeouts: BTreeSet<(Instant, SequenceNumber)>,
     resend_timeout: Duration,
+
+    // Number of packets which have been sent again after their timeout expired
+    resent: u64,
+
+    // When packets have been sent which haven't been sent again yet; acks of resent packets
+    // are ambiguous and don't contribute to the round trip time
+    sent_at: BTreeMap<SequenceNumber, Instant>,
+    // Smoothed round trip time of the acknowledged packets
+    rtt: Option<Duration>,
 }
 
 impl ReliableSender {
     pub(super) fn new() -> Self {
+        let config = ReliableConfig::default();
         ReliableSender {
             next_seqnum: SequenceNumber::init(),
-            window_size: START_RELIABLE_WINDOW_SIZE,
+            window_size: config.window_size,
             buffer: BTreeMap::new(),
             timeouts: BTreeSet::new(),
-            resend_timeout: Duration::from_millis(RESEND_TIMEOUT_START_MS),
+            resend_timeout: config.resend_timeout,
             queued: VecDeque::new(),
+            resent: 0,
+            sent_at: BTreeMap::new(),
+            rtt: None,
         }
     }
 
-    pub(super) fn process_ack(&mut self, ack: &AckBody) {
+    /// Applies a new window size and resend timeout. Packets which are already in flight keep
+    /// their current timeout.
+    pub(super) fn set_config(&mut self, config: ReliableConfig) {
+        let config = config.clamped();
+        self.window_size = config.window_size;
+        self.resend_timeout = config.resend_timeout;
+    }
+
+    pub(super) fn process_ack(&mut self, ack: &AckBody, now: Instant) {
         let Some(unacked_base) = self.oldest_unacked() else {
             return;
         };
         let seqnum = unacked_base.goto(ack.seqnum);
         self.buffer.remove(&seqnum);
+        if let Some(sent_at) = self.sent_at.remove(&seqnum) {
+            let sample = now.saturating_duration_since(sent_at);
+            // the same smoothing as used by TCP
+            self.rtt = Some(self.rtt.map_or(sample, |rtt| (rtt * 7 + sample) / 8));
+        }
     }
 
     /// Push a packet for reliable send.
@@ -67,6 +85,26 @@ impl ReliableSender {
         self.queued.push_back((seqnum, body));
     }
 
+    /// Number of packets waiting for the send window
+    pub(super) fn queued_len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Number of packets which have been sent but not yet been acknowledged
+    pub(super) fn in_flight_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of packets which have been sent again because they weren't acknowledged in time
+    pub(super) fn resent_count(&self) -> u64 {
+        self.resent
+    }
+
+    /// Smoothed time between sending a packet and receiving its acknowledgement
+    pub(super) fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
     fn oldest_unacked(&self) -> Option<SequenceNumber> {
         self.buffer.first_key_value().map(|(seqnum, _)| *seqnum)
     }
@@ -111,6 +149,7 @@ impl ReliableSender {
             Some((seqnum, body)) => {
                 self.buffer.insert(seqnum, PacketBody::clone(&body));
                 self.timeouts.insert((now + self.resend_timeout, seqnum));
+                self.sent_at.insert(seqnum, now);
                 Some(body)
             }
             None => None,
@@ -138,6 +177,8 @@ impl ReliableSender {
                         let body = self.buffer.get(&seqnum).unwrap().clone();
                         // Schedule future resend
                         self.timeouts.insert((now + self.resend_timeout, seqnum));
+                        self.sent_at.remove(&seqnum);
+                        self.resent += 1;
                         return Some(body);
                     } else {
                         // Not expired yet. Re-insert
@@ -247,7 +288,7 @@ mod tests {
                         "Resending already acknowledged packet"
                     );
                     let spread = recovered_index - oldest_unacked_index;
-                    assert!(spread < (MAX_RELIABLE_WINDOW_SIZE as usize));
+                    assert!(spread < (ReliableConfig::MAX_WINDOW_SIZE as usize));
                 }
 
                 // Send acks for 50% of transmitted packets, forcing retries for the others
@@ -265,7 +306,7 @@ mod tests {
 
             // Send the acks
             for seqnum in send_ack_now {
-                sender.process_ack(&AckBody { seqnum });
+                sender.process_ack(&AckBody { seqnum }, now);
             }
 
             // If we're given a timeout, simulate sleeping until the timeout 50% of the time.
@@ -285,15 +326,83 @@ mod tests {
             }
         }
 
+        // Every transmission after the first one of a packet is a retransmission
+        let resent: usize = inflight
+            .values()
+            .map(|info| info.sent_time.len().saturating_sub(1))
+            .sum();
+        assert_eq!(sender.resent_count(), resent as u64);
+
         // Make sure the send intervals are sane
         for (_, info) in inflight {
-            // Resend delay should be approximately RESEND_TIMEOUT_START_MS to within 50ms
+            // Resend delay should be approximately the default resend timeout to within 50ms
+            let resend_timeout = ReliableConfig::default().resend_timeout;
             for i in 1..info.sent_time.len() {
                 let resend_delay = info.sent_time[i] - info.sent_time[i - 1];
                 let delta =
-                    ((resend_delay.as_millis() as i64) - (RESEND_TIMEOUT_START_MS as i64)).abs();
+                    ((resend_delay.as_millis() as i64) - (resend_timeout.as_millis() as i64)).abs();
                 assert!(delta < 100, "Unexpected resend interval: {resend_delay:?}");
             }
         }
     }
+
+    #[test]
+    fn window_size_is_configurable() {
+        let mut sender = ReliableSender::new();
+        sender.set_config(ReliableConfig {
+            window_size: ReliableConfig::MIN_WINDOW_SIZE,
+            ..ReliableConfig::default()
+        });
+        for index in 0..1000 {
+            sender.push(make_inner(index));
+        }
+        let now = Instant::now();
+        while sender.pop(now).is_some() {}
+        assert_eq!(
+            sender.in_flight_len(),
+            usize::from(ReliableConfig::MIN_WINDOW_SIZE)
+        );
+        assert_eq!(
+            sender.queued_len(),
+            1000 - usize::from(ReliableConfig::MIN_WINDOW_SIZE)
+        );
+
+        // a larger window releases more packets right away
+        sender.set_config(ReliableConfig {
+            window_size: 0x100,
+            ..ReliableConfig::default()
+        });
+        while sender.pop(now).is_some() {}
+        assert_eq!(sender.in_flight_len(), 0x100);
+    }
+
+    #[test]
+    fn round_trip_time_is_smoothed() {
+        let mut sender = ReliableSender::new();
+        let ack_of = |body: PacketBody| match body {
+            PacketBody::Reliable(rb) => AckBody { seqnum: rb.seqnum },
+            PacketBody::Inner(_) => panic!("Unexpected body"),
+        };
+        let start = Instant::now();
+        assert_eq!(sender.rtt(), None);
+
+        sender.push(make_inner(0));
+        let first = sender.pop(start).unwrap();
+        sender.process_ack(&ack_of(first), start + Duration::from_millis(80));
+        assert_eq!(sender.rtt(), Some(Duration::from_millis(80)));
+
+        sender.push(make_inner(1));
+        let second = sender.pop(start).unwrap();
+        sender.process_ack(&ack_of(second), start + Duration::from_millis(160));
+        assert_eq!(sender.rtt(), Some(Duration::from_millis(90)));
+
+        // the ack of a resent packet can't be attributed to one of its transmissions
+        sender.push(make_inner(2));
+        assert!(sender.pop(start).is_some());
+        let resend_time = start + ReliableConfig::default().resend_timeout;
+        let third = sender.pop(resend_time).unwrap();
+        sender.process_ack(&ack_of(third), resend_time + Duration::from_secs(1));
+        assert_eq!(sender.rtt(), Some(Duration::from_millis(90)));
+        assert_eq!(sender.in_flight_len(), 0);
+    }
 }
@@ -0,0 +1,317 @@
+//! Metrics of the outgoing queues of a peer, policies for dropping traffic when the remote
+//! side can't keep up and the configuration of the reliable send window.
+
+use std::time::Duration;
+
+use crate::commands::Command;
+use crate::commands::server_to_client::{ActiveObjectMessagesCommand, ToClientCommand};
+use crate::types::ActiveObjectCommand;
+use crate::wire::channel_id::ChannelId;
+
+/// Commands which may be dropped or sent unreliably if the remote side falls behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrafficClass {
+    /// `ActiveObjectMessages` consisting of position updates only, each of which is superseded
+    /// by the next update of the same object
+    ActiveObjectUpdates,
+    /// `TimeOfDay`
+    TimeOfDay,
+}
+
+impl TrafficClass {
+    /// Returns the class of the given command, if it belongs to any.
+    #[must_use]
+    pub fn of(command: &Command) -> Option<Self> {
+        match command {
+            Command::ToClient(ToClientCommand::ActiveObjectMessages(command))
+                if is_superseded_by_later_updates(command) =>
+            {
+                Some(Self::ActiveObjectUpdates)
+            }
+            Command::ToClient(ToClientCommand::TimeOfDay(_)) => Some(Self::TimeOfDay),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `true` if all messages are position updates of moving objects. Other messages like
+/// property changes or the final position of an object wouldn't be repeated.
+fn is_superseded_by_later_updates(command: &ActiveObjectMessagesCommand) -> bool {
+    !command.objects.is_empty()
+        && command.objects.iter().all(|message| {
+            matches!(
+                &message.data,
+                ActiveObjectCommand::UpdatePosition(update) if !update.is_end_position
+            )
+        })
+}
+
+/// What to do with commands of a [`TrafficClass`] while the remote side is behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropPolicy {
+    /// Queue the command as usual
+    #[default]
+    Keep,
+    /// Send the command unreliably, bypassing the reliable queue
+    SendUnreliable,
+    /// Discard the command; a later one will supersede it anyway
+    Drop,
+}
+
+/// Configures when and how commands get dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropPolicies {
+    /// The number of reliable packets waiting for the send window of a channel at which the
+    /// remote side is considered to be behind
+    pub backlog_threshold: usize,
+    /// Policy for [`TrafficClass::ActiveObjectUpdates`]
+    pub active_object_updates: DropPolicy,
+    /// Policy for [`TrafficClass::TimeOfDay`]
+    pub time_of_day: DropPolicy,
+}
+
+impl DropPolicies {
+    /// Policies which never drop anything
+    pub const KEEP_ALL: Self = Self {
+        backlog_threshold: usize::MAX,
+        active_object_updates: DropPolicy::Keep,
+        time_of_day: DropPolicy::Keep,
+    };
+
+    /// Returns the policy for the given class.
+    #[must_use]
+    pub fn policy(&self, class: TrafficClass) -> DropPolicy {
+        match class {
+            TrafficClass::ActiveObjectUpdates => self.active_object_updates,
+            TrafficClass::TimeOfDay => self.time_of_day,
+        }
+    }
+
+    /// Returns the policy to be applied to `command` given the backlog of its channel.
+    #[must_use]
+    pub fn decide(&self, command: &Command, backlog: usize) -> DropPolicy {
+        if backlog < self.backlog_threshold {
+            return DropPolicy::Keep;
+        }
+        TrafficClass::of(command).map_or(DropPolicy::Keep, |class| self.policy(class))
+    }
+}
+
+impl Default for DropPolicies {
+    /// Keeps everything as long as the backlog stays reasonable; beyond that, time updates are
+    /// sent unreliably and position updates of moving objects are dropped.
+    fn default() -> Self {
+        Self {
+            backlog_threshold: 1024,
+            active_object_updates: DropPolicy::Drop,
+            time_of_day: DropPolicy::SendUnreliable,
+        }
+    }
+}
+
+/// Configures how many reliable packets may be in flight and when they are sent again
+///
+/// The defaults are the initial values of the Luanti engine, which adapts them at runtime. A peer
+/// talking to a remote side with plenty of bandwidth, e.g. while sending a large world, may use
+/// a larger window to avoid stalling on every round trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReliableConfig {
+    /// The number of reliable packets per channel which may be sent without being acknowledged
+    pub window_size: u16,
+    /// The time after which a reliable packet is sent again if it hasn't been acknowledged
+    pub resend_timeout: Duration,
+}
+
+impl ReliableConfig {
+    /// The smallest supported window size
+    pub const MIN_WINDOW_SIZE: u16 = 0x40;
+    /// The largest supported window size; larger windows would make sequence numbers ambiguous
+    pub const MAX_WINDOW_SIZE: u16 = 0x8000;
+    /// The shortest supported resend timeout
+    pub const MIN_RESEND_TIMEOUT: Duration = Duration::from_millis(100);
+    /// The longest supported resend timeout
+    pub const MAX_RESEND_TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Limits all values to the supported ranges.
+    #[must_use]
+    pub fn clamped(self) -> Self {
+        Self {
+            window_size: self
+                .window_size
+                .clamp(Self::MIN_WINDOW_SIZE, Self::MAX_WINDOW_SIZE),
+            resend_timeout: self
+                .resend_timeout
+                .clamp(Self::MIN_RESEND_TIMEOUT, Self::MAX_RESEND_TIMEOUT),
+        }
+    }
+}
+
+impl Default for ReliableConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 0x400,
+            resend_timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+/// The state of the outgoing queues of a single channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelQueueStats {
+    /// unreliable packets not yet handed to the socket
+    pub unreliable: usize,
+    /// reliable packets waiting for the send window
+    pub reliable_queued: usize,
+    /// reliable packets which have been sent but not yet been acknowledged
+    pub reliable_in_flight: usize,
+    /// number of commands dropped due to the [`DropPolicies`]
+    pub dropped: u64,
+    /// number of commands sent unreliably due to the [`DropPolicies`]
+    pub downgraded: u64,
+    /// number of reliable packets which have been sent again as they weren't acknowledged in time
+    pub retransmitted: u64,
+    /// number of commands which were too large for a single packet and have been split
+    pub split: u64,
+    /// number of commands exceeding the size set by [`Peer::set_max_command_size`]
+    ///
+    /// [`Peer::set_max_command_size`]: crate::peer::Peer::set_max_command_size
+    pub oversized: u64,
+    /// smoothed time between sending a reliable packet and receiving its acknowledgement; `None`
+    /// until the first packet has been acknowledged
+    pub rtt: Option<Duration>,
+}
+
+/// The state of the outgoing queues of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    /// stats per channel, indexed by [`ChannelId`]
+    pub channels: [ChannelQueueStats; 3],
+}
+
+impl QueueStats {
+    /// Returns the stats of a single channel.
+    #[must_use]
+    pub fn channel(&self, channel: ChannelId) -> &ChannelQueueStats {
+        &self.channels[usize::from(channel)]
+    }
+
+    /// Returns the total number of packets which haven't been acknowledged yet.
+    #[must_use]
+    pub fn total_pending(&self) -> usize {
+        self.channels
+            .iter()
+            .map(|stats| stats.unreliable + stats.reliable_queued + stats.reliable_in_flight)
+            .sum()
+    }
+
+    /// Returns the total number of retransmitted reliable packets.
+    #[must_use]
+    pub fn total_retransmitted(&self) -> u64 {
+        self.channels.iter().map(|stats| stats.retransmitted).sum()
+    }
+
+    /// Returns the round trip time averaged over all channels which have measured one.
+    #[must_use]
+    pub fn rtt(&self) -> Option<Duration> {
+        let measured: Vec<_> = self.channels.iter().filter_map(|stats| stats.rtt).collect();
+        let count = u32::try_from(measured.len())
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(measured.into_iter().sum::<Duration>() / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use glam::Vec3;
+
+    use super::{DropPolicies, DropPolicy, ReliableConfig, TrafficClass};
+    use crate::commands::Command;
+    use crate::commands::server_to_client::{
+        ActiveObjectMessage, ActiveObjectMessagesCommand, BreathSpec, TimeOfDaySpec,
+    };
+    use crate::types::{AOCPunched, AOCUpdatePosition, ActiveObjectCommand, TrailingBytes};
+
+    #[test]
+    fn policies_apply_only_when_behind() {
+        let policies = DropPolicies::default();
+        let time_of_day = Command::ToClient(
+            TimeOfDaySpec {
+                time_of_day: 6000,
+                time_speed: Some(72.0),
+                extra: TrailingBytes::default(),
+            }
+            .into(),
+        );
+        let breath = Command::ToClient(BreathSpec { breath: 10 }.into());
+
+        assert_eq!(policies.decide(&time_of_day, 0), DropPolicy::Keep);
+        assert_eq!(
+            policies.decide(&time_of_day, policies.backlog_threshold),
+            DropPolicy::SendUnreliable
+        );
+        assert_eq!(
+            policies.decide(&breath, policies.backlog_threshold),
+            DropPolicy::Keep
+        );
+        assert_eq!(
+            DropPolicies::KEEP_ALL.decide(&time_of_day, usize::MAX - 1),
+            DropPolicy::Keep
+        );
+    }
+
+    #[test]
+    fn only_superseded_object_updates_are_classified() {
+        let messages = |data: Vec<ActiveObjectCommand>| {
+            Command::ToClient(
+                ActiveObjectMessagesCommand {
+                    objects: data
+                        .into_iter()
+                        .map(|data| ActiveObjectMessage { id: 7, data })
+                        .collect(),
+                }
+                .into(),
+            )
+        };
+        let update = |is_end_position| {
+            ActiveObjectCommand::UpdatePosition(AOCUpdatePosition {
+                position: Vec3::ZERO,
+                velocity: Vec3::X,
+                acceleration: Vec3::ZERO,
+                rotation: Vec3::ZERO,
+                do_interpolate: true,
+                is_end_position,
+                update_interval: 0.1,
+            })
+        };
+        let punched = ActiveObjectCommand::Punched(AOCPunched { hp: 5 });
+
+        assert_eq!(
+            TrafficClass::of(&messages(vec![update(false), update(false)])),
+            Some(TrafficClass::ActiveObjectUpdates)
+        );
+        assert_eq!(TrafficClass::of(&messages(vec![update(true)])), None);
+        assert_eq!(
+            TrafficClass::of(&messages(vec![update(false), punched])),
+            None
+        );
+        assert_eq!(TrafficClass::of(&messages(vec![])), None);
+    }
+
+    #[test]
+    fn reliable_config_is_clamped() {
+        let config = ReliableConfig {
+            window_size: u16::MAX,
+            resend_timeout: Duration::ZERO,
+        }
+        .clamped();
+        assert_eq!(config.window_size, ReliableConfig::MAX_WINDOW_SIZE);
+        assert_eq!(config.resend_timeout, ReliableConfig::MIN_RESEND_TIMEOUT);
+        assert_eq!(
+            ReliableConfig::default().clamped(),
+            ReliableConfig::default()
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::wire::buf_pool::BufPool;
+use crate::wire::buf_pool::PooledBuf;
 use crate::wire::packet::SplitBody;
 use crate::wire::sequence_number::WrappingSequenceNumber;
 use anyhow::bail;
@@ -43,7 +45,7 @@ impl IncomingBuffer {
         Ok(self.chunks.len() == self.chunk_count as usize)
     }
 
-    fn take(self) -> Vec<u8> {
+    fn take(self, buf_pool: &BufPool) -> PooledBuf {
         assert_eq!(
             self.chunks.len(),
             self.chunk_count as usize,
@@ -51,7 +53,7 @@ impl IncomingBuffer {
         );
         // TODO replace with `flatten`
         let total_size: usize = self.chunks.values().map(Vec::len).sum();
-        let mut buf = Vec::with_capacity(total_size);
+        let mut buf = buf_pool.take(total_size);
         for chunk in self.chunks.values() {
             buf.extend_from_slice(chunk);
         }
@@ -62,12 +64,15 @@ impl IncomingBuffer {
 
 pub(super) struct SplitReceiver {
     pending: HashMap<WrappingSequenceNumber, IncomingBuffer>,
+    /// provides the buffers of reassembled commands
+    buf_pool: BufPool,
 }
 
 impl SplitReceiver {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(buf_pool: BufPool) -> Self {
         Self {
             pending: HashMap::new(),
+            buf_pool,
         }
     }
 
@@ -77,7 +82,7 @@ impl SplitReceiver {
         &mut self,
         now: Instant,
         body: SplitBody,
-    ) -> anyhow::Result<Option<Vec<u8>>> {
+    ) -> anyhow::Result<Option<PooledBuf>> {
         let seqnum = body.seqnum;
         let should_take = self
             .pending
@@ -86,7 +91,9 @@ impl SplitReceiver {
             .push(now, body)?;
 
         if should_take {
-            Ok(Some(self.pending.remove(&seqnum).unwrap().take()))
+            Ok(Some(
+                self.pending.remove(&seqnum).unwrap().take(&self.buf_pool),
+            ))
         } else {
             Ok(None)
         }
@@ -1,11 +1,15 @@
+use anyhow::bail;
+
 use crate::commands::Command;
+use crate::commands::CommandProperties;
 use crate::types::ProtocolContext;
+use crate::wire::buf_pool::BufPool;
 use crate::wire::packet::InnerBody;
+use crate::wire::packet::MAX_COMMAND_SIZE;
 use crate::wire::packet::MAX_ORIGINAL_BODY_SIZE;
 use crate::wire::packet::MAX_SPLIT_BODY_SIZE;
 use crate::wire::packet::OriginalBody;
 use crate::wire::packet::SplitBody;
-use crate::wire::ser::MockSerializer;
 use crate::wire::ser::Serialize;
 use crate::wire::ser::VecSerializer;
 
@@ -13,27 +17,34 @@ use super::sequence_number::SequenceNumber;
 
 pub(super) struct SplitSender {
     next_seqnum: SequenceNumber,
+    /// provides the temporary buffer holding the command being split
+    buf_pool: BufPool,
 }
 
 impl SplitSender {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(buf_pool: BufPool) -> Self {
         Self {
             next_seqnum: SequenceNumber::init(),
+            buf_pool,
         }
     }
 
     /// Push a Command for transmission
     /// This will possibly split it into 1 or more packets.
+    ///
+    /// `total_size` must be the [`Command::estimated_size`] for the given context.
     pub(super) fn push(
         &mut self,
         context: ProtocolContext,
         command: Command,
+        total_size: usize,
     ) -> anyhow::Result<Vec<InnerBody>> {
-        let total_size = {
-            let mut ser = MockSerializer::new(context);
-            Command::serialize(&command, &mut ser)?;
-            ser.len()
-        };
+        if total_size > MAX_COMMAND_SIZE {
+            bail!(
+                "{name} is too large to be sent: {total_size} bytes exceed the limit of {MAX_COMMAND_SIZE} bytes",
+                name = command.command_name()
+            );
+        }
         let mut result = Vec::new();
         // Packets should serialize to at most 512 bytes
         if total_size <= MAX_ORIGINAL_BODY_SIZE {
@@ -42,10 +53,9 @@ impl SplitSender {
                 command: Some(command),
             }));
         } else {
-            // TODO(paradust): Can this extra allocation be avoided?
-            let mut ser = VecSerializer::new(context, total_size);
+            let mut ser = VecSerializer::pooled(context, &self.buf_pool, total_size);
             Command::serialize(&command, &mut ser)?;
-            let data = ser.take();
+            let data = ser.take_pooled();
             assert_eq!(data.len(), total_size, "length mismatch");
             let mut index: usize = 0;
             let mut offset: usize = 0;
@@ -67,3 +77,44 @@ impl SplitSender {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SplitSender;
+    use crate::commands::Command;
+    use crate::commands::client_to_server::TSChatMessageSpec;
+    use crate::types::ProtocolContext;
+    use crate::wire::buf_pool::BufPool;
+    use crate::wire::packet::{InnerBody, MAX_ORIGINAL_BODY_SIZE, MAX_SPLIT_BODY_SIZE};
+
+    fn chat_message(length: usize) -> Command {
+        Command::ToServer(
+            TSChatMessageSpec {
+                message: "x".repeat(length),
+            }
+            .into(),
+        )
+    }
+
+    #[test]
+    fn only_large_commands_are_split() {
+        let context = ProtocolContext::latest_for_send(true);
+        let mut sender = SplitSender::new(BufPool::default());
+
+        let small = chat_message(10);
+        let size = small.estimated_size(context).unwrap();
+        assert!(size <= MAX_ORIGINAL_BODY_SIZE);
+        let bodies = sender.push(context, small, size).unwrap();
+        assert!(matches!(bodies.as_slice(), [InnerBody::Original(_)]));
+
+        let large = chat_message(MAX_ORIGINAL_BODY_SIZE);
+        let large_size = large.estimated_size(context).unwrap();
+        let chunks = sender.push(context, large, large_size).unwrap();
+        assert_eq!(chunks.len(), large_size.div_ceil(MAX_SPLIT_BODY_SIZE));
+        assert!(
+            chunks
+                .iter()
+                .all(|body| matches!(body, InnerBody::Split(_)))
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Metrics of a peer covering both directions of its reliable streams.
+
+use crate::wire::channel_id::ChannelId;
+
+use super::send_queue::QueueStats;
+
+/// Counters of the reliable packets received on a single channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelReceiveStats {
+    /// reliable packets which have been received more than once, usually because an ack got lost
+    pub duplicates: u64,
+    /// reliable packets which arrived before one of their predecessors
+    pub out_of_order: u64,
+    /// reliable packets currently waiting for one of their predecessors
+    pub buffered: usize,
+}
+
+/// The state of a peer as published after every round of sending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PeerStats {
+    /// the outgoing queues
+    pub queues: QueueStats,
+    /// the incoming reliable streams, indexed by [`ChannelId`]
+    pub received: [ChannelReceiveStats; 3],
+}
+
+impl PeerStats {
+    /// Returns the receive counters of a single channel.
+    #[must_use]
+    pub fn received(&self, channel: ChannelId) -> &ChannelReceiveStats {
+        &self.received[usize::from(channel)]
+    }
+
+    /// Returns the total number of reliable packets which have been received more than once.
+    #[must_use]
+    pub fn total_duplicates(&self) -> u64 {
+        self.received.iter().map(|stats| stats.duplicates).sum()
+    }
+
+    /// Returns the total number of reliable packets which arrived before one of their
+    /// predecessors.
+    #[must_use]
+    pub fn total_out_of_order(&self) -> u64 {
+        self.received.iter().map(|stats| stats.out_of_order).sum()
+    }
+}
@@ -0,0 +1,509 @@
+//! Machine-readable description of the wire protocol
+//!
+//! The layout of every type deriving `LuantiSerialize` is available through [`Describe`]; the
+//! command ids, channels, reliability and version constraints come from the `define_protocol!`
+//! tables. Types with hand-written serializers are described as [`TypeSchema::Custom`].
+//!
+//! [`to_json`] renders the whole protocol, e.g. for generating documentation or for validating
+//! it against the C++ implementation. Values are rendered through [`ToJson`], which follows the
+//! same layout.
+
+use std::fmt::Write;
+
+use glam::I16Vec2;
+use glam::I16Vec3;
+use glam::IVec2;
+use glam::IVec3;
+use glam::U8Vec4;
+use glam::UVec2;
+use glam::Vec2;
+use glam::Vec3;
+use luanti_core::MapNode;
+use luanti_core::MapNodeIndex;
+
+use crate::types::BoneAbsolute;
+use crate::types::PlayerKeys;
+
+use crate::CommandDirection;
+use crate::commands::CommandProperties;
+use crate::commands::client_to_server::ToServerCommand;
+use crate::commands::server_to_client::ToClientCommand;
+use crate::wire::channel_id::ChannelId;
+use crate::wire::packet::LATEST_PROTOCOL_VERSION;
+use crate::wire::packet::PROTOCOL_ID;
+
+/// A field as it appears on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSchema {
+    /// name of the field; the index for tuple structs
+    pub name: &'static str,
+    /// type of the field as declared in Rust
+    pub ty: &'static str,
+    /// type used for (de)serialization if it differs from `ty`, see `#[wrap(…)]`
+    pub wire_ty: Option<&'static str>,
+    /// value used if the field is absent because the stream has ended
+    pub default: Option<&'static str>,
+    /// predicate telling whether the field is omitted when sending
+    pub skip_if: Option<&'static str>,
+    /// the lowest protocol version a peer needs to announce to receive the field
+    pub since: Option<&'static str>,
+}
+
+impl FieldSchema {
+    /// Whether the field may be absent, e.g. because an older peer doesn't know it.
+    #[must_use]
+    pub fn is_optional(&self) -> bool {
+        self.default.is_some()
+    }
+}
+
+/// Layout of a serialized type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeSchema {
+    /// fields being serialized in order
+    Struct(&'static [FieldSchema]),
+    /// unnamed fields being serialized in order
+    Tuple(&'static [FieldSchema]),
+    /// a `u8` tag; the variants are listed in the order of their tags starting with `0`
+    Enum(&'static [&'static str]),
+    /// serialized by hand; see the type's `Serialize` implementation
+    Custom,
+}
+
+/// Types which are able to describe their serialized layout
+///
+/// This is implemented by `#[derive(LuantiSerialize)]`.
+pub trait Describe {
+    /// layout of the serialized type
+    const SCHEMA: TypeSchema;
+}
+
+/// Values which can be rendered as JSON
+///
+/// This is implemented by `#[derive(LuantiSerialize)]`: structs become objects with the field
+/// names of their [`TypeSchema::Struct`], tuple structs become arrays and enums become the name
+/// of their variant. `None` and non-finite numbers become `null`.
+pub trait ToJson {
+    /// Appends the value to a JSON document.
+    fn write_json(&self, json: &mut String);
+}
+
+macro_rules! display_to_json {
+    ($($ty: ty),*) => {
+        $(impl ToJson for $ty {
+            fn write_json(&self, json: &mut String) {
+                write!(json, "{self}").expect("writing to a string cannot fail");
+            }
+        })*
+    };
+}
+
+display_to_json!(bool, u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+macro_rules! float_to_json {
+    ($($ty: ty),*) => {
+        $(impl ToJson for $ty {
+            fn write_json(&self, json: &mut String) {
+                if self.is_finite() {
+                    write!(json, "{self}").expect("writing to a string cannot fail");
+                } else {
+                    json.push_str("null");
+                }
+            }
+        })*
+    };
+}
+
+float_to_json!(f32, f64);
+
+impl ToJson for str {
+    fn write_json(&self, json: &mut String) {
+        write_json_string(json, self);
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, json: &mut String) {
+        write_json_string(json, self);
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn write_json(&self, json: &mut String) {
+        T::write_json(self, json);
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn write_json(&self, json: &mut String) {
+        match self {
+            Some(value) => value.write_json(json),
+            None => json.push_str("null"),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn write_json(&self, json: &mut String) {
+        json.push('[');
+        for (index, value) in self.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            value.write_json(json);
+        }
+        json.push(']');
+    }
+}
+
+impl<T: ToJson, const LENGTH: usize> ToJson for [T; LENGTH] {
+    fn write_json(&self, json: &mut String) {
+        self.as_slice().write_json(json);
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn write_json(&self, json: &mut String) {
+        self.as_slice().write_json(json);
+    }
+}
+
+macro_rules! tuple_to_json {
+    ($($ty: ident $index: tt),*) => {
+        impl<$($ty: ToJson),*> ToJson for ($($ty,)*) {
+            fn write_json(&self, json: &mut String) {
+                json.push('[');
+                $(
+                    if $index > 0 {
+                        json.push(',');
+                    }
+                    self.$index.write_json(json);
+                )*
+                json.push(']');
+            }
+        }
+    };
+}
+
+tuple_to_json!(T1 0, T2 1);
+tuple_to_json!(T1 0, T2 1, T3 2);
+
+/// Renders the names of the set flags; unknown bits are appended as a number.
+macro_rules! flags_to_json {
+    ($($ty: ty),*) => {
+        $(impl ToJson for $ty {
+            fn write_json(&self, json: &mut String) {
+                json.push('[');
+                for (index, (name, _)) in self.iter_names().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    write_json_string(json, name);
+                }
+                let unknown = self.bits() & !<$ty>::all().bits();
+                if unknown != 0 {
+                    if self.intersects(<$ty>::all()) {
+                        json.push(',');
+                    }
+                    unknown.write_json(json);
+                }
+                json.push(']');
+            }
+        })*
+    };
+}
+
+flags_to_json!(PlayerKeys, BoneAbsolute);
+
+macro_rules! vector_to_json {
+    ($($ty: ty),*) => {
+        $(impl ToJson for $ty {
+            fn write_json(&self, json: &mut String) {
+                self.to_array().write_json(json);
+            }
+        })*
+    };
+}
+
+vector_to_json!(Vec2, Vec3, IVec2, IVec3, UVec2, I16Vec2, I16Vec3, U8Vec4);
+
+impl ToJson for MapNode {
+    fn write_json(&self, json: &mut String) {
+        let Self {
+            content_id,
+            param1,
+            param2,
+        } = self;
+        write!(
+            json,
+            r#"{{"content_id":{},"param1":{param1},"param2":{param2}}}"#,
+            content_id.0
+        )
+        .expect("writing to a string cannot fail");
+    }
+}
+
+impl ToJson for MapNodeIndex {
+    fn write_json(&self, json: &mut String) {
+        u16::from(*self).write_json(json);
+    }
+}
+
+/// A command as listed in the protocol tables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandSchema {
+    /// name of the command; see [`CommandProperties::command_name`]
+    pub name: &'static str,
+    /// id preceding the command on the wire
+    pub id: u16,
+    pub direction: CommandDirection,
+    /// channel used unless requested otherwise
+    pub channel: ChannelId,
+    /// whether the command is sent reliably unless requested otherwise
+    pub reliable: bool,
+    /// the lowest protocol version a peer needs to announce to receive this command
+    pub since: Option<u16>,
+    /// name of the type holding the command's fields
+    pub body_type: &'static str,
+    pub body: TypeSchema,
+}
+
+/// Picks the optional `since` version of a `define_protocol!` entry.
+pub(crate) const fn since(versions: &[u16]) -> Option<u16> {
+    if let [version] = versions {
+        Some(*version)
+    } else {
+        None
+    }
+}
+
+/// Returns all commands, the ones sent to the server first.
+pub fn commands() -> impl Iterator<Item = &'static CommandSchema> {
+    ToServerCommand::SCHEMA
+        .iter()
+        .chain(ToClientCommand::SCHEMA.iter())
+}
+
+/// Looks up a command by its direction and id.
+#[must_use]
+pub fn find(direction: CommandDirection, id: u16) -> Option<&'static CommandSchema> {
+    commands().find(|command| command.direction == direction && command.id == id)
+}
+
+/// Looks up the description of the given command.
+#[must_use]
+pub fn describe<Cmd: CommandProperties>(command: &Cmd) -> Option<&'static CommandSchema> {
+    let direction = command.direction();
+    let name = command.command_name();
+    commands().find(|schema| schema.direction == direction && schema.name == name)
+}
+
+/// Renders the protocol description as a single JSON object.
+#[must_use]
+pub fn to_json() -> String {
+    let mut json = String::new();
+    write!(
+        json,
+        r#"{{"protocol_id":{PROTOCOL_ID},"protocol_version":{LATEST_PROTOCOL_VERSION},"commands":["#
+    )
+    .expect("writing to a string cannot fail");
+    for (index, command) in commands().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        write_command(&mut json, command);
+    }
+    json.push_str("]}");
+    json
+}
+
+fn write_command(json: &mut String, command: &CommandSchema) {
+    json.push_str(r#"{"name":"#);
+    write_json_string(json, command.name);
+    json.push_str(r#","id":"#);
+    json.push_str(&command.id.to_string());
+    json.push_str(r#","direction":"#);
+    write_json_string(json, &format!("{:?}", command.direction));
+    json.push_str(r#","channel":"#);
+    write_json_string(json, &command.channel.to_string());
+    json.push_str(r#","reliable":"#);
+    json.push_str(if command.reliable { "true" } else { "false" });
+    json.push_str(r#","since":"#);
+    json.push_str(
+        &command
+            .since
+            .map_or_else(|| "null".into(), |since| since.to_string()),
+    );
+    json.push_str(r#","type":"#);
+    write_json_string(json, command.body_type);
+    json.push_str(r#","body":"#);
+    write_type(json, &command.body);
+    json.push('}');
+}
+
+fn write_type(json: &mut String, schema: &TypeSchema) {
+    match schema {
+        TypeSchema::Struct(fields) | TypeSchema::Tuple(fields) => {
+            json.push_str(if matches!(schema, TypeSchema::Struct(_)) {
+                r#"{"kind":"struct","fields":["#
+            } else {
+                r#"{"kind":"tuple","fields":["#
+            });
+            for (index, field) in fields.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_field(json, field);
+            }
+            json.push_str("]}");
+        }
+        TypeSchema::Enum(variants) => {
+            json.push_str(r#"{"kind":"enum","variants":["#);
+            for (index, variant) in variants.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_json_string(json, variant);
+            }
+            json.push_str("]}");
+        }
+        TypeSchema::Custom => json.push_str(r#"{"kind":"custom"}"#),
+    }
+}
+
+fn write_field(json: &mut String, field: &FieldSchema) {
+    json.push_str(r#"{"name":"#);
+    write_json_string(json, field.name);
+    json.push_str(r#","type":"#);
+    write_json_string(json, field.ty);
+    for (key, value) in [
+        ("wire_type", field.wire_ty),
+        ("default", field.default),
+        ("skip_if", field.skip_if),
+        ("since", field.since),
+    ] {
+        json.push_str(",\"");
+        json.push_str(key);
+        json.push_str("\":");
+        if let Some(value) = value {
+            write_json_string(json, value);
+        } else {
+            json.push_str("null");
+        }
+    }
+    json.push('}');
+}
+
+/// Appends a string literal to a JSON document.
+pub fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            control if control.is_control() => {
+                write!(json, "\\u{:04x}", u32::from(control))
+                    .expect("writing to a string cannot fail");
+            }
+            other => json.push(other),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I16Vec3;
+
+    use super::{Describe, FieldSchema, ToJson, TypeSchema, commands, describe, find, to_json};
+    use crate::CommandDirection;
+    use crate::commands::server_to_client::{FarBlocksSpec, TimeOfDaySpec, ToClientCommand};
+    use crate::types::{PointedThing, TrailingBytes};
+    use crate::wire::channel_id::ChannelId;
+    use crate::wire::packet::FAR_BLOCKS_PROTOCOL_VERSION;
+
+    #[test]
+    fn commands_are_described() {
+        let far_blocks = find(CommandDirection::ToClient, 0x64).unwrap();
+        assert_eq!(far_blocks.name, "FarBlocks");
+        assert_eq!(far_blocks.channel, ChannelId::Response);
+        assert_eq!(far_blocks.since, Some(FAR_BLOCKS_PROTOCOL_VERSION));
+        assert_eq!(far_blocks.body, FarBlocksSpec::SCHEMA);
+
+        let TypeSchema::Struct(fields) = far_blocks.body else {
+            panic!("unexpected schema: {:?}", far_blocks.body);
+        };
+        let names: Vec<_> = fields.iter().map(|field| field.name).collect();
+        assert_eq!(names, ["pos", "level", "block"]);
+        assert_eq!(fields[0].ty, "I16Vec3");
+
+        let command = ToClientCommand::from(TimeOfDaySpec {
+            time_of_day: 0,
+            time_speed: Some(1.0),
+            extra: TrailingBytes::default(),
+        });
+        assert_eq!(describe(&command).unwrap().name, "TimeOfDay");
+
+        // ids are unique per direction
+        for schema in commands() {
+            assert_eq!(find(schema.direction, schema.id), Some(schema));
+        }
+    }
+
+    #[test]
+    fn json_escapes_source_text() {
+        let json = to_json();
+        assert!(json.starts_with(r#"{"protocol_id":"#));
+        assert!(json.contains(r#""name":"FarBlocks","id":100,"direction":"ToClient","channel":"response","reliable":true,"since":49,"type":"FarBlocksSpec""#));
+
+        let mut field = String::new();
+        super::write_field(
+            &mut field,
+            &FieldSchema {
+                name: "name",
+                ty: "String",
+                wire_ty: None,
+                default: Some("\"a\\b\"\n"),
+                skip_if: None,
+                since: None,
+            },
+        );
+        assert_eq!(
+            field,
+            r#"{"name":"name","type":"String","wire_type":null,"default":"\"a\\b\"\n","skip_if":null,"since":null}"#
+        );
+    }
+
+    #[test]
+    fn values_are_rendered_as_json() {
+        fn json(value: &impl ToJson) -> String {
+            let mut json = String::new();
+            value.write_json(&mut json);
+            json
+        }
+
+        let command = ToClientCommand::from(TimeOfDaySpec {
+            time_of_day: 6000,
+            time_speed: Some(1.5),
+            extra: TrailingBytes::default(),
+        });
+        assert_eq!(
+            json(&command),
+            r#"{"time_of_day":6000,"time_speed":1.5,"extra":[]}"#
+        );
+
+        assert_eq!(json(&PointedThing::Nothing), r#""Nothing""#);
+        assert_eq!(
+            json(&PointedThing::Node {
+                under_surface: I16Vec3::new(1, 2, 3),
+                above_surface: I16Vec3::new(1, 3, 3),
+            }),
+            r#"{"Node":{"under_surface":[1,2,3],"above_surface":[1,3,3]}}"#
+        );
+        assert_eq!(json(&Some(f32::NAN)), "null");
+        assert_eq!(json(&None::<u8>), "null");
+    }
+}
@@ -1,4 +1,6 @@
 pub mod client;
 pub mod conn;
+pub mod handshake;
+pub mod recorder;
 pub mod server;
 pub mod socket;
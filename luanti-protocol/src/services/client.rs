@@ -1,49 +1,572 @@
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
+use log::{debug, info, warn};
+use luanti_core::{MapNode, MapNodePos};
+use tokio::sync::watch;
 
+use super::handshake::{HandshakeState, HandshakeStateMachine};
 use super::socket::LuantiSocket;
 use crate::{
-    commands::{client_to_server::ToServerCommand, server_to_client::ToClientCommand},
+    commands::{
+        client_to_server::{InitSpec, ToServerCommand},
+        server_to_client::{CsmRestrictions, ToClientCommand},
+    },
     peer::Peer,
+    types::{CommandId, ContentFeatures, ModChannelState},
+    versions::{SER_FMT_HIGHEST_READ, clamp_protocol_version},
+    wire::{channel_id::ChannelId, packet::ControlBody},
 };
 
+mod content_store;
+mod controller;
+mod events;
+mod map_cache;
+mod media_cache;
+mod mod_channels;
+mod reconnect;
+
+pub use content_store::ContentStore;
+pub use controller::{DEFAULT_WALK_SPEED, PlayerController};
+pub use events::{ClientEvent, ClientEvents, EventCallback};
+pub use map_cache::MapCache;
+pub use media_cache::MediaCache;
+use mod_channels::ModChannels;
+use reconnect::Subscriptions;
+pub use reconnect::{ReconnectPolicy, Reconnected};
+
 #[allow(
     clippy::wildcard_imports,
     reason = "commands are expected to be used in bulk"
 )]
 use crate::commands::*;
 
+/// How long to wait for the server to answer a new connection; same as the engine's client
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct LuantiClient {
     server: Peer,
+    /// the received map blocks; `None` unless enabled
+    map_cache: Option<MapCache>,
+    /// the downloaded media files; `None` unless enabled
+    media_cache: Option<MediaCache>,
+    /// the node and item definitions; `None` unless enabled
+    content_store: Option<ContentStore>,
+    events: ClientEvents,
+    /// `None` if lost connections shall not be re-established
+    reconnect_policy: Option<ReconnectPolicy>,
+    subscriptions: Subscriptions,
+    mod_channels: ModChannels,
+    handshake: HandshakeStateMachine,
+    /// the restrictions of client-side mods as most recently sent by the server
+    csm_restrictions: CsmRestrictions,
+    /// whether the server asked to reconnect; this happens before receiving the next command
+    reconnect_requested: bool,
+    /// whether received commands are being audited, also after reconnecting
+    audit: bool,
 }
 
 impl LuantiClient {
     pub async fn connect(server_address: SocketAddr) -> anyhow::Result<Self> {
+        Self::connect_with_audit(server_address, false).await
+    }
+
+    /// Like [`Self::connect`], but all commands received from the server are being audited; see
+    /// [`crate::wire::audit`].
+    pub async fn connect_with_audit(
+        server_address: SocketAddr,
+        audit: bool,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            server: Self::connect_peer(server_address, CONNECT_TIMEOUT, audit).await?,
+            map_cache: None,
+            media_cache: None,
+            content_store: None,
+            events: ClientEvents::default(),
+            reconnect_policy: None,
+            subscriptions: Subscriptions::default(),
+            mod_channels: ModChannels::default(),
+            handshake: HandshakeStateMachine::new(Instant::now()),
+            csm_restrictions: CsmRestrictions::UNRESTRICTED,
+            reconnect_requested: false,
+            audit,
+        })
+    }
+
+    /// Connects to the server and waits for it to assign a peer id. Fails if the server doesn't
+    /// answer within `timeout`.
+    async fn connect_peer(
+        server_address: SocketAddr,
+        timeout: Duration,
+        audit: bool,
+    ) -> anyhow::Result<Peer> {
         let bind_addr = if server_address.is_ipv4() {
             "0.0.0.0:0".parse()?
         } else {
             "[::]:0".parse()?
         };
-        let mut socket = LuantiSocket::new(bind_addr, false).await?;
+        // the socket shuts down along with the peer
+        let (_, audit) = watch::channel(audit);
+        let mut socket = LuantiSocket::with_audit(bind_addr, false, audit).await?;
+        let mut server = socket.add_server(server_address).await;
 
-        // Send a null packet to server.
+        // Send a reliable ping to the server.
         // It should answer back, establishing a peer ids.
-        let server = socket.add_server(server_address).await;
+        server.send_inner(ChannelId::Default, true, ControlBody::Ping.into_inner())?;
+        let Ok(established) = tokio::time::timeout(timeout, server.established()).await else {
+            server.close();
+            bail!("{server_address} didn't answer within {timeout:?}");
+        };
+        established?;
+        Ok(server)
+    }
+
+    /// Enables re-establishing lost connections; `None` disables it (default).
+    ///
+    /// A connection is considered lost if the server denies access and asks the client to
+    /// reconnect, or if nothing has been received for a while. After reconnecting, a
+    /// [`ClientEvent::Reconnected`] is emitted, [`Self::recv`] and [`Self::recv_command`] fail
+    /// with [`Reconnected`] once, and the application needs to redo the handshake.
+    /// Joined mod channels are joined again once the client is ready.
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = reconnect_policy;
+    }
+
+    /// The progress of the handshake; see [`HandshakeStateMachine`]
+    #[must_use]
+    pub fn handshake_state(&self) -> HandshakeState {
+        self.handshake.state()
+    }
 
-        Ok(Self { server })
+    /// Changes the time after which a handshake without any progress fails; `None` disables the
+    /// timeout.
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake.set_timeout(timeout);
+    }
+
+    /// The restrictions of client-side mods imposed by the server;
+    /// [`CsmRestrictions::UNRESTRICTED`] until the server sent some after authentication.
+    #[must_use]
+    pub fn csm_restrictions(&self) -> CsmRestrictions {
+        self.csm_restrictions
+    }
+
+    /// The underlying peer, e.g. for sending raw packets on a specific channel
+    #[must_use]
+    pub fn peer(&self) -> &Peer {
+        &self.server
+    }
+
+    /// Starts keeping a copy of all received map blocks. Up to `max_blocks` blocks are being kept
+    /// before the ones farthest away from the player are evicted.
+    ///
+    /// Reception and eviction of map blocks will be acknowledged automatically from now on.
+    pub fn enable_map_cache(&mut self, max_blocks: usize) {
+        self.map_cache
+            .get_or_insert_with(|| MapCache::new(max_blocks));
+    }
+
+    /// The received map blocks; `None` unless the map cache has been enabled
+    #[must_use]
+    pub fn map_cache(&self) -> Option<&MapCache> {
+        self.map_cache.as_ref()
+    }
+
+    /// Returns a node of the cached map; `None` if its map block hasn't been received or the map
+    /// cache isn't enabled.
+    #[must_use]
+    pub fn get_node(&self, pos: MapNodePos) -> Option<MapNode> {
+        self.map_cache.as_ref()?.get_node(pos)
+    }
+
+    /// Starts downloading all announced media files which aren't available in the given cache
+    /// directory yet.
+    ///
+    /// This needs to be enabled before the server announces its media.
+    pub fn enable_media_cache(&mut self, directory: impl AsRef<Path>) -> anyhow::Result<()> {
+        if self.media_cache.is_none() {
+            self.media_cache = Some(MediaCache::new(directory)?);
+        }
+        Ok(())
     }
 
+    /// The downloaded media files; `None` unless the media cache has been enabled
+    #[must_use]
+    pub fn media_cache(&self) -> Option<&MediaCache> {
+        self.media_cache.as_ref()
+    }
+
+    /// Starts keeping the node and item definitions and media announcements received from the
+    /// server.
+    ///
+    /// This needs to be enabled before the server sends its definitions.
+    pub fn enable_content_store(&mut self) {
+        self.content_store.get_or_insert_with(ContentStore::new);
+    }
+
+    /// The received node and item definitions; `None` unless the content store has been enabled
+    #[must_use]
+    pub fn content_store(&self) -> Option<&ContentStore> {
+        self.content_store.as_ref()
+    }
+
+    /// Returns the definition of a node of the cached map; `None` if its map block or its
+    /// definition hasn't been received or the map cache or content store isn't enabled.
+    #[must_use]
+    pub fn get_node_features(&self, pos: MapNodePos) -> Option<&ContentFeatures> {
+        self.content_store.as_ref()?.resolve(self.get_node(pos)?)
+    }
+
+    /// Asks the server to join a mod channel. The channel becomes writable once the server
+    /// confirmed it.
+    ///
+    /// If this fails, the client has disconnected.
+    pub fn join_mod_channel(&mut self, channel: &str) -> anyhow::Result<()> {
+        self.send(ToServerCommand::ModchannelJoin(Box::new(
+            client_to_server::ModchannelJoinSpec {
+                channel_name: channel.to_owned(),
+            },
+        )))
+    }
+
+    /// Asks the server to leave a mod channel.
+    ///
     /// If this fails, the client has disconnected.
+    pub fn leave_mod_channel(&mut self, channel: &str) -> anyhow::Result<()> {
+        self.send(ToServerCommand::ModchannelLeave(Box::new(
+            client_to_server::ModchannelLeaveSpec {
+                channel_name: channel.to_owned(),
+            },
+        )))
+    }
+
+    /// Sends a message to everyone else who joined the mod channel.
+    ///
+    /// Fails if the channel isn't writable or the client has disconnected.
+    pub fn send_mod_channel_message(&mut self, channel: &str, message: &str) -> anyhow::Result<()> {
+        let state = self.mod_channels.state(channel);
+        if state != Some(ModChannelState::ReadWrite) {
+            bail!("mod channel '{channel}' isn't writable: {state:?}");
+        }
+        self.send(ToServerCommand::TSModchannelMsg(Box::new(
+            client_to_server::TSModchannelMsgSpec {
+                channel_name: channel.to_owned(),
+                channel_msg: message.to_owned(),
+            },
+        )))
+    }
+
+    /// Returns the state of a mod channel; `None` if it hasn't been joined.
+    #[must_use]
+    pub fn mod_channel_state(&self, channel: &str) -> Option<ModChannelState> {
+        self.mod_channels.state(channel)
+    }
+
+    /// Registers a callback which will be called for every [`ClientEvent`] being received from now
+    /// on. Events are only being produced while commands are being received.
+    pub fn subscribe(&mut self, callback: impl FnMut(&ClientEvent) + Send + 'static) {
+        self.events.subscribe(callback);
+    }
+
+    /// If this fails with [`Reconnected`], a new connection has been established according to
+    /// the reconnect policy. Otherwise the client has disconnected.
     pub async fn recv(&mut self) -> anyhow::Result<ToClientCommand> {
-        match self.server.recv().await? {
-            Command::ToClient(cmd) => Ok(cmd),
-            Command::ToServer(_) => bail!("Invalid packet direction"),
+        loop {
+            match self.recv_with_event().await? {
+                (Some(Command::ToClient(command)), _) => return Ok(command),
+                (Some(Command::Unknown(command)), _) => {
+                    debug!("ignoring unknown command {}", command.id);
+                }
+                (None, Some(ClientEvent::Reconnected { attempt })) => {
+                    bail!(Reconnected { attempt });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Like [`Self::recv`] but also returns the commands this crate doesn't know about as
+    /// [`Command::Unknown`], e.g. for passing them on; see [`Self::send_raw_command`].
+    ///
+    /// If this fails with [`Reconnected`], a new connection has been established according to
+    /// the reconnect policy. Otherwise the client has disconnected.
+    pub async fn recv_command(&mut self) -> anyhow::Result<Command> {
+        loop {
+            match self.recv_with_event().await? {
+                (Some(command), _) => return Ok(command),
+                (None, Some(ClientEvent::Reconnected { attempt })) => {
+                    bail!(Reconnected { attempt });
+                }
+                _ => (),
+            }
         }
     }
 
+    /// Receives commands until one of them translates into a [`ClientEvent`].
+    ///
     /// If this fails, the client has disconnected.
-    pub fn send(&mut self, command: ToServerCommand) -> anyhow::Result<()> {
-        self.server.send(Command::ToServer(command))
+    pub async fn recv_event(&mut self) -> anyhow::Result<ClientEvent> {
+        loop {
+            if let (_, Some(event)) = self.recv_with_event().await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Receives the next command and its event. Only the event is being returned after
+    /// reconnecting and for events caused by sent commands; see [`ClientEvents::take_queued`].
+    ///
+    /// A failed handshake and a requested reconnect are handled by the call following the command
+    /// which caused it, so the application gets to see e.g. the `AccessDenied` command.
+    async fn recv_with_event(&mut self) -> anyhow::Result<(Option<Command>, Option<ClientEvent>)> {
+        if let Some(event) = self.events.take_queued() {
+            return Ok((None, Some(event)));
+        }
+        if self.reconnect_requested {
+            self.reconnect_requested = false;
+            let event = self.reconnect().await?;
+            return Ok((None, Some(event)));
+        }
+        let now = Instant::now();
+        self.handshake.check(now)?;
+        let deadline = [
+            self.handshake.deadline(),
+            self.reconnect_policy.map(|policy| now + policy.timeout),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let received = if let Some(deadline) = deadline {
+            tokio::time::timeout_at(deadline.into(), self.server.recv())
+                .await
+                .unwrap_or_else(|_| Err(self.timed_out(now)))
+        } else {
+            self.server.recv().await
+        };
+
+        let cmd = match received {
+            Ok(Command::ToClient(cmd)) => cmd,
+            Ok(Command::ToServer(_)) => bail!("Invalid packet direction"),
+            Ok(command @ Command::Unknown(_)) => return Ok((Some(command), None)),
+            Err(error) if self.reconnect_policy.is_some() => {
+                warn!("connection lost: {error}");
+                let event = self.reconnect().await?;
+                return Ok((None, Some(event)));
+            }
+            Err(error) => return Err(error),
+        };
+
+        let mut replies = Vec::new();
+        if let Some(map_cache) = &mut self.map_cache {
+            replies.extend(map_cache.handle_command(&cmd));
+        }
+        if let Some(media_cache) = &mut self.media_cache {
+            replies.extend(media_cache.handle_command(&cmd)?);
+        }
+        if let Some(content_store) = &mut self.content_store {
+            content_store.handle_command(&cmd);
+        }
+        self.mod_channels.handle_command(&cmd);
+        if let ToClientCommand::CsmRestrictionFlags(spec) = &cmd {
+            self.csm_restrictions = CsmRestrictions::from(spec.as_ref());
+            debug!(
+                "client-side mods are restricted: {:?}",
+                self.csm_restrictions
+            );
+        }
+        for reply in replies {
+            self.send(reply)?;
+        }
+
+        if let ToClientCommand::AccessDenied(access_denied) = &cmd {
+            if access_denied.reconnect && self.reconnect_policy.is_some() {
+                info!(
+                    "server asked to reconnect: {reason}",
+                    reason = access_denied.reason
+                );
+                self.reconnect_requested = true;
+                return Ok((Some(Command::ToClient(cmd)), None));
+            }
+        }
+
+        if let Err(error) = self.handshake.handle_command(&cmd, Instant::now()) {
+            debug!("handshake failed: {error}");
+        }
+        let event = self.events.handle_command(&cmd);
+        Ok((Some(Command::ToClient(cmd)), event))
+    }
+
+    /// Explains why nothing has been received since `since`.
+    fn timed_out(&mut self, since: Instant) -> anyhow::Error {
+        match self.handshake.check(Instant::now()) {
+            Err(error) => error.into(),
+            Ok(_) => anyhow!(
+                "nothing received for {elapsed:?}",
+                elapsed = since.elapsed()
+            ),
+        }
+    }
+
+    /// Establishes a new connection to the same server according to the reconnect policy.
+    ///
+    /// The previous connection is closed first, which also shuts down its socket.
+    async fn reconnect(&mut self) -> anyhow::Result<ClientEvent> {
+        let policy = self.reconnect_policy.unwrap_or_default();
+        let server_address = self.server.remote_addr();
+        self.server.close();
+        for attempt in 1..=policy.max_attempts {
+            tokio::time::sleep(policy.delay).await;
+            match Self::connect_peer(server_address, policy.timeout, self.audit).await {
+                Ok(server) => {
+                    info!("reconnected to {server_address} after {attempt} attempt(s)");
+                    self.server = server;
+                    // the new session starts without any map blocks
+                    if let Some(map_cache) = &mut self.map_cache {
+                        map_cache.clear();
+                    }
+                    // the definitions will be sent again during the handshake
+                    if let Some(content_store) = &mut self.content_store {
+                        content_store.clear();
+                    }
+                    self.subscriptions.reconnected();
+                    self.mod_channels.clear();
+                    self.csm_restrictions = CsmRestrictions::UNRESTRICTED;
+                    self.handshake.reset(Instant::now());
+                    return Ok(self.events.handle_reconnect(attempt));
+                }
+                Err(error) => warn!("reconnect attempt {attempt} failed: {error}"),
+            }
+        }
+        bail!(
+            "failed to reconnect to {server_address} after {attempts} attempts",
+            attempts = policy.max_attempts
+        )
+    }
+
+    /// If this fails, the client has disconnected.
+    ///
+    /// The protocol versions announced by an `Init` command are limited to the ones this crate
+    /// implements; see [`clamp_protocol_version`].
+    pub fn send(&mut self, mut command: ToServerCommand) -> anyhow::Result<()> {
+        if let ToServerCommand::Init(spec) = &mut command {
+            clamp_init_versions(spec);
+        }
+        if let Some(map_cache) = &mut self.map_cache {
+            map_cache.handle_sent_command(&command);
+        }
+        self.events.handle_sent_command(&command);
+        self.mod_channels.handle_sent_command(&command);
+        if let Err(error) = self.handshake.handle_command(&command, Instant::now()) {
+            debug!("handshake failed: {error}");
+        }
+        let replays = self.subscriptions.handle_sent_command(&command);
+        self.server.send(Command::ToServer(command))?;
+        for replay in replays {
+            self.send(replay)?;
+        }
+        Ok(())
+    }
+
+    /// Send a command this crate doesn't know about; see [`Peer::send_raw_command`].
+    pub fn send_raw_command(&self, id: CommandId, payload: Vec<u8>) -> anyhow::Result<()> {
+        self.server.send_raw_command(id, payload)
+    }
+}
+
+/// Prevents the server from picking versions this crate doesn't implement.
+fn clamp_init_versions(spec: &mut InitSpec) {
+    let max_version = clamp_protocol_version(spec.max_net_proto_version);
+    if max_version != spec.max_net_proto_version {
+        warn!(
+            "protocol version {requested} is not implemented; announcing {max_version} instead",
+            requested = spec.max_net_proto_version
+        );
+        spec.max_net_proto_version = max_version;
+    }
+    spec.min_net_proto_version = spec.min_net_proto_version.min(max_version);
+    spec.serialization_ver_max = spec.serialization_ver_max.min(SER_FMT_HIGHEST_READ);
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{LuantiClient, ReconnectPolicy, Reconnected, clamp_init_versions};
+    use crate::commands::Command;
+    use crate::commands::client_to_server::InitSpec;
+    use crate::commands::server_to_client::{
+        AccessDeniedCode, AccessDeniedCommand, ToClientCommand,
+    };
+    use crate::services::socket::LuantiSocket;
+    use crate::versions::MAX_PROTOCOL_VERSION;
+
+    #[tokio::test]
+    async fn connecting_fails_if_the_server_does_not_answer() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let result = LuantiClient::connect_peer(
+            silent.local_addr().unwrap(),
+            Duration::from_millis(100),
+            false,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_asked_to() {
+        let mut server = LuantiSocket::new("127.0.0.1:0".parse().unwrap(), true)
+            .await
+            .unwrap();
+        let mut client = LuantiClient::connect(server.local_addr()).await.unwrap();
+        client.set_reconnect_policy(Some(ReconnectPolicy {
+            max_attempts: 1,
+            delay: Duration::ZERO,
+            timeout: Duration::from_secs(5),
+        }));
+        let mut first_session = server.accept().await.unwrap();
+
+        let denied: ToClientCommand = AccessDeniedCommand {
+            code: AccessDeniedCode::Shutdown,
+            reason: String::new(),
+            reconnect: true,
+        }
+        .into();
+        first_session.send(Command::ToClient(denied)).unwrap();
+        // the application gets to see the command before the client reconnects
+        assert!(matches!(
+            client.recv().await.unwrap(),
+            ToClientCommand::AccessDenied(_)
+        ));
+        let error = client.recv().await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Reconnected>(),
+            Some(&Reconnected { attempt: 1 })
+        );
+
+        // the previous connection has been closed
+        first_session.recv().await.unwrap_err();
+        let second_session = server.accept().await.unwrap();
+        assert_ne!(second_session.remote_addr(), first_session.remote_addr());
+    }
+
+    #[test]
+    fn unimplemented_versions_are_not_announced() {
+        let mut spec = InitSpec {
+            serialization_ver_max: 30,
+            supp_compr_modes: 0,
+            min_net_proto_version: 37,
+            max_net_proto_version: 48,
+            user_name: "alice".into(),
+        };
+        clamp_init_versions(&mut spec);
+        assert_eq!(spec.max_net_proto_version, MAX_PROTOCOL_VERSION);
+        assert_eq!(spec.min_net_proto_version, 37);
+        assert_eq!(spec.serialization_ver_max, 29);
     }
 }
@@ -0,0 +1,216 @@
+//! Contains the `ContentStore`
+
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use luanti_core::{ContentId, MapNode};
+
+use crate::commands::server_to_client::{
+    AnnounceMediaSpec, ItemAlias, ItemDef, ItemdefCommand, NodedefSpec, ToClientCommand,
+};
+use crate::types::{ContentFeatures, MediaAnnouncement, NodeDefManager};
+
+/// The node and item definitions and the media announced by the server.
+///
+/// Map blocks refer to nodes by the numeric [`ContentId`] the server assigned to them while
+/// everything else refers to them by name. This keeps both directions of the mapping, so the
+/// nodes of received map blocks can be resolved without reimplementing it.
+#[derive(Debug, Default)]
+pub struct ContentStore {
+    /// node definitions by content id
+    nodes: HashMap<ContentId, ContentFeatures>,
+    /// content ids by node name
+    content_ids: HashMap<String, ContentId>,
+    /// item definitions (including nodes) by name
+    items: HashMap<String, ItemDef>,
+    /// names being redirected to other items or nodes
+    aliases: HashMap<String, String>,
+    /// the announced SHA1 (Base64-encoded) of every media file
+    media: HashMap<String, String>,
+}
+
+impl ContentStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` once the node and item definitions have been received.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        !self.nodes.is_empty() && !self.items.is_empty()
+    }
+
+    /// Returns the definition of a node; `None` if the content id hasn't been defined.
+    #[must_use]
+    pub fn features(&self, content_id: ContentId) -> Option<&ContentFeatures> {
+        self.nodes.get(&content_id)
+    }
+
+    /// Returns the definition of the material of a (map) node.
+    #[must_use]
+    pub fn resolve(&self, node: MapNode) -> Option<&ContentFeatures> {
+        self.features(node.content_id)
+    }
+
+    /// Returns the name of a node; `None` if the content id hasn't been defined.
+    #[must_use]
+    pub fn node_name(&self, content_id: ContentId) -> Option<&str> {
+        self.features(content_id)
+            .map(|features| features.name.as_str())
+    }
+
+    /// Returns the content id of a node by its name or alias.
+    #[must_use]
+    pub fn content_id(&self, name: &str) -> Option<ContentId> {
+        self.content_ids.get(self.resolve_alias(name)).copied()
+    }
+
+    /// Returns the definition of a node by its name or alias.
+    #[must_use]
+    pub fn node(&self, name: &str) -> Option<&ContentFeatures> {
+        self.features(self.content_id(name)?)
+    }
+
+    /// Returns the definition of an item by its name or alias.
+    #[must_use]
+    pub fn item(&self, name: &str) -> Option<&ItemDef> {
+        self.items.get(self.resolve_alias(name))
+    }
+
+    /// Returns the name an alias refers to, or the name itself if it isn't an alias.
+    #[must_use]
+    pub fn resolve_alias<'name>(&'name self, name: &'name str) -> &'name str {
+        self.aliases.get(name).map_or(name, String::as_str)
+    }
+
+    /// Returns the announced SHA1 (Base64-encoded) of a media file.
+    #[must_use]
+    pub fn media_sha1(&self, name: &str) -> Option<&str> {
+        self.media.get(name).map(String::as_str)
+    }
+
+    /// Returns the names of all announced media files.
+    pub fn media_names(&self) -> impl Iterator<Item = &str> {
+        self.media.keys().map(String::as_str)
+    }
+
+    /// Forgets everything, e.g. because a new session is about to start.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Records the definitions and media announcements received from the server.
+    pub fn handle_command(&mut self, command: &ToClientCommand) {
+        match command {
+            ToClientCommand::Nodedef(spec) => {
+                let NodedefSpec { node_def } = spec.as_ref();
+                self.handle_node_defs(node_def);
+            }
+            ToClientCommand::Itemdef(spec) => {
+                let ItemdefCommand { item_def } = spec.as_ref();
+                self.items = item_def
+                    .defs
+                    .iter()
+                    .map(|def| (def.name.clone(), def.clone()))
+                    .collect();
+                self.aliases = item_def
+                    .aliases
+                    .iter()
+                    .map(|ItemAlias { name, convert_to }| (name.clone(), convert_to.clone()))
+                    .collect();
+                debug!(
+                    "received {items} item definitions and {aliases} aliases",
+                    items = self.items.len(),
+                    aliases = self.aliases.len()
+                );
+            }
+            ToClientCommand::AnnounceMedia(spec) => {
+                let AnnounceMediaSpec { files, .. } = spec.as_ref();
+                self.media = files
+                    .iter()
+                    .map(|MediaAnnouncement { name, sha1_base64 }| {
+                        (name.clone(), sha1_base64.clone())
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_node_defs(&mut self, node_def: &NodeDefManager) {
+        self.nodes.clear();
+        self.content_ids.clear();
+        for (id, features) in &node_def.content_features {
+            let content_id = ContentId(*id);
+            if let Some(previous) = self.content_ids.insert(features.name.clone(), content_id) {
+                warn!(
+                    "node '{name}' has been defined as {previous:?} and {content_id:?}",
+                    name = features.name
+                );
+            }
+            self.nodes.insert(content_id, features.clone());
+        }
+        debug!(
+            "received {nodes} node definitions",
+            nodes = self.nodes.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use luanti_core::{ContentId, MapNode};
+
+    use super::ContentStore;
+    use crate::commands::server_to_client::{
+        AnnounceMediaSpec, ItemAlias, ItemdefCommand, ItemdefList, NodedefSpec, ToClientCommand,
+    };
+    use crate::types::{ContentFeatures, MediaAnnouncement, NodeDefManager};
+
+    #[test]
+    fn map_nodes_are_resolved() {
+        let mut store = ContentStore::new();
+        store.handle_command(&ToClientCommand::Nodedef(Box::new(NodedefSpec {
+            node_def: NodeDefManager {
+                content_features: vec![
+                    (0, ContentFeatures::new_unknown("default:stone".into())),
+                    (126, ContentFeatures::new_unknown("air".into())),
+                ],
+            },
+        })));
+        store.handle_command(&ToClientCommand::Itemdef(Box::new(ItemdefCommand {
+            item_def: ItemdefList {
+                itemdef_manager_version: 0,
+                defs: Vec::new(),
+                aliases: vec![ItemAlias {
+                    name: "stone".into(),
+                    convert_to: "default:stone".into(),
+                }],
+            },
+        })));
+        store.handle_command(&ToClientCommand::AnnounceMedia(Box::new(
+            AnnounceMediaSpec {
+                files: vec![MediaAnnouncement {
+                    name: "default_stone.png".into(),
+                    sha1_base64: "hash".into(),
+                }],
+                remote_servers: String::new(),
+            },
+        )));
+
+        let node = MapNode {
+            content_id: ContentId(0),
+            param1: 0,
+            param2: 0,
+        };
+        assert_eq!(store.resolve(node).unwrap().name, "default:stone");
+        assert_eq!(store.node_name(ContentId::AIR), Some("air"));
+        assert_eq!(store.node_name(ContentId::IGNORE), None);
+        assert_eq!(store.content_id("stone"), Some(ContentId(0)));
+        assert_eq!(store.node("default:stone").unwrap().name, "default:stone");
+        assert_eq!(store.media_sha1("default_stone.png"), Some("hash"));
+        // no items have been defined
+        assert!(!store.is_ready());
+    }
+}
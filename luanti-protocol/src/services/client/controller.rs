@@ -0,0 +1,268 @@
+//! Contains the `PlayerController` for headless clients
+
+use std::time::Duration;
+
+use glam::{I16Vec3, Vec3};
+
+use crate::commands::client_to_server::{
+    InteractSpec, PlayerItemSpec, PlayerPosCommand, ToServerCommand,
+};
+use crate::commands::server_to_client::{MovePlayerSpec, ToClientCommand};
+use crate::types::{InteractAction, PlayerKeys, PlayerPos, PointedThing};
+
+/// positions and speeds are being transferred in tenths of a node
+const PROTOCOL_SCALE: f32 = 10.0;
+
+/// The walking speed of the engine's default physics in nodes per second
+pub const DEFAULT_WALK_SPEED: f32 = 4.0;
+
+/// Remote-controls a player like a human would do using a real client.
+///
+/// The controller doesn't send anything by itself but creates the commands to be sent, so it can
+/// be used with any transport. Call [`Self::step`] periodically (the engine's clients send their
+/// position every 100 ms) to make the player walk towards its target.
+///
+/// Positions are in nodes and angles in degrees.
+#[derive(Debug, Clone)]
+pub struct PlayerController {
+    position: Vec3,
+    velocity: Vec3,
+    pitch: f32,
+    yaw: f32,
+    target: Option<Vec3>,
+    walk_speed: f32,
+    selected_item: u16,
+    /// field of view in radians
+    fov: f32,
+    /// view range in map blocks
+    wanted_range: u8,
+}
+
+impl PlayerController {
+    /// Creates a controller for a player standing at the given position.
+    #[must_use]
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            pitch: 0.0,
+            yaw: 0.0,
+            target: None,
+            walk_speed: DEFAULT_WALK_SPEED,
+            selected_item: 0,
+            fov: 72_f32.to_radians(),
+            wanted_range: 10,
+        }
+    }
+
+    /// Changes the speed the player walks at in nodes per second.
+    pub fn set_walk_speed(&mut self, walk_speed: f32) {
+        self.walk_speed = walk_speed.max(0.0);
+    }
+
+    #[must_use]
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Returns the hotbar slot being selected.
+    #[must_use]
+    pub fn selected_item(&self) -> u16 {
+        self.selected_item
+    }
+
+    /// Returns `true` if the player hasn't reached its target yet.
+    #[must_use]
+    pub fn is_walking(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Lets the player walk towards the given position in a straight line.
+    pub fn walk_to(&mut self, target: Vec3) {
+        self.target = Some(target);
+    }
+
+    /// Stops walking immediately.
+    pub fn stop(&mut self) {
+        self.target = None;
+        self.velocity = Vec3::ZERO;
+    }
+
+    /// Turns the player to look at the given position.
+    pub fn look_at(&mut self, target: Vec3) {
+        let direction = target - self.position;
+        if direction.length_squared() > 0.0 {
+            self.yaw = (-direction.x).atan2(direction.z).to_degrees();
+            self.pitch = (-direction.y)
+                .atan2(direction.x.hypot(direction.z))
+                .to_degrees();
+        }
+    }
+
+    /// Advances the player's movement by the given time and returns the position update which
+    /// needs to be sent to the server.
+    pub fn step(&mut self, elapsed: Duration) -> ToServerCommand {
+        let seconds = elapsed.as_secs_f32();
+        if let Some(target) = self.target {
+            let remaining = target - self.position;
+            let distance = remaining.length();
+            let max_distance = self.walk_speed * seconds;
+            if distance <= max_distance {
+                self.position = target;
+                self.stop();
+            } else {
+                let direction = remaining / distance;
+                self.velocity = direction * self.walk_speed;
+                self.position += direction * max_distance;
+                // keep the player looking ahead without changing the pitch
+                let pitch = self.pitch;
+                self.look_at(target);
+                self.pitch = pitch;
+            }
+        }
+        ToServerCommand::Playerpos(Box::new(PlayerPosCommand {
+            player_pos: self.player_pos(),
+        }))
+    }
+
+    /// Selects a hotbar slot; the slots are counted from `0`.
+    pub fn select_item(&mut self, slot: u16) -> ToServerCommand {
+        self.selected_item = slot;
+        ToServerCommand::PlayerItem(Box::new(PlayerItemSpec { item: slot }))
+    }
+
+    /// Returns the commands for digging a node with the selected item.
+    ///
+    /// The commands start and complete the digging at once. Servers checking the digging time
+    /// expect a delay between both commands, depending on the node and the tool being used; see
+    /// [`ToolCapabilities::dig_params`](crate::commands::server_to_client::ToolCapabilities::dig_params).
+    #[must_use]
+    pub fn dig(&self, pos: I16Vec3) -> [ToServerCommand; 2] {
+        [
+            self.interact(InteractAction::StartDigging, pos),
+            self.interact(InteractAction::DiggingCompleted, pos),
+        ]
+    }
+
+    /// Returns the command for placing the selected item against the face of the node at `pos`
+    /// which points towards the player.
+    #[must_use]
+    pub fn place(&self, pos: I16Vec3) -> ToServerCommand {
+        self.interact(InteractAction::Place, pos)
+    }
+
+    /// Returns the command for an arbitrary interaction with a node.
+    #[must_use]
+    pub fn interact(&self, action: InteractAction, pos: I16Vec3) -> ToServerCommand {
+        ToServerCommand::Interact(Box::new(InteractSpec {
+            action,
+            item_index: self.selected_item,
+            pointed_thing: PointedThing::Node {
+                under_surface: pos,
+                above_surface: pos + self.facing_side(pos),
+            },
+            player_pos: self.player_pos(),
+        }))
+    }
+
+    /// Adopts positions the server moved the player to.
+    pub fn handle_command(&mut self, command: &ToClientCommand) {
+        if let ToClientCommand::MovePlayer(spec) = command {
+            let &MovePlayerSpec { pos, pitch, yaw } = spec.as_ref();
+            self.position = pos / PROTOCOL_SCALE;
+            self.pitch = pitch;
+            self.yaw = yaw;
+            self.stop();
+        }
+    }
+
+    /// Returns the direction of the neighbor of a node which is closest to the player.
+    fn facing_side(&self, pos: I16Vec3) -> I16Vec3 {
+        let offset = self.position - pos.as_vec3();
+        let abs = offset.abs();
+        if abs.x >= abs.y && abs.x >= abs.z {
+            I16Vec3::X * offset.x.signum() as i16
+        } else if abs.y >= abs.z {
+            I16Vec3::Y * offset.y.signum() as i16
+        } else {
+            I16Vec3::Z * offset.z.signum() as i16
+        }
+    }
+
+    fn player_pos(&self) -> PlayerPos {
+        let walking = self.velocity != Vec3::ZERO;
+        PlayerPos {
+            position: self.position * PROTOCOL_SCALE,
+            speed: self.velocity * PROTOCOL_SCALE,
+            pitch: self.pitch,
+            yaw: self.yaw,
+            keys_pressed: if walking {
+                PlayerKeys::UP
+            } else {
+                PlayerKeys::empty()
+            },
+            fov: self.fov,
+            wanted_range: self.wanted_range,
+            camera_inverted: false,
+            movement_speed: if walking { 1.0 } else { 0.0 },
+            movement_direction: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use glam::{I16Vec3, Vec3};
+
+    use super::PlayerController;
+    use crate::commands::client_to_server::ToServerCommand;
+    use crate::types::{InteractAction, PointedThing};
+
+    #[test]
+    fn players_walk_towards_their_target() {
+        let mut controller = PlayerController::new(Vec3::ZERO);
+        controller.walk_to(Vec3::new(0.0, 0.0, 1.0));
+        let ToServerCommand::Playerpos(command) = controller.step(Duration::from_millis(100))
+        else {
+            panic!("expected a position update");
+        };
+        let player_pos = &command.player_pos;
+        assert!(
+            player_pos
+                .position
+                .abs_diff_eq(Vec3::new(0.0, 0.0, 4.0), 1e-4)
+        );
+        assert!(
+            player_pos
+                .speed
+                .abs_diff_eq(Vec3::new(0.0, 0.0, 40.0), 1e-4)
+        );
+        assert!(player_pos.yaw.abs() < 1e-4);
+        assert!(controller.is_walking());
+
+        for _ in 0..3 {
+            controller.step(Duration::from_millis(100));
+        }
+        assert_eq!(controller.position(), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!controller.is_walking());
+    }
+
+    #[test]
+    fn interactions_point_at_the_side_facing_the_player() {
+        let controller = PlayerController::new(Vec3::new(0.0, 0.0, 0.0));
+        let [start, _] = controller.dig(I16Vec3::new(0, -1, 0));
+        let ToServerCommand::Interact(spec) = start else {
+            panic!("expected an interaction");
+        };
+        assert_eq!(spec.action, InteractAction::StartDigging);
+        assert_eq!(
+            spec.pointed_thing,
+            PointedThing::Node {
+                under_surface: I16Vec3::new(0, -1, 0),
+                above_surface: I16Vec3::new(0, 0, 0),
+            }
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Contains `ClientEvents` translating received commands into semantic events
+
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+
+use glam::Vec3;
+use luanti_core::MapBlockPos;
+
+use crate::commands::client_to_server::{RequestMediaSpec, ToServerCommand};
+use crate::commands::server_to_client::{
+    AnnounceMediaSpec, BlockdataSpec, InventorySpec, MediaSpec, ModchannelSignalSpec,
+    MovePlayerSpec, TCChatMessageSpec, TCModchannelMsgSpec, ToClientCommand,
+};
+use crate::types::{Inventory, MediaFileData, ModChannelSignal, RichText};
+
+/// positions are being transferred in tenths of a node
+const PROTOCOL_SCALE: f32 = 10.0;
+
+/// Something which happened from the perspective of a player
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// A chat message has been received.
+    ChatReceived {
+        /// name of the sending player; empty for messages of the server
+        sender: String,
+        message: RichText,
+        /// the engine's `ChatMessageType`, e.g. `0` for raw messages
+        message_type: u8,
+        /// seconds since the Unix epoch
+        timestamp: u64,
+    },
+    /// A map block has been received.
+    BlockLoaded(MapBlockPos),
+    /// The player's inventory has been changed.
+    InventoryUpdated(Inventory),
+    /// The player has been moved by the server.
+    PlayerMoved {
+        /// position of the player's feet in nodes
+        position: Vec3,
+        pitch: f32,
+        yaw: f32,
+    },
+    /// The media exchange of the session has finished: all requested media files have been
+    /// received, or none had to be requested. Contains the received files; this is reported once
+    /// per session.
+    MediaReady(Vec<MediaFileData>),
+    /// A message has been sent to a joined mod channel.
+    ModChannelMessage {
+        channel: String,
+        /// name of the sending player; empty for messages of the server
+        sender: String,
+        message: String,
+    },
+    /// The server answered a mod channel request or changed the state of a channel.
+    ModChannelSignal {
+        channel: String,
+        signal: ModChannelSignal,
+    },
+    /// The connection has been lost and a new one has been established. The application needs to
+    /// redo the handshake as the server treats this as a new session.
+    Reconnected {
+        /// number of connection attempts it took to reconnect; starts at `1`
+        attempt: u32,
+    },
+}
+
+/// A callback being notified about every [`ClientEvent`]
+pub type EventCallback = Box<dyn FnMut(&ClientEvent) + Send>;
+
+/// Translates the commands received from the server into [`ClientEvent`]s and forwards them to
+/// all subscribers.
+#[derive(Default)]
+pub struct ClientEvents {
+    subscribers: Vec<EventCallback>,
+    media: MediaExchange,
+    /// an event caused by a sent command which hasn't been returned yet
+    queued: Option<ClientEvent>,
+}
+
+/// The progress of requesting the media files announced by the server
+#[derive(Debug, Default)]
+struct MediaExchange {
+    /// whether the server announced its media files
+    announced: bool,
+    /// whether media files have been requested
+    requested: bool,
+    /// names of requested media files which haven't been received yet
+    pending: HashSet<String>,
+    /// requested media files which have been received
+    received: Vec<MediaFileData>,
+    /// whether [`ClientEvent::MediaReady`] has been reported
+    finished: bool,
+}
+
+impl MediaExchange {
+    /// Reports the end of the exchange unless it has already been reported.
+    fn finish(&mut self) -> Option<ClientEvent> {
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        self.pending.clear();
+        Some(ClientEvent::MediaReady(mem::take(&mut self.received)))
+    }
+
+    /// Reports the end of the exchange once all requested files have been received.
+    fn check(&mut self) -> Option<ClientEvent> {
+        if self.announced && self.requested && self.pending.is_empty() {
+            self.finish()
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Debug for ClientEvents {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("ClientEvents")
+            .field("subscribers", &self.subscribers.len())
+            .field("media", &self.media)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientEvents {
+    /// Registers a callback which will be called for every future event.
+    pub fn subscribe(&mut self, callback: impl FnMut(&ClientEvent) + Send + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    /// Translates a received command and notifies all subscribers.
+    ///
+    /// Returns the event; `None` if the command doesn't translate into an event.
+    pub fn handle_command(&mut self, command: &ToClientCommand) -> Option<ClientEvent> {
+        let event = self.translate(command)?;
+        self.notify(&event);
+        Some(event)
+    }
+
+    /// Forgets about the previous session and notifies all subscribers about the reconnect.
+    pub fn handle_reconnect(&mut self, attempt: u32) -> ClientEvent {
+        self.media = MediaExchange::default();
+        self.queued = None;
+        let event = ClientEvent::Reconnected { attempt };
+        self.notify(&event);
+        event
+    }
+
+    fn notify(&mut self, event: &ClientEvent) {
+        for subscriber in &mut self.subscribers {
+            subscriber(event);
+        }
+    }
+
+    /// Keeps track of the media files requested from the server.
+    ///
+    /// Reporting to be ready without having requested any of the announced media files skips the
+    /// media exchange; the subscribers are notified about [`ClientEvent::MediaReady`] right away
+    /// while [`Self::take_queued`] returns it afterwards.
+    pub fn handle_sent_command(&mut self, command: &ToServerCommand) {
+        let event = match command {
+            ToServerCommand::RequestMedia(spec) if !self.media.finished => {
+                let RequestMediaSpec { files } = spec.as_ref();
+                self.media.requested = true;
+                self.media.pending.extend(files.iter().cloned());
+                self.media.check()
+            }
+            ToServerCommand::ClientReady(_) if self.media.announced => self.media.finish(),
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.notify(&event);
+            self.queued = Some(event);
+        }
+    }
+
+    /// Returns the event caused by the most recently sent command, if it hasn't been returned yet.
+    pub fn take_queued(&mut self) -> Option<ClientEvent> {
+        self.queued.take()
+    }
+
+    fn translate(&mut self, command: &ToClientCommand) -> Option<ClientEvent> {
+        match command {
+            ToClientCommand::TCChatMessage(spec) => {
+                let TCChatMessageSpec {
+                    version: _,
+                    message_type,
+                    sender,
+                    message,
+                    timestamp,
+                } = spec.as_ref();
+                Some(ClientEvent::ChatReceived {
+                    sender: sender.clone(),
+                    message: message.clone(),
+                    message_type: *message_type,
+                    timestamp: *timestamp,
+                })
+            }
+            ToClientCommand::Blockdata(spec) => {
+                let BlockdataSpec { pos, .. } = spec.as_ref();
+                MapBlockPos::new(*pos).map(ClientEvent::BlockLoaded)
+            }
+            ToClientCommand::Inventory(spec) => {
+                let InventorySpec { inventory } = spec.as_ref();
+                Some(ClientEvent::InventoryUpdated(inventory.clone()))
+            }
+            ToClientCommand::MovePlayer(spec) => {
+                let &MovePlayerSpec { pos, pitch, yaw } = spec.as_ref();
+                Some(ClientEvent::PlayerMoved {
+                    position: pos / PROTOCOL_SCALE,
+                    pitch,
+                    yaw,
+                })
+            }
+            ToClientCommand::TCModchannelMsg(spec) => {
+                let TCModchannelMsgSpec {
+                    channel_name,
+                    sender,
+                    channel_msg,
+                } = spec.as_ref();
+                Some(ClientEvent::ModChannelMessage {
+                    channel: channel_name.clone(),
+                    sender: sender.clone(),
+                    message: channel_msg.clone(),
+                })
+            }
+            ToClientCommand::ModchannelSignal(spec) => {
+                let ModchannelSignalSpec {
+                    signal, channel, ..
+                } = spec.as_ref();
+                Some(ClientEvent::ModChannelSignal {
+                    channel: channel.clone(),
+                    signal: *signal,
+                })
+            }
+            ToClientCommand::AnnounceMedia(spec) => {
+                let AnnounceMediaSpec { files, .. } = spec.as_ref();
+                self.media.announced = true;
+                // a server without any media doesn't expect a request
+                if files.is_empty() {
+                    self.media.finish()
+                } else {
+                    self.media.check()
+                }
+            }
+            ToClientCommand::Media(spec) => {
+                let MediaSpec { files, .. } = spec.as_ref();
+                for file in files {
+                    // files which haven't been requested don't take part in the exchange
+                    if self.media.pending.remove(&file.name) {
+                        self.media.received.push(file.clone());
+                    }
+                }
+                self.media.check()
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use glam::Vec3;
+
+    use super::{ClientEvent, ClientEvents};
+    use crate::commands::client_to_server::{ClientReadySpec, RequestMediaSpec, ToServerCommand};
+    use crate::commands::server_to_client::{
+        AnnounceMediaSpec, MediaSpec, MovePlayerSpec, ToClientCommand,
+    };
+    use crate::types::{MediaAnnouncement, MediaFileData};
+
+    fn announce(names: &[&str]) -> ToClientCommand {
+        ToClientCommand::AnnounceMedia(Box::new(AnnounceMediaSpec {
+            files: names
+                .iter()
+                .map(|name| MediaAnnouncement {
+                    name: (*name).to_owned(),
+                    sha1_base64: String::new(),
+                })
+                .collect(),
+            remote_servers: String::new(),
+        }))
+    }
+
+    fn request(names: &[&str]) -> ToServerCommand {
+        ToServerCommand::RequestMedia(Box::new(RequestMediaSpec {
+            files: names.iter().map(|name| (*name).to_owned()).collect(),
+        }))
+    }
+
+    fn ready() -> ToServerCommand {
+        ToServerCommand::ClientReady(Box::new(ClientReadySpec {
+            major_ver: 5,
+            minor_ver: 10,
+            patch_ver: 0,
+            reserved: 0,
+            full_ver: "5.10.0".into(),
+            formspec_ver: Some(7),
+        }))
+    }
+
+    fn media(names: &[&str]) -> ToClientCommand {
+        ToClientCommand::Media(Box::new(MediaSpec {
+            num_bunches: 1,
+            bunch_index: 0,
+            files: names
+                .iter()
+                .map(|name| MediaFileData {
+                    name: (*name).to_owned(),
+                    data: Vec::new(),
+                })
+                .collect(),
+        }))
+    }
+
+    #[test]
+    fn subscribers_receive_translated_events() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut events = ClientEvents::default();
+        let sink = Arc::clone(&received);
+        events.subscribe(move |event| sink.lock().expect("poisoned").push(event.clone()));
+
+        let event = events.handle_command(&ToClientCommand::MovePlayer(Box::new(MovePlayerSpec {
+            pos: Vec3::new(10.0, 20.0, -30.0),
+            pitch: 1.0,
+            yaw: 2.0,
+        })));
+        let expected = ClientEvent::PlayerMoved {
+            position: Vec3::new(1.0, 2.0, -3.0),
+            pitch: 1.0,
+            yaw: 2.0,
+        };
+        assert_eq!(event, Some(expected.clone()));
+        assert_eq!(*received.lock().expect("poisoned"), vec![expected]);
+    }
+
+    #[test]
+    fn media_is_ready_once_all_requested_files_arrived() {
+        let mut events = ClientEvents::default();
+        assert_eq!(events.handle_command(&announce(&["a.png", "b.ogg"])), None);
+        events.handle_sent_command(&request(&["a.png", "b.ogg"]));
+        assert_eq!(events.handle_command(&media(&["a.png"])), None);
+        let Some(ClientEvent::MediaReady(files)) = events.handle_command(&media(&["b.ogg"])) else {
+            panic!("media should be ready");
+        };
+        assert_eq!(files.len(), 2);
+
+        // the exchange is reported only once
+        assert_eq!(events.handle_command(&media(&["b.ogg"])), None);
+        events.handle_sent_command(&ready());
+        assert_eq!(events.take_queued(), None);
+    }
+
+    #[test]
+    fn media_is_ready_when_nothing_needs_to_be_requested() {
+        // the server has no media at all
+        let mut events = ClientEvents::default();
+        assert_eq!(
+            events.handle_command(&announce(&[])),
+            Some(ClientEvent::MediaReady(Vec::new()))
+        );
+
+        // all media files have been cached
+        let mut cached = ClientEvents::default();
+        assert_eq!(cached.handle_command(&announce(&["a.png"])), None);
+        cached.handle_sent_command(&ready());
+        assert_eq!(
+            cached.take_queued(),
+            Some(ClientEvent::MediaReady(Vec::new()))
+        );
+        assert_eq!(cached.take_queued(), None);
+    }
+
+    #[test]
+    fn unrequested_media_does_not_finish_the_exchange() {
+        let mut events = ClientEvents::default();
+        // nothing has been announced or requested yet
+        assert_eq!(events.handle_command(&media(&["a.png"])), None);
+
+        assert_eq!(events.handle_command(&announce(&["a.png", "b.ogg"])), None);
+        events.handle_sent_command(&request(&["b.ogg"]));
+        assert_eq!(events.handle_command(&media(&["a.png"])), None);
+        assert!(matches!(
+            events.handle_command(&media(&["b.ogg"])),
+            Some(ClientEvent::MediaReady(files)) if files.len() == 1
+        ));
+    }
+}
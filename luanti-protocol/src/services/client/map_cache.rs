@@ -0,0 +1,277 @@
+//! Contains the `MapCache`
+
+use std::collections::HashMap;
+
+use glam::{I16Vec3, Vec3};
+use log::{trace, warn};
+use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodePos};
+
+use crate::commands::client_to_server::{DeletedblocksSpec, GotBlocksSpec, ToServerCommand};
+use crate::commands::server_to_client::{
+    AddnodeSpec, BlockdataSpec, MovePlayerSpec, RemovenodeSpec, ToClientCommand,
+};
+
+/// positions are being transferred in tenths of a node
+const PROTOCOL_SCALE: f32 = 10.0;
+
+/// the list of a `DeletedblocksSpec` is prefixed by a `u8` length
+const MAX_BLOCKS_PER_COMMAND: usize = u8::MAX as usize;
+
+/// A client-side copy of the map blocks which have been received from the server.
+///
+/// Once more than `max_blocks` blocks are being held, the blocks farthest away from the player
+/// are evicted and the server is told about it, so it will send them again when needed.
+pub struct MapCache {
+    blocks: HashMap<MapBlockPos, MapBlockNodes>,
+    max_blocks: usize,
+    /// last known position of the player in nodes
+    player_position: Vec3,
+}
+
+impl MapCache {
+    #[must_use]
+    pub fn new(max_blocks: usize) -> Self {
+        Self {
+            blocks: HashMap::new(),
+            max_blocks,
+            player_position: Vec3::ZERO,
+        }
+    }
+
+    /// Returns the number of cached map blocks.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the nodes of a map block; `None` if the block hasn't been received.
+    #[must_use]
+    pub fn block(&self, pos: MapBlockPos) -> Option<&MapBlockNodes> {
+        self.blocks.get(&pos)
+    }
+
+    /// Returns a single node; `None` if its map block hasn't been received.
+    #[must_use]
+    pub fn get_node(&self, pos: MapNodePos) -> Option<MapNode> {
+        let (block_pos, index) = pos.split_index();
+        self.blocks.get(&block_pos).map(|nodes| nodes[index])
+    }
+
+    /// Forgets all map blocks, e.g. after the server has lost track of them.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Updates the position eviction distances are measured from. The position is in nodes.
+    pub fn set_player_position(&mut self, position: Vec3) {
+        self.player_position = position;
+    }
+
+    /// Applies a command received from the server to the cached map.
+    ///
+    /// Returns the commands which need to be sent back to the server.
+    pub fn handle_command(&mut self, command: &ToClientCommand) -> Vec<ToServerCommand> {
+        match command {
+            ToClientCommand::Blockdata(spec) => {
+                let BlockdataSpec { pos, block, .. } = spec.as_ref();
+                let Some(block_pos) = MapBlockPos::new(*pos) else {
+                    warn!("ignoring map block at invalid position {pos}");
+                    return Vec::new();
+                };
+                self.blocks
+                    .insert(block_pos, MapBlockNodes(block.nodes.nodes));
+
+                let mut replies = vec![ToServerCommand::GotBlocks(Box::new(GotBlocksSpec {
+                    blocks: vec![*pos],
+                }))];
+                let evicted = self.evict();
+                if !evicted.is_empty() {
+                    trace!("evicted {count} map blocks", count = evicted.len());
+                    replies.extend(evicted.chunks(MAX_BLOCKS_PER_COMMAND).map(|blocks| {
+                        ToServerCommand::Deletedblocks(Box::new(DeletedblocksSpec {
+                            blocks: blocks.to_vec(),
+                        }))
+                    }));
+                }
+                replies
+            }
+            ToClientCommand::Addnode(spec) => {
+                let AddnodeSpec { pos, node, .. } = spec.as_ref();
+                self.set_node(*pos, *node);
+                Vec::new()
+            }
+            ToClientCommand::Removenode(spec) => {
+                let RemovenodeSpec { pos } = spec.as_ref();
+                self.set_node(
+                    *pos,
+                    MapNode {
+                        content_id: ContentId::AIR,
+                        param1: 0,
+                        param2: 0,
+                    },
+                );
+                Vec::new()
+            }
+            ToClientCommand::MovePlayer(spec) => {
+                let MovePlayerSpec { pos, .. } = spec.as_ref();
+                self.set_player_position(*pos / PROTOCOL_SCALE);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Keeps track of the player's position reported to the server.
+    pub fn handle_sent_command(&mut self, command: &ToServerCommand) {
+        if let ToServerCommand::Playerpos(spec) = command {
+            self.set_player_position(spec.player_pos.position / PROTOCOL_SCALE);
+        }
+    }
+
+    /// Replaces a node if its map block has been received.
+    fn set_node(&mut self, pos: I16Vec3, node: MapNode) {
+        let (block_pos, index) = MapNodePos(pos).split_index();
+        if let Some(nodes) = self.blocks.get_mut(&block_pos) {
+            nodes[index] = node;
+        }
+    }
+
+    /// Removes the blocks farthest away from the player until the limit is met again.
+    ///
+    /// A tenth of the capacity is freed up in addition, so the expensive sorting doesn't happen
+    /// for every block being received.
+    fn evict(&mut self) -> Vec<I16Vec3> {
+        if self.blocks.len() <= self.max_blocks {
+            return Vec::new();
+        }
+        let keep = self.max_blocks - self.max_blocks / 10;
+        let mut by_distance: Vec<(f32, MapBlockPos)> = self
+            .blocks
+            .keys()
+            .map(|&pos| {
+                let center = MapNodePos::from(pos).0.as_vec3()
+                    + Vec3::splat(f32::from(MapBlockPos::SIZE) / 2.0);
+                (center.distance_squared(self.player_position), pos)
+            })
+            .collect();
+        by_distance
+            .sort_unstable_by(|(distance_a, _), (distance_b, _)| distance_a.total_cmp(distance_b));
+        by_distance
+            .into_iter()
+            .skip(keep)
+            .map(|(_, pos)| {
+                self.blocks.remove(&pos);
+                pos.vec()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{I16Vec3, Vec3};
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodePos};
+
+    use super::MapCache;
+    use crate::commands::client_to_server::ToServerCommand;
+    use crate::commands::server_to_client::{
+        AddnodeSpec, BlockdataSpec, RemovenodeSpec, ToClientCommand,
+    };
+    use crate::types::{MapNodesBulk, NodeMetadataList, TransferrableMapBlock};
+
+    fn blockdata(x: i16) -> ToClientCommand {
+        ToClientCommand::Blockdata(Box::new(BlockdataSpec {
+            pos: I16Vec3::new(x, 0, 0),
+            block: TransferrableMapBlock {
+                is_underground: false,
+                day_night_differs: false,
+                generated: true,
+                lighting_complete: Some(0xffff),
+                nodes: MapNodesBulk {
+                    nodes: [MapNode {
+                        content_id: ContentId::AIR,
+                        param1: 0,
+                        param2: 0,
+                    }; 4096],
+                },
+                node_metadata: NodeMetadataList {
+                    metadata: Vec::new(),
+                },
+            },
+            network_specific_version: 2,
+        }))
+    }
+
+    #[test]
+    fn node_changes_are_applied_to_received_blocks() {
+        let mut cache = MapCache::new(10);
+        let replies = cache.handle_command(&blockdata(1));
+        assert!(matches!(
+            replies.as_slice(),
+            [ToServerCommand::GotBlocks(spec)] if spec.blocks == [I16Vec3::new(1, 0, 0)]
+        ));
+
+        let pos = I16Vec3::new(17, 2, 3);
+        let stone = MapNode {
+            content_id: ContentId(1),
+            param1: 0,
+            param2: 4,
+        };
+        cache.handle_command(&ToClientCommand::Addnode(Box::new(AddnodeSpec {
+            pos,
+            node: stone,
+            keep_metadata: false,
+        })));
+        assert_eq!(cache.get_node(MapNodePos(pos)), Some(stone));
+
+        cache.handle_command(&ToClientCommand::Removenode(Box::new(RemovenodeSpec {
+            pos,
+        })));
+        assert_eq!(
+            cache.get_node(MapNodePos(pos)).map(|node| node.content_id),
+            Some(ContentId::AIR)
+        );
+        assert_eq!(cache.get_node(MapNodePos(I16Vec3::new(-1, 0, 0))), None);
+    }
+
+    #[test]
+    fn farthest_blocks_are_evicted() {
+        let mut cache = MapCache::new(3);
+        cache.set_player_position(Vec3::new(40.0, 0.0, 0.0));
+        for x in 0..3 {
+            cache.handle_command(&blockdata(x));
+        }
+        let replies = cache.handle_command(&blockdata(-5));
+        assert!(matches!(
+            replies.as_slice(),
+            [_, ToServerCommand::Deletedblocks(spec)] if spec.blocks == [I16Vec3::new(-5, 0, 0)]
+        ));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn many_evicted_blocks_are_split_up() {
+        let mut cache = MapCache::new(10);
+        let nodes = MapBlockNodes([MapNode::default(); MapBlockPos::NODE_COUNT as usize]);
+        // more than fit into a single command arrived while the player was far away
+        for x in 1..300 {
+            let pos = MapBlockPos::new(I16Vec3::new(x, 0, 0)).unwrap_or(MapBlockPos::ZERO);
+            cache.blocks.insert(pos, nodes.clone());
+        }
+        let replies = cache.handle_command(&blockdata(0));
+        let deleted: Vec<_> = replies
+            .iter()
+            .filter_map(|reply| match reply {
+                ToServerCommand::Deletedblocks(spec) => Some(spec.blocks.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deleted, [255, 36]);
+        assert_eq!(cache.len(), 9);
+    }
+}
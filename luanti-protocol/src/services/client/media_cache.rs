@@ -0,0 +1,239 @@
+//! Contains the `MediaCache`
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use log::{debug, trace, warn};
+use sha1::{Digest, Sha1};
+
+use crate::commands::client_to_server::{RequestMediaSpec, ToServerCommand};
+use crate::commands::server_to_client::{AnnounceMediaSpec, MediaSpec, ToClientCommand};
+use crate::types::{MediaAnnouncement, MediaFileData};
+
+/// Downloads the media files announced by the server and keeps them in a local directory.
+///
+/// Files are stored by the hex-encoded SHA1 of their content like the engine does, so files
+/// being used by multiple servers only need to be downloaded once.
+#[derive(Debug)]
+pub struct MediaCache {
+    directory: PathBuf,
+    /// the announced SHA1 (Base64-encoded) of every file
+    announced: HashMap<String, String>,
+    /// names of requested files which haven't been received yet
+    pending: HashSet<String>,
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MediaCache {
+    /// Uses the given directory for caching media files. The directory is being created if it
+    /// doesn't exist.
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref().to_owned();
+        fs::create_dir_all(&directory).with_context(|| {
+            format!(
+                "failed to create media cache directory {directory}",
+                directory = directory.display()
+            )
+        })?;
+        Ok(Self {
+            directory,
+            announced: HashMap::new(),
+            pending: HashSet::new(),
+            files: HashMap::new(),
+        })
+    }
+
+    /// Returns `true` if all announced files are available.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty() && self.files.len() == self.announced.len()
+    }
+
+    /// Returns the content of a media file; `None` if it hasn't been received (yet).
+    #[must_use]
+    pub fn file(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns all available media files by name.
+    #[must_use]
+    pub fn files(&self) -> &HashMap<String, Vec<u8>> {
+        &self.files
+    }
+
+    /// Handles media announcements and transfers.
+    ///
+    /// Returns the commands which need to be sent back to the server.
+    pub fn handle_command(&mut self, command: &ToClientCommand) -> Result<Vec<ToServerCommand>> {
+        match command {
+            ToClientCommand::AnnounceMedia(spec) => Ok(self.handle_announcement(spec)),
+            ToClientCommand::Media(spec) => {
+                self.handle_media(spec)?;
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn handle_announcement(&mut self, spec: &AnnounceMediaSpec) -> Vec<ToServerCommand> {
+        let AnnounceMediaSpec {
+            files,
+            remote_servers: _,
+        } = spec;
+
+        // files requested in a previous session won't be sent anymore
+        self.pending.clear();
+        let mut missing = Vec::new();
+        for MediaAnnouncement { name, sha1_base64 } in files {
+            self.announced.insert(name.clone(), sha1_base64.clone());
+            match self.load_cached(sha1_base64) {
+                Ok(Some(data)) => {
+                    trace!("media file '{name}' has been cached");
+                    self.files.insert(name.clone(), data);
+                }
+                Ok(None) => missing.push(name.clone()),
+                Err(error) => {
+                    warn!("failed to load cached media file '{name}': {error:?}");
+                    missing.push(name.clone());
+                }
+            }
+        }
+
+        debug!(
+            "{cached} of {announced} announced media files have been cached",
+            cached = files.len() - missing.len(),
+            announced = files.len()
+        );
+        if missing.is_empty() {
+            return Vec::new();
+        }
+        self.pending.extend(missing.iter().cloned());
+        vec![ToServerCommand::RequestMedia(Box::new(RequestMediaSpec {
+            files: missing,
+        }))]
+    }
+
+    fn handle_media(&mut self, spec: &MediaSpec) -> Result<()> {
+        let MediaSpec {
+            num_bunches,
+            bunch_index,
+            files,
+        } = spec;
+        trace!("received media bunch {bunch_index} of {num_bunches}");
+
+        for MediaFileData { name, data } in files {
+            let Some(expected) = self.announced.get(name) else {
+                warn!("ignoring media file '{name}' which hasn't been announced");
+                continue;
+            };
+            let sha1_base64 = STANDARD.encode(Sha1::digest(data));
+            if &sha1_base64 != expected {
+                warn!("ignoring media file '{name}' with mismatching hash");
+                continue;
+            }
+            fs::write(self.cache_path(&sha1_base64)?, data)?;
+            self.pending.remove(name);
+            self.files.insert(name.clone(), data.clone());
+        }
+        Ok(())
+    }
+
+    /// Loads a file from the cache; `None` if it hasn't been cached.
+    fn load_cached(&self, sha1_base64: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.cache_path(sha1_base64)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path)?;
+        // don't trust files which may have been modified or truncated
+        Ok((STANDARD.encode(Sha1::digest(&data)) == sha1_base64).then_some(data))
+    }
+
+    fn cache_path(&self, sha1_base64: &str) -> Result<PathBuf> {
+        let hash = STANDARD.decode(sha1_base64)?;
+        let mut hex = String::with_capacity(2 * hash.len());
+        for byte in hash {
+            write!(hex, "{byte:02x}")?;
+        }
+        Ok(self.directory.join(hex))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use sha1::{Digest, Sha1};
+
+    use super::MediaCache;
+    use crate::commands::client_to_server::ToServerCommand;
+    use crate::commands::server_to_client::{AnnounceMediaSpec, MediaSpec, ToClientCommand};
+    use crate::types::{MediaAnnouncement, MediaFileData};
+
+    fn announce(files: &[(&str, &[u8])]) -> ToClientCommand {
+        ToClientCommand::AnnounceMedia(Box::new(AnnounceMediaSpec {
+            files: files
+                .iter()
+                .map(|&(name, data)| MediaAnnouncement {
+                    name: name.to_owned(),
+                    sha1_base64: STANDARD.encode(Sha1::digest(data)),
+                })
+                .collect(),
+            remote_servers: String::new(),
+        }))
+    }
+
+    fn media(files: &[(&str, &[u8])]) -> ToClientCommand {
+        ToClientCommand::Media(Box::new(MediaSpec {
+            num_bunches: 1,
+            bunch_index: 0,
+            files: files
+                .iter()
+                .map(|&(name, data)| MediaFileData {
+                    name: name.to_owned(),
+                    data: data.to_vec(),
+                })
+                .collect(),
+        }))
+    }
+
+    #[test]
+    fn missing_files_are_requested_verified_and_cached() {
+        let directory = env::temp_dir().join(format!("luanti-media-cache-{}", std::process::id()));
+        let files: [(&str, &[u8]); 2] = [("a.png", b"image"), ("b.ogg", b"sound")];
+
+        let mut cache = MediaCache::new(&directory).unwrap();
+        let replies = cache.handle_command(&announce(&files)).unwrap();
+        assert!(matches!(
+            replies.as_slice(),
+            [ToServerCommand::RequestMedia(spec)] if spec.files.len() == 2
+        ));
+        cache
+            .handle_command(&media(&[("a.png", b"corrupted"), ("b.ogg", b"sound")]))
+            .unwrap();
+        assert!(!cache.is_complete());
+        cache
+            .handle_command(&media(&[("a.png", b"image")]))
+            .unwrap();
+        assert!(cache.is_complete());
+        assert_eq!(cache.file("a.png"), Some(&b"image"[..]));
+
+        // a second client doesn't need to download anything
+        let mut second_cache = MediaCache::new(&directory).unwrap();
+        assert!(
+            second_cache
+                .handle_command(&announce(&files))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(second_cache.is_complete());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! Contains `ModChannels` keeping track of the mod channels joined by the client
+
+use std::collections::HashMap;
+
+use log::warn;
+
+use crate::commands::client_to_server::{ModchannelJoinSpec, ToServerCommand};
+use crate::commands::server_to_client::{ModchannelSignalSpec, ToClientCommand};
+use crate::types::{ModChannelSignal, ModChannelState};
+
+/// The mod channels the client has asked to join, along with the state the server reported.
+#[derive(Debug, Default)]
+pub(super) struct ModChannels {
+    channels: HashMap<String, ModChannelState>,
+}
+
+impl ModChannels {
+    /// Returns the state of a channel; `None` if it hasn't been joined.
+    pub(super) fn state(&self, channel: &str) -> Option<ModChannelState> {
+        self.channels.get(channel).copied()
+    }
+
+    /// Forgets all channels, as they don't survive the session.
+    pub(super) fn clear(&mut self) {
+        self.channels.clear();
+    }
+
+    /// Keeps track of join requests.
+    pub(super) fn handle_sent_command(&mut self, command: &ToServerCommand) {
+        if let ToServerCommand::ModchannelJoin(spec) = command {
+            let ModchannelJoinSpec { channel_name } = spec.as_ref();
+            self.channels
+                .insert(channel_name.clone(), ModChannelState::Init);
+        }
+    }
+
+    /// Applies the signals of the server.
+    pub(super) fn handle_command(&mut self, command: &ToClientCommand) {
+        let ToClientCommand::ModchannelSignal(spec) = command else {
+            return;
+        };
+        let ModchannelSignalSpec {
+            signal,
+            channel,
+            state,
+        } = spec.as_ref();
+        match signal {
+            ModChannelSignal::JoinOk => {
+                self.channels
+                    .insert(channel.clone(), ModChannelState::ReadWrite);
+            }
+            ModChannelSignal::JoinFailure | ModChannelSignal::LeaveOk => {
+                self.channels.remove(channel);
+            }
+            ModChannelSignal::LeaveFailure | ModChannelSignal::ChannelNotRegistered => {}
+            ModChannelSignal::SetState => match state {
+                // like the engine, only accept states a joined channel can be in
+                Some(state @ (ModChannelState::ReadWrite | ModChannelState::ReadOnly)) => {
+                    if let Some(current) = self.channels.get_mut(channel) {
+                        *current = *state;
+                    }
+                }
+                Some(ModChannelState::Init) | None => {
+                    warn!("invalid state {state:?} for mod channel '{channel}'");
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModChannels;
+    use crate::commands::client_to_server::{ModchannelJoinSpec, ToServerCommand};
+    use crate::commands::server_to_client::{ModchannelSignalSpec, ToClientCommand};
+    use crate::types::{ModChannelSignal, ModChannelState};
+
+    fn signal(spec: ModchannelSignalSpec) -> ToClientCommand {
+        ToClientCommand::ModchannelSignal(Box::new(spec))
+    }
+
+    #[test]
+    fn signals_update_the_state() {
+        let mut channels = ModChannels::default();
+        channels.handle_sent_command(&ToServerCommand::ModchannelJoin(Box::new(
+            ModchannelJoinSpec {
+                channel_name: "chat".into(),
+            },
+        )));
+        assert_eq!(channels.state("chat"), Some(ModChannelState::Init));
+
+        channels.handle_command(&signal(ModchannelSignalSpec::new(
+            ModChannelSignal::JoinOk,
+            "chat".into(),
+        )));
+        assert_eq!(channels.state("chat"), Some(ModChannelState::ReadWrite));
+
+        channels.handle_command(&signal(ModchannelSignalSpec::set_state(
+            "chat".into(),
+            ModChannelState::ReadOnly,
+        )));
+        assert_eq!(channels.state("chat"), Some(ModChannelState::ReadOnly));
+
+        channels.handle_command(&signal(ModchannelSignalSpec::new(
+            ModChannelSignal::LeaveOk,
+            "chat".into(),
+        )));
+        assert_eq!(channels.state("chat"), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! Contains the `ReconnectPolicy` and the state being restored after reconnecting
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use crate::commands::client_to_server::{ModchannelJoinSpec, ModchannelLeaveSpec, ToServerCommand};
+
+/// Controls whether and how a `LuantiClient` re-establishes lost connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// number of connection attempts before giving up
+    pub max_attempts: u32,
+    /// time to wait before each attempt
+    pub delay: Duration,
+    /// the connection is considered lost if nothing has been received for this long
+    pub timeout: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            delay: Duration::from_secs(5),
+            // same as the engine's connection timeout
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The error [`LuantiClient::recv`] fails with once after a lost connection has been
+/// re-established.
+///
+/// The client can still be used but the application needs to redo the handshake. Use
+/// `downcast_ref` to tell it apart from a disconnect.
+///
+/// [`LuantiClient::recv`]: super::LuantiClient::recv
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("reconnected after {attempt} attempt(s)")]
+pub struct Reconnected {
+    /// number of connection attempts it took to reconnect; starts at `1`
+    pub attempt: u32,
+}
+
+/// Keeps track of the mod channels the client has joined, so they can be joined again after a
+/// reconnect.
+#[derive(Debug, Default)]
+pub(super) struct Subscriptions {
+    channels: BTreeSet<String>,
+    /// whether the channels need to be joined once the new session is ready
+    replay_pending: bool,
+}
+
+impl Subscriptions {
+    /// Marks all subscriptions to be replayed in the next session.
+    pub(super) fn reconnected(&mut self) {
+        self.replay_pending = !self.channels.is_empty();
+    }
+
+    /// Keeps track of joined channels and returns the commands to replay the subscriptions once
+    /// the client is ready.
+    pub(super) fn handle_sent_command(
+        &mut self,
+        command: &ToServerCommand,
+    ) -> Vec<ToServerCommand> {
+        match command {
+            ToServerCommand::ModchannelJoin(spec) => {
+                self.channels.insert(spec.channel_name.clone());
+            }
+            ToServerCommand::ModchannelLeave(spec) => {
+                let ModchannelLeaveSpec { channel_name } = spec.as_ref();
+                self.channels.remove(channel_name);
+            }
+            // mod channels can only be joined after the player has entered the game
+            ToServerCommand::ClientReady(_) if self.replay_pending => {
+                self.replay_pending = false;
+                return self
+                    .channels
+                    .iter()
+                    .map(|channel_name| {
+                        ToServerCommand::ModchannelJoin(Box::new(ModchannelJoinSpec {
+                            channel_name: channel_name.clone(),
+                        }))
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+}
@@ -3,25 +3,64 @@
 //!
 //!
 use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
 
 use crate::commands::Command;
 use crate::commands::client_to_server::ToServerCommand;
 use crate::commands::server_to_client::AccessDeniedCode;
 use crate::commands::server_to_client::AccessDeniedCommand;
+use crate::commands::server_to_client::DisconnectReason;
 use crate::commands::server_to_client::ToClientCommand;
 use crate::peer::Peer;
+use crate::peer::send_queue::DropPolicies;
+use crate::peer::send_queue::QueueStats;
+use crate::peer::send_queue::ReliableConfig;
+use crate::peer::stats::PeerStats;
+use crate::services::handshake::HandshakeState;
+use crate::services::handshake::HandshakeStateMachine;
+use crate::types::CommandId;
+use crate::wire::buf_pool::BufPoolStats;
+use crate::wire::compression::CompressionConfig;
 use anyhow::Result;
 use anyhow::bail;
+use log::debug;
 
 /// This is owned by the driver
 pub struct LuantiConnection {
     peer: Peer,
+    /// observes sent and received commands, so it needs to be shared with `send`
+    handshake: Mutex<HandshakeStateMachine>,
 }
 
 impl LuantiConnection {
     #[must_use]
     pub fn new(peer: Peer) -> Self {
-        Self { peer }
+        Self {
+            peer,
+            handshake: Mutex::new(HandshakeStateMachine::new(Instant::now())),
+        }
+    }
+
+    /// The progress of the handshake; see [`HandshakeStateMachine`]
+    #[must_use]
+    pub fn handshake_state(&self) -> HandshakeState {
+        self.lock_handshake().state()
+    }
+
+    /// Changes the time after which a handshake without any progress fails; `None` disables the
+    /// timeout.
+    pub fn set_handshake_timeout(&self, timeout: Option<Duration>) {
+        self.lock_handshake().set_timeout(timeout);
+    }
+
+    fn lock_handshake(&self) -> MutexGuard<'_, HandshakeStateMachine> {
+        self.handshake
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 
     #[must_use]
@@ -29,9 +68,62 @@ impl LuantiConnection {
         self.peer.remote_addr()
     }
 
+    /// The underlying peer, e.g. for sending raw packets on a specific channel
+    #[must_use]
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// Returns the current state of the outgoing queues.
+    #[must_use]
+    pub fn queue_stats(&self) -> QueueStats {
+        self.peer.queue_stats()
+    }
+
+    /// Returns the state of the outgoing queues and the counters of the incoming reliable
+    /// streams.
+    #[must_use]
+    pub fn stats(&self) -> PeerStats {
+        self.peer.stats()
+    }
+
+    /// Returns the counters of the buffer pool, e.g. for tuning its size.
+    #[must_use]
+    pub fn buf_pool_stats(&self) -> BufPoolStats {
+        self.peer.buf_pool_stats()
+    }
+
+    /// Replace the policies applied when the client falls behind.
+    pub fn set_drop_policies(&self, drop_policies: DropPolicies) {
+        self.peer.set_drop_policies(drop_policies);
+    }
+
+    /// Changes the send window and resend timeout of reliable packets.
+    pub fn set_reliable_config(&self, reliable_config: ReliableConfig) {
+        self.peer.set_reliable_config(reliable_config);
+    }
+
+    /// Changes how compressed payloads of commands sent from now on are being compressed; see
+    /// [`Peer::set_compression`].
+    pub fn set_compression(&self, compression: CompressionConfig) {
+        self.peer.set_compression(compression);
+    }
+
     /// Send a command to the client
     pub fn send(&self, command: impl Into<ToClientCommand>) -> Result<()> {
-        self.peer.send(Command::ToClient(command.into()))
+        let command = command.into();
+        if let Err(error) = self
+            .lock_handshake()
+            .handle_command(&command, Instant::now())
+        {
+            debug!("handshake failed: {error}");
+        }
+        self.peer.send(Command::ToClient(command))
+    }
+
+    /// Send a command this crate doesn't know about; see [`Peer::send_raw_command`].
+    pub fn send_raw_command(&self, id: CommandId, payload: Vec<u8>) -> Result<()> {
+        self.peer.send_raw_command(id, payload)
     }
 
     pub fn send_access_denied(
@@ -47,15 +139,58 @@ impl LuantiConnection {
         })
     }
 
+    /// Tells the client why it is being disconnected.
+    ///
+    /// The peer keeps running; it's up to the caller to drop the connection once the command had
+    /// a chance to be delivered.
+    pub fn disconnect(&self, reason: &DisconnectReason, reconnect: bool) -> Result<()> {
+        self.send(reason.to_command(reconnect))
+    }
+
     /// Await a command from the peer
-    /// Returns (channel, reliable flag, Command)
     /// Returns None when the peer is disconnected
+    ///
+    /// Fails with a [`HandshakeError`](crate::services::handshake::HandshakeError) if the
+    /// handshake has failed or got stuck.
     pub async fn recv(&mut self) -> Result<ToServerCommand> {
-        match self.peer.recv().await? {
-            Command::ToServer(command) => Ok(command),
+        loop {
+            match self.recv_command().await? {
+                Command::ToServer(command) => return Ok(command),
+                Command::Unknown(command) => debug!("ignoring unknown command {}", command.id),
+                Command::ToClient(_) => bail!("Received wrong direction command from SocketPeer"),
+            }
+        }
+    }
+
+    /// Like [`Self::recv`] but also returns the commands this crate doesn't know about as
+    /// [`Command::Unknown`], e.g. for passing them on; see [`Peer::send_raw_command`].
+    pub async fn recv_command(&mut self) -> Result<Command> {
+        let handshake = self
+            .handshake
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        handshake.check(Instant::now())?;
+        let received = if let Some(deadline) = handshake.deadline() {
+            let Ok(received) = tokio::time::timeout_at(deadline.into(), self.peer.recv()).await
+            else {
+                handshake.check(Instant::now())?;
+                bail!("handshake timed out");
+            };
+            received?
+        } else {
+            self.peer.recv().await?
+        };
+        match received {
+            Command::ToServer(command) => {
+                if let Err(error) = handshake.handle_command(&command, Instant::now()) {
+                    debug!("handshake failed: {error}");
+                }
+                Ok(Command::ToServer(command))
+            }
             Command::ToClient(_) => {
                 bail!("Received wrong direction command from SocketPeer")
             }
+            command @ Command::Unknown(_) => Ok(command),
         }
     }
 }
@@ -0,0 +1,519 @@
+//! Tracks the handshake of a connection
+//!
+//! Before a client may play, it introduces itself (`Init`), the server picks a protocol version
+//! (`Hello`), the client authenticates (`FirstSrp` or `SrpBytesA`/`SrpBytesSB`/`SrpBytesM`
+//! followed by `AuthAccept`) and finally loads the definitions and media until it reports to be
+//! ready (`ClientReady`).
+//!
+//! The [`HandshakeStateMachine`] observes the commands of both directions, so the same
+//! implementation serves the client and the server side of a connection. Commands which aren't
+//! part of the handshake are ignored, just like repetitions of `Init`; the other ones arriving out
+//! of order fail the handshake.
+
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::commands::CommandProperties;
+use crate::commands::CommandRef;
+use crate::commands::client_to_server::ClientReadySpec;
+use crate::commands::client_to_server::InitSpec;
+use crate::commands::client_to_server::ToServerCommand;
+use crate::commands::server_to_client::AccessDeniedCode;
+use crate::commands::server_to_client::AccessDeniedCommand;
+use crate::commands::server_to_client::ToClientCommand;
+use crate::versions::ClientVersion;
+
+/// A handshake is considered stuck if no command has been exchanged for this long.
+///
+/// This is rather generous as new players might need to confirm their registration.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The steps of the handshake, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HandshakeState {
+    /// waiting for the client to introduce itself
+    Init,
+    /// waiting for the server to pick a protocol version
+    Hello,
+    /// the client is authenticating
+    Auth,
+    /// the client is loading definitions and media
+    Loading,
+    /// the handshake has been completed
+    Ready,
+}
+
+/// The reasons a handshake may fail
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("no protocol version within {client:?} is supported by the server")]
+    UnsupportedVersion {
+        /// the protocol versions announced by the client
+        client: RangeInclusive<u16>,
+        /// the version picked by the server, if any
+        server: Option<u16>,
+    },
+    #[error("authentication failed ({code:?}): {reason}")]
+    AuthFailed {
+        code: AccessDeniedCode,
+        reason: String,
+    },
+    #[error("kicked by the server: {reason}")]
+    Kicked {
+        reason: String,
+        /// whether the server asked the client to reconnect
+        reconnect: bool,
+    },
+    #[error("unexpected {command} during {state:?}")]
+    UnexpectedCommand {
+        state: HandshakeState,
+        command: &'static str,
+    },
+    #[error("no progress during {state:?} for {timeout:?}")]
+    TimedOut {
+        state: HandshakeState,
+        timeout: Duration,
+    },
+}
+
+/// Enforces the order of the handshake and detects stuck handshakes.
+///
+/// Feed every command being sent or received into [`Self::handle_command`] and check
+/// [`Self::check`] regularly; [`Self::deadline`] tells when the handshake will time out.
+#[derive(Debug, Clone)]
+pub struct HandshakeStateMachine {
+    state: HandshakeState,
+    /// the protocol versions announced by the client
+    client_versions: Option<RangeInclusive<u16>>,
+    /// the protocol version picked by the server
+    protocol_version: Option<u16>,
+    /// the name the client logs in with
+    user_name: Option<String>,
+    /// the language of the client's user interface
+    language: Option<String>,
+    /// the engine version of the client
+    client_version: Option<ClientVersion>,
+    /// `None` disables the timeout
+    timeout: Option<Duration>,
+    /// when the last command has been exchanged
+    last_activity: Instant,
+    failure: Option<HandshakeError>,
+}
+
+impl HandshakeStateMachine {
+    /// Starts tracking a new connection with the [`DEFAULT_HANDSHAKE_TIMEOUT`].
+    #[must_use]
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: HandshakeState::Init,
+            client_versions: None,
+            protocol_version: None,
+            user_name: None,
+            language: None,
+            client_version: None,
+            timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            last_activity: now,
+            failure: None,
+        }
+    }
+
+    /// Changes the time after which a handshake without any progress fails; `None` disables the
+    /// timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Starts over, e.g. because a new connection has been established. The timeout is kept.
+    pub fn reset(&mut self, now: Instant) {
+        *self = Self {
+            timeout: self.timeout,
+            ..Self::new(now)
+        };
+    }
+
+    #[must_use]
+    pub fn state(&self) -> HandshakeState {
+        self.state
+    }
+
+    /// Returns `true` once the handshake has been completed.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.state == HandshakeState::Ready
+    }
+
+    /// The protocol version picked by the server; `None` until `Hello` has been exchanged.
+    #[must_use]
+    pub fn protocol_version(&self) -> Option<u16> {
+        self.protocol_version
+    }
+
+    /// The name the client logs in with; `None` until `Init` has been exchanged.
+    #[must_use]
+    pub fn user_name(&self) -> Option<&str> {
+        self.user_name.as_deref()
+    }
+
+    /// The language of the client's user interface; `None` until `Init2` has been exchanged or
+    /// if the client didn't tell.
+    #[must_use]
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// The engine version of the client; `None` until `ClientReady` has been exchanged.
+    #[must_use]
+    pub fn client_version(&self) -> Option<&ClientVersion> {
+        self.client_version.as_ref()
+    }
+
+    /// The point in time the handshake is going to time out; `None` if it can't time out (anymore).
+    #[must_use]
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.is_ready() || self.failure.is_some() {
+            return None;
+        }
+        self.timeout.map(|timeout| self.last_activity + timeout)
+    }
+
+    /// Returns the current state or the reason of the failure, including timeouts.
+    pub fn check(&mut self, now: Instant) -> Result<HandshakeState, HandshakeError> {
+        if let Some(failure) = &self.failure {
+            return Err(failure.clone());
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline(), self.timeout) {
+            if now >= deadline {
+                return Err(self.fail(HandshakeError::TimedOut {
+                    state: self.state,
+                    timeout,
+                }));
+            }
+        }
+        Ok(self.state)
+    }
+
+    /// Advances the handshake according to a command being sent or received.
+    ///
+    /// Once the handshake has failed, the failure is being returned for all following commands.
+    pub fn handle_command<Cmd: CommandRef>(
+        &mut self,
+        command: &Cmd,
+        now: Instant,
+    ) -> Result<HandshakeState, HandshakeError> {
+        if let Some(failure) = &self.failure {
+            return Err(failure.clone());
+        }
+        if self.is_ready() {
+            return Ok(self.state);
+        }
+        self.last_activity = now;
+
+        let next_state = if let Some(command) = command.toserver_ref() {
+            self.handle_toserver(command)
+        } else if let Some(command) = command.toclient_ref() {
+            self.handle_toclient(command)
+        } else {
+            Ok(self.state)
+        };
+        match next_state {
+            Ok(next_state) => {
+                if next_state != self.state {
+                    debug!("handshake: {:?} -> {next_state:?}", self.state);
+                    self.state = next_state;
+                }
+                Ok(next_state)
+            }
+            Err(error) => Err(self.fail(error)),
+        }
+    }
+
+    fn handle_toserver(
+        &mut self,
+        command: &ToServerCommand,
+    ) -> Result<HandshakeState, HandshakeError> {
+        use HandshakeState::{Auth, Hello, Init, Loading, Ready};
+        match (self.state, command) {
+            (Init, ToServerCommand::Init(spec)) => {
+                let InitSpec {
+                    min_net_proto_version,
+                    max_net_proto_version,
+                    user_name,
+                    ..
+                } = spec.as_ref();
+                self.client_versions = Some(*min_net_proto_version..=*max_net_proto_version);
+                self.user_name = Some(user_name.clone());
+                Ok(Hello)
+            }
+            // `Init` is sent unreliably and thus repeated until the server answers; like the
+            // engine, late repetitions are ignored in every state
+            (state, ToServerCommand::Init(_)) => {
+                debug!("ignoring repeated Init in state {state:?}");
+                Ok(state)
+            }
+            (
+                Auth,
+                ToServerCommand::FirstSrp(_)
+                | ToServerCommand::SrpBytesA(_)
+                | ToServerCommand::SrpBytesM(_),
+            ) => Ok(self.state),
+            (Loading, ToServerCommand::Init2(spec)) => {
+                self.language.clone_from(&spec.lang);
+                Ok(Loading)
+            }
+            (Loading, ToServerCommand::ClientReady(spec)) => {
+                self.client_version = Some(ClientVersion::from(ClientReadySpec::clone(spec)));
+                Ok(Ready)
+            }
+            (
+                state,
+                ToServerCommand::FirstSrp(_)
+                | ToServerCommand::SrpBytesA(_)
+                | ToServerCommand::SrpBytesM(_)
+                | ToServerCommand::Init2(_)
+                | ToServerCommand::ClientReady(_),
+            ) => Err(HandshakeError::UnexpectedCommand {
+                state,
+                command: command.command_name(),
+            }),
+            _ => Ok(self.state),
+        }
+    }
+
+    fn handle_toclient(
+        &mut self,
+        command: &ToClientCommand,
+    ) -> Result<HandshakeState, HandshakeError> {
+        use HandshakeState::{Auth, Hello, Loading};
+        match (self.state, command) {
+            (Hello, ToClientCommand::Hello(spec)) => {
+                let client = self.client_versions.clone().unwrap_or(0..=u16::MAX);
+                if !client.contains(&spec.protocol_version) {
+                    return Err(HandshakeError::UnsupportedVersion {
+                        client,
+                        server: Some(spec.protocol_version),
+                    });
+                }
+                self.protocol_version = Some(spec.protocol_version);
+                Ok(Auth)
+            }
+            (Auth, ToClientCommand::SrpBytesSB(_)) => Ok(Auth),
+            (Auth, ToClientCommand::AuthAccept(_)) => Ok(Loading),
+            (state, ToClientCommand::AccessDenied(command)) => Err(self.denied(state, command)),
+            (_, ToClientCommand::AccessDeniedLegacy(spec)) => Err(HandshakeError::Kicked {
+                reason: spec.reason.clone(),
+                reconnect: false,
+            }),
+            (
+                state,
+                ToClientCommand::Hello(_)
+                | ToClientCommand::SrpBytesSB(_)
+                | ToClientCommand::AuthAccept(_),
+            ) => Err(HandshakeError::UnexpectedCommand {
+                state,
+                command: command.command_name(),
+            }),
+            _ => Ok(self.state),
+        }
+    }
+
+    /// Translates the server's refusal into the matching failure.
+    fn denied(&self, state: HandshakeState, command: &AccessDeniedCommand) -> HandshakeError {
+        let AccessDeniedCommand {
+            code,
+            reason,
+            reconnect,
+        } = command;
+        match code {
+            AccessDeniedCode::WrongVersion if state >= HandshakeState::Hello => {
+                HandshakeError::UnsupportedVersion {
+                    client: self.client_versions.clone().unwrap_or(0..=u16::MAX),
+                    server: None,
+                }
+            }
+            AccessDeniedCode::WrongPassword
+            | AccessDeniedCode::EmptyPassword
+            | AccessDeniedCode::WrongName
+            | AccessDeniedCode::WrongCharsInName
+            | AccessDeniedCode::AlreadyConnected => HandshakeError::AuthFailed {
+                code: *code,
+                reason: reason.clone(),
+            },
+            _ => HandshakeError::Kicked {
+                reason: reason.clone(),
+                reconnect: *reconnect,
+            },
+        }
+    }
+
+    fn fail(&mut self, error: HandshakeError) -> HandshakeError {
+        debug!(
+            "handshake failed during {state:?}: {error}",
+            state = self.state
+        );
+        self.failure = Some(error.clone());
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{HandshakeError, HandshakeState, HandshakeStateMachine};
+    use crate::commands::client_to_server::{
+        ClientReadySpec, Init2Spec, InitSpec, SrpBytesASpec, ToServerCommand,
+    };
+    use crate::commands::server_to_client::{
+        AccessDeniedCode, AccessDeniedCommand, AuthAcceptSpec, HelloSpec, ToClientCommand,
+    };
+    use crate::types::AuthMechsBitset;
+
+    fn init() -> ToServerCommand {
+        InitSpec {
+            serialization_ver_max: 29,
+            supp_compr_modes: 0,
+            min_net_proto_version: 37,
+            max_net_proto_version: 46,
+            user_name: "player".into(),
+        }
+        .into()
+    }
+
+    fn hello(protocol_version: u16) -> ToClientCommand {
+        HelloSpec {
+            serialization_version: 29,
+            compression_mode: 0,
+            protocol_version,
+            auth_mechs: AuthMechsBitset::default(),
+            username_legacy: String::new(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn handshake_is_completed() {
+        let now = Instant::now();
+        let mut handshake = HandshakeStateMachine::new(now);
+        handshake.handle_command(&init(), now).unwrap();
+        handshake.handle_command(&init(), now).unwrap();
+        assert_eq!(
+            handshake.handle_command(&hello(46), now),
+            Ok(HandshakeState::Auth)
+        );
+        assert_eq!(handshake.protocol_version(), Some(46));
+        let srp: ToServerCommand = SrpBytesASpec {
+            bytes_a: Vec::new(),
+            based_on: 1,
+        }
+        .into();
+        handshake.handle_command(&srp, now).unwrap();
+        let accept: ToClientCommand = AuthAcceptSpec {
+            player_pos: glam::Vec3::ZERO,
+            map_seed: 0,
+            recommended_send_interval: 0.1,
+            sudo_auth_methods: 0,
+        }
+        .into();
+        assert_eq!(
+            handshake.handle_command(&accept, now),
+            Ok(HandshakeState::Loading)
+        );
+        assert_eq!(handshake.user_name(), Some("player"));
+        let init2: ToServerCommand = Init2Spec {
+            lang: Some("de".into()),
+        }
+        .into();
+        handshake.handle_command(&init2, now).unwrap();
+        assert_eq!(handshake.language(), Some("de"));
+        // a late repetition of `Init` doesn't fail the handshake
+        assert_eq!(
+            handshake.handle_command(&init(), now),
+            Ok(HandshakeState::Loading)
+        );
+        assert_eq!(handshake.client_version(), None);
+        let ready: ToServerCommand = ClientReadySpec {
+            major_ver: 5,
+            minor_ver: 11,
+            patch_ver: 0,
+            reserved: 0,
+            full_ver: "5.11.0".into(),
+            formspec_ver: Some(8),
+        }
+        .into();
+        assert_eq!(
+            handshake.handle_command(&ready, now),
+            Ok(HandshakeState::Ready)
+        );
+        let client_version = handshake.client_version().unwrap();
+        assert!(client_version.is_at_least(5, 11, 0));
+        assert_eq!(client_version.formspec_version, Some(8));
+        assert_eq!(handshake.deadline(), None);
+        // commands after the handshake don't matter
+        handshake.handle_command(&hello(46), now).unwrap();
+    }
+
+    #[test]
+    fn failures_are_typed() {
+        let now = Instant::now();
+        let mut version_mismatch = HandshakeStateMachine::new(now);
+        version_mismatch.handle_command(&init(), now).unwrap();
+        assert_eq!(
+            version_mismatch.handle_command(&hello(47), now),
+            Err(HandshakeError::UnsupportedVersion {
+                client: 37..=46,
+                server: Some(47),
+            })
+        );
+
+        let mut out_of_order = HandshakeStateMachine::new(now);
+        let unexpected = out_of_order.handle_command(&hello(46), now).unwrap_err();
+        assert_eq!(
+            unexpected,
+            HandshakeError::UnexpectedCommand {
+                state: HandshakeState::Init,
+                command: "Hello",
+            }
+        );
+        // the failure sticks
+        assert_eq!(out_of_order.check(now), Err(unexpected));
+
+        let mut rejected = HandshakeStateMachine::new(now);
+        rejected.handle_command(&init(), now).unwrap();
+        rejected.handle_command(&hello(46), now).unwrap();
+        let denied: ToClientCommand = AccessDeniedCommand {
+            code: AccessDeniedCode::WrongPassword,
+            reason: String::new(),
+            reconnect: false,
+        }
+        .into();
+        assert!(matches!(
+            rejected.handle_command(&denied, now),
+            Err(HandshakeError::AuthFailed {
+                code: AccessDeniedCode::WrongPassword,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn stuck_handshakes_time_out() {
+        let now = Instant::now();
+        let mut handshake = HandshakeStateMachine::new(now);
+        handshake.set_timeout(Some(Duration::from_secs(10)));
+        handshake.handle_command(&init(), now).unwrap();
+
+        let later = now + Duration::from_secs(5);
+        assert_eq!(handshake.check(later), Ok(HandshakeState::Hello));
+        assert_eq!(handshake.deadline(), Some(now + Duration::from_secs(10)));
+        assert_eq!(
+            handshake.check(now + Duration::from_secs(10)),
+            Err(HandshakeError::TimedOut {
+                state: HandshakeState::Hello,
+                timeout: Duration::from_secs(10),
+            })
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Recording and replaying command streams
+//!
+//! A [`Recorder`] writes every command of a session to a file, along with the
+//! time it has been sent or received. A [`Recording`] loads such a file and
+//! replays the commands with their original timing, e.g. against a server for
+//! load testing or against a client for reproducing a user-reported issue.
+//!
+//! A recording starts with a header consisting of the magic `LREC`, the format
+//! version (u8), the protocol version (u16) and the serialization format (u8).
+//! It's followed by one entry per command: the elapsed time in microseconds
+//! (u64), the direction (u8, `0` = to server, `1` = to client), the length of
+//! the serialized command (u32) and the command itself. All numbers are big
+//! endian like on the wire.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use log::debug;
+use tokio::time::Instant;
+
+use crate::CommandDirection;
+use crate::commands::Command;
+use crate::commands::CommandProperties;
+use crate::commands::CommandRef;
+use crate::commands::serialize_commandref;
+use crate::peer::Peer;
+use crate::types::ProtocolContext;
+use crate::wire::compression::CompressionConfig;
+use crate::wire::deser::Deserialize;
+use crate::wire::deser::Deserializer;
+use crate::wire::ser::VecSerializer;
+
+const MAGIC: &[u8; 4] = b"LREC";
+const FORMAT_VERSION: u8 = 1;
+
+/// Writes the commands of a session to a recording.
+pub struct Recorder<W: Write = BufWriter<File>> {
+    writer: W,
+    /// context used for serializing; only the direction differs between commands
+    context: ProtocolContext,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Creates a new recording file, replacing an existing one.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("failed to create recording {path}", path = path.display()))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Starts a recording by writing its header. The time of the recorded commands is relative to
+    /// the creation of the recorder.
+    pub fn new(mut writer: W) -> Result<Self> {
+        let context = ProtocolContext::latest_for_send(true);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        writer.write_all(&context.protocol_version.to_be_bytes())?;
+        writer.write_all(&[context.ser_fmt])?;
+        Ok(Self {
+            writer,
+            context,
+            start: Instant::now(),
+        })
+    }
+
+    /// Records a command being sent or received right now.
+    pub fn record<Cmd: CommandRef>(&mut self, command: &Cmd) -> Result<()> {
+        self.record_at(self.start.elapsed(), command)
+    }
+
+    /// Records a command at the given time since the start of the recording.
+    pub fn record_at<Cmd: CommandRef>(&mut self, elapsed: Duration, command: &Cmd) -> Result<()> {
+        let direction = command.direction();
+        let context = ProtocolContext {
+            dir: direction,
+            ..self.context
+        };
+        let mut serializer = VecSerializer::new(context, 64);
+        serialize_commandref(command, &mut serializer)?;
+        let data = serializer.take();
+
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let length = u32::try_from(data.len()).context("command is too large to be recorded")?;
+        self.writer.write_all(&micros.to_be_bytes())?;
+        self.writer.write_all(&[encode_direction(direction)])?;
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Flushes the recording and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A command of a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCommand {
+    /// time since the start of the recording
+    pub elapsed: Duration,
+    pub command: Command,
+}
+
+/// A recorded command stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    commands: Vec<RecordedCommand>,
+}
+
+impl Recording {
+    /// Loads a recording from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("failed to open recording {path}", path = path.display()))?;
+        Self::read_from(file)
+            .with_context(|| format!("failed to read recording {path}", path = path.display()))
+    }
+
+    /// Reads a complete recording.
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut remaining = data.as_slice();
+
+        let magic: [u8; 4] = take_array(&mut remaining)?;
+        if &magic != MAGIC {
+            bail!("not a recording");
+        }
+        let [format_version] = take_array(&mut remaining)?;
+        if format_version != FORMAT_VERSION {
+            bail!("unsupported recording format version {format_version}");
+        }
+        let protocol_version = u16::from_be_bytes(take_array(&mut remaining)?);
+        let [ser_fmt] = take_array(&mut remaining)?;
+
+        let mut commands = Vec::new();
+        while !remaining.is_empty() {
+            let micros = u64::from_be_bytes(take_array(&mut remaining)?);
+            let [direction] = take_array(&mut remaining)?;
+            let length = u32::from_be_bytes(take_array(&mut remaining)?);
+            let Some((payload, rest)) = remaining.split_at_checked(usize::try_from(length)?) else {
+                bail!("truncated recording");
+            };
+            remaining = rest;
+
+            let context = ProtocolContext {
+                dir: decode_direction(direction)?,
+                protocol_version,
+                ser_fmt,
+                audit: false,
+                compression: CompressionConfig::default(),
+                strict_inventory: false,
+            };
+            let mut deserializer = Deserializer::new(context, payload);
+            let Some(command) = Command::deserialize(&mut deserializer)? else {
+                debug!("skipping unknown command in recording");
+                continue;
+            };
+            commands.push(RecordedCommand {
+                elapsed: Duration::from_micros(micros),
+                command,
+            });
+        }
+        Ok(Self { commands })
+    }
+
+    /// All recorded commands in the order they have been recorded
+    #[must_use]
+    pub fn commands(&self) -> &[RecordedCommand] {
+        &self.commands
+    }
+
+    /// Starts replaying the commands of the given direction.
+    #[must_use]
+    pub fn replay(&self, direction: CommandDirection) -> Replay<'_> {
+        Replay {
+            commands: &self.commands,
+            position: 0,
+            direction,
+            start: Instant::now(),
+            speed: 1.0,
+        }
+    }
+
+    /// Sends the recorded commands meant for the remote side of the peer with their original
+    /// timing, e.g. the commands sent to the server if the peer is connected to a server.
+    ///
+    /// Commands received in the meantime are queued by the peer. Returns the number of commands
+    /// being sent.
+    pub async fn replay_to(&self, peer: &Peer, speed: f64) -> Result<usize> {
+        let mut replay = self
+            .replay(CommandDirection::for_send(peer.is_server()))
+            .with_speed(speed)?;
+        let mut count = 0;
+        while let Some(command) = replay.next_command().await {
+            peer.send(command.clone())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Yields the commands of a recording at the time they have been recorded.
+///
+/// [`Replay::next_command`] is cancellation safe, so it can be awaited along with receiving
+/// commands in a `tokio::select!`.
+#[derive(Debug)]
+pub struct Replay<'rec> {
+    commands: &'rec [RecordedCommand],
+    /// index of the next command to be checked
+    position: usize,
+    direction: CommandDirection,
+    start: Instant,
+    speed: f64,
+}
+
+impl<'rec> Replay<'rec> {
+    /// Changes the replay speed, e.g. `2.0` replays twice as fast as recorded and `f64::INFINITY`
+    /// replays without any delays.
+    ///
+    /// Fails if the speed isn't positive, including `NaN`.
+    pub fn with_speed(mut self, speed: f64) -> Result<Self> {
+        if speed.is_nan() || speed <= 0.0 {
+            bail!("the replay speed must be positive: {speed}");
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    /// Waits for the time of the next command and returns it; `None` at the end of the recording.
+    pub async fn next_command(&mut self) -> Option<&'rec Command> {
+        loop {
+            let recorded = self.commands.get(self.position)?;
+            if recorded.command.direction() != self.direction {
+                self.position += 1;
+                continue;
+            }
+            tokio::time::sleep_until(self.start + recorded.elapsed.div_f64(self.speed)).await;
+            self.position += 1;
+            return Some(&recorded.command);
+        }
+    }
+}
+
+fn encode_direction(direction: CommandDirection) -> u8 {
+    match direction {
+        CommandDirection::ToServer => 0,
+        CommandDirection::ToClient => 1,
+    }
+}
+
+fn decode_direction(direction: u8) -> Result<CommandDirection> {
+    Ok(match direction {
+        0 => CommandDirection::ToServer,
+        1 => CommandDirection::ToClient,
+        other => bail!("invalid direction {other} in recording"),
+    })
+}
+
+/// Removes a fixed number of bytes from the front of the data.
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N]> {
+    let Some((array, rest)) = data.split_first_chunk() else {
+        bail!("truncated recording");
+    };
+    *data = rest;
+    Ok(*array)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Recorder;
+    use super::Recording;
+    use crate::CommandDirection;
+    use crate::commands::Command;
+    use crate::commands::client_to_server::{TSChatMessageSpec, ToServerCommand};
+    use crate::commands::server_to_client::{TimeOfDaySpec, ToClientCommand};
+    use crate::types::TrailingBytes;
+
+    fn time_of_day(time_of_day: u16) -> Command {
+        Command::ToClient(ToClientCommand::TimeOfDay(Box::new(TimeOfDaySpec {
+            time_of_day,
+            time_speed: Some(72.0),
+            extra: TrailingBytes::default(),
+        })))
+    }
+
+    fn chat(message: &str) -> Command {
+        Command::ToServer(ToServerCommand::TSChatMessage(Box::new(
+            TSChatMessageSpec {
+                message: message.to_owned(),
+            },
+        )))
+    }
+
+    #[test]
+    fn recorded_commands_are_read_back() {
+        let commands = [
+            (Duration::ZERO, time_of_day(6000)),
+            (Duration::from_millis(150), chat("hello")),
+            (Duration::from_secs(3), time_of_day(6050)),
+        ];
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        for (elapsed, command) in &commands {
+            recorder.record_at(*elapsed, command).unwrap();
+        }
+        let data = recorder.finish().unwrap();
+
+        let recording = Recording::read_from(data.as_slice()).unwrap();
+        let read_back: Vec<_> = recording
+            .commands()
+            .iter()
+            .map(|recorded| (recorded.elapsed, recorded.command.clone()))
+            .collect();
+        assert_eq!(read_back, commands);
+
+        Recording::read_from(&data[..data.len() - 1]).unwrap_err();
+        Recording::read_from(&b"LREC"[..]).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn replay_yields_the_commands_of_one_direction() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        recorder
+            .record_at(Duration::from_secs(1), &time_of_day(1))
+            .unwrap();
+        recorder
+            .record_at(Duration::from_secs(2), &chat("ignored"))
+            .unwrap();
+        recorder
+            .record_at(Duration::from_secs(4), &time_of_day(2))
+            .unwrap();
+        let data = recorder.finish().unwrap();
+        let recording = Recording::read_from(data.as_slice()).unwrap();
+
+        let mut replay = recording
+            .replay(CommandDirection::ToClient)
+            .with_speed(f64::INFINITY)
+            .unwrap();
+        assert_eq!(replay.next_command().await, Some(&time_of_day(1)));
+        assert_eq!(replay.next_command().await, Some(&time_of_day(2)));
+        assert_eq!(replay.next_command().await, None);
+    }
+
+    #[test]
+    fn replay_speeds_must_be_positive() {
+        let recording = Recording::read_from(
+            Recorder::new(Vec::new())
+                .unwrap()
+                .finish()
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+        for speed in [0.0, -1.0, f64::NAN] {
+            recording
+                .replay(CommandDirection::ToClient)
+                .with_speed(speed)
+                .unwrap_err();
+        }
+    }
+}
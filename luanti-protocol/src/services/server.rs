@@ -12,24 +12,40 @@ use std::time::Duration;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::watch;
 
 use super::conn::LuantiConnection;
 use super::socket::LuantiSocket;
 
 pub struct LuantiServer {
     accept_rx: UnboundedReceiver<LuantiConnection>,
+    audit: watch::Sender<bool>,
 }
 
 impl LuantiServer {
     #[must_use]
     pub fn new(server_address: SocketAddr) -> Self {
         let (accept_tx, accept_rx) = unbounded_channel();
+        let (audit_tx, audit_rx) = watch::channel(false);
         let runner = LuantiServerRunner {
             server_address,
             accept_tx,
+            audit: audit_rx,
         };
         tokio::spawn(runner.run());
-        Self { accept_rx }
+        Self {
+            accept_rx,
+            audit: audit_tx,
+        }
+    }
+
+    /// Switches auditing of received commands on or off for the connections accepted from now
+    /// on; see [`crate::wire::audit`].
+    ///
+    /// Unlike [`Peer::set_audit`](crate::peer::Peer::set_audit), this also covers the very first
+    /// commands of a connection.
+    pub fn set_audit(&self, audit: bool) {
+        self.audit.send_replace(audit);
     }
 
     pub async fn accept(&mut self) -> LuantiConnection {
@@ -40,6 +56,7 @@ impl LuantiServer {
 struct LuantiServerRunner {
     server_address: SocketAddr,
     accept_tx: UnboundedSender<LuantiConnection>,
+    audit: watch::Receiver<bool>,
 }
 
 impl LuantiServerRunner {
@@ -47,16 +64,17 @@ impl LuantiServerRunner {
         let Self {
             server_address,
             accept_tx,
+            audit,
         } = self;
 
         info!("LuantiServer listening on {server_address}");
         let mut socket = loop {
-            match LuantiSocket::new(server_address, true).await {
+            match LuantiSocket::with_audit(server_address, true, audit.clone()).await {
                 Ok(socket) => break socket,
                 Err(err) => {
                     warn!("LuantiServer: bind failed: {err}");
                     info!("Retrying in 5 seconds");
-                    tokio::time::sleep(Duration::from_millis(5000)).await;
+                    tokio::time::sleep(Duration::from_secs(5)).await;
                 }
             }
         };
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::collections::VecDeque;
 use std::io::Error;
 use std::net::SocketAddr;
 
@@ -11,13 +10,20 @@ use tokio::net::UdpSocket;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::watch;
 
 use crate::peer::PeerToSocket;
+use crate::wire::buf_pool::BufPool;
+use crate::wire::buf_pool::PooledBuf;
 
 use crate::peer::Peer;
 use crate::peer::PeerIO;
 use crate::peer::new_peer;
 
+mod send_scheduler;
+
+use send_scheduler::SendScheduler;
+
 const MAX_DATAGRAM_SIZE: usize = 0x0001_0000;
 
 ///
@@ -32,6 +38,7 @@ const MAX_DATAGRAM_SIZE: usize = 0x0001_0000;
 pub struct LuantiSocket {
     accept_rx: UnboundedReceiver<Peer>,
     knock_tx: UnboundedSender<SocketAddr>,
+    local_addr: SocketAddr,
     for_server: bool,
 }
 
@@ -40,13 +47,26 @@ impl LuantiSocket {
     /// The address may be V4 or V6.
     /// To select a random bind port, use 0.0.0.0:0 or [::]:0
     pub async fn new(bind_addr: SocketAddr, for_server: bool) -> Result<Self, Error> {
+        let (_, audit) = watch::channel(false);
+        Self::with_audit(bind_addr, for_server, audit).await
+    }
+
+    /// Like [`Self::new`], but the commands received by the peers being created while `audit` is
+    /// `true` are being audited; see [`crate::wire::audit`].
+    pub async fn with_audit(
+        bind_addr: SocketAddr,
+        for_server: bool,
+        audit: watch::Receiver<bool>,
+    ) -> Result<Self, Error> {
         let socket = UdpSocket::bind(bind_addr).await?;
+        let local_addr = socket.local_addr()?;
         let (peer_tx, peer_rx) = unbounded_channel();
         let (accept_tx, accept_rx) = unbounded_channel();
         let (knock_tx, knock_rx) = unbounded_channel();
         let luanti_socket = Self {
             accept_rx,
             knock_tx,
+            local_addr,
             for_server,
         };
         let luanti_socket_runner = LuantiSocketRunner {
@@ -54,15 +74,24 @@ impl LuantiSocket {
             peers: HashMap::new(),
             peer_tx,
             peer_rx,
-            outgoing: VecDeque::new(),
+            outgoing: SendScheduler::default(),
+            blocked: None,
+            buf_pool: BufPool::default(),
             accept_tx,
             knock_rx,
             for_server,
+            audit,
         };
         tokio::spawn(luanti_socket_runner.run());
         Ok(luanti_socket)
     }
 
+    /// The address the socket is bound to, including the port picked by the system
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
     /// Returns None when the server has shutdown.
     pub async fn accept(&mut self) -> Option<Peer> {
         self.accept_rx.recv().await
@@ -89,7 +118,7 @@ impl LuantiSocket {
                 debug!("peer responded from remote address: {remote_address}");
                 return peer;
             }
-            debug!("ignoring random connect from another address: {remote_address}",);
+            debug!("ignoring random connect from another address: {remote_address}");
         }
     }
 }
@@ -99,10 +128,16 @@ pub struct LuantiSocketRunner {
     peers: HashMap<SocketAddr, PeerIO>,
     peer_tx: UnboundedSender<PeerToSocket>,
     peer_rx: UnboundedReceiver<PeerToSocket>,
-    outgoing: VecDeque<(SocketAddr, Vec<u8>)>,
+    outgoing: SendScheduler,
+    /// a datagram which couldn't be sent as the socket wasn't ready; it is retried first
+    blocked: Option<(SocketAddr, PooledBuf)>,
+    /// shared by all peers of this socket
+    buf_pool: BufPool,
     accept_tx: UnboundedSender<Peer>,
     knock_rx: UnboundedReceiver<SocketAddr>,
     for_server: bool,
+    /// whether new peers audit the commands they receive
+    audit: watch::Receiver<bool>,
 }
 
 impl LuantiSocketRunner {
@@ -121,7 +156,7 @@ impl LuantiSocketRunner {
         let mut buf = vec![0_u8; MAX_DATAGRAM_SIZE];
 
         loop {
-            let interest = if self.outgoing.is_empty() {
+            let interest = if self.blocked.is_none() && self.outgoing.is_empty() {
                 Interest::READABLE
             } else {
                 Interest::READABLE | Interest::WRITABLE
@@ -141,6 +176,16 @@ impl LuantiSocketRunner {
                     }
                 }
             }
+            // clients drop their socket after adding the server, so they shut down along with
+            // their only peer; pending datagrams like the disconnect packet are sent first
+            if knock_closed
+                && self.peers.is_empty()
+                && self.blocked.is_none()
+                && self.outgoing.is_empty()
+            {
+                debug!("LuantiSocket has no more peers; shutting down");
+                return Ok(());
+            }
         }
     }
 
@@ -158,12 +203,14 @@ impl LuantiSocketRunner {
                 Err(error) => panic!("Unexpected socket error: {error:?}"),
             }
         }
-        if ready.is_writable() && !self.outgoing.is_empty() {
-            let (addr, data) = self.outgoing.pop_back().unwrap();
+        if !ready.is_writable() {
+            return;
+        }
+        if let Some((addr, data)) = self.blocked.take().or_else(|| self.outgoing.pop()) {
             match self.socket.try_send_to(&data, addr) {
                 Ok(_) => (),
                 Err(ref error) if error.kind() == std::io::ErrorKind::WouldBlock => {
-                    self.outgoing.push_back((addr, data));
+                    self.blocked = Some((addr, data));
                 }
                 Err(error) => panic!("Unexpected socket error: {error:?}"),
             }
@@ -175,8 +222,7 @@ impl LuantiSocketRunner {
             panic!("Unexpected Server shutdown?");
         };
         match msg {
-            PeerToSocket::SendImmediate(addr, data) => self.outgoing.push_back((addr, data)),
-            PeerToSocket::Send(addr, data) => self.outgoing.push_front((addr, data)),
+            PeerToSocket::Send(addr, priority, data) => self.outgoing.push(addr, priority, data),
             PeerToSocket::PeerIsDisconnected(addr) => self.remove_peer(addr),
         }
     }
@@ -189,7 +235,13 @@ impl LuantiSocketRunner {
     }
 
     fn insert_peer(&mut self, remote_addr: SocketAddr) {
-        let (peer, peer_io) = new_peer(remote_addr, !self.for_server, self.peer_tx.clone());
+        let (peer, peer_io) = new_peer(
+            remote_addr,
+            !self.for_server,
+            self.peer_tx.clone(),
+            self.buf_pool.clone(),
+            *self.audit.borrow(),
+        );
         self.peers.insert(remote_addr, peer_io);
         self.accept_tx.send(peer).unwrap();
     }
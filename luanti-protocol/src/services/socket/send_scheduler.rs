@@ -0,0 +1,105 @@
+//! Decides which of the queued datagrams is sent next
+//!
+//! Each [`SendPriority`] has its own queue per peer. The highest priority with pending datagrams
+//! is served first, and its peers take turns so a single peer downloading the map can't delay the
+//! datagrams of all others.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use crate::peer::SendPriority;
+use crate::wire::buf_pool::PooledBuf;
+
+#[derive(Debug, Default)]
+pub(super) struct SendScheduler {
+    /// indexed by the position within [`SendPriority::ALL`]
+    levels: [PriorityLevel; SendPriority::ALL.len()],
+}
+
+/// The datagrams of a single priority
+#[derive(Debug, Default)]
+struct PriorityLevel {
+    queues: HashMap<SocketAddr, VecDeque<PooledBuf>>,
+    /// the peers with pending datagrams in the order of their next turn
+    turns: VecDeque<SocketAddr>,
+}
+
+impl SendScheduler {
+    /// Queues a datagram behind all others of the same peer and priority.
+    pub(super) fn push(&mut self, addr: SocketAddr, priority: SendPriority, data: PooledBuf) {
+        let level = &mut self.levels[priority as usize];
+        let queue = level.queues.entry(addr).or_default();
+        if queue.is_empty() {
+            level.turns.push_back(addr);
+        }
+        queue.push_back(data);
+    }
+
+    /// Removes the datagram to be sent next.
+    pub(super) fn pop(&mut self) -> Option<(SocketAddr, PooledBuf)> {
+        self.levels.iter_mut().find_map(PriorityLevel::pop)
+    }
+
+    /// Returns `true` if there is nothing left to send.
+    pub(super) fn is_empty(&self) -> bool {
+        self.levels.iter().all(|level| level.turns.is_empty())
+    }
+}
+
+impl PriorityLevel {
+    fn pop(&mut self) -> Option<(SocketAddr, PooledBuf)> {
+        let addr = self.turns.pop_front()?;
+        let queue = self.queues.get_mut(&addr)?;
+        let data = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&addr);
+        } else {
+            self.turns.push_back(addr);
+        }
+        Some((addr, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::SendScheduler;
+    use crate::peer::SendPriority;
+    use crate::wire::buf_pool::PooledBuf;
+
+    fn datagram(tag: u8) -> PooledBuf {
+        PooledBuf::unpooled(vec![tag])
+    }
+
+    #[test]
+    fn priorities_come_first_and_peers_take_turns() {
+        let alice: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let bob: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        let mut scheduler = SendScheduler::default();
+        scheduler.push(alice, SendPriority::Response, datagram(1));
+        scheduler.push(alice, SendPriority::Response, datagram(2));
+        scheduler.push(alice, SendPriority::Response, datagram(3));
+        scheduler.push(bob, SendPriority::Response, datagram(4));
+        scheduler.push(bob, SendPriority::Default, datagram(5));
+        scheduler.push(alice, SendPriority::Control, datagram(6));
+
+        let mut sent = Vec::new();
+        while let Some((addr, data)) = scheduler.pop() {
+            sent.push((addr, data[0]));
+        }
+        assert_eq!(
+            sent,
+            [
+                (alice, 6),
+                (bob, 5),
+                (alice, 1),
+                (bob, 4),
+                (alice, 2),
+                (alice, 3)
+            ]
+        );
+        assert!(scheduler.is_empty());
+    }
+}
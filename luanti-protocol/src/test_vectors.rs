@@ -0,0 +1,884 @@
+//! Canonical serializations of representative commands
+//!
+//! Alternative implementations of the protocol may use these vectors to validate their
+//! serialization against this crate as a reference.
+
+use std::fmt::{self, Display, Write};
+use std::ops::RangeInclusive;
+
+use anyhow::{Result, bail};
+use glam::{I16Vec3, IVec2, UVec2, Vec2, Vec3};
+
+use crate::commands::client_to_server::{
+    ClientReadySpec, GotBlocksSpec, GotFarBlocksSpec, Init2Spec, InitSpec, InteractSpec,
+    InventoryActionSpec, InventoryFieldsSpec, PlayerPosCommand, TSChatMessageSpec, ToServerCommand,
+    UpdateClientInfoSpec,
+};
+use crate::commands::server_to_client::{
+    AuthAcceptSpec, BreathSpec, EPHEMERAL_SOUND_ID, FovSpec, HelloSpec, HpSpec, HudStat,
+    HudaddSpec, HudchangeCommand, HudrmSpec, MovePlayerSpec, PlaySoundSpec, PrivilegesSpec,
+    RemovenodeSpec, ShowFormspecSpec, SoundLocationType, TCChatMessageSpec, TimeOfDaySpec,
+    ToClientCommand,
+};
+use crate::commands::{Command, CommandProperties};
+use crate::types::{
+    AuthMechsBitset, CommandDirection, InteractAction, InventoryAction, InventoryLocation,
+    PlayerKeys, PlayerPos, PointedThing, ProtocolContext, TrailingBytes,
+};
+use crate::versions::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::wire::compression::CompressionConfig;
+use crate::wire::deser::{Deserialize, Deserializer};
+use crate::wire::packet::{LATEST_PROTOCOL_VERSION, SER_FMT_VER_HIGHEST_WRITE};
+use crate::wire::ser::{Serialize, VecSerializer};
+
+/// All protocol versions test vectors will be generated for
+pub const TEST_VECTOR_PROTOCOL_VERSIONS: RangeInclusive<u16> = SUPPORTED_PROTOCOL_VERSIONS;
+
+/// A command along with its canonical serialization for a specific protocol version
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    /// A unique name for this vector (within a protocol version and direction)
+    pub name: &'static str,
+    /// The protocol version used for serialization
+    pub protocol_version: u16,
+    /// The serialization format version used for serialization
+    pub ser_fmt: u8,
+    /// The command which has been serialized
+    pub command: Command,
+    /// The serialized command including the leading command id
+    pub bytes: Vec<u8>,
+}
+
+impl TestVector {
+    /// Returns the direction the command is being sent in.
+    #[must_use]
+    pub fn direction(&self) -> CommandDirection {
+        self.command.direction()
+    }
+
+    /// Returns the serialized bytes as lowercase hex string.
+    #[must_use]
+    pub fn hex(&self) -> String {
+        self.bytes.iter().fold(
+            String::with_capacity(self.bytes.len() * 2),
+            |mut hex, byte| {
+                // writing to a `String` cannot fail
+                let _ignore = write!(hex, "{byte:02x}");
+                hex
+            },
+        )
+    }
+
+    /// Returns a path-friendly name for the direction of the command.
+    #[must_use]
+    pub fn direction_name(&self) -> &'static str {
+        match self.direction() {
+            CommandDirection::ToClient => "to_client",
+            CommandDirection::ToServer => "to_server",
+        }
+    }
+
+    /// Deserializes the bytes again, which yields the command as seen by the receiver.
+    ///
+    /// Fields which are unknown to the protocol version have their default values; they aren't
+    /// part of the bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization fails or doesn't consume all bytes.
+    pub fn decode(&self) -> Result<Command> {
+        let mut deser = Deserializer::new(self.context(), &self.bytes);
+        let Some(command) = Command::deserialize(&mut deser)? else {
+            bail!("test vector '{}' did not yield a command", self.name);
+        };
+        if deser.has_remaining() {
+            bail!("test vector '{}' has trailing bytes", self.name);
+        }
+        Ok(command)
+    }
+
+    /// Deserializes the bytes again and checks whether serializing the result yields the same
+    /// bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization fails or yields different bytes.
+    pub fn verify(&self) -> Result<()> {
+        let command = self.decode()?;
+        let mut ser = VecSerializer::new(self.context(), self.bytes.len());
+        Command::serialize(&command, &mut ser)?;
+        if ser.take() != self.bytes {
+            bail!("test vector '{}' did not round-trip", self.name);
+        }
+        Ok(())
+    }
+
+    fn context(&self) -> ProtocolContext {
+        ProtocolContext {
+            dir: self.direction(),
+            protocol_version: self.protocol_version,
+            ser_fmt: self.ser_fmt,
+            audit: false,
+            compression: CompressionConfig::default(),
+            strict_inventory: false,
+        }
+    }
+}
+
+impl Display for TestVector {
+    /// Formats the vector as a single line: `<protocol version> <direction> <name> <hex bytes>`
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} {} {} {}",
+            self.protocol_version,
+            self.direction_name(),
+            self.name,
+            self.hex()
+        )
+    }
+}
+
+/// Generates the test vectors for all supported protocol versions.
+///
+/// # Errors
+///
+/// Returns an error if any of the commands cannot be serialized.
+pub fn generate() -> Result<Vec<TestVector>> {
+    let mut result = Vec::new();
+    for protocol_version in TEST_VECTOR_PROTOCOL_VERSIONS {
+        result.extend(generate_for_version(protocol_version)?);
+    }
+    Ok(result)
+}
+
+/// Generates the test vectors for a single protocol version.
+///
+/// # Errors
+///
+/// Returns an error if any of the commands cannot be serialized.
+pub fn generate_for_version(protocol_version: u16) -> Result<Vec<TestVector>> {
+    representative_commands()
+        .into_iter()
+        .map(|(name, command)| {
+            let context = ProtocolContext {
+                dir: command.direction(),
+                protocol_version,
+                ser_fmt: SER_FMT_VER_HIGHEST_WRITE,
+                audit: false,
+                compression: CompressionConfig::default(),
+                strict_inventory: false,
+            };
+            let mut ser = VecSerializer::new(context, 64);
+            Command::serialize(&command, &mut ser)?;
+            Ok(TestVector {
+                name,
+                protocol_version,
+                ser_fmt: context.ser_fmt,
+                command,
+                bytes: ser.take(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the library of commands test vectors are being generated for.
+///
+/// Commands using compression are excluded as their serialization isn't canonical.
+#[must_use]
+#[expect(clippy::too_many_lines, reason = "this is a plain list of examples")]
+pub fn representative_commands() -> Vec<(&'static str, Command)> {
+    let player_pos = PlayerPos {
+        position: Vec3::new(12.5, -3.0, 1024.25),
+        speed: Vec3::new(0.5, 0.0, -1.5),
+        pitch: 12.5,
+        yaw: -90.0,
+        keys_pressed: PlayerKeys::UP | PlayerKeys::JUMP,
+        fov: 1.25,
+        wanted_range: 12,
+        camera_inverted: false,
+        movement_speed: 1.0,
+        movement_direction: 0.5,
+    };
+
+    let to_server = |command: ToServerCommand| Command::ToServer(command);
+    let to_client = |command: ToClientCommand| Command::ToClient(command);
+
+    vec![
+        (
+            "init",
+            to_server(
+                InitSpec {
+                    serialization_ver_max: SER_FMT_VER_HIGHEST_WRITE,
+                    supp_compr_modes: 0,
+                    min_net_proto_version: 37,
+                    max_net_proto_version: LATEST_PROTOCOL_VERSION,
+                    user_name: "singleplayer".into(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "init2",
+            to_server(
+                Init2Spec {
+                    lang: Some("de".into()),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "client_ready",
+            to_server(
+                ClientReadySpec {
+                    major_ver: 5,
+                    minor_ver: 10,
+                    patch_ver: 0,
+                    reserved: 0,
+                    full_ver: "5.10.0".into(),
+                    formspec_ver: Some(8),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "player_pos",
+            to_server(
+                PlayerPosCommand {
+                    player_pos: player_pos.clone(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "got_blocks",
+            to_server(
+                GotBlocksSpec {
+                    blocks: vec![I16Vec3::new(0, 0, 0), I16Vec3::new(-1, 2, -3)],
+                }
+                .into(),
+            ),
+        ),
+        (
+            "got_far_blocks",
+            to_server(
+                GotFarBlocksSpec {
+                    level: 2,
+                    blocks: vec![I16Vec3::new(-4, 0, 8)],
+                }
+                .into(),
+            ),
+        ),
+        (
+            "chat_message",
+            to_server(
+                TSChatMessageSpec {
+                    message: "Hello, wörld! ☺".into(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "interact",
+            to_server(
+                InteractSpec {
+                    action: InteractAction::Place,
+                    item_index: 3,
+                    pointed_thing: PointedThing::Node {
+                        under_surface: I16Vec3::new(10, 4, -7),
+                        above_surface: I16Vec3::new(10, 5, -7),
+                    },
+                    player_pos,
+                }
+                .into(),
+            ),
+        ),
+        (
+            "inventory_action_move",
+            to_server(
+                InventoryActionSpec {
+                    action: InventoryAction::Move {
+                        count: 5,
+                        from_inv: InventoryLocation::CurrentPlayer,
+                        from_list: "main".into(),
+                        from_i: 0,
+                        to_inv: InventoryLocation::CurrentPlayer,
+                        to_list: "craft".into(),
+                        to_i: Some(4),
+                    },
+                }
+                .into(),
+            ),
+        ),
+        (
+            "inventory_fields",
+            to_server(
+                InventoryFieldsSpec {
+                    client_formspec_name: "my_form".into(),
+                    fields: vec![
+                        ("name".into(), "value".into()),
+                        ("quit".into(), "true".into()),
+                    ],
+                }
+                .into(),
+            ),
+        ),
+        (
+            "update_client_info",
+            to_server(
+                UpdateClientInfoSpec {
+                    render_target_size: UVec2::new(1920, 1080),
+                    real_gui_scaling: 1.5,
+                    real_hud_scaling: 1.0,
+                    max_fs_size: Vec2::new(20.0, 11.25),
+                    touch_controls: false,
+                }
+                .into(),
+            ),
+        ),
+        (
+            "hello",
+            to_client(
+                HelloSpec {
+                    serialization_version: SER_FMT_VER_HIGHEST_WRITE,
+                    compression_mode: 0,
+                    protocol_version: LATEST_PROTOCOL_VERSION,
+                    auth_mechs: AuthMechsBitset::default(),
+                    username_legacy: "singleplayer".into(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "auth_accept",
+            to_client(
+                AuthAcceptSpec {
+                    player_pos: Vec3::new(0.0, 100.0, 0.0),
+                    map_seed: 0x0123_4567_89ab_cdef,
+                    recommended_send_interval: 0.1,
+                    sudo_auth_methods: 2,
+                }
+                .into(),
+            ),
+        ),
+        (
+            "time_of_day",
+            to_client(
+                TimeOfDaySpec {
+                    time_of_day: 6000,
+                    time_speed: Some(72.0),
+                    extra: TrailingBytes::default(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "remove_node",
+            to_client(
+                RemovenodeSpec {
+                    pos: I16Vec3::new(-100, 20, 300),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "chat_message",
+            to_client(
+                TCChatMessageSpec {
+                    version: 1,
+                    message_type: 1,
+                    sender: "server".into(),
+                    message: "Welcome! ☺".into(),
+                    timestamp: 1_700_000_000,
+                }
+                .into(),
+            ),
+        ),
+        (
+            "hp",
+            to_client(
+                HpSpec {
+                    hp: 20,
+                    damage_effect: Some(true),
+                    extra: TrailingBytes::default(),
+                }
+                .into(),
+            ),
+        ),
+        ("breath", to_client(BreathSpec { breath: 10 }.into())),
+        (
+            "move_player",
+            to_client(
+                MovePlayerSpec {
+                    pos: Vec3::new(10.0, 20.0, 30.0),
+                    pitch: 0.0,
+                    yaw: 180.0,
+                }
+                .into(),
+            ),
+        ),
+        (
+            "fov",
+            to_client(
+                FovSpec {
+                    fov: 1.5,
+                    is_multiplier: true,
+                    transition_time: Some(1.0),
+                    extra: TrailingBytes::default(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "privileges",
+            to_client(
+                PrivilegesSpec {
+                    privileges: vec!["interact".into(), "shout".into()],
+                }
+                .into(),
+            ),
+        ),
+        (
+            "show_formspec",
+            to_client(
+                ShowFormspecSpec {
+                    form_spec: "size[8,4]\nbutton_exit[3,3;2,1;ok;OK]".into(),
+                    form_name: "my_form".into(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "hud_add",
+            to_client(
+                HudaddSpec {
+                    server_id: 1,
+                    typ: 4,
+                    pos: Vec2::ZERO,
+                    name: "home".into(),
+                    scale: Vec2::ONE,
+                    text: "m".into(),
+                    number: 0x00ff_ff00,
+                    item: 1,
+                    dir: 0,
+                    align: Vec2::ZERO,
+                    offset: Vec2::ZERO,
+                    world_pos: Some(Vec3::new(1.0, 2.0, 3.0)),
+                    size: Some(IVec2::ZERO),
+                    z_index: Some(0),
+                    text2: Some(String::new()),
+                    style: Some(0),
+                    extra: TrailingBytes::default(),
+                }
+                .into(),
+            ),
+        ),
+        (
+            "hud_change",
+            to_client(
+                HudchangeCommand {
+                    server_id: 1,
+                    stat: HudStat::WorldPos(Vec3::new(4.0, 5.0, 6.0)),
+                }
+                .into(),
+            ),
+        ),
+        ("hud_remove", to_client(HudrmSpec { server_id: 1 }.into())),
+        (
+            "play_sound",
+            to_client(
+                PlaySoundSpec {
+                    server_id: EPHEMERAL_SOUND_ID,
+                    spec_name: "default_place_node".into(),
+                    spec_gain: 0.5,
+                    typ: SoundLocationType::Position,
+                    pos: Vec3::new(10.0, 20.0, 30.0),
+                    object_id: 0,
+                    spec_loop: false,
+                    spec_fade: 0.0,
+                    spec_pitch: 1.0,
+                    ephemeral: true,
+                    start_time: 0.25,
+                }
+                .into(),
+            ),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::Command;
+    use crate::commands::server_to_client::ToClientCommand;
+    use crate::versions::{
+        EPHEMERAL_SOUND_PROTOCOL_VERSION, MAX_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION,
+        SOUND_START_TIME_PROTOCOL_VERSION,
+    };
+
+    use super::{generate, generate_for_version};
+
+    /// The expected serializations of the oldest and the newest protocol version as
+    /// `(protocol version, direction, name, hex bytes)`
+    const GOLDEN_VECTORS: &[(u16, &str, &str, &str)] = &[
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "init",
+            "00021d00000025002f000c73696e676c65706c61796572",
+        ),
+        (MIN_PROTOCOL_VERSION, "to_server", "init2", "001100026465"),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "client_ready",
+            "0043050a00000006352e31302e300008",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "player_pos",
+            "0023000004e2fffffed4000190190000003200000000ffffff6a000004e2ffffdcd800000011640c003f8000003f000000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "got_blocks",
+            "002402000000000000ffff0002fffd",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "got_far_blocks",
+            "00540201fffc00000008",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "chat_message",
+            "0032000f00480065006c006c006f002c0020007700f60072006c006400210020263a",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "interact",
+            "00390300030000000e0001000a0004fff9000a0005fff9000004e2fffffed4000190190000003200000000ffffff6a000004e2ffffdcd800000011640c003f8000003f000000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "inventory_action_move",
+            "00314d6f766520352063757272656e745f706c61796572206d61696e20302063757272656e745f706c617965722063726166742034",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "inventory_fields",
+            "003c00076d795f666f726d000200046e616d650000000576616c75650004717569740000000474727565",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_server",
+            "update_client_info",
+            "005300000780000004383fc000003f80000041a000004134000000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "hello",
+            "00021d0000002f00000002000c73696e676c65706c61796572",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "auth_accept",
+            "00030000000042c80000000000000123456789abcdef3dcccccd00000002",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "time_of_day",
+            "0029177042900000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "remove_node",
+            "0022ff9c0014012c",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "chat_message",
+            "002f01010006007300650072007600650072000a00570065006c0063006f006d006500210020263a000000006553f100",
+        ),
+        (MIN_PROTOCOL_VERSION, "to_client", "hp", "0033001401"),
+        (MIN_PROTOCOL_VERSION, "to_client", "breath", "004e000a"),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "move_player",
+            "00344120000041a0000041f000000000000043340000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "fov",
+            "00363fc00000013f800000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "privileges",
+            "004100020008696e746572616374000573686f7574",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "show_formspec",
+            "00440000002473697a655b382c345d0a627574746f6e5f657869745b332c333b322c313b6f6b3b4f4b5d00076d795f666f726d",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "hud_add",
+            "0049000000010400000000000000000004686f6d653f8000003f80000000016d00ffff000000000100000000000000000000000000000000000000003f800000400000004040000000000000000000000000000000000000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "hud_change",
+            "004b00000001094080000040a0000040c00000",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "hud_remove",
+            "004a00000001",
+        ),
+        (
+            MIN_PROTOCOL_VERSION,
+            "to_client",
+            "play_sound",
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f800000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "init",
+            "00021d00000025002f000c73696e676c65706c61796572",
+        ),
+        (MAX_PROTOCOL_VERSION, "to_server", "init2", "001100026465"),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "client_ready",
+            "0043050a00000006352e31302e300008",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "player_pos",
+            "0023000004e2fffffed4000190190000003200000000ffffff6a000004e2ffffdcd800000011640c003f8000003f000000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "got_blocks",
+            "002402000000000000ffff0002fffd",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "got_far_blocks",
+            "00540201fffc00000008",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "chat_message",
+            "0032000f00480065006c006c006f002c0020007700f60072006c006400210020263a",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "interact",
+            "00390300030000000e0001000a0004fff9000a0005fff9000004e2fffffed4000190190000003200000000ffffff6a000004e2ffffdcd800000011640c003f8000003f000000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "inventory_action_move",
+            "00314d6f766520352063757272656e745f706c61796572206d61696e20302063757272656e745f706c617965722063726166742034",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "inventory_fields",
+            "003c00076d795f666f726d000200046e616d650000000576616c75650004717569740000000474727565",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_server",
+            "update_client_info",
+            "005300000780000004383fc000003f80000041a000004134000000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "hello",
+            "00021d0000002f00000002000c73696e676c65706c61796572",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "auth_accept",
+            "00030000000042c80000000000000123456789abcdef3dcccccd00000002",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "time_of_day",
+            "0029177042900000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "remove_node",
+            "0022ff9c0014012c",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "chat_message",
+            "002f01010006007300650072007600650072000a00570065006c0063006f006d006500210020263a000000006553f100",
+        ),
+        (MAX_PROTOCOL_VERSION, "to_client", "hp", "0033001401"),
+        (MAX_PROTOCOL_VERSION, "to_client", "breath", "004e000a"),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "move_player",
+            "00344120000041a0000041f000000000000043340000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "fov",
+            "00363fc00000013f800000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "privileges",
+            "004100020008696e746572616374000573686f7574",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "show_formspec",
+            "00440000002473697a655b382c345d0a627574746f6e5f657869745b332c333b322c313b6f6b3b4f4b5d00076d795f666f726d",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "hud_add",
+            "0049000000010400000000000000000004686f6d653f8000003f80000000016d00ffff000000000100000000000000000000000000000000000000003f800000400000004040000000000000000000000000000000000000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "hud_change",
+            "004b00000001094080000040a0000040c00000",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "hud_remove",
+            "004a00000001",
+        ),
+        (
+            MAX_PROTOCOL_VERSION,
+            "to_client",
+            "play_sound",
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f800000013e800000",
+        ),
+    ];
+
+    /// The expected serializations of `play_sound` around the versions introducing its fields
+    const GOLDEN_PLAY_SOUND: &[(u16, &str)] = &[
+        (
+            37,
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f800000",
+        ),
+        (
+            38,
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f80000001",
+        ),
+        (
+            42,
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f80000001",
+        ),
+        (
+            43,
+            "003fffffffff001264656661756c745f706c6163655f6e6f64653f000000014120000041a0000041f00000000000000000003f800000013e800000",
+        ),
+    ];
+
+    #[test]
+    fn vectors_round_trip() {
+        for vector in generate().unwrap() {
+            vector.verify().unwrap();
+        }
+    }
+
+    #[test]
+    fn vectors_match_the_golden_bytes() {
+        let vectors = [MIN_PROTOCOL_VERSION, MAX_PROTOCOL_VERSION]
+            .into_iter()
+            .flat_map(|protocol_version| generate_for_version(protocol_version).unwrap());
+        let actual: Vec<_> = vectors
+            .map(|vector| {
+                (
+                    vector.protocol_version,
+                    vector.direction_name(),
+                    vector.name,
+                    vector.hex(),
+                )
+            })
+            .collect();
+        let expected: Vec<_> = GOLDEN_VECTORS
+            .iter()
+            .map(|&(protocol_version, direction, name, hex)| {
+                (protocol_version, direction, name, hex.to_owned())
+            })
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn fields_unknown_to_older_versions_are_dropped() {
+        for &(protocol_version, hex) in GOLDEN_PLAY_SOUND {
+            let vector = generate_for_version(protocol_version)
+                .unwrap()
+                .into_iter()
+                .find(|vector| vector.name == "play_sound")
+                .unwrap();
+            assert_eq!(vector.hex(), hex, "protocol version {protocol_version}");
+
+            let Command::ToClient(ToClientCommand::PlaySound(sound)) = vector.decode().unwrap()
+            else {
+                unreachable!("play_sound decodes to a sound");
+            };
+            assert_eq!(
+                sound.ephemeral,
+                protocol_version >= EPHEMERAL_SOUND_PROTOCOL_VERSION
+            );
+            let start_time = if protocol_version >= SOUND_START_TIME_PROTOCOL_VERSION {
+                0.25
+            } else {
+                0.0
+            };
+            assert!((sound.start_time - start_time).abs() < f32::EPSILON);
+        }
+    }
+}
@@ -20,20 +20,31 @@
 mod active_object;
 mod arrays;
 mod binary;
+mod color;
 mod compressed;
+mod item_groups;
+mod item_stack;
+mod minimap;
 mod node_box;
 mod options;
+mod prediction;
 mod primitives;
+mod rich_text;
 mod strings;
 mod tile;
 mod vectors;
 
 use crate::itos;
+use crate::wire::compression;
+use crate::wire::compression::CompressionConfig;
 use crate::wire::deser::Deserialize;
 use crate::wire::deser::DeserializeError;
 use crate::wire::deser::DeserializeResult;
 use crate::wire::deser::Deserializer;
+use crate::wire::deser::LineError;
+use crate::wire::packet::ALPHA_MODE_PROTOCOL_VERSION;
 use crate::wire::packet::LATEST_PROTOCOL_VERSION;
+use crate::wire::packet::MOVE_RESISTANCE_PROTOCOL_VERSION;
 use crate::wire::packet::SER_FMT_HIGHEST_READ;
 use crate::wire::ser::Serialize;
 use crate::wire::ser::SerializeResult;
@@ -47,31 +58,39 @@ use crate::wire::util::serialize_json_string_if_needed;
 use crate::wire::util::skip_whitespace;
 use crate::wire::util::split_by_whitespace;
 use crate::wire::util::stoi;
-use crate::wire::util::zstd_compress;
+use crate::wire::util::zstd_compress_with_level;
 use crate::wire::util::zstd_decompress;
 pub use active_object::*;
 use anyhow::anyhow;
 use anyhow::bail;
 pub use arrays::*;
 pub use binary::*;
+pub use color::*;
 pub use compressed::*;
 use glam::I16Vec3;
 use glam::IVec3;
-use glam::U8Vec3;
 use glam::U8Vec4;
 use glam::Vec3;
-use glam::Vec4Swizzles;
+pub use item_groups::*;
 use luanti_core::ContentId;
+use luanti_core::FaceDir;
 use luanti_core::LEVELED_MAX;
 use luanti_core::LIQUID_LEVEL_SOURCE;
 use luanti_core::MapNode;
 use luanti_core::MapNodeIndex;
+use luanti_core::WallMounted;
 use luanti_protocol_derive::LuantiDeserialize;
 use luanti_protocol_derive::LuantiSerialize;
+use luanti_protocol_derive::ToJson;
+pub use minimap::*;
 pub use node_box::*;
 pub use options::*;
+pub use prediction::*;
+pub use rich_text::*;
+use std::borrow::Cow;
 use std::fmt;
 use std::marker::PhantomData;
+use std::time::Instant;
 pub use strings::*;
 pub use tile::*;
 
@@ -79,9 +98,10 @@ pub use tile::*;
 /// write checks that depend directly on the protocol version instead.
 const CONTENTFEATURES_VERSION: u8 = 13;
 
-pub type CommandId = u8;
+/// The id preceding every command on the wire
+pub type CommandId = u16;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, ToJson)]
 pub enum CommandDirection {
     ToClient,
     ToServer,
@@ -116,6 +136,14 @@ pub struct ProtocolContext {
     pub dir: CommandDirection,
     pub protocol_version: u16,
     pub ser_fmt: u8,
+    /// Whether received commands are re-serialized and compared to the original; see
+    /// [`crate::wire::audit`]
+    pub audit: bool,
+    /// How compressed payloads are being compressed when sending
+    pub compression: CompressionConfig,
+    /// Whether the text format of an [`Inventory`] is being parsed as strictly as Luanti does,
+    /// rejecting unknown lines instead of ignoring them
+    pub strict_inventory: bool,
 }
 
 impl ProtocolContext {
@@ -125,6 +153,9 @@ impl ProtocolContext {
             dir: CommandDirection::for_receive(remote_is_server),
             protocol_version: LATEST_PROTOCOL_VERSION,
             ser_fmt: SER_FMT_HIGHEST_READ,
+            audit: false,
+            compression: CompressionConfig::default(),
+            strict_inventory: false,
         }
     }
 
@@ -134,6 +165,33 @@ impl ProtocolContext {
             dir: CommandDirection::for_send(remote_is_server),
             protocol_version: LATEST_PROTOCOL_VERSION,
             ser_fmt: SER_FMT_HIGHEST_READ,
+            audit: false,
+            compression: CompressionConfig::default(),
+            strict_inventory: false,
+        }
+    }
+
+    /// Returns this context with auditing switched on or off.
+    #[must_use]
+    pub fn with_audit(self, audit: bool) -> Self {
+        Self { audit, ..self }
+    }
+
+    /// Returns this context with strict parsing of inventories switched on or off.
+    #[must_use]
+    pub fn with_strict_inventory(self, strict_inventory: bool) -> Self {
+        Self {
+            strict_inventory,
+            ..self
+        }
+    }
+
+    /// Returns this context with the given compression settings.
+    #[must_use]
+    pub fn with_compression(self, compression: CompressionConfig) -> Self {
+        Self {
+            compression,
+            ..self
         }
     }
 }
@@ -196,51 +254,63 @@ pub struct StarParams {
     pub day_opacity: Option<f32>,
 }
 
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
-pub struct SColor(pub U8Vec4);
-
-impl SColor {
-    pub const BLACK: Self = Self(U8Vec4::new(0, 0, 0, 255));
-    pub const RED: Self = Self(U8Vec4::new(255, 0, 0, 255));
-    pub const GREEN: Self = Self(U8Vec4::new(0, 255, 0, 255));
-    pub const YELLOW: Self = Self(U8Vec4::new(255, 255, 0, 255));
-    pub const BLUE: Self = Self(U8Vec4::new(0, 0, 0, 255));
-    pub const MAGENTA: Self = Self(U8Vec4::new(255, 0, 255, 255));
-    pub const CYAN: Self = Self(U8Vec4::new(0, 255, 255, 255));
-    pub const WHITE: Self = Self(U8Vec4::new(255, 255, 255, 255));
-
-    #[expect(
-        clippy::min_ident_chars,
-        reason = "those identifiers are well-known and clear from the context"
-    )]
+bitflags::bitflags! {
+    /// The keys a player is holding down, as sent along with every [`PlayerPos`]
+    ///
+    /// Bits unknown to this crate are being retained, so newer clients don't lose information.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct PlayerKeys: u32 {
+        /// walking forward
+        const UP = 1 << 0;
+        /// walking backward
+        const DOWN = 1 << 1;
+        /// walking to the left
+        const LEFT = 1 << 2;
+        /// walking to the right
+        const RIGHT = 1 << 3;
+        /// jumping, swimming or flying up
+        const JUMP = 1 << 4;
+        /// the special key; moving fast or climbing down, depending on the client's settings
+        const AUX1 = 1 << 5;
+        /// sneaking, swimming or flying down
+        const SNEAK = 1 << 6;
+        /// digging or punching (left mouse button)
+        const DIG = 1 << 7;
+        /// placing or using (right mouse button)
+        const PLACE = 1 << 8;
+        /// zooming
+        const ZOOM = 1 << 9;
+    }
+}
+
+impl PlayerKeys {
+    /// Returns `true` if any of the keys for walking is pressed.
     #[must_use]
-    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
-        Self((r, g, b, a).into())
+    pub fn is_walking(self) -> bool {
+        self.intersects(Self::UP | Self::DOWN | Self::LEFT | Self::RIGHT)
     }
 
+    /// Returns `true` if the player is sneaking (or swimming or flying down).
     #[must_use]
-    pub fn rgb(self) -> U8Vec3 {
-        self.0.xyz()
+    pub fn is_sneaking(self) -> bool {
+        self.contains(Self::SNEAK)
     }
-}
 
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
-pub struct MinimapMode {
-    pub typ: u16,
-    pub label: String,
-    pub size: u16,
-    pub texture: String,
-    pub scale: u16,
+    /// Returns `true` if the player is jumping (or swimming or flying up).
+    #[must_use]
+    pub fn is_jumping(self) -> bool {
+        self.contains(Self::JUMP)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct PlayerPos {
-    pub position: Vec3,    // serialized as v3i32, *100.0f
-    pub speed: Vec3,       // serialized as v3i32, *100.0f
-    pub pitch: f32,        // serialized as i32, *100.0f
-    pub yaw: f32,          // serialized as i32, *100.0f
-    pub keys_pressed: u32, // bitset
-    pub fov: f32,          // serialized as u8, *80.0f
+    pub position: Vec3, // serialized as v3i32, *100.0f
+    pub speed: Vec3,    // serialized as v3i32, *100.0f
+    pub pitch: f32,     // serialized as i32, *100.0f
+    pub yaw: f32,       // serialized as i32, *100.0f
+    pub keys_pressed: PlayerKeys,
+    pub fov: f32, // serialized as u8, *80.0f
     pub wanted_range: u8,
 
     pub camera_inverted: bool,
@@ -263,7 +333,7 @@ impl Serialize for PlayerPos {
         IVec3::serialize(&s_speed, ser)?;
         i32::serialize(&s_pitch, ser)?;
         i32::serialize(&s_yaw, ser)?;
-        u32::serialize(&value.keys_pressed, ser)?;
+        u32::serialize(&value.keys_pressed.bits(), ser)?;
         u8::serialize(&s_fov, ser)?;
         u8::serialize(&value.wanted_range, ser)?;
         u8::serialize(&bits, ser)?;
@@ -280,7 +350,7 @@ impl Deserialize for PlayerPos {
         let s_speed = IVec3::deserialize(deserializer)?;
         let s_pitch = i32::deserialize(deserializer)?;
         let s_yaw = i32::deserialize(deserializer)?;
-        let keys_pressed = u32::deserialize(deserializer)?;
+        let keys_pressed = PlayerKeys::from_bits_retain(u32::deserialize(deserializer)?);
         let s_fov = u8::deserialize(deserializer)?;
         let wanted_range = u8::deserialize(deserializer)?;
 
@@ -330,40 +400,7 @@ impl<T1: Deserialize, T2: Deserialize> Deserialize for Pair<T1, T2> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct MinimapModeList {
-    pub mode: u16,
-    pub vec: Vec<MinimapMode>,
-}
-
-impl Serialize for MinimapModeList {
-    type Input = Self;
-    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
-        // The length of the list is a u16 which precedes `mode`,
-        // which makes the layout not fit into any usual pattern.
-        u16::serialize(&u16::try_from(value.vec.len())?, ser)?;
-        u16::serialize(&value.mode, ser)?;
-        for mode in &value.vec {
-            MinimapMode::serialize(mode, ser)?;
-        }
-        Ok(())
-    }
-}
-
-impl Deserialize for MinimapModeList {
-    type Output = Self;
-    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
-        let count = u16::deserialize(deser)?;
-        let mode = u16::deserialize(deser)?;
-        let mut vec: Vec<MinimapMode> = Vec::with_capacity(count as usize);
-        for _ in 0..count {
-            vec.push(MinimapMode::deserialize(deser)?);
-        }
-        Ok(MinimapModeList { mode, vec })
-    }
-}
-
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct AuthMechsBitset {
     pub legacy_password: bool,
     pub srp: bool,
@@ -494,8 +531,7 @@ pub enum LiquidType {
 pub struct ContentFeatures {
     pub version: u8,
     pub name: String,
-    #[wrap(Array16<Pair<String, i16>>)]
-    pub groups: Vec<(String, i16)>,
+    pub groups: ItemGroups,
     pub param_type: ParamType,
     pub param_type_2: ParamType2,
     pub drawtype: DrawType,
@@ -548,9 +584,18 @@ pub struct ContentFeatures {
     pub legacy_facedir_simple: bool,
     pub legacy_wallmounted: bool,
     pub node_dig_prediction: String,
+    #[default(LEVELED_MAX)]
+    #[since(ALPHA_MODE_PROTOCOL_VERSION)]
     pub leveled_max: u8,
+    /// Older clients use `alpha_for_legacy` instead; see [`ContentFeatures::for_protocol`].
+    #[default(AlphaMode::LegacyCompat)]
+    #[since(ALPHA_MODE_PROTOCOL_VERSION)]
     pub alpha: AlphaMode,
+    #[default]
+    #[since(MOVE_RESISTANCE_PROTOCOL_VERSION)]
     pub move_resistance: u8,
+    #[default]
+    #[since(MOVE_RESISTANCE_PROTOCOL_VERSION)]
     pub liquid_move_physics: bool,
 }
 
@@ -566,7 +611,7 @@ impl ContentFeatures {
             version: CONTENTFEATURES_VERSION, // compare to NodeDefManager::serialize
             name,
             // Unknown nodes can be dug
-            groups: vec![("dig_immediate".into(), 2)],
+            groups: [("dig_immediate", 2)].into_iter().collect(),
             param_type: ParamType::None,
             param_type_2: ParamType2::None,
             drawtype: DrawType::Normal,
@@ -622,6 +667,127 @@ impl ContentFeatures {
         }
     }
 
+    /// Returns the node definition as it needs to be sent to a peer using the given protocol
+    /// version.
+    ///
+    /// Fields the peer doesn't know about are omitted during serialization anyway; this takes care
+    /// of the fields whose meaning has changed.
+    #[must_use]
+    pub fn for_protocol(&self, protocol_version: u16) -> Cow<'_, Self> {
+        if protocol_version >= ALPHA_MODE_PROTOCOL_VERSION {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(Self {
+            alpha_for_legacy: self.legacy_alpha(),
+            ..self.clone()
+        })
+    }
+
+    /// Returns the alpha value for peers which don't know about [`AlphaMode`].
+    #[must_use]
+    pub fn legacy_alpha(&self) -> u8 {
+        match self.alpha {
+            AlphaMode::Opaque | AlphaMode::Clip => 255,
+            AlphaMode::Blend | AlphaMode::LegacyCompat => self.alpha_for_legacy,
+        }
+    }
+
+    /// Returns the orientation of a node of this kind; `None` if its param2 holds no facedir.
+    ///
+    /// `4dir` nodes only use the four rotations around the vertical axis.
+    #[must_use]
+    pub fn facedir(&self, node: &MapNode) -> Option<FaceDir> {
+        match self.param_type_2 {
+            ParamType2::FaceDir | ParamType2::ColoredFaceDir => Some(node.facedir()),
+            ParamType2::Dir4 | ParamType2::ColoredDir4 => Some(FaceDir::new(0, node.param2)),
+            _ => None,
+        }
+    }
+
+    /// Returns the direction a node of this kind is attached to; `None` if its param2 holds no
+    /// wallmounted direction.
+    #[must_use]
+    pub fn wallmounted(&self, node: &MapNode) -> Option<WallMounted> {
+        match self.param_type_2 {
+            ParamType2::WallMounted | ParamType2::ColoredWallMounted => Some(node.wallmounted()),
+            _ => None,
+        }
+    }
+
+    /// Returns the index into the palette of a node of this kind; `None` if it isn't colored by
+    /// param2.
+    #[must_use]
+    pub fn color_index(&self, node: &MapNode) -> Option<u8> {
+        match self.param_type_2 {
+            ParamType2::Color => Some(node.param2),
+            ParamType2::ColoredFaceDir | ParamType2::ColoredDegRotate => Some(node.param2 >> 5),
+            ParamType2::ColoredWallMounted => Some(node.param2 >> 3),
+            ParamType2::ColoredDir4 => Some(node.param2 >> 2),
+            _ => None,
+        }
+    }
+
+    /// Returns the rotation of a node of this kind around the vertical axis in degrees; `None` if
+    /// its param2 holds no such rotation.
+    #[must_use]
+    pub fn rotation_degrees(&self, node: &MapNode) -> Option<f32> {
+        match self.param_type_2 {
+            ParamType2::DegRotate => Some(f32::from(node.param2 % 240) * 1.5),
+            ParamType2::ColoredDegRotate => Some(f32::from(node.param2 & 0x1F) * 15.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the amount of liquid or material within a node of this kind like `getLevel` of
+    /// Luanti: sources are full, flowing liquids and `leveled` nodes store their level in param2
+    /// and all other nodes use their fixed [`Self::leveled`] value.
+    #[must_use]
+    pub fn level(&self, node: &MapNode) -> u8 {
+        if self.liquid_type == LiquidType::Source {
+            return LIQUID_LEVEL_SOURCE;
+        }
+        if self.param_type_2 == ParamType2::FlowingLiquid || self.liquid_type == LiquidType::Flowing
+        {
+            return node.liquid_level();
+        }
+        if self.param_type_2 == ParamType2::Leveled {
+            let level = node.leveled();
+            if level != 0 {
+                return level;
+            }
+        }
+        self.leveled.min(self.leveled_max)
+    }
+
+    /// Returns what clients show after a node of this kind has been dug.
+    #[must_use]
+    pub fn dig_prediction(&self) -> DigPrediction<'_> {
+        match self.node_dig_prediction.as_str() {
+            "" => DigPrediction::None,
+            "air" => DigPrediction::Air,
+            name => DigPrediction::Node(name),
+        }
+    }
+
+    /// Returns `true` if this is a wallmounted node.
+    fn is_wallmounted(&self) -> bool {
+        matches!(
+            self.param_type_2,
+            ParamType2::WallMounted | ParamType2::ColoredWallMounted
+        )
+    }
+
+    /// Returns `true` if this is a facedir or 4dir node.
+    fn is_facedir(&self) -> bool {
+        matches!(
+            self.param_type_2,
+            ParamType2::FaceDir
+                | ParamType2::ColoredFaceDir
+                | ParamType2::Dir4
+                | ParamType2::ColoredDir4
+        )
+    }
+
     /// Create the node definition for `CONTENT_UNKNOWN`.
     #[must_use]
     pub fn unknown() -> Self {
@@ -693,7 +859,7 @@ pub enum AlphaMode {
     LegacyCompat,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct NodeDefManager {
     pub content_features: Vec<(u16, ContentFeatures)>,
 }
@@ -701,9 +867,13 @@ pub struct NodeDefManager {
 /// The way this structure is encoded is really unusual, in order to
 /// allow the `ContentFeatures` to be extended in the future without
 /// changing the encoding.
+///
+/// The node definitions are adapted to the protocol version of the peer, so the same
+/// `NodeDefManager` can be sent to all clients.
 impl Serialize for NodeDefManager {
     type Input = Self;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        let protocol_version = ser.context().protocol_version;
         // Version
         u8::serialize(&1, ser)?;
         let count: u16 = u16::try_from(value.content_features.len())?;
@@ -715,7 +885,7 @@ impl Serialize for NodeDefManager {
             u16::serialize(index, ser)?;
             // The contents of each feature is wrapped in a String16.
             let string16_wrapper = ser.write_marker(2)?;
-            ContentFeatures::serialize(features, ser)?;
+            ContentFeatures::serialize(&features.for_protocol(protocol_version), ser)?;
             let len: u16 = u16::try_from(ser.marker_distance(&string16_wrapper))?;
             ser.set_marker(string16_wrapper, &len.to_be_bytes()[..])?;
         }
@@ -757,7 +927,7 @@ const MAP_BLOCKSIZE: u16 = 16;
 // Number of nodes in a block
 const NODE_COUNT: u16 = MAP_BLOCKSIZE * MAP_BLOCKSIZE * MAP_BLOCKSIZE;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct TransferrableMapBlock {
     /// Should be set to `false` if there will be no light obstructions above the block.
     /// If/when sunlight of a block is updated and there is no block above it, this value is checked
@@ -848,7 +1018,14 @@ impl Serialize for TransferrableMapBlock {
         if ver >= 29 {
             // The whole thing is zstd compressed
             let tmp = tmp_ser.take();
-            zstd_compress(&tmp, |chunk| serializer.write_bytes(chunk))?;
+            let started = Instant::now();
+            let level = serializer.context().compression.map_block_level;
+            let mut compressed = 0;
+            zstd_compress_with_level(&tmp, level, |chunk| {
+                compressed += chunk.len();
+                serializer.write_bytes(chunk)
+            })?;
+            compression::record::<Self>(tmp.len(), compressed, started.elapsed());
         } else {
             // Just write it directly
             let tmp = tmp_ser.take();
@@ -943,7 +1120,7 @@ impl Deserialize for TransferrableMapBlock {
                 Ok(())
             })?;
             deser.take(bytes_taken)?;
-            let deser = &mut Deserializer::new(deser.context(), &tmp);
+            let deser = &mut deser.nested(&tmp);
             let header = MapBlockHeader::deserialize(deser)?;
             let nodes = MapNodesBulk::deserialize(deser)?;
             let node_metadata = NodeMetadataList::deserialize(deser)?;
@@ -960,13 +1137,13 @@ impl Deserialize for TransferrableMapBlock {
             let (consumed1, nodes_raw) = decompress_zlib(deser.peek_all())?;
             deser.take(consumed1)?;
             let nodes = {
-                let mut tmp = Deserializer::new(deser.context(), &nodes_raw);
+                let mut tmp = deser.nested(&nodes_raw);
                 MapNodesBulk::deserialize(&mut tmp)?
             };
             let (consumed2, metadata_raw) = decompress_zlib(deser.peek_all())?;
             deser.take(consumed2)?;
             let node_metadata = {
-                let mut tmp = Deserializer::new(deser.context(), &metadata_raw);
+                let mut tmp = deser.nested(&metadata_raw);
                 NodeMetadataList::deserialize(&mut tmp)?
             };
             Ok(Self {
@@ -983,7 +1160,7 @@ impl Deserialize for TransferrableMapBlock {
 
 /// This has a special serialization, presumably to make it compress better.
 /// Each param is stored in a separate array.
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, ToJson)]
 pub struct MapNodesBulk {
     // TODO(kawogi) replace with `MapBlockNodes`
     pub nodes: [MapNode; NODE_COUNT as usize],
@@ -999,35 +1176,20 @@ impl Serialize for MapNodesBulk {
     type Input = Self;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
         let nodecount = NODE_COUNT as usize;
-        // Write all param0 first
-        ser.write(2 * nodecount, |buf| {
-            assert_eq!(buf.len(), 2 * nodecount, "size mismatch");
-            for index in 0..nodecount {
-                let bytes = value.nodes[index].content_id.0.to_be_bytes();
-                buf[2 * index] = bytes[0];
-                buf[2 * index + 1] = bytes[1];
+        // The nodes are transferred as three planes: all param0 first, then all param1 and
+        // finally all param2. Writing them in one go avoids bounds checks for every byte.
+        ser.write(4 * nodecount, |buf| {
+            assert_eq!(buf.len(), 4 * nodecount, "size mismatch");
+            let (content_ids, param_planes) = buf.split_at_mut(2 * nodecount);
+            let (param1s, param2s) = param_planes.split_at_mut(nodecount);
+            for (bytes, node) in content_ids.chunks_exact_mut(2).zip(&value.nodes) {
+                bytes.copy_from_slice(&node.content_id.0.to_be_bytes());
             }
-        })?;
-        // Write all param1
-        ser.write(nodecount, |buf| {
-            assert_eq!(buf.len(), nodecount, "size mismatch");
-            #[expect(
-                clippy::needless_range_loop,
-                reason = "// TODO transform into iterator"
-            )]
-            for index in 0..nodecount {
-                buf[index] = value.nodes[index].param1;
+            for (param1, node) in param1s.iter_mut().zip(&value.nodes) {
+                *param1 = node.param1;
             }
-        })?;
-        // Write all param2
-        ser.write(nodecount, |buf| {
-            assert_eq!(buf.len(), nodecount, "size mismatch");
-            #[expect(
-                clippy::needless_range_loop,
-                reason = "// TODO transform into iterator"
-            )]
-            for i in 0..nodecount {
-                buf[i] = value.nodes[i].param2;
+            for (param2, node) in param2s.iter_mut().zip(&value.nodes) {
+                *param2 = node.param2;
             }
         })?;
         Ok(())
@@ -1102,7 +1264,7 @@ impl Serialize for MapNode {
 //     }
 // }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct NodeMetadataList {
     pub metadata: Vec<(MapNodeIndex, NodeMetadata)>,
 }
@@ -1142,7 +1304,7 @@ impl Deserialize for NodeMetadataList {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct AbsNodeMetadataList {
     pub metadata: Vec<(AbsBlockPos, NodeMetadata)>,
 }
@@ -1188,6 +1350,20 @@ pub struct AbsBlockPos {
     pos: I16Vec3,
 }
 
+impl AbsBlockPos {
+    /// Wraps the position of a node.
+    #[must_use]
+    pub fn new(pos: I16Vec3) -> Self {
+        Self { pos }
+    }
+
+    /// Returns the position of the node.
+    #[must_use]
+    pub fn vec(&self) -> I16Vec3 {
+        self.pos
+    }
+}
+
 // /// `BlockPos` addresses a node within a block
 // /// It is equivalent to (16*z + y)*16 + x, where x,y,z are from 0 to 15.
 // #[derive(Debug, Clone, PartialEq)]
@@ -1260,12 +1436,12 @@ pub struct StringVar {
     pub is_private: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct Inventory {
     pub entries: Vec<InventoryEntry>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum InventoryEntry {
     // Inventory lists to keep
     KeepList(String),
@@ -1302,6 +1478,8 @@ impl Serialize for Inventory {
 impl Deserialize for Inventory {
     type Output = Self;
     fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
+        let text = deser.peek_all();
+        let strict = deser.context().strict_inventory;
         let mut result = Self {
             entries: Vec::new(),
         };
@@ -1309,39 +1487,53 @@ impl Deserialize for Inventory {
             // Peek the line, but don't take it yet.
             let line = deser.peek_line()?;
             let words = split_by_whitespace(line);
-            if words.is_empty() {
-                deser.take_line()?;
+            if words.first().is_some_and(|name| *name == b"List") {
+                // InventoryList will take the line
+                result
+                    .entries
+                    .push(InventoryEntry::Update(deserialize_inventory_list(
+                        text, deser,
+                    )?));
                 continue;
             }
-            let name = words[0];
-            if name == b"EndInventory" || name == b"End" {
+            let end = parse_inventory_line(text, deser, |deser| {
                 // Take the line
                 deser.take_line()?;
-                return Ok(result);
-            } else if name == b"List" {
-                // InventoryList will take the line
-                result
-                    .entries
-                    .push(InventoryEntry::Update(InventoryList::deserialize(deser)?));
-            } else if name == b"KeepList" {
-                if words.len() < 2 {
-                    bail!(DeserializeError::InvalidValue(
-                        "KeepList missing name".into(),
-                    ));
-                }
-                match std::str::from_utf8(words[1]) {
-                    Ok(str) => result.entries.push(InventoryEntry::KeepList(str.into())),
-                    Err(_) => {
+                let Some(&name) = words.first() else {
+                    if strict {
+                        bail!(DeserializeError::InvalidValue("Empty line".into()));
+                    }
+                    return Ok(false);
+                };
+                if name == b"EndInventory" || name == b"End" {
+                    return Ok(true);
+                } else if name == b"KeepList" {
+                    if words.len() < 2 {
                         bail!(DeserializeError::InvalidValue(
-                            "KeepList name is invalid UTF8".into(),
-                        ))
+                            "KeepList missing name".into(),
+                        ));
+                    }
+                    if strict && words.len() > 2 {
+                        bail!(DeserializeError::InvalidValue(
+                            "KeepList with trailing words".into(),
+                        ));
+                    }
+                    match std::str::from_utf8(words[1]) {
+                        Ok(str) => result.entries.push(InventoryEntry::KeepList(str.into())),
+                        Err(_) => {
+                            bail!(DeserializeError::InvalidValue(
+                                "KeepList name is invalid UTF8".into(),
+                            ))
+                        }
                     }
+                } else if strict {
+                    bail!(DeserializeError::InvalidValue("Unknown line".into()));
                 }
-                // Take the line
-                deser.take_line()?;
-            } else {
                 // Anything else is supposed to be ignored. Gross.
-                deser.take_line()?;
+                Ok(false)
+            })?;
+            if end {
+                return Ok(result);
             }
         }
         // If we ran out before seeing the end marker, it's an error
@@ -1349,14 +1541,25 @@ impl Deserialize for Inventory {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Parses a single line (or, for items, the lines being taken by the closure) of the inventory
+/// text format. Failures are annotated with a [`LineError`] numbering the line within `text`.
+fn parse_inventory_line<'data, R>(
+    text: &[u8],
+    deser: &mut Deserializer<'data>,
+    parse: impl FnOnce(&mut Deserializer<'data>) -> DeserializeResult<R>,
+) -> DeserializeResult<R> {
+    let offset = text.len().saturating_sub(deser.remaining());
+    parse(deser).map_err(|source| LineError::new(text, offset, source).into())
+}
+
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct InventoryList {
     pub name: String,
     pub width: u32,
     pub items: Vec<ItemStackUpdate>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum ItemStackUpdate {
     Empty,
     Keep, // this seems to not be used yet
@@ -1396,31 +1599,48 @@ impl Serialize for InventoryList {
 impl Deserialize for InventoryList {
     type Output = Self;
     fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
-        // First line should be: List <name> <item_count>
+        let text = deser.peek_all();
+        deserialize_inventory_list(text, deser)
+    }
+}
+
+/// Parses an [`InventoryList`] being part of `text`, which the lines are numbered by.
+fn deserialize_inventory_list(
+    text: &[u8],
+    deser: &mut Deserializer<'_>,
+) -> DeserializeResult<InventoryList> {
+    let strict = deser.context().strict_inventory;
+    // First line should be: List <name> <item_count>
+    let (mut result, count) = parse_inventory_line(text, deser, |deser| {
         let line = deser.take_line()?;
         let words = split_by_whitespace(line);
         if words.len() != 3 || words[0] != b"List" {
             bail!(DeserializeError::InvalidValue("Broken List tag".into(),));
         }
         let list_name = std::str::from_utf8(words[1])?;
-        let _count: u32 = stoi(words[2])?;
-        let mut result = Self {
+        let count: usize = stoi(words[2])?;
+        let list = InventoryList {
             name: list_name.into(),
             width: 0,
             items: Vec::new(),
         };
-        while deser.has_remaining() {
+        Ok((list, count))
+    })?;
+    while deser.has_remaining() {
+        let end = parse_inventory_line(text, deser, |deser| {
             // Peek the line, but don't take it yet.
             let peeked_line = deser.peek_line()?;
             let peeked_words = split_by_whitespace(peeked_line);
-            if peeked_words.is_empty() {
+            let Some(&name) = peeked_words.first() else {
+                if strict {
+                    bail!(DeserializeError::InvalidValue("Empty line".into()));
+                }
                 deser.take_line()?;
-                continue;
-            }
-            let name = peeked_words[0];
+                return Ok(false);
+            };
             if name == b"EndInventoryList" || name == b"end" {
                 deser.take_line()?;
-                return Ok(result);
+                return Ok(true);
             } else if name == b"Width" {
                 if peeked_words.len() < 2 {
                     bail!(DeserializeError::InvalidValue("Width value missing".into(),));
@@ -1438,19 +1658,31 @@ impl Deserialize for InventoryList {
             } else if name == b"Keep" {
                 result.items.push(ItemStackUpdate::Keep);
                 deser.take_line()?;
+            } else if strict {
+                bail!(DeserializeError::InvalidValue("Unknown line".into()));
             } else {
                 // Ignore unrecognized lines
                 deser.take_line()?;
             }
+            // Luanti rejects these as well but they've always been accepted here
+            if strict && result.items.len() > count {
+                bail!(DeserializeError::InvalidValue(format!(
+                    "More than {count} items"
+                )));
+            }
+            Ok(false)
+        })?;
+        if end {
+            return Ok(result);
         }
-        bail!(DeserializeError::Eof(
-            "InventoryList::deserialize(_)".into()
-        ))
     }
+    bail!(DeserializeError::Eof(
+        "InventoryList::deserialize(_)".into()
+    ))
 }
 
 // Custom deserialization, part of Inventory
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct ItemStack {
     pub name: String,
     pub count: u16,
@@ -1519,7 +1751,7 @@ impl Deserialize for ItemStack {
                 result.wear = stoi(wear_str)?;
                 let line = skip_whitespace(line);
                 if !line.is_empty() {
-                    let mut tmp_deser = Deserializer::new(deser.context(), line);
+                    let mut tmp_deser = deser.nested(line);
                     result.metadata = ItemStackMetadata::deserialize(&mut tmp_deser)?;
                 }
             }
@@ -1529,7 +1761,7 @@ impl Deserialize for ItemStack {
 }
 
 // Custom deserialization as json blob
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default, ToJson)]
 pub struct ItemStackMetadata {
     pub string_vars: Vec<(ByteString, ByteString)>,
 }
@@ -1633,7 +1865,7 @@ pub struct AutoExposure {
     pub center_weight_power: f32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum HudSetParam {
     SetHotBarItemCount(i32),
     SetHotBarImage(String),
@@ -1684,7 +1916,7 @@ impl Deserialize for HudSetParam {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 #[expect(
     clippy::struct_excessive_bools,
     reason = "// TODO rewrite using a crate for flags or bit-fields"
@@ -1763,7 +1995,7 @@ pub enum InteractAction {
     Activate,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 #[expect(variant_size_differences, reason = "all variants are small enough")]
 pub enum PointedThing {
     Nothing,
@@ -1828,7 +2060,7 @@ impl Deserialize for PointedThing {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum InventoryAction {
     Move {
         count: u16,
@@ -1949,7 +2181,7 @@ impl Deserialize for InventoryAction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub enum InventoryLocation {
     Undefined,
     CurrentPlayer,
@@ -2013,3 +2245,331 @@ impl Deserialize for InventoryLocation {
         }
     }
 }
+
+/// The server's reply to a mod channel request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, LuantiSerialize, LuantiDeserialize)]
+pub enum ModChannelSignal {
+    JoinOk,
+    JoinFailure,
+    LeaveOk,
+    LeaveFailure,
+    /// a message has been sent to a channel nobody joined
+    ChannelNotRegistered,
+    /// the state of the channel has been changed
+    SetState,
+}
+
+/// What a client may do with a mod channel it joined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, LuantiSerialize, LuantiDeserialize)]
+pub enum ModChannelState {
+    /// the join request hasn't been answered yet
+    Init,
+    ReadWrite,
+    ReadOnly,
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, FaceDir, LIQUID_LEVEL_SOURCE, MapNode, WallMounted};
+    use proptest::collection::vec;
+    use proptest::prelude::{Just, Strategy, any, prop_assert, prop_assert_eq, prop_oneof};
+    use proptest::sample::Index;
+    use proptest::{prop_assume, proptest};
+
+    use super::{
+        AlphaMode, ContentFeatures, Inventory, InventoryEntry, InventoryList, ItemStack,
+        ItemStackMetadata, ItemStackUpdate, LiquidType, MapNodesBulk, NodeDefManager, ParamType2,
+    };
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, DeserializeResult, Deserializer, line_error};
+    use crate::wire::packet::LATEST_PROTOCOL_VERSION;
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn map_nodes_bulk_round_trip() {
+        let nodes = MapNodesBulk {
+            nodes: std::array::from_fn(|index| {
+                let [low, high] = u16::try_from(index).unwrap().to_le_bytes();
+                MapNode {
+                    content_id: ContentId(u16::from_le_bytes([high, low])),
+                    param1: low,
+                    param2: high,
+                }
+            }),
+        };
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 0);
+        MapNodesBulk::serialize(&nodes, &mut ser).unwrap();
+        let data = ser.take();
+        assert_eq!(data.len(), 4 * nodes.nodes.len());
+        // the first node's content id is followed by the second one's
+        assert_eq!(data[..4], [0x00, 0x00, 0x01, 0x00]);
+
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        let deserialized = MapNodesBulk::deserialize(&mut deser).unwrap();
+        assert_eq!(deserialized.nodes, nodes.nodes);
+    }
+
+    #[test]
+    fn node_definitions_are_downgraded_for_old_clients() {
+        let node_def = NodeDefManager {
+            content_features: vec![(
+                0,
+                ContentFeatures {
+                    alpha_for_legacy: 128,
+                    move_resistance: 3,
+                    ..ContentFeatures::new_unknown("default:stone".into())
+                },
+            )],
+        };
+        let round_trip = |protocol_version| {
+            let context = ProtocolContext {
+                protocol_version,
+                ..ProtocolContext::latest_for_send(false)
+            };
+            let mut ser = VecSerializer::new(context, 0);
+            NodeDefManager::serialize(&node_def, &mut ser).unwrap();
+            let data = ser.take();
+            let mut deser = Deserializer::new(context, &data);
+            NodeDefManager::deserialize(&mut deser).unwrap()
+        };
+
+        assert_eq!(round_trip(LATEST_PROTOCOL_VERSION), node_def);
+
+        let (_, features) = &round_trip(38).content_features[0];
+        assert_eq!(features.alpha, AlphaMode::LegacyCompat);
+        assert_eq!(features.alpha_for_legacy, 255);
+        assert_eq!(features.move_resistance, 0);
+        assert_eq!(features.name, "default:stone");
+    }
+
+    fn param2_node(param2: u8) -> MapNode {
+        MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2,
+        }
+    }
+
+    #[test]
+    fn param2_is_interpreted_by_type() {
+        let features = |param_type_2| ContentFeatures {
+            param_type_2,
+            ..ContentFeatures::new_unknown("test:node".into())
+        };
+
+        let colored_facedir = features(ParamType2::ColoredFaceDir);
+        let facedir = colored_facedir
+            .facedir(&param2_node(0x43))
+            .unwrap_or_default();
+        assert_eq!(facedir.facing(), I16Vec3::NEG_X);
+        assert_eq!(colored_facedir.color_index(&param2_node(0x43)), Some(2));
+        assert_eq!(colored_facedir.wallmounted(&param2_node(0x43)), None);
+
+        let dir4 = features(ParamType2::Dir4);
+        assert_eq!(
+            dir4.facedir(&param2_node(5)).map(FaceDir::top),
+            Some(I16Vec3::Y)
+        );
+
+        let wallmounted = features(ParamType2::ColoredWallMounted);
+        assert_eq!(
+            wallmounted
+                .wallmounted(&param2_node(0x1B))
+                .map(WallMounted::dir),
+            Some(I16Vec3::NEG_X)
+        );
+        assert_eq!(wallmounted.color_index(&param2_node(0x1B)), Some(3));
+        assert_eq!(
+            features(ParamType2::Full).color_index(&param2_node(7)),
+            None
+        );
+
+        assert_eq!(
+            features(ParamType2::DegRotate).rotation_degrees(&param2_node(60)),
+            Some(90.0)
+        );
+    }
+
+    #[test]
+    fn levels_follow_luanti() {
+        let source = ContentFeatures {
+            liquid_type: LiquidType::Source,
+            ..ContentFeatures::new_unknown("test:water_source".into())
+        };
+        assert_eq!(source.level(&param2_node(0)), LIQUID_LEVEL_SOURCE);
+
+        let flowing = ContentFeatures {
+            liquid_type: LiquidType::Flowing,
+            param_type_2: ParamType2::FlowingLiquid,
+            ..ContentFeatures::new_unknown("test:water_flowing".into())
+        };
+        assert_eq!(flowing.level(&param2_node(0x0D)), 5);
+
+        let leveled = ContentFeatures {
+            param_type_2: ParamType2::Leveled,
+            leveled: 16,
+            ..ContentFeatures::new_unknown("test:snow".into())
+        };
+        assert_eq!(leveled.level(&param2_node(0x85)), 5);
+        assert_eq!(leveled.level(&param2_node(0)), 16);
+    }
+
+    /// An inventory as being sent by Luanti
+    const CAPTURED_INVENTORY: &str = r#"List main 4
+Width 0
+Item default:pick_steel 1 21845
+Empty
+Item default:dirt 99
+Item default:book_written 1 0 "\u0001title\u0002Notes\u0003"
+EndInventoryList
+KeepList craft
+EndInventory
+"#;
+
+    /// The first words of the lines of the inventory text format
+    const KEYWORDS: [&str; 10] = [
+        "List",
+        "Width",
+        "Item",
+        "Empty",
+        "Keep",
+        "KeepList",
+        "EndInventoryList",
+        "EndInventory",
+        "End",
+        "end",
+    ];
+
+    fn parse_inventory(text: &[u8], strict: bool) -> DeserializeResult<Inventory> {
+        let context = ProtocolContext::latest_for_receive(true).with_strict_inventory(strict);
+        Inventory::deserialize(&mut Deserializer::new(context, text))
+    }
+
+    fn serialize_inventory(inventory: &Inventory) -> Vec<u8> {
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 64);
+        Inventory::serialize(inventory, &mut ser).unwrap();
+        ser.take()
+    }
+
+    fn item_stack() -> impl Strategy<Value = ItemStackUpdate> {
+        let fields = vec(("[a-z_]{1,8}", "[ -~]{1,12}"), 0..3);
+        let item = ("[a-z]{1,8}:[a-z_]{1,8}", 1..=u16::MAX, any::<u16>(), fields).prop_map(
+            |(name, count, wear, fields)| {
+                let mut metadata = ItemStackMetadata::new();
+                for (key, value) in fields {
+                    metadata.set_string(&key, &value);
+                }
+                ItemStackUpdate::Item(ItemStack {
+                    name,
+                    count,
+                    wear,
+                    metadata,
+                })
+            },
+        );
+        prop_oneof![
+            Just(ItemStackUpdate::Empty),
+            Just(ItemStackUpdate::Keep),
+            item
+        ]
+    }
+
+    fn inventory() -> impl Strategy<Value = Inventory> {
+        let list =
+            ("[a-z]{1,8}", 0..8_u32, vec(item_stack(), 0..6)).prop_map(|(name, width, items)| {
+                InventoryEntry::Update(InventoryList { name, width, items })
+            });
+        let keep = "[a-z]{1,8}".prop_map(InventoryEntry::KeepList);
+        vec(prop_oneof![list, keep], 0..4).prop_map(|entries| Inventory { entries })
+    }
+
+    #[test]
+    fn captured_inventories_are_parsed_strictly() {
+        let inventory = parse_inventory(CAPTURED_INVENTORY.as_bytes(), true).unwrap();
+        let [InventoryEntry::Update(list), InventoryEntry::KeepList(kept)] =
+            inventory.entries.as_slice()
+        else {
+            panic!("unexpected entries: {inventory:?}");
+        };
+        assert_eq!(list.items.len(), 4);
+        assert_eq!(kept, "craft");
+        let ItemStackUpdate::Item(book) = &list.items[3] else {
+            panic!("unexpected item: {:?}", list.items[3]);
+        };
+        assert_eq!(book.metadata.get_string("title"), "Notes");
+        assert_eq!(
+            serialize_inventory(&inventory),
+            CAPTURED_INVENTORY.as_bytes()
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let broken = CAPTURED_INVENTORY.replace("Item default:dirt 99", "Item default:dirt lots");
+        for strict in [false, true] {
+            let error = parse_inventory(broken.as_bytes(), strict).unwrap_err();
+            let line = line_error(&error).unwrap();
+            assert_eq!(line.line, 5);
+            assert_eq!(line.content, "Item default:dirt lots");
+        }
+
+        let junk = CAPTURED_INVENTORY.replace("Empty\n", "Empty\nJunk\n");
+        parse_inventory(junk.as_bytes(), false).unwrap();
+        let junk_error = parse_inventory(junk.as_bytes(), true).unwrap_err();
+        assert_eq!(line_error(&junk_error).unwrap().line, 5);
+
+        let surplus = CAPTURED_INVENTORY.replace("Empty\n", "Empty\nEmpty\n");
+        parse_inventory(surplus.as_bytes(), false).unwrap();
+        let surplus_error = parse_inventory(surplus.as_bytes(), true).unwrap_err();
+        assert_eq!(line_error(&surplus_error).unwrap().line, 7);
+    }
+
+    proptest! {
+        #[test]
+        fn inventories_round_trip(inventory in inventory()) {
+            let text = serialize_inventory(&inventory);
+            let parsed = parse_inventory(&text, true).unwrap();
+            prop_assert_eq!(serialize_inventory(&parsed), text);
+            prop_assert_eq!(parsed, inventory);
+        }
+
+        #[test]
+        fn junk_lines_are_rejected_when_strict(
+            inventory in inventory(),
+            junk in "[A-Za-z]{1,8}( [a-z0-9]{1,4})?",
+            position in any::<Index>(),
+        ) {
+            let first_word = junk.split(' ').next().unwrap_or_default();
+            prop_assume!(!KEYWORDS.contains(&first_word));
+            let original = String::from_utf8(serialize_inventory(&inventory)).unwrap();
+            let mut lines: Vec<&str> = original.lines().collect();
+            // anywhere but after the final `EndInventory`
+            let line = position.index(lines.len());
+            lines.insert(line, &junk);
+            let text = lines.join("\n");
+
+            prop_assert_eq!(parse_inventory(text.as_bytes(), false).unwrap(), inventory);
+            let error = parse_inventory(text.as_bytes(), true).unwrap_err();
+            let error = line_error(&error).unwrap();
+            prop_assert_eq!(error.line, line + 1);
+            prop_assert_eq!(&error.content, &junk);
+        }
+
+        #[test]
+        fn garbage_is_rejected_gracefully(
+            lines in vec(
+                "(List|Width|Item|Empty|Keep|KeepList|EndInventoryList|EndInventory|[a-z]{0,4})( [a-z0-9:\"\\\\]{0,6}){0,4}",
+                0..12,
+            ),
+            strict in any::<bool>(),
+        ) {
+            let text = lines.join("\n");
+            if let Ok(inventory) = parse_inventory(text.as_bytes(), strict) {
+                // whatever has been accepted has to survive a round trip
+                let reparsed = parse_inventory(&serialize_inventory(&inventory), true);
+                prop_assert!(reparsed.is_ok(), "{inventory:?}");
+            }
+        }
+    }
+}
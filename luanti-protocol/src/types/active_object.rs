@@ -1,11 +1,18 @@
 use super::{Array8, Array16, Pair, SColor, Wrapped32, aabb3f};
 use crate::wire::{
     deser::{Deserialize, DeserializeResult, Deserializer},
+    packet::{
+        BONE_OVERRIDE_PROTOCOL_VERSION, DAMAGE_TEXTURE_PROTOCOL_VERSION,
+        NODE_VISUAL_PROTOCOL_VERSION, OBJECT_SHADING_PROTOCOL_VERSION,
+        ROTATE_SELECTIONBOX_PROTOCOL_VERSION,
+    },
     ser::{Serialize, SerializeResult, Serializer},
 };
 use anyhow::bail;
 use glam::{I16Vec2, Vec2, Vec3};
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_core::{ContentId, MapNode};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
+use std::time::Duration;
 
 /// This corresponds to `GenericCAO::Initialize` in Luanti
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -22,7 +29,7 @@ pub struct GenericInitData {
 }
 
 // TODO(paradust): Handle this in derive macros
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToJson)]
 #[expect(clippy::large_enum_variant, reason = "consider `Box`ing variants")]
 pub enum ActiveObjectCommand {
     SetProperties(AOCSetProperties),
@@ -150,6 +157,20 @@ pub struct AOCSetProperties {
     pub newprops: ObjectProperties,
 }
 
+/// The properties of an active object.
+///
+/// Fields which have been added to the protocol later on are only being sent to clients which
+/// support them; they get their default value when being received from an older peer. Start
+/// from [`ObjectProperties::default`], which matches the defaults of Luanti, and only change what
+/// differs:
+///
+/// ```
+/// # use luanti_protocol::types::ObjectProperties;
+/// let props = ObjectProperties::default()
+///     .with_visual("mesh")
+///     .with_mesh("character.b3d")
+///     .with_textures(["character.png"]);
+/// ```
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 #[expect(clippy::struct_excessive_bools, reason = "this is mandated by the API")]
 pub struct ObjectProperties {
@@ -187,11 +208,173 @@ pub struct ObjectProperties {
     pub eye_height: f32,
     pub zoom_fov: f32,
     pub use_texture_alpha: bool,
-    pub damage_texture_modifier: Option<String>,
-    pub shaded: Option<bool>,
-    pub show_on_minimap: Option<bool>,
+    #[default(DEFAULT_DAMAGE_TEXTURE_MODIFIER.into())]
+    #[since(DAMAGE_TEXTURE_PROTOCOL_VERSION)]
+    pub damage_texture_modifier: String,
+    #[default(true)]
+    #[since(OBJECT_SHADING_PROTOCOL_VERSION)]
+    pub shaded: bool,
+    #[default]
+    #[since(OBJECT_SHADING_PROTOCOL_VERSION)]
+    pub show_on_minimap: bool,
+    /// `None` uses the default background of the client
+    #[wrap(NametagBackground)]
+    #[default]
+    #[since(OBJECT_SHADING_PROTOCOL_VERSION)]
     pub nametag_bgcolor: Option<SColor>,
-    pub rotate_selectionbox: Option<bool>,
+    #[default]
+    #[since(ROTATE_SELECTIONBOX_PROTOCOL_VERSION)]
+    pub rotate_selectionbox: bool,
+    /// the node being shown by the `node` visual
+    #[default(NO_NODE)]
+    #[since(NODE_VISUAL_PROTOCOL_VERSION)]
+    pub node: MapNode,
+}
+
+/// The texture modifier being applied to objects which have been punched, by default
+pub const DEFAULT_DAMAGE_TEXTURE_MODIFIER: &str = "^[brighten";
+
+/// The node of objects which don't use the `node` visual
+const NO_NODE: MapNode = MapNode {
+    content_id: ContentId::IGNORE,
+    param1: 0,
+    param2: 0,
+};
+
+impl Default for ObjectProperties {
+    /// Returns the properties of a new object, compare to Luanti, `object_properties.cpp`,
+    /// `ObjectProperties::ObjectProperties`.
+    fn default() -> Self {
+        Self {
+            version: 4,
+            hp_max: 1,
+            physical: false,
+            _unused: 0,
+            collision_box: aabb3f::NODE,
+            selection_box: aabb3f::NODE,
+            pointable: true,
+            visual: "sprite".into(),
+            visual_size: Vec3::ONE,
+            textures: vec!["no_texture.png".into()],
+            spritediv: I16Vec2::ONE,
+            initial_sprite_basepos: I16Vec2::ZERO,
+            is_visible: true,
+            makes_footstep_sound: false,
+            automatic_rotate: 0.0,
+            mesh: String::new(),
+            colors: vec![SColor::WHITE],
+            collide_with_objects: true,
+            stepheight: 0.0,
+            automatic_face_movement_dir: false,
+            automatic_face_movement_dir_offset: 0.0,
+            backface_culling: true,
+            nametag: String::new(),
+            nametag_color: SColor::WHITE,
+            automatic_face_movement_max_rotation_per_sec: -1.0,
+            infotext: String::new(),
+            wield_item: String::new(),
+            glow: 0,
+            breath_max: 0,
+            eye_height: 1.625,
+            zoom_fov: 0.0,
+            use_texture_alpha: false,
+            damage_texture_modifier: DEFAULT_DAMAGE_TEXTURE_MODIFIER.into(),
+            shaded: true,
+            show_on_minimap: false,
+            nametag_bgcolor: None,
+            rotate_selectionbox: false,
+            node: NO_NODE,
+        }
+    }
+}
+
+impl ObjectProperties {
+    /// Sets how the object is being drawn, e.g. `"sprite"`, `"cube"`, `"mesh"` or `"node"`.
+    #[must_use]
+    pub fn with_visual(mut self, visual: impl Into<String>) -> Self {
+        self.visual = visual.into();
+        self
+    }
+
+    /// Sets the model of the `mesh` visual.
+    #[must_use]
+    pub fn with_mesh(mut self, mesh: impl Into<String>) -> Self {
+        self.mesh = mesh.into();
+        self
+    }
+
+    /// Sets the textures; their meaning depends on the visual.
+    #[must_use]
+    pub fn with_textures<T: Into<String>>(mut self, textures: impl IntoIterator<Item = T>) -> Self {
+        self.textures = textures.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the node shown by the `node` visual.
+    #[must_use]
+    pub fn with_node(mut self, node: MapNode) -> Self {
+        self.node = node;
+        self
+    }
+
+    /// Sets the scale of the visual.
+    #[must_use]
+    pub fn with_visual_size(mut self, visual_size: Vec3) -> Self {
+        self.visual_size = visual_size;
+        self
+    }
+
+    /// Sets the box used for collisions as well as the one for pointing at the object.
+    #[must_use]
+    pub fn with_box(mut self, min_edge: Vec3, max_edge: Vec3) -> Self {
+        self.collision_box = aabb3f::new(min_edge, max_edge);
+        self.selection_box = self.collision_box;
+        self
+    }
+
+    /// Sets the health an object is being spawned with.
+    #[must_use]
+    pub fn with_hp_max(mut self, hp_max: u16) -> Self {
+        self.hp_max = hp_max;
+        self
+    }
+
+    /// Sets the text shown above the object along with its color.
+    #[must_use]
+    pub fn with_nametag(mut self, nametag: impl Into<String>, color: SColor) -> Self {
+        self.nametag = nametag.into();
+        self.nametag_color = color;
+        self
+    }
+}
+
+/// The special color Luanti uses to tell the absence of a nametag background apart from a
+/// fully transparent one
+const NULL_BGCOLOR: SColor = SColor::new(0, 1, 1, 1);
+
+/// Serializes an optional nametag background the way Luanti does
+#[derive(Debug)]
+struct NametagBackground;
+
+impl Serialize for NametagBackground {
+    type Input = Option<SColor>;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        let color = match value {
+            None => NULL_BGCOLOR,
+            // a fully transparent color must not be mistaken for the special value
+            Some(color) if color.alpha() == 0 => SColor::TRANSPARENT,
+            Some(color) => *color,
+        };
+        SColor::serialize(&color, ser)
+    }
+}
+
+impl Deserialize for NametagBackground {
+    type Output = Option<SColor>;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self::Output> {
+        let color = SColor::deserialize(deser)?;
+        Ok((color != NULL_BGCOLOR).then_some(color))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
@@ -241,22 +424,198 @@ pub struct AOCSetAnimationSpeed {
     pub speed: f32,
 }
 
+/// Overrides the transformation of a bone of an object's model.
+///
+/// Older clients only support absolute positions and rotations without interpolation; they
+/// receive the `position` and `rotation` only.
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AOCSetBonePosition {
     pub bone: String,
     pub position: Vec3,
+    /// Euler angles in degrees
     pub rotation: Vec3,
+    #[default(Vec3::ONE)]
+    #[since(BONE_OVERRIDE_PROTOCOL_VERSION)]
+    pub scale: Vec3,
+    /// seconds to blend from the previous position to the new one
+    #[default]
+    #[since(BONE_OVERRIDE_PROTOCOL_VERSION)]
+    pub position_interpolation: f32,
+    /// seconds to blend from the previous rotation to the new one
+    #[default]
+    #[since(BONE_OVERRIDE_PROTOCOL_VERSION)]
+    pub rotation_interpolation: f32,
+    /// seconds to blend from the previous scale to the new one
+    #[default]
+    #[since(BONE_OVERRIDE_PROTOCOL_VERSION)]
+    pub scale_interpolation: f32,
+    /// the parts of the transformation which replace the animation instead of being added to it
+    #[default(BoneAbsolute::POSITION | BoneAbsolute::ROTATION)]
+    #[since(BONE_OVERRIDE_PROTOCOL_VERSION)]
+    pub absolute: BoneAbsolute,
+}
+
+impl AOCSetBonePosition {
+    /// Creates an override which keeps the bone as animated. Sending it removes a previous
+    /// override.
+    #[must_use]
+    pub fn new(bone: impl Into<String>) -> Self {
+        Self {
+            bone: bone.into(),
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+            position_interpolation: 0.0,
+            rotation_interpolation: 0.0,
+            scale_interpolation: 0.0,
+            absolute: BoneAbsolute::empty(),
+        }
+    }
+
+    /// Moves the bone; an `absolute` position replaces the animated one, otherwise it's being
+    /// added to it.
+    #[must_use]
+    pub fn with_position(mut self, position: Vec3, absolute: bool) -> Self {
+        self.position = position;
+        self.absolute.set(BoneAbsolute::POSITION, absolute);
+        self
+    }
+
+    /// Rotates the bone by the given Euler angles in degrees; an `absolute` rotation replaces the
+    /// animated one, otherwise it's being added to it.
+    #[must_use]
+    pub fn with_rotation(mut self, rotation: Vec3, absolute: bool) -> Self {
+        self.rotation = rotation;
+        self.absolute.set(BoneAbsolute::ROTATION, absolute);
+        self
+    }
+
+    /// Scales the bone; an `absolute` scale replaces the animated one, otherwise it's being
+    /// multiplied with it.
+    #[must_use]
+    pub fn with_scale(mut self, scale: Vec3, absolute: bool) -> Self {
+        self.scale = scale;
+        self.absolute.set(BoneAbsolute::SCALE, absolute);
+        self
+    }
+
+    /// Lets the client blend all parts of the transformation over the given time instead of
+    /// applying them at once.
+    #[must_use]
+    pub fn with_interpolation(mut self, duration: Duration) -> Self {
+        let seconds = duration.as_secs_f32();
+        self.position_interpolation = seconds;
+        self.rotation_interpolation = seconds;
+        self.scale_interpolation = seconds;
+        self
+    }
+}
+
+impl From<AOCSetBonePosition> for ActiveObjectCommand {
+    fn from(value: AOCSetBonePosition) -> Self {
+        Self::SetBonePosition(value)
+    }
 }
 
+bitflags::bitflags! {
+    /// The parts of an [`AOCSetBonePosition`] which replace the animation of the bone
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct BoneAbsolute: u8 {
+        const POSITION = 1 << 0;
+        const ROTATION = 1 << 1;
+        const SCALE = 1 << 2;
+    }
+}
+
+impl Serialize for BoneAbsolute {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        u8::serialize(&value.bits(), ser)
+    }
+}
+
+impl Deserialize for BoneAbsolute {
+    type Output = Self;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
+        u8::deserialize(deser).map(Self::from_bits_retain)
+    }
+}
+
+/// Attaches an object to another one, e.g. a player to the boat they're riding.
+///
+/// The attached object follows its parent and its own position updates are being ignored by the
+/// clients until it has been detached again.
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AOCAttachTo {
+    /// the id of the parent object or `0` to detach
     pub parent_id: i16,
+    /// the bone of the parent the object is being attached to; empty for the parent's origin
     pub bone: String,
+    /// the position relative to the bone, in nodes multiplied with 10
     pub position: Vec3,
+    /// Euler angles in degrees relative to the bone
     pub rotation: Vec3,
+    /// shows the object even in first person view of the parent
     pub force_visible: bool,
 }
 
+impl AOCAttachTo {
+    /// Attaches an object to the origin of the parent object with the given id.
+    #[must_use]
+    pub fn new(parent_id: u16) -> Self {
+        Self {
+            // the C++ engine uses a signed type but assigns ids up to `u16::MAX`
+            parent_id: i16::from_be_bytes(parent_id.to_be_bytes()),
+            bone: String::new(),
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            force_visible: false,
+        }
+    }
+
+    /// Detaches an object from its parent.
+    #[must_use]
+    pub fn detach() -> Self {
+        Self::new(0)
+    }
+
+    /// Returns the id of the parent object or `None` if this detaches the object.
+    #[must_use]
+    pub fn parent(&self) -> Option<u16> {
+        let parent_id = u16::from_be_bytes(self.parent_id.to_be_bytes());
+        (parent_id != 0).then_some(parent_id)
+    }
+
+    /// Attaches the object to a bone of the parent.
+    #[must_use]
+    pub fn with_bone(mut self, bone: impl Into<String>) -> Self {
+        self.bone = bone.into();
+        self
+    }
+
+    /// Places the object relative to the bone; the position is given in nodes.
+    #[must_use]
+    pub fn with_offset(mut self, position: Vec3, rotation: Vec3) -> Self {
+        // the client divides the position by `BS`
+        self.position = position * 10.0;
+        self.rotation = rotation;
+        self
+    }
+
+    /// Shows the object even in the first person view of the parent.
+    #[must_use]
+    pub fn with_force_visible(mut self) -> Self {
+        self.force_visible = true;
+        self
+    }
+}
+
+impl From<AOCAttachTo> for ActiveObjectCommand {
+    fn from(value: AOCAttachTo) -> Self {
+        Self::AttachTo(value)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AOCPunched {
     pub hp: u16,
@@ -277,3 +636,101 @@ pub struct AOCSpawnInfant {
 
 #[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct AOCObsolete1;
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use luanti_core::{ContentId, MapNode};
+
+    use std::time::Duration;
+
+    use glam::Vec3;
+
+    use super::{
+        AOCAttachTo, AOCSetBonePosition, ActiveObjectCommand, DEFAULT_DAMAGE_TEXTURE_MODIFIER,
+        ObjectProperties,
+    };
+    use crate::types::{ProtocolContext, SColor};
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::packet::LATEST_PROTOCOL_VERSION;
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    fn round_trip(props: &ObjectProperties, protocol_version: u16) -> ObjectProperties {
+        let context = ProtocolContext {
+            protocol_version,
+            ..ProtocolContext::latest_for_send(false)
+        };
+        let mut ser = VecSerializer::new(context, 0);
+        ObjectProperties::serialize(props, &mut ser).unwrap();
+        let data = ser.take();
+        let mut deser = Deserializer::new(context, &data);
+        ObjectProperties::deserialize(&mut deser).unwrap()
+    }
+
+    #[test]
+    fn bone_overrides_fall_back_to_absolute_transformations() {
+        let bone_override = AOCSetBonePosition::new("Head")
+            .with_rotation(Vec3::new(0.0, 45.0, 0.0), false)
+            .with_scale(Vec3::splat(2.0), true)
+            .with_interpolation(Duration::from_millis(500));
+        let command = ActiveObjectCommand::from(bone_override.clone());
+
+        let round_trip = |protocol_version| {
+            let context = ProtocolContext {
+                protocol_version,
+                ..ProtocolContext::latest_for_send(false)
+            };
+            let mut ser = VecSerializer::new(context, 0);
+            ActiveObjectCommand::serialize(&command, &mut ser).unwrap();
+            let data = ser.take();
+            let mut deser = Deserializer::new(context, &data);
+            ActiveObjectCommand::deserialize(&mut deser).unwrap()
+        };
+
+        assert_eq!(round_trip(LATEST_PROTOCOL_VERSION), command);
+        // older clients only know about absolute positions and rotations
+        let expected = AOCSetBonePosition::new("Head")
+            .with_position(Vec3::ZERO, true)
+            .with_rotation(Vec3::new(0.0, 45.0, 0.0), true);
+        assert_eq!(round_trip(43), ActiveObjectCommand::from(expected));
+    }
+
+    #[test]
+    fn attachments_use_unsigned_ids() {
+        let attach = AOCAttachTo::new(40000)
+            .with_bone("Body")
+            .with_offset(Vec3::new(0.0, 0.5, 0.0), Vec3::ZERO);
+        assert_eq!(attach.parent(), Some(40000));
+        assert_eq!(attach.position, Vec3::new(0.0, 5.0, 0.0));
+        assert_eq!(AOCAttachTo::detach().parent(), None);
+    }
+
+    #[test]
+    fn new_fields_are_only_sent_to_new_clients() {
+        let stone = MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2: 0,
+        };
+        let mut props = ObjectProperties::default()
+            .with_visual("node")
+            .with_node(stone);
+        props.shaded = false;
+        props.nametag_bgcolor = Some(SColor::new(0, 0, 0, 0));
+
+        assert_eq!(round_trip(&props, LATEST_PROTOCOL_VERSION), props);
+
+        let old = round_trip(&props, 37);
+        assert_eq!(old.visual, "node");
+        assert_eq!(old.damage_texture_modifier, DEFAULT_DAMAGE_TEXTURE_MODIFIER);
+        assert!(old.shaded);
+        assert_eq!(old.nametag_bgcolor, None);
+        assert_eq!(old.node.content_id, ContentId::IGNORE);
+
+        let without_node = round_trip(&props, 45);
+        assert!(!without_node.shaded);
+        assert_eq!(without_node.nametag_bgcolor, Some(SColor::new(0, 0, 0, 0)));
+        assert_eq!(without_node.node.content_id, ContentId::IGNORE);
+    }
+}
@@ -0,0 +1,449 @@
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use glam::{U8Vec3, U8Vec4, Vec4};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+
+use crate::wire::{
+    deser::{Deserialize, DeserializeResult, Deserializer},
+    ser::{Serialize, SerializeResult, Serializer},
+};
+
+/// A color with an alpha channel, corresponding to `video::SColor` in C++ land
+///
+/// The channels are stored in the order they're being sent: alpha, red, green and blue.
+///
+/// Colors can be parsed from and formatted as the `ColorString`s of Luanti's Lua API, e.g.
+/// `"#ff000080"`, `"#f00"` or `"red#80"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, LuantiSerialize, LuantiDeserialize)]
+pub struct SColor(pub U8Vec4);
+
+impl SColor {
+    pub const TRANSPARENT: Self = Self::new(0, 0, 0, 0);
+    pub const BLACK: Self = Self::new(255, 0, 0, 0);
+    pub const RED: Self = Self::new(255, 255, 0, 0);
+    pub const GREEN: Self = Self::new(255, 0, 255, 0);
+    pub const YELLOW: Self = Self::new(255, 255, 255, 0);
+    pub const BLUE: Self = Self::new(255, 0, 0, 255);
+    pub const MAGENTA: Self = Self::new(255, 255, 0, 255);
+    pub const CYAN: Self = Self::new(255, 0, 255, 255);
+    pub const WHITE: Self = Self::new(255, 255, 255, 255);
+
+    /// Creates a color from its channels in the order of the C++ constructor.
+    #[expect(
+        clippy::min_ident_chars,
+        reason = "those identifiers are well-known and clear from the context"
+    )]
+    #[must_use]
+    pub const fn new(a: u8, r: u8, g: u8, b: u8) -> Self {
+        Self(U8Vec4::new(a, r, g, b))
+    }
+
+    /// Creates a color from its channels in the order of a `ColorString`.
+    #[expect(
+        clippy::min_ident_chars,
+        reason = "those identifiers are well-known and clear from the context"
+    )]
+    #[must_use]
+    pub const fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self::new(a, r, g, b)
+    }
+
+    /// Creates a color from its packed `0xAARRGGBB` representation.
+    #[must_use]
+    pub const fn from_argb(argb: u32) -> Self {
+        let [alpha, red, green, blue] = argb.to_be_bytes();
+        Self::new(alpha, red, green, blue)
+    }
+
+    /// Returns the packed `0xAARRGGBB` representation; HUD elements take the lower 24 bits.
+    #[must_use]
+    pub const fn argb(self) -> u32 {
+        u32::from_be_bytes(self.0.to_array())
+    }
+
+    #[must_use]
+    pub const fn alpha(self) -> u8 {
+        self.0.x
+    }
+
+    #[must_use]
+    pub const fn red(self) -> u8 {
+        self.0.y
+    }
+
+    #[must_use]
+    pub const fn green(self) -> u8 {
+        self.0.z
+    }
+
+    #[must_use]
+    pub const fn blue(self) -> u8 {
+        self.0.w
+    }
+
+    #[must_use]
+    pub fn rgb(self) -> U8Vec3 {
+        U8Vec3::new(self.red(), self.green(), self.blue())
+    }
+
+    /// Returns this color with a different alpha channel.
+    #[must_use]
+    pub const fn with_alpha(self, alpha: u8) -> Self {
+        Self::new(alpha, self.red(), self.green(), self.blue())
+    }
+
+    /// Returns the channels in the order red, green, blue and alpha, ranging from `0.0` to `1.0`.
+    #[must_use]
+    pub fn to_rgba_f32(self) -> [f32; 4] {
+        self.rgba_vec().to_array()
+    }
+
+    /// Creates a color from channels in the order red, green, blue and alpha, ranging from `0.0`
+    /// to `1.0`. Values out of range are clamped.
+    #[must_use]
+    pub fn from_rgba_f32(rgba: [f32; 4]) -> Self {
+        Self::from_rgba_vec(Vec4::from_array(rgba))
+    }
+
+    /// Interpolates linearly between two colors, including their alpha channels; `progress`
+    /// ranges from `0.0` (`self`) to `1.0` (`other`).
+    #[must_use]
+    pub fn lerp(self, other: Self, progress: f32) -> Self {
+        Self::from_rgba_vec(self.rgba_vec().lerp(other.rgba_vec(), progress))
+    }
+
+    /// Draws this color over a background color, taking the alpha channels of both into account.
+    #[must_use]
+    pub fn blend_over(self, background: Self) -> Self {
+        let foreground = self.rgba_vec();
+        let background = background.rgba_vec();
+        let alpha = foreground.w + background.w * (1.0 - foreground.w);
+        if alpha <= 0.0 {
+            return Self::TRANSPARENT;
+        }
+        let rgb = (foreground.truncate() * foreground.w
+            + background.truncate() * background.w * (1.0 - foreground.w))
+            / alpha;
+        Self::from_rgba_vec(rgb.extend(alpha))
+    }
+
+    fn rgba_vec(self) -> Vec4 {
+        Vec4::new(
+            f32::from(self.red()),
+            f32::from(self.green()),
+            f32::from(self.blue()),
+            f32::from(self.alpha()),
+        ) / 255.0
+    }
+
+    fn from_rgba_vec(rgba: Vec4) -> Self {
+        let [red, green, blue, alpha] = (rgba.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+            .round()
+            .as_u8vec4()
+            .to_array();
+        Self::from_rgba(red, green, blue, alpha)
+    }
+}
+
+impl From<[f32; 4]> for SColor {
+    fn from(rgba: [f32; 4]) -> Self {
+        Self::from_rgba_f32(rgba)
+    }
+}
+
+impl From<SColor> for [f32; 4] {
+    fn from(color: SColor) -> Self {
+        color.to_rgba_f32()
+    }
+}
+
+/// A string which isn't a valid `ColorString`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid color {0:?}")]
+pub struct ParseColorError(pub String);
+
+impl FromStr for SColor {
+    type Err = ParseColorError;
+
+    /// Parses a `ColorString` the way Luanti does: `#RGB`, `#RGBA`, `#RRGGBB`, `#RRGGBBAA` or
+    /// one of the CSS color names (ignoring case) optionally followed by an alpha value like `#8`
+    /// or `#80`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || ParseColorError(value.to_owned());
+        let (name, hex) = value.split_once('#').unwrap_or((value, ""));
+        if name.is_empty() {
+            return match *parse_hex_channels(hex, 3..=4).ok_or_else(error)?.as_slice() {
+                [red, green, blue] => Ok(Self::from_rgba(red, green, blue, 0xff)),
+                [red, green, blue, alpha] => Ok(Self::from_rgba(red, green, blue, alpha)),
+                _ => Err(error()),
+            };
+        }
+
+        let name = name.to_ascii_lowercase();
+        let &(_, rgb) = NAMED_COLORS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(error)?;
+        let alpha = match *parse_hex_channels(hex, 0..=1).ok_or_else(error)?.as_slice() {
+            [] => 0xff,
+            [alpha] => alpha,
+            _ => return Err(error()),
+        };
+        Ok(Self::from_argb(rgb).with_alpha(alpha))
+    }
+}
+
+/// Parses the given number of channels of either one hex digit each, like `f` for `ff`, or two
+/// hex digits each.
+fn parse_hex_channels(hex: &str, channels: RangeInclusive<usize>) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|digit| {
+            digit
+                .to_digit(16)
+                .and_then(|value| u8::try_from(value).ok())
+        })
+        .collect::<Option<_>>()?;
+    if channels.contains(&digits.len()) {
+        Some(digits.iter().map(|digit| digit * 0x11).collect())
+    } else if digits.len() % 2 == 0 && channels.contains(&(digits.len() / 2)) {
+        Some(
+            digits
+                .chunks_exact(2)
+                .map(|pair| (pair[0] << 4) | pair[1])
+                .collect(),
+        )
+    } else {
+        None
+    }
+}
+
+impl Display for SColor {
+    /// Formats the color as `#RRGGBB` or, if it's translucent, as `#RRGGBBAA`.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "#{:02x}{:02x}{:02x}",
+            self.red(),
+            self.green(),
+            self.blue()
+        )?;
+        if self.alpha() != 0xff {
+            write!(formatter, "{:02x}", self.alpha())?;
+        }
+        Ok(())
+    }
+}
+
+/// The named colors Luanti understands, which are the ones of CSS
+#[expect(
+    clippy::unreadable_literal,
+    reason = "the colors are written the way CSS does"
+)]
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use super::SColor;
+    use crate::types::ProtocolContext;
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn color_strings_are_parsed_like_luanti() {
+        assert_eq!("#f00".parse(), Ok(SColor::RED));
+        assert_eq!("#0000ff".parse(), Ok(SColor::BLUE));
+        assert_eq!(
+            "#12345678".parse(),
+            Ok(SColor::from_rgba(0x12, 0x34, 0x56, 0x78))
+        );
+        assert_eq!("#fff8".parse(), Ok(SColor::WHITE.with_alpha(0x88)));
+        assert_eq!(
+            "CornflowerBlue#80".parse(),
+            Ok(SColor::from_argb(0x8064_95ed))
+        );
+        assert_eq!("yellow".parse(), Ok(SColor::YELLOW));
+        for invalid in ["", "#", "#12345", "#ggg", "nocolor", "red#123"] {
+            assert!(invalid.parse::<SColor>().is_err(), "{invalid}");
+        }
+
+        assert_eq!(SColor::from_argb(0xff64_95ed).to_string(), "#6495ed");
+        assert_eq!(SColor::new(0x80, 1, 2, 3).to_string(), "#01020380");
+
+        // colors are sent as `0xAARRGGBB`
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 4);
+        SColor::serialize(&SColor::from_rgba(1, 2, 3, 4), &mut ser).unwrap();
+        assert_eq!(ser.take(), [4, 1, 2, 3]);
+    }
+
+    #[test]
+    fn colors_are_blended() {
+        let translucent_red = SColor::RED.with_alpha(128);
+        assert_eq!(SColor::from(translucent_red.to_rgba_f32()), translucent_red);
+        assert_eq!(SColor::from([1.0, 0.0, 0.0, 2.0]), SColor::RED);
+        assert_eq!(
+            SColor::BLACK.lerp(SColor::WHITE, 0.5),
+            SColor::from_rgba(128, 128, 128, 255)
+        );
+        assert_eq!(
+            translucent_red.blend_over(SColor::BLUE),
+            SColor::from_rgba(128, 0, 127, 255)
+        );
+        assert_eq!(
+            SColor::TRANSPARENT.blend_over(SColor::TRANSPARENT),
+            SColor::TRANSPARENT
+        );
+    }
+}
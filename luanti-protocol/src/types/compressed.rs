@@ -1,12 +1,14 @@
 use std::marker::PhantomData;
+use std::time::Instant;
 
 use anyhow::bail;
 use log::trace;
 
 use crate::wire::{
+    compression,
     deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
     ser::{Serialize, SerializeError, SerializeResult, Serializer, VecSerializer},
-    util::{zstd_compress, zstd_decompress},
+    util::{zstd_compress_with_level, zstd_decompress},
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,11 +23,14 @@ impl<T: Serialize> Serialize for ZLibCompressed<T> {
         let mut tmp = VecSerializer::new(ser.context(), 1024);
         <T as Serialize>::serialize(value, &mut tmp)?;
         let tmp = tmp.take();
-        let tmp = miniz_oxide::deflate::compress_to_vec_zlib(&tmp, 6);
+        let started = Instant::now();
+        let level = ser.context().compression.zlib_level(tmp.len());
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&tmp, level);
+        compression::record::<T>(tmp.len(), compressed.len(), started.elapsed());
 
         // Write the size as a u32, followed by the data
-        u32::serialize(&u32::try_from(tmp.len())?, ser)?;
-        ser.write_bytes(&tmp)?;
+        u32::serialize(&u32::try_from(compressed.len())?, ser)?;
+        ser.write_bytes(&compressed)?;
         Ok(())
     }
 }
@@ -39,7 +44,7 @@ impl<T: Deserialize> Deserialize for ZLibCompressed<T> {
         // TODO(paradust): DANGEROUS. There is no decompression size bound.
         match miniz_oxide::inflate::decompress_to_vec_zlib(data) {
             Ok(decompressed) => {
-                let mut tmp = Deserializer::new(deser.context(), &decompressed);
+                let mut tmp = deser.nested(&decompressed);
                 Ok(<T as Deserialize>::deserialize(&mut tmp)?)
             }
             Err(err) => bail!(DeserializeError::DecompressionFailed(err.to_string())),
@@ -58,11 +63,18 @@ impl<T: Serialize> Serialize for ZStdCompressed<T> {
         let mut tmp = VecSerializer::new(ser.context(), 0x0001_0000);
         <T as Serialize>::serialize(value, &mut tmp)?;
         let tmp = tmp.take();
-        match zstd_compress(&tmp, |chunk| {
+        let started = Instant::now();
+        let level = ser.context().compression.zstd_level(tmp.len());
+        let mut compressed = 0;
+        match zstd_compress_with_level(&tmp, level, |chunk| {
+            compressed += chunk.len();
             ser.write_bytes(chunk)?;
             Ok(())
         }) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                compression::record::<T>(tmp.len(), compressed, started.elapsed());
+                Ok(())
+            }
             Err(err) => bail!(SerializeError::CompressionFailed(err.to_string())),
         }
     }
@@ -79,7 +91,7 @@ impl<T: Deserialize> Deserialize for ZStdCompressed<T> {
         }) {
             Ok(consumed) => {
                 deser.take(consumed)?;
-                let mut tmp_deser = Deserializer::new(deser.context(), &tmp);
+                let mut tmp_deser = deser.nested(&tmp);
                 Ok(<T as Deserialize>::deserialize(&mut tmp_deser)?)
             }
             Err(err) => bail!(DeserializeError::DecompressionFailed(err.to_string())),
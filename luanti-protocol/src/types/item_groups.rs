@@ -0,0 +1,97 @@
+use crate::wire::{
+    deser::{Deserialize, DeserializeResult, Deserializer},
+    ser::{Serialize, SerializeResult, Serializer},
+};
+use luanti_protocol_derive::ToJson;
+
+use super::{Array16, Pair};
+
+/// The groups of an item or node along with their ratings, like `cracky = 3`.
+///
+/// Groups which aren't listed have a rating of `0`, so [`ItemGroups::set`] drops them. The order
+/// of the entries is kept as-is to serialize them exactly as they have been received.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, ToJson)]
+pub struct ItemGroups(Vec<(String, i16)>);
+
+impl ItemGroups {
+    /// Creates an empty list of groups.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the rating of a group or `0` if the item isn't a member of it, just like Luanti's
+    /// `itemgroup_get`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> i16 {
+        self.0
+            .iter()
+            .find_map(|(group, rating)| (group == name).then_some(*rating))
+            .unwrap_or(0)
+    }
+
+    /// Sets the rating of a group; a rating of `0` removes the group.
+    pub fn set(&mut self, name: &str, rating: i16) {
+        if rating == 0 {
+            self.0.retain(|(group, _)| group != name);
+        } else if let Some((_, existing)) = self.0.iter_mut().find(|(group, _)| group == name) {
+            *existing = rating;
+        } else {
+            self.0.push((name.to_owned(), rating));
+        }
+    }
+
+    /// Returns `true` if the item isn't a member of any group.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns all groups along with their ratings.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i16)> {
+        self.0
+            .iter()
+            .map(|(group, rating)| (group.as_str(), *rating))
+    }
+}
+
+impl<Name: Into<String>> FromIterator<(Name, i16)> for ItemGroups {
+    fn from_iter<T: IntoIterator<Item = (Name, i16)>>(iter: T) -> Self {
+        let mut groups = Self::new();
+        for (name, rating) in iter {
+            groups.set(&name.into(), rating);
+        }
+        groups
+    }
+}
+
+impl Serialize for ItemGroups {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        <Array16<Pair<String, i16>> as Serialize>::serialize(&value.0, ser)
+    }
+}
+
+impl Deserialize for ItemGroups {
+    type Output = Self;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
+        // keep the groups verbatim, even those with a rating of `0`
+        <Array16<Pair<String, i16>> as Deserialize>::deserialize(deser).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ItemGroups;
+
+    #[test]
+    fn missing_groups_have_no_rating() {
+        let mut groups: ItemGroups = [("cracky", 3), ("stone", 1)].into_iter().collect();
+        assert_eq!(groups.get("cracky"), 3);
+        assert_eq!(groups.get("choppy"), 0);
+
+        groups.set("cracky", 2);
+        groups.set("stone", 0);
+        assert_eq!(groups.iter().collect::<Vec<_>>(), [("cracky", 2)]);
+    }
+}
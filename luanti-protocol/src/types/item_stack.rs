@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+use crate::commands::server_to_client::wear_per_use;
+
+use super::{ByteString, ItemStack, ItemStackMetadata};
+
+impl ItemStackMetadata {
+    /// overrides the description of the item definition
+    pub const DESCRIPTION: &str = "description";
+    /// overrides the short description of the item definition
+    pub const SHORT_DESCRIPTION: &str = "short_description";
+    /// a `ColorString` overriding the color of the item
+    pub const COLOR: &str = "color";
+    /// the index into the palette of the item definition
+    pub const PALETTE_INDEX: &str = "palette_index";
+    /// replaces the count shown in the inventory
+    pub const COUNT_META: &str = "count_meta";
+    /// the alignment of the count shown in the inventory
+    pub const COUNT_ALIGNMENT: &str = "count_alignment";
+    /// overrides the pointing range of the item definition
+    pub const RANGE: &str = "range";
+    /// JSON of the tool capabilities overriding those of the item definition
+    pub const TOOL_CAPABILITIES: &str = "tool_capabilities";
+    /// JSON of the wear bar params overriding those of the item definition
+    pub const WEAR_BAR_PARAMS: &str = "wear_color";
+
+    /// Creates metadata without any fields.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the raw value of a field.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&ByteString> {
+        self.string_vars
+            .iter()
+            .find_map(|(name, value)| (name.as_bytes() == key.as_bytes()).then_some(value))
+    }
+
+    /// Returns `true` if the field is present.
+    #[must_use]
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value of a field or an empty string if it's missing, just like Luanti's
+    /// `get_string`. Invalid UTF-8 is replaced.
+    #[must_use]
+    pub fn get_string(&self, key: &str) -> Cow<'_, str> {
+        self.get(key)
+            .map_or(Cow::Borrowed(""), |value| String::from_utf8_lossy(value))
+    }
+
+    /// Returns the value of a field as an integer or `0` if it's missing or not a number, just like
+    /// Luanti's `get_int`.
+    #[must_use]
+    pub fn get_int(&self, key: &str) -> i32 {
+        self.get_string(key).trim().parse().unwrap_or(0)
+    }
+
+    /// Returns the value of a field as a float or `0.0` if it's missing or not a number, just like
+    /// Luanti's `get_float`.
+    #[must_use]
+    pub fn get_float(&self, key: &str) -> f32 {
+        self.get_string(key).trim().parse().unwrap_or(0.0)
+    }
+
+    /// Sets the raw value of a field; an empty value removes the field. Returns `true` if the
+    /// metadata has been changed.
+    pub fn set(&mut self, key: &str, value: impl Into<ByteString>) -> bool {
+        let value = value.into();
+        if value.is_empty() {
+            return self.remove(key);
+        }
+        match self
+            .string_vars
+            .iter_mut()
+            .find(|(name, _)| name.as_bytes() == key.as_bytes())
+        {
+            Some((_, existing)) if *existing == value => false,
+            Some((_, existing)) => {
+                *existing = value;
+                true
+            }
+            None => {
+                self.string_vars.push((key.as_bytes().into(), value));
+                true
+            }
+        }
+    }
+
+    /// Sets the value of a field; an empty string removes the field. Returns `true` if the
+    /// metadata has been changed.
+    pub fn set_string(&mut self, key: &str, value: &str) -> bool {
+        self.set(key, value.as_bytes())
+    }
+
+    /// Sets the value of a field to an integer. Returns `true` if the metadata has been changed.
+    pub fn set_int(&mut self, key: &str, value: i32) -> bool {
+        self.set_string(key, &value.to_string())
+    }
+
+    /// Sets the value of a field to a float. Returns `true` if the metadata has been changed.
+    pub fn set_float(&mut self, key: &str, value: f32) -> bool {
+        self.set_string(key, &value.to_string())
+    }
+
+    /// Removes a field. Returns `true` if it has been present.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.string_vars.len();
+        self.string_vars
+            .retain(|(name, _)| name.as_bytes() != key.as_bytes());
+        self.string_vars.len() != len
+    }
+}
+
+impl ItemStack {
+    /// The wear at which a tool breaks
+    pub const MAX_WEAR: u16 = u16::MAX;
+
+    /// Returns `true` if the stack doesn't hold any items.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty() || self.count == 0
+    }
+
+    /// Removes all items along with their wear and metadata.
+    pub fn clear(&mut self) {
+        self.name.clear();
+        self.count = 0;
+        self.wear = 0;
+        self.metadata = ItemStackMetadata::new();
+    }
+
+    /// Adds wear to a tool; a negative amount repairs it. The stack is cleared if the wear exceeds
+    /// [`Self::MAX_WEAR`], just like Luanti's `add_wear`. Returns `true` if the tool broke.
+    ///
+    /// Whether the item is a tool at all depends on its definition and isn't checked here.
+    pub fn add_wear(&mut self, amount: i32) -> bool {
+        let wear = i32::from(self.wear) + amount;
+        match u16::try_from(wear) {
+            Ok(wear) => {
+                self.wear = wear;
+                false
+            }
+            Err(_) if wear < 0 => {
+                self.wear = 0;
+                false
+            }
+            Err(_) => {
+                self.clear();
+                true
+            }
+        }
+    }
+
+    /// Returns the wear a single use adds to a tool which breaks after the given number of uses,
+    /// just like Luanti's `get_tool_wear_after_use`. The wear is spread evenly, so the tool breaks
+    /// after exactly `uses` uses. Returns `0` for unlimited uses.
+    #[must_use]
+    pub fn wear_per_use(&self, uses: u32) -> u16 {
+        wear_per_use(uses, self.wear)
+    }
+
+    /// Adds the wear of a single use of a tool which breaks after the given number of uses.
+    /// Returns `true` if the tool broke.
+    pub fn add_wear_by_uses(&mut self, uses: u32) -> bool {
+        let wear = self.wear_per_use(uses);
+        self.add_wear(i32::from(wear))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use crate::types::{ItemStack, ItemStackMetadata, ProtocolContext};
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn metadata_fields_are_typed() {
+        let mut metadata = ItemStackMetadata::new();
+        assert!(metadata.set_string(ItemStackMetadata::DESCRIPTION, "Sharp"));
+        assert!(!metadata.set_string(ItemStackMetadata::DESCRIPTION, "Sharp"));
+        assert!(metadata.set_int(ItemStackMetadata::PALETTE_INDEX, 12));
+        assert!(metadata.set_float(ItemStackMetadata::RANGE, 2.5));
+        assert_eq!(metadata.get_string(ItemStackMetadata::DESCRIPTION), "Sharp");
+        assert_eq!(metadata.get_int(ItemStackMetadata::PALETTE_INDEX), 12);
+        assert!((metadata.get_float(ItemStackMetadata::RANGE) - 2.5).abs() < f32::EPSILON);
+        assert_eq!(metadata.get_int(ItemStackMetadata::DESCRIPTION), 0);
+        assert_eq!(metadata.get_string(ItemStackMetadata::COLOR), "");
+
+        // empty values remove a field
+        assert!(metadata.set_string(ItemStackMetadata::RANGE, ""));
+        assert!(!metadata.contains(ItemStackMetadata::RANGE));
+
+        let stack = ItemStack {
+            name: "default:pick_steel".into(),
+            count: 1,
+            wear: 0,
+            metadata,
+        };
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 32);
+        ItemStack::serialize(&stack, &mut ser).unwrap();
+        let data = ser.take();
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        let deserialized = ItemStack::deserialize(&mut deser).unwrap();
+        assert_eq!(
+            deserialized
+                .metadata
+                .get_string(ItemStackMetadata::DESCRIPTION),
+            "Sharp"
+        );
+        assert_eq!(deserialized, stack);
+    }
+
+    #[test]
+    fn tools_wear_out() {
+        let mut stack = ItemStack {
+            name: "default:pick_wood".into(),
+            count: 1,
+            wear: 0,
+            metadata: ItemStackMetadata::new(),
+        };
+        // 65536 isn't divisible by 3, so the last use adds one more point
+        assert_eq!(stack.wear_per_use(3), 21845);
+        assert!(!stack.add_wear_by_uses(3));
+        assert!(!stack.add_wear_by_uses(3));
+        assert_eq!(stack.wear_per_use(3), 21846);
+        assert!(stack.add_wear_by_uses(3));
+        assert!(stack.is_empty());
+
+        stack.name = "default:pick_wood".into();
+        stack.count = 1;
+        assert!(!stack.add_wear(100));
+        assert!(!stack.add_wear(-1000));
+        assert_eq!(stack.wear, 0);
+        assert_eq!(stack.wear_per_use(0), 0);
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::bail;
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
+
+use crate::wire::{
+    deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
+    ser::{Serialize, SerializeResult, Serializer},
+};
+
+/// The smallest possible size of a serialized [`MinimapMode`]: three `u16` and two empty strings
+const MIN_MODE_SIZE: usize = 10;
+
+/// What a [`MinimapMode`] shows, corresponding to `MinimapType` in C++ land
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum MinimapType {
+    /// the minimap is hidden
+    Off = Self::OFF,
+    /// the topmost nodes as seen from above
+    Surface = Self::SURFACE,
+    /// the density of the nodes around the player
+    Radar = Self::RADAR,
+    /// a texture provided by the server, e.g. a pre-rendered map
+    Texture = Self::TEXTURE,
+}
+
+impl MinimapType {
+    const OFF: u16 = 0;
+    const SURFACE: u16 = 1;
+    const RADAR: u16 = 2;
+    const TEXTURE: u16 = 3;
+}
+
+impl From<MinimapType> for u16 {
+    fn from(value: MinimapType) -> Self {
+        value as u16
+    }
+}
+
+impl TryFrom<u16> for MinimapType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Self::OFF => MinimapType::Off,
+            Self::SURFACE => MinimapType::Surface,
+            Self::RADAR => MinimapType::Radar,
+            Self::TEXTURE => MinimapType::Texture,
+            _ => bail!("Invalid MinimapType u16: {value}"),
+        })
+    }
+}
+
+/// A single mode the player can switch to, corresponding to an entry of `set_minimap_modes` in the
+/// Lua API
+#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+pub struct MinimapMode {
+    /// see [`MinimapType`]
+    pub typ: u16,
+    /// shown when switching to this mode; an empty label picks the client's default
+    pub label: String,
+    /// side length of the shown area in nodes
+    pub size: u16,
+    /// the texture of [`MinimapType::Texture`]
+    pub texture: String,
+    /// texture pixels per node of [`MinimapType::Texture`]
+    pub scale: u16,
+}
+
+impl MinimapMode {
+    /// Creates a mode hiding the minimap.
+    #[must_use]
+    pub fn off() -> Self {
+        Self::new(MinimapType::Off, 0)
+    }
+
+    /// Creates a mode showing the surface in an area of `size` nodes.
+    #[must_use]
+    pub fn surface(size: u16) -> Self {
+        Self::new(MinimapType::Surface, size)
+    }
+
+    /// Creates a mode showing the radar in an area of `size` nodes.
+    #[must_use]
+    pub fn radar(size: u16) -> Self {
+        Self::new(MinimapType::Radar, size)
+    }
+
+    /// Creates a mode showing a texture with `scale` pixels per node in an area of `size` nodes.
+    #[must_use]
+    pub fn texture(texture: impl Into<String>, size: u16, scale: u16) -> Self {
+        Self {
+            texture: texture.into(),
+            scale,
+            ..Self::new(MinimapType::Texture, size)
+        }
+    }
+
+    fn new(typ: MinimapType, size: u16) -> Self {
+        Self {
+            typ: typ.into(),
+            label: String::new(),
+            size,
+            texture: String::new(),
+            scale: 1,
+        }
+    }
+
+    /// Sets the label being shown when switching to this mode.
+    #[must_use]
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Returns the type of this mode or `None` if it's unknown to this crate.
+    #[must_use]
+    pub fn minimap_type(&self) -> Option<MinimapType> {
+        MinimapType::try_from(self.typ).ok()
+    }
+}
+
+/// The modes of the minimap along with the one being selected
+#[derive(Debug, Clone, PartialEq, ToJson)]
+pub struct MinimapModeList {
+    /// index of the selected mode within `vec`
+    pub mode: u16,
+    pub vec: Vec<MinimapMode>,
+}
+
+impl MinimapModeList {
+    #[must_use]
+    pub fn new(modes: Vec<MinimapMode>, selected: u16) -> Self {
+        Self {
+            mode: selected,
+            vec: modes,
+        }
+    }
+
+    /// Returns the selected mode; clients fall back to the first one if the index is out of range.
+    #[must_use]
+    pub fn selected(&self) -> Option<&MinimapMode> {
+        self.vec
+            .get(usize::from(self.mode))
+            .or_else(|| self.vec.first())
+    }
+
+    /// Checks that the list can be shown by a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first problem being found.
+    pub fn validate(&self) -> Result<(), InvalidMinimapModes> {
+        if self.vec.is_empty() {
+            return Err(InvalidMinimapModes::Empty);
+        }
+        if self.vec.len() > usize::from(u16::MAX) {
+            return Err(InvalidMinimapModes::TooMany(self.vec.len()));
+        }
+        if usize::from(self.mode) >= self.vec.len() {
+            return Err(InvalidMinimapModes::SelectedOutOfRange {
+                selected: self.mode,
+                count: self.vec.len(),
+            });
+        }
+        for (index, mode) in self.vec.iter().enumerate() {
+            match mode.minimap_type() {
+                None => {
+                    return Err(InvalidMinimapModes::UnknownType {
+                        index,
+                        typ: mode.typ,
+                    });
+                }
+                Some(MinimapType::Texture) if mode.texture.is_empty() => {
+                    return Err(InvalidMinimapModes::MissingTexture { index });
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`MinimapModeList`] which can't be shown by clients
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidMinimapModes {
+    /// clients require at least one mode
+    #[error("no minimap modes")]
+    Empty,
+    /// the number of modes doesn't fit into the length prefix
+    #[error("{0} minimap modes are too many")]
+    TooMany(usize),
+    /// the selected mode doesn't exist
+    #[error("selected minimap mode {selected} of {count} doesn't exist")]
+    SelectedOutOfRange { selected: u16, count: usize },
+    /// the type of a mode is unknown
+    #[error("minimap mode {index} has an unknown type {typ}")]
+    UnknownType { index: usize, typ: u16 },
+    /// a texture mode without a texture
+    #[error("minimap mode {index} has no texture")]
+    MissingTexture { index: usize },
+}
+
+impl Serialize for MinimapModeList {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        // The length of the list is a u16 which precedes `mode`,
+        // which makes the layout not fit into any usual pattern.
+        u16::serialize(&u16::try_from(value.vec.len())?, ser)?;
+        u16::serialize(&value.mode, ser)?;
+        for mode in &value.vec {
+            MinimapMode::serialize(mode, ser)?;
+        }
+        Ok(())
+    }
+}
+
+impl Deserialize for MinimapModeList {
+    type Output = Self;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
+        let count = u16::deserialize(deser)?;
+        let mode = u16::deserialize(deser)?;
+        // the count isn't followed by the list, so make sure it's plausible before allocating
+        if usize::from(count) * MIN_MODE_SIZE > deser.remaining() {
+            bail!(DeserializeError::InvalidValue(format!(
+                "{count} minimap modes don't fit into {} bytes",
+                deser.remaining()
+            )));
+        }
+        let mut vec: Vec<MinimapMode> = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            vec.push(MinimapMode::deserialize(deser)?);
+        }
+        Ok(MinimapModeList { mode, vec })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InvalidMinimapModes, MinimapMode, MinimapModeList, MinimapType};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn modes_are_validated_and_round_trip() {
+        let mut modes = MinimapModeList::new(
+            vec![
+                MinimapMode::off(),
+                MinimapMode::surface(256).with_label("Surface"),
+                MinimapMode::radar(128),
+                MinimapMode::texture("world_map.png", 512, 4),
+            ],
+            1,
+        );
+        modes.validate().unwrap();
+        assert_eq!(
+            modes.selected().unwrap().minimap_type(),
+            Some(MinimapType::Surface)
+        );
+
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(true), 64);
+        MinimapModeList::serialize(&modes, &mut ser).unwrap();
+        let mut data = ser.take();
+        // count, selected mode, type of the first mode
+        assert_eq!(data[..6], [0, 4, 0, 1, 0, 0]);
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(false), &data);
+        assert_eq!(MinimapModeList::deserialize(&mut deser).unwrap(), modes);
+
+        // a count which can't be satisfied by the remaining data
+        data[0] = 0x10;
+        let mut implausible = Deserializer::new(ProtocolContext::latest_for_receive(false), &data);
+        MinimapModeList::deserialize(&mut implausible).unwrap_err();
+
+        modes.mode = 4;
+        assert_eq!(
+            modes.validate(),
+            Err(InvalidMinimapModes::SelectedOutOfRange {
+                selected: 4,
+                count: 4
+            })
+        );
+        assert_eq!(modes.selected(), modes.vec.first());
+        modes.mode = 0;
+        modes.vec.push(MinimapMode::texture("", 64, 1));
+        assert_eq!(
+            modes.validate(),
+            Err(InvalidMinimapModes::MissingTexture { index: 4 })
+        );
+        assert_eq!(
+            MinimapModeList::new(Vec::new(), 0).validate(),
+            Err(InvalidMinimapModes::Empty)
+        );
+    }
+}
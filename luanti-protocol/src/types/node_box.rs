@@ -1,6 +1,6 @@
 use anyhow::bail;
 use glam::Vec3;
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
 use crate::wire::{
     deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
@@ -9,7 +9,15 @@ use crate::wire::{
 
 use super::Array16;
 
-#[derive(Debug, Clone, PartialEq)]
+/// The version of the node box format coming with `CONTENTFEATURES_VERSION` 13; Luanti rejects
+/// older ones
+const NODEBOX_VERSION: u8 = 6;
+
+/// The shape of a node, corresponding to `NodeBox` in C++ land
+///
+/// The boxes are given in nodes relative to the center of the node, so a full node spans from
+/// `-0.5` to `0.5` on each axis.
+#[derive(Debug, Clone, PartialEq, ToJson)]
 #[expect(
     clippy::large_enum_variant,
     reason = "// TODO consider `Box`ing variants"
@@ -25,8 +33,7 @@ pub enum NodeBox {
 impl Serialize for NodeBox {
     type Input = Self;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
-        // Unused version number, always 6
-        u8::serialize(&6, ser)?;
+        u8::serialize(&NODEBOX_VERSION, ser)?;
 
         let typ = match value {
             NodeBox::Regular => 0,
@@ -50,8 +57,10 @@ impl Deserialize for NodeBox {
     type Output = Self;
     fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
         let ver = u8::deserialize(deser)?;
-        if ver != 6 {
-            bail!(DeserializeError::InvalidValue("Invalid NodeBox ver".into(),));
+        if ver < NODEBOX_VERSION {
+            bail!(DeserializeError::InvalidValue(format!(
+                "Unsupported NodeBox version {ver}"
+            )));
         }
         let typ = u8::deserialize(deser)?;
         match typ {
@@ -69,20 +78,171 @@ impl Deserialize for NodeBox {
     }
 }
 
+impl NodeBox {
+    /// Creates a node consisting of the given boxes.
+    #[must_use]
+    pub fn fixed(boxes: Vec<aabb3f>) -> Self {
+        Self::Fixed(NodeBoxFixed { fixed: boxes })
+    }
+
+    /// Creates a node consisting of the given boxes, whose top is raised or lowered according to
+    /// `param2`.
+    #[must_use]
+    pub fn leveled(boxes: Vec<aabb3f>) -> Self {
+        Self::Leveled(NodeBoxLeveled { fixed: boxes })
+    }
+
+    /// Creates a node consisting of a single box which is being rotated according to the
+    /// wallmounted `param2`.
+    #[must_use]
+    pub fn wallmounted(wall_top: aabb3f, wall_bottom: aabb3f, wall_side: aabb3f) -> Self {
+        Self::Wallmounted(NodeBoxWallmounted {
+            wall_top,
+            wall_bottom,
+            wall_side,
+        })
+    }
+
+    /// Returns all boxes along with the name of the list they belong to.
+    pub fn boxes(&self) -> impl Iterator<Item = (&'static str, &aabb3f)> {
+        let lists: Vec<(&'static str, &[aabb3f])> = match self {
+            NodeBox::Regular => Vec::new(),
+            NodeBox::Fixed(NodeBoxFixed { fixed }) | NodeBox::Leveled(NodeBoxLeveled { fixed }) => {
+                vec![("fixed", fixed)]
+            }
+            NodeBox::Wallmounted(value) => vec![
+                ("wall_top", std::slice::from_ref(&value.wall_top)),
+                ("wall_bottom", std::slice::from_ref(&value.wall_bottom)),
+                ("wall_side", std::slice::from_ref(&value.wall_side)),
+            ],
+            NodeBox::Connected(value) => value.lists().collect(),
+        };
+        lists
+            .into_iter()
+            .flat_map(|(name, boxes)| boxes.iter().map(move |aabb| (name, aabb)))
+    }
+
+    fn boxes_mut(&mut self) -> Vec<&mut aabb3f> {
+        match self {
+            NodeBox::Regular => Vec::new(),
+            NodeBox::Fixed(NodeBoxFixed { fixed }) | NodeBox::Leveled(NodeBoxLeveled { fixed }) => {
+                fixed.iter_mut().collect()
+            }
+            NodeBox::Wallmounted(value) => vec![
+                &mut value.wall_top,
+                &mut value.wall_bottom,
+                &mut value.wall_side,
+            ],
+            NodeBox::Connected(value) => value.lists_mut().flatten().collect(),
+        }
+    }
+
+    /// Checks that every box has its minimum edge below its maximum edge and that no list has
+    /// more boxes than can be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first offending box.
+    pub fn validate(&self) -> Result<(), InvalidNodeBox> {
+        let mut counts: Vec<(&'static str, usize)> = Vec::new();
+        for (list, aabb) in self.boxes() {
+            let index = match counts.last_mut() {
+                Some((name, count)) if *name == list => {
+                    *count += 1;
+                    *count - 1
+                }
+                _ => {
+                    counts.push((list, 1));
+                    0
+                }
+            };
+            if index > usize::from(u16::MAX) {
+                return Err(InvalidNodeBox::TooManyBoxes { list });
+            }
+            if !aabb.is_valid() {
+                return Err(InvalidNodeBox::InvertedBox {
+                    list,
+                    index,
+                    aabb: *aabb,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Swaps the coordinates of boxes whose minimum edge is beyond their maximum edge, just like
+    /// Luanti does for node boxes defined by mods.
+    pub fn repair(&mut self) {
+        for aabb in self.boxes_mut() {
+            aabb.repair();
+        }
+    }
+}
+
+/// A [`NodeBox`] which would be misinterpreted by clients
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum InvalidNodeBox {
+    /// the minimum edge of a box is beyond its maximum edge on some axis
+    #[error("box {index} of `{list}` is inverted: {aabb:?}")]
+    InvertedBox {
+        list: &'static str,
+        index: usize,
+        aabb: aabb3f,
+    },
+    /// a list has more boxes than its length prefix can count
+    #[error("`{list}` has too many boxes")]
+    TooManyBoxes { list: &'static str },
+}
+
 #[allow(non_camel_case_types, reason = "aligns with the original C++ codebase")]
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, LuantiSerialize, LuantiDeserialize)]
 pub struct aabb3f {
     pub min_edge: Vec3,
     pub max_edge: Vec3,
 }
 
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+impl aabb3f {
+    /// The box of a full node
+    pub const NODE: Self = Self::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+
+    #[must_use]
+    pub const fn new(min_edge: Vec3, max_edge: Vec3) -> Self {
+        Self { min_edge, max_edge }
+    }
+
+    /// Creates a box from the `{x1, y1, z1, x2, y2, z2}` notation of Luanti's Lua API, repairing
+    /// it if necessary.
+    #[must_use]
+    pub fn from_array([x1, y1, z1, x2, y2, z2]: [f32; 6]) -> Self {
+        let mut aabb = Self::new(Vec3::new(x1, y1, z1), Vec3::new(x2, y2, z2));
+        aabb.repair();
+        aabb
+    }
+
+    /// Returns `true` if the minimum edge isn't beyond the maximum edge on any axis.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.min_edge.cmple(self.max_edge).all()
+    }
+
+    /// Swaps the coordinates of each axis where the minimum edge is beyond the maximum edge.
+    pub fn repair(&mut self) {
+        let (min_edge, max_edge) = (
+            self.min_edge.min(self.max_edge),
+            self.min_edge.max(self.max_edge),
+        );
+        self.min_edge = min_edge;
+        self.max_edge = max_edge;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, LuantiSerialize, LuantiDeserialize)]
 pub struct NodeBoxLeveled {
     #[wrap(Array16<aabb3f>)]
     pub fixed: Vec<aabb3f>,
 }
 
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+#[derive(Debug, Clone, PartialEq, Default, LuantiSerialize, LuantiDeserialize)]
 pub struct NodeBoxFixed {
     #[wrap(Array16<aabb3f>)]
     pub fixed: Vec<aabb3f>,
@@ -95,7 +255,11 @@ pub struct NodeBoxWallmounted {
     pub wall_side: aabb3f,
 }
 
-#[derive(Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+/// The boxes of a node which connects to its neighbors, like a fence
+///
+/// The boxes of a direction are shown if the node is connected (or not connected) to a neighbor
+/// in that direction; see [`ConnectDirection`].
+#[derive(Debug, Clone, PartialEq, Default, LuantiSerialize, LuantiDeserialize)]
 pub struct NodeBoxConnected {
     #[wrap(Array16<aabb3f>)]
     pub fixed: Vec<aabb3f>,
@@ -128,3 +292,193 @@ pub struct NodeBoxConnected {
     #[wrap(Array16<aabb3f>)]
     pub disconnected_sides: Vec<aabb3f>,
 }
+
+impl NodeBoxConnected {
+    /// Creates a node which consists of the given boxes as long as it isn't connected.
+    #[must_use]
+    pub fn new(fixed: Vec<aabb3f>) -> Self {
+        Self {
+            fixed,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a box being shown while the node is connected to a neighbor in the given direction.
+    #[must_use]
+    pub fn with_connected(mut self, direction: ConnectDirection, aabb: aabb3f) -> Self {
+        self.connected_mut(direction).push(aabb);
+        self
+    }
+
+    /// Adds a box being shown while the node isn't connected to a neighbor in the given direction.
+    #[must_use]
+    pub fn with_disconnected(mut self, direction: ConnectDirection, aabb: aabb3f) -> Self {
+        self.disconnected_mut(direction).push(aabb);
+        self
+    }
+
+    /// Returns the boxes being shown while the node is connected in the given direction.
+    pub fn connected_mut(&mut self, direction: ConnectDirection) -> &mut Vec<aabb3f> {
+        match direction {
+            ConnectDirection::Top => &mut self.connect_top,
+            ConnectDirection::Bottom => &mut self.connect_bottom,
+            ConnectDirection::Front => &mut self.connect_front,
+            ConnectDirection::Left => &mut self.connect_left,
+            ConnectDirection::Back => &mut self.connect_back,
+            ConnectDirection::Right => &mut self.connect_right,
+        }
+    }
+
+    /// Returns the boxes being shown while the node isn't connected in the given direction.
+    pub fn disconnected_mut(&mut self, direction: ConnectDirection) -> &mut Vec<aabb3f> {
+        match direction {
+            ConnectDirection::Top => &mut self.disconnected_top,
+            ConnectDirection::Bottom => &mut self.disconnected_bottom,
+            ConnectDirection::Front => &mut self.disconnected_front,
+            ConnectDirection::Left => &mut self.disconnected_left,
+            ConnectDirection::Back => &mut self.disconnected_back,
+            ConnectDirection::Right => &mut self.disconnected_right,
+        }
+    }
+
+    /// Returns all lists of boxes along with their names in the order of serialization.
+    fn lists(&self) -> impl Iterator<Item = (&'static str, &[aabb3f])> {
+        [
+            ("fixed", &self.fixed),
+            ("connect_top", &self.connect_top),
+            ("connect_bottom", &self.connect_bottom),
+            ("connect_front", &self.connect_front),
+            ("connect_left", &self.connect_left),
+            ("connect_back", &self.connect_back),
+            ("connect_right", &self.connect_right),
+            ("disconnected_top", &self.disconnected_top),
+            ("disconnected_bottom", &self.disconnected_bottom),
+            ("disconnected_front", &self.disconnected_front),
+            ("disconnected_left", &self.disconnected_left),
+            ("disconnected_back", &self.disconnected_back),
+            ("disconnected_right", &self.disconnected_right),
+            ("disconnected", &self.disconnected),
+            ("disconnected_sides", &self.disconnected_sides),
+        ]
+        .into_iter()
+        .map(|(name, boxes)| (name, boxes.as_slice()))
+    }
+
+    fn lists_mut(&mut self) -> impl Iterator<Item = &mut Vec<aabb3f>> {
+        [
+            &mut self.fixed,
+            &mut self.connect_top,
+            &mut self.connect_bottom,
+            &mut self.connect_front,
+            &mut self.connect_left,
+            &mut self.connect_back,
+            &mut self.connect_right,
+            &mut self.disconnected_top,
+            &mut self.disconnected_bottom,
+            &mut self.disconnected_front,
+            &mut self.disconnected_left,
+            &mut self.disconnected_back,
+            &mut self.disconnected_right,
+            &mut self.disconnected,
+            &mut self.disconnected_sides,
+        ]
+        .into_iter()
+    }
+}
+
+/// A direction a node can connect to its neighbors in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectDirection {
+    /// +Y
+    Top,
+    /// -Y
+    Bottom,
+    /// -Z
+    Front,
+    /// -X
+    Left,
+    /// +Z
+    Back,
+    /// +X
+    Right,
+}
+
+impl ConnectDirection {
+    /// All directions in the order of `connect_sides`
+    pub const ALL: [Self; 6] = [
+        Self::Top,
+        Self::Bottom,
+        Self::Front,
+        Self::Left,
+        Self::Back,
+        Self::Right,
+    ];
+
+    /// Returns the bit of this direction within `connect_sides` of
+    /// [`ContentFeatures`](super::ContentFeatures).
+    #[must_use]
+    pub const fn mask(self) -> u8 {
+        match self {
+            Self::Top => 1,
+            Self::Bottom => 2,
+            Self::Front => 4,
+            Self::Left => 8,
+            Self::Back => 16,
+            Self::Right => 32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::Vec3;
+
+    use super::{ConnectDirection, InvalidNodeBox, NodeBox, NodeBoxConnected, aabb3f};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn node_boxes_are_validated_and_repaired() {
+        let post = aabb3f::from_array([-0.125, -0.5, -0.125, 0.125, 0.5, 0.125]);
+        let rail = aabb3f::new(
+            Vec3::new(-0.0625, 0.25, 0.5),
+            Vec3::new(0.0625, 0.375, -0.5),
+        );
+        let mut fence = NodeBox::Connected(
+            NodeBoxConnected::new(vec![post])
+                .with_connected(ConnectDirection::Front, rail)
+                .with_connected(ConnectDirection::Back, rail),
+        );
+        assert_eq!(
+            fence.validate(),
+            Err(InvalidNodeBox::InvertedBox {
+                list: "connect_front",
+                index: 0,
+                aabb: rail,
+            })
+        );
+        fence.repair();
+        fence.validate().unwrap();
+        assert_eq!(fence.boxes().count(), 3);
+
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 64);
+        NodeBox::serialize(&fence, &mut ser).unwrap();
+        let mut data = ser.take();
+        assert_eq!(data[..2], [6, 4]);
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        assert_eq!(NodeBox::deserialize(&mut deser).unwrap(), fence);
+
+        // Luanti doesn't support older versions either
+        data[0] = 5;
+        let mut old = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        NodeBox::deserialize(&mut old).unwrap_err();
+
+        let mask = ConnectDirection::ALL
+            .iter()
+            .fold(0, |mask, direction| mask | direction.mask());
+        assert_eq!(mask, 0b11_1111);
+    }
+}
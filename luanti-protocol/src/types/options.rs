@@ -1,10 +1,15 @@
+use crate::schema::ToJson;
 use crate::wire::{
     deser::{Deserialize, DeserializeResult, Deserializer},
     ser::{Serialize, SerializeResult, Serializer, VecSerializer},
 };
+use luanti_protocol_derive::ToJson;
 
 /// Option is used for optional values at the end of a structure.
 /// Once Option is used, all following must be Option as well.
+///
+/// Fields which have a well-defined value if absent may use `#[default]` and
+/// `#[skip_serializing_if(predicate)]` of the derive macros instead.
 impl<T: Serialize> Serialize for Option<T>
 where
     <T as Serialize>::Input: Sized,
@@ -29,6 +34,40 @@ impl<T: Deserialize> Deserialize for Option<T> {
     }
 }
 
+/// The bytes following the last field known to this crate, e.g. fields added by a newer protocol
+/// version
+///
+/// Used as the last field of a command, the bytes are captured on deserialization and written back
+/// unchanged, so commands being passed on keep all their fields. Following fields with
+/// `#[skip_serializing_if(predicate)]` this needs `#[skip_serializing_if(TrailingBytes::is_empty)]`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, ToJson)]
+pub struct TrailingBytes(pub Vec<u8>);
+
+impl TrailingBytes {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for TrailingBytes {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        ser.write_bytes(&value.0)
+    }
+}
+
+impl Deserialize for TrailingBytes {
+    type Output = Self;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self::Output> {
+        let trailing = deser.take_all();
+        if !trailing.is_empty() {
+            log::debug!("capturing {} unknown trailing bytes", trailing.len());
+        }
+        Ok(Self(trailing.to_vec()))
+    }
+}
+
 // An Optional value controlled by a u16 size parameter.
 // Unlike Option, this can appear anywhere in the message.
 #[derive(Debug, Clone, PartialEq)]
@@ -69,3 +108,91 @@ impl<T: Deserialize> Deserialize for Option16<T> {
         }
     }
 }
+
+impl<T: ToJson> ToJson for Option16<T> {
+    fn write_json(&self, json: &mut String) {
+        match self {
+            Option16::None => json.push_str("null"),
+            Option16::Some(value) => value.write_json(json),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, DeserializeResult, Deserializer};
+    use crate::wire::ser::{Serialize, SerializeResult, Serializer, VecSerializer};
+
+    use super::TrailingBytes;
+
+    #[derive(Debug, PartialEq, LuantiSerialize, LuantiDeserialize)]
+    struct Trailing {
+        id: u8,
+        #[default]
+        #[skip_serializing_if(String::is_empty)]
+        description: String,
+        #[default(String::from("unnamed"))]
+        #[skip_serializing_if(is_unnamed)]
+        name: String,
+        #[skip_serializing_if(TrailingBytes::is_empty)]
+        extra: TrailingBytes,
+    }
+
+    fn is_unnamed(name: &str) -> bool {
+        name == "unnamed"
+    }
+
+    fn serialize(value: &Trailing) -> Vec<u8> {
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 16);
+        Trailing::serialize(value, &mut ser).unwrap();
+        ser.take()
+    }
+
+    fn deserialize(data: &[u8]) -> Trailing {
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), data);
+        Trailing::deserialize(&mut deser).unwrap()
+    }
+
+    #[test]
+    fn trailing_fields_with_default_values_are_omitted() {
+        let all_default = Trailing {
+            id: 7,
+            description: String::new(),
+            name: "unnamed".into(),
+            extra: TrailingBytes::default(),
+        };
+        assert_eq!(serialize(&all_default), [7]);
+        assert_eq!(deserialize(&[7]), all_default);
+
+        // the default description needs to be written for the name to be found
+        let named = Trailing {
+            id: 7,
+            description: String::new(),
+            name: "x".into(),
+            extra: TrailingBytes::default(),
+        };
+        assert_eq!(serialize(&named), [7, 0, 0, 0, 1, b'x']);
+        assert_eq!(deserialize(&serialize(&named)), named);
+
+        let described = Trailing {
+            id: 7,
+            description: "y".into(),
+            name: "unnamed".into(),
+            extra: TrailingBytes::default(),
+        };
+        assert_eq!(serialize(&described), [7, 0, 1, b'y']);
+        assert_eq!(deserialize(&serialize(&described)), described);
+    }
+
+    #[test]
+    fn unknown_trailing_bytes_survive_a_round_trip() {
+        let data = [7, 0, 1, b'y', 0, 1, b'x', 0xca, 0xfe];
+        let extended = deserialize(&data);
+        assert_eq!(extended.name, "x");
+        assert_eq!(extended.extra, TrailingBytes(vec![0xca, 0xfe]));
+        assert_eq!(serialize(&extended), data);
+    }
+}
@@ -0,0 +1,503 @@
+//! Predicts the changes of digging and placing nodes before the server confirms them
+//!
+//! Clients don't wait for the server to show the outcome of digging or placing a node. They
+//! replace the node right away as described by [`ContentFeatures::node_dig_prediction`] and
+//! [`ItemDef::node_placement_prediction`] and let the server correct them afterwards. The helpers
+//! of this module follow `Game::handleDigging` and `Game::nodePlacement` of the C++ client, so a
+//! mirror of the map shows exactly what a vanilla client shows.
+//!
+//! How long digging takes is predicted the same way, see [`NodeDefManager::dig_params`].
+
+use glam::I16Vec3;
+use luanti_core::{ContentId, MapNode};
+
+use crate::commands::server_to_client::{DigParams, ItemDef, ToolCapabilities};
+
+use super::{ContentFeatures, NodeDefManager, ParamType2};
+
+/// The name of the built-in node which players can walk through
+const AIR: &str = "air";
+
+/// The name of the node representing unloaded parts of the map
+const IGNORE: &str = "ignore";
+
+/// What a client shows after digging a node, see [`ContentFeatures::dig_prediction`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigPrediction<'def> {
+    /// the node stays until the server tells otherwise
+    None,
+    /// the node is removed
+    Air,
+    /// the node is replaced by the node with the given name
+    Node(&'def str),
+}
+
+/// What a client shows after placing an item, see [`NodeDefManager::predict_placement`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlacementPrediction {
+    /// the item doesn't predict a node; the client waits for the server
+    None,
+    /// the node can't be placed; clients play [`ItemDef::sound_place_failed`]
+    Failed,
+    /// the node is placed; clients play [`ItemDef::sound_place`]
+    Placed {
+        /// the position of the new node
+        pos: I16Vec3,
+        /// the new node
+        node: MapNode,
+    },
+}
+
+/// Where a player places an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacementSite {
+    /// the node the player points at
+    pub under: I16Vec3,
+    /// the node in front of the face the player points at
+    pub above: I16Vec3,
+    /// the node containing the feet of the player
+    pub player: I16Vec3,
+    /// the index into the palette of a colored node, i.e. the `palette_index` of the item's
+    /// metadata
+    pub palette_index: Option<u8>,
+    /// allows placing walkable nodes where the player stands, like the setting
+    /// `enable_build_where_you_stand` or flying through walls with `noclip`
+    pub build_where_you_stand: bool,
+}
+
+impl NodeDefManager {
+    /// Returns the definition of a node; `None` if there is none, like for the built-in `air`
+    /// and `ignore`.
+    #[must_use]
+    pub fn get(&self, content_id: ContentId) -> Option<&ContentFeatures> {
+        self.content_features
+            .iter()
+            .find_map(|(id, features)| (*id == content_id.0).then_some(features))
+    }
+
+    /// Returns the content id of a node by its name, including the built-in `air` and `ignore`.
+    #[must_use]
+    pub fn id(&self, name: &str) -> Option<ContentId> {
+        self.content_features
+            .iter()
+            .find_map(|(id, features)| (features.name == name).then_some(ContentId(*id)))
+            .or(match name {
+                AIR => Some(ContentId::AIR),
+                IGNORE => Some(ContentId::IGNORE),
+                _ => None,
+            })
+    }
+
+    /// Returns the node a client shows after digging the given node; `None` if the node stays
+    /// until the server tells otherwise.
+    ///
+    /// Predictions naming an unknown node are ignored, just like the C++ client does.
+    #[must_use]
+    pub fn predict_dig(&self, node: &MapNode) -> Option<MapNode> {
+        let content_id = match self.get(node.content_id)?.dig_prediction() {
+            DigPrediction::None => return None,
+            DigPrediction::Air => ContentId::AIR,
+            DigPrediction::Node(name) => self.id(name)?,
+        };
+        Some(MapNode {
+            content_id,
+            param1: 0,
+            param2: 0,
+        })
+    }
+
+    /// Returns how long it takes to dig the given node with a tool and how much this wears the
+    /// tool; `None` if the node can't be dug at all.
+    ///
+    /// `tool` are the capabilities of the wielded item and `hand` those of the hand. Like the C++
+    /// client, items without capabilities dig like the hand, and nodes the tool can't dig are
+    /// being dug by hand instead, which doesn't wear the tool.
+    #[must_use]
+    pub fn dig_params(
+        &self,
+        node: &MapNode,
+        tool: Option<&ToolCapabilities>,
+        hand: &ToolCapabilities,
+        wear: u16,
+    ) -> Option<DigParams> {
+        let groups = &self.get(node.content_id)?.groups;
+        tool.unwrap_or(hand).dig_params(groups, wear).or_else(|| {
+            let params = hand.dig_params(groups, 0)?;
+            Some(DigParams { wear: 0, ..params })
+        })
+    }
+
+    /// Returns the node a client shows after placing an item.
+    ///
+    /// `map` returns the nodes around the placement site; `None` for nodes which haven't been
+    /// loaded. Like the C++ client, the node replaces the pointed node if that one is
+    /// `buildable_to` and is oriented towards the player or the pointed face.
+    #[must_use]
+    pub fn predict_placement(
+        &self,
+        item: &ItemDef,
+        site: &PlacementSite,
+        map: impl Fn(I16Vec3) -> Option<MapNode>,
+    ) -> PlacementPrediction {
+        let Some(under) = map(site.under) else {
+            return PlacementPrediction::Failed;
+        };
+        if item.node_placement_prediction.is_empty() {
+            return PlacementPrediction::None;
+        }
+        let pos = if self.is_buildable_to(under.content_id) {
+            site.under
+        } else {
+            if map(site.above).is_some_and(|above| !self.is_buildable_to(above.content_id)) {
+                return PlacementPrediction::Failed;
+            }
+            site.above
+        };
+        let Some(content_id) = self.id(&item.node_placement_prediction) else {
+            return PlacementPrediction::Failed;
+        };
+        let mut node = MapNode {
+            content_id,
+            param1: 0,
+            param2: 0,
+        };
+        let Some(features) = self.get(content_id) else {
+            // only `air` and `ignore` lack a definition; neither can be attached or collide
+            return PlacementPrediction::Placed { pos, node };
+        };
+
+        if let Some(param2) = item.place_param2 {
+            node.param2 = param2;
+        } else if features.is_wallmounted() {
+            node.param2 = wallmounted_param2(site.under - site.above);
+        } else if features.is_facedir() {
+            node.param2 = facedir_param2(site.under - site.player);
+        }
+
+        let attached_to = match features.groups.get("attached_node") {
+            0 => None,
+            3 => Some(I16Vec3::NEG_Y),
+            4 => Some(I16Vec3::Y),
+            2 if features.is_facedir() => Some(node.facedir().facing()),
+            2 => Some(I16Vec3::ZERO),
+            _ if features.is_wallmounted() => Some(node.wallmounted().dir()),
+            _ => Some(I16Vec3::NEG_Y),
+        };
+        if let Some(offset) = attached_to {
+            if !map(pos + offset).is_some_and(|support| self.is_walkable(support.content_id)) {
+                return PlacementPrediction::Failed;
+            }
+        }
+
+        if item.place_param2.is_none() {
+            if let Some(index) = site.palette_index {
+                node.param2 = match features.param_type_2 {
+                    ParamType2::Color => index,
+                    ParamType2::ColoredWallMounted => (index & 0xF8) | (node.param2 & 0x07),
+                    ParamType2::ColoredFaceDir => (index & 0xE0) | (node.param2 & 0x1F),
+                    ParamType2::ColoredDir4 => (index & 0xFC) | (node.param2 & 0x03),
+                    _ => node.param2,
+                };
+            }
+        }
+
+        // the C++ client compares the pointed face instead of the final position
+        let inside_player = site.above == site.player || site.above == site.player + I16Vec3::Y;
+        if features.walkable && inside_player && !site.build_where_you_stand {
+            return PlacementPrediction::Failed;
+        }
+        PlacementPrediction::Placed { pos, node }
+    }
+
+    /// Returns `true` if placing a node replaces a node of the given kind.
+    fn is_buildable_to(&self, content_id: ContentId) -> bool {
+        self.get(content_id)
+            .map_or(content_id.is_air() || content_id.is_ignore(), |features| {
+                features.buildable_to
+            })
+    }
+
+    /// Returns `true` if players collide with a node of the given kind.
+    fn is_walkable(&self, content_id: ContentId) -> bool {
+        self.get(content_id).map_or(
+            !content_id.is_air() && !content_id.is_ignore(),
+            |features| features.walkable,
+        )
+    }
+}
+
+/// Returns the param2 of a wallmounted node attached in the given direction, like
+/// `core.dir_to_wallmounted`.
+fn wallmounted_param2(dir: I16Vec3) -> u8 {
+    let abs = dir.abs();
+    if abs.y > abs.x.max(abs.z) {
+        u8::from(dir.y < 0)
+    } else if abs.x > abs.z {
+        if dir.x < 0 { 3 } else { 2 }
+    } else if dir.z < 0 {
+        5
+    } else {
+        4
+    }
+}
+
+/// Returns the param2 of a facedir node facing away from a player looking in the given
+/// direction, like `core.dir_to_facedir`.
+fn facedir_param2(dir: I16Vec3) -> u8 {
+    if dir.x.abs() > dir.z.abs() {
+        if dir.x < 0 { 3 } else { 1 }
+    } else if dir.z < 0 {
+        2
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{I16Vec3, U8Vec4, Vec3};
+    use luanti_core::{ContentId, MapNode};
+
+    use crate::commands::server_to_client::{ItemDef, ItemType, ToolCapabilities, ToolGroupCap};
+    use crate::types::{
+        ContentFeatures, ItemGroups, NodeDefManager, Option16, ParamType2, RichText, SColor,
+        SoundSpec,
+    };
+
+    use super::{DigPrediction, PlacementPrediction, PlacementSite};
+
+    const STONE: ContentId = ContentId(1);
+    const TORCH: ContentId = ContentId(2);
+    const CHEST: ContentId = ContentId(3);
+    const GRASS: ContentId = ContentId(4);
+
+    fn node_def() -> NodeDefManager {
+        let mut stone = ContentFeatures::new_unknown("stone".into());
+        stone.groups = [("cracky", 3)].into_iter().collect();
+        let mut torch = ContentFeatures::new_unknown("torch".into());
+        torch.param_type_2 = ParamType2::WallMounted;
+        torch.walkable = false;
+        torch.groups.set("attached_node", 1);
+        torch.groups.set("dig_immediate", 3);
+        let mut chest = ContentFeatures::new_unknown("chest".into());
+        chest.param_type_2 = ParamType2::FaceDir;
+        chest.node_dig_prediction = String::new();
+        let mut grass = ContentFeatures::new_unknown("grass".into());
+        grass.buildable_to = true;
+        grass.walkable = false;
+        grass.node_dig_prediction = "stone".into();
+        grass.groups = [("crumbly", 3)].into_iter().collect();
+        NodeDefManager {
+            content_features: vec![
+                (STONE.0, stone),
+                (TORCH.0, torch),
+                (CHEST.0, chest),
+                (GRASS.0, grass),
+            ],
+        }
+    }
+
+    fn node(content_id: ContentId) -> MapNode {
+        MapNode {
+            content_id,
+            param1: 0,
+            param2: 0,
+        }
+    }
+
+    fn item(prediction: &str) -> ItemDef {
+        ItemDef {
+            version: 6,
+            item_type: ItemType::Node,
+            name: prediction.into(),
+            description: RichText::new(prediction),
+            inventory_image: String::new(),
+            wield_image: String::new(),
+            wield_scale: Vec3::ONE,
+            stack_max: 99,
+            usable: false,
+            liquids_pointable: false,
+            tool_capabilities: Option16::None,
+            groups: ItemGroups::new(),
+            node_placement_prediction: prediction.into(),
+            sound_place: SoundSpec::new_null(),
+            sound_place_failed: SoundSpec::new_null(),
+            range: 4.0,
+            palette_image: String::new(),
+            color: SColor(U8Vec4::MAX),
+            inventory_overlay: String::new(),
+            wield_overlay: String::new(),
+            short_description: None,
+            sound_use: None,
+            sound_use_air: None,
+            place_param2: None,
+        }
+    }
+
+    fn tool(group: &str, uses: i16, time: f32) -> ToolCapabilities {
+        ToolCapabilities {
+            version: 5,
+            full_punch_interval: 1.0,
+            max_drop_level: 0,
+            group_caps: vec![(
+                group.into(),
+                ToolGroupCap {
+                    uses,
+                    maxlevel: 1,
+                    times: vec![(3, time)],
+                },
+            )],
+            damage_groups: vec![],
+            punch_attack_uses: None,
+        }
+    }
+
+    /// A floor of stone below `y = 0` with a patch of grass at the origin and a pillar of stone
+    fn map(pos: I16Vec3) -> MapNode {
+        node(match pos.to_array() {
+            [0, 0, 0] => GRASS,
+            [3, _, 0] => STONE,
+            [_, y, _] if y < 0 => STONE,
+            _ => ContentId::AIR,
+        })
+    }
+
+    #[test]
+    fn dug_nodes_are_predicted() {
+        let node_def = node_def();
+        let stone = node_def.get(STONE).map(ContentFeatures::dig_prediction);
+        assert_eq!(stone, Some(DigPrediction::Air));
+        assert_eq!(
+            node_def.predict_dig(&node(STONE)),
+            Some(node(ContentId::AIR))
+        );
+        assert_eq!(node_def.predict_dig(&node(CHEST)), None);
+        assert_eq!(node_def.predict_dig(&node(GRASS)), Some(node(STONE)));
+        assert_eq!(node_def.predict_dig(&node(ContentId(99))), None);
+        assert_eq!(node_def.id("air"), Some(ContentId::AIR));
+        assert_eq!(node_def.id("dirt"), None);
+    }
+
+    #[test]
+    fn dig_times_are_predicted() {
+        let node_def = node_def();
+        let pickaxe = tool("cracky", 10, 0.5);
+        let hand = tool("crumbly", 0, 2.0);
+        let time = |node_id, tool| {
+            let params = node_def.dig_params(&node(node_id), tool, &hand, 0);
+            params.map(|params| (params.time, params.wear))
+        };
+
+        assert_eq!(time(STONE, Some(&pickaxe)), Some((0.5, 2184)));
+        assert_eq!(time(STONE, None), None);
+        // the pickaxe can't dig grass, so the hand digs it without wearing the pickaxe
+        assert_eq!(time(GRASS, Some(&pickaxe)), Some((2.0, 0)));
+        assert_eq!(time(TORCH, Some(&pickaxe)), Some((0.0, 0)));
+        assert_eq!(time(ContentId::AIR, Some(&pickaxe)), None);
+    }
+
+    #[test]
+    fn placed_nodes_are_predicted() {
+        let node_def = node_def();
+        let site = PlacementSite {
+            under: I16Vec3::new(2, -1, 0),
+            above: I16Vec3::new(2, 0, 0),
+            player: I16Vec3::new(0, 0, 5),
+            palette_index: None,
+            build_where_you_stand: false,
+        };
+        let placed = |item: &ItemDef, at: &PlacementSite| {
+            node_def.predict_placement(item, at, |pos| Some(map(pos)))
+        };
+
+        assert_eq!(placed(&item(""), &site), PlacementPrediction::None);
+        assert_eq!(placed(&item("dirt"), &site), PlacementPrediction::Failed);
+        assert_eq!(
+            placed(&item("stone"), &site),
+            PlacementPrediction::Placed {
+                pos: site.above,
+                node: node(STONE)
+            }
+        );
+
+        // the torch is put onto the floor
+        let torch = PlacementPrediction::Placed {
+            pos: site.above,
+            node: MapNode {
+                param2: 1,
+                ..node(TORCH)
+            },
+        };
+        assert_eq!(placed(&item("torch"), &site), torch);
+        // or onto the side of the pillar
+        let pillar = PlacementSite {
+            under: I16Vec3::new(3, 1, 0),
+            above: I16Vec3::new(2, 1, 0),
+            ..site
+        };
+        assert_eq!(
+            placed(&item("torch"), &pillar),
+            PlacementPrediction::Placed {
+                pos: pillar.above,
+                node: MapNode {
+                    param2: 2,
+                    ..node(TORCH)
+                }
+            }
+        );
+        // but not onto nodes which haven't been loaded
+        let unloaded = |pos: I16Vec3| (pos.x != 3).then(|| map(pos));
+        assert_eq!(
+            node_def.predict_placement(&item("torch"), &pillar, unloaded),
+            PlacementPrediction::Failed
+        );
+
+        // chests face the player and replace the grass
+        let grass = PlacementSite {
+            under: I16Vec3::ZERO,
+            above: I16Vec3::Y,
+            ..site
+        };
+        assert_eq!(
+            placed(&item("chest"), &grass),
+            PlacementPrediction::Placed {
+                pos: I16Vec3::ZERO,
+                node: MapNode {
+                    param2: 2,
+                    ..node(CHEST)
+                }
+            }
+        );
+        let mut fixed = item("chest");
+        fixed.place_param2 = Some(7);
+        assert_eq!(
+            placed(&fixed, &grass),
+            PlacementPrediction::Placed {
+                pos: I16Vec3::ZERO,
+                node: MapNode {
+                    param2: 7,
+                    ..node(CHEST)
+                }
+            }
+        );
+
+        // players don't place walkable nodes where they stand
+        let standing = PlacementSite {
+            player: site.above,
+            ..site
+        };
+        assert_eq!(
+            placed(&item("stone"), &standing),
+            PlacementPrediction::Failed
+        );
+        assert_eq!(placed(&item("torch"), &standing), torch);
+        let building = PlacementSite {
+            build_where_you_stand: true,
+            ..standing
+        };
+        assert!(matches!(
+            placed(&item("stone"), &building),
+            PlacementPrediction::Placed { .. }
+        ));
+    }
+}
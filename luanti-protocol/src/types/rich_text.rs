@@ -0,0 +1,297 @@
+use crate::wire::{
+    deser::{Deserialize, DeserializeResult, Deserializer},
+    ser::{Serialize, SerializeResult, Serializer},
+};
+use luanti_protocol_derive::ToJson;
+use std::fmt;
+
+/// Starts every escape sequence within a [`RichText`]
+pub const ESCAPE: char = '\x1b';
+
+/// Text which may contain Luanti's translation and color escape sequences.
+///
+/// The escape sequences are kept as-is, so the text is serialized exactly as it has been
+/// received. Use [`RichText::segments`] to inspect them or [`RichText::to_plain_text`] to get
+/// the untranslated text with all escape sequences removed.
+///
+/// The supported sequences are:
+/// - `ESC(T@domain)` or `ESC T`: start of a translatable string
+/// - `ESC F`: start of an argument of the enclosing translatable string
+/// - `ESC E`: end of the innermost translatable string or argument
+/// - `ESC(c@color)` and `ESC(b@color)`: text and background color
+#[derive(Clone, Default, PartialEq, Eq, Hash, ToJson)]
+pub struct RichText(String);
+
+impl RichText {
+    /// Wraps text which may already contain escape sequences.
+    #[must_use]
+    pub fn new(raw: impl Into<String>) -> Self {
+        Self(raw.into())
+    }
+
+    /// Creates a translatable string like Luanti's `core.translate(domain, text)`.
+    #[must_use]
+    pub fn translated(domain: &str, text: &str) -> Self {
+        Self(format!("{ESCAPE}(T@{domain}){text}{ESCAPE}E"))
+    }
+
+    /// Returns the text including all escape sequences.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    #[must_use]
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Splits the text into plain text and escape sequences.
+    #[must_use]
+    pub fn segments(&self) -> Segments<'_> {
+        Segments { rest: &self.0 }
+    }
+
+    /// Returns the untranslated text with the arguments of translatable strings filled in and
+    /// all other escape sequences removed.
+    #[must_use]
+    pub fn to_plain_text(&self) -> String {
+        let mut output = String::with_capacity(self.0.len());
+        render_until_end(&mut self.segments(), &mut output);
+        output
+    }
+}
+
+impl fmt::Debug for RichText {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, formatter)
+    }
+}
+
+impl AsRef<str> for RichText {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for RichText {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for RichText {
+    fn from(value: &str) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<RichText> for String {
+    fn from(value: RichText) -> Self {
+        value.0
+    }
+}
+
+impl Serialize for RichText {
+    type Input = Self;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        <str as Serialize>::serialize(&value.0, ser)
+    }
+}
+
+impl Deserialize for RichText {
+    type Output = Self;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self> {
+        String::deserialize(deser).map(Self)
+    }
+}
+
+/// A piece of a [`RichText`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'text> {
+    /// Plain text; within translatable strings it may contain placeholders like `@1`.
+    Text(&'text str),
+    /// Start of a translatable string, optionally naming its text domain
+    TranslationStart { domain: Option<&'text str> },
+    /// Start of an argument of the enclosing translatable string
+    ArgumentStart,
+    /// End of the innermost translatable string or argument
+    End,
+    /// Text color
+    Color(&'text str),
+    /// Background color
+    Background(&'text str),
+    /// Any other escape sequence, without the leading [`ESCAPE`]
+    Unknown(&'text str),
+}
+
+/// Iterator over the [`Segment`]s of a [`RichText`]
+#[derive(Debug, Clone)]
+pub struct Segments<'text> {
+    rest: &'text str,
+}
+
+impl<'text> Iterator for Segments<'text> {
+    type Item = Segment<'text>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let Some(escaped) = self.rest.strip_prefix(ESCAPE) else {
+            let end = self.rest.find(ESCAPE).unwrap_or(self.rest.len());
+            let (text, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            return Some(Segment::Text(text));
+        };
+
+        if let Some(enclosed) = escaped.strip_prefix('(') {
+            // `(code@argument)`; an unterminated sequence swallows the remaining text
+            let Some((sequence, rest)) = enclosed.split_once(')') else {
+                self.rest = "";
+                return Some(Segment::Unknown(escaped));
+            };
+            self.rest = rest;
+            let (code, argument) = sequence
+                .split_once('@')
+                .map_or((sequence, None), |(code, argument)| (code, Some(argument)));
+            return Some(match (code, argument) {
+                ("T", domain) => Segment::TranslationStart { domain },
+                ("c", Some(color)) => Segment::Color(color),
+                ("b", Some(color)) => Segment::Background(color),
+                _ => {
+                    let length = escaped.len() - rest.len();
+                    Segment::Unknown(escaped.split_at(length).0)
+                }
+            });
+        }
+
+        let code_length = escaped.chars().next().map_or(0, char::len_utf8);
+        let (code, rest) = escaped.split_at(code_length);
+        self.rest = rest;
+        Some(match code {
+            "T" => Segment::TranslationStart { domain: None },
+            "F" => Segment::ArgumentStart,
+            "E" => Segment::End,
+            _ => Segment::Unknown(code),
+        })
+    }
+}
+
+/// Renders all segments up to the end of the enclosing translatable string or argument.
+fn render_until_end(segments: &mut Segments<'_>, output: &mut String) {
+    while let Some(segment) = segments.next() {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::TranslationStart { .. } => render_translation(segments, output),
+            Segment::End => return,
+            // arguments outside of translatable strings and formatting don't contain any text
+            Segment::ArgumentStart
+            | Segment::Color(_)
+            | Segment::Background(_)
+            | Segment::Unknown(_) => {}
+        }
+    }
+}
+
+/// Renders a translatable string whose start has just been consumed.
+fn render_translation(segments: &mut Segments<'_>, output: &mut String) {
+    let mut template = String::new();
+    let mut arguments = Vec::new();
+    while let Some(segment) = segments.next() {
+        match segment {
+            Segment::Text(text) => template.push_str(text),
+            Segment::TranslationStart { .. } => render_translation(segments, &mut template),
+            Segment::ArgumentStart => {
+                let mut argument = String::new();
+                render_until_end(segments, &mut argument);
+                arguments.push(argument);
+            }
+            Segment::End => break,
+            Segment::Color(_) | Segment::Background(_) | Segment::Unknown(_) => {}
+        }
+    }
+
+    // `@1` to `@9` refer to the arguments, `@n` is a line break and `@` escapes everything else
+    let mut chars = template.chars();
+    while let Some(character) = chars.next() {
+        if character != '@' {
+            output.push(character);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some(digit @ '1'..='9') => {
+                let argument = digit
+                    .to_digit(10)
+                    .and_then(|number| usize::try_from(number).ok())
+                    .and_then(|number| arguments.get(number - 1));
+                if let Some(argument) = argument {
+                    output.push_str(argument);
+                } else {
+                    output.push('@');
+                    output.push(digit);
+                }
+            }
+            Some(other) => output.push(other),
+            None => output.push('@'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RichText, Segment};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn escape_sequences_are_parsed() {
+        let text = RichText::new(
+            "\x1b(c@#ff0000)\x1b(T@default)@1 placed @2\x1bFStone\x1bE\x1bF\x1bTa@@b\x1bE\x1bE\x1b(x@y)",
+        );
+        let segments: Vec<_> = text.segments().collect();
+        assert_eq!(
+            segments,
+            [
+                Segment::Color("#ff0000"),
+                Segment::TranslationStart {
+                    domain: Some("default")
+                },
+                Segment::Text("@1 placed @2"),
+                Segment::ArgumentStart,
+                Segment::Text("Stone"),
+                Segment::End,
+                Segment::ArgumentStart,
+                Segment::TranslationStart { domain: None },
+                Segment::Text("a@@b"),
+                Segment::End,
+                Segment::End,
+                Segment::Unknown("(x@y)"),
+            ]
+        );
+        assert_eq!(text.to_plain_text(), "Stone placed a@b");
+
+        assert_eq!(
+            RichText::translated("default", "Dirt").to_plain_text(),
+            "Dirt"
+        );
+        assert_eq!(
+            RichText::new("trailing\x1b").segments().last(),
+            Some(Segment::Unknown(""))
+        );
+    }
+
+    #[test]
+    fn raw_text_is_preserved() {
+        let text = RichText::new("\x1b(T@default)Stone\x1bE\x1b(c@#fff");
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 32);
+        RichText::serialize(&text, &mut ser).unwrap();
+        let data = ser.take();
+
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        assert_eq!(RichText::deserialize(&mut deser).unwrap(), text);
+        assert_eq!(text.segments().last(), Some(Segment::Unknown("(c@#fff")));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::schema::ToJson;
+use crate::schema::write_json_string;
 use crate::wire::{
     deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
     ser::{Serialize, SerializeResult, Serializer},
@@ -20,6 +22,16 @@ impl std::fmt::Debug for ByteString {
     }
 }
 
+/// Valid UTF-8 is rendered as-is, anything else like the `Debug` output.
+impl ToJson for ByteString {
+    fn write_json(&self, json: &mut String) {
+        match std::str::from_utf8(&self.0) {
+            Ok(text) => write_json_string(json, text),
+            Err(_) => write_json_string(json, &self.escape_ascii()),
+        }
+    }
+}
+
 impl ByteString {
     #[must_use]
     pub fn as_bytes(&self) -> &[u8] {
@@ -124,40 +136,126 @@ impl Deserialize for LongString {
     }
 }
 
-/// Corresponds to `std::wstring` in C++ land
+/// Corresponds to `std::wstring` in C++ land, which is being sent as UTF-16
+///
+/// The text is stored as `T`, e.g. `WString<RichText>` for text containing escape sequences.
+/// Characters beyond the Basic Multilingual Plane, like most emoji, are sent as surrogate pairs.
+/// Text containing unpaired surrogates is rejected; see [`LossyWString`] for text which should
+/// rather be mangled than dropped.
 #[derive(Debug, Clone, PartialEq)]
-pub struct WString(PhantomData<String>);
+pub struct WString<T = String>(PhantomData<T>);
 
-impl Serialize for WString {
-    type Input = String;
+impl<T: AsRef<str>> Serialize for WString<T> {
+    type Input = T;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
-        let enc: Vec<u16> = value.encode_utf16().collect();
-
-        u16::serialize(&u16::try_from(enc.len())?, ser)?;
-        // TODO: This could be made more efficient.
-        let mut buf: Vec<u8> = vec![0; 2 * enc.len()];
-        let mut index: usize = 0;
-        for codepoint in enc {
-            buf[index] = (codepoint >> 8) as u8;
-            buf[index + 1] = codepoint as u8;
-            index += 2;
-        }
-        ser.write_bytes(&buf)
+        serialize_utf16(value.as_ref(), false, ser)
     }
 }
 
-impl Deserialize for WString {
-    type Output = String;
+impl<T: From<String> + std::fmt::Debug> Deserialize for WString<T> {
+    type Output = T;
     fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self::Output> {
-        let length = u16::deserialize(deser)? as usize;
-        let raw = deser.take(2 * length)?;
-        let mut seq: Vec<u16> = vec![0; length];
-        for i in 0..length {
-            seq[i] = u16::from_be_bytes(raw[2 * i..2 * i + 2].try_into().unwrap());
-        }
-        match String::from_utf16(&seq) {
-            Ok(str) => Ok(str),
+        let units = deserialize_utf16(deser)?;
+        match String::from_utf16(&units) {
+            Ok(str) => Ok(str.into()),
             Err(err) => bail!(DeserializeError::InvalidValue(err.to_string())),
         }
     }
 }
+
+/// Like [`WString`], but unpaired surrogates are replaced by `U+FFFD` when receiving and text
+/// exceeding the length limit is truncated when sending.
+///
+/// Meant for chat messages, as clients which still encode them as UCS-2 may split surrogate pairs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossyWString<T = String>(PhantomData<T>);
+
+impl<T: AsRef<str>> Serialize for LossyWString<T> {
+    type Input = T;
+    fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
+        serialize_utf16(value.as_ref(), true, ser)
+    }
+}
+
+impl<T: From<String> + std::fmt::Debug> Deserialize for LossyWString<T> {
+    type Output = T;
+    fn deserialize(deser: &mut Deserializer<'_>) -> DeserializeResult<Self::Output> {
+        let units = deserialize_utf16(deser)?;
+        Ok(String::from_utf16_lossy(&units).into())
+    }
+}
+
+/// Writes the text as UTF-16 code units preceded by their number. Text exceeding [`u16::MAX`]
+/// code units is either rejected or, if `truncate` is set, cut off without splitting a surrogate
+/// pair.
+fn serialize_utf16<S: Serializer>(text: &str, truncate: bool, ser: &mut S) -> SerializeResult {
+    let mut units: Vec<u16> = text.encode_utf16().collect();
+    if truncate && units.len() > usize::from(u16::MAX) {
+        let mut len = usize::from(u16::MAX);
+        if (0xd800..0xdc00).contains(&units[len - 1]) {
+            // the last unit would be the first half of a surrogate pair
+            len -= 1;
+        }
+        units.truncate(len);
+    }
+    u16::serialize(&u16::try_from(units.len())?, ser)?;
+    let bytes: Vec<u8> = units.iter().flat_map(|unit| unit.to_be_bytes()).collect();
+    ser.write_bytes(&bytes)
+}
+
+/// Reads UTF-16 code units preceded by their number.
+fn deserialize_utf16(deser: &mut Deserializer<'_>) -> DeserializeResult<Vec<u16>> {
+    let length = usize::from(u16::deserialize(deser)?);
+    let raw = deser.take(2 * length)?;
+    Ok(raw
+        .chunks_exact(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use super::{LossyWString, WString};
+    use crate::types::ProtocolContext;
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    fn encode<W: Serialize<Input = String>>(text: &str) -> anyhow::Result<Vec<u8>> {
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 16);
+        W::serialize(&text.to_owned(), &mut ser)?;
+        Ok(ser.take())
+    }
+
+    fn decode<W: Deserialize<Output = String>>(data: &[u8]) -> anyhow::Result<String> {
+        W::deserialize(&mut Deserializer::new(
+            ProtocolContext::latest_for_receive(true),
+            data,
+        ))
+    }
+
+    #[test]
+    fn wide_strings_carry_surrogate_pairs() {
+        let text = "hi 🦀";
+        let data = encode::<WString>(text).unwrap();
+        assert_eq!(
+            data,
+            [0, 5, 0, b'h', 0, b'i', 0, b' ', 0xd8, 0x3e, 0xdd, 0x80]
+        );
+        assert_eq!(decode::<WString>(&data).unwrap(), text);
+
+        // the second half of the pair is missing
+        let broken = [0, 2, 0, b'!', 0xd8, 0x3e];
+        decode::<WString>(&broken).unwrap_err();
+        assert_eq!(decode::<LossyWString>(&broken).unwrap(), "!\u{fffd}");
+
+        let long = "🦀".repeat(40_000);
+        encode::<WString>(&long).unwrap_err();
+        let truncated = encode::<LossyWString>(&long).unwrap();
+        assert_eq!(
+            decode::<LossyWString>(&truncated).unwrap(),
+            "🦀".repeat(usize::from(u16::MAX) / 2)
+        );
+    }
+}
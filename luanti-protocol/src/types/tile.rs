@@ -1,13 +1,23 @@
+use std::time::Duration;
+
 use anyhow::bail;
-use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize};
+use glam::UVec2;
+use luanti_protocol_derive::{LuantiDeserialize, LuantiSerialize, ToJson};
 
+use super::SColor;
 use crate::wire::{
     deser::{Deserialize, DeserializeError, DeserializeResult, Deserializer},
     ser::{Serialize, SerializeResult, Serializer},
 };
 
-#[derive(Debug, Clone, PartialEq)]
+/// The version of the tile format coming with `CONTENTFEATURES_VERSION` 13; Luanti rejects older
+/// ones
+const TILEDEF_VERSION: u8 = 6;
+
+/// A single entry of the `tiles`, `overlay_tiles` or `special_tiles` of a node definition
+#[derive(Debug, Clone, PartialEq, ToJson)]
 pub struct TileDef {
+    /// the texture, which may be a texture modifier like `"default_dirt.png^default_grass.png"`
     pub name: String,
     pub animation: TileAnimationParams,
     // These are stored in a single u8 flags
@@ -15,7 +25,10 @@ pub struct TileDef {
     pub tileable_horizontal: bool,
     pub tileable_vertical: bool,
     // The flags also determine which of these is present
+    /// multiplies the color of the texture, overriding the `color` of the node
     pub color_rgb: Option<(u8, u8, u8)>,
+    /// the number of nodes a world-aligned texture spans; `0` picks the `world_aligned_mode` of
+    /// the client
     pub scale: u8,
     pub align_style: AlignStyle,
 }
@@ -41,6 +54,52 @@ impl TileDef {
     pub fn new_null() -> Self {
         Self::new(String::new())
     }
+
+    /// Sets the animation of the texture.
+    #[must_use]
+    pub fn with_animation(mut self, animation: TileAnimationParams) -> Self {
+        self.animation = animation;
+        self
+    }
+
+    /// Sets whether the back side of the tile is hidden.
+    #[must_use]
+    pub fn with_backface_culling(mut self, backface_culling: bool) -> Self {
+        self.backface_culling = backface_culling;
+        self
+    }
+
+    /// Sets whether the texture repeats in either direction; non-tileable textures are being
+    /// stretched across merged faces instead.
+    #[must_use]
+    pub fn with_tileable(mut self, horizontal: bool, vertical: bool) -> Self {
+        self.tileable_horizontal = horizontal;
+        self.tileable_vertical = vertical;
+        self
+    }
+
+    /// Sets the color the texture is multiplied with. The alpha channel is ignored.
+    #[must_use]
+    pub fn with_color(mut self, color: SColor) -> Self {
+        self.color_rgb = Some(color.rgb().into());
+        self
+    }
+
+    /// Aligns the texture to the world rather than to the node, spanning `scale` nodes.
+    #[must_use]
+    pub fn with_world_align(mut self, scale: u8) -> Self {
+        self.align_style = AlignStyle::World;
+        self.scale = scale;
+        self
+    }
+
+    /// Sets how the texture is being aligned along with the number of nodes it spans.
+    #[must_use]
+    pub fn with_align_style(mut self, align_style: AlignStyle, scale: u8) -> Self {
+        self.align_style = align_style;
+        self.scale = scale;
+        self
+    }
 }
 
 const TILE_FLAG_BACKFACE_CULLING: u16 = 1 << 0;
@@ -53,7 +112,7 @@ const TILE_FLAG_HAS_ALIGN_STYLE: u16 = 1 << 5;
 impl Serialize for TileDef {
     type Input = Self;
     fn serialize<S: Serializer>(value: &Self::Input, ser: &mut S) -> SerializeResult {
-        u8::serialize(&6, ser)?; // tiledef version
+        u8::serialize(&TILEDEF_VERSION, ser)?;
         String::serialize(&value.name, ser)?;
         TileAnimationParams::serialize(&value.animation, ser)?;
         let mut flags: u16 = 0;
@@ -95,10 +154,10 @@ impl Deserialize for TileDef {
     type Output = Self;
     fn deserialize(deserializer: &mut Deserializer<'_>) -> DeserializeResult<Self> {
         let version: u8 = u8::deserialize(deserializer)?;
-        if version != 6 {
-            bail!(DeserializeError::InvalidValue(
-                "Invalid TileDef version".into(),
-            ));
+        if version < TILEDEF_VERSION {
+            bail!(DeserializeError::InvalidValue(format!(
+                "Unsupported TileDef version {version}"
+            )));
         }
         let name = String::deserialize(deserializer)?;
         let animation = TileAnimationParams::deserialize(deserializer)?;
@@ -136,18 +195,28 @@ impl Deserialize for TileDef {
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+/// The animation of a texture, corresponding to the `animation` table of a tile in the Lua API
+#[derive(Default, Debug, Clone, Copy, PartialEq, ToJson)]
 pub enum TileAnimationParams {
     #[default]
     None,
+    /// The frames are stacked on top of each other; their number follows from the size of the
+    /// texture.
     VerticalFrames {
+        /// width of a frame relative to `aspect_h`
         aspect_w: u16,
+        /// height of a frame relative to `aspect_w`
         aspect_h: u16,
+        /// duration of the whole animation in seconds
         length: f32,
     },
+    /// The frames are arranged in a grid and played row by row.
     Sheet2D {
+        /// number of frames in a row
         frames_w: u8,
+        /// number of rows
         frames_h: u8,
+        /// duration of a single frame in seconds
         frame_length: f32,
     },
 }
@@ -157,6 +226,67 @@ impl TileAnimationParams {
     const NONE: u8 = 0;
     const VERTICAL_FRAMES: u8 = 1;
     const SHEET_2D: u8 = 2;
+
+    /// Creates an animation of square frames stacked on top of each other.
+    #[must_use]
+    pub fn vertical_frames(length: Duration) -> Self {
+        Self::VerticalFrames {
+            aspect_w: 1,
+            aspect_h: 1,
+            length: length.as_secs_f32(),
+        }
+    }
+
+    /// Creates an animation of frames arranged in a grid.
+    #[must_use]
+    pub fn sheet_2d(frames_w: u8, frames_h: u8, frame_length: Duration) -> Self {
+        Self::Sheet2D {
+            frames_w,
+            frames_h,
+            frame_length: frame_length.as_secs_f32(),
+        }
+    }
+
+    /// Returns the number of frames, the duration of a single frame (in whole milliseconds) and
+    /// the size of a frame of a texture of the given size, just like Luanti's `determineParams`.
+    #[must_use]
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss,
+        reason = "Luanti truncates the frame size and length as well"
+    )]
+    pub fn frames(&self, texture_size: UVec2) -> (u32, Duration, UVec2) {
+        match *self {
+            Self::None => (1, Duration::from_secs(1), texture_size),
+            Self::VerticalFrames {
+                aspect_w,
+                aspect_h,
+                length,
+            } => {
+                let frame_height =
+                    (texture_size.x as f32 / f32::from(aspect_w) * f32::from(aspect_h)) as u32;
+                let frame_count = texture_size.y.checked_div(frame_height).unwrap_or(0).max(1);
+                (
+                    frame_count,
+                    Duration::from_millis((1000.0 * length / frame_count as f32) as u64),
+                    UVec2::new(texture_size.x, frame_height),
+                )
+            }
+            Self::Sheet2D {
+                frames_w,
+                frames_h,
+                frame_length,
+            } => (
+                u32::from(frames_w) * u32::from(frames_h),
+                Duration::from_millis((1000.0 * frame_length) as u64),
+                UVec2::new(
+                    texture_size.x.checked_div(frames_w.into()).unwrap_or(0),
+                    texture_size.y.checked_div(frames_h.into()).unwrap_or(0),
+                ),
+            ),
+        }
+    }
 }
 
 impl Serialize for TileAnimationParams {
@@ -217,10 +347,75 @@ impl Deserialize for TileAnimationParams {
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, LuantiSerialize, LuantiDeserialize)]
+/// How a texture is being mapped onto the faces of a node
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, LuantiSerialize, LuantiDeserialize)]
 pub enum AlignStyle {
+    /// each node shows the whole texture
     #[default]
     Node,
+    /// the texture spans [`TileDef::scale`] nodes
     World,
+    /// the texture is aligned to the world only where the client's `world_aligned_mode` asks for
+    /// it, e.g. on the faces of node boxes
     UserDefined,
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::time::Duration;
+
+    use glam::UVec2;
+
+    use super::{AlignStyle, TileAnimationParams, TileDef};
+    use crate::types::{ProtocolContext, SColor};
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn tiles_round_trip() {
+        let tiles = [
+            TileDef::new_null(),
+            TileDef::new("default_water_source_animated.png".into())
+                .with_animation(TileAnimationParams::vertical_frames(Duration::from_secs(2)))
+                .with_backface_culling(false)
+                .with_tileable(false, true),
+            TileDef::new("default_stone_block.png".into())
+                .with_world_align(2)
+                .with_color(SColor::RED),
+            TileDef::new("default_fire_animated.png".into())
+                .with_animation(TileAnimationParams::sheet_2d(
+                    4,
+                    2,
+                    Duration::from_millis(250),
+                ))
+                .with_align_style(AlignStyle::UserDefined, 0),
+        ];
+        for tile in tiles {
+            let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 64);
+            TileDef::serialize(&tile, &mut ser).unwrap();
+            let data = ser.take();
+            let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+            assert_eq!(TileDef::deserialize(&mut deser).unwrap(), tile);
+        }
+    }
+
+    #[test]
+    fn animation_frames_follow_the_texture_size() {
+        let vertical = TileAnimationParams::vertical_frames(Duration::from_secs(2));
+        assert_eq!(
+            vertical.frames(UVec2::new(16, 128)),
+            (8, Duration::from_millis(250), UVec2::new(16, 16))
+        );
+        let sheet = TileAnimationParams::sheet_2d(4, 2, Duration::from_millis(100));
+        assert_eq!(
+            sheet.frames(UVec2::new(64, 32)),
+            (8, Duration::from_millis(100), UVec2::new(16, 16))
+        );
+        assert_eq!(
+            TileAnimationParams::None.frames(UVec2::new(16, 16)),
+            (1, Duration::from_secs(1), UVec2::new(16, 16))
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! The protocol and serialization versions implemented by this crate
+//!
+//! Luanti negotiates two versions during the handshake: the network protocol version, which
+//! decides the layout of commands, and the serialization format (`ser_fmt`), which decides the
+//! layout of map blocks. The constants here describe what this crate is able to read and write;
+//! the `supports_*` functions tell whether a negotiated version includes a certain feature.
+//!
+//! A client announcing a higher protocol version than [`MAX_PROTOCOL_VERSION`] doesn't make the
+//! crate understand it. Use [`clamp_protocol_version`] for anything provided by the user.
+
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+use crate::commands::client_to_server::ClientReadySpec;
+
+/// The oldest protocol version this crate is able to talk; the one of Luanti 5.0.0
+pub const MIN_PROTOCOL_VERSION: u16 = 37;
+/// The newest protocol version this crate is able to talk
+pub const MAX_PROTOCOL_VERSION: u16 = 47;
+/// All protocol versions this crate is able to talk
+pub const SUPPORTED_PROTOCOL_VERSIONS: RangeInclusive<u16> =
+    MIN_PROTOCOL_VERSION..=MAX_PROTOCOL_VERSION;
+
+/// The first protocol version supporting the `damage_texture_modifier` of object properties
+pub const DAMAGE_TEXTURE_PROTOCOL_VERSION: u16 = 38;
+/// The first protocol version supporting ephemeral sounds
+pub const EPHEMERAL_SOUND_PROTOCOL_VERSION: u16 = 38;
+/// The first protocol version supporting `leveled_max` and the `AlphaMode` of node definitions
+pub const ALPHA_MODE_PROTOCOL_VERSION: u16 = 39;
+/// The first protocol version supporting `shaded`, `show_on_minimap` and `nametag_bgcolor` of
+/// object properties
+pub const OBJECT_SHADING_PROTOCOL_VERSION: u16 = 39;
+/// The first protocol version supporting `move_resistance` and `liquid_move_physics` of node
+/// definitions
+pub const MOVE_RESISTANCE_PROTOCOL_VERSION: u16 = 41;
+/// The first protocol version supporting the `rotate_selectionbox` of object properties
+pub const ROTATE_SELECTIONBOX_PROTOCOL_VERSION: u16 = 41;
+/// The first protocol version supporting the `start_time` of sounds
+pub const SOUND_START_TIME_PROTOCOL_VERSION: u16 = 43;
+/// The first protocol version supporting the scale, interpolation and relative transformations of
+/// bone overrides
+pub const BONE_OVERRIDE_PROTOCOL_VERSION: u16 = 44;
+/// The first protocol version supporting the `node` visual of objects
+pub const NODE_VISUAL_PROTOCOL_VERSION: u16 = 46;
+/// The first protocol version supporting low-detail far blocks
+///
+/// This is newer than [`MAX_PROTOCOL_VERSION`] as the command is implemented ahead of time.
+pub const FAR_BLOCKS_PROTOCOL_VERSION: u16 = 49;
+
+/// The oldest serialization format of map blocks this crate is able to read
+pub const SER_FMT_LOWEST_READ: u8 = 28;
+/// The newest serialization format of map blocks this crate is able to read
+pub const SER_FMT_HIGHEST_READ: u8 = 29;
+/// The oldest serialization format of map blocks this crate is able to write
+pub const SER_FMT_LOWEST_WRITE: u8 = 29;
+/// The newest serialization format of map blocks this crate is able to write
+pub const SER_FMT_HIGHEST_WRITE: u8 = 29;
+/// All serialization formats of map blocks this crate is able to read
+pub const SUPPORTED_SER_FMT_READ: RangeInclusive<u8> = SER_FMT_LOWEST_READ..=SER_FMT_HIGHEST_READ;
+/// All serialization formats of map blocks this crate is able to write
+pub const SUPPORTED_SER_FMT_WRITE: RangeInclusive<u8> =
+    SER_FMT_LOWEST_WRITE..=SER_FMT_HIGHEST_WRITE;
+
+/// The first serialization format compressing entire map blocks with zstd rather than compressing
+/// their parts with zlib
+pub const ZSTD_SER_FMT: u8 = 29;
+/// The first protocol version whose clients read [`ZSTD_SER_FMT`]; the one of Luanti 5.5.0
+///
+/// Older clients only read serialization format 28, which this crate doesn't write.
+pub const ZSTD_PROTOCOL_VERSION: u16 = 40;
+
+/// Limits a protocol version to the ones this crate implements.
+#[must_use]
+pub fn clamp_protocol_version(version: u16) -> u16 {
+    version.clamp(MIN_PROTOCOL_VERSION, MAX_PROTOCOL_VERSION)
+}
+
+/// Limits a range of protocol versions to the ones this crate implements. Returns `None` if the
+/// ranges don't overlap.
+#[must_use]
+pub fn clamp_protocol_versions(versions: &RangeInclusive<u16>) -> Option<RangeInclusive<u16>> {
+    let min = (*versions.start()).max(MIN_PROTOCOL_VERSION);
+    let max = (*versions.end()).min(MAX_PROTOCOL_VERSION);
+    (min <= max).then_some(min..=max)
+}
+
+/// Returns `true` if this crate is able to talk the given protocol version.
+#[must_use]
+pub fn is_supported_protocol_version(version: u16) -> bool {
+    SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+}
+
+/// Returns `true` if map blocks of this serialization format are compressed with zstd as a
+/// whole.
+#[must_use]
+pub fn supports_zstd(ser_fmt: u8) -> bool {
+    ser_fmt >= ZSTD_SER_FMT
+}
+
+/// Returns `true` if node definitions of this protocol version contain `leveled_max` and the
+/// `AlphaMode`.
+#[must_use]
+pub fn supports_alpha_mode(version: u16) -> bool {
+    version >= ALPHA_MODE_PROTOCOL_VERSION
+}
+
+/// Returns `true` if node definitions of this protocol version contain `move_resistance` and
+/// `liquid_move_physics`.
+#[must_use]
+pub fn supports_move_resistance(version: u16) -> bool {
+    version >= MOVE_RESISTANCE_PROTOCOL_VERSION
+}
+
+/// Returns `true` if bone overrides of this protocol version contain scale, interpolation and
+/// relative transformations.
+#[must_use]
+pub fn supports_bone_override(version: u16) -> bool {
+    version >= BONE_OVERRIDE_PROTOCOL_VERSION
+}
+
+/// Returns `true` if objects of this protocol version may look like nodes.
+#[must_use]
+pub fn supports_node_visual(version: u16) -> bool {
+    version >= NODE_VISUAL_PROTOCOL_VERSION
+}
+
+/// Returns `true` if clients of this protocol version accept low-detail far blocks.
+#[must_use]
+pub fn supports_far_blocks(version: u16) -> bool {
+    version >= FAR_BLOCKS_PROTOCOL_VERSION
+}
+
+/// The engine version a client reports once it finished loading
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientVersion {
+    /// e.g. `5` of `5.10.0`
+    pub major: u8,
+    /// e.g. `10` of `5.10.0`
+    pub minor: u8,
+    /// e.g. `0` of `5.10.0`
+    pub patch: u8,
+    /// the full version string including a possible suffix, e.g. `5.10.0-dev-1a2b3c4`
+    pub full: String,
+    /// the newest formspec version the client understands; `None` for clients older than 5.1.0
+    pub formspec_version: Option<u16>,
+}
+
+impl ClientVersion {
+    /// Returns `true` if the client is at least of the given version.
+    #[must_use]
+    pub fn is_at_least(&self, major: u8, minor: u8, patch: u8) -> bool {
+        (self.major, self.minor, self.patch) >= (major, minor, patch)
+    }
+}
+
+impl From<ClientReadySpec> for ClientVersion {
+    fn from(spec: ClientReadySpec) -> Self {
+        let ClientReadySpec {
+            major_ver,
+            minor_ver,
+            patch_ver,
+            reserved: _,
+            full_ver,
+            formspec_ver,
+        } = spec;
+        Self {
+            major: major_ver,
+            minor: minor_ver,
+            patch: patch_ver,
+            full: full_ver,
+            formspec_version: formspec_ver,
+        }
+    }
+}
+
+impl Display for ClientVersion {
+    /// Shows the full version string or the numeric version if the string is empty.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.full.is_empty() {
+            write!(formatter, "{}.{}.{}", self.major, self.minor, self.patch)
+        } else {
+            formatter.write_str(&self.full)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ClientVersion, MAX_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, clamp_protocol_version,
+        clamp_protocol_versions,
+    };
+    use crate::commands::client_to_server::ClientReadySpec;
+
+    #[test]
+    fn versions_are_clamped_to_the_implemented_ones() {
+        assert_eq!(clamp_protocol_version(48), MAX_PROTOCOL_VERSION);
+        assert_eq!(clamp_protocol_version(36), MIN_PROTOCOL_VERSION);
+        assert_eq!(clamp_protocol_version(46), 46);
+
+        assert_eq!(
+            clamp_protocol_versions(&(40..=50)),
+            Some(40..=MAX_PROTOCOL_VERSION)
+        );
+        assert_eq!(clamp_protocol_versions(&(48..=50)), None);
+    }
+
+    #[test]
+    fn client_versions_are_compared() {
+        let version = ClientVersion::from(ClientReadySpec {
+            major_ver: 5,
+            minor_ver: 10,
+            patch_ver: 1,
+            reserved: 0,
+            full_ver: "5.10.1-dev".into(),
+            formspec_ver: Some(8),
+        });
+        assert!(version.is_at_least(5, 10, 1));
+        assert!(version.is_at_least(4, 11, 9));
+        assert!(!version.is_at_least(5, 11, 0));
+        assert_eq!(version.formspec_version, Some(8));
+        assert_eq!(version.to_string(), "5.10.1-dev");
+        let unnamed = ClientVersion {
+            full: String::new(),
+            ..version
+        };
+        assert_eq!(unnamed.to_string(), "5.10.1");
+    }
+}
@@ -1,5 +1,7 @@
 pub mod audit;
+pub mod buf_pool;
 pub mod channel_id;
+pub mod compression;
 pub mod deser;
 pub mod packet;
 pub mod peer_id;
@@ -8,6 +8,9 @@
 //!
 //! But it should not be enabled normally, because a malformed packet from a
 //! broken/modified client will cause a crash.
+//!
+//! Auditing is configured per connection using [`Peer::set_audit`](crate::peer::Peer::set_audit),
+//! or for a single deserialization using [`ProtocolContext::audit`].
 
 use anyhow::Result;
 use anyhow::bail;
@@ -20,17 +23,9 @@ use crate::commands::CommandRef;
 use crate::commands::serialize_commandref;
 use crate::commands::server_to_client::ToClientCommand;
 use crate::types::ProtocolContext;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
-
-static AUDIT_ENABLED: AtomicBool = AtomicBool::new(false);
-
-pub fn audit_on() {
-    AUDIT_ENABLED.store(true, Ordering::SeqCst);
-}
 
 pub fn audit_command<Cmd: CommandRef>(context: ProtocolContext, orig: &[u8], command: &Cmd) {
-    if !AUDIT_ENABLED.load(Ordering::Relaxed) {
+    if !context.audit {
         return;
     }
     let mut ser = VecSerializer::new(context, 2 * orig.len());
@@ -155,6 +150,18 @@ fn audit_command_inner<Cmd: CommandRef>(
                 );
             }
         }
+        Some(ToClientCommand::FarBlocks(_)) if context.ser_fmt >= 29 => {
+            // Layout:
+            //
+            //   command type: u16
+            //   pos: I16Vec3, (6 bytes)
+            //   level: u8
+            //   datastring: ZStdCompressed<MapBlock>,
+            do_compare("FarBlocks prefix", &reserialized[..9], &orig[..9], command);
+            let reserialized = zstd_decompress_to_vec(&reserialized[9..])?;
+            let orig = zstd_decompress_to_vec(&orig[9..])?;
+            do_compare("FarBlocks contents", &reserialized, &orig, command);
+        }
         Some(
             ToClientCommand::NodemetaChanged(_)
             | ToClientCommand::Itemdef(_)
@@ -200,3 +207,29 @@ fn zstd_decompress_to_vec(compressed: &[u8]) -> Result<Vec<u8>> {
     })?;
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::Command;
+    use crate::commands::server_to_client::{TimeOfDaySpec, ToClientCommand};
+    use crate::types::{ProtocolContext, TrailingBytes};
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn audited_command_is_deserialized() {
+        let command = Command::ToClient(ToClientCommand::TimeOfDay(Box::new(TimeOfDaySpec {
+            time_of_day: 6000,
+            time_speed: Some(72.0),
+            extra: TrailingBytes::default(),
+        })));
+        let mut ser = VecSerializer::new(ProtocolContext::latest_for_send(false), 16);
+        Command::serialize(&command, &mut ser).unwrap();
+        let data = ser.take();
+
+        // a mismatch would terminate the process
+        let context = ProtocolContext::latest_for_receive(true).with_audit(true);
+        let mut deser = Deserializer::new(context, &data);
+        assert_eq!(Command::deserialize(&mut deser).unwrap(), Some(command));
+    }
+}
@@ -0,0 +1,214 @@
+//! A pool of reusable byte buffers
+//!
+//! Every datagram being sent or received needs a buffer. Instead of allocating
+//! a fresh `Vec<u8>` each time, buffers are handed out by a [`BufPool`] and go
+//! back to it when being dropped, so steady-state operation doesn't allocate.
+
+use std::mem;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+/// Buffers with a larger capacity aren't kept, so a single huge command doesn't pin its memory.
+pub const MAX_POOLED_CAPACITY: usize = 0x0001_0000;
+
+/// Number of idle buffers being kept by default
+pub const DEFAULT_MAX_IDLE: usize = 256;
+
+/// Counters for tuning the size of a [`BufPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufPoolStats {
+    /// number of buffers handed out
+    pub taken: u64,
+    /// number of buffers handed out which have been reused instead of allocated
+    pub reused: u64,
+    /// number of buffers which went back to the pool
+    pub returned: u64,
+    /// number of buffers which have been freed because the pool was full or they were too large
+    pub discarded: u64,
+    /// number of buffers currently waiting in the pool
+    pub idle: usize,
+}
+
+/// Hands out byte buffers and takes them back once they're no longer used.
+///
+/// Cloning a pool is cheap; all clones share the same buffers.
+#[derive(Debug, Clone)]
+pub struct BufPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    idle: Mutex<Vec<Vec<u8>>>,
+    max_idle: usize,
+    taken: AtomicU64,
+    reused: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+
+impl Default for BufPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IDLE)
+    }
+}
+
+impl BufPool {
+    /// Creates a pool keeping up to `max_idle` unused buffers.
+    #[must_use]
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                idle: Mutex::new(Vec::new()),
+                max_idle,
+                taken: AtomicU64::new(0),
+                reused: AtomicU64::new(0),
+                returned: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Hands out an empty buffer with at least the given capacity.
+    #[must_use]
+    pub fn take(&self, capacity: usize) -> PooledBuf {
+        self.shared.taken.fetch_add(1, Ordering::Relaxed);
+        let reused = self.lock_idle().pop();
+        let data = if let Some(mut data) = reused {
+            self.shared.reused.fetch_add(1, Ordering::Relaxed);
+            data.reserve(capacity);
+            data
+        } else {
+            Vec::with_capacity(capacity)
+        };
+        PooledBuf {
+            data,
+            pool: Some(self.clone()),
+        }
+    }
+
+    /// Returns the current counters of this pool.
+    #[must_use]
+    pub fn stats(&self) -> BufPoolStats {
+        BufPoolStats {
+            taken: self.shared.taken.load(Ordering::Relaxed),
+            reused: self.shared.reused.load(Ordering::Relaxed),
+            returned: self.shared.returned.load(Ordering::Relaxed),
+            discarded: self.shared.discarded.load(Ordering::Relaxed),
+            idle: self.lock_idle().len(),
+        }
+    }
+
+    fn give_back(&self, mut data: Vec<u8>) {
+        if data.capacity() == 0 {
+            // nothing worth keeping
+            return;
+        }
+        if data.capacity() <= MAX_POOLED_CAPACITY {
+            let mut idle = self.lock_idle();
+            if idle.len() < self.shared.max_idle {
+                data.clear();
+                idle.push(data);
+                self.shared.returned.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+        self.shared.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The idle buffers are still usable if another thread panicked.
+    fn lock_idle(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.shared
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A byte buffer which goes back to its [`BufPool`] when being dropped
+#[derive(Debug, Default)]
+pub struct PooledBuf {
+    data: Vec<u8>,
+    /// `None` if the buffer doesn't belong to any pool
+    pool: Option<BufPool>,
+}
+
+impl PooledBuf {
+    /// Wraps a buffer which doesn't belong to any pool.
+    #[must_use]
+    pub fn unpooled(data: Vec<u8>) -> Self {
+        Self { data, pool: None }
+    }
+
+    /// Detaches the buffer from its pool.
+    #[must_use]
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.pool = None;
+        mem::take(&mut self.data)
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.give_back(mem::take(&mut self.data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufPool, BufPoolStats, MAX_POOLED_CAPACITY, PooledBuf};
+
+    #[test]
+    fn buffers_are_reused() {
+        let pool = BufPool::new(1);
+        let mut first = pool.take(100);
+        first.extend_from_slice(b"data");
+        let second = pool.take(100);
+        drop(first);
+        // the pool is full, so this one gets freed
+        drop(second);
+
+        let reused = pool.take(10);
+        assert!(reused.is_empty());
+        assert!(reused.capacity() >= 100);
+        assert_eq!(
+            pool.stats(),
+            BufPoolStats {
+                taken: 3,
+                reused: 1,
+                returned: 1,
+                discarded: 1,
+                idle: 0,
+            }
+        );
+
+        // detached and oversized buffers don't go back to the pool
+        drop(reused.into_vec());
+        drop(pool.take(MAX_POOLED_CAPACITY + 1));
+        drop(PooledBuf::unpooled(vec![1, 2, 3]));
+        let stats = pool.stats();
+        assert_eq!((stats.returned, stats.discarded, stats.idle), (1, 2, 0));
+    }
+}
@@ -19,7 +19,7 @@ use super::{
  * Packet order is only guaranteed inside a channel, so packets that operate on
  * the same objects are *required* to be in the same channel.
  */
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum ChannelId {
     #[default]
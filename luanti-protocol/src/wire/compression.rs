@@ -0,0 +1,240 @@
+//! Settings and statistics of the compressed payloads of commands
+//!
+//! Some commands carry large payloads which are compressed with zlib or zstd, most notably the node
+//! and item definitions sent while a client joins and the map blocks sent while playing. The
+//! [`CompressionConfig`] of the
+//! [`ProtocolContext`](crate::types::ProtocolContext) controls how much effort goes into
+//! compressing them, and every compressed payload is counted in [`compression_stats`], so the
+//! settings can be tuned with actual numbers.
+
+use std::any::type_name;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+/// The zlib level the C++ engine uses
+pub const DEFAULT_ZLIB_LEVEL: u8 = 6;
+
+/// Payloads of at least this many bytes get the highest level when compressing adaptively
+pub const LARGE_PAYLOAD_SIZE: usize = 0x0001_0000;
+
+/// The highest level supported by zlib
+const MAX_ZLIB_LEVEL: u8 = 9;
+
+/// The fastest and the strongest zstd levels being used when compressing adaptively
+const FAST_ZSTD_LEVEL: i32 = 1;
+const STRONG_ZSTD_LEVEL: i32 = 19;
+
+/// How payloads of compressed wrappers like `ZLibCompressed` are being compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// the zlib level from 0 (stored) to 9
+    pub zlib_level: u8,
+    /// the zstd level; 0 picks zstd's default
+    pub zstd_level: i32,
+    /// payloads smaller than this are stored without compression by zlib and compressed with the
+    /// fastest level by zstd, as compressing them costs more time than it saves bytes
+    pub min_size: usize,
+    /// whether payloads of at least [`LARGE_PAYLOAD_SIZE`] bytes get the highest level, as
+    /// sending them takes much longer than compressing them
+    pub adaptive: bool,
+    /// the zstd level of map blocks, which are compressed as a whole since `ser_fmt` 29; 0 picks
+    /// zstd's default just like the `map_compression_level_net` of the C++ engine
+    ///
+    /// Compressing map blocks is the dominant cost of a busy server, so negative (faster) levels
+    /// may be worth the additional bandwidth. See the `map block` benchmarks for a comparison.
+    pub map_block_level: i32,
+}
+
+impl Default for CompressionConfig {
+    /// The fixed levels the C++ engine uses
+    fn default() -> Self {
+        Self {
+            zlib_level: DEFAULT_ZLIB_LEVEL,
+            zstd_level: 0,
+            min_size: 0,
+            adaptive: false,
+            map_block_level: 0,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Chooses the level by the size of the payload
+    pub const ADAPTIVE: Self = Self {
+        zlib_level: DEFAULT_ZLIB_LEVEL,
+        zstd_level: 0,
+        min_size: 64,
+        adaptive: true,
+        map_block_level: 0,
+    };
+
+    /// Returns the zlib level for a payload of the given size.
+    #[must_use]
+    pub fn zlib_level(&self, size: usize) -> u8 {
+        if size < self.min_size {
+            0
+        } else if self.adaptive && size >= LARGE_PAYLOAD_SIZE {
+            MAX_ZLIB_LEVEL
+        } else {
+            self.zlib_level.min(MAX_ZLIB_LEVEL)
+        }
+    }
+
+    /// Returns the zstd level for a payload of the given size.
+    #[must_use]
+    pub fn zstd_level(&self, size: usize) -> i32 {
+        if size < self.min_size {
+            FAST_ZSTD_LEVEL
+        } else if self.adaptive && size >= LARGE_PAYLOAD_SIZE {
+            STRONG_ZSTD_LEVEL
+        } else {
+            self.zstd_level
+        }
+    }
+}
+
+/// Counters of the payloads of a single type which have been compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+    /// number of compressed payloads
+    pub count: u64,
+    /// total size of the payloads before compression
+    pub uncompressed_bytes: u64,
+    /// total size of the payloads after compression
+    pub compressed_bytes: u64,
+    /// total time spent compressing
+    pub duration: Duration,
+}
+
+impl CompressionStats {
+    /// Returns the compressed size relative to the uncompressed size; `1.0` if nothing has been
+    /// compressed yet.
+    #[must_use]
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "the ratio doesn't need to be exact"
+    )]
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_bytes == 0 {
+            return 1.0;
+        }
+        self.compressed_bytes as f64 / self.uncompressed_bytes as f64
+    }
+}
+
+/// The counters of all payload types; shared by all connections of the process
+static STATS: Mutex<BTreeMap<&'static str, CompressionStats>> = Mutex::new(BTreeMap::new());
+
+/// Returns the counters of all compressed payloads by the name of their type, e.g.
+/// `NodeDefManager`.
+#[must_use]
+pub fn compression_stats() -> BTreeMap<&'static str, CompressionStats> {
+    STATS.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Counts a compressed payload of type `T`.
+pub(crate) fn record<T: ?Sized>(uncompressed: usize, compressed: usize, duration: Duration) {
+    let mut stats = STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    let stats = stats.entry(short_type_name::<T>()).or_default();
+    stats.count += 1;
+    stats.uncompressed_bytes += u64::try_from(uncompressed).unwrap_or(u64::MAX);
+    stats.compressed_bytes += u64::try_from(compressed).unwrap_or(u64::MAX);
+    stats.duration += duration;
+}
+
+/// Strips the module path, e.g. `luanti_protocol::types::NodeDefManager` becomes `NodeDefManager`.
+fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = type_name::<T>();
+    let path = name.split('<').next().unwrap_or(name);
+    let start = path.rfind("::").map_or(0, |index| index + 2);
+    name.get(start..).unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use luanti_core::{ContentId, MapNode};
+
+    use super::{CompressionConfig, LARGE_PAYLOAD_SIZE, compression_stats, short_type_name};
+    use crate::types::{
+        MapNodesBulk, NodeDefManager, NodeMetadataList, ProtocolContext, TransferrableMapBlock,
+        ZLibCompressed,
+    };
+    use crate::wire::deser::{Deserialize, Deserializer};
+    use crate::wire::ser::{Serialize, VecSerializer};
+
+    #[test]
+    fn levels_are_chosen_by_size() {
+        let fixed = CompressionConfig::default();
+        assert_eq!(fixed.zlib_level(1), 6);
+        assert_eq!(fixed.zlib_level(LARGE_PAYLOAD_SIZE), 6);
+
+        let adaptive = CompressionConfig::ADAPTIVE;
+        assert_eq!(adaptive.zlib_level(10), 0);
+        assert_eq!(adaptive.zlib_level(1000), 6);
+        assert_eq!(adaptive.zlib_level(LARGE_PAYLOAD_SIZE), 9);
+        assert_eq!(adaptive.zstd_level(10), 1);
+        assert_eq!(adaptive.zstd_level(1000), 0);
+
+        assert_eq!(short_type_name::<NodeDefManager>(), "NodeDefManager");
+        assert_eq!(short_type_name::<Vec<u8>>(), "Vec<u8>");
+    }
+
+    #[test]
+    fn compressed_payloads_are_counted() {
+        let context =
+            ProtocolContext::latest_for_send(false).with_compression(CompressionConfig::ADAPTIVE);
+        let mut ser = VecSerializer::new(context, 32);
+        ZLibCompressed::<u64>::serialize(&42, &mut ser).unwrap();
+        let data = ser.take();
+
+        let stats = compression_stats()["u64"];
+        assert!(stats.count >= 1);
+        assert!(stats.uncompressed_bytes >= 8);
+
+        let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+        assert_eq!(ZLibCompressed::<u64>::deserialize(&mut deser).unwrap(), 42);
+    }
+
+    #[test]
+    fn map_blocks_use_their_own_level() {
+        let block = TransferrableMapBlock {
+            is_underground: false,
+            day_night_differs: false,
+            generated: true,
+            lighting_complete: Some(0xFFFF),
+            nodes: MapNodesBulk {
+                // some noise, so the levels make a difference
+                nodes: std::array::from_fn(|index| MapNode {
+                    content_id: ContentId(u16::from(index % 3 == 0)),
+                    param1: u8::try_from((index * index) >> 7 & 0x0f).unwrap(),
+                    param2: 0,
+                }),
+            },
+            node_metadata: NodeMetadataList {
+                metadata: Vec::new(),
+            },
+        };
+        let sizes = [-5, 19].map(|map_block_level| {
+            let compression = CompressionConfig {
+                map_block_level,
+                ..CompressionConfig::default()
+            };
+            let context = ProtocolContext::latest_for_send(false).with_compression(compression);
+            let mut ser = VecSerializer::new(context, 1024);
+            TransferrableMapBlock::serialize(&block, &mut ser).unwrap();
+            let data = ser.take();
+            let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &data);
+            assert_eq!(
+                TransferrableMapBlock::deserialize(&mut deser).unwrap(),
+                block
+            );
+            data.len()
+        });
+        assert!(sizes[1] < sizes[0], "{sizes:?}");
+        assert!(compression_stats()["TransferrableMapBlock"].count >= 2);
+    }
+}
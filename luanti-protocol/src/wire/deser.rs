@@ -4,6 +4,9 @@ use anyhow::bail;
 use std::fmt::Debug;
 use std::num::ParseIntError;
 use std::str::Utf8Error;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DeserializeError {
@@ -45,23 +48,211 @@ impl From<anyhow::Error> for DeserializeError {
 
 pub type DeserializeResult<R> = anyhow::Result<R>;
 
+/// Attached to the error of a field which couldn't be deserialized.
+///
+/// Nested structs add one of these for each level, so the path to the failing field can be
+/// retrieved from an error using [`field_path`].
+#[derive(Debug, thiserror::Error)]
+#[error("failed to deserialize field {type_name}::{field} at offset {offset}")]
+pub struct FieldError {
+    /// name of the struct containing the field
+    pub type_name: &'static str,
+    /// name of the field
+    pub field: &'static str,
+    /// position of the field's first byte within the buffer being deserialized
+    pub offset: usize,
+    /// The error of the field itself.
+    ///
+    /// This isn't attached as context, as `anyhow` hides context values from [`anyhow::Chain`].
+    #[source]
+    source: anyhow::Error,
+}
+
+/// Returns the fields which were being deserialized when the error occurred, outermost first.
+#[must_use]
+pub fn field_path(error: &anyhow::Error) -> Vec<&FieldError> {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<FieldError>())
+        .collect()
+}
+
+/// Attached to the error of a line of a text format which couldn't be parsed, like the one of
+/// [`Inventory`](crate::types::Inventory).
+#[derive(Debug, thiserror::Error)]
+#[error("failed to parse line {line}: {content:?}")]
+pub struct LineError {
+    /// number of the line, starting at `1`
+    pub line: usize,
+    /// the line without its line break; invalid UTF-8 has been replaced
+    pub content: String,
+    /// The error of the line itself
+    #[source]
+    source: anyhow::Error,
+}
+
+impl LineError {
+    /// Wraps the error of the line starting at `offset` within `text`.
+    pub(crate) fn new(text: &[u8], offset: usize, source: anyhow::Error) -> Self {
+        let (before, after) = text.split_at(offset.min(text.len()));
+        let content = after.split(|ch| *ch == b'\n').next().unwrap_or_default();
+        Self {
+            line: before.split(|ch| *ch == b'\n').count(),
+            content: String::from_utf8_lossy(content).into_owned(),
+            source,
+        }
+    }
+}
+
+/// Returns the line of a text format which failed to be parsed, if any.
+#[must_use]
+pub fn line_error(error: &anyhow::Error) -> Option<&LineError> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<LineError>())
+}
+
+/// A field visited while deserializing with [`Deserializer::with_trace`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldTrace {
+    /// names of the enclosing fields, outermost first, followed by the name of this field
+    pub path: Vec<&'static str>,
+    /// name of the struct containing the field
+    pub type_name: &'static str,
+    /// position of the field's first byte within the buffer being deserialized
+    ///
+    /// Compressed data is being deserialized from a separate buffer, so the offsets of fields
+    /// within it are relative to the start of the decompressed data.
+    pub offset: usize,
+    /// the `Debug` representation of the decoded value or `None` if deserialization failed
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    path: Vec<&'static str>,
+    fields: Vec<FieldTrace>,
+}
+
 pub struct Deserializer<'data> {
     pub context: ProtocolContext,
     pub data: &'data [u8], // Remaining data
+    /// offset of `data` within the buffer at the time this deserializer has been created
+    start: usize,
+    /// length of `data` at the time this deserializer has been created
+    len: usize,
+    /// shared with all nested deserializers
+    trace: Option<Arc<Mutex<TraceState>>>,
 }
 
 impl<'data> Deserializer<'data> {
     #[must_use]
     pub fn new(context: ProtocolContext, data: &'data [u8]) -> Self {
-        Self { context, data }
+        Self {
+            context,
+            data,
+            start: 0,
+            len: data.len(),
+            trace: None,
+        }
+    }
+
+    /// Enables capturing every field visited by derived `Deserialize` implementations.
+    ///
+    /// The captured fields can be retrieved using [`Deserializer::trace`] at any time; even after
+    /// deserialization failed.
+    #[must_use]
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Arc::default());
+        self
+    }
+
+    /// Returns the fields visited so far in the order their deserialization started, or `None`
+    /// if tracing hasn't been enabled.
+    #[must_use]
+    pub fn trace(&self) -> Option<Vec<FieldTrace>> {
+        self.trace.as_ref().map(|trace| {
+            trace
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fields
+                .clone()
+        })
+    }
+
+    /// Creates a deserializer for a separate buffer, e.g. after decompression, which shares the
+    /// context and trace of this deserializer.
+    #[must_use]
+    pub fn nested<'nested>(&self, data: &'nested [u8]) -> Deserializer<'nested> {
+        Deserializer {
+            context: self.context,
+            data,
+            start: 0,
+            len: data.len(),
+            trace: self.trace.clone(),
+        }
     }
 
     /// Take a number of bytes, and return a sub-Deserializer which
     /// only operates on those bytes
     pub fn slice(&mut self, count: usize) -> DeserializeResult<Self> {
+        let start = self.offset();
+        let data = self.take(count)?;
         Ok(Self {
             context: self.context,
-            data: self.take(count)?,
+            data,
+            start,
+            len: data.len(),
+            trace: self.trace.clone(),
+        })
+    }
+
+    /// The position of the next byte within the buffer being deserialized
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.start + self.len - self.data.len()
+    }
+
+    /// Deserializes a field of a struct.
+    ///
+    /// Failures are annotated with a [`FieldError`] and the field is being recorded if tracing
+    /// has been enabled.
+    pub fn deserialize_field<T: Deserialize>(
+        &mut self,
+        type_name: &'static str,
+        field: &'static str,
+    ) -> DeserializeResult<T::Output> {
+        let offset = self.offset();
+        let index = self.trace.as_ref().map(|trace| {
+            let mut trace = trace.lock().unwrap_or_else(PoisonError::into_inner);
+            trace.path.push(field);
+            let path = trace.path.clone();
+            trace.fields.push(FieldTrace {
+                path,
+                type_name,
+                offset,
+                value: None,
+            });
+            trace.fields.len() - 1
+        });
+
+        let result = T::deserialize(self);
+
+        if let Some((trace, index)) = self.trace.as_ref().zip(index) {
+            let mut trace = trace.lock().unwrap_or_else(PoisonError::into_inner);
+            trace.path.pop();
+            if let (Ok(value), Some(entry)) = (&result, trace.fields.get_mut(index)) {
+                entry.value = Some(format!("{value:?}"));
+            }
+        }
+        result.map_err(|source| {
+            FieldError {
+                type_name,
+                field,
+                offset,
+                source,
+            }
+            .into()
         })
     }
 
@@ -180,6 +371,56 @@ impl<'data> Deserializer<'data> {
 
 pub trait Deserialize: Sized + Debug {
     /// Output should be Self, except for wrapper types.
-    type Output;
+    type Output: Debug;
     fn deserialize(deserializer: &mut Deserializer<'_>) -> DeserializeResult<Self::Output>;
 }
+
+#[cfg(test)]
+mod tests {
+    use luanti_protocol_derive::LuantiDeserialize;
+
+    use super::{Deserialize, DeserializeResult, Deserializer, field_path};
+    use crate::types::ProtocolContext;
+
+    #[derive(Debug, LuantiDeserialize)]
+    #[expect(dead_code, reason = "only the deserialization is being tested")]
+    struct Inner {
+        first: u8,
+        second: u16,
+    }
+
+    #[derive(Debug, LuantiDeserialize)]
+    #[expect(dead_code, reason = "only the deserialization is being tested")]
+    struct Outer {
+        header: u8,
+        inner: Inner,
+    }
+
+    #[test]
+    fn failing_field_is_reported() {
+        let context = ProtocolContext::latest_for_receive(false);
+        let mut deser = Deserializer::new(context, &[1, 2, 3]).with_trace();
+        let error = Outer::deserialize(&mut deser).unwrap_err();
+
+        let path: Vec<_> = field_path(&error)
+            .iter()
+            .map(|field| (field.type_name, field.field, field.offset))
+            .collect();
+        assert_eq!(path, [("Outer", "inner", 1), ("Inner", "second", 2)]);
+
+        let trace = deser.trace().unwrap();
+        let fields: Vec<_> = trace
+            .iter()
+            .map(|field| (field.path.join("."), field.offset, field.value.as_deref()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("header".to_owned(), 0, Some("1")),
+                ("inner".to_owned(), 1, None),
+                ("inner.first".to_owned(), 1, Some("2")),
+                ("inner.second".to_owned(), 2, None),
+            ]
+        );
+    }
+}
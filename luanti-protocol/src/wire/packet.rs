@@ -12,17 +12,21 @@ use super::ser::Serialize;
 use super::ser::SerializeResult;
 use super::ser::Serializer;
 use crate::commands::Command;
+use crate::versions::MAX_PROTOCOL_VERSION;
 
 pub const PROTOCOL_ID: u32 = 0x4f45_7403;
 
-pub const LATEST_PROTOCOL_VERSION: u16 = 47;
-pub const SER_FMT_VER_HIGHEST_WRITE: u8 = 29;
+/// The newest protocol version implemented; see [`crate::versions`]
+pub const LATEST_PROTOCOL_VERSION: u16 = MAX_PROTOCOL_VERSION;
+pub const SER_FMT_VER_HIGHEST_WRITE: u8 = SER_FMT_HIGHEST_WRITE;
 
-// Serialization format of map data
-pub const SER_FMT_HIGHEST_READ: u8 = 29;
-pub const SER_FMT_HIGHEST_WRITE: u8 = 29;
-pub const SER_FMT_LOWEST_READ: u8 = 28;
-pub const SER_FMT_LOWEST_WRITE: u8 = 29;
+// kept here for compatibility
+pub use crate::versions::{
+    ALPHA_MODE_PROTOCOL_VERSION, BONE_OVERRIDE_PROTOCOL_VERSION, DAMAGE_TEXTURE_PROTOCOL_VERSION,
+    FAR_BLOCKS_PROTOCOL_VERSION, MOVE_RESISTANCE_PROTOCOL_VERSION, NODE_VISUAL_PROTOCOL_VERSION,
+    OBJECT_SHADING_PROTOCOL_VERSION, ROTATE_SELECTIONBOX_PROTOCOL_VERSION, SER_FMT_HIGHEST_READ,
+    SER_FMT_HIGHEST_WRITE, SER_FMT_LOWEST_READ, SER_FMT_LOWEST_WRITE,
+};
 
 pub const MAX_PACKET_SIZE: usize = 512;
 pub const PACKET_HEADER_SIZE: usize = 7;
@@ -31,6 +35,9 @@ pub const SPLIT_HEADER_SIZE: usize = 7;
 pub const MAX_ORIGINAL_BODY_SIZE: usize =
     MAX_PACKET_SIZE - PACKET_HEADER_SIZE - RELIABLE_HEADER_SIZE;
 pub const MAX_SPLIT_BODY_SIZE: usize = MAX_ORIGINAL_BODY_SIZE - SPLIT_HEADER_SIZE;
+/// The size of the largest command which can be sent; the number of chunks of a split command is
+/// limited to a `u16`
+pub const MAX_COMMAND_SIZE: usize = u16::MAX as usize * MAX_SPLIT_BODY_SIZE;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AckBody {
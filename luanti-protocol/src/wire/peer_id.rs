@@ -17,7 +17,8 @@ impl PeerId {
         self == Self::NONE
     }
 
-    pub(crate) fn is_server(self) -> bool {
+    #[must_use]
+    pub fn is_server(self) -> bool {
         self == Self::SERVER
     }
 
@@ -4,6 +4,8 @@ use std::num::TryFromIntError;
 
 use crate::types::CommandDirection;
 use crate::types::ProtocolContext;
+use crate::wire::buf_pool::BufPool;
+use crate::wire::buf_pool::PooledBuf;
 
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum SerializeError {
@@ -151,7 +153,7 @@ impl Serializer for SliceSerializer<'_> {
 
 pub struct VecSerializer {
     context: ProtocolContext,
-    data: Vec<u8>,
+    data: PooledBuf,
 }
 
 impl VecSerializer {
@@ -159,12 +161,29 @@ impl VecSerializer {
     pub fn new(context: ProtocolContext, initial_capacity: usize) -> Self {
         Self {
             context,
-            data: Vec::with_capacity(initial_capacity),
+            data: PooledBuf::unpooled(Vec::with_capacity(initial_capacity)),
         }
     }
 
+    /// Serializes into a buffer of the given pool. The buffer goes back to the pool when the
+    /// serializer or the result of [`Self::take_pooled`] is dropped.
+    #[must_use]
+    pub fn pooled(context: ProtocolContext, pool: &BufPool, initial_capacity: usize) -> Self {
+        Self {
+            context,
+            data: pool.take(initial_capacity),
+        }
+    }
+
+    /// Returns the serialized data; the buffer is detached from its pool.
     #[must_use]
     pub fn take(self) -> Vec<u8> {
+        self.data.into_vec()
+    }
+
+    /// Returns the serialized data without detaching the buffer from its pool.
+    #[must_use]
+    pub fn take_pooled(self) -> PooledBuf {
         self.data
     }
 }
@@ -187,7 +206,8 @@ impl Serializer for VecSerializer {
 
     fn write_marker(&mut self, length: usize) -> Result<Self::Marker, SerializeError> {
         let marker = (self.data.len(), length);
-        self.data.resize(self.data.len() + length, 0_u8);
+        let new_len = self.data.len() + length;
+        self.data.resize(new_len, 0_u8);
         Ok(marker)
     }
 
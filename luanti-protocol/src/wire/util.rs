@@ -60,12 +60,23 @@ macro_rules! stoi {
 
 ///
 /// Streaming Zstd compress
-pub fn zstd_compress<F>(input: &[u8], mut write: F) -> Result<()>
+pub fn zstd_compress<F>(input: &[u8], write: F) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    zstd_compress_with_level(input, 0, write)
+}
+
+/// Streaming Zstd compress with the given level; `0` picks zstd's default
+pub fn zstd_compress_with_level<F>(input: &[u8], level: i32, mut write: F) -> Result<()>
 where
     F: FnMut(&[u8]) -> Result<()>,
 {
     const BUFSIZE: usize = 0x4000;
     let mut ctx = zstd_safe::CCtx::create();
+    if let Err(error) = ctx.set_parameter(zstd_safe::CParameter::CompressionLevel(level)) {
+        bail!("zstd_compress level: {}", zstd_safe::get_error_name(error));
+    }
     let mut buf = [0_u8; BUFSIZE];
     let mut input_buffer = InBuffer { src: input, pos: 0 };
     while input_buffer.pos < input.len() {
@@ -40,6 +40,9 @@ def on_ts_chat_message(message: str):
     print(f"on_ts_chat_message: {message}")
     if message == "inv":
         luanti.inv()
+    elif message.startswith("light "):
+        player = message.removeprefix("light ")
+        print(f"{player} sees a day-night ratio of {luanti.day_night_ratio(player)} at {luanti.time_of_day()}")
 
 # Damage, 0x35, Default, true => DamageSpec,
 def on_damage(damage: int):
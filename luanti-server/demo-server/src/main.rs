@@ -1,10 +1,8 @@
 //! Luanti demo server implemented in Rust
 
 use anyhow::Context;
-use anyhow::bail;
 use clap::ArgGroup;
 use clap::Parser;
-use flexstr::SharedStr;
 use log::info;
 use luanti_protocol::commands::client_to_server::DamageSpec;
 use luanti_protocol::commands::client_to_server::InteractSpec;
@@ -18,7 +16,6 @@ use luanti_protocol::commands::client_to_server::PlayerPosCommand;
 use luanti_protocol::commands::client_to_server::RespawnSpec;
 use luanti_protocol::commands::client_to_server::TSChatMessageSpec;
 use luanti_protocol::commands::client_to_server::TSModchannelMsgSpec;
-use luanti_protocol::types::AlignStyle;
 use luanti_protocol::types::AlphaMode;
 use luanti_protocol::types::ContentFeatures;
 use luanti_protocol::types::DrawType;
@@ -28,44 +25,69 @@ use luanti_protocol::types::NodeDefManager;
 use luanti_protocol::types::PlayerPos;
 use luanti_protocol::types::PointabilityType;
 use luanti_protocol::types::SColor;
-use luanti_protocol::types::TileAnimationParams;
 use luanti_protocol::types::TileDef;
 use luanti_server::api::FromPluginEvent;
 use luanti_server::api::ToPluginEvent;
 use luanti_server::authentication::dummy::DummyAuthenticator;
+use luanti_server::clock::WorldClock;
+use luanti_server::config::ServerConfig;
+use luanti_server::formspec::FormDispatcher;
+use luanti_server::health::FixedSpawnPoint;
+use luanti_server::metrics;
+use luanti_server::player_store::file::FilePlayerStore;
 use luanti_server::server::LuantiWorldServer;
-use luanti_server::world::content_id_map::ContentIdMap;
-use luanti_server::world::generation::flat::MapgenFlat;
+#[cfg(feature = "serverlist")]
+use luanti_server::serverlist;
+use luanti_server::world::backup;
+use luanti_server::world::block_cache::BlockCacheConfig;
+use luanti_server::world::content_id_mapper::ContentIdMapper;
+use luanti_server::world::emerge_queue::EmergeConfig;
+use luanti_server::world::generation::v7::MapgenV7;
+use luanti_server::world::generation::v7::MapgenV7Nodes;
+use luanti_server::world::generation::v7::MapgenV7Params;
 use luanti_server::world::map_block_provider::MapBlockProvider;
 use luanti_server::world::map_block_router::MapBlockRouter;
+use luanti_server::world::map_meta::world_seed;
 use luanti_server::world::media_registry::MediaRegistry;
-use luanti_server::world::storage::minetestworld::MinetestworldStorage;
 use pyo3::Python;
 use pyo3::types::PyAnyMethods;
 use pyo3::types::PyModule;
 use std::array;
 use std::ffi::CString;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::thread;
-use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-#[command(group(ArgGroup::new("source").required(true).args(["listen", "bind"])))]
+#[command(group(ArgGroup::new("source").args(["listen", "bind"])))]
 struct Args {
-    /// Listen on port
+    /// Configuration file in the format of `minetest.conf`
+    #[arg(short, long, default_value = "luanti-server/demo-server/minetest.conf")]
+    config: PathBuf,
+
+    /// Listen on port (overrides the configuration file)
     #[arg(group = "source", short, long)]
     listen: Option<u16>,
 
-    /// Listen with specific bind address (ip:port)
+    /// Listen with specific bind address (ip:port; overrides the configuration file)
     #[arg(group = "source", short, long)]
     bind: Option<SocketAddr>,
 
+    /// Seed of the map generator for new worlds (overrides the configuration file)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Serve Prometheus metrics at this address (ip:port)
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
+
     /// Verbosity level (up to -vvv)
     #[arg(short, long, default_value_t = 0, action = clap::ArgAction::Count)]
     verbose: u8,
@@ -98,6 +120,8 @@ async fn real_main() -> anyhow::Result<()> {
         }
     });
 
+    let config = ServerConfig::load(&args.config)?;
+
     let bind_addr: SocketAddr = if let Some(listen_port) = args.listen {
         // TODO(kawogi) re-enable IPv6 support
         if true {
@@ -108,27 +132,26 @@ async fn real_main() -> anyhow::Result<()> {
     } else if let Some(bind_addr) = args.bind {
         bind_addr
     } else {
-        bail!("One of --listen or --bind must be specified");
+        config.bind_addr
     };
     info!("Starting demo server on {bind_addr}");
 
     let mut media_registry = MediaRegistry::default();
-    media_registry
-        .load_directory("luanti-server/demo-server/assets")
-        .context("failed to load assets")?;
-
-    let mut content_id_map = ContentIdMap::new();
-    let content_id_stone = content_id_map.push(SharedStr::from_borrowed("basenodes:stone"))?;
-    let content_id_sand = content_id_map.push(SharedStr::from_borrowed("basenodes:sand"))?;
-    let content_id_dirt_with_grass =
-        content_id_map.push(SharedStr::from_borrowed("basenodes:dirt_with_grass"))?;
-    let content_id_dirt = content_id_map.push(SharedStr::from_borrowed("basenodes:dirt"))?;
-    let content_id_water_source =
-        content_id_map.push(SharedStr::from_borrowed("basenodes:water_source"))?;
-    let content_id_water_flowing =
-        content_id_map.push(SharedStr::from_borrowed("basenodes:water_flowing"))?;
-    let content_id_block_of_rust =
-        content_id_map.push(SharedStr::from_borrowed("demo:block_of_rust"))?;
+    for media_path in &config.media_paths {
+        media_registry
+            .load_directory(media_path)
+            .with_context(|| format!("failed to load assets from {}", media_path.display()))?;
+    }
+
+    // ids which have been assigned in a previous run are kept
+    let content_ids = Arc::new(ContentIdMapper::open(&config.world_path)?);
+    let content_id_stone = content_ids.id(b"basenodes:stone")?;
+    let content_id_sand = content_ids.id(b"basenodes:sand")?;
+    let content_id_dirt_with_grass = content_ids.id(b"basenodes:dirt_with_grass")?;
+    let content_id_dirt = content_ids.id(b"basenodes:dirt")?;
+    let content_id_water_source = content_ids.id(b"basenodes:water_source")?;
+    let content_id_water_flowing = content_ids.id(b"basenodes:water_flowing")?;
+    let content_id_block_of_rust = content_ids.id(b"demo:block_of_rust")?;
 
     let tile_dirt = tile_def("demo_dirt.png");
     let tile_grass_east = tile_def("demo_grass_east.png");
@@ -185,22 +208,21 @@ async fn real_main() -> anyhow::Result<()> {
         ],
     };
 
-    let world_generator = MapgenFlat::new(content_id_block_of_rust);
-    let storage = pollster::block_on(MinetestworldStorage::new(
-        "worlds/luanti-rs",
-        Arc::new(content_id_map),
-    ))?;
+    let map_seed = world_seed(&config.world_path, args.seed.or(config.fixed_map_seed))?;
+    let world_generator = MapgenV7::new(
+        map_seed,
+        MapgenV7Nodes::basenodes(&content_ids.map())?,
+        MapgenV7Params::default(),
+    );
+    let storage = pollster::block_on(
+        config
+            .storage_backend
+            .open(&config.world_path, Arc::clone(&content_ids)),
+    )?;
 
     let (block_request_to_provider, block_request_from_router) = mpsc::unbounded_channel();
     let (block_interest_sender, block_interest_receiver) = mpsc::unbounded_channel();
     let (world_update_to_router, world_update_from_provider) = mpsc::unbounded_channel();
-    let _block_provider = MapBlockProvider::new(
-        block_request_from_router,
-        world_update_to_router,
-        Some(Box::new(storage)),
-        Some(Box::new(world_generator)),
-    );
-
     let mut server = LuantiWorldServer::new(
         bind_addr,
         args.verbose,
@@ -209,6 +231,56 @@ async fn real_main() -> anyhow::Result<()> {
         to_plugin_event_sender,
         from_plugin_event_receiver,
     );
+    server.set_max_clients(config.max_clients);
+    server.set_motd(config.motd);
+    server.set_server_info(config.server_info);
+    server.set_csm_restrictions(config.csm_restrictions);
+    server.set_view_config(config.view_config);
+    server.set_send_rate_config(config.send_rate);
+    server.set_health_config(config.health);
+    server.set_content_ids(content_ids);
+    server.set_map_seed(map_seed);
+    let clock = server.clock();
+    clock.set_time_speed(config.time_speed);
+    clock.set_time(config.start_time);
+    CLOCK.get_or_init(|| clock);
+    if let Some(spawn_point) = config.spawn_point {
+        server.set_spawn_point_provider(Arc::new(FixedSpawnPoint(spawn_point)));
+    }
+    server.set_player_store(Arc::new(FilePlayerStore::new(&config.world_path)?));
+    #[cfg(feature = "serverlist")]
+    let announcements = config.announce.map(|announce_config| {
+        serverlist::spawn_announcements(announce_config, server.status_provider())
+    });
+    #[cfg(not(feature = "serverlist"))]
+    if let Some(announce_config) = config.announce {
+        log::warn!(
+            "not announcing to {} as the `serverlist` feature is disabled",
+            announce_config.url
+        );
+    }
+    if let Some(metrics_addr) = args.metrics {
+        let server_metrics = server.metrics();
+        tokio::spawn(async move {
+            if let Err(error) = metrics::serve(server_metrics, metrics_addr).await {
+                log::error!("metrics endpoint failed: {error}");
+            }
+        });
+    }
+
+    let _block_provider = MapBlockProvider::new(
+        block_request_from_router,
+        world_update_to_router,
+        Some(storage),
+        Some(Box::new(world_generator)),
+        BlockCacheConfig::default(),
+        EmergeConfig::default(),
+        server.metrics(),
+    );
+
+    if let Some(backup_config) = config.backup {
+        let _backups = backup::spawn_backups(block_request_to_provider.clone(), backup_config);
+    }
 
     let _map_block_router = MapBlockRouter::new(
         block_request_to_provider,
@@ -217,19 +289,26 @@ async fn real_main() -> anyhow::Result<()> {
     );
 
     server.start(DummyAuthenticator, block_interest_sender);
-    #[expect(
-        clippy::infinite_loop,
-        reason = "// TODO implement a cancellation mechanism"
-    )]
-    loop {
-        tokio::time::sleep(Duration::from_secs(3600)).await;
+    tokio::signal::ctrl_c().await?;
+    info!("shutting down");
+    // remove the server from the public list before the players notice it's gone
+    #[cfg(feature = "serverlist")]
+    if let Some(announcements) = announcements {
+        announcements.stop().await;
     }
+    Ok(())
 
     // python_thread.join().unwrap();
 }
 
+/// Name of the formspec shown by the `inv` plugin function
+const INVENTORY_FORM_NAME: &str = "my_formspec";
+
 static API_SENDER: Mutex<ApiSender> = Mutex::new(ApiSender::new());
 
+/// the clock of the world, for answering the plugin's questions about the time
+static CLOCK: OnceLock<WorldClock> = OnceLock::new();
+
 struct ApiSender {
     sender: Option<UnboundedSender<FromPluginEvent>>,
 }
@@ -251,6 +330,7 @@ impl ApiSender {
     }
 }
 
+#[expect(clippy::too_many_lines, reason = "// TODO(kawogi) split this up")]
 fn run_python(mut receiver: UnboundedReceiver<ToPluginEvent>) -> anyhow::Result<()> {
     pyo3::append_to_inittab!(luanti);
     Python::attach(|py| {
@@ -278,12 +358,26 @@ fn run_python(mut receiver: UnboundedReceiver<ToPluginEvent>) -> anyhow::Result<
         let on_nodemeta_fields_fn = module.getattr("on_nodemeta_fields")?;
         let on_inventory_fields_fn = module.getattr("on_inventory_fields")?;
 
+        let mut form_dispatcher = FormDispatcher::new();
+        form_dispatcher.register(INVENTORY_FORM_NAME, |response| {
+            if response.is_quit() {
+                info!("inventory formspec has been closed");
+            }
+            Ok(())
+        });
+
         // let t = py.run(&code, None, None)?;
         // let user: String = py.eval(&code, None, None)?.extract()?;
 
         while let Some(event) = receiver.blocking_recv() {
             log::trace!("received plugin event: {event:?}");
 
+            // the plugin still sees the fields of registered forms, as it did before forms could
+            // be registered
+            if let Err(error) = form_dispatcher.dispatch_event(&event) {
+                log::error!("failed to handle form response: {error}");
+            }
+
             let response = match event {
                 ToPluginEvent::ModchannelJoin(ModchannelJoinSpec { channel_name }) => {
                     on_modchannel_join_fn.call1((channel_name,))
@@ -353,6 +447,9 @@ fn run_python(mut receiver: UnboundedReceiver<ToPluginEvent>) -> anyhow::Result<
                     client_formspec_name,
                     fields,
                 }) => on_inventory_fields_fn.call0(),
+                ToPluginEvent::RemovedSounds(_)
+                | ToPluginEvent::HaveMedia(_)
+                | ToPluginEvent::HpChange(_) => continue,
             };
 
             if let Err(error) = response {
@@ -365,16 +462,7 @@ fn run_python(mut receiver: UnboundedReceiver<ToPluginEvent>) -> anyhow::Result<
 }
 
 fn tile_def(name: &str) -> TileDef {
-    TileDef {
-        name: name.into(),
-        animation: TileAnimationParams::None,
-        backface_culling: true,
-        tileable_horizontal: false,
-        tileable_vertical: false,
-        color_rgb: None,
-        scale: 0,
-        align_style: AlignStyle::Node,
-    }
+    TileDef::new(name.into()).with_tileable(false, false)
 }
 
 fn content_features(
@@ -430,10 +518,17 @@ fn content_features(
 mod luanti {
 
     use luanti_protocol::commands::server_to_client::FovSpec;
+    use luanti_protocol::types::TrailingBytes;
     use luanti_server::api::FromPluginEvent;
+    use luanti_server::clock::DAY_NIGHT_RATIO_DAY;
+    use luanti_server::clock::WorldClock;
+    use luanti_server::formspec::Formspec;
+    use luanti_server::formspec::InventoryRef;
     use pyo3::prelude::*;
 
     use crate::API_SENDER;
+    use crate::CLOCK;
+    use crate::INVENTORY_FORM_NAME;
 
     #[pyfunction]
     fn fov(fov: f32) {
@@ -444,6 +539,7 @@ mod luanti {
                 fov,
                 is_multiplier: true,
                 transition_time: Some(1.0),
+                extra: TrailingBytes::default(),
             }));
     }
 
@@ -453,15 +549,32 @@ mod luanti {
             .lock()
             .unwrap()
             .send(FromPluginEvent::ShowFormspec(
-                luanti_protocol::commands::server_to_client::ShowFormspecSpec {
-                    form_spec: "size[8,7.5]
-image[1,0.6;1,2;player.png]
-list[current_player;main;0,3.5;8,4;]
-list[current_player;craft;3,0;3,3;]
-list[current_player;craftpreview;7,1;1,1;]"
-                        .into(),
-                    form_name: "my_formspec".into(),
-                },
+                Formspec::new()
+                    .size(8.0, 7.5)
+                    .image((1.0, 0.6), (1.0, 2.0), "player.png")
+                    .list(InventoryRef::CurrentPlayer, "main", (0.0, 3.5), (8, 4))
+                    .list(InventoryRef::CurrentPlayer, "craft", (3.0, 0.0), (3, 3))
+                    .list(
+                        InventoryRef::CurrentPlayer,
+                        "craftpreview",
+                        (7.0, 1.0),
+                        (1, 1),
+                    )
+                    .show(INVENTORY_FORM_NAME),
             ));
     }
+
+    /// Returns the time of day in ticks after midnight.
+    #[pyfunction]
+    fn time_of_day() -> u16 {
+        CLOCK.get().map_or(0, WorldClock::time_of_day)
+    }
+
+    /// Returns the day-night ratio the given player sees, from `0` (night) to `1000` (day).
+    #[pyfunction]
+    fn day_night_ratio(player: &str) -> u16 {
+        CLOCK
+            .get()
+            .map_or(DAY_NIGHT_RATIO_DAY, |clock| clock.day_night_ratio(player))
+    }
 }
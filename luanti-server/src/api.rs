@@ -1,19 +1,21 @@
+use crate::health::HpChange;
 use luanti_protocol::commands::{
     client_to_server::{
-        DamageSpec, InteractSpec, InventoryActionSpec, InventoryFieldsSpec, ModchannelJoinSpec,
-        ModchannelLeaveSpec, NodemetaFieldsSpec, PlayerItemSpec, PlayerPosCommand, RespawnSpec,
-        TSChatMessageSpec, TSModchannelMsgSpec,
+        DamageSpec, HaveMediaSpec, InteractSpec, InventoryActionSpec, InventoryFieldsSpec,
+        ModchannelJoinSpec, ModchannelLeaveSpec, NodemetaFieldsSpec, PlayerItemSpec,
+        PlayerPosCommand, RemovedSoundsSpec, RespawnSpec, TSChatMessageSpec, TSModchannelMsgSpec,
     },
     server_to_client::{
         AcceptSudoModeSpec, AccessDeniedCommand, ActiveObjectMessagesCommand,
         ActiveObjectRemoveAddSpec, AddParticlespawnerCommand, AddnodeSpec, AnnounceMediaSpec,
         AuthAcceptSpec, BreathSpec, CloudParamsSpec, CsmRestrictionFlagsSpec, DeathscreenSpec,
         DeleteParticlespawnerSpec, DenySudoModeSpec, DetachedInventorySpec, EyeOffsetSpec,
-        FadeSoundSpec, FormspecPrependSpec, FovSpec, HelloSpec, HpSpec, InventoryFormspecSpec,
-        InventorySpec, ItemdefCommand, LocalPlayerAnimationsSpec, MediaPushSpec, MinimapModesSpec,
-        ModchannelSignalSpec, MovePlayerSpec, MovementSpec, NodedefSpec, NodemetaChangedSpec,
-        OverrideDayNightRatioSpec, PlaySoundSpec, PlayerSpeedSpec, PrivilegesSpec, RemovenodeSpec,
-        SetLightingSpec, SetMoonSpec, SetSkyCommand, SetStarsSpec, SetSunSpec, ShowFormspecSpec,
+        FadeSoundSpec, FormspecPrependSpec, FovSpec, HelloSpec, HpSpec, HudaddSpec,
+        HudchangeCommand, HudrmSpec, InventoryFormspecSpec, InventorySpec, ItemdefCommand,
+        LocalPlayerAnimationsSpec, MediaPushSpec, MinimapModesSpec, ModchannelSignalSpec,
+        MovePlayerSpec, MovementSpec, NodedefSpec, NodemetaChangedSpec, OverrideDayNightRatioSpec,
+        PlaySoundSpec, PlayerSpeedSpec, PrivilegesSpec, RemovenodeSpec, SetLightingSpec,
+        SetMoonSpec, SetSkyCommand, SetStarsSpec, SetSunSpec, ShowFormspecSpec,
         SpawnParticleCommand, SrpBytesSBSpec, StopSoundSpec, TCChatMessageSpec,
         TCModchannelMsgSpec, TimeOfDaySpec, UpdatePlayerListSpec,
     },
@@ -33,6 +35,14 @@ pub enum ToPluginEvent {
     Interact(InteractSpec),
     NodemetaFields(NodemetaFieldsSpec),
     InventoryFields(InventoryFieldsSpec),
+    /// the client stopped playing the sounds with the given ids, because they ended or have been
+    /// faded out; see [`crate::sound::SoundManager::removed_by_client`]
+    RemovedSounds(RemovedSoundsSpec),
+    /// the client received the dynamic media with the given tokens (see
+    /// [`FromPluginEvent::MediaPush`])
+    HaveMedia(HaveMediaSpec),
+    /// the hit points of the player changed, e.g. by falling, drowning or respawning
+    HpChange(HpChange),
 }
 
 #[derive(Debug)]
@@ -56,6 +66,12 @@ pub enum FromPluginEvent {
     MovePlayer(MovePlayerSpec),
     // AccessDeniedLegacy(AccessDeniedLegacySpec),
     Fov(FovSpec),
+    /// shows a new HUD element, e.g. one managed by [`crate::hud::HudManager`]
+    Hudadd(HudaddSpec),
+    /// removes a HUD element
+    Hudrm(HudrmSpec),
+    /// changes a single property of a HUD element
+    Hudchange(HudchangeCommand),
     Deathscreen(DeathscreenSpec),
     Nodedef(NodedefSpec),
     AnnounceMedia(AnnounceMediaSpec),
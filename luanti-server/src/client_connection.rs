@@ -1,25 +1,61 @@
 //! A single client connection to the server
 
 mod authenticating;
+mod dispatch;
 mod loading;
 mod running;
 mod setup;
 mod uninitialized;
 
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use crate::MediaRegistry;
 use crate::api::FromPluginEvent;
 use crate::api::ToPluginEvent;
 use crate::authentication::Authenticator;
-use crate::world::WorldBlock;
+use crate::clock::WorldClock;
+use crate::connections::ConnectionInfo;
+use crate::connections::ConnectionState;
+use crate::connections::Connections;
+use crate::flood::FloodConfig;
+use crate::flood::FloodGuard;
+use crate::flood::FloodVerdict;
+use crate::formspec::node_forms::NodeFormRules;
+use crate::formspec::node_forms::NodeInteractions;
+use crate::formspec::version::client_formspec_version;
+use crate::formspec::version::degrade_spec;
+use crate::health::HealthRules;
+use crate::inventory::InventoryManager;
+use crate::load_budget::LoadBudget;
+use crate::metrics::ServerMetrics;
+use crate::mod_channels::ModChannels;
+use crate::movement::MovementMetrics;
+use crate::movement::MovementTolerances;
+use crate::movement::MovementValidator;
+use crate::player_store::PlayerData;
+use crate::player_store::PlayerStore;
+use crate::send_rate::SendRateConfig;
+use crate::send_rate::UpdateBatch;
 use crate::world::WorldUpdate;
 use crate::world::map_block_router::ToRouterMessage;
+use crate::world::unknown_nodes::UnknownNodeFilter;
+use crate::world::view_tracker::InterestSnapshots;
+use crate::world::view_tracker::ViewConfig;
 use crate::world::view_tracker::ViewTracker;
 use anyhow::Result;
 use anyhow::anyhow;
 use authenticating::AuthenticatingState;
 use flexstr::SharedStr;
+use glam::Vec3;
 use loading::LoadingState;
 use log::debug;
 use log::error;
@@ -28,34 +64,262 @@ use log::trace;
 use luanti_protocol::CommandDirection;
 use luanti_protocol::CommandRef;
 use luanti_protocol::LuantiConnection;
+use luanti_protocol::commands::CommandProperties;
 use luanti_protocol::commands::client_to_server::ToServerCommand;
+use luanti_protocol::commands::server_to_client::ActiveObjectMessage;
+use luanti_protocol::commands::server_to_client::ActiveObjectMessagesCommand;
+use luanti_protocol::commands::server_to_client::AddnodeSpec;
 use luanti_protocol::commands::server_to_client::BlockdataSpec;
+use luanti_protocol::commands::server_to_client::BreathSpec;
+use luanti_protocol::commands::server_to_client::CsmRestrictionFlagsSpec;
+use luanti_protocol::commands::server_to_client::CsmRestrictions;
+use luanti_protocol::commands::server_to_client::DisconnectReason;
+use luanti_protocol::commands::server_to_client::HpSpec;
+use luanti_protocol::commands::server_to_client::InventorySpec;
+use luanti_protocol::commands::server_to_client::MovePlayerSpec;
+use luanti_protocol::commands::server_to_client::NodemetaChangedSpec;
+use luanti_protocol::commands::server_to_client::TCChatMessageSpec;
 use luanti_protocol::commands::server_to_client::ToClientCommand;
-use luanti_protocol::peer::PeerError;
-use luanti_protocol::types::MapNodesBulk;
+use luanti_protocol::peer::Disconnect;
+use luanti_protocol::types::AOCUpdatePosition;
+use luanti_protocol::types::AbsBlockPos;
+use luanti_protocol::types::AbsNodeMetadataList;
+use luanti_protocol::types::ActiveObjectCommand;
+use luanti_protocol::types::Inventory;
 use luanti_protocol::types::NodeDefManager;
-use luanti_protocol::types::NodeMetadataList;
-use luanti_protocol::types::TransferrableMapBlock;
+use luanti_protocol::types::NodeMetadata;
+use luanti_protocol::types::TrailingBytes;
+use luanti_protocol::versions::ClientVersion;
+use luanti_protocol::wire::compression::CompressionConfig;
 use running::RunningState;
 use setup::SetupState;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use uninitialized::UninitializedState;
 
+/// Players are being saved at least this often while they're online
+const PLAYER_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The metrics derived from the peer's statistics are updated this often
+const METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The surroundings of the player (e.g. hazardous nodes and breath) are being simulated this often
+const ENVIRONMENT_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The type of chat messages sent by the server itself
+const CHAT_MESSAGE_TYPE_SYSTEM: u8 = 3;
+
+/// Kicked clients are given this much time to receive the reason before they're dropped
+const KICK_LINGER: Duration = Duration::from_secs(1);
+
+/// What other parts of the server may ask a connection to do
+#[derive(Debug)]
+pub(crate) enum ConnectionRequest {
+    /// disconnect the player, telling the client whether it shall reconnect
+    Kick(DisconnectReason, bool),
+    /// forward a command to the client
+    Send(ToClientCommand),
+}
+
+/// The players who are currently online, by name
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectedPlayers {
+    players: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<ConnectionRequest>>>>,
+}
+
+impl ConnectedPlayers {
+    /// Locks the players; a panic while holding the lock leaves the map consistent, as every
+    /// change is a single insertion or removal.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, mpsc::UnboundedSender<ConnectionRequest>>> {
+        self.players.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Asks the connection of a player to disconnect; returns `false` if the player is offline.
+    pub(crate) fn kick(&self, name: &str, reason: DisconnectReason, reconnect: bool) -> bool {
+        self.request(name, ConnectionRequest::Kick(reason, reconnect))
+    }
+
+    /// Sends a command to a player; returns `false` if the player is offline.
+    pub(crate) fn send(&self, name: &str, command: ToClientCommand) -> bool {
+        self.request(name, ConnectionRequest::Send(command))
+    }
+
+    fn request(&self, name: &str, request: ConnectionRequest) -> bool {
+        self.lock()
+            .get(name)
+            .is_some_and(|sender| sender.send(request).is_ok())
+    }
+
+    /// Sends a command to all players who are online and returns their number.
+    pub(crate) fn broadcast(&self, command: &ToClientCommand) -> usize {
+        self.lock()
+            .values()
+            .filter(|sender| {
+                sender
+                    .send(ConnectionRequest::Send(command.clone()))
+                    .is_ok()
+            })
+            .count()
+    }
+
+    /// Returns the names of the players who are online.
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    /// Returns the number of players who are online.
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn register(&self, name: &str, sender: mpsc::UnboundedSender<ConnectionRequest>) {
+        self.lock().insert(name.to_owned(), sender);
+    }
+
+    /// Removes a player if they're registered with the given connection; returns `false` if
+    /// they aren't.
+    fn unregister(&self, name: &str, sender: &mpsc::UnboundedSender<ConnectionRequest>) -> bool {
+        let mut players = self.lock();
+        // the player might already have joined again through another connection
+        let registered = players
+            .get(name)
+            .is_some_and(|registered| registered.same_channel(sender));
+        if registered {
+            players.remove(name);
+        }
+        registered
+    }
+}
+
+/// The settings of all connections; fixed once the server has been started
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionConfig {
+    /// the protocol versions clients may connect with
+    pub(crate) protocol_versions: RangeInclusive<u16>,
+    /// how many of the exchanged commands are being logged
+    pub(crate) verbosity: u8,
+    /// `None` if the movements of players aren't being validated
+    pub(crate) movement_tolerances: Option<MovementTolerances>,
+    pub(crate) view_config: ViewConfig,
+    pub(crate) send_rate: SendRateConfig,
+    pub(crate) flood_config: FloodConfig,
+    /// further clients are rejected if this many players are online
+    pub(crate) max_clients: usize,
+    /// shown to the player after joining unless empty
+    pub(crate) motd: SharedStr,
+    /// sent to the client after the definitions
+    pub(crate) csm_restrictions: CsmRestrictions,
+    /// sent to the client once it's authenticated
+    pub(crate) map_seed: u64,
+    /// how payloads like the node definitions and map blocks are being compressed
+    pub(crate) compression: CompressionConfig,
+}
+
+/// The parts of the server which are shared by all connections
+#[derive(Clone)]
+pub(crate) struct ServerContext {
+    pub(crate) block_interest_sender: mpsc::UnboundedSender<ToRouterMessage>,
+    pub(crate) node_def: Arc<NodeDefManager>,
+    pub(crate) media: Arc<MediaRegistry>,
+    pub(crate) load_budget: Arc<LoadBudget>,
+    pub(crate) player_store: Option<Arc<dyn PlayerStore>>,
+    pub(crate) movement_metrics: MovementMetrics,
+    pub(crate) health_rules: HealthRules,
+    pub(crate) node_forms: Arc<NodeFormRules>,
+    pub(crate) unknown_node_filter: Arc<UnknownNodeFilter>,
+    pub(crate) plugin_event_sender: mpsc::UnboundedSender<ToPluginEvent>,
+    pub(crate) plugin_events: PluginEvents,
+    pub(crate) connected_players: ConnectedPlayers,
+    pub(crate) connections: Connections,
+    pub(crate) mod_channels: ModChannels,
+    pub(crate) clock: WorldClock,
+    pub(crate) inventories: InventoryManager,
+    /// the interests of players who left, restored when they join again
+    pub(crate) interest_snapshots: InterestSnapshots,
+    pub(crate) metrics: ServerMetrics,
+}
+
+/// The events of the plugins, which are being handled by one connection at a time
+///
+/// The plugins don't address players, so the connection which took the events handles all of them
+/// until it closes. Then the next connection being opened takes them over.
+#[derive(Debug, Clone)]
+pub(crate) struct PluginEvents {
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<FromPluginEvent>>>>,
+}
+
+impl PluginEvents {
+    pub(crate) fn new(receiver: mpsc::UnboundedReceiver<FromPluginEvent>) -> Self {
+        Self {
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    /// Locks the receiver; a panic while holding the lock leaves it consistent, as every change
+    /// is a single replacement.
+    fn lock(&self) -> MutexGuard<'_, Option<mpsc::UnboundedReceiver<FromPluginEvent>>> {
+        self.receiver.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes the events unless another connection handles them already.
+    fn take(&self) -> Option<mpsc::UnboundedReceiver<FromPluginEvent>> {
+        self.lock().take()
+    }
+
+    /// Hands the events over to the next connection.
+    fn put_back(&self, receiver: mpsc::UnboundedReceiver<FromPluginEvent>) {
+        self.lock().replace(receiver);
+    }
+}
+
 pub(crate) struct ClientConnection<Auth: Authenticator> {
     id: u64,
     connection: LuantiConnection,
-    verbosity: u8,
+    config: Arc<ConnectionConfig>,
     state: State<Auth>,
+    /// the protocol version negotiated with the client; `None` before the client introduced itself
+    protocol_version: Option<u16>,
     language: Option<String>,
+    /// the engine version of the client; `None` until the client finished loading
+    client_version: Option<ClientVersion>,
     player_key: SharedStr,
     block_interest_sender: Option<mpsc::UnboundedSender<ToRouterMessage>>,
     world_update_sender: Option<mpsc::UnboundedSender<WorldUpdate>>,
     world_update_receiver: mpsc::UnboundedReceiver<WorldUpdate>,
     node_def: Arc<NodeDefManager>,
     media: Arc<MediaRegistry>,
+    load_budget: Arc<LoadBudget>,
+    player_store: Option<Arc<dyn PlayerStore>>,
+    /// the persistent state of the player; only meaningful after authentication
+    player: PlayerData,
+    /// whether `player` has been loaded from the `player_store`
+    player_restored: bool,
+    movement_metrics: MovementMetrics,
+    /// throttled updates waiting for the next tick of the send rate
+    pending_updates: UpdateBatch,
+    flood_guard: FloodGuard,
+    health_rules: HealthRules,
+    node_forms: Arc<NodeFormRules>,
+    unknown_node_filter: Arc<UnknownNodeFilter>,
     plugin_event_sender: mpsc::UnboundedSender<ToPluginEvent>,
-    from_plugin_event_receiver: mpsc::UnboundedReceiver<FromPluginEvent>,
+    /// `None` while another connection handles the events of the plugins
+    from_plugin_event_receiver: Option<mpsc::UnboundedReceiver<FromPluginEvent>>,
+    plugin_events: PluginEvents,
+    connected_players: ConnectedPlayers,
+    connections: Connections,
+    mod_channels: ModChannels,
+    clock: WorldClock,
+    inventories: InventoryManager,
+    /// the interests of players who left, restored when they join again
+    interest_snapshots: InterestSnapshots,
+    request_sender: mpsc::UnboundedSender<ConnectionRequest>,
+    request_receiver: mpsc::UnboundedReceiver<ConnectionRequest>,
+    metrics: ServerMetrics,
+    /// the number of retransmitted packets which have already been added to the `metrics`
+    reported_retransmits: u64,
+    /// the span all events of this connection are attributed to
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
@@ -63,31 +327,82 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
         id: u64,
         connection: LuantiConnection,
         authenticator: Auth,
-        verbosity: u8,
-        block_interest_sender: mpsc::UnboundedSender<ToRouterMessage>,
-        node_def: Arc<NodeDefManager>,
-        media: Arc<MediaRegistry>,
-        plugin_event_sender: mpsc::UnboundedSender<ToPluginEvent>,
-        from_plugin_event_receiver: mpsc::UnboundedReceiver<FromPluginEvent>,
+        config: Arc<ConnectionConfig>,
+        context: &ServerContext,
     ) -> JoinHandle<()> {
+        let ServerContext {
+            block_interest_sender,
+            node_def,
+            media,
+            load_budget,
+            player_store,
+            movement_metrics,
+            health_rules,
+            node_forms,
+            unknown_node_filter,
+            plugin_event_sender,
+            plugin_events,
+            connected_players,
+            connections,
+            mod_channels,
+            clock,
+            inventories,
+            interest_snapshots,
+            metrics,
+        } = context.clone();
         let (world_update_sender, world_update_receiver) = mpsc::unbounded_channel();
+        let (request_sender, request_receiver) = mpsc::unbounded_channel();
 
         let runner = ClientConnection {
             id,
             connection,
-            verbosity,
-            state: State::Uninitialized(UninitializedState::new(authenticator)),
+            state: State::Uninitialized(UninitializedState::new(
+                authenticator,
+                config.protocol_versions.clone(),
+            )),
+            protocol_version: None,
             language: None,
+            client_version: None,
             block_interest_sender: Some(block_interest_sender),
             player_key: SharedStr::empty(),
             world_update_sender: Some(world_update_sender),
             world_update_receiver,
             node_def,
             media,
+            load_budget,
+            player_store,
+            player: PlayerData::default(),
+            player_restored: false,
+            movement_metrics,
+            pending_updates: UpdateBatch::default(),
+            flood_guard: FloodGuard::new(config.flood_config, Instant::now()),
+            health_rules,
+            node_forms,
+            unknown_node_filter,
             plugin_event_sender,
-            from_plugin_event_receiver,
+            from_plugin_event_receiver: plugin_events.take(),
+            plugin_events,
+            connected_players,
+            connections,
+            mod_channels,
+            clock,
+            inventories,
+            interest_snapshots,
+            request_sender,
+            request_receiver,
+            metrics,
+            config,
+            reported_retransmits: 0,
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("connection", id, player = tracing::field::Empty),
         };
-        tokio::spawn(runner.run())
+        #[cfg(feature = "tracing")]
+        let span = runner.span.clone();
+        runner.publish_info();
+        let run = runner.run();
+        #[cfg(feature = "tracing")]
+        let run = tracing::Instrument::instrument(run, span);
+        tokio::spawn(run)
     }
 
     async fn run(mut self) {
@@ -99,33 +414,66 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
         match self.run_inner().await {
             Ok(()) => (),
             Err(err) => {
-                let show_err = if let Some(err) = err.downcast_ref::<PeerError>() {
-                    !matches!(err, PeerError::PeerSentDisconnect)
+                if let Some(disconnect) = err.downcast_ref::<Disconnect>() {
+                    info!("[{}] {disconnect}", self.id);
                 } else {
-                    true
-                };
-                if show_err {
                     error!("[{}] Disconnected: {:?}", self.id, err);
-                } else {
-                    info!("[{}] Disconnected", self.id);
                 }
             }
         }
+        self.save_player();
+        self.report_retransmits();
+        if matches!(self.state, State::Running(_)) {
+            self.metrics.player_left();
+        }
+        // the player's state belongs to another connection if they already joined again
+        if self
+            .connected_players
+            .unregister(&self.player_key, &self.request_sender)
+        {
+            self.mod_channels.leave_all(&self.player_key);
+            self.clock.leave(&self.player_key);
+            if let State::Running(state) = &self.state {
+                state.leave();
+            }
+        }
+        self.connections.remove(self.id);
+        if let Some(receiver) = self.from_plugin_event_receiver.take() {
+            self.plugin_events.put_back(receiver);
+        }
     }
 
+    #[expect(clippy::too_many_lines, reason = "// TODO split this up")]
     async fn run_inner(&mut self) -> Result<()> {
         enum Event {
             ClientMessage(Result<ToServerCommand>),
             WorldUpdate(Option<WorldUpdate>),
             FromPlugin(Option<FromPluginEvent>),
+            Request(Option<ConnectionRequest>),
+            SavePlayer,
+            UpdateMetrics,
+            StepEnvironment,
+            FlushUpdates,
         }
 
+        let mut save_interval = tokio::time::interval(PLAYER_SAVE_INTERVAL);
+        let mut metrics_interval = tokio::time::interval(METRICS_INTERVAL);
+        let mut environment_interval = tokio::time::interval(ENVIRONMENT_STEP_INTERVAL);
+        environment_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut send_interval = tokio::time::interval(self.config.send_rate.interval);
+        send_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_step = Instant::now();
         loop {
             // TODO(kawogi) review whether this should be refactored; all state transitions seem to be expressible as a simple sequence and do not require a full-fledged state machine
             let event = tokio::select! {
                 message = self.connection.recv() => Event::ClientMessage(message),
                 message = self.world_update_receiver.recv() => Event::WorldUpdate(message),
-                message = self.from_plugin_event_receiver.recv() => Event::FromPlugin(message),
+                message = next_plugin_event(self.from_plugin_event_receiver.as_mut()) => Event::FromPlugin(message),
+                message = self.request_receiver.recv() => Event::Request(message),
+                _ = save_interval.tick() => Event::SavePlayer,
+                _ = metrics_interval.tick() => Event::UpdateMetrics,
+                _ = environment_interval.tick() => Event::StepEnvironment,
+                _ = send_interval.tick() => Event::FlushUpdates,
             };
 
             match event {
@@ -147,43 +495,202 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
                     let Some(message) = message else {
                         anyhow::bail!("plugin sender has been disconnected");
                     };
-                    match message {
-                        FromPluginEvent::Fov(fov) => {
-                            if self.connection.send(fov).is_err() {
-                                error!("failed to send API command");
+                    let command: ToClientCommand = match message {
+                        FromPluginEvent::Fov(spec) => spec.into(),
+                        FromPluginEvent::ShowFormspec(spec) => {
+                            degrade_spec(spec, self.formspec_version(), &self.player_key).into()
+                        }
+                        FromPluginEvent::Hudadd(spec) => spec.into(),
+                        FromPluginEvent::Hudrm(spec) => spec.into(),
+                        FromPluginEvent::Hudchange(command) => command.into(),
+                        FromPluginEvent::SetSky(command) => command.into(),
+                        FromPluginEvent::SetSun(spec) => spec.into(),
+                        FromPluginEvent::SetMoon(spec) => spec.into(),
+                        FromPluginEvent::SetStars(spec) => spec.into(),
+                        FromPluginEvent::CloudParams(spec) => spec.into(),
+                        FromPluginEvent::SetLighting(spec) => spec.into(),
+                        FromPluginEvent::PlaySound(spec) => spec.into(),
+                        FromPluginEvent::StopSound(spec) => spec.into(),
+                        FromPluginEvent::FadeSound(spec) => spec.into(),
+                        FromPluginEvent::ModchannelSignal(spec) => spec.into(),
+                        FromPluginEvent::TimeOfDay(spec) => {
+                            // the time is shared, so everyone gets the update
+                            self.clock.set_time_of_day(&spec);
+                            continue;
+                        }
+                        FromPluginEvent::OverrideDayNightRatio(spec) => {
+                            let ratio = spec.do_override.then_some(spec.day_night_ratio);
+                            self.clock.set_override(&self.player_key, ratio);
+                            spec.into()
+                        }
+                        FromPluginEvent::TCModchannelMsg(spec) => {
+                            // the plugin speaks for the server, so everyone gets the message
+                            self.mod_channels.broadcast(&spec, None);
+                            continue;
+                        }
+                        FromPluginEvent::MovePlayer(spec) => {
+                            self.player.position = spec.pos / 10.0;
+                            self.player.pitch = spec.pitch;
+                            self.player.yaw = spec.yaw;
+                            if let State::Running(state) = &mut self.state {
+                                state.teleport(self.player.position);
                             }
+                            spec.into()
                         }
-                        FromPluginEvent::ShowFormspec(spec) => {
-                            if self.connection.send(spec).is_err() {
-                                error!("failed to send API command");
+                        FromPluginEvent::Movement(spec) => {
+                            if let State::Running(state) = &mut self.state {
+                                state.set_physics(spec.clone());
+                            }
+                            spec.into()
+                        }
+                        FromPluginEvent::Hp(spec) => {
+                            if let State::Running(state) = &mut self.state {
+                                state.set_hp(&spec, &mut self.player, &self.connection)?;
+                                continue;
+                            }
+                            self.player.hp = spec.hp;
+                            spec.into()
+                        }
+                        FromPluginEvent::Deathscreen(spec) => {
+                            if let State::Running(state) = &mut self.state {
+                                state.show_death_screen();
                             }
+                            spec.into()
+                        }
+                        FromPluginEvent::Breath(spec) => {
+                            self.player.breath = spec.breath;
+                            spec.into()
+                        }
+                        FromPluginEvent::Inventory(spec) => {
+                            self.player.update_inventory(&spec.inventory);
+                            spec.into()
                         }
+                        FromPluginEvent::Privileges(spec) => {
+                            self.player.privileges.clone_from(&spec.privileges);
+                            spec.into()
+                        }
+                        FromPluginEvent::ActiveObjectMessages(command) => command.into(),
+                        FromPluginEvent::UpdatePlayerList(spec) => spec.into(),
                         other => {
                             error!("unhandled API call: {other:?}");
+                            continue;
                         }
+                    };
+                    if self.send_throttled(command).is_err() {
+                        error!("failed to send API command");
+                    }
+                }
+                Event::Request(message) => {
+                    let Some(message) = message else {
+                        anyhow::bail!("request sender has been disconnected");
+                    };
+                    let (reason, reconnect) = match message {
+                        ConnectionRequest::Kick(reason, reconnect) => (reason, reconnect),
+                        ConnectionRequest::Send(command) => {
+                            self.send_throttled(command)?;
+                            continue;
+                        }
+                    };
+                    info!(
+                        "[{}] kicking player '{}': {}",
+                        self.id,
+                        self.player_key,
+                        reason.message()
+                    );
+                    self.connection.disconnect(&reason, reconnect)?;
+                    self.linger().await;
+                    return Ok(());
+                }
+                Event::SavePlayer => self.save_player(),
+                Event::UpdateMetrics => {
+                    self.report_retransmits();
+                    self.publish_info();
+                }
+                Event::StepEnvironment => {
+                    let now = Instant::now();
+                    if let State::Running(state) = &mut self.state {
+                        state.step(
+                            now.duration_since(last_step),
+                            &mut self.player,
+                            &self.connection,
+                        )?;
                     }
+                    last_step = now;
                 }
+                Event::FlushUpdates => self.flush_updates()?,
             }
         }
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(command = message.command_name()))
+    )]
     async fn handle_client_message(&mut self, message: ToServerCommand) -> Result<()> {
+        let previous_state = self.state.connection_state();
+        if dispatch::handling_state(&message) != previous_state {
+            debug!(
+                "[{}] dropping {} of '{}' while {previous_state}",
+                self.id,
+                message.command_name(),
+                self.player_key
+            );
+            self.metrics.command_unhandled(message.command_name());
+            return Ok(());
+        }
+        self.handle_client_message_inner(message).await?;
+        if self.state.connection_state() != previous_state {
+            self.publish_info();
+        }
+        Ok(())
+    }
+
+    #[expect(clippy::too_many_lines, reason = "// TODO split this up")]
+    async fn handle_client_message_inner(&mut self, message: ToServerCommand) -> Result<()> {
         match &mut self.state {
             State::Uninitialized(state) => {
-                if state.handle_message(message, &self.connection).await? {
+                let initialized = match state.handle_message(message, &self.connection).await {
+                    Ok(initialized) => initialized,
+                    Err(error) => {
+                        // give the client a chance to receive the `AccessDenied`
+                        if state.is_rejected() {
+                            self.linger().await;
+                        }
+                        return Err(error);
+                    }
+                };
+                if initialized {
                     debug!(
                         "initialization successfully completed; switching to authentication mode"
                     );
+                    self.protocol_version = state.protocol_version();
                     let next_state = state.next();
                     self.player_key = next_state.player_key().to_owned().into();
+                    #[cfg(feature = "tracing")]
+                    self.span.record("player", &*self.player_key);
                     self.state = State::Authenticating(next_state);
+                    if self.connected_players.len() >= self.config.max_clients {
+                        info!("[{}] rejecting client: too many users", self.id);
+                        self.connection
+                            .disconnect(&DisconnectReason::TooManyUsers, false)?;
+                        self.linger().await;
+                        anyhow::bail!("too many users");
+                    }
                 } else {
                     debug!("initialization is still incomplete");
                 }
             }
             State::Authenticating(state) => {
-                if state.handle_message(message, &self.connection)? {
+                if state.handle_message(
+                    message,
+                    &self.connection,
+                    &self.config.send_rate,
+                    self.config.map_seed,
+                )? {
                     debug!("authentication successfully completed; switching to setup mode");
+                    self.connected_players
+                        .register(&self.player_key, self.request_sender.clone());
+                    self.load_player();
                     self.state = State::Setup(SetupState::new());
                 } else {
                     debug!("authentication is still incomplete");
@@ -201,14 +708,39 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
                         // sending out all media to the client
                         unreachable!();
                     };
-                    loading_state.send_data(&self.connection, &self.node_def, &self.media)?;
+                    loading_state
+                        .send_data(
+                            &self.connection,
+                            &self.node_def,
+                            &self.media,
+                            &self.load_budget,
+                        )
+                        .await?;
+                    // the client loads its mods once it knows the restrictions
+                    self.connection
+                        .send(CsmRestrictionFlagsSpec::from(self.config.csm_restrictions))?;
                 } else {
                     debug!("setup is still incomplete");
                 }
             }
             State::Loading(state) => {
-                if state.handle_message(message, &self.connection)? {
+                if state
+                    .handle_message(
+                        message,
+                        &self.connection,
+                        &self.load_budget,
+                        &self.player.privileges,
+                    )
+                    .await?
+                {
                     debug!("loading successfully completed; switching to authenticated mode");
+                    self.client_version = state.version().cloned();
+                    info!(
+                        "[{}] player '{}' joined using protocol version {}",
+                        self.id,
+                        self.player_key,
+                        self.protocol_version.unwrap_or_default()
+                    );
 
                     let block_interest_sender = self
                         .block_interest_sender
@@ -220,28 +752,189 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
                         .ok_or(anyhow!("tried to take world_update_sender twice"))?;
                     let view_tracker = ViewTracker::new(
                         self.player_key.clone(),
+                        self.config.view_config,
                         block_interest_sender,
                         world_update_sender,
+                        self.interest_snapshots.clone(),
                     )?;
 
+                    let movement_validator = self.config.movement_tolerances.map(|tolerances| {
+                        MovementValidator::new(
+                            &self.player_key,
+                            self.player.position,
+                            tolerances,
+                            self.movement_metrics.clone(),
+                            Instant::now(),
+                        )
+                    });
                     self.state = State::Running(RunningState::new(
+                        self.player_key.clone(),
                         view_tracker,
                         self.plugin_event_sender.clone(),
+                        movement_validator,
+                        self.mod_channels.clone(),
+                        self.health_rules.clone(),
+                        NodeInteractions::new(
+                            Arc::clone(&self.node_forms),
+                            self.formspec_version(),
+                        ),
                     ));
+                    self.metrics.player_joined();
+                    self.connection.send(self.clock.time_of_day_spec())?;
+                    for spec in self.inventories.join(&self.player_key) {
+                        self.connection.send(spec)?;
+                    }
+                    if self.player_restored {
+                        self.send_player()?;
+                    }
+                    if let State::Running(running) = &mut self.state {
+                        running.join(&self.player, &self.connection)?;
+                    }
+                    self.send_motd()?;
                 } else {
                     debug!("loading is still incomplete");
                 }
             }
-            State::Running(state) => state.handle_message(message, &self.connection)?,
+            State::Running(state) => {
+                let verdict = self.flood_guard.check(&message, Instant::now());
+                match verdict {
+                    FloodVerdict::Accepted => {
+                        state.handle_message(message, &self.connection, &mut self.player)?;
+                    }
+                    FloodVerdict::Dropped | FloodVerdict::Muted(_) => {
+                        debug!(
+                            "[{}] dropping {} of '{}': {verdict:?}",
+                            self.id,
+                            message.command_name(),
+                            self.player_key
+                        );
+                        if matches!(message, ToServerCommand::TSChatMessage(_)) {
+                            if let Some(notice) = self.flood_guard.chat_notice(verdict) {
+                                self.send_system_message(notice)?;
+                            }
+                        }
+                    }
+                    FloodVerdict::Kick => {
+                        // handled like any other kick, so the client receives the reason
+                        self.request_sender.send(ConnectionRequest::Kick(
+                            FloodGuard::kick_reason(&message),
+                            false,
+                        ))?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a command right away unless it's being throttled by the send rate.
+    fn send_throttled(&mut self, command: ToClientCommand) -> Result<()> {
+        match self.pending_updates.push(command) {
+            Some(command) => self.connection.send(command),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends the updates which have been throttled since the previous tick of the send rate.
+    fn flush_updates(&mut self) -> Result<()> {
+        for command in self.pending_updates.flush() {
+            self.connection.send(command)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the connection alive until the client disconnects or [`KICK_LINGER`] has passed, so
+    /// a final command can still be delivered.
+    async fn linger(&mut self) {
+        let drain = async { while self.connection.recv().await.is_ok() {} };
+        if tokio::time::timeout(KICK_LINGER, drain).await.is_err() {
+            debug!("[{}] client didn't disconnect in time", self.id);
+        }
+    }
+
+    /// Loads the persistent state of the player who just authenticated.
+    fn load_player(&mut self) {
+        let Some(player_store) = &self.player_store else {
+            return;
+        };
+        match player_store.load_player(&self.player_key) {
+            Ok(Some(player)) => {
+                debug!("[{}] restored player '{}'", self.id, self.player_key);
+                self.player = player;
+                self.player_restored = true;
+            }
+            Ok(None) => debug!("[{}] new player '{}'", self.id, self.player_key),
+            // starting from scratch is better than refusing the player
+            Err(error) => error!(
+                "[{}] failed to load player '{}': {error:?}",
+                self.id, self.player_key
+            ),
         }
+    }
 
+    /// Sends the restored state of the player to the client.
+    fn send_player(&self) -> Result<()> {
+        let PlayerData {
+            position,
+            pitch,
+            yaw,
+            hp,
+            breath,
+            inventory: _,
+            privileges: _,
+        } = self.player;
+        self.connection.send(MovePlayerSpec {
+            pos: position * 10.0,
+            pitch,
+            yaw,
+        })?;
+        self.connection.send(HpSpec {
+            hp,
+            damage_effect: Some(false),
+            extra: TrailingBytes::default(),
+        })?;
+        self.connection.send(BreathSpec { breath })?;
+        self.connection.send(InventorySpec {
+            inventory: self.player.inventory(),
+        })?;
         Ok(())
     }
 
+    /// Shows the message of the day to the player.
+    fn send_motd(&self) -> Result<()> {
+        if self.config.motd.is_empty() {
+            return Ok(());
+        }
+        self.send_system_message(format!("# Server: {}", self.config.motd))
+    }
+
+    /// Shows a message of the server in the chat of the player.
+    fn send_system_message(&self, message: String) -> Result<()> {
+        self.connection.send(system_message(message))
+    }
+
+    /// Writes the persistent state of the player to the store if the player is in-game.
+    fn save_player(&self) {
+        let (Some(player_store), State::Running(_)) = (&self.player_store, &self.state) else {
+            return;
+        };
+        if let Err(error) = player_store.save_player(&self.player_key, &self.player) {
+            error!(
+                "[{}] failed to save player '{}': {error:?}",
+                self.id, self.player_key
+            );
+        }
+    }
+
     fn is_bulk_command<Cmd: CommandRef>(command: &Cmd) -> bool {
         matches!(
             command.toclient_ref(),
-            Some(ToClientCommand::Blockdata(_) | ToClientCommand::Media(_))
+            Some(
+                ToClientCommand::Blockdata(_)
+                    | ToClientCommand::FarBlocks(_)
+                    | ToClientCommand::Media(_)
+            )
         )
     }
 
@@ -251,7 +944,7 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
             CommandDirection::ToServer => "C->S",
         };
         let prefix = format!("[{}] {} ", self.id, dir);
-        let mut verbosity = self.verbosity;
+        let mut verbosity = self.config.verbosity;
         if verbosity == 2 && Self::is_bulk_command(command) {
             // Show the contents of smaller commands, but skip the huge ones
             verbosity = 1;
@@ -265,36 +958,123 @@ impl<Auth: Authenticator + 'static> ClientConnection<Auth> {
 
     fn handle_world_update(&mut self, update: WorldUpdate) -> Result<()> {
         match update {
-            WorldUpdate::NewMapBlock(world_block) => {
-                let WorldBlock {
-                    version: _,
-                    pos,
-                    is_underground,
-                    day_night_differs,
-                    lighting_complete,
-                    nodes,
-                    metadata,
-                } = world_block;
-
+            WorldUpdate::NewMapBlock(mut world_block) => {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!("block", pos = %world_block.pos).entered();
+                self.unknown_node_filter.apply(&mut world_block.nodes);
+                if let State::Running(state) = &mut self.state {
+                    state.block_sent(&world_block);
+                }
                 self.connection
                     .send(ToClientCommand::Blockdata(Box::new(BlockdataSpec {
-                        pos: pos.vec(),
-                        block: TransferrableMapBlock {
-                            is_underground,
-                            day_night_differs,
-                            generated: true,
-                            lighting_complete: Some(lighting_complete),
-                            nodes: MapNodesBulk { nodes: nodes.0 },
-                            node_metadata: NodeMetadataList { metadata },
-                        },
+                        pos: world_block.pos.vec(),
+                        block: world_block.into(),
                         network_specific_version: 2,
-                    })))
-                //
+                    })))?;
+                self.metrics.block_sent();
+                Ok(())
+            }
+            WorldUpdate::NodeChanged { pos, mut node } => {
+                self.unknown_node_filter.apply_node(&mut node);
+                if let State::Running(state) = &mut self.state {
+                    state.node_changed(pos, node.content_id);
+                }
+                // changes of the metadata are sent separately
+                self.connection.send(AddnodeSpec {
+                    pos: pos.0,
+                    node,
+                    keep_metadata: true,
+                })
+            }
+            WorldUpdate::NodeMetaChanged { pos, metadata } => {
+                // clients remove the metadata of a node if it's empty
+                let metadata = metadata.unwrap_or_else(|| NodeMetadata {
+                    stringvars: Vec::new(),
+                    inventory: Inventory {
+                        entries: Vec::new(),
+                    },
+                });
+                self.connection.send(NodemetaChangedSpec {
+                    list: AbsNodeMetadataList {
+                        metadata: vec![(AbsBlockPos::new(pos.0), metadata)],
+                    },
+                })
+            }
+            WorldUpdate::BlockUnloaded(pos) => {
+                trace!("map block {pos} has been unloaded");
+                Ok(())
+            }
+            WorldUpdate::EntityMoved {
+                id,
+                position,
+                velocity,
+                rotation,
+            } => {
+                let message = ActiveObjectMessage {
+                    id,
+                    data: ActiveObjectCommand::UpdatePosition(AOCUpdatePosition {
+                        position: position * 10.0,
+                        velocity: velocity * 10.0,
+                        acceleration: Vec3::ZERO,
+                        rotation,
+                        do_interpolate: true,
+                        is_end_position: false,
+                        update_interval: self.config.send_rate.interval.as_secs_f32(),
+                    }),
+                };
+                self.send_throttled(
+                    ActiveObjectMessagesCommand {
+                        objects: vec![message],
+                    }
+                    .into(),
+                )
+            }
+            WorldUpdate::Batch(updates) => {
+                for batched in updates {
+                    self.handle_world_update(batched)?;
+                }
+                Ok(())
             }
         }
     }
+
+    /// Returns the newest formspec version the client understands.
+    fn formspec_version(&self) -> u16 {
+        client_formspec_version(self.client_version.as_ref())
+    }
+
+    /// Publishes the current state of this connection to the [`Connections`].
+    fn publish_info(&self) {
+        let pending_blocks = match &self.state {
+            State::Running(state) => state.pending_blocks(),
+            _ => 0,
+        };
+        self.connections.update(ConnectionInfo {
+            id: self.id,
+            address: self.connection.remote_addr(),
+            state: self.state.connection_state(),
+            player_name: (!self.player_key.is_empty()).then(|| self.player_key.to_string()),
+            protocol_version: self.protocol_version,
+            language: self.language.clone(),
+            version: self.client_version.clone(),
+            rtt: self.connection.queue_stats().rtt(),
+            pending_blocks,
+        });
+    }
+
+    /// Adds the packets retransmitted since the previous report to the metrics.
+    fn report_retransmits(&mut self) {
+        let retransmits = self.connection.queue_stats().total_retransmitted();
+        self.metrics
+            .add_retransmits(retransmits.saturating_sub(self.reported_retransmits));
+        self.reported_retransmits = retransmits;
+    }
 }
 
+#[expect(
+    clippy::large_enum_variant,
+    reason = "there's only a single state per connection"
+)]
 enum State<Auth: Authenticator> {
     Uninitialized(UninitializedState<Auth>),
     Authenticating(AuthenticatingState),
@@ -302,3 +1082,39 @@ enum State<Auth: Authenticator> {
     Loading(LoadingState),
     Running(RunningState),
 }
+
+impl<Auth: Authenticator> State<Auth> {
+    fn connection_state(&self) -> ConnectionState {
+        match self {
+            Self::Uninitialized(_) => ConnectionState::Initializing,
+            Self::Authenticating(_) => ConnectionState::Authenticating,
+            Self::Setup(_) => ConnectionState::Setup,
+            Self::Loading(_) => ConnectionState::Loading,
+            Self::Running(_) => ConnectionState::Running,
+        }
+    }
+}
+
+/// Waits for the next event of the plugins; stays pending if another connection handles them.
+async fn next_plugin_event(
+    receiver: Option<&mut mpsc::UnboundedReceiver<FromPluginEvent>>,
+) -> Option<FromPluginEvent> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Creates a message of the server for the chat of a player.
+fn system_message(message: String) -> TCChatMessageSpec {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    TCChatMessageSpec {
+        version: 1,
+        message_type: CHAT_MESSAGE_TYPE_SYSTEM,
+        sender: String::new(),
+        message: message.into(),
+        timestamp,
+    }
+}
@@ -1,4 +1,5 @@
 use crate::authentication::SrpUserAuthData;
+use crate::send_rate::SendRateConfig;
 use anyhow::Result;
 use anyhow::anyhow;
 use anyhow::bail;
@@ -45,6 +46,8 @@ impl AuthenticatingState {
         &mut self,
         message: ToServerCommand,
         connection: &LuantiConnection,
+        send_rate: &SendRateConfig,
+        map_seed: u64,
     ) -> Result<bool> {
         match (&mut self.state, message) {
             // a `BytesA`-messages performs a state transition `Init` → `Init2`
@@ -66,6 +69,8 @@ impl AuthenticatingState {
                     verifier,
                     *srp_bytes_mspec,
                     connection,
+                    send_rate,
+                    map_seed,
                 )? {
                     self.state = SrpAuthState::Authenticated;
                     Ok(true)
@@ -130,6 +135,8 @@ impl AuthenticatingState {
         verifier: &Verifier,
         srp_bytes_mspec: SrpBytesMSpec,
         connection: &LuantiConnection,
+        send_rate: &SendRateConfig,
+        map_seed: u64,
     ) -> Result<bool> {
         let SrpBytesMSpec { bytes_m } = srp_bytes_mspec;
 
@@ -157,10 +164,8 @@ impl AuthenticatingState {
                 y: (0.0 + 0.5) * 10.0,
                 z: 0.0 * 10.0,
             },
-            // TODO(kawogi) load from actual map?
-            map_seed: 0,
-            // TODO(kawogi) what is this value?
-            recommended_send_interval: 0.05,
+            map_seed,
+            recommended_send_interval: send_rate.recommended_send_interval(),
             // TODO(kawogi) what is this value? look up `choseAuthMech` in original source code
             sudo_auth_methods: 2,
         };
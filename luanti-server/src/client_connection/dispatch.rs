@@ -0,0 +1,44 @@
+//! The state of a connection handling each command of a client
+//!
+//! Clients may send commands the current state isn't prepared for, e.g. a late `RequestMedia`
+//! after they've joined the game. Such commands are dropped before they reach the state and are
+//! counted in the [`ServerMetrics`](crate::metrics::ServerMetrics), rather than being ignored in
+//! a different way by every state.
+
+use luanti_protocol::commands::client_to_server::ToServerCommand;
+
+use crate::connections::ConnectionState;
+
+/// Returns the state handling a command; commands arriving in any other state are dropped.
+pub(super) fn handling_state(command: &ToServerCommand) -> ConnectionState {
+    match command {
+        ToServerCommand::Init(_) => ConnectionState::Initializing,
+        ToServerCommand::SrpBytesA(_) | ToServerCommand::SrpBytesM(_) => {
+            ConnectionState::Authenticating
+        }
+        ToServerCommand::Init2(_) => ConnectionState::Setup,
+        ToServerCommand::RequestMedia(_) | ToServerCommand::ClientReady(_) => {
+            ConnectionState::Loading
+        }
+        // players who already joined change their password with `FirstSrp`
+        ToServerCommand::FirstSrp(_)
+        | ToServerCommand::ModchannelJoin(_)
+        | ToServerCommand::ModchannelLeave(_)
+        | ToServerCommand::TSModchannelMsg(_)
+        | ToServerCommand::Playerpos(_)
+        | ToServerCommand::GotBlocks(_)
+        | ToServerCommand::Deletedblocks(_)
+        | ToServerCommand::InventoryAction(_)
+        | ToServerCommand::TSChatMessage(_)
+        | ToServerCommand::Damage(_)
+        | ToServerCommand::PlayerItem(_)
+        | ToServerCommand::Respawn(_)
+        | ToServerCommand::Interact(_)
+        | ToServerCommand::RemovedSounds(_)
+        | ToServerCommand::NodemetaFields(_)
+        | ToServerCommand::InventoryFields(_)
+        | ToServerCommand::HaveMedia(_)
+        | ToServerCommand::UpdateClientInfo(_)
+        | ToServerCommand::GotFarBlocks(_) => ConnectionState::Running,
+    }
+}
@@ -1,6 +1,7 @@
 use std::{sync::Arc, vec};
 
 use crate::MediaRegistry;
+use crate::load_budget::{LoadBudget, serialized_size};
 use anyhow::Result;
 use log::{debug, error, info, warn};
 use luanti_protocol::{
@@ -13,12 +14,19 @@ use luanti_protocol::{
         },
     },
     types::{MediaAnnouncement, MediaFileData, NodeDefManager},
+    versions::ClientVersion,
 };
 
+/// The maximum size of the file data sent within a single `MediaSpec` (unless a single file
+/// exceeds this size)
+const MEDIA_BUNCH_SIZE: usize = 512 * 1024;
+
 /// The state after a successful setup.
 /// In this state all map data, media, etc. will be submitted
 pub(super) struct LoadingState {
     language: Option<String>,
+    /// the version the client reported once it's ready
+    version: Option<ClientVersion>,
     media: Arc<MediaRegistry>,
     // pub(crate) player_key: SharedStr,
 }
@@ -28,16 +36,18 @@ impl LoadingState {
     pub(super) fn new(language: Option<String>, media: Arc<MediaRegistry>) -> Self {
         Self {
             language,
+            version: None,
             media,
             // player_key,
         }
     }
 
-    pub(super) fn send_data(
+    pub(super) async fn send_data(
         &self,
         connection: &LuantiConnection,
         node_def: &NodeDefManager,
         media: &MediaRegistry,
+        load_budget: &LoadBudget,
     ) -> Result<()> {
         #[expect(
             unused_variables,
@@ -59,14 +69,21 @@ impl LoadingState {
             aliases: vec![],
         };
 
+        load_budget
+            .acquire(serialized_size::<ItemdefList>(&itemdef_list))
+            .await;
         connection.send(ItemdefCommand {
             item_def: itemdef_list,
         })?;
 
+        load_budget
+            .acquire(serialized_size::<NodeDefManager>(node_def))
+            .await;
         connection.send(NodedefSpec {
             node_def: node_def.clone(),
         })?;
 
+        // the announcement is small compared to the definitions; no need to wait for it
         connection.send(AnnounceMediaSpec {
             files,
             remote_servers: String::new(),
@@ -75,17 +92,20 @@ impl LoadingState {
         Ok(())
     }
 
-    pub(crate) fn handle_message(
-        &self,
+    pub(crate) async fn handle_message(
+        &mut self,
         message: ToServerCommand,
         connection: &LuantiConnection,
+        load_budget: &LoadBudget,
+        privileges: &[String],
     ) -> Result<bool> {
         match message {
             ToServerCommand::ClientReady(client_ready_spec) => {
-                Self::handle_client_ready(*client_ready_spec, connection)
+                self.handle_client_ready(*client_ready_spec, connection, privileges)
             }
             ToServerCommand::RequestMedia(request_media_spec) => {
-                self.handle_request_media(*request_media_spec, connection)
+                self.handle_request_media(*request_media_spec, connection, load_budget)
+                    .await
             }
             unexpected => {
                 warn!(
@@ -98,63 +118,75 @@ impl LoadingState {
     }
 
     fn handle_client_ready(
+        &mut self,
         client_ready_spec: ClientReadySpec,
         connection: &LuantiConnection,
+        privileges: &[String],
     ) -> Result<bool> {
-        let ClientReadySpec {
-            major_ver: _,
-            minor_ver: _,
-            patch_ver: _,
-            reserved: _,
-            full_ver,
-            formspec_ver,
-        } = client_ready_spec;
-
+        let version = ClientVersion::from(client_ready_spec);
         info!(
-            "Client ready: v{full_ver}, formspec v{}",
-            formspec_ver
+            "Client ready: v{version}, formspec v{}",
+            version
+                .formspec_version
                 .as_ref()
                 .map_or("<none>".into(), ToString::to_string)
         );
 
         connection.send(PrivilegesSpec {
-            privileges: vec![
-                "fly".into(),
-                "fast".into(),
-                "noclip".into(),
-                "rollback".into(),
-                "debug".into(),
-            ],
+            privileges: privileges.to_vec(),
         })?;
+        self.version = Some(version);
 
         Ok(true)
     }
 
-    fn handle_request_media(
+    async fn handle_request_media(
         &self,
         request_media_spec: RequestMediaSpec,
         connection: &LuantiConnection,
+        load_budget: &LoadBudget,
     ) -> Result<bool> {
         let RequestMediaSpec { files } = request_media_spec;
 
         debug!("client requested files: {files:?}");
-        let mut media_file_data = vec![];
+        let mut bunches: Vec<Vec<MediaFileData>> = vec![];
+        let mut bunch_size = 0;
         for file in files {
-            debug!("sending file: {file}");
-
             let Some(data) = self.media.file_content(&file)? else {
                 error!("could not find file: {file}");
                 continue;
             };
 
-            media_file_data.push(MediaFileData { name: file, data });
+            // start a new bunch if this file doesn't fit into the current one
+            if bunches.is_empty() || bunch_size + data.len() > MEDIA_BUNCH_SIZE {
+                bunches.push(vec![]);
+                bunch_size = 0;
+            }
+            bunch_size += data.len();
+            if let Some(bunch) = bunches.last_mut() {
+                bunch.push(MediaFileData { name: file, data });
+            }
+        }
+
+        if bunches.is_empty() {
+            // the client still expects an answer
+            bunches.push(vec![]);
         }
 
-        connection.send(MediaSpec {
-            num_bunches: 1,
-            bunch_index: 0,
-            files: media_file_data,
-        })?;
+        let num_bunches = u16::try_from(bunches.len())?;
+        for (bunch_index, bunch) in (0..num_bunches).zip(bunches) {
+            let size = bunch.iter().map(|file| file.data.len()).sum();
+            load_budget.acquire(size).await;
+            debug!(
+                "sending media bunch {bunch_index}/{num_bunches} with {} files",
+                bunch.len()
+            );
+            connection.send(MediaSpec {
+                num_bunches,
+                bunch_index,
+                files: bunch,
+            })?;
+        }
 
         Ok(false)
     }
@@ -162,4 +194,8 @@ impl LoadingState {
     pub(crate) fn language(&self) -> Option<&String> {
         self.language.as_ref()
     }
+
+    pub(crate) fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
 }
@@ -1,29 +1,62 @@
 use anyhow::Result;
+use std::time::Duration;
+use std::time::Instant;
+
 use anyhow::bail;
+use flexstr::SharedStr;
+use glam::Vec3;
 use log::debug;
+use log::warn;
+use luanti_core::ContentId;
+use luanti_core::MapBlockPos;
+use luanti_core::MapNodePos;
 use luanti_protocol::LuantiConnection;
 use luanti_protocol::commands::CommandProperties;
 use luanti_protocol::commands::client_to_server::DamageSpec;
+use luanti_protocol::commands::client_to_server::DeletedblocksSpec;
 use luanti_protocol::commands::client_to_server::GotBlocksSpec;
 use luanti_protocol::commands::client_to_server::InteractSpec;
 use luanti_protocol::commands::client_to_server::InventoryActionSpec;
+use luanti_protocol::commands::client_to_server::ModchannelJoinSpec;
+use luanti_protocol::commands::client_to_server::ModchannelLeaveSpec;
 use luanti_protocol::commands::client_to_server::PlayerItemSpec;
 use luanti_protocol::commands::client_to_server::PlayerPosCommand;
 use luanti_protocol::commands::client_to_server::TSChatMessageSpec;
+use luanti_protocol::commands::client_to_server::TSModchannelMsgSpec;
 use luanti_protocol::commands::client_to_server::ToServerCommand;
 use luanti_protocol::commands::client_to_server::UpdateClientInfoSpec;
+use luanti_protocol::commands::server_to_client::HpSpec;
+use luanti_protocol::commands::server_to_client::ModchannelSignalSpec;
+use luanti_protocol::commands::server_to_client::MovePlayerSpec;
+use luanti_protocol::commands::server_to_client::MovementSpec;
+use luanti_protocol::commands::server_to_client::TCModchannelMsgSpec;
+use luanti_protocol::commands::server_to_client::ToClientCommand;
 use luanti_protocol::types::InventoryAction;
 use luanti_protocol::types::InventoryLocation;
+use luanti_protocol::types::ModChannelSignal;
 use luanti_protocol::types::PlayerPos;
 use luanti_protocol::types::PointedThing;
 use tokio::sync::mpsc;
 
+use super::system_message;
 use crate::api::ToPluginEvent;
+use crate::formspec::node_forms::NodeInteractions;
+use crate::health::HealthRules;
+use crate::health::HealthTracker;
+use crate::health::HpChange;
+use crate::health::HpChangeReason;
+use crate::mod_channels::ModChannels;
+use crate::movement::MovementValidator;
+use crate::movement::Verdict;
+use crate::player_store::PlayerData;
+use crate::world::WorldBlock;
+use crate::world::view_tracker::PlayerView;
 use crate::world::view_tracker::PlayerViewEvent;
 use crate::world::view_tracker::ViewTracker;
 
 /// Everything has been set up. We're in-game now!
 pub(super) struct RunningState {
+    player_key: SharedStr,
     /// Keeps track of the player's movements and informs us about what parts of the world were
     /// updated or emerged through a channel
     view_tracker: ViewTracker,
@@ -31,61 +64,179 @@ pub(super) struct RunningState {
     // /// shall be forwarded to the client.
     // world_update_receiver: UnboundedReceiver<WorldUpdate>,
     plugin_event_sender: mpsc::UnboundedSender<ToPluginEvent>,
+    /// Checks the player's movements for plausibility; `None` if the validation is disabled
+    movement_validator: Option<MovementValidator>,
+    mod_channels: ModChannels,
+    health: HealthTracker,
+    node_interactions: NodeInteractions,
 }
 
 impl RunningState {
     #[must_use]
     pub(super) fn new(
-        // player_key: SharedStr,
+        player_key: SharedStr,
         // block_interest_sender: UnboundedSender<ToRouterMessage>,
         view_tracker: ViewTracker,
         plugin_event_sender: mpsc::UnboundedSender<ToPluginEvent>,
+        movement_validator: Option<MovementValidator>,
+        mod_channels: ModChannels,
+        health_rules: HealthRules,
+        node_interactions: NodeInteractions,
     ) -> Self {
         Self {
+            player_key,
             view_tracker,
             plugin_event_sender,
+            movement_validator,
+            mod_channels,
+            health: HealthTracker::new(health_rules),
+            node_interactions,
+        }
+    }
+
+    /// Shows the death screen if the player left the game while being dead.
+    pub(super) fn join(
+        &mut self,
+        player: &PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        Self::send_all(self.health.join(player), connection)
+    }
+
+    /// Keeps the interests of the player in map blocks for the next time they join.
+    pub(super) fn leave(&self) {
+        if let Err(error) = self.view_tracker.leave() {
+            warn!(
+                "the view tracker of player '{}' has already stopped: {error}",
+                self.player_key
+            );
+        }
+    }
+
+    /// Remembers the nodes of a map block that has been sent to the client.
+    pub(super) fn block_sent(&mut self, block: &WorldBlock) {
+        self.health.hazards_mut().insert(block);
+        self.node_interactions.insert(block);
+    }
+
+    /// Returns the number of map blocks which are waiting to be sent or to be confirmed by the
+    /// client.
+    pub(super) fn pending_blocks(&self) -> usize {
+        self.view_tracker.pending_blocks()
+    }
+
+    /// Remembers a node which has been changed within a map block known to the client.
+    pub(super) fn node_changed(&mut self, pos: MapNodePos, content_id: ContentId) {
+        self.health.hazards_mut().set_node(pos, content_id);
+        self.node_interactions.set_node(pos, content_id);
+    }
+
+    /// Advances the simulation of the player's surroundings.
+    pub(super) fn step(
+        &mut self,
+        elapsed: Duration,
+        player: &mut PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        let (changes, commands) = self.health.step(player, elapsed);
+        self.apply_hp_change(changes, commands, connection)
+    }
+
+    /// Sets the hit points of the player on behalf of a plugin.
+    pub(super) fn set_hp(
+        &mut self,
+        hp_spec: &HpSpec,
+        player: &mut PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        let &HpSpec {
+            hp, damage_effect, ..
+        } = hp_spec;
+        let (change, commands) = self.health.set_hp(
+            player,
+            hp,
+            HpChangeReason::SetHp,
+            damage_effect.unwrap_or(true),
+        );
+        self.apply_hp_change(change, commands, connection)
+    }
+
+    /// Allows the client to respawn after a plugin showed the death screen.
+    pub(super) fn show_death_screen(&mut self) {
+        self.health.show_death_screen();
+    }
+
+    /// Informs the movement validation about the player having been moved by the server.
+    pub(super) fn teleport(&mut self, position: Vec3) {
+        if let Some(validator) = &mut self.movement_validator {
+            validator.teleport(position, Instant::now());
         }
     }
 
+    /// Informs the movement validation about new physics having been sent to the client.
+    pub(super) fn set_physics(&mut self, physics: MovementSpec) {
+        if let Some(validator) = &mut self.movement_validator {
+            validator.set_physics(physics);
+        }
+    }
+
+    #[expect(clippy::too_many_lines, reason = "// TODO split this up")]
     pub(crate) fn handle_message(
         &mut self,
         message: ToServerCommand,
-        _connection: &LuantiConnection,
+        connection: &LuantiConnection,
+        player: &mut PlayerData,
     ) -> Result<()> {
         match message {
             ToServerCommand::Playerpos(player_pos_command) => {
-                self.handle_player_pos(*player_pos_command.clone())?;
-                let event = ToPluginEvent::Playerpos(*player_pos_command);
-                self.plugin_event_sender.send(event)?;
+                // plugins don't learn about positions the validation refused
+                if self.handle_player_pos(*player_pos_command.clone(), player, connection)? {
+                    let event = ToPluginEvent::Playerpos(*player_pos_command);
+                    self.plugin_event_sender.send(event)?;
+                }
             }
             ToServerCommand::UpdateClientInfo(update_client_info_spec) => {
                 Self::handle_update_client_info(&update_client_info_spec)?;
             }
             ToServerCommand::ModchannelJoin(modchannel_join_spec) => {
+                self.handle_modchannel_join(&modchannel_join_spec, connection)?;
                 let event = ToPluginEvent::ModchannelJoin(*modchannel_join_spec);
                 self.plugin_event_sender.send(event)?;
-                // todo!();
             }
             ToServerCommand::ModchannelLeave(modchannel_leave_spec) => {
+                self.handle_modchannel_leave(&modchannel_leave_spec, connection)?;
                 let event = ToPluginEvent::ModchannelLeave(*modchannel_leave_spec);
                 self.plugin_event_sender.send(event)?;
-                // todo!();
             }
             ToServerCommand::TSModchannelMsg(ts_modchannel_msg_spec) => {
-                let event = ToPluginEvent::TSModchannelMsg(*ts_modchannel_msg_spec);
-                self.plugin_event_sender.send(event)?;
-                // todo!();
+                if self.handle_modchannel_msg(&ts_modchannel_msg_spec, connection)? {
+                    let event = ToPluginEvent::TSModchannelMsg(*ts_modchannel_msg_spec);
+                    self.plugin_event_sender.send(event)?;
+                }
             }
             ToServerCommand::GotBlocks(got_blocks_spec) => {
-                Self::handle_got_blocks(*got_blocks_spec)?;
+                self.handle_got_blocks(*got_blocks_spec)?;
+            }
+            ToServerCommand::Deletedblocks(deletedblocks_spec) => {
+                self.handle_deleted_blocks(*deletedblocks_spec)?;
             }
-            ToServerCommand::Deletedblocks(_deletedblocks_spec) => {
-                todo!();
+            ToServerCommand::GotFarBlocks(got_far_blocks_spec) => {
+                // far blocks aren't being scheduled yet, so there's nothing to keep track of
+                debug!(
+                    "got far blocks: level:{level} {blocks:?}",
+                    level = got_far_blocks_spec.level,
+                    blocks = got_far_blocks_spec.blocks
+                );
             }
             ToServerCommand::InventoryAction(inventory_action_spec) => {
                 Self::handle_inventory_action(*inventory_action_spec.clone())?;
-                let event = ToPluginEvent::InventoryAction(*inventory_action_spec);
-                self.plugin_event_sender.send(event)?;
+                if self
+                    .node_interactions
+                    .accept_inventory_action(&inventory_action_spec.action, player)
+                {
+                    let event = ToPluginEvent::InventoryAction(*inventory_action_spec);
+                    self.plugin_event_sender.send(event)?;
+                }
             }
             ToServerCommand::TSChatMessage(ts_chat_message_spec) => {
                 Self::handle_chat_message(*ts_chat_message_spec.clone())?;
@@ -93,7 +244,7 @@ impl RunningState {
                 self.plugin_event_sender.send(event)?;
             }
             ToServerCommand::Damage(damage_spec) => {
-                Self::handle_damage(&damage_spec)?;
+                self.handle_damage(&damage_spec, player, connection)?;
                 let event = ToPluginEvent::Damage(*damage_spec);
                 self.plugin_event_sender.send(event)?;
             }
@@ -103,33 +254,50 @@ impl RunningState {
                 self.plugin_event_sender.send(event)?;
             }
             ToServerCommand::Respawn(respawn_spec) => {
-                let event = ToPluginEvent::Respawn(*respawn_spec);
-                self.plugin_event_sender.send(event)?;
-                // todo!();
+                if self.handle_respawn(player, connection)? {
+                    let event = ToPluginEvent::Respawn(*respawn_spec);
+                    self.plugin_event_sender.send(event)?;
+                }
             }
             ToServerCommand::Interact(interact_spec) => {
                 Self::handle_interact(*interact_spec.clone())?;
+                if let Some(form) =
+                    self.node_interactions
+                        .interact(&interact_spec, &self.player_key, player)
+                {
+                    connection.send(form)?;
+                }
                 let event = ToPluginEvent::Interact(*interact_spec);
                 self.plugin_event_sender.send(event)?;
             }
-            ToServerCommand::RemovedSounds(_removed_sounds_spec) => {
-                // todo!();
+            ToServerCommand::RemovedSounds(removed_sounds_spec) => {
+                let event = ToPluginEvent::RemovedSounds(*removed_sounds_spec);
+                self.plugin_event_sender.send(event)?;
             }
             ToServerCommand::NodemetaFields(nodemeta_fields_spec) => {
-                let event = ToPluginEvent::NodemetaFields(*nodemeta_fields_spec);
-                self.plugin_event_sender.send(event)?;
-                // todo!();
+                if self
+                    .node_interactions
+                    .accept_nodemeta_fields(&nodemeta_fields_spec, player)
+                {
+                    let event = ToPluginEvent::NodemetaFields(*nodemeta_fields_spec);
+                    self.plugin_event_sender.send(event)?;
+                }
             }
             ToServerCommand::InventoryFields(inventory_fields_spec) => {
-                let event = ToPluginEvent::InventoryFields(*inventory_fields_spec);
-                self.plugin_event_sender.send(event)?;
-                // todo!();
+                if let Some(event) = self
+                    .node_interactions
+                    .inventory_fields(*inventory_fields_spec, player)
+                {
+                    self.plugin_event_sender.send(event)?;
+                }
             }
-            ToServerCommand::HaveMedia(_have_media_spec) => {
-                todo!();
+            ToServerCommand::HaveMedia(have_media_spec) => {
+                debug!("client received dynamic media {:?}", have_media_spec.tokens);
+                let event = ToPluginEvent::HaveMedia(*have_media_spec);
+                self.plugin_event_sender.send(event)?;
             }
             ToServerCommand::FirstSrp(_first_srp_spec) => {
-                todo!();
+                self.handle_password_change(connection)?;
             }
             unexpected => {
                 bail!(
@@ -142,10 +310,25 @@ impl RunningState {
         Ok(())
     }
 
+    /// Refuses to change the password, as authenticators are only able to look up players.
+    fn handle_password_change(&self, connection: &LuantiConnection) -> Result<()> {
+        warn!(
+            "player '{}' tried to change their password, which isn't supported",
+            self.player_key
+        );
+        connection.send(system_message(
+            "Changing the password isn't supported by this server.".to_owned(),
+        ))
+    }
+
+    /// Applies a new position of the player. Returns `false` if the movement validation refused
+    /// the position.
     fn handle_player_pos(
-        &self,
+        &mut self,
         player_pos_command: PlayerPosCommand,
-    ) -> std::result::Result<(), anyhow::Error> {
+        player: &mut PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<bool> {
         let PlayerPosCommand { player_pos } = player_pos_command;
 
         let PlayerPos {
@@ -162,7 +345,7 @@ impl RunningState {
         } = &player_pos;
 
         debug!(
-            "player moved: pos:({px},{py},{pz}) speed:({sx},{sy},{sz}) pitch:{pitch} yaw:{yaw} keys:{keys_pressed} fov:{fov} range:{wanted_range} cam_inv:{camera_inverted} mov_speed:{movement_speed} mov_dir:{movement_direction} ",
+            "player moved: pos:({px},{py},{pz}) speed:({sx},{sy},{sz}) pitch:{pitch} yaw:{yaw} keys:{keys_pressed:?} fov:{fov} range:{wanted_range} cam_inv:{camera_inverted} mov_speed:{movement_speed} mov_dir:{movement_direction} ",
             px = position.x,
             py = position.y,
             pz = position.z,
@@ -171,11 +354,42 @@ impl RunningState {
             sz = speed.z,
         );
 
-        self.view_tracker.update_view(PlayerViewEvent::PlayerPos {
-            position: position / 10.0,
-        })?;
+        if let Some(validator) = &mut self.movement_validator {
+            let verdict = validator.check(
+                position / 10.0,
+                speed / 10.0,
+                &player.privileges,
+                Instant::now(),
+            );
+            match verdict {
+                Verdict::Accepted => {}
+                Verdict::Dropped => return Ok(false),
+                Verdict::Rewind(valid_position) => {
+                    warn!("player moved too fast; moving back to {valid_position}");
+                    connection.send(MovePlayerSpec {
+                        pos: valid_position * 10.0,
+                        pitch: *pitch,
+                        yaw: *yaw,
+                    })?;
+                    return Ok(false);
+                }
+            }
+        }
 
-        Ok(())
+        player.position = position / 10.0;
+        player.pitch = *pitch;
+        player.yaw = *yaw;
+
+        self.view_tracker
+            .update_view(PlayerViewEvent::PlayerPos(PlayerView {
+                position: player.position,
+                pitch: *pitch,
+                yaw: *yaw,
+                fov: *fov,
+                wanted_range: *wanted_range,
+            }))?;
+
+        Ok(true)
     }
 
     #[expect(
@@ -311,27 +525,142 @@ impl RunningState {
         Ok(())
     }
 
-    #[expect(
-        clippy::unnecessary_wraps,
-        reason = "//TODO(kawogi) for symmetry with other handlers, but should be reviewed"
-    )]
-    fn handle_got_blocks(got_blocks_spec: GotBlocksSpec) -> Result<()> {
-        let GotBlocksSpec { blocks } = got_blocks_spec;
+    fn handle_got_blocks(&self, got_blocks_spec: GotBlocksSpec) -> Result<()> {
+        debug!("got blocks: {blocks:?}", blocks = got_blocks_spec.blocks);
+
+        self.view_tracker
+            .update_view(PlayerViewEvent::GotMapBlocks(got_blocks_spec))
+    }
 
-        debug!("got blocks: {blocks:?}");
+    fn handle_deleted_blocks(&mut self, deletedblocks_spec: DeletedblocksSpec) -> Result<()> {
+        debug!(
+            "deleted blocks: {blocks:?}",
+            blocks = deletedblocks_spec.blocks
+        );
 
-        Ok(())
+        for pos in deletedblocks_spec
+            .blocks
+            .iter()
+            .copied()
+            .filter_map(MapBlockPos::new)
+        {
+            self.health.hazards_mut().remove(pos);
+            self.node_interactions.remove(pos);
+        }
+
+        self.view_tracker
+            .update_view(PlayerViewEvent::DroppedBlocks(deletedblocks_spec))
     }
 
-    #[expect(
-        clippy::unnecessary_wraps,
-        reason = "//TODO(kawogi) for symmetry with other handlers, but should be reviewed"
-    )]
-    fn handle_damage(damage_spec: &DamageSpec) -> Result<()> {
-        let DamageSpec { damage } = damage_spec;
+    fn handle_modchannel_join(
+        &self,
+        modchannel_join_spec: &ModchannelJoinSpec,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        let ModchannelJoinSpec { channel_name } = modchannel_join_spec;
+        let signal = if self.mod_channels.join(channel_name, &self.player_key) {
+            ModChannelSignal::JoinOk
+        } else {
+            ModChannelSignal::JoinFailure
+        };
+        debug!("join mod channel '{channel_name}': {signal:?}");
+        connection.send(ModchannelSignalSpec::new(signal, channel_name.clone()))
+    }
+
+    fn handle_modchannel_leave(
+        &self,
+        modchannel_leave_spec: &ModchannelLeaveSpec,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        let ModchannelLeaveSpec { channel_name } = modchannel_leave_spec;
+        let signal = if self.mod_channels.leave(channel_name, &self.player_key) {
+            ModChannelSignal::LeaveOk
+        } else {
+            ModChannelSignal::LeaveFailure
+        };
+        debug!("leave mod channel '{channel_name}': {signal:?}");
+        connection.send(ModchannelSignalSpec::new(signal, channel_name.clone()))
+    }
+
+    /// Forwards the message to all other members of the channel. Returns `false` if nobody
+    /// joined the channel.
+    fn handle_modchannel_msg(
+        &self,
+        ts_modchannel_msg_spec: &TSModchannelMsgSpec,
+        connection: &LuantiConnection,
+    ) -> Result<bool> {
+        let TSModchannelMsgSpec {
+            channel_name,
+            channel_msg,
+        } = ts_modchannel_msg_spec;
+        // like the engine, players don't need to be members to write to a channel
+        if !self.mod_channels.is_registered(channel_name) {
+            connection.send(ModchannelSignalSpec::new(
+                ModChannelSignal::ChannelNotRegistered,
+                channel_name.clone(),
+            ))?;
+            return Ok(false);
+        }
+        let receivers = self.mod_channels.broadcast(
+            &TCModchannelMsgSpec {
+                channel_name: channel_name.clone(),
+                sender: self.player_key.to_string(),
+                channel_msg: channel_msg.clone(),
+            },
+            Some(&*self.player_key),
+        );
+        debug!("mod channel message to '{channel_name}' has been sent to {receivers} players");
+        Ok(true)
+    }
+
+    fn handle_damage(
+        &mut self,
+        damage_spec: &DamageSpec,
+        player: &mut PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        let &DamageSpec { damage } = damage_spec;
 
         debug!("damage: {damage}");
+        let (change, commands) = self.health.damage(player, damage, HpChangeReason::Fall);
+        self.apply_hp_change(change, commands, connection)
+    }
 
+    /// Returns `false` if the player isn't dead and the request has been ignored.
+    fn handle_respawn(
+        &mut self,
+        player: &mut PlayerData,
+        connection: &LuantiConnection,
+    ) -> Result<bool> {
+        let Some((change, commands)) = self.health.respawn(&self.player_key, player) else {
+            warn!("ignoring respawn request of a living player");
+            return Ok(false);
+        };
+        // the view follows as soon as the client reports its new position
+        self.teleport(player.position);
+        self.apply_hp_change(Some(change), commands, connection)?;
+        Ok(true)
+    }
+
+    /// Sends the commands resulting from changes of the hit points and informs the plugins.
+    fn apply_hp_change(
+        &self,
+        changes: impl IntoIterator<Item = HpChange>,
+        commands: Vec<ToClientCommand>,
+        connection: &LuantiConnection,
+    ) -> Result<()> {
+        Self::send_all(commands, connection)?;
+        for change in changes {
+            self.plugin_event_sender
+                .send(ToPluginEvent::HpChange(change))?;
+        }
+        Ok(())
+    }
+
+    fn send_all(commands: Vec<ToClientCommand>, connection: &LuantiConnection) -> Result<()> {
+        for command in commands {
+            connection.send(command)?;
+        }
         Ok(())
     }
 }
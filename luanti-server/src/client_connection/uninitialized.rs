@@ -9,37 +9,58 @@ use luanti_protocol::LuantiConnection;
 use luanti_protocol::commands::CommandProperties;
 use luanti_protocol::commands::client_to_server::InitSpec;
 use luanti_protocol::commands::client_to_server::ToServerCommand;
+use luanti_protocol::commands::server_to_client::AccessDeniedCode;
 use luanti_protocol::commands::server_to_client::HelloSpec;
+use luanti_protocol::services::handshake::HandshakeError;
 use luanti_protocol::types::AuthMechsBitset;
-use luanti_protocol::wire::packet::LATEST_PROTOCOL_VERSION;
-use luanti_protocol::wire::packet::SER_FMT_VER_HIGHEST_WRITE;
+use luanti_protocol::versions::SUPPORTED_SER_FMT_WRITE;
 use std::ops::RangeInclusive;
 
 use super::authenticating::AuthenticatingState;
 
-const SUPPORTED_PROTOCOL_VERSIONS: RangeInclusive<u16> =
-    LATEST_PROTOCOL_VERSION..=LATEST_PROTOCOL_VERSION;
-const SUPPORTED_SERIALIZATION_VERSIONS: RangeInclusive<u8> =
-    SER_FMT_VER_HIGHEST_WRITE..=SER_FMT_VER_HIGHEST_WRITE;
+const SUPPORTED_SERIALIZATION_VERSIONS: RangeInclusive<u8> = SUPPORTED_SER_FMT_WRITE;
 
 /// The initial state after establishing the connection before any kind of communication happened.
 /// The player/user name is not yet known and we're waiting for an Init-command to arrive.
 pub(super) struct UninitializedState<Auth: Authenticator> {
     authenticator: Auth,
+    /// the protocol versions the server is willing to speak
+    protocol_versions: RangeInclusive<u16>,
+    /// the protocol version both sides agreed upon; `None` until the Init-command arrived
+    protocol_version: Option<u16>,
     /// upon receiving the user name the authenticator will be used to retrieve the user's
     /// authentication data.
     user_auth_data: Option<SrpUserAuthData>,
+    /// whether the client has been told that it can't connect
+    rejected: bool,
 }
 
 impl<Auth: Authenticator + 'static> UninitializedState<Auth> {
     #[must_use]
-    pub(super) fn new(authenticator: Auth) -> Self {
+    pub(super) fn new(authenticator: Auth, protocol_versions: RangeInclusive<u16>) -> Self {
         Self {
             authenticator,
+            protocol_versions,
+            protocol_version: None,
             user_auth_data: None,
+            rejected: false,
         }
     }
 
+    /// The protocol version which has been negotiated with the client; `None` if the client
+    /// hasn't introduced itself yet.
+    #[must_use]
+    pub(super) fn protocol_version(&self) -> Option<u16> {
+        self.protocol_version
+    }
+
+    /// Returns `true` if the client has been sent an `AccessDenied` as its versions aren't
+    /// supported.
+    #[must_use]
+    pub(super) fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
     /// This handles the first message a client sends after a connect
     pub(crate) async fn handle_message(
         &mut self,
@@ -71,14 +92,20 @@ impl<Auth: Authenticator + 'static> UninitializedState<Auth> {
 
         let protocol_version = {
             // intersect version ranges
-            let min_version = (*SUPPORTED_PROTOCOL_VERSIONS.start()).max(min_net_proto_version);
-            let max_version = (*SUPPORTED_PROTOCOL_VERSIONS.end()).min(max_net_proto_version);
+            let min_version = (*self.protocol_versions.start()).max(min_net_proto_version);
+            let max_version = (*self.protocol_versions.end()).min(max_net_proto_version);
             if min_version > max_version {
-                bail!(
-                    "unsupported protocol version. Only {min}..{max} is supported, but {min_net_proto_version}..{max_net_proto_version} was requested",
-                    min = SUPPORTED_PROTOCOL_VERSIONS.start(),
-                    max = SUPPORTED_PROTOCOL_VERSIONS.end(),
+                let client = min_net_proto_version..=max_net_proto_version;
+                info!(
+                    "rejecting client with protocol versions {client:?}; only {:?} is supported",
+                    self.protocol_versions
                 );
+                self.rejected = true;
+                reject_version(connection, "protocol", &self.protocol_versions, &client)?;
+                bail!(HandshakeError::UnsupportedVersion {
+                    client,
+                    server: None,
+                });
             }
             max_version
         };
@@ -89,17 +116,26 @@ impl<Auth: Authenticator + 'static> UninitializedState<Auth> {
             let min_version = *SUPPORTED_SERIALIZATION_VERSIONS.start();
             let max_version = (*SUPPORTED_SERIALIZATION_VERSIONS.end()).min(serialization_ver_max);
             if min_version > max_version {
+                self.rejected = true;
+                reject_version(
+                    connection,
+                    "serialization",
+                    &SUPPORTED_SERIALIZATION_VERSIONS,
+                    &(0..=serialization_ver_max),
+                )?;
                 bail!(
-                    "unsupported serialization version. Only {min}..{max} is supported, but 0..{max_net_proto_version} was requested",
-                    min = SUPPORTED_PROTOCOL_VERSIONS.start(),
-                    max = SUPPORTED_PROTOCOL_VERSIONS.end(),
+                    "unsupported serialization version. Only {min}..{max} is supported, but 0..{serialization_ver_max} was requested",
+                    min = SUPPORTED_SERIALIZATION_VERSIONS.start(),
+                    max = SUPPORTED_SERIALIZATION_VERSIONS.end(),
                 );
             }
             max_version
         };
         debug!("negotiated serialization_version version {serialization_version}");
 
-        // TODO(kawogi) Verify that the technical protocol switch is transparently performed by the underlying connection implementation
+        // the connection picks up both versions from the `Hello` being sent below and uses them
+        // for (de)serializing all following commands
+        self.protocol_version = Some(protocol_version);
 
         assert!(
             self.user_auth_data
@@ -128,3 +164,155 @@ impl<Auth: Authenticator + 'static> UninitializedState<Auth> {
         )
     }
 }
+
+/// Tells the client which versions the server supports, as the engine only shows a generic
+/// message for [`AccessDeniedCode::WrongVersion`].
+fn reject_version<T: std::fmt::Display>(
+    connection: &LuantiConnection,
+    kind: &str,
+    supported: &RangeInclusive<T>,
+    requested: &RangeInclusive<T>,
+) -> Result<()> {
+    connection.send_access_denied(
+        AccessDeniedCode::WrongVersion,
+        version_mismatch_message(kind, supported, requested),
+        false,
+    )
+}
+
+fn version_mismatch_message<T: std::fmt::Display>(
+    kind: &str,
+    supported: &RangeInclusive<T>,
+    requested: &RangeInclusive<T>,
+) -> String {
+    format!(
+        "{default}\nThe server supports {kind} versions {} to {}, your client {} to {}.",
+        supported.start(),
+        supported.end(),
+        requested.start(),
+        requested.end(),
+        default = AccessDeniedCode::WrongVersion.to_str(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::ops::RangeInclusive;
+
+    use luanti_protocol::LuantiConnection;
+    use luanti_protocol::commands::Command;
+    use luanti_protocol::commands::client_to_server::{InitSpec, ToServerCommand};
+    use luanti_protocol::commands::server_to_client::ToClientCommand;
+    use luanti_protocol::peer::{PeerIO, PeerToSocket, new_peer};
+    use luanti_protocol::services::handshake::HandshakeError;
+    use luanti_protocol::types::ProtocolContext;
+    use luanti_protocol::versions::MIN_PROTOCOL_VERSION;
+    use luanti_protocol::wire::buf_pool::BufPool;
+    use luanti_protocol::wire::deser::{Deserialize, Deserializer};
+    use luanti_protocol::wire::packet::Packet;
+    use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+    use super::{UninitializedState, version_mismatch_message};
+    use crate::authentication::dummy::DummyAuthenticator;
+    use crate::server::DEFAULT_PROTOCOL_VERSIONS;
+
+    /// Creates a connection to a client; the peer stops once its [`PeerIO`] is dropped.
+    fn connection() -> (LuantiConnection, PeerIO, UnboundedReceiver<PeerToSocket>) {
+        let (to_socket, from_peer) = unbounded_channel();
+        let (peer, peer_io) = new_peer(
+            "127.0.0.1:30000".parse().unwrap(),
+            false,
+            to_socket,
+            BufPool::default(),
+            false,
+        );
+        (LuantiConnection::new(peer), peer_io, from_peer)
+    }
+
+    fn init(protocol_versions: RangeInclusive<u16>, ser_fmt: u8) -> ToServerCommand {
+        InitSpec {
+            serialization_ver_max: ser_fmt,
+            supp_compr_modes: 0,
+            min_net_proto_version: *protocol_versions.start(),
+            max_net_proto_version: *protocol_versions.end(),
+            user_name: "player".into(),
+        }
+        .into()
+    }
+
+    /// Returns the next command being sent to the client, skipping control packets like the
+    /// assignment of the peer id.
+    async fn sent(from_peer: &mut UnboundedReceiver<PeerToSocket>) -> Option<Command> {
+        loop {
+            let PeerToSocket::Send(_, _, datagram) = from_peer.recv().await? else {
+                return None;
+            };
+            let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(true), &datagram);
+            if let Some(command) = Packet::deserialize(&mut deser).ok()?.body.command() {
+                return Some(command.clone());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn older_clients_are_answered_in_their_version() {
+        let (connection, _peer_io, mut from_peer) = connection();
+        let mut state = UninitializedState::new(DummyAuthenticator, DEFAULT_PROTOCOL_VERSIONS);
+
+        // a client of Luanti 5.5.0
+        let init = init(MIN_PROTOCOL_VERSION..=40, 29);
+        assert!(state.handle_message(init, &connection).await.unwrap());
+        assert_eq!(state.protocol_version(), Some(40));
+        assert!(matches!(
+            sent(&mut from_peer).await,
+            Some(Command::ToClient(ToClientCommand::Hello(hello))) if hello.protocol_version == 40
+        ));
+    }
+
+    #[tokio::test]
+    async fn clients_without_a_common_version_are_rejected() {
+        let (connection, _peer_io, _from_peer) = connection();
+        let mut state = UninitializedState::new(DummyAuthenticator, 44..=47);
+
+        let init = init(MIN_PROTOCOL_VERSION..=40, 29);
+        let error = state.handle_message(init, &connection).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<HandshakeError>(),
+            Some(&HandshakeError::UnsupportedVersion {
+                client: MIN_PROTOCOL_VERSION..=40,
+                server: None,
+            })
+        );
+        assert!(state.is_rejected());
+        assert_eq!(state.protocol_version(), None);
+    }
+
+    #[tokio::test]
+    async fn clients_without_zstd_map_blocks_are_rejected_by_default() {
+        let (connection, _peer_io, _from_peer) = connection();
+        let mut state = UninitializedState::new(DummyAuthenticator, DEFAULT_PROTOCOL_VERSIONS);
+
+        // a client of Luanti 5.4.0 only reads the serialization format 28
+        let init = init(MIN_PROTOCOL_VERSION..=39, 28);
+        let error = state.handle_message(init, &connection).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<HandshakeError>(),
+            Some(&HandshakeError::UnsupportedVersion {
+                client: MIN_PROTOCOL_VERSION..=39,
+                server: None,
+            })
+        );
+        assert!(state.is_rejected());
+    }
+
+    #[test]
+    fn version_mismatches_name_both_ranges() {
+        assert_eq!(
+            version_mismatch_message("protocol", &(37..=47), &(30..=36)),
+            "Your client's version is not supported.\nPlease contact the server administrator.\n\
+             The server supports protocol versions 37 to 47, your client 30 to 36."
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! The time of day of the world
+//!
+//! Like in the C++ engine, a day consists of 24000 ticks and `time_speed` tells how many times
+//! faster than real time the day passes. Clients advance the time on their own, so it is only
+//! being sent to them when they join, when it has been changed and every
+//! [`TIME_SEND_INTERVAL`] to correct their drift.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::debug;
+use luanti_protocol::commands::server_to_client::OverrideDayNightRatioSpec;
+use luanti_protocol::commands::server_to_client::TimeOfDaySpec;
+use luanti_protocol::types::TrailingBytes;
+use tokio::task::JoinHandle;
+
+use crate::client_connection::ConnectedPlayers;
+
+/// The number of ticks of a full day
+pub const TICKS_PER_DAY: u16 = 24000;
+
+/// The default `time_speed` of the C++ engine; a day lasts 20 minutes
+pub const DEFAULT_TIME_SPEED: f32 = 72.0;
+
+/// The default `world_start_time` of the C++ engine; shortly after sunrise
+pub const DEFAULT_START_TIME: u16 = 6125;
+
+/// The time is being sent to all players this often; the default `time_send_interval` of the C++
+/// engine
+pub const TIME_SEND_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The day-night ratio at full daylight
+pub const DAY_NIGHT_RATIO_DAY: u16 = 1000;
+
+/// The day-night ratio at the given points of time (in ticks after midnight) as used by the C++
+/// engine; the ratio is being interpolated linearly in between
+const DAY_NIGHT_RATIOS: [(u16, u16); 9] = [
+    (4375, 175),
+    (4625, 175),
+    (4875, 250),
+    (5125, 350),
+    (5375, 500),
+    (5625, 675),
+    (5875, 875),
+    (6125, 1000),
+    (6375, 1000),
+];
+
+/// The time of day shared by all players
+#[derive(Debug, Clone)]
+pub struct WorldClock {
+    state: Arc<Mutex<ClockState>>,
+    connected_players: ConnectedPlayers,
+}
+
+#[derive(Debug)]
+struct ClockState {
+    /// the time of day in ticks at `anchor`
+    time_at_anchor: f64,
+    anchor: Instant,
+    time_speed: f32,
+    /// day-night ratios forced upon individual players, by name
+    overrides: HashMap<String, u16>,
+}
+
+impl ClockState {
+    /// Returns the time of day in ticks.
+    fn time_of_day(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.anchor).as_secs_f64();
+        // a day lasts 86400 seconds at a speed of 1
+        let ticks = elapsed * f64::from(self.time_speed) * f64::from(TICKS_PER_DAY) / 86400.0;
+        (self.time_at_anchor + ticks).rem_euclid(f64::from(TICKS_PER_DAY))
+    }
+
+    /// Restarts the time from the current point, so a new speed only applies from now on.
+    fn reanchor(&mut self, now: Instant) {
+        self.time_at_anchor = self.time_of_day(now);
+        self.anchor = now;
+    }
+
+    fn ticks(&self, now: Instant) -> u16 {
+        #[expect(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            reason = "the time of day is always within a single day"
+        )]
+        let ticks = self.time_of_day(now) as u16;
+        ticks.min(TICKS_PER_DAY - 1)
+    }
+}
+
+impl WorldClock {
+    pub(crate) fn new(connected_players: ConnectedPlayers) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ClockState {
+                time_at_anchor: f64::from(DEFAULT_START_TIME),
+                anchor: Instant::now(),
+                time_speed: DEFAULT_TIME_SPEED,
+                overrides: HashMap::new(),
+            })),
+            connected_players,
+        }
+    }
+
+    /// Returns the time of day in ticks after midnight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    #[must_use]
+    pub fn time_of_day(&self) -> u16 {
+        self.state
+            .lock()
+            .expect("poisoned world clock")
+            .ticks(Instant::now())
+    }
+
+    /// Returns how many times faster than real time the day passes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    #[must_use]
+    pub fn time_speed(&self) -> f32 {
+        self.state.lock().expect("poisoned world clock").time_speed
+    }
+
+    /// Sets the time of day in ticks after midnight and informs all players. Values beyond a
+    /// single day wrap around.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    pub fn set_time(&self, time_of_day: u16) {
+        {
+            let mut state = self.state.lock().expect("poisoned world clock");
+            state.time_at_anchor = f64::from(time_of_day % TICKS_PER_DAY);
+            state.anchor = Instant::now();
+        }
+        self.broadcast();
+    }
+
+    /// Applies the time and, if present, the speed of a [`TimeOfDaySpec`] and informs all
+    /// players.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    pub fn set_time_of_day(&self, spec: &TimeOfDaySpec) {
+        {
+            let mut state = self.state.lock().expect("poisoned world clock");
+            state.time_at_anchor = f64::from(spec.time_of_day % TICKS_PER_DAY);
+            state.anchor = Instant::now();
+            if let Some(time_speed) = spec.time_speed {
+                state.time_speed = time_speed.max(0.0);
+            }
+        }
+        self.broadcast();
+    }
+
+    /// Sets how many times faster than real time the day passes and informs all players. `0`
+    /// stops the time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    pub fn set_time_speed(&self, time_speed: f32) {
+        {
+            let mut state = self.state.lock().expect("poisoned world clock");
+            state.reanchor(Instant::now());
+            state.time_speed = time_speed.max(0.0);
+        }
+        self.broadcast();
+    }
+
+    /// Returns the day-night ratio the given player sees, which ranges from `0` (night) to
+    /// [`DAY_NIGHT_RATIO_DAY`]. Use it with [`MapNode::blend_light`](luanti_core::MapNode::blend_light) to
+    /// compute the light level of a node as perceived by the player.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the clock's lock.
+    #[must_use]
+    pub fn day_night_ratio(&self, player: &str) -> u16 {
+        let state = self.state.lock().expect("poisoned world clock");
+        state
+            .overrides
+            .get(player)
+            .copied()
+            .unwrap_or_else(|| day_night_ratio(state.ticks(Instant::now())))
+    }
+
+    /// Forces a day-night ratio upon a player regardless of the time of day; `None` removes the
+    /// override. Returns `false` if the player is offline.
+    #[must_use]
+    pub fn override_day_night_ratio(&self, player: &str, ratio: Option<u16>) -> bool {
+        let spec = OverrideDayNightRatioSpec {
+            do_override: ratio.is_some(),
+            day_night_ratio: ratio.map_or(0, |ratio| ratio.min(DAY_NIGHT_RATIO_DAY)),
+        };
+        if !self.connected_players.send(player, spec.into()) {
+            return false;
+        }
+        self.set_override(player, ratio);
+        true
+    }
+
+    /// Remembers an override which has been sent to a player.
+    pub(crate) fn set_override(&self, player: &str, ratio: Option<u16>) {
+        let mut state = self.state.lock().expect("poisoned world clock");
+        match ratio {
+            Some(ratio) => state
+                .overrides
+                .insert(player.to_owned(), ratio.min(DAY_NIGHT_RATIO_DAY)),
+            None => state.overrides.remove(player),
+        };
+    }
+
+    /// Forgets about a player who left the game.
+    pub(crate) fn leave(&self, player: &str) {
+        self.set_override(player, None);
+    }
+
+    /// Returns the command telling a client the current time.
+    pub(crate) fn time_of_day_spec(&self) -> TimeOfDaySpec {
+        let state = self.state.lock().expect("poisoned world clock");
+        TimeOfDaySpec {
+            time_of_day: state.ticks(Instant::now()),
+            time_speed: Some(state.time_speed),
+            extra: TrailingBytes::default(),
+        }
+    }
+
+    /// Sends the current time to all players.
+    fn broadcast(&self) {
+        let spec = self.time_of_day_spec();
+        let receivers = self.connected_players.broadcast(&spec.clone().into());
+        debug!(
+            "sent time of day {} to {receivers} players",
+            spec.time_of_day
+        );
+    }
+
+    /// Starts a task which keeps sending the time to all players until the returned handle is
+    /// aborted.
+    #[must_use]
+    pub(crate) fn spawn_broadcasts(&self) -> JoinHandle<()> {
+        let clock = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TIME_SEND_INTERVAL);
+            loop {
+                interval.tick().await;
+                clock.broadcast();
+            }
+        })
+    }
+}
+
+/// Returns the day-night ratio at the given time of day in ticks after midnight, ranging from `0`
+/// (night) to [`DAY_NIGHT_RATIO_DAY`].
+#[must_use]
+pub fn day_night_ratio(time_of_day: u16) -> u16 {
+    let time = time_of_day % TICKS_PER_DAY;
+    // the evening mirrors the morning
+    let time = if time > TICKS_PER_DAY / 2 {
+        TICKS_PER_DAY - time
+    } else {
+        time
+    };
+    for pair in DAY_NIGHT_RATIOS.windows(2) {
+        let &[(start, start_ratio), (end, end_ratio)] = pair else {
+            continue;
+        };
+        if time < start {
+            return start_ratio;
+        }
+        if time < end {
+            let progress = u32::from(time - start);
+            let length = u32::from(end - start);
+            let ratio = (u32::from(start_ratio) * (length - progress)
+                + u32::from(end_ratio) * progress)
+                / length;
+            return u16::try_from(ratio).unwrap_or(DAY_NIGHT_RATIO_DAY);
+        }
+    }
+    DAY_NIGHT_RATIO_DAY
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use luanti_protocol::commands::server_to_client::TimeOfDaySpec;
+    use luanti_protocol::types::TrailingBytes;
+
+    use super::{ClockState, DAY_NIGHT_RATIO_DAY, WorldClock, day_night_ratio};
+    use crate::client_connection::ConnectedPlayers;
+
+    #[test]
+    fn time_advances_with_its_speed() {
+        let start = Instant::now();
+        let mut state = ClockState {
+            time_at_anchor: 23000.0,
+            anchor: start,
+            time_speed: 72.0,
+            overrides: HashMap::new(),
+        };
+        // 20 minutes per day make 20 ticks per second
+        assert_eq!(state.ticks(start + Duration::from_secs(10)), 23200);
+        // wraps around at midnight
+        assert_eq!(state.ticks(start + Duration::from_secs(60)), 200);
+
+        state.reanchor(start + Duration::from_secs(60));
+        state.time_speed = 0.0;
+        assert_eq!(state.ticks(start + Duration::from_secs(600)), 200);
+    }
+
+    #[test]
+    fn day_night_ratio_follows_the_sun() {
+        assert_eq!(day_night_ratio(0), 175);
+        assert_eq!(day_night_ratio(4625), 175);
+        assert_eq!(day_night_ratio(5000), 300);
+        assert_eq!(day_night_ratio(12000), DAY_NIGHT_RATIO_DAY);
+        // the evening mirrors the morning
+        assert_eq!(day_night_ratio(19000), 300);
+        assert_eq!(day_night_ratio(23999), 175);
+    }
+
+    #[test]
+    fn time_updates_set_time_and_speed() {
+        let clock = WorldClock::new(ConnectedPlayers::default());
+        clock.set_time_of_day(&TimeOfDaySpec {
+            time_of_day: 30000,
+            time_speed: Some(0.0),
+            extra: TrailingBytes::default(),
+        });
+        assert_eq!(clock.time_of_day(), 6000);
+        assert!(clock.time_speed() <= 0.0);
+
+        // the speed is optional
+        clock.set_time_of_day(&TimeOfDaySpec {
+            time_of_day: 100,
+            time_speed: None,
+            extra: TrailingBytes::default(),
+        });
+        assert_eq!(clock.time_of_day(), 100);
+        assert!(clock.time_speed() <= 0.0);
+    }
+
+    #[tokio::test]
+    async fn broadcasts_can_be_stopped() {
+        let clock = WorldClock::new(ConnectedPlayers::default());
+        let broadcasts = clock.spawn_broadcasts();
+        broadcasts.abort();
+        assert!(broadcasts.await.is_err_and(|error| error.is_cancelled()));
+    }
+}
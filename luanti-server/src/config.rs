@@ -0,0 +1,494 @@
+//! Server settings loaded from `minetest.conf`-style files
+//!
+//! The keys of the C++ engine are being used where they exist, so an existing `minetest.conf` can
+//! be reused. Settings unknown to this server are ignored.
+
+pub mod file;
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use file::{Config, ConfigFile};
+use glam::Vec3;
+use luanti_protocol::commands::server_to_client::{CsmRestrictionFlags, CsmRestrictions};
+
+#[cfg(feature = "sqlite")]
+use crate::world::storage::sqlite::SqliteStorage;
+use crate::{
+    clock::{DEFAULT_START_TIME, DEFAULT_TIME_SPEED},
+    health::HealthConfig,
+    send_rate::SendRateConfig,
+    server::DEFAULT_MAX_CLIENTS,
+    serverlist::AnnounceConfig,
+    status::ServerInfo,
+    world::{
+        backup::BackupConfig,
+        content_id_mapper::ContentIdMapper,
+        map_meta::parse_seed,
+        storage::{WorldStorage, dummy::DummyStorage, minetestworld::MinetestworldStorage},
+        view_tracker::ViewConfig,
+    },
+};
+
+/// The port being used if the configuration doesn't specify one
+pub const DEFAULT_PORT: u16 = 30000;
+
+/// The storage implementation a world is being served from
+///
+/// Servers built without the `sqlite` feature default to [`Self::Minetestworld`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageBackend {
+    /// reads and writes the `map.sqlite` of the world directly; requires the `sqlite` feature
+    #[cfg_attr(feature = "sqlite", default)]
+    Sqlite3,
+    /// uses the `minetestworld` crate; see [`MinetestworldStorage`]
+    #[cfg_attr(not(feature = "sqlite"), default)]
+    Minetestworld,
+    /// doesn't store anything at all; see [`DummyStorage`]
+    Dummy,
+}
+
+impl StorageBackend {
+    /// Opens the storage of the world in the given directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage cannot be opened or its backend hasn't been built in.
+    pub async fn open(
+        self,
+        world_directory: impl AsRef<Path>,
+        content_ids: Arc<ContentIdMapper>,
+    ) -> Result<Box<dyn WorldStorage>> {
+        let storage: Box<dyn WorldStorage> = match self {
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite3 => Box::new(SqliteStorage::new(world_directory, content_ids).await?),
+            #[cfg(not(feature = "sqlite"))]
+            Self::Sqlite3 => bail!("the `{self}` storage requires the `sqlite` feature"),
+            Self::Minetestworld => {
+                Box::new(MinetestworldStorage::new(world_directory, content_ids).await?)
+            }
+            Self::Dummy => Box::new(DummyStorage),
+        };
+        Ok(storage)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sqlite3 => "sqlite3",
+            Self::Minetestworld => "minetestworld",
+            Self::Dummy => "dummy",
+        }
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        [Self::Sqlite3, Self::Minetestworld, Self::Dummy]
+            .into_iter()
+            .find(|backend| backend.name() == name)
+            .ok_or_else(|| anyhow!("unknown storage backend `{name}`"))
+    }
+}
+
+impl Display for StorageBackend {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+/// The settings of a server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// the address to accept connections on; `bind_address` and `port`
+    pub bind_addr: SocketAddr,
+    /// the maximum number of players being online at the same time; `max_users`
+    pub max_clients: usize,
+    /// the view range cap and the number of map blocks in flight;
+    /// `max_block_send_distance` and `max_simultaneous_block_sends_per_client`
+    pub view_config: ViewConfig,
+    /// how often frequent updates are being sent to each player; `dedicated_server_step` in
+    /// seconds
+    pub send_rate: SendRateConfig,
+    /// the message of the day being shown to joining players; `motd`
+    pub motd: String,
+    /// how the server is presented in the server list; `server_name`, `server_description` and
+    /// `server_url`
+    pub server_info: ServerInfo,
+    /// announcements to the public server list; enabled by `server_announce`, with the list in
+    /// `serverlist_url` and the address clients shall connect to in `server_address`
+    pub announce: Option<AnnounceConfig>,
+    /// the features client-side mods may not use; `csm_restriction_flags` as a bit set and
+    /// `csm_restriction_noderange`
+    pub csm_restrictions: CsmRestrictions,
+    /// directories containing the media files being sent to clients; `media_paths` as a comma
+    /// separated list
+    pub media_paths: Vec<PathBuf>,
+    /// the directory of the world being served; `world_path`
+    pub world_path: PathBuf,
+    /// how the map of the world is being stored; `backend`
+    pub storage_backend: StorageBackend,
+    /// the seed of the map generator for new worlds; `fixed_map_seed`, see [`parse_seed`]
+    ///
+    /// Existing worlds keep their seed; see [`world_seed`](crate::world::map_meta::world_seed).
+    pub fixed_map_seed: Option<u64>,
+    /// periodic backups of the map; enabled by `backup_path`, with `backup_interval` in seconds and
+    /// the number of backups to keep in `backup_keep`
+    pub backup: Option<BackupConfig>,
+    /// whether players can be hurt; `enable_damage`
+    pub health: HealthConfig,
+    /// where players respawn; `static_spawnpoint` as `(x, y, z)` in nodes
+    pub spawn_point: Option<Vec3>,
+    /// how many times faster than real time the day passes; `time_speed`
+    pub time_speed: f32,
+    /// the time of day in ticks when the server starts; `world_start_time`
+    pub start_time: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DEFAULT_PORT),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            view_config: ViewConfig::default(),
+            send_rate: SendRateConfig::default(),
+            motd: String::new(),
+            server_info: ServerInfo::default(),
+            announce: None,
+            csm_restrictions: CsmRestrictions::default(),
+            media_paths: Vec::new(),
+            world_path: PathBuf::from("worlds/world"),
+            storage_backend: StorageBackend::default(),
+            fixed_map_seed: None,
+            backup: None,
+            health: HealthConfig::default(),
+            spawn_point: None,
+            time_speed: DEFAULT_TIME_SPEED,
+            start_time: DEFAULT_START_TIME,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Loads the settings from a `minetest.conf`-style file. Missing settings keep their
+    /// defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains invalid values.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let file = ConfigFile::load(path)?;
+        Self::from_config(file.config())
+            .with_context(|| format!("invalid settings in {}", file.path().display()))
+    }
+
+    /// Extracts the settings from a parsed configuration. Missing settings keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a setting has an invalid value.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let defaults = Self::default();
+
+        let bind_ip = match config.get_str("bind_address") {
+            // the C++ engine treats an empty address like an unspecified one
+            None | Some("") => defaults.bind_addr.ip(),
+            Some(_) => parse(config, "bind_address")?.unwrap_or(defaults.bind_addr.ip()),
+        };
+        let port = parse(config, "port")?.unwrap_or(defaults.bind_addr.port());
+
+        let view_config = ViewConfig {
+            max_range: parse(config, "max_block_send_distance")?
+                .unwrap_or(defaults.view_config.max_range),
+            max_blocks_in_flight: parse(config, "max_simultaneous_block_sends_per_client")?
+                .unwrap_or(defaults.view_config.max_blocks_in_flight),
+            // `0` always sends the changed nodes
+            max_node_changes: parse(config, "max_node_changes_per_block")?
+                .map_or(defaults.view_config.max_node_changes, |max| {
+                    (max > 0).then_some(max)
+                }),
+            ..defaults.view_config
+        };
+
+        let send_rate = match parse::<f32>(config, "dedicated_server_step")? {
+            None => defaults.send_rate,
+            Some(seconds) => SendRateConfig {
+                interval: Duration::try_from_secs_f32(seconds)
+                    .ok()
+                    .filter(|interval| !interval.is_zero())
+                    .ok_or_else(|| {
+                        anyhow!("`dedicated_server_step` must be a positive number of seconds")
+                    })?,
+            },
+        };
+
+        let media_paths = config
+            .get_str("media_paths")
+            .map_or(defaults.media_paths, |paths| {
+                paths
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            });
+
+        let backup = match config.get_str("backup_path") {
+            None | Some("") => None,
+            Some(path) => {
+                let mut backup = BackupConfig::new(path);
+                if let Some(seconds) = parse(config, "backup_interval")? {
+                    if seconds == 0 {
+                        bail!("`backup_interval` must not be zero");
+                    }
+                    backup.interval = Duration::from_secs(seconds);
+                }
+                backup.keep = parse(config, "backup_keep")?.unwrap_or(backup.keep);
+                Some(backup)
+            }
+        };
+
+        let health = HealthConfig {
+            enable_damage: parse_bool(config, "enable_damage")?
+                .unwrap_or(defaults.health.enable_damage),
+            ..defaults.health
+        };
+        let server_info = parse_server_info(config, defaults.server_info)?;
+        let announce = parse_announce_config(config, port)?;
+        let csm_restrictions = CsmRestrictions {
+            flags: parse(config, "csm_restriction_flags")?.map_or(
+                defaults.csm_restrictions.flags,
+                CsmRestrictionFlags::from_bits_retain,
+            ),
+            noderange: parse(config, "csm_restriction_noderange")?
+                .unwrap_or(defaults.csm_restrictions.noderange),
+        };
+        let spawn_point = config
+            .get_str("static_spawnpoint")
+            .map(|value| {
+                parse_position(value).ok_or_else(|| {
+                    anyhow!("invalid value `{value}` for `static_spawnpoint`: expected `(x, y, z)`")
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            bind_addr: SocketAddr::new(bind_ip, port),
+            max_clients: parse(config, "max_users")?.unwrap_or(defaults.max_clients),
+            view_config,
+            send_rate,
+            motd: get_string(config, "motd", defaults.motd),
+            server_info,
+            announce,
+            csm_restrictions,
+            media_paths,
+            world_path: config
+                .get_str("world_path")
+                .map_or(defaults.world_path, PathBuf::from),
+            storage_backend: parse(config, "backend")?.unwrap_or(defaults.storage_backend),
+            fixed_map_seed: config
+                .get_str("fixed_map_seed")
+                .filter(|value| !value.trim().is_empty())
+                .map(parse_seed),
+            backup,
+            health,
+            spawn_point,
+            time_speed: parse(config, "time_speed")?.unwrap_or(defaults.time_speed),
+            start_time: parse(config, "world_start_time")?.unwrap_or(defaults.start_time),
+        })
+    }
+}
+
+/// Extracts the description of the server; missing settings keep the given defaults.
+fn parse_server_info(config: &Config, defaults: ServerInfo) -> Result<ServerInfo> {
+    Ok(ServerInfo {
+        name: get_string(config, "server_name", defaults.name),
+        description: get_string(config, "server_description", defaults.description),
+        url: get_string(config, "server_url", defaults.url),
+        creative: parse_bool(config, "creative_mode")?.unwrap_or(defaults.creative),
+        pvp: parse_bool(config, "enable_pvp")?.unwrap_or(defaults.pvp),
+        password: parse_bool(config, "disallow_empty_password")?.unwrap_or(defaults.password),
+        ..defaults
+    })
+}
+
+/// Extracts the announcements to the server list; `None` unless `server_announce` is enabled.
+fn parse_announce_config(config: &Config, port: u16) -> Result<Option<AnnounceConfig>> {
+    let enabled = parse_bool(config, "server_announce")?.unwrap_or(false);
+    Ok(enabled.then(|| {
+        let mut announce = AnnounceConfig::new(port);
+        announce.url = get_string(config, "serverlist_url", announce.url);
+        announce.address = config
+            .get_str("server_address")
+            .filter(|address| !address.is_empty())
+            .map(ToOwned::to_owned);
+        announce
+    }))
+}
+
+/// Returns a setting as text; returns the default if it's missing.
+fn get_string(config: &Config, key: &str, default: String) -> String {
+    config.get_str(key).map_or(default, ToOwned::to_owned)
+}
+
+/// Parses a setting; returns `None` if it's missing.
+fn parse<T>(config: &Config, key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    config
+        .get_str(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|error| anyhow!("invalid value `{value}` for `{key}`: {error}"))
+        })
+        .transpose()
+}
+
+/// Parses a boolean setting like the C++ engine, which also accepts `yes` and numbers; returns
+/// `None` if it's missing.
+fn parse_bool(config: &Config, key: &str) -> Result<Option<bool>> {
+    config
+        .get_str(key)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            number => number
+                .parse::<i64>()
+                .map(|number| number != 0)
+                .map_err(|error| anyhow!("invalid value `{value}` for `{key}`: {error}")),
+        })
+        .transpose()
+}
+
+/// Parses a position written as `(x, y, z)`; the parentheses are optional.
+fn parse_position(value: &str) -> Option<Vec3> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('(')
+        .and_then(|value| value.strip_suffix(')'))
+        .unwrap_or(value);
+    let mut coordinates = value
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse().ok());
+    let position = Vec3::new(
+        coordinates.next()??,
+        coordinates.next()??,
+        coordinates.next()??,
+    );
+    coordinates.next().is_none().then_some(position)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{path::PathBuf, time::Duration};
+
+    use glam::Vec3;
+    use luanti_protocol::commands::server_to_client::CsmRestrictionFlags;
+
+    use super::{ServerConfig, StorageBackend};
+    use crate::config::file::Config;
+
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        let config = ServerConfig::from_config(&Config::parse("# empty\n").unwrap()).unwrap();
+        assert_eq!(config, ServerConfig::default());
+    }
+
+    #[test]
+    fn settings_are_being_applied() {
+        let text = "bind_address = 127.0.0.1\n\
+                    port = 30005\n\
+                    max_users = 3\n\
+                    max_block_send_distance = 6\n\
+                    max_node_changes_per_block = 0\n\
+                    dedicated_server_step = 0.25\n\
+                    motd = Hello there\n\
+                    server_name = Rusty\n\
+                    server_announce = true\n\
+                    server_address = luanti.example.com\n\
+                    csm_restriction_flags = 17\n\
+                    csm_restriction_noderange = 8\n\
+                    media_paths = assets, more/assets\n\
+                    world_path = worlds/test\n\
+                    fixed_map_seed = 12345\n\
+                    backend = dummy\n\
+                    time_speed = 0\n\
+                    world_start_time = 12000\n";
+        let config = ServerConfig::from_config(&Config::parse(text).unwrap()).unwrap();
+        assert_eq!(config.bind_addr, "127.0.0.1:30005".parse().unwrap());
+        assert_eq!(config.max_clients, 3);
+        assert_eq!(config.view_config.max_range, 6);
+        assert_eq!(config.view_config.max_node_changes, None);
+        assert_eq!(config.send_rate.interval, Duration::from_millis(250));
+        assert_eq!(config.motd, "Hello there");
+        assert_eq!(config.server_info.name, "Rusty");
+        let announce = config.announce.unwrap();
+        assert_eq!(announce.port, 30005);
+        assert_eq!(announce.address.as_deref(), Some("luanti.example.com"));
+        assert_eq!(
+            config.csm_restrictions.flags,
+            CsmRestrictionFlags::LOAD_CLIENT_MODS | CsmRestrictionFlags::LOOKUP_NODES
+        );
+        assert_eq!(config.csm_restrictions.noderange, 8);
+        assert_eq!(
+            config.media_paths,
+            [PathBuf::from("assets"), PathBuf::from("more/assets")]
+        );
+        assert_eq!(config.world_path, PathBuf::from("worlds/test"));
+        assert_eq!(config.storage_backend, StorageBackend::Dummy);
+        assert_eq!(config.fixed_map_seed, Some(12345));
+        assert_eq!(config.backup, None);
+        assert!(config.time_speed.abs() < f32::EPSILON);
+        assert_eq!(config.start_time, 12000);
+    }
+
+    #[test]
+    fn health_settings_are_being_applied() {
+        let text = "enable_damage = false
+                    static_spawnpoint = (10, 2.5, -30)
+";
+        let config = ServerConfig::from_config(&Config::parse(text).unwrap()).unwrap();
+        assert!(!config.health.enable_damage);
+        assert_eq!(config.spawn_point, Some(Vec3::new(10.0, 2.5, -30.0)));
+    }
+
+    #[test]
+    fn backups_are_enabled_by_their_path() {
+        let text = "backup_path = backups\n\
+                    backup_interval = 600\n\
+                    backup_keep = 5\n";
+        let config = ServerConfig::from_config(&Config::parse(text).unwrap()).unwrap();
+        let backup = config.backup.unwrap();
+        assert_eq!(backup.directory, PathBuf::from("backups"));
+        assert_eq!(backup.interval, Duration::from_secs(600));
+        assert_eq!(backup.keep, 5);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for text in [
+            "port = 70000",
+            "max_users = many",
+            "backend = leveldb",
+            "backup_path = backups\nbackup_interval = 0",
+            "enable_damage = maybe",
+            "static_spawnpoint = 1, 2",
+        ] {
+            let config = Config::parse(text).unwrap();
+            assert!(ServerConfig::from_config(&config).is_err(), "{text}");
+        }
+    }
+}
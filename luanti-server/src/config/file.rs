@@ -1,43 +1,62 @@
-// TODO this entire parser is in draft state. Missing features are: lossless re-write, all sorts of tests
+//! Parser for `minetest.conf`-style configuration files
+//!
+//! Comments and blank lines are being retained, so a parsed file can be written back.
+// TODO the indentation of group values isn't being retained when writing a file back
 
 use std::{
     fmt::Display,
     fs,
     io::{BufRead, BufReader},
     mem,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use flexstr::SharedStr;
 
-#[derive(Default)]
-struct ConfigFile {
-    path: Option<PathBuf>,
+/// A configuration file which has been loaded from disk
+pub struct ConfigFile {
+    path: PathBuf,
     config: Config,
 }
 
 impl ConfigFile {
-    pub fn load(path: PathBuf) -> Result<Self> {
-        let reader = fs::File::open(&path)?;
+    /// Reads and parses the configuration file at the given path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains invalid lines.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let reader =
+            fs::File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
         let reader = BufReader::new(reader);
 
         let mut config_builder = ConfigBuilder::default();
         for line in reader.lines() {
             let line = line?;
-            config_builder.parse_line(&line)?;
+            config_builder
+                .parse_line(&line)
+                .with_context(|| format!("failed to parse {}", path.display()))?;
         }
-        let config = config_builder.finish()?;
+        let config = config_builder
+            .finish()
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        Ok(Self { path, config })
+    }
 
-        Ok(Self {
-            path: Some(path),
-            config,
-        })
+    /// Returns the path the file has been loaded from.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    // pub fn path(&self) -> Option<&PathBuf> {
-    //     self.path.as_ref()
-    // }
+    /// Returns the parsed content of the file.
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 }
 
 #[derive(Default)]
@@ -98,9 +117,16 @@ impl ConfigBuilder {
                     let value = value.trim();
 
                     if value == "{" {
+                        let mut builder = Self::new(self.config.depth + 1);
+                        builder.termination_tag = Some(SharedStr::from_borrowed("}"));
                         ConfigBuilderState::Section {
                             key: key.to_owned().into(),
-                            builder: Box::new(Self::new(self.config.depth + 1)),
+                            builder: Box::new(builder),
+                        }
+                    } else if value == r#"""""# {
+                        ConfigBuilderState::Multiline {
+                            key: key.to_owned().into(),
+                            multiline: String::new(),
                         }
                     } else {
                         let item = ConfigItem {
@@ -178,8 +204,9 @@ impl ConfigBuilder {
     }
 }
 
+/// The settings of a configuration file or of a group value
 #[derive(Default)]
-struct Config {
+pub struct Config {
     items: Vec<ConfigItem>,
     depth: u32,
 }
@@ -191,6 +218,58 @@ impl Config {
             depth,
         }
     }
+
+    /// Parses the content of a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is neither a comment nor a `key = value` pair or if a group or
+    /// multiline value isn't terminated.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config_builder = ConfigBuilder::default();
+        for line in text.lines() {
+            config_builder.parse_line(line)?;
+        }
+        config_builder.finish()
+    }
+
+    /// Returns the value of a setting. If a key occurs multiple times the last one wins, just
+    /// like in the C++ engine.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.items
+            .iter()
+            .rev()
+            .filter_map(|item| item.key_value.as_ref())
+            .find(|(item_key, _)| item_key.as_ref_type() == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value of a setting if it's a plain string.
+    #[must_use]
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            ConfigValue::String(value) => Some(value.as_ref_type()),
+            ConfigValue::Group(_) => None,
+        }
+    }
+
+    /// Returns the value of a setting if it's a group of settings.
+    #[must_use]
+    pub fn get_group(&self, key: &str) -> Option<&Config> {
+        match self.get(key)? {
+            ConfigValue::String(_) => None,
+            ConfigValue::Group(group) => Some(group),
+        }
+    }
+
+    /// Iterates over all keys in the order of their appearance.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .filter_map(|item| item.key_value.as_ref())
+            .map(|(key, _)| key.as_ref_type())
+    }
 }
 
 impl Display for Config {
@@ -211,11 +290,16 @@ struct ConfigItem {
 impl Display for ConfigItem {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for line in &self.prelude {
-            formatter.write_str(line)?;
+            writeln!(formatter, "{line}")?;
         }
         if let Some((key, value)) = &self.key_value {
             write!(formatter, "{key} = ")?;
             match value {
+                ConfigValue::String(str) if str.contains('\n') => {
+                    writeln!(formatter, r#"""""#)?;
+                    writeln!(formatter, "{str}")?;
+                    writeln!(formatter, r#"""""#)?;
+                }
                 ConfigValue::String(str) => writeln!(formatter, "{str}")?,
                 ConfigValue::Group(group) => {
                     writeln!(formatter, "{{")?;
@@ -230,7 +314,67 @@ impl Display for ConfigItem {
     }
 }
 
-enum ConfigValue {
+/// The value of a single setting
+pub enum ConfigValue {
+    /// a plain value, possibly spanning multiple lines
     String(SharedStr),
+    /// a nested group of settings enclosed in `{` and `}`
     Group(Config),
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use super::Config;
+
+    const TEXT: &str = r#"# generated by luanti-cli
+server_name = My World
+port = 30000
+
+motd = """
+Welcome!
+Be nice.
+"""
+secure = {
+    # comment inside a group
+    enable_security = true
+}
+port = 30001
+"#;
+
+    #[test]
+    fn settings_can_be_looked_up() {
+        let config = Config::parse(TEXT).unwrap();
+        assert_eq!(config.get_str("server_name"), Some("My World"));
+        assert_eq!(config.get_str("port"), Some("30001"), "the last key wins");
+        assert_eq!(config.get_str("motd"), Some("Welcome!\nBe nice."));
+        assert_eq!(
+            config
+                .get_group("secure")
+                .and_then(|group| group.get_str("enable_security")),
+            Some("true")
+        );
+        assert_eq!(config.get_str("secure"), None);
+        assert_eq!(config.get_str("missing"), None);
+        assert_eq!(
+            config.keys().collect::<Vec<_>>(),
+            ["server_name", "port", "motd", "secure", "port"]
+        );
+    }
+
+    #[test]
+    fn written_files_can_be_parsed_again() {
+        let config = Config::parse(TEXT).unwrap();
+        let reparsed = Config::parse(&config.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), config.to_string());
+        assert_eq!(reparsed.get_str("motd"), Some("Welcome!\nBe nice."));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(Config::parse("no value").is_err());
+        assert!(Config::parse("group = {\nkey = value\n").is_err());
+        assert!(Config::parse("text = \"\"\"\nunterminated\n").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Introspection of the client connections of a server, e.g. for admin tools or the `/status`
+//! command
+//!
+//! Every connection publishes a [`ConnectionInfo`] when it advances to another state and
+//! refreshes it periodically. The information is therefore a few seconds old at most.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use luanti_protocol::versions::ClientVersion;
+
+/// The steps a client passes while joining a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionState {
+    /// the client hasn't agreed on a protocol version yet
+    Initializing,
+    /// the client is logging in or registering a new player
+    Authenticating,
+    /// the player has been authenticated and the client is setting up
+    Setup,
+    /// the client receives definitions and media
+    Loading,
+    /// the player has joined the game
+    Running,
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Initializing => "initializing",
+            Self::Authenticating => "authenticating",
+            Self::Setup => "setup",
+            Self::Loading => "loading",
+            Self::Running => "running",
+        })
+    }
+}
+
+/// A snapshot of the state of a single client connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// identifies the connection within the server's log
+    pub id: u64,
+    /// the address of the client
+    pub address: SocketAddr,
+    /// how far the client got while joining
+    pub state: ConnectionState,
+    /// the name of the player; `None` before the client introduced itself
+    pub player_name: Option<String>,
+    /// the protocol version negotiated with the client; `None` before the client introduced
+    /// itself
+    pub protocol_version: Option<u16>,
+    /// the language of the client's user interface, if it told the server about it
+    pub language: Option<String>,
+    /// the engine version of the client including the newest formspec version it understands;
+    /// `None` until the client finished loading
+    pub version: Option<ClientVersion>,
+    /// the time between sending a packet and receiving its acknowledgement; `None` until the
+    /// client acknowledged a packet
+    pub rtt: Option<Duration>,
+    /// the number of map blocks waiting to be sent or to be confirmed by the client
+    pub pending_blocks: usize,
+}
+
+impl Display for ConnectionInfo {
+    /// Renders a single line like `[3] singleplayer@127.0.0.1:50000: running, protocol 47,
+    /// client 5.10.0, formspec 8, language de, rtt 12ms, 4 pending blocks`.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "[{}] {}@{}: {}",
+            self.id,
+            self.player_name.as_deref().unwrap_or("?"),
+            self.address,
+            self.state
+        )?;
+        if let Some(protocol_version) = self.protocol_version {
+            write!(formatter, ", protocol {protocol_version}")?;
+        }
+        if let Some(version) = &self.version {
+            write!(formatter, ", client {version}")?;
+            if let Some(formspec_version) = version.formspec_version {
+                write!(formatter, ", formspec {formspec_version}")?;
+            }
+        }
+        if let Some(language) = &self.language {
+            write!(formatter, ", language {language}")?;
+        }
+        if let Some(rtt) = self.rtt {
+            write!(formatter, ", rtt {}ms", rtt.as_millis())?;
+        }
+        write!(formatter, ", {} pending blocks", self.pending_blocks)
+    }
+}
+
+/// The state of all client connections of a server; cloning yields a handle to the same
+/// connections.
+#[derive(Debug, Clone, Default)]
+pub struct Connections {
+    /// by id
+    connections: Arc<Mutex<BTreeMap<u64, ConnectionInfo>>>,
+}
+
+impl Connections {
+    /// Returns the state of all connections ordered by id, i.e. the oldest connection first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the connections' lock.
+    #[must_use]
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .lock()
+            .expect("poisoned connections")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the state of the connection with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the connections' lock.
+    #[must_use]
+    pub fn get(&self, id: u64) -> Option<ConnectionInfo> {
+        self.connections
+            .lock()
+            .expect("poisoned connections")
+            .get(&id)
+            .cloned()
+    }
+
+    /// Returns the state of the connection of a player. If the player is about to replace an
+    /// older connection, the newer one is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the connections' lock.
+    #[must_use]
+    pub fn player(&self, name: &str) -> Option<ConnectionInfo> {
+        self.connections
+            .lock()
+            .expect("poisoned connections")
+            .values()
+            .rev()
+            .find(|info| info.player_name.as_deref() == Some(name))
+            .cloned()
+    }
+
+    /// Returns the number of open connections, including those of clients which are still
+    /// joining.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the connections' lock.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.connections.lock().expect("poisoned connections").len()
+    }
+
+    /// Returns `true` if no client is connected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the connections' lock.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn update(&self, info: ConnectionInfo) {
+        self.connections
+            .lock()
+            .expect("poisoned connections")
+            .insert(info.id, info);
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.connections
+            .lock()
+            .expect("poisoned connections")
+            .remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    use luanti_protocol::versions::ClientVersion;
+
+    use super::{ConnectionInfo, ConnectionState, Connections};
+
+    fn info(id: u64, player_name: Option<&str>) -> ConnectionInfo {
+        ConnectionInfo {
+            id,
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, 50000)),
+            state: ConnectionState::Initializing,
+            player_name: player_name.map(ToOwned::to_owned),
+            protocol_version: None,
+            language: None,
+            version: None,
+            rtt: None,
+            pending_blocks: 0,
+        }
+    }
+
+    #[test]
+    fn connections_are_listed_and_found() {
+        let connections = Connections::default();
+        assert!(connections.is_empty());
+        connections.update(info(2, Some("alice")));
+        connections.update(info(1, None));
+        connections.update(info(3, Some("alice")));
+        let ids: Vec<_> = connections.list().iter().map(|info| info.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        // the newer connection replaces the older one
+        assert_eq!(connections.player("alice").map(|info| info.id), Some(3));
+        assert_eq!(connections.player("bob"), None);
+
+        let mut running = info(3, Some("alice"));
+        running.state = ConnectionState::Running;
+        running.protocol_version = Some(47);
+        running.language = Some("de".to_owned());
+        running.version = Some(ClientVersion {
+            major: 5,
+            minor: 10,
+            patch: 0,
+            full: "5.10.0".to_owned(),
+            formspec_version: Some(8),
+        });
+        running.rtt = Some(Duration::from_millis(12));
+        running.pending_blocks = 4;
+        connections.update(running.clone());
+        assert_eq!(connections.get(3), Some(running));
+        assert_eq!(
+            connections.get(3).map(|info| info.to_string()).as_deref(),
+            Some(
+                "[3] alice@127.0.0.1:50000: running, protocol 47, client 5.10.0, formspec 8, \
+                 language de, rtt 12ms, 4 pending blocks"
+            )
+        );
+
+        connections.remove(2);
+        connections.remove(3);
+        assert_eq!(connections.player("alice"), None);
+        assert_eq!(connections.len(), 1);
+    }
+}
@@ -0,0 +1,298 @@
+//! Protection against players flooding the chat or spamming interactions
+//!
+//! Every connection has a token bucket per kind of message: each message takes a token and the
+//! tokens refill at a fixed rate up to the size of a burst. Messages arriving while the bucket is
+//! empty are dropped and count as a strike; once a player collected too many strikes without
+//! calming down in between, the configured [`FloodAction`] is taken. The defaults for the chat
+//! follow the C++ engine (`chat_message_limit_per_10sec` and `chat_message_limit_trigger_kick`).
+
+use std::time::{Duration, Instant};
+
+use luanti_protocol::commands::client_to_server::ToServerCommand;
+use luanti_protocol::commands::server_to_client::DisconnectReason;
+
+/// How many messages may be sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// number of messages which may be sent at once after a pause
+    pub burst: u32,
+    /// number of messages which may be sent per second on average
+    pub per_second: f32,
+}
+
+/// What happens once a player exceeded a [`RateLimit`] too often
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloodAction {
+    /// keep dropping the excess messages only
+    Drop,
+    /// drop all messages of that kind for the given time
+    Mute(Duration),
+    /// disconnect the player
+    Kick,
+}
+
+/// The limit of a single kind of message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloodLimit {
+    /// messages beyond this rate are dropped
+    pub rate: RateLimit,
+    /// number of dropped messages after which `action` is taken; the count starts over once the
+    /// player sent nothing for long enough to fill up the burst again
+    pub strikes: u32,
+    /// taken once the player collected `strikes`
+    pub action: FloodAction,
+}
+
+/// Settings of the flood protection; `None` disables the limit of a kind of message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloodConfig {
+    /// limits `TSChatMessage`
+    pub chat: Option<FloodLimit>,
+    /// limits `Interact`, i.e. digging, placing and using items
+    pub interact: Option<FloodLimit>,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            chat: Some(FloodLimit {
+                rate: RateLimit {
+                    burst: 8,
+                    per_second: 0.8,
+                },
+                strikes: 50,
+                action: FloodAction::Kick,
+            }),
+            // clients send a few interactions per second while digging or placing continuously
+            interact: Some(FloodLimit {
+                rate: RateLimit {
+                    burst: 100,
+                    per_second: 20.0,
+                },
+                strikes: 200,
+                action: FloodAction::Kick,
+            }),
+        }
+    }
+}
+
+impl FloodConfig {
+    /// Disables the flood protection entirely.
+    pub const DISABLED: Self = Self {
+        chat: None,
+        interact: None,
+    };
+}
+
+/// The outcome of [`FloodGuard::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FloodVerdict {
+    /// the message shall be processed
+    Accepted,
+    /// the message exceeded the rate limit and shall be ignored
+    Dropped,
+    /// the player has been muted for the given remaining time; the message shall be ignored
+    Muted(Duration),
+    /// the player shall be disconnected
+    Kick,
+}
+
+/// The token bucket of a single kind of message
+#[derive(Debug)]
+struct TokenBucket {
+    limit: FloodLimit,
+    tokens: f32,
+    updated: Instant,
+    strikes: u32,
+    muted_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "bursts are way smaller than 2^24"
+    )]
+    fn new(limit: FloodLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.rate.burst as f32,
+            updated: now,
+            strikes: 0,
+            muted_until: None,
+        }
+    }
+
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "bursts are way smaller than 2^24"
+    )]
+    fn take(&mut self, now: Instant) -> FloodVerdict {
+        if let Some(muted_until) = self.muted_until {
+            if now < muted_until {
+                return FloodVerdict::Muted(muted_until - now);
+            }
+            self.muted_until = None;
+        }
+
+        let burst = self.limit.rate.burst as f32;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f32();
+        self.updated = now;
+        self.tokens = elapsed
+            .mul_add(self.limit.rate.per_second, self.tokens)
+            .min(burst);
+        if self.tokens >= burst {
+            // the player calmed down
+            self.strikes = 0;
+        }
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return FloodVerdict::Accepted;
+        }
+
+        self.strikes += 1;
+        if self.strikes < self.limit.strikes {
+            return FloodVerdict::Dropped;
+        }
+        self.strikes = 0;
+        match self.limit.action {
+            FloodAction::Drop => FloodVerdict::Dropped,
+            FloodAction::Mute(duration) => {
+                self.muted_until = Some(now + duration);
+                FloodVerdict::Muted(duration)
+            }
+            FloodAction::Kick => FloodVerdict::Kick,
+        }
+    }
+}
+
+/// Applies the [`FloodConfig`] to the messages of a single connection.
+#[derive(Debug)]
+pub(crate) struct FloodGuard {
+    chat: Option<TokenBucket>,
+    interact: Option<TokenBucket>,
+}
+
+impl FloodGuard {
+    pub(crate) fn new(config: FloodConfig, now: Instant) -> Self {
+        Self {
+            chat: config.chat.map(|limit| TokenBucket::new(limit, now)),
+            interact: config.interact.map(|limit| TokenBucket::new(limit, now)),
+        }
+    }
+
+    /// Decides whether a message of the player shall be processed.
+    pub(crate) fn check(&mut self, message: &ToServerCommand, now: Instant) -> FloodVerdict {
+        let bucket = match message {
+            ToServerCommand::TSChatMessage(_) => self.chat.as_mut(),
+            ToServerCommand::Interact(_) => self.interact.as_mut(),
+            _ => None,
+        };
+        bucket.map_or(FloodVerdict::Accepted, |bucket| bucket.take(now))
+    }
+
+    /// Returns the reason shown to a player who is being kicked for flooding.
+    pub(crate) fn kick_reason(message: &ToServerCommand) -> DisconnectReason {
+        let kind = match message {
+            ToServerCommand::Interact(_) => "interaction",
+            _ => "message",
+        };
+        DisconnectReason::Custom(format!("You have been kicked due to {kind} flooding."))
+    }
+
+    /// Returns the message explaining to the player why a chat message has been ignored.
+    pub(crate) fn chat_notice(&self, verdict: FloodVerdict) -> Option<String> {
+        let limit = self.chat.as_ref()?.limit;
+        match verdict {
+            FloodVerdict::Accepted | FloodVerdict::Kick => None,
+            FloodVerdict::Dropped => Some(format!(
+                "You cannot send more messages. You are limited to {} messages per 10 seconds.",
+                limit.rate.per_second * 10.0
+            )),
+            FloodVerdict::Muted(remaining) => Some(format!(
+                "You have been muted for flooding the chat. Try again in {} seconds.",
+                remaining.as_secs().max(1)
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use luanti_protocol::commands::client_to_server::{TSChatMessageSpec, ToServerCommand};
+
+    use super::{FloodAction, FloodConfig, FloodGuard, FloodLimit, FloodVerdict, RateLimit};
+
+    fn chat() -> ToServerCommand {
+        TSChatMessageSpec {
+            message: "spam".to_owned(),
+        }
+        .into()
+    }
+
+    fn guard(action: FloodAction, now: Instant) -> FloodGuard {
+        FloodGuard::new(
+            FloodConfig {
+                chat: Some(FloodLimit {
+                    rate: RateLimit {
+                        burst: 3,
+                        per_second: 1.0,
+                    },
+                    strikes: 2,
+                    action,
+                }),
+                interact: None,
+            },
+            now,
+        )
+    }
+
+    #[test]
+    fn chat_is_limited() {
+        let now = Instant::now();
+        let mut guard = guard(FloodAction::Kick, now);
+        for _ in 0..3 {
+            assert_eq!(guard.check(&chat(), now), FloodVerdict::Accepted);
+        }
+        assert_eq!(guard.check(&chat(), now), FloodVerdict::Dropped);
+        assert!(guard.chat_notice(FloodVerdict::Dropped).is_some());
+
+        // a token has been refilled
+        let later = now + Duration::from_secs(1);
+        assert_eq!(guard.check(&chat(), later), FloodVerdict::Accepted);
+        // the strike of the previous burst still counts
+        assert_eq!(guard.check(&chat(), later), FloodVerdict::Kick);
+
+        // calming down forgives the strikes
+        let calm = later + Duration::from_secs(3);
+        for _ in 0..3 {
+            assert_eq!(guard.check(&chat(), calm), FloodVerdict::Accepted);
+        }
+        assert_eq!(guard.check(&chat(), calm), FloodVerdict::Dropped);
+    }
+
+    #[test]
+    fn flooders_are_muted() {
+        let now = Instant::now();
+        let mute = Duration::from_secs(60);
+        let mut guard = guard(FloodAction::Mute(mute), now);
+        for _ in 0..3 {
+            guard.check(&chat(), now);
+        }
+        assert_eq!(guard.check(&chat(), now), FloodVerdict::Dropped);
+        assert_eq!(guard.check(&chat(), now), FloodVerdict::Muted(mute));
+        let later = now + Duration::from_secs(30);
+        assert_eq!(
+            guard.check(&chat(), later),
+            FloodVerdict::Muted(Duration::from_secs(30))
+        );
+        assert_eq!(guard.check(&chat(), now + mute), FloodVerdict::Accepted);
+
+        // nothing is limited when disabled
+        let mut unlimited = FloodGuard::new(FloodConfig::DISABLED, now);
+        for _ in 0..100 {
+            assert_eq!(unlimited.check(&chat(), now), FloodVerdict::Accepted);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Typed construction of formspecs and dispatching of the client's responses
+//!
+//! Formspecs are transferred as plain strings using a rather fragile syntax. [`Formspec`] allows
+//! to assemble them from typed elements and takes care of escaping.
+
+pub mod dispatch;
+pub mod node_forms;
+pub mod version;
+
+use std::fmt::{self, Display, Write};
+
+use luanti_protocol::commands::server_to_client::ShowFormspecSpec;
+
+pub use dispatch::{FormDispatcher, FormResponse, FormSource};
+
+/// Identifies an inventory that can be shown in a formspec `list` element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InventoryRef {
+    /// The inventory of the player the formspec is being shown to
+    CurrentPlayer,
+    /// The inventory of the named player
+    Player(String),
+    /// The inventory of the node at the given position
+    NodeMeta {
+        /// x-coordinate of the node
+        x: i16,
+        /// y-coordinate of the node
+        y: i16,
+        /// z-coordinate of the node
+        z: i16,
+    },
+    /// The context dependent inventory of the node the formspec has been opened from
+    Context,
+    /// A detached inventory with the given name
+    Detached(String),
+}
+
+impl Display for InventoryRef {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrentPlayer => formatter.write_str("current_player"),
+            Self::Player(name) => write!(formatter, "player:{}", Escaped(name)),
+            Self::NodeMeta { x, y, z } => write!(formatter, "nodemeta:{x}\\,{y}\\,{z}"),
+            Self::Context => formatter.write_str("context"),
+            Self::Detached(name) => write!(formatter, "detached:{}", Escaped(name)),
+        }
+    }
+}
+
+/// A single element of a formspec
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    /// `size[<W>,<H>]`
+    Size {
+        /// width in inventory slots
+        width: f32,
+        /// height in inventory slots
+        height: f32,
+    },
+    /// `label[<X>,<Y>;<label>]`
+    Label {
+        /// x-position
+        x: f32,
+        /// y-position
+        y: f32,
+        /// text to be shown
+        label: String,
+    },
+    /// `image[<X>,<Y>;<W>,<H>;<texture name>]`
+    Image {
+        /// x-position
+        x: f32,
+        /// y-position
+        y: f32,
+        /// width
+        width: f32,
+        /// height
+        height: f32,
+        /// texture to be shown
+        texture: String,
+    },
+    /// `button[<X>,<Y>;<W>,<H>;<name>;<label>]` or `button_exit[…]`
+    Button {
+        /// x-position
+        x: f32,
+        /// y-position
+        y: f32,
+        /// width
+        width: f32,
+        /// height
+        height: f32,
+        /// field name reported back to the server when clicked
+        name: String,
+        /// text shown on the button
+        label: String,
+        /// whether clicking the button closes the formspec
+        exit: bool,
+    },
+    /// `field[<X>,<Y>;<W>,<H>;<name>;<label>;<default>]` or `pwdfield[…]`
+    Field {
+        /// x-position
+        x: f32,
+        /// y-position
+        y: f32,
+        /// width
+        width: f32,
+        /// height
+        height: f32,
+        /// field name reported back to the server
+        name: String,
+        /// label shown above the field
+        label: String,
+        /// initial content of the field; ignored for password fields
+        default: String,
+        /// whether the input shall be hidden
+        password: bool,
+    },
+    /// `list[<inventory location>;<list name>;<X>,<Y>;<W>,<H>;<starting item index>]`
+    List {
+        /// the inventory to show
+        inventory: InventoryRef,
+        /// name of the list within the inventory
+        list_name: String,
+        /// x-position
+        x: f32,
+        /// y-position
+        y: f32,
+        /// number of columns
+        width: u16,
+        /// number of rows
+        height: u16,
+        /// index of the first item to show
+        start: u16,
+    },
+    /// Raw formspec code which is emitted without any escaping
+    Raw(String),
+}
+
+impl Display for Element {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size { width, height } => write!(formatter, "size[{width},{height}]"),
+            Self::Label { x, y, label } => {
+                write!(formatter, "label[{x},{y};{}]", Escaped(label))
+            }
+            Self::Image {
+                x,
+                y,
+                width,
+                height,
+                texture,
+            } => write!(
+                formatter,
+                "image[{x},{y};{width},{height};{}]",
+                Escaped(texture)
+            ),
+            Self::Button {
+                x,
+                y,
+                width,
+                height,
+                name,
+                label,
+                exit,
+            } => write!(
+                formatter,
+                "{}[{x},{y};{width},{height};{};{}]",
+                if *exit { "button_exit" } else { "button" },
+                Escaped(name),
+                Escaped(label)
+            ),
+            Self::Field {
+                x,
+                y,
+                width,
+                height,
+                name,
+                label,
+                default,
+                password,
+            } => {
+                if *password {
+                    write!(
+                        formatter,
+                        "pwdfield[{x},{y};{width},{height};{};{}]",
+                        Escaped(name),
+                        Escaped(label)
+                    )
+                } else {
+                    write!(
+                        formatter,
+                        "field[{x},{y};{width},{height};{};{};{}]",
+                        Escaped(name),
+                        Escaped(label),
+                        Escaped(default)
+                    )
+                }
+            }
+            Self::List {
+                inventory,
+                list_name,
+                x,
+                y,
+                width,
+                height,
+                start,
+            } => write!(
+                formatter,
+                "list[{inventory};{};{x},{y};{width},{height};{start}]",
+                Escaped(list_name)
+            ),
+            Self::Raw(raw) => formatter.write_str(raw),
+        }
+    }
+}
+
+/// A formspec assembled from typed elements.
+///
+/// Use [`Display`] (or [`Formspec::render`]) to get the string representation understood by the
+/// client.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Formspec {
+    elements: Vec<Element>,
+}
+
+impl Formspec {
+    /// Create an empty formspec.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an arbitrary element.
+    #[must_use]
+    pub fn element(mut self, element: Element) -> Self {
+        self.elements.push(element);
+        self
+    }
+
+    /// Set the size of the formspec in inventory slots.
+    #[must_use]
+    pub fn size(self, width: f32, height: f32) -> Self {
+        self.element(Element::Size { width, height })
+    }
+
+    /// Add a text label.
+    #[must_use]
+    pub fn label(self, x: f32, y: f32, label: impl Into<String>) -> Self {
+        self.element(Element::Label {
+            x,
+            y,
+            label: label.into(),
+        })
+    }
+
+    /// Add an image.
+    #[must_use]
+    pub fn image(
+        self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        texture: impl Into<String>,
+    ) -> Self {
+        self.element(Element::Image {
+            x,
+            y,
+            width,
+            height,
+            texture: texture.into(),
+        })
+    }
+
+    /// Add a button which reports `name` back to the server when being clicked.
+    #[must_use]
+    pub fn button(
+        self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        name: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Self {
+        self.element(Element::Button {
+            x,
+            y,
+            width,
+            height,
+            name: name.into(),
+            label: label.into(),
+            exit: false,
+        })
+    }
+
+    /// Add a button which additionally closes the formspec when being clicked.
+    #[must_use]
+    pub fn button_exit(
+        self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        name: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Self {
+        self.element(Element::Button {
+            x,
+            y,
+            width,
+            height,
+            name: name.into(),
+            label: label.into(),
+            exit: true,
+        })
+    }
+
+    /// Add a single-line text input field.
+    #[must_use]
+    pub fn field(
+        self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        name: impl Into<String>,
+        label: impl Into<String>,
+        default: impl Into<String>,
+    ) -> Self {
+        self.element(Element::Field {
+            x,
+            y,
+            width,
+            height,
+            name: name.into(),
+            label: label.into(),
+            default: default.into(),
+            password: false,
+        })
+    }
+
+    /// Add a single-line text input field which hides its content.
+    #[must_use]
+    pub fn password_field(
+        self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        name: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Self {
+        self.element(Element::Field {
+            x,
+            y,
+            width,
+            height,
+            name: name.into(),
+            label: label.into(),
+            default: String::new(),
+            password: true,
+        })
+    }
+
+    /// Add an inventory list showing `width`×`height` slots of the given inventory list.
+    #[must_use]
+    pub fn list(
+        self,
+        inventory: InventoryRef,
+        list_name: impl Into<String>,
+        (x, y): (f32, f32),
+        (width, height): (u16, u16),
+    ) -> Self {
+        self.element(Element::List {
+            inventory,
+            list_name: list_name.into(),
+            x,
+            y,
+            width,
+            height,
+            start: 0,
+        })
+    }
+
+    /// Returns the elements of this formspec.
+    #[must_use]
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    /// Render this formspec into the string representation understood by the client.
+    #[must_use]
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+
+    /// Create the command to show this formspec to a client.
+    ///
+    /// The `form_name` will be reported back by the client along with the submitted fields and
+    /// can be used to register a handler with [`FormDispatcher`].
+    #[must_use]
+    pub fn show(&self, form_name: impl Into<String>) -> ShowFormspecSpec {
+        ShowFormspecSpec {
+            form_spec: self.render(),
+            form_name: form_name.into(),
+        }
+    }
+}
+
+impl Display for Formspec {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, element) in self.elements.iter().enumerate() {
+            if index > 0 {
+                formatter.write_char('\n')?;
+            }
+            element.fmt(formatter)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes all characters having a special meaning within formspecs
+/// (equivalent to `core.formspec_escape`)
+struct Escaped<'text>(&'text str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for char in self.0.chars() {
+            if matches!(char, '\\' | '[' | ']' | ';' | ',' | '$') {
+                formatter.write_char('\\')?;
+            }
+            formatter.write_char(char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes all characters having a special meaning within formspecs
+/// (equivalent to `core.formspec_escape`).
+#[must_use]
+pub fn escape(text: &str) -> String {
+    Escaped(text).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Formspec, InventoryRef, escape};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a[b];c,d\\e"), "a\\[b\\]\\;c\\,d\\\\e");
+        assert_eq!(escape("plain text"), "plain text");
+    }
+
+    #[test]
+    fn render_inventory() {
+        let formspec = Formspec::new()
+            .size(8.0, 7.5)
+            .image((1.0, 0.6), (1.0, 2.0), "player.png")
+            .list(InventoryRef::CurrentPlayer, "main", (0.0, 3.5), (8, 4))
+            .button_exit((3.0, 6.0), (2.0, 1.0), "ok", "OK; done");
+
+        assert_eq!(
+            formspec.render(),
+            "size[8,7.5]\n\
+             image[1,0.6;1,2;player.png]\n\
+             list[current_player;main;0,3.5;8,4;0]\n\
+             button_exit[3,6;2,1;ok;OK\\; done]"
+        );
+    }
+
+    #[test]
+    fn render_fields() {
+        let formspec = Formspec::new()
+            .field((0.5, 1.0), (4.0, 1.0), "name", "Name", "a,b")
+            .password_field((0.5, 2.0), (4.0, 1.0), "secret", "Password")
+            .list(
+                InventoryRef::NodeMeta { x: 1, y: -2, z: 3 },
+                "src",
+                (0.0, 0.0),
+                (1, 1),
+            );
+
+        assert_eq!(
+            formspec.render(),
+            "field[0.5,1;4,1;name;Name;a\\,b]\n\
+             pwdfield[0.5,2;4,1;secret;Password]\n\
+             list[nodemeta:1\\,-2\\,3;src;0,0;1,1;0]"
+        );
+    }
+}
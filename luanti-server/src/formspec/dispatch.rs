@@ -0,0 +1,151 @@
+//! Dispatching of formspec responses to handlers registered by form name
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use glam::I16Vec3;
+use log::debug;
+use luanti_protocol::commands::client_to_server::{InventoryFieldsSpec, NodemetaFieldsSpec};
+
+use crate::api::ToPluginEvent;
+
+/// Field name sent by the client when a formspec has been closed
+pub(crate) const QUIT_FIELD: &str = "quit";
+
+/// Where a formspec response originated from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormSource {
+    /// A formspec which has been shown through `ShowFormspec` or the player's inventory formspec
+    Player,
+    /// The formspec stored in the metadata of the node at the given position
+    Node(I16Vec3),
+}
+
+/// The fields submitted by a client for a formspec
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormResponse {
+    /// Name of the form as provided when it was shown; empty for the inventory formspec
+    pub form_name: String,
+    /// Origin of the formspec
+    pub source: FormSource,
+    /// All submitted fields as name-value pairs
+    pub fields: Vec<(String, String)>,
+}
+
+impl FormResponse {
+    /// Extracts a response from a plugin event if it is related to a formspec.
+    #[must_use]
+    pub fn from_event(event: &ToPluginEvent) -> Option<Self> {
+        match event {
+            ToPluginEvent::InventoryFields(spec) => Some(spec.clone().into()),
+            ToPluginEvent::NodemetaFields(spec) => Some(spec.clone().into()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the field with the given name.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns `true` if the formspec has been closed by the client.
+    #[must_use]
+    pub fn is_quit(&self) -> bool {
+        self.field(QUIT_FIELD).is_some()
+    }
+}
+
+impl From<InventoryFieldsSpec> for FormResponse {
+    fn from(spec: InventoryFieldsSpec) -> Self {
+        let InventoryFieldsSpec {
+            client_formspec_name,
+            fields,
+        } = spec;
+        Self {
+            form_name: client_formspec_name,
+            source: FormSource::Player,
+            fields,
+        }
+    }
+}
+
+impl From<NodemetaFieldsSpec> for FormResponse {
+    fn from(spec: NodemetaFieldsSpec) -> Self {
+        let NodemetaFieldsSpec {
+            p: pos,
+            form_name,
+            fields,
+        } = spec;
+        Self {
+            form_name,
+            source: FormSource::Node(pos),
+            fields,
+        }
+    }
+}
+
+/// A callback receiving the responses for a specific form
+pub type FormHandler = Box<dyn FnMut(&FormResponse) -> Result<()> + Send>;
+
+/// Routes formspec responses to the handler registered for their form name.
+#[derive(Default)]
+pub struct FormDispatcher {
+    handlers: HashMap<String, FormHandler>,
+}
+
+impl FormDispatcher {
+    /// Create a dispatcher without any handlers.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for the given form name, replacing any previous handler.
+    pub fn register(
+        &mut self,
+        form_name: impl Into<String>,
+        handler: impl FnMut(&FormResponse) -> Result<()> + Send + 'static,
+    ) {
+        self.handlers.insert(form_name.into(), Box::new(handler));
+    }
+
+    /// Remove the handler for the given form name.
+    /// Returns `true` if a handler had been registered.
+    pub fn unregister(&mut self, form_name: &str) -> bool {
+        self.handlers.remove(form_name).is_some()
+    }
+
+    /// Pass the response to the handler registered for its form name.
+    ///
+    /// Returns `false` if no handler has been registered for this form.
+    ///
+    /// # Errors
+    ///
+    /// Forwards any error returned by the handler.
+    pub fn dispatch(&mut self, response: &FormResponse) -> Result<bool> {
+        let Some(handler) = self.handlers.get_mut(&response.form_name) else {
+            debug!("no handler for form '{}'", response.form_name);
+            return Ok(false);
+        };
+        handler(response)?;
+        Ok(true)
+    }
+
+    /// Dispatch the plugin event if it is a formspec response.
+    ///
+    /// Returns `true` if a handler has consumed the event.
+    ///
+    /// # Errors
+    ///
+    /// Forwards any error returned by the handler.
+    pub fn dispatch_event(&mut self, event: &ToPluginEvent) -> Result<bool> {
+        match FormResponse::from_event(event) {
+            Some(response) => self.dispatch(&response),
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Formspecs being shown when players right-click nodes
+//!
+//! [`NodeForms`] maps node names to the formspec of e.g. a chest or a furnace. When a player
+//! places against such a node, the form is being sent and the client's response is forwarded to
+//! the plugins as [`ToPluginEvent::NodemetaFields`], so a [`FormResponse`](super::FormResponse)
+//! reports the node as its [`FormSource`](super::FormSource).
+//!
+//! Responses to node forms as well as inventory actions targeting `nodemeta:` inventories are
+//! only accepted from players who have the `interact` privilege and are close to the node.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use glam::I16Vec3;
+use log::warn;
+use luanti_core::{ContentId, MapBlockPos, MapNodePos};
+use luanti_protocol::commands::client_to_server::{
+    InteractSpec, InventoryFieldsSpec, NodemetaFieldsSpec,
+};
+use luanti_protocol::commands::server_to_client::ShowFormspecSpec;
+use luanti_protocol::types::{
+    InteractAction, InventoryAction, InventoryLocation, NodeDefManager, PointedThing,
+};
+
+use super::Formspec;
+use super::dispatch::QUIT_FIELD;
+use super::version::degrade_spec;
+use crate::api::ToPluginEvent;
+use crate::player_store::PlayerData;
+use crate::world::WorldBlock;
+
+/// The maximum distance between a player and a node being interacted with, in nodes; the range
+/// of the hand plus Luanti's tolerance for nodes exceeding their cube
+pub const DEFAULT_INTERACT_DISTANCE: f32 = 4.0 + 2.6;
+
+/// The privilege required for interacting with nodes
+const INTERACT_PRIVILEGE: &str = "interact";
+
+/// Creates the formspec of a node at the given position for the given player
+pub type NodeFormBuilder = Box<dyn Fn(I16Vec3, &str) -> Formspec + Send + Sync>;
+
+/// A form registered for a node
+struct NodeForm {
+    form_name: String,
+    build: NodeFormBuilder,
+}
+
+/// The forms of nodes by node name
+pub struct NodeForms {
+    forms: HashMap<String, NodeForm>,
+    max_distance: f32,
+}
+
+impl Default for NodeForms {
+    fn default() -> Self {
+        Self {
+            forms: HashMap::new(),
+            max_distance: DEFAULT_INTERACT_DISTANCE,
+        }
+    }
+}
+
+impl NodeForms {
+    /// Creates a registry without any forms.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the form of a node, replacing any previous form of this node.
+    ///
+    /// The response of the client carries `form_name`, which should be used for registering a
+    /// handler with the [`FormDispatcher`](super::FormDispatcher).
+    pub fn register(
+        &mut self,
+        node_name: impl Into<String>,
+        form_name: impl Into<String>,
+        build: impl Fn(I16Vec3, &str) -> Formspec + Send + Sync + 'static,
+    ) {
+        self.forms.insert(
+            node_name.into(),
+            NodeForm {
+                form_name: form_name.into(),
+                build: Box::new(build),
+            },
+        );
+    }
+
+    /// Sets the maximum distance between a player and a node being interacted with, in nodes.
+    /// Defaults to [`DEFAULT_INTERACT_DISTANCE`].
+    pub fn set_max_distance(&mut self, max_distance: f32) {
+        self.max_distance = max_distance;
+    }
+
+    /// Resolves the node names of the forms to the content ids of the given definitions.
+    pub(crate) fn resolve(self, node_def: &NodeDefManager) -> NodeFormRules {
+        let Self {
+            mut forms,
+            max_distance,
+        } = self;
+        let forms = node_def
+            .content_features
+            .iter()
+            .filter_map(|(id, features)| Some((ContentId(*id), forms.remove(&features.name)?)))
+            .collect();
+        NodeFormRules {
+            forms,
+            max_distance,
+        }
+    }
+}
+
+/// The forms of nodes by content id
+pub(crate) struct NodeFormRules {
+    forms: HashMap<ContentId, NodeForm>,
+    max_distance: f32,
+}
+
+/// Keeps track of the node forms a single player may open or has opened
+pub(crate) struct NodeInteractions {
+    rules: Arc<NodeFormRules>,
+    /// the content of the map blocks known to the client which contain nodes with a form
+    blocks: HashMap<MapBlockPos, Box<[ContentId]>>,
+    /// the positions of the nodes whose forms have been shown, by form name
+    open_forms: HashMap<String, I16Vec3>,
+    /// the newest formspec version the client understands
+    formspec_version: u16,
+}
+
+impl NodeInteractions {
+    pub(crate) fn new(rules: Arc<NodeFormRules>, formspec_version: u16) -> Self {
+        Self {
+            rules,
+            blocks: HashMap::new(),
+            open_forms: HashMap::new(),
+            formspec_version,
+        }
+    }
+
+    /// Remembers the nodes of a block, replacing a previous version of the block.
+    pub(crate) fn insert(&mut self, block: &WorldBlock) {
+        if self.rules.forms.is_empty() {
+            return;
+        }
+        let has_forms = block
+            .nodes
+            .0
+            .iter()
+            .any(|node| self.rules.forms.contains_key(&node.content_id));
+        if has_forms {
+            let content_ids = block.nodes.0.iter().map(|node| node.content_id).collect();
+            self.blocks.insert(block.pos, content_ids);
+        } else {
+            self.blocks.remove(&block.pos);
+        }
+    }
+
+    /// Forgets a block which the client dropped.
+    pub(crate) fn remove(&mut self, pos: MapBlockPos) {
+        self.blocks.remove(&pos);
+    }
+
+    /// Updates a single node of a block known to the client.
+    pub(crate) fn set_node(&mut self, pos: MapNodePos, content_id: ContentId) {
+        let (block_pos, index) = pos.split_index();
+        if let Some(content_ids) = self.blocks.get_mut(&block_pos) {
+            if let Some(slot) = content_ids.get_mut(usize::from(index)) {
+                *slot = content_id;
+            }
+        } else if self.rules.forms.contains_key(&content_id) {
+            // the block contained nothing of interest before, so the other nodes don't matter
+            let mut content_ids =
+                vec![ContentId::IGNORE; usize::from(MapBlockPos::NODE_COUNT)].into_boxed_slice();
+            if let Some(slot) = content_ids.get_mut(usize::from(index)) {
+                *slot = content_id;
+            }
+            self.blocks.insert(block_pos, content_ids);
+        }
+    }
+
+    /// Returns the form to show if the player right-clicked a node which has one.
+    pub(crate) fn interact(
+        &mut self,
+        interact: &InteractSpec,
+        player_name: &str,
+        player: &PlayerData,
+    ) -> Option<ShowFormspecSpec> {
+        let InteractSpec {
+            action: InteractAction::Place,
+            pointed_thing: PointedThing::Node { under_surface, .. },
+            ..
+        } = interact
+        else {
+            return None;
+        };
+        let (block_pos, index) = MapNodePos(*under_surface).split_index();
+        let content_id = self.blocks.get(&block_pos)?.get(usize::from(index))?;
+        let form = self.rules.forms.get(content_id)?;
+        if !self.may_access(*under_surface, player) {
+            return None;
+        }
+        self.open_forms
+            .insert(form.form_name.clone(), *under_surface);
+        let spec = (form.build)(*under_surface, player_name).show(form.form_name.clone());
+        Some(degrade_spec(spec, self.formspec_version, player_name))
+    }
+
+    /// Returns `true` if the player may submit the fields of a formspec stored in the metadata of
+    /// a node.
+    pub(crate) fn accept_nodemeta_fields(
+        &self,
+        fields: &NodemetaFieldsSpec,
+        player: &PlayerData,
+    ) -> bool {
+        self.may_access(fields.p, player)
+    }
+
+    /// Turns the response to a node form into a response of the node, so plugins can tell which
+    /// node it belongs to. Returns `None` if the player may no longer use the node.
+    pub(crate) fn inventory_fields(
+        &mut self,
+        spec: InventoryFieldsSpec,
+        player: &PlayerData,
+    ) -> Option<ToPluginEvent> {
+        let Some(&pos) = self.open_forms.get(&spec.client_formspec_name) else {
+            return Some(ToPluginEvent::InventoryFields(spec));
+        };
+        let InventoryFieldsSpec {
+            client_formspec_name: form_name,
+            fields,
+        } = spec;
+        if fields.iter().any(|(name, _)| name == QUIT_FIELD) {
+            self.open_forms.remove(&form_name);
+        }
+        self.may_access(pos, player)
+            .then_some(ToPluginEvent::NodemetaFields(NodemetaFieldsSpec {
+                p: pos,
+                form_name,
+                fields,
+            }))
+    }
+
+    /// Returns `true` if the player may access all node inventories involved in the action.
+    pub(crate) fn accept_inventory_action(
+        &self,
+        action: &InventoryAction,
+        player: &PlayerData,
+    ) -> bool {
+        let locations = match action {
+            InventoryAction::Move {
+                from_inv, to_inv, ..
+            } => vec![from_inv, to_inv],
+            InventoryAction::Craft { craft_inv, .. } => vec![craft_inv],
+            InventoryAction::Drop { from_inv, .. } => vec![from_inv],
+        };
+        locations.into_iter().all(|location| match location {
+            InventoryLocation::NodeMeta { pos } => self.may_access(*pos, player),
+            InventoryLocation::Undefined
+            | InventoryLocation::CurrentPlayer
+            | InventoryLocation::Player { .. }
+            | InventoryLocation::Detached { .. } => true,
+        })
+    }
+
+    /// Returns `true` if the player is allowed to interact with the node at the given position.
+    fn may_access(&self, pos: I16Vec3, player: &PlayerData) -> bool {
+        if !player
+            .privileges
+            .iter()
+            .any(|privilege| privilege == INTERACT_PRIVILEGE)
+        {
+            warn!("player without the `{INTERACT_PRIVILEGE}` privilege tried to use node {pos}");
+            return false;
+        }
+        let distance = player.position.distance(pos.as_vec3());
+        if distance > self.rules.max_distance {
+            warn!("player is too far away ({distance:.1} nodes) to use node {pos}");
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::sync::Arc;
+
+    use glam::{I16Vec3, Vec3};
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode};
+    use luanti_protocol::commands::client_to_server::{InteractSpec, InventoryFieldsSpec};
+    use luanti_protocol::types::{
+        ContentFeatures, InteractAction, InventoryAction, InventoryLocation, NodeDefManager,
+        PlayerKeys, PlayerPos, PointedThing,
+    };
+
+    use super::{NodeForms, NodeInteractions};
+    use crate::api::ToPluginEvent;
+    use crate::formspec::Formspec;
+    use crate::player_store::PlayerData;
+    use crate::world::WorldBlock;
+
+    const CHEST: ContentId = ContentId(20);
+
+    fn interactions() -> NodeInteractions {
+        let mut forms = NodeForms::new();
+        forms.register("default:chest", "chest", |pos, _| {
+            Formspec::new().label(0.0, 0.0, format!("chest at {pos}"))
+        });
+        let node_def = NodeDefManager {
+            content_features: vec![(
+                CHEST.0,
+                ContentFeatures::new_unknown("default:chest".into()),
+            )],
+        };
+        let mut interactions = NodeInteractions::new(Arc::new(forms.resolve(&node_def)), 8);
+
+        let mut nodes = [MapNode {
+            content_id: ContentId::AIR,
+            param1: 0,
+            param2: 0,
+        }; MapBlockPos::NODE_COUNT as usize];
+        if let Some(node) = nodes.first_mut() {
+            node.content_id = CHEST;
+        }
+        interactions.insert(&WorldBlock {
+            version: 0,
+            pos: MapBlockPos::new(I16Vec3::ZERO).unwrap(),
+            is_underground: false,
+            day_night_differs: false,
+            lighting_complete: 0,
+            nodes: MapBlockNodes(nodes),
+            metadata: vec![],
+        });
+        interactions
+    }
+
+    fn place(pos: I16Vec3) -> InteractSpec {
+        InteractSpec {
+            action: InteractAction::Place,
+            item_index: 0,
+            pointed_thing: PointedThing::Node {
+                under_surface: pos,
+                above_surface: pos + I16Vec3::Y,
+            },
+            player_pos: PlayerPos {
+                position: Vec3::ZERO,
+                speed: Vec3::ZERO,
+                pitch: 0.0,
+                yaw: 0.0,
+                keys_pressed: PlayerKeys::empty(),
+                fov: 1.0,
+                wanted_range: 10,
+                camera_inverted: false,
+                movement_speed: 0.0,
+                movement_direction: 0.0,
+            },
+        }
+    }
+
+    fn player(position: Vec3) -> PlayerData {
+        let mut player = PlayerData {
+            position,
+            ..PlayerData::default()
+        };
+        player.privileges.push("interact".into());
+        player
+    }
+
+    #[test]
+    fn chests_open_their_form() {
+        let mut interactions = interactions();
+        let mut player = player(Vec3::new(2.0, 0.0, 0.0));
+
+        let form = interactions.interact(&place(I16Vec3::ZERO), "alice", &player);
+        assert_eq!(form.map(|form| form.form_name), Some("chest".into()));
+        assert!(
+            interactions
+                .interact(&place(I16Vec3::X), "alice", &player)
+                .is_none()
+        );
+
+        let response = interactions.inventory_fields(
+            InventoryFieldsSpec {
+                client_formspec_name: "chest".into(),
+                fields: vec![("quit".into(), "true".into())],
+            },
+            &player,
+        );
+        assert!(matches!(
+            response,
+            Some(ToPluginEvent::NodemetaFields(spec)) if spec.p == I16Vec3::ZERO
+        ));
+
+        player.position = Vec3::new(20.0, 0.0, 0.0);
+        assert!(
+            interactions
+                .interact(&place(I16Vec3::ZERO), "alice", &player)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn node_inventories_need_the_interact_privilege() {
+        let interactions = interactions();
+        let mut player = player(Vec3::ZERO);
+        let take = InventoryAction::Move {
+            count: 1,
+            from_inv: InventoryLocation::NodeMeta { pos: I16Vec3::ZERO },
+            from_list: "main".into(),
+            from_i: 0,
+            to_inv: InventoryLocation::CurrentPlayer,
+            to_list: "main".into(),
+            to_i: None,
+        };
+        assert!(interactions.accept_inventory_action(&take, &player));
+
+        player.privileges.clear();
+        assert!(!interactions.accept_inventory_action(&take, &player));
+    }
+}
@@ -0,0 +1,224 @@
+//! Adapts formspecs to the formspec version a client understands
+//!
+//! Clients report the newest formspec version they support once they've finished loading (see
+//! [`ClientVersion::formspec_version`]). A client shows an error instead of a formspec declaring a
+//! newer version, and elements it doesn't know yet garble the layout. [`degrade`] therefore lowers
+//! the declared version and removes the elements the client doesn't know.
+
+use std::iter;
+
+use log::warn;
+use luanti_protocol::commands::server_to_client::ShowFormspecSpec;
+use luanti_protocol::versions::ClientVersion;
+
+/// The formspec version of clients which don't report one, i.e. clients older than 5.1.0
+pub const LEGACY_FORMSPEC_VERSION: u16 = 0;
+
+/// The first formspec version using real coordinates without `real_coordinates[true]`
+const REAL_COORDINATES_VERSION: u16 = 2;
+
+/// The elements introduced after 5.0.0 with the first formspec version supporting them
+const ELEMENT_VERSIONS: &[(&str, u16)] = &[
+    ("formspec_version", 1),
+    ("real_coordinates", 1),
+    ("style", 1),
+    ("hypertext", 1),
+    ("style_type", 2),
+    ("scroll_container", 3),
+    ("scroll_container_end", 3),
+    ("scrollbaroptions", 3),
+    ("animated_image", 3),
+    ("model", 4),
+    ("padding", 5),
+    ("field_enter_after_edit", 7),
+];
+
+/// A formspec adapted to the formspec version of a client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Degraded {
+    /// the formspec as it can be shown to the client
+    pub formspec: String,
+    /// the names of the elements which have been removed as the client doesn't know them
+    pub removed: Vec<String>,
+}
+
+/// Returns the formspec version a client understands.
+#[must_use]
+pub fn client_formspec_version(version: Option<&ClientVersion>) -> u16 {
+    version
+        .and_then(|version| version.formspec_version)
+        .unwrap_or(LEGACY_FORMSPEC_VERSION)
+}
+
+/// Returns the formspec version a client needs to show the whole formspec, i.e. the version the
+/// formspec declares or the version of its newest element, whichever is higher.
+#[must_use]
+pub fn required_version(formspec: &str) -> u16 {
+    elements(formspec)
+        .map(|element| {
+            let version = element_version(element_name(element));
+            declared_version(element).map_or(version, |declared| declared.max(version))
+        })
+        .max()
+        .unwrap_or(LEGACY_FORMSPEC_VERSION)
+}
+
+/// Adapts a formspec to a client understanding the given formspec version.
+///
+/// A newer `formspec_version[]` is lowered to the client's version; clients understanding
+/// version 1 are switched to real coordinates like newer ones. Elements the client doesn't know
+/// are removed and reported in [`Degraded::removed`].
+#[must_use]
+pub fn degrade(formspec: &str, client_version: u16) -> Degraded {
+    let mut degraded = String::with_capacity(formspec.len());
+    let mut removed = Vec::new();
+    for element in elements(formspec) {
+        let name = element_name(element);
+        if let Some(declared) = declared_version(element) {
+            if declared > client_version && client_version > LEGACY_FORMSPEC_VERSION {
+                let indentation = element.strip_suffix(element.trim_start()).unwrap_or("");
+                degraded.push_str(indentation);
+                degraded.push_str("formspec_version[");
+                degraded.push_str(&client_version.to_string());
+                degraded.push(']');
+                if declared >= REAL_COORDINATES_VERSION && client_version < REAL_COORDINATES_VERSION
+                {
+                    degraded.push_str("real_coordinates[true]");
+                }
+                continue;
+            }
+        }
+        if element_version(name) > client_version {
+            removed.push(name.to_owned());
+        } else {
+            degraded.push_str(element);
+        }
+    }
+    Degraded {
+        formspec: degraded,
+        removed,
+    }
+}
+
+/// Adapts a formspec which is about to be shown to a player and warns about removed elements.
+pub(crate) fn degrade_spec(
+    spec: ShowFormspecSpec,
+    client_version: u16,
+    player_name: &str,
+) -> ShowFormspecSpec {
+    if required_version(&spec.form_spec) <= client_version {
+        return spec;
+    }
+    let Degraded { formspec, removed } = degrade(&spec.form_spec, client_version);
+    if !removed.is_empty() {
+        warn!(
+            "formspec '{}' of '{player_name}' lacks {} as the client only supports formspec \
+             version {client_version}",
+            spec.form_name,
+            removed.join(", ")
+        );
+    }
+    ShowFormspecSpec {
+        form_spec: formspec,
+        form_name: spec.form_name,
+    }
+}
+
+/// Splits a formspec into its elements, each one including its leading whitespace and its
+/// closing bracket.
+fn elements(formspec: &str) -> impl Iterator<Item = &str> {
+    let mut rest = formspec;
+    iter::from_fn(move || {
+        if rest.trim().is_empty() {
+            return None;
+        }
+        let mut escaped = false;
+        let end = rest
+            .char_indices()
+            .find_map(|(index, char)| {
+                if escaped {
+                    escaped = false;
+                } else if char == '\\' {
+                    escaped = true;
+                } else if char == ']' {
+                    return Some(index + 1);
+                }
+                None
+            })
+            .unwrap_or(rest.len());
+        let (element, remainder) = rest.split_at_checked(end)?;
+        rest = remainder;
+        Some(element)
+    })
+}
+
+/// Returns the name of an element, e.g. `label` for `label[1,1;Hello]`.
+fn element_name(element: &str) -> &str {
+    element
+        .split_once('[')
+        .map_or(element, |(name, _)| name)
+        .trim()
+}
+
+/// Returns the version declared by a `formspec_version[]` element.
+fn declared_version(element: &str) -> Option<u16> {
+    let (name, parameters) = element.split_once('[')?;
+    if name.trim() != "formspec_version" {
+        return None;
+    }
+    parameters.strip_suffix(']')?.trim().parse().ok()
+}
+
+/// Returns the first formspec version supporting an element.
+fn element_version(name: &str) -> u16 {
+    ELEMENT_VERSIONS
+        .iter()
+        .find(|(element, _)| *element == name)
+        .map_or(LEGACY_FORMSPEC_VERSION, |(_, version)| *version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LEGACY_FORMSPEC_VERSION, degrade, required_version};
+
+    #[test]
+    fn formspecs_are_degraded() {
+        let formspec = "formspec_version[6]\nsize[8,6]\nlabel[1,1;a\\]b]\n\
+                        scroll_container[0,0;8,5;bar;vertical]\nbutton[1,2;2,1;ok;OK]\n\
+                        scroll_container_end[]\npadding[0.1,0.1]";
+        assert_eq!(required_version(formspec), 6);
+        assert_eq!(required_version("size[8,6]label[1,1;x]"), 0);
+
+        let current = degrade(formspec, 8);
+        assert_eq!(current.formspec, formspec);
+        assert!(current.removed.is_empty());
+
+        let older = degrade(formspec, 3);
+        assert_eq!(
+            older.formspec,
+            "formspec_version[3]\nsize[8,6]\nlabel[1,1;a\\]b]\n\
+             scroll_container[0,0;8,5;bar;vertical]\nbutton[1,2;2,1;ok;OK]\n\
+             scroll_container_end[]"
+        );
+        assert_eq!(older.removed, ["padding"]);
+
+        // version 1 still knows real coordinates, but doesn't use them by default
+        let first = degrade(formspec, 1);
+        assert_eq!(
+            first.formspec,
+            "formspec_version[1]real_coordinates[true]\nsize[8,6]\nlabel[1,1;a\\]b]\n\
+             button[1,2;2,1;ok;OK]"
+        );
+        assert_eq!(
+            first.removed,
+            ["scroll_container", "scroll_container_end", "padding"]
+        );
+
+        let legacy = degrade(formspec, LEGACY_FORMSPEC_VERSION);
+        assert!(legacy.formspec.starts_with("\nsize[8,6]"));
+        assert_eq!(
+            legacy.removed.first().map(String::as_str),
+            Some("formspec_version")
+        );
+    }
+}
@@ -0,0 +1,645 @@
+//! Hit points, damage and respawning of players
+//!
+//! Like in the C++ engine, fall damage is reported by the client while damage caused by nodes
+//! (`damage_per_second`) is computed by the server. The server has no access to the map from
+//! within a connection, so it remembers the hazardous nodes of all map blocks it sent to the
+//! client instead. A player whose hit points drop to zero sees the death screen until the client
+//! asks to respawn.
+//!
+//! Players lose breath while their head is inside a node which cannot be breathed in (`drowning`)
+//! and take the `drowning` damage of that node once they ran out of breath.
+
+use std::{collections::HashMap, fmt::Display, sync::Arc, time::Duration};
+
+use flexstr::SharedStr;
+use glam::Vec3;
+use log::{debug, info};
+use luanti_core::{ContentId, MapBlockPos, MapNodePos};
+use luanti_protocol::{
+    commands::server_to_client::{
+        BreathSpec, DeathscreenSpec, HpSpec, MovePlayerSpec, ToClientCommand,
+    },
+    types::{NodeDefManager, TrailingBytes},
+};
+
+use crate::{
+    player_store::{DEFAULT_BREATH, DEFAULT_HP, PlayerData},
+    world::WorldBlock,
+};
+
+/// Distance between the feet and the eyes of a player in nodes
+pub const EYE_HEIGHT: f32 = 1.625;
+
+/// Nodes deal their `damage_per_second` this often
+const NODE_DAMAGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Players lose one breath or take drowning damage this often; the value of the C++ engine
+const DROWNING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Players regain one breath this often; the value of the C++ engine
+const BREATHING_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Why the hit points of a player changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HpChangeReason {
+    /// reported by the client, which computes the damage of falls itself
+    Fall,
+    /// the player ran out of breath
+    Drowning,
+    /// the player touches a node with `damage_per_second`; contains the name of the node
+    Node(SharedStr),
+    /// a plugin set the hit points
+    SetHp,
+    /// the player respawned after dying
+    Respawn,
+}
+
+impl Display for HpChangeReason {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fall => formatter.write_str("fall"),
+            Self::Drowning => formatter.write_str("drowning"),
+            Self::Node(name) => write!(formatter, "node {name}"),
+            Self::SetHp => formatter.write_str("set_hp"),
+            Self::Respawn => formatter.write_str("respawn"),
+        }
+    }
+}
+
+/// Describes a change of the hit points of a player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HpChange {
+    /// hit points before the change
+    pub previous_hp: u16,
+    /// hit points after the change; zero if the player died
+    pub hp: u16,
+    /// what caused the change
+    pub reason: HpChangeReason,
+}
+
+/// Decides where players (re)spawn
+pub trait SpawnPointProvider: Send + Sync {
+    /// Returns the position of the feet in nodes where the given player shall respawn.
+    fn spawn_point(&self, player_name: &str) -> Vec3;
+}
+
+/// Lets all players respawn at the same position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedSpawnPoint(pub Vec3);
+
+impl SpawnPointProvider for FixedSpawnPoint {
+    fn spawn_point(&self, _player_name: &str) -> Vec3 {
+        self.0
+    }
+}
+
+/// Settings of the health of players
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// whether players can be hurt at all
+    pub enable_damage: bool,
+    /// hit points of a player after respawning
+    pub max_hp: u16,
+    /// breath of a player who isn't under water
+    pub max_breath: u16,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enable_damage: true,
+            max_hp: DEFAULT_HP,
+            max_breath: DEFAULT_BREATH,
+        }
+    }
+}
+
+/// The properties of a node which may hurt players
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeHazard {
+    /// name of the node
+    pub name: SharedStr,
+    /// damage dealt to players touching the node every second
+    pub damage_per_second: u32,
+    /// damage dealt to players without breath whose head is inside the node every two seconds;
+    /// nodes with a non-zero value cannot be breathed in
+    pub drowning: u8,
+}
+
+/// All nodes which may hurt players
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeHazards(HashMap<ContentId, NodeHazard>);
+
+impl NodeHazards {
+    /// Collects the hazardous nodes of the given definitions.
+    #[must_use]
+    pub fn new(node_def: &NodeDefManager) -> Self {
+        Self(
+            node_def
+                .content_features
+                .iter()
+                .filter(|(_, features)| features.damage_per_second > 0 || features.drowning > 0)
+                .map(|(id, features)| {
+                    let hazard = NodeHazard {
+                        name: features.name.clone().into(),
+                        damage_per_second: features.damage_per_second,
+                        drowning: features.drowning,
+                    };
+                    (ContentId(*id), hazard)
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the hazard of the given node, if it is one.
+    #[must_use]
+    pub fn get(&self, content_id: ContentId) -> Option<&NodeHazard> {
+        self.0.get(&content_id)
+    }
+}
+
+/// The hazardous nodes of the map blocks known to a client
+///
+/// Blocks without any hazardous node aren't stored at all, which keeps this small for most
+/// worlds.
+#[derive(Debug)]
+pub(crate) struct HazardMap {
+    hazards: Arc<NodeHazards>,
+    blocks: HashMap<MapBlockPos, Box<[ContentId]>>,
+}
+
+impl HazardMap {
+    pub(crate) fn new(hazards: Arc<NodeHazards>) -> Self {
+        Self {
+            hazards,
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Remembers the hazardous nodes of a block, replacing a previous version of the block.
+    pub(crate) fn insert(&mut self, block: &WorldBlock) {
+        let hazardous = block
+            .nodes
+            .0
+            .iter()
+            .any(|node| self.hazards.get(node.content_id).is_some());
+        if hazardous {
+            let content_ids = block.nodes.0.iter().map(|node| node.content_id).collect();
+            self.blocks.insert(block.pos, content_ids);
+        } else {
+            self.blocks.remove(&block.pos);
+        }
+    }
+
+    /// Forgets a block which the client dropped.
+    pub(crate) fn remove(&mut self, pos: MapBlockPos) {
+        self.blocks.remove(&pos);
+    }
+
+    /// Updates a single node of a block known to the client.
+    pub(crate) fn set_node(&mut self, pos: MapNodePos, content_id: ContentId) {
+        let (block_pos, index) = pos.split_index();
+        if let Some(content_ids) = self.blocks.get_mut(&block_pos) {
+            if let Some(slot) = content_ids.get_mut(usize::from(index)) {
+                *slot = content_id;
+            }
+        } else if self.hazards.get(content_id).is_some() {
+            // the block contained nothing of interest before, so the other nodes don't matter
+            let mut content_ids =
+                vec![ContentId::IGNORE; usize::from(MapBlockPos::NODE_COUNT)].into_boxed_slice();
+            if let Some(slot) = content_ids.get_mut(usize::from(index)) {
+                *slot = content_id;
+            }
+            self.blocks.insert(block_pos, content_ids);
+        }
+    }
+
+    /// Returns the hazard at the given position in nodes, if there is one.
+    pub(crate) fn get(&self, position: Vec3) -> Option<&NodeHazard> {
+        // nodes are centered around their integer coordinates
+        let (block_pos, index) = MapNodePos(position.round().as_i16vec3()).split_index();
+        let content_id = self.blocks.get(&block_pos)?.get(usize::from(index))?;
+        self.hazards.get(*content_id)
+    }
+}
+
+/// Everything a connection needs to know to keep track of the health of its player
+#[derive(Clone)]
+pub(crate) struct HealthRules {
+    pub(crate) config: HealthConfig,
+    pub(crate) spawn_points: Arc<dyn SpawnPointProvider>,
+    pub(crate) hazards: Arc<NodeHazards>,
+}
+
+/// Keeps track of the health of a single player who's in-game
+pub(crate) struct HealthTracker {
+    config: HealthConfig,
+    spawn_points: Arc<dyn SpawnPointProvider>,
+    hazards: HazardMap,
+    /// whether the player is dead and waiting for the client to respawn
+    dead: bool,
+    /// time since nodes dealt damage for the last time
+    node_damage_elapsed: Duration,
+    /// time since the player lost breath or drowned for the last time
+    drowning_elapsed: Duration,
+    /// time since the player regained breath for the last time
+    breathing_elapsed: Duration,
+}
+
+impl HealthTracker {
+    pub(crate) fn new(rules: HealthRules) -> Self {
+        let HealthRules {
+            config,
+            spawn_points,
+            hazards,
+        } = rules;
+        Self {
+            config,
+            spawn_points,
+            hazards: HazardMap::new(hazards),
+            dead: false,
+            node_damage_elapsed: Duration::ZERO,
+            drowning_elapsed: Duration::ZERO,
+            breathing_elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns the nodes which may hurt the player for updating them.
+    pub(crate) fn hazards_mut(&mut self) -> &mut HazardMap {
+        &mut self.hazards
+    }
+
+    /// Marks the player as dead after a plugin showed the death screen, so the client may ask
+    /// to respawn.
+    pub(crate) fn show_death_screen(&mut self) {
+        self.dead = true;
+    }
+
+    /// Shows the death screen to players who left the game while being dead.
+    pub(crate) fn join(&mut self, player: &PlayerData) -> Vec<ToClientCommand> {
+        if player.hp > 0 {
+            return vec![];
+        }
+        self.dead = true;
+        vec![death_screen()]
+    }
+
+    /// Hurts the player. Players who are dead already or cannot be hurt don't take damage.
+    pub(crate) fn damage(
+        &mut self,
+        player: &mut PlayerData,
+        damage: u16,
+        reason: HpChangeReason,
+    ) -> (Option<HpChange>, Vec<ToClientCommand>) {
+        if !self.config.enable_damage || self.dead || damage == 0 {
+            return (None, vec![]);
+        }
+        // the client has shown the effect of damage it computed itself already
+        let damage_effect = reason != HpChangeReason::Fall;
+        self.set_hp(
+            player,
+            player.hp.saturating_sub(damage),
+            reason,
+            damage_effect,
+        )
+    }
+
+    /// Sets the hit points of the player, killing or reviving them if necessary.
+    pub(crate) fn set_hp(
+        &mut self,
+        player: &mut PlayerData,
+        hp: u16,
+        reason: HpChangeReason,
+        damage_effect: bool,
+    ) -> (Option<HpChange>, Vec<ToClientCommand>) {
+        let change = HpChange {
+            previous_hp: player.hp,
+            hp,
+            reason,
+        };
+        debug!("hp: {} → {hp} ({})", change.previous_hp, change.reason);
+        player.hp = hp;
+
+        let mut commands = vec![
+            HpSpec {
+                hp,
+                damage_effect: Some(damage_effect && hp < change.previous_hp),
+                extra: TrailingBytes::default(),
+            }
+            .into(),
+        ];
+        if hp == 0 && !self.dead {
+            info!("player died ({})", change.reason);
+            self.dead = true;
+            commands.push(death_screen());
+        } else if hp > 0 {
+            self.dead = false;
+        }
+        (Some(change), commands)
+    }
+
+    /// Applies the damage of the nodes the player is touching and updates the breath of the
+    /// player.
+    pub(crate) fn step(
+        &mut self,
+        player: &mut PlayerData,
+        elapsed: Duration,
+    ) -> (Vec<HpChange>, Vec<ToClientCommand>) {
+        let (node_damage, mut commands) = self.node_damage(player, elapsed);
+        let (drowning, breath_commands) = self.breathe(player, elapsed);
+        commands.extend(breath_commands);
+        (node_damage.into_iter().chain(drowning).collect(), commands)
+    }
+
+    fn node_damage(
+        &mut self,
+        player: &mut PlayerData,
+        elapsed: Duration,
+    ) -> (Option<HpChange>, Vec<ToClientCommand>) {
+        self.node_damage_elapsed += elapsed;
+        if self.node_damage_elapsed < NODE_DAMAGE_INTERVAL {
+            return (None, vec![]);
+        }
+        self.node_damage_elapsed = Duration::ZERO;
+
+        let feet = self.hazards.get(player.position + Vec3::new(0.0, 0.1, 0.0));
+        let head = self.hazards.get(head_position(player));
+        let Some(hazard) = [feet, head]
+            .into_iter()
+            .flatten()
+            .filter(|hazard| hazard.damage_per_second > 0)
+            .max_by_key(|hazard| hazard.damage_per_second)
+        else {
+            return (None, vec![]);
+        };
+        let damage = u16::try_from(hazard.damage_per_second).unwrap_or(u16::MAX);
+        let reason = HpChangeReason::Node(hazard.name.clone());
+        self.damage(player, damage, reason)
+    }
+
+    /// Lets the player lose breath inside nodes which cannot be breathed in and regain it
+    /// outside of them.
+    fn breathe(
+        &mut self,
+        player: &mut PlayerData,
+        elapsed: Duration,
+    ) -> (Option<HpChange>, Vec<ToClientCommand>) {
+        if !self.config.enable_damage || self.dead {
+            return (None, vec![]);
+        }
+        let drowning = self
+            .hazards
+            .get(head_position(player))
+            .map_or(0, |hazard| hazard.drowning);
+
+        self.drowning_elapsed += elapsed;
+        self.breathing_elapsed += elapsed;
+        if drowning > 0 {
+            self.breathing_elapsed = Duration::ZERO;
+            if self.drowning_elapsed < DROWNING_INTERVAL {
+                return (None, vec![]);
+            }
+            self.drowning_elapsed = Duration::ZERO;
+            if player.breath > 0 {
+                player.breath -= 1;
+                return (None, vec![breath(player)]);
+            }
+            return self.damage(player, u16::from(drowning), HpChangeReason::Drowning);
+        }
+
+        self.drowning_elapsed = Duration::ZERO;
+        if self.breathing_elapsed < BREATHING_INTERVAL {
+            return (None, vec![]);
+        }
+        self.breathing_elapsed = Duration::ZERO;
+        if player.breath >= self.config.max_breath {
+            return (None, vec![]);
+        }
+        player.breath += 1;
+        (None, vec![breath(player)])
+    }
+
+    /// Brings a dead player back to life at the spawn point. Returns `None` if the player isn't
+    /// dead.
+    pub(crate) fn respawn(
+        &mut self,
+        player_name: &str,
+        player: &mut PlayerData,
+    ) -> Option<(HpChange, Vec<ToClientCommand>)> {
+        if !self.dead {
+            return None;
+        }
+        player.position = self.spawn_points.spawn_point(player_name);
+        player.breath = self.config.max_breath;
+        info!("player respawned at {}", player.position);
+
+        let (change, mut commands) =
+            self.set_hp(player, self.config.max_hp, HpChangeReason::Respawn, false);
+        commands.push(breath(player));
+        commands.push(
+            MovePlayerSpec {
+                pos: player.position * 10.0,
+                pitch: player.pitch,
+                yaw: player.yaw,
+            }
+            .into(),
+        );
+        change.map(|change| (change, commands))
+    }
+}
+
+/// Returns the position of the eyes of the player, which decides whether the player can breathe.
+fn head_position(player: &PlayerData) -> Vec3 {
+    player.position + Vec3::new(0.0, EYE_HEIGHT, 0.0)
+}
+
+fn breath(player: &PlayerData) -> ToClientCommand {
+    BreathSpec {
+        breath: player.breath,
+    }
+    .into()
+}
+
+fn death_screen() -> ToClientCommand {
+    DeathscreenSpec {
+        set_camera_point_target: false,
+        camera_point_target: Vec3::ZERO,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use glam::{I16Vec3, Vec3};
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodePos};
+    use luanti_protocol::commands::server_to_client::ToClientCommand;
+
+    use super::{
+        EYE_HEIGHT, FixedSpawnPoint, HealthConfig, HealthRules, HealthTracker, HpChangeReason,
+        NodeHazard, NodeHazards,
+    };
+    use crate::{player_store::PlayerData, world::WorldBlock};
+
+    const LAVA: ContentId = ContentId(10);
+
+    fn tracker(config: HealthConfig) -> HealthTracker {
+        let hazards = NodeHazards(HashMap::from([(
+            LAVA,
+            NodeHazard {
+                name: "default:lava_source".into(),
+                damage_per_second: 8,
+                drowning: 1,
+            },
+        )]));
+        HealthTracker::new(HealthRules {
+            config,
+            spawn_points: Arc::new(FixedSpawnPoint(Vec3::new(0.0, 10.0, 0.0))),
+            hazards: Arc::new(hazards),
+        })
+    }
+
+    fn lava_block() -> WorldBlock {
+        let mut nodes = MapBlockNodes([MapNode::default(); MapBlockPos::NODE_COUNT as usize]);
+        nodes.0.first_mut().unwrap().content_id = LAVA;
+        WorldBlock {
+            version: 0,
+            pos: MapBlockPos::ZERO,
+            is_underground: false,
+            day_night_differs: false,
+            lighting_complete: 0,
+            nodes,
+            metadata: vec![],
+        }
+    }
+
+    #[test]
+    fn dying_and_respawning() {
+        let mut tracker = tracker(HealthConfig::default());
+        let mut player = PlayerData::default();
+
+        let (change, commands) = tracker.damage(&mut player, 5, HpChangeReason::Fall);
+        assert_eq!(change.unwrap().hp, 15);
+        assert_eq!(commands.len(), 1);
+        assert!(tracker.respawn("player", &mut player).is_none());
+
+        let (fatal, death_commands) = tracker.damage(&mut player, 50, HpChangeReason::Fall);
+        assert_eq!(fatal.unwrap().hp, 0);
+        assert!(matches!(
+            death_commands.as_slice(),
+            [ToClientCommand::Hp(_), ToClientCommand::Deathscreen(_)]
+        ));
+        assert!(tracker.dead);
+        // dead players don't take further damage
+        assert!(
+            tracker
+                .damage(&mut player, 1, HpChangeReason::Fall)
+                .0
+                .is_none()
+        );
+
+        let (respawn, respawn_commands) = tracker.respawn("player", &mut player).unwrap();
+        assert_eq!(respawn.reason, HpChangeReason::Respawn);
+        assert_eq!(player.hp, 20);
+        assert_eq!(player.position, Vec3::new(0.0, 10.0, 0.0));
+        assert_eq!(respawn_commands.len(), 3);
+        assert!(!tracker.dead);
+    }
+
+    #[test]
+    fn nodes_hurt_players_touching_them() {
+        let mut tracker = tracker(HealthConfig::default());
+        tracker.hazards_mut().insert(&lava_block());
+        let mut player = PlayerData::default();
+
+        assert!(
+            tracker
+                .node_damage(&mut player, Duration::from_millis(500))
+                .0
+                .is_none()
+        );
+        let (change, _) = tracker.node_damage(&mut player, Duration::from_millis(500));
+        let change = change.unwrap();
+        assert_eq!(change.hp, 12);
+        assert_eq!(
+            change.reason,
+            HpChangeReason::Node("default:lava_source".into())
+        );
+
+        player.position = Vec3::new(3.0, 0.0, 0.0);
+        assert!(
+            tracker
+                .node_damage(&mut player, Duration::from_secs(1))
+                .0
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn changed_nodes_update_the_hazards() {
+        let mut tracker = tracker(HealthConfig::default());
+        let hazards = tracker.hazards_mut();
+        hazards.insert(&lava_block());
+        let origin = MapNodePos(I16Vec3::ZERO);
+        let beside = MapNodePos(I16Vec3::new(5, 0, 0));
+
+        // the lava has been removed
+        hazards.set_node(origin, ContentId::AIR);
+        assert!(hazards.get(Vec3::ZERO).is_none());
+        // and placed elsewhere
+        hazards.set_node(beside, LAVA);
+        assert!(hazards.get(Vec3::new(5.0, 0.0, 0.0)).is_some());
+
+        // blocks without any hazard so far pick up new ones, too
+        let elsewhere = MapNodePos(I16Vec3::new(100, 0, 0));
+        hazards.set_node(elsewhere, LAVA);
+        assert!(hazards.get(Vec3::new(100.0, 0.0, 0.0)).is_some());
+        assert!(hazards.get(Vec3::new(101.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn players_drown_without_breath() {
+        let mut tracker = tracker(HealthConfig::default());
+        tracker.hazards_mut().insert(&lava_block());
+        // the head is inside the hazardous node
+        let mut player = PlayerData {
+            position: Vec3::new(0.0, -EYE_HEIGHT, 0.0),
+            breath: 1,
+            ..PlayerData::default()
+        };
+
+        let (changes, commands) = tracker.breathe(&mut player, Duration::from_secs(2));
+        assert!(changes.is_none());
+        assert!(matches!(commands.as_slice(), [ToClientCommand::Breath(_)]));
+        assert_eq!(player.breath, 0);
+
+        let (change, _) = tracker.breathe(&mut player, Duration::from_secs(2));
+        assert_eq!(change.unwrap().reason, HpChangeReason::Drowning);
+        assert_eq!(player.hp, 19);
+
+        player.position = Vec3::new(3.0, 0.0, 0.0);
+        for _ in 0..4 {
+            tracker.step(&mut player, Duration::from_millis(500));
+        }
+        assert_eq!(player.breath, 4);
+    }
+
+    #[test]
+    fn damage_can_be_disabled() {
+        let mut tracker = tracker(HealthConfig {
+            enable_damage: false,
+            ..HealthConfig::default()
+        });
+        let mut player = PlayerData::default();
+        assert!(
+            tracker
+                .damage(&mut player, 5, HpChangeReason::Fall)
+                .0
+                .is_none()
+        );
+        assert_eq!(player.hp, 20);
+    }
+}
@@ -0,0 +1,374 @@
+//! Helpers for managing HUD elements shown to a player
+
+pub mod waypoint;
+
+use std::collections::BTreeMap;
+
+use glam::{IVec2, Vec2, Vec3};
+use luanti_protocol::commands::server_to_client::{
+    HudStat, HudaddSpec, HudchangeCommand, HudrmSpec,
+};
+use luanti_protocol::types::TrailingBytes;
+
+use crate::api::FromPluginEvent;
+
+/// The type of a HUD element as transmitted in the `typ` field of `HudaddSpec`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HudElementType {
+    /// A static image
+    Image = 0,
+    /// A text
+    Text = 1,
+    /// A bar made of repeated images
+    Statbar = 2,
+    /// An inventory list
+    Inventory = 3,
+    /// A label pointing to a position in the world
+    Waypoint = 4,
+    /// An image pointing to a position in the world
+    ImageWaypoint = 5,
+    /// A compass
+    Compass = 6,
+    /// The minimap
+    Minimap = 7,
+    /// The hotbar
+    Hotbar = 8,
+}
+
+impl From<HudElementType> for u8 {
+    fn from(value: HudElementType) -> Self {
+        value as Self
+    }
+}
+
+/// Identifies a HUD element of a single player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HudId(u32);
+
+impl HudId {
+    /// Returns the id as transmitted to the client.
+    #[must_use]
+    pub fn server_id(self) -> u32 {
+        self.0
+    }
+}
+
+/// The full state of a HUD element.
+///
+/// The meaning of most fields depends on the element's type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HudElement {
+    /// type of the element
+    pub typ: HudElementType,
+    /// position relative to the screen size (0.0 … 1.0)
+    pub pos: Vec2,
+    /// name or label of the element
+    pub name: String,
+    /// scale of the element
+    pub scale: Vec2,
+    /// text, texture or suffix depending on the type
+    pub text: String,
+    /// number, color or texture count depending on the type
+    pub number: u32,
+    /// item count, precision or selected item depending on the type
+    pub item: u32,
+    /// direction in which the element grows
+    pub dir: u32,
+    /// alignment relative to `pos`
+    pub align: Vec2,
+    /// offset in pixels
+    pub offset: Vec2,
+    /// position in the world (waypoints only)
+    pub world_pos: Vec3,
+    /// size in pixels
+    pub size: IVec2,
+    /// elements with a higher z-index are drawn on top
+    pub z_index: i16,
+    /// secondary text or texture
+    pub text2: String,
+    /// style flags for text elements
+    pub style: u32,
+}
+
+impl HudElement {
+    /// Create a new element of the given type using neutral defaults for all other fields.
+    #[must_use]
+    pub fn new(typ: HudElementType) -> Self {
+        Self {
+            typ,
+            pos: Vec2::ZERO,
+            name: String::new(),
+            scale: Vec2::ONE,
+            text: String::new(),
+            number: 0,
+            item: 0,
+            dir: 0,
+            align: Vec2::ZERO,
+            offset: Vec2::ZERO,
+            world_pos: Vec3::ZERO,
+            size: IVec2::ZERO,
+            z_index: 0,
+            text2: String::new(),
+            style: 0,
+        }
+    }
+
+    /// Create a text element at the given screen position using the color `0xRRGGBB`.
+    #[must_use]
+    pub fn text(pos: Vec2, text: impl Into<String>, color: u32) -> Self {
+        Self {
+            pos,
+            text: text.into(),
+            number: color,
+            ..Self::new(HudElementType::Text)
+        }
+    }
+
+    /// Create an image element at the given screen position.
+    #[must_use]
+    pub fn image(pos: Vec2, texture: impl Into<String>, scale: Vec2) -> Self {
+        Self {
+            pos,
+            text: texture.into(),
+            scale,
+            ..Self::new(HudElementType::Image)
+        }
+    }
+
+    /// Create a statbar showing `count` half-textures.
+    #[must_use]
+    pub fn statbar(pos: Vec2, texture: impl Into<String>, count: u32) -> Self {
+        Self {
+            pos,
+            text: texture.into(),
+            number: count,
+            ..Self::new(HudElementType::Statbar)
+        }
+    }
+
+    /// Apply a change to this element.
+    pub fn apply(&mut self, stat: &HudStat) {
+        match stat {
+            HudStat::Pos(value) => self.pos = *value,
+            HudStat::Name(value) => self.name.clone_from(value),
+            HudStat::Scale(value) => self.scale = *value,
+            HudStat::Text(value) => self.text.clone_from(value),
+            HudStat::Number(value) => self.number = *value,
+            HudStat::Item(value) => self.item = *value,
+            HudStat::Dir(value) => self.dir = *value,
+            HudStat::Align(value) => self.align = *value,
+            HudStat::Offset(value) => self.offset = *value,
+            HudStat::WorldPos(value) => self.world_pos = *value,
+            HudStat::Size(value) => self.size = *value,
+            HudStat::ZIndex(value) => {
+                // the value is transmitted as `s32` but stored as `s16` by the client
+                let value = (*value).clamp(i16::MIN.into(), i16::MAX.into());
+                self.z_index = i16::try_from(value).unwrap_or_default();
+            }
+            HudStat::Text2(value) => self.text2.clone_from(value),
+            HudStat::Style(value) => self.style = *value,
+        }
+    }
+
+    fn to_spec(&self, id: HudId) -> HudaddSpec {
+        HudaddSpec {
+            server_id: id.0,
+            typ: self.typ.into(),
+            pos: self.pos,
+            name: self.name.clone(),
+            scale: self.scale,
+            text: self.text.clone(),
+            number: self.number,
+            item: self.item,
+            dir: self.dir,
+            align: self.align,
+            offset: self.offset,
+            world_pos: Some(self.world_pos),
+            size: Some(self.size),
+            z_index: Some(self.z_index),
+            text2: Some(self.text2.clone()),
+            style: Some(self.style),
+            extra: TrailingBytes::default(),
+        }
+    }
+}
+
+/// Manages the HUD elements of a single player.
+///
+/// Allocates the element ids and keeps a copy of each element's state, so all elements can be
+/// sent again, e.g. after a reconnect.
+/// All methods return the commands which need to be sent to the client to reflect the change.
+#[derive(Debug, Default)]
+pub struct HudManager {
+    next_id: u32,
+    elements: BTreeMap<HudId, HudElement>,
+}
+
+impl HudManager {
+    /// Create a manager without any elements.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show a new element.
+    pub fn add(&mut self, element: HudElement) -> (HudId, FromPluginEvent) {
+        let id = self.allocate_id();
+        let event = FromPluginEvent::Hudadd(element.to_spec(id));
+        self.elements.insert(id, element);
+        (id, event)
+    }
+
+    /// Change a single property of an existing element.
+    ///
+    /// Returns `None` if the element doesn't exist (anymore).
+    pub fn change(&mut self, id: HudId, stat: HudStat) -> Option<FromPluginEvent> {
+        let element = self.elements.get_mut(&id)?;
+        element.apply(&stat);
+        Some(FromPluginEvent::Hudchange(HudchangeCommand {
+            server_id: id.0,
+            stat,
+        }))
+    }
+
+    /// Remove an element.
+    ///
+    /// Returns `None` if the element doesn't exist (anymore).
+    pub fn remove(&mut self, id: HudId) -> Option<FromPluginEvent> {
+        self.elements.remove(&id)?;
+        Some(FromPluginEvent::Hudrm(HudrmSpec { server_id: id.0 }))
+    }
+
+    /// Returns the current state of an element.
+    #[must_use]
+    pub fn get(&self, id: HudId) -> Option<&HudElement> {
+        self.elements.get(&id)
+    }
+
+    /// Iterates over all elements ordered by their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (HudId, &HudElement)> {
+        self.elements.iter().map(|(&id, element)| (id, element))
+    }
+
+    /// Returns the commands required to show all elements again, e.g. after a reconnect.
+    #[must_use]
+    pub fn resend(&self) -> Vec<FromPluginEvent> {
+        self.elements
+            .iter()
+            .map(|(&id, element)| FromPluginEvent::Hudadd(element.to_spec(id)))
+            .collect()
+    }
+
+    /// Returns the next id which isn't in use.
+    fn allocate_id(&mut self) -> HudId {
+        while self.elements.contains_key(&HudId(self.next_id)) {
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        let id = HudId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use luanti_protocol::commands::server_to_client::{
+        HudStat, HudaddSpec, HudchangeCommand, HudrmSpec,
+    };
+
+    use super::{HudElement, HudElementType, HudId, HudManager};
+    use crate::api::FromPluginEvent;
+
+    #[test]
+    fn added_elements_get_distinct_ids() {
+        let mut hud = HudManager::new();
+        let (first, event) = hud.add(HudElement::text(Vec2::new(0.5, 0.5), "hello", 0x00ff_0000));
+        assert!(matches!(
+            event,
+            FromPluginEvent::Hudadd(HudaddSpec {
+                server_id: 0,
+                typ: 1,
+                ref text,
+                number: 0x00ff_0000,
+                z_index: Some(0),
+                ..
+            }) if text == "hello"
+        ));
+        let (second, _) = hud.add(HudElement::new(HudElementType::Minimap));
+        assert_eq!((first.server_id(), second.server_id()), (0, 1));
+
+        // ids of removed elements aren't reused right away
+        assert!(hud.remove(first).is_some());
+        let (third, _) = hud.add(HudElement::new(HudElementType::Compass));
+        assert_eq!(third.server_id(), 2);
+        assert_eq!(
+            hud.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            [second, third]
+        );
+    }
+
+    #[test]
+    fn changes_are_applied_and_sent() {
+        let mut hud = HudManager::new();
+        let (id, _) = hud.add(HudElement::new(HudElementType::Waypoint));
+        let target = Vec3::new(1.0, 2.0, 3.0);
+
+        let event = hud.change(id, HudStat::WorldPos(target));
+        assert!(matches!(
+            event,
+            Some(FromPluginEvent::Hudchange(HudchangeCommand {
+                server_id: 0,
+                stat: HudStat::WorldPos(pos),
+            })) if pos == target
+        ));
+        assert_eq!(hud.get(id).map(|element| element.world_pos), Some(target));
+
+        assert!(matches!(
+            hud.remove(id),
+            Some(FromPluginEvent::Hudrm(HudrmSpec { server_id: 0 }))
+        ));
+        assert!(hud.change(id, HudStat::Number(1)).is_none());
+        assert!(hud.remove(id).is_none());
+        assert!(hud.remove(HudId(42)).is_none());
+    }
+
+    #[test]
+    fn z_indices_are_limited_to_16_bits() {
+        let mut element = HudElement::new(HudElementType::Image);
+        element.apply(&HudStat::ZIndex(-5));
+        assert_eq!(element.z_index, -5);
+        element.apply(&HudStat::ZIndex(100_000));
+        assert_eq!(element.z_index, i16::MAX);
+        element.apply(&HudStat::ZIndex(-100_000));
+        assert_eq!(element.z_index, i16::MIN);
+    }
+
+    #[test]
+    fn all_elements_can_be_sent_again() {
+        let mut hud = HudManager::new();
+        let (id, _) = hud.add(HudElement::statbar(Vec2::ZERO, "heart.png", 20));
+        hud.add(HudElement::image(Vec2::ONE, "logo.png", Vec2::ONE));
+        hud.change(id, HudStat::ZIndex(7));
+
+        let events = hud.resend();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                FromPluginEvent::Hudadd(HudaddSpec {
+                    server_id: 0,
+                    typ: 2,
+                    number: 20,
+                    z_index: Some(7),
+                    ..
+                }),
+                FromPluginEvent::Hudadd(HudaddSpec {
+                    server_id: 1,
+                    typ: 0,
+                    ..
+                }),
+            ]
+        ));
+    }
+}
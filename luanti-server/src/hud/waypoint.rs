@@ -0,0 +1,230 @@
+//! Waypoints are HUD elements pointing to a position in the world
+
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec3};
+use luanti_protocol::commands::server_to_client::HudStat;
+
+use crate::api::FromPluginEvent;
+
+use super::{HudElement, HudElementType, HudId, HudManager};
+
+/// Description of a waypoint
+#[derive(Clone, Debug, PartialEq)]
+pub struct Waypoint {
+    /// Text shown next to the waypoint
+    pub label: String,
+    /// Position in node coordinates the waypoint points to
+    pub target: Vec3,
+    /// Color of the label as `0xRRGGBB`
+    pub color: u32,
+    /// Text appended to the distance, e.g. `"m"`
+    pub suffix: String,
+    /// Precision of the shown distance; `10` shows one decimal place, `0` hides the distance
+    pub precision: u32,
+    /// The waypoint will be removed automatically once the player gets closer than this distance
+    /// (in nodes)
+    pub reach_distance: Option<f32>,
+}
+
+impl Waypoint {
+    /// Create a white waypoint pointing to `target` showing the distance in whole meters.
+    #[must_use]
+    pub fn new(label: impl Into<String>, target: Vec3) -> Self {
+        Self {
+            label: label.into(),
+            target,
+            color: 0x00ff_ffff,
+            suffix: "m".into(),
+            precision: 1,
+            reach_distance: None,
+        }
+    }
+
+    /// Removes the waypoint automatically once the player gets closer than `distance`.
+    #[must_use]
+    pub fn with_reach_distance(mut self, distance: f32) -> Self {
+        self.reach_distance = Some(distance);
+        self
+    }
+
+    /// Sets the color of the label as `0xRRGGBB`.
+    #[must_use]
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn hud_element(&self) -> HudElement {
+        HudElement {
+            name: self.label.clone(),
+            scale: Vec2::ZERO,
+            text: self.suffix.clone(),
+            number: self.color,
+            item: self.precision,
+            world_pos: self.target,
+            ..HudElement::new(HudElementType::Waypoint)
+        }
+    }
+}
+
+/// Keeps track of all waypoints shown to a single player.
+///
+/// The HUD elements are managed by the player's [`HudManager`], which needs to be passed to all
+/// methods modifying waypoints. The returned commands need to be sent to the client to reflect
+/// the change.
+#[derive(Debug, Default)]
+pub struct WaypointTracker {
+    waypoints: HashMap<HudId, Waypoint>,
+}
+
+impl WaypointTracker {
+    /// Create a tracker without any waypoints.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show a new waypoint.
+    pub fn add(&mut self, hud: &mut HudManager, waypoint: Waypoint) -> (HudId, FromPluginEvent) {
+        let (id, event) = hud.add(waypoint.hud_element());
+        self.waypoints.insert(id, waypoint);
+        (id, event)
+    }
+
+    /// Returns the waypoint with the given id.
+    #[must_use]
+    pub fn get(&self, id: HudId) -> Option<&Waypoint> {
+        self.waypoints.get(&id)
+    }
+
+    /// Move an existing waypoint to a new target position.
+    ///
+    /// Returns `None` if the waypoint doesn't exist (anymore) or didn't move.
+    pub fn move_to(
+        &mut self,
+        hud: &mut HudManager,
+        id: HudId,
+        target: Vec3,
+    ) -> Option<FromPluginEvent> {
+        let waypoint = self.waypoints.get_mut(&id)?;
+        if waypoint.target == target {
+            return None;
+        }
+        waypoint.target = target;
+        hud.change(id, HudStat::WorldPos(target))
+    }
+
+    /// Remove a waypoint.
+    ///
+    /// Returns `None` if the waypoint doesn't exist (anymore).
+    pub fn remove(&mut self, hud: &mut HudManager, id: HudId) -> Option<FromPluginEvent> {
+        self.waypoints.remove(&id)?;
+        hud.remove(id)
+    }
+
+    /// Inform the tracker about the current position of the player (in node coordinates).
+    ///
+    /// All waypoints which have been reached will be removed. Returns the ids of those waypoints
+    /// along with the commands for removing them.
+    pub fn update_player_position(
+        &mut self,
+        hud: &mut HudManager,
+        position: Vec3,
+    ) -> Vec<(HudId, FromPluginEvent)> {
+        let reached: Vec<_> = self
+            .waypoints
+            .iter()
+            .filter(|(_, waypoint)| {
+                waypoint
+                    .reach_distance
+                    .is_some_and(|distance| waypoint.target.distance(position) < distance)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        reached
+            .into_iter()
+            .filter_map(|id| Some((id, self.remove(hud, id)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use luanti_protocol::commands::server_to_client::{
+        HudStat, HudaddSpec, HudchangeCommand, HudrmSpec,
+    };
+
+    use super::{Waypoint, WaypointTracker};
+    use crate::api::FromPluginEvent;
+    use crate::hud::HudManager;
+
+    #[test]
+    fn waypoints_can_be_added_moved_and_removed() {
+        let mut hud = HudManager::new();
+        let mut waypoints = WaypointTracker::new();
+        let home = Vec3::new(10.0, 0.0, -5.0);
+
+        let (id, event) = waypoints.add(&mut hud, Waypoint::new("home", home));
+        assert!(matches!(
+            event,
+            FromPluginEvent::Hudadd(HudaddSpec {
+                typ: 4,
+                ref name,
+                world_pos: Some(pos),
+                ..
+            }) if name == "home" && pos == home
+        ));
+        assert!(hud.get(id).is_some());
+
+        let garden = Vec3::new(20.0, 1.0, 0.0);
+        assert!(matches!(
+            waypoints.move_to(&mut hud, id, garden),
+            Some(FromPluginEvent::Hudchange(HudchangeCommand {
+                stat: HudStat::WorldPos(pos),
+                ..
+            })) if pos == garden
+        ));
+        assert!(waypoints.move_to(&mut hud, id, garden).is_none());
+        assert_eq!(
+            waypoints.get(id).map(|waypoint| waypoint.target),
+            Some(garden)
+        );
+        assert_eq!(hud.get(id).map(|element| element.world_pos), Some(garden));
+
+        assert!(matches!(
+            waypoints.remove(&mut hud, id),
+            Some(FromPluginEvent::Hudrm(HudrmSpec { server_id })) if server_id == id.server_id()
+        ));
+        assert!(waypoints.get(id).is_none());
+        assert!(hud.get(id).is_none());
+        assert!(waypoints.remove(&mut hud, id).is_none());
+    }
+
+    #[test]
+    fn reached_waypoints_are_removed() {
+        let mut hud = HudManager::new();
+        let mut waypoints = WaypointTracker::new();
+        let target = Vec3::new(0.0, 0.0, 10.0);
+        let (reachable, _) = waypoints.add(
+            &mut hud,
+            Waypoint::new("goal", target).with_reach_distance(2.0),
+        );
+        let (permanent, _) = waypoints.add(&mut hud, Waypoint::new("spawn", target));
+
+        assert!(
+            waypoints
+                .update_player_position(&mut hud, Vec3::ZERO)
+                .is_empty()
+        );
+        let removed = waypoints.update_player_position(&mut hud, Vec3::new(0.0, 0.0, 9.0));
+        assert!(matches!(
+            removed.as_slice(),
+            [(id, FromPluginEvent::Hudrm(_))] if *id == reachable
+        ));
+        assert!(waypoints.get(reachable).is_none());
+        assert!(waypoints.get(permanent).is_some());
+    }
+}
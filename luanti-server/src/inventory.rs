@@ -0,0 +1,299 @@
+//! Inventories which are managed by the server rather than belonging to a player or a node
+//!
+//! Detached inventories are identified by their name and used for chests shared across the world,
+//! trade interfaces or creative inventories. They are shown in formspecs via
+//! [`InventoryRef::Detached`](crate::formspec::InventoryRef::Detached). An inventory with an owner
+//! is only being sent to that player, all others are sent to everyone.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::debug;
+use luanti_protocol::commands::server_to_client::DetachedInventorySpec;
+use luanti_protocol::types::{Inventory, InventoryEntry, InventoryList};
+
+use crate::client_connection::ConnectedPlayers;
+use crate::load_budget::serialized_size;
+
+/// The detached inventories of the server
+#[derive(Debug, Clone)]
+pub struct InventoryManager {
+    /// detached inventories by name
+    detached: Arc<Mutex<HashMap<String, DetachedInventory>>>,
+    connected_players: ConnectedPlayers,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DetachedInventory {
+    /// the only player who may see the inventory; everybody if `None`
+    owner: Option<String>,
+    lists: Vec<InventoryList>,
+}
+
+impl DetachedInventory {
+    fn is_visible_to(&self, player: &str) -> bool {
+        self.owner.as_ref().is_none_or(|owner| owner == player)
+    }
+
+    /// Returns the update of the inventory; lists which are not contained in `changed` are
+    /// being kept by the client. All lists are being sent if `changed` is `None`.
+    fn spec(&self, name: &str, changed: Option<&[&str]>) -> DetachedInventorySpec {
+        let entries = self
+            .lists
+            .iter()
+            .map(|list| {
+                if changed.is_none_or(|changed| changed.contains(&list.name.as_str())) {
+                    InventoryEntry::Update(list.clone())
+                } else {
+                    InventoryEntry::KeepList(list.name.clone())
+                }
+            })
+            .collect();
+        let contents = Inventory { entries };
+        DetachedInventorySpec {
+            name: name.to_owned(),
+            keep_inv: true,
+            // Luanti 5.0.0 clients expect the size of the inventory in front of it
+            ignore: Some(
+                u16::try_from(serialized_size::<Inventory>(&contents)).unwrap_or(u16::MAX),
+            ),
+            contents: Some(contents),
+        }
+    }
+}
+
+impl InventoryManager {
+    pub(crate) fn new(connected_players: ConnectedPlayers) -> Self {
+        Self {
+            detached: Arc::default(),
+            connected_players,
+        }
+    }
+
+    /// Creates an empty detached inventory and sends it to the players who may see it. Returns
+    /// `false` if an inventory of this name already exists.
+    ///
+    /// If an `owner` is given, only this player gets to see the inventory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    pub fn create_detached(&self, name: &str, owner: Option<&str>) -> bool {
+        let inventory = DetachedInventory {
+            owner: owner.map(ToOwned::to_owned),
+            lists: Vec::new(),
+        };
+        {
+            let mut detached = self.detached.lock().expect("poisoned inventories");
+            if detached.contains_key(name) {
+                return false;
+            }
+            detached.insert(name.to_owned(), inventory.clone());
+        }
+        self.send(&inventory, inventory.spec(name, None));
+        true
+    }
+
+    /// Removes a detached inventory from the server and all clients. Returns `false` if there is
+    /// no inventory of this name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    #[must_use]
+    pub fn remove_detached(&self, name: &str) -> bool {
+        let Some(inventory) = self
+            .detached
+            .lock()
+            .expect("poisoned inventories")
+            .remove(name)
+        else {
+            return false;
+        };
+        let spec = DetachedInventorySpec {
+            name: name.to_owned(),
+            keep_inv: false,
+            ignore: None,
+            contents: None,
+        };
+        self.send(&inventory, spec);
+        true
+    }
+
+    /// Returns the names of all detached inventories ordered by name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    #[must_use]
+    pub fn detached(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .detached
+            .lock()
+            .expect("poisoned inventories")
+            .keys()
+            .cloned()
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the lists of a detached inventory or `None` if it doesn't exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    #[must_use]
+    pub fn lists(&self, name: &str) -> Option<Vec<InventoryList>> {
+        self.detached
+            .lock()
+            .expect("poisoned inventories")
+            .get(name)
+            .map(|inventory| inventory.lists.clone())
+    }
+
+    /// Returns `true` if the player may see and use the detached inventory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    #[must_use]
+    pub fn is_visible_to(&self, name: &str, player: &str) -> bool {
+        self.detached
+            .lock()
+            .expect("poisoned inventories")
+            .get(name)
+            .is_some_and(|inventory| inventory.is_visible_to(player))
+    }
+
+    /// Modifies the lists of a detached inventory and sends the lists which have been changed to
+    /// the players who may see it. Returns `false` if there is no inventory of this name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the inventories' lock.
+    pub fn modify_detached(
+        &self,
+        name: &str,
+        modify: impl FnOnce(&mut Vec<InventoryList>),
+    ) -> bool {
+        let (inventory, spec) = {
+            let mut detached = self.detached.lock().expect("poisoned inventories");
+            let Some(inventory) = detached.get_mut(name) else {
+                return false;
+            };
+            let previous = inventory.lists.clone();
+            modify(&mut inventory.lists);
+            let changed = changed_lists(&previous, &inventory.lists);
+            // removing a list changes the set of lists being sent
+            if changed.is_empty() && previous.len() == inventory.lists.len() {
+                return true;
+            }
+            (inventory.clone(), inventory.spec(name, Some(&changed)))
+        };
+        self.send(&inventory, spec);
+        true
+    }
+
+    /// Returns all detached inventories a player who just joined may see.
+    pub(crate) fn join(&self, player: &str) -> Vec<DetachedInventorySpec> {
+        self.detached
+            .lock()
+            .expect("poisoned inventories")
+            .iter()
+            .filter(|(_, inventory)| inventory.is_visible_to(player))
+            .map(|(name, inventory)| inventory.spec(name, None))
+            .collect()
+    }
+
+    fn send(&self, inventory: &DetachedInventory, spec: DetachedInventorySpec) {
+        let name = spec.name.clone();
+        let receivers = match &inventory.owner {
+            Some(owner) => usize::from(self.connected_players.send(owner, spec.into())),
+            None => self.connected_players.broadcast(&spec.into()),
+        };
+        debug!("sent detached inventory '{name}' to {receivers} players");
+    }
+}
+
+/// Returns the names of the lists which have been added or modified.
+fn changed_lists<'lists>(
+    previous: &[InventoryList],
+    current: &'lists [InventoryList],
+) -> Vec<&'lists str> {
+    current
+        .iter()
+        .filter(|list| !previous.contains(list))
+        .map(|list| list.name.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use luanti_protocol::types::{InventoryEntry, InventoryList, ItemStackUpdate};
+
+    use super::InventoryManager;
+    use crate::client_connection::ConnectedPlayers;
+
+    fn list(name: &str, size: usize) -> InventoryList {
+        InventoryList {
+            name: name.into(),
+            width: 0,
+            items: vec![ItemStackUpdate::Empty; size],
+        }
+    }
+
+    #[test]
+    fn owned_inventories_are_private() {
+        let inventories = InventoryManager::new(ConnectedPlayers::default());
+        assert!(inventories.create_detached("shop", None));
+        assert!(!inventories.create_detached("shop", Some("alice")));
+        assert!(inventories.create_detached("bag", Some("alice")));
+
+        assert_eq!(inventories.detached(), ["bag", "shop"]);
+        assert_eq!(inventories.join("alice").len(), 2);
+        let specs = inventories.join("bob");
+        assert_eq!(specs.len(), 1);
+        assert!(specs.iter().all(|spec| spec.name == "shop"));
+        assert!(!inventories.is_visible_to("bag", "bob"));
+
+        assert!(inventories.remove_detached("bag"));
+        assert!(!inventories.remove_detached("bag"));
+        assert_eq!(inventories.join("alice").len(), 1);
+    }
+
+    #[test]
+    fn only_changed_lists_are_updated() {
+        let inventories = InventoryManager::new(ConnectedPlayers::default());
+        inventories.create_detached("chest", None);
+        assert!(inventories.modify_detached("chest", |lists| {
+            lists.push(list("main", 8));
+            lists.push(list("fuel", 1));
+        }));
+        assert!(!inventories.modify_detached("missing", |_| {}));
+
+        let previous = inventories.lists("chest").unwrap_or_default();
+        let mut current = previous.clone();
+        if let Some(fuel) = current.last_mut() {
+            fuel.items = vec![ItemStackUpdate::Keep];
+        }
+        let changed = super::changed_lists(&previous, &current);
+        assert_eq!(changed, ["fuel"]);
+
+        let spec = {
+            let detached = inventories.detached.lock().expect("poisoned inventories");
+            detached
+                .get("chest")
+                .map(|chest| chest.spec("chest", Some(&changed)))
+        };
+        let entries = spec
+            .and_then(|spec| spec.contents)
+            .map(|contents| contents.entries)
+            .unwrap_or_default();
+        assert!(matches!(
+            entries.as_slice(),
+            [InventoryEntry::KeepList(main), InventoryEntry::Update(_)] if main == "main"
+        ));
+    }
+}
@@ -2,7 +2,6 @@
 // #![expect(clippy::expect_used, reason = "//TODO improve error handling")]
 
 #![expect(
-    clippy::todo,
     clippy::expect_used,
     reason = "//TODO remove before completion of the prototype"
 )]
@@ -10,8 +9,28 @@
 pub mod api;
 pub mod authentication;
 mod client_connection;
+pub mod clock;
+pub mod config;
+pub mod connections;
+pub mod flood;
+pub mod formspec;
+pub mod health;
+pub mod hud;
+pub mod inventory;
+pub mod load_budget;
+pub mod metrics;
+pub mod minimap;
+pub mod mod_channels;
+pub mod movement;
+pub mod player_store;
+pub mod send_rate;
 pub mod server;
+pub mod serverlist;
+pub mod sky;
+pub mod sound;
+pub mod status;
 pub mod world;
 
 use world::content_id_map::ContentIdMap;
+use world::content_id_mapper::ContentIdMapper;
 use world::media_registry::MediaRegistry;
@@ -0,0 +1,182 @@
+//! A global limit for the amount of data being sent to loading clients
+//!
+//! When many players join at the same time (e.g. after a server restart) each of them needs to
+//! receive the item definitions, node definitions and media. Sending all of this at once would
+//! saturate the socket and the peer channels. [`LoadBudget`] staggers those transfers across
+//! ticks by granting a fixed number of bytes per tick to all connections together.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::trace;
+use luanti_protocol::types::ProtocolContext;
+use luanti_protocol::wire::ser::{MockSerializer, Serialize};
+use tokio::time::{Instant, sleep_until};
+
+/// Shared byte budget for the transfer of definitions and media.
+///
+/// Transfers larger than the budget of a single tick are granted as soon as the budget is
+/// positive and the excess is borrowed from the following ticks.
+#[derive(Debug)]
+pub struct LoadBudget {
+    bytes_per_tick: u64,
+    tick: Duration,
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    /// may become negative if a transfer exceeded the remaining budget
+    available: i64,
+    last_refill: Instant,
+}
+
+impl LoadBudget {
+    /// The default budget of 1 MiB every 100 ms
+    pub const DEFAULT_BYTES_PER_TICK: u64 = 1024 * 1024;
+
+    /// The default tick length
+    pub const DEFAULT_TICK: Duration = Duration::from_millis(100);
+
+    /// Create a budget granting `bytes_per_tick` bytes every `tick`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick` is zero.
+    #[must_use]
+    pub fn new(bytes_per_tick: u64, tick: Duration) -> Self {
+        assert!(!tick.is_zero(), "tick length must not be zero");
+        Self {
+            bytes_per_tick,
+            tick,
+            state: Mutex::new(BudgetState {
+                available: Self::saturating_i64(bytes_per_tick),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Create a budget which never delays any transfer.
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self::new(u64::MAX, Self::DEFAULT_TICK)
+    }
+
+    /// Wait until `bytes` may be sent and deduct them from the budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the budget's lock.
+    pub async fn acquire(&self, bytes: usize) {
+        let bytes = Self::saturating_i64(u64::try_from(bytes).unwrap_or(u64::MAX));
+        loop {
+            let next_tick = {
+                let mut state = self.state.lock().expect("poisoned load budget");
+                self.refill(&mut state);
+                if state.available > 0 {
+                    state.available = state.available.saturating_sub(bytes);
+                    return;
+                }
+                state.last_refill + self.tick
+            };
+            trace!("load budget exhausted; waiting for next tick");
+            sleep_until(next_tick).await;
+        }
+    }
+
+    fn refill(&self, state: &mut BudgetState) {
+        let elapsed = state.last_refill.elapsed();
+        let ticks = elapsed.as_nanos() / self.tick.as_nanos();
+        if ticks == 0 {
+            return;
+        }
+        let ticks = u32::try_from(ticks).unwrap_or(u32::MAX);
+        let refill = self.bytes_per_tick.saturating_mul(u64::from(ticks));
+        let capacity = Self::saturating_i64(self.bytes_per_tick);
+        state.available = state
+            .available
+            .saturating_add(Self::saturating_i64(refill))
+            .min(capacity);
+        state.last_refill += self.tick.saturating_mul(ticks);
+    }
+
+    fn saturating_i64(value: u64) -> i64 {
+        i64::try_from(value).unwrap_or(i64::MAX)
+    }
+}
+
+impl Default for LoadBudget {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BYTES_PER_TICK, Self::DEFAULT_TICK)
+    }
+}
+
+/// Returns the (uncompressed) number of bytes `value` will be serialized to.
+pub(crate) fn serialized_size<T: Serialize>(value: &T::Input) -> usize {
+    let mut ser = MockSerializer::new(ProtocolContext::latest_for_send(false));
+    match T::serialize(value, &mut ser) {
+        Ok(()) => ser.len(),
+        // this will fail again when actually being sent; no need to delay that
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::LoadBudget;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    #[tokio::test(start_paused = true)]
+    async fn transfers_within_the_budget_are_not_delayed() {
+        let budget = LoadBudget::new(1000, TICK);
+        let start = Instant::now();
+        budget.acquire(600).await;
+        budget.acquire(399).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // the budget is exhausted after 1000 bytes
+        budget.acquire(1).await;
+        budget.acquire(1).await;
+        assert_eq!(start.elapsed(), TICK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn large_transfers_borrow_from_the_following_ticks() {
+        let budget = LoadBudget::new(1000, TICK);
+        let start = Instant::now();
+        budget.acquire(2500).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // -1500 after the first transfer; positive again after two refills
+        budget.acquire(1).await;
+        assert_eq!(start.elapsed(), TICK * 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_ticks_do_not_accumulate() {
+        let budget = LoadBudget::new(1000, TICK);
+        tokio::time::sleep(TICK * 10).await;
+        let start = Instant::now();
+        budget.acquire(1000).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        budget.acquire(1).await;
+        budget.acquire(1).await;
+        assert_eq!(start.elapsed(), TICK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unlimited_budgets_never_delay() {
+        let budget = LoadBudget::unlimited();
+        let start = Instant::now();
+        for _ in 0..10 {
+            budget.acquire(1 << 30).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
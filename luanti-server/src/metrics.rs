@@ -0,0 +1,427 @@
+//! Statistics about the server which can be exported in Prometheus' text format
+//!
+//! All values are counted since the server has been started. Rates like the number of map blocks
+//! being sent per second are expected to be computed by Prometheus, e.g. with
+//! `rate(luanti_blocks_sent_total[1m])`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use log::info;
+use luanti_protocol::wire::compression::CompressionStats;
+use luanti_protocol::wire::compression::compression_stats;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+/// The path the metrics are being served at
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Requests must not be larger than this
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Statistics about the server; cloning yields a handle to the same statistics.
+///
+/// Its [`fmt::Display`] implementation renders Prometheus' text exposition format.
+#[derive(Debug, Clone, Default)]
+pub struct ServerMetrics {
+    inner: Arc<Metrics>,
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    connected_players: AtomicU64,
+    blocks_sent: AtomicU64,
+    retransmits: AtomicU64,
+    mapgen_queue_depth: AtomicU64,
+    coalesced_requests: AtomicU64,
+    /// by command name
+    unhandled_commands: Mutex<BTreeMap<&'static str, u64>>,
+    storage_load: Timing,
+    storage_store: Timing,
+    generation: Timing,
+    provider_tick: Timing,
+}
+
+/// Sum and number of measured durations
+#[derive(Debug, Default)]
+struct Timing {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Timing {
+    fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn sum(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+impl ServerMetrics {
+    /// Returns the number of players being in-game.
+    #[must_use]
+    pub fn connected_players(&self) -> u64 {
+        self.inner.connected_players.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of map blocks which have been sent to all players.
+    #[must_use]
+    pub fn blocks_sent(&self) -> u64 {
+        self.inner.blocks_sent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of reliable packets which had to be sent again to any client.
+    #[must_use]
+    pub fn retransmits(&self) -> u64 {
+        self.inner.retransmits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of map block requests waiting to be loaded or generated.
+    #[must_use]
+    pub fn mapgen_queue_depth(&self) -> u64 {
+        self.inner.mapgen_queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of map block requests which have been merged with a waiting request
+    /// for the same map block.
+    #[must_use]
+    pub fn coalesced_requests(&self) -> u64 {
+        self.inner.coalesced_requests.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of commands which have been dropped as they arrived in a state of the
+    /// connection not handling them, by command name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while counting a command.
+    #[must_use]
+    pub fn unhandled_commands(&self) -> BTreeMap<&'static str, u64> {
+        self.inner
+            .unhandled_commands
+            .lock()
+            .expect("poisoned unhandled commands")
+            .clone()
+    }
+
+    pub(crate) fn player_joined(&self) {
+        self.inner.connected_players.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn player_left(&self) {
+        self.inner.connected_players.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn block_sent(&self) {
+        self.inner.blocks_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_retransmits(&self, count: u64) {
+        self.inner.retransmits.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_mapgen_queue_depth(&self, depth: usize) {
+        let depth = u64::try_from(depth).unwrap_or(u64::MAX);
+        self.inner
+            .mapgen_queue_depth
+            .store(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request_coalesced(&self) {
+        self.inner
+            .coalesced_requests
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn command_unhandled(&self, command_name: &'static str) {
+        *self
+            .inner
+            .unhandled_commands
+            .lock()
+            .expect("poisoned unhandled commands")
+            .entry(command_name)
+            .or_default() += 1;
+    }
+
+    pub(crate) fn record_storage_load(&self, duration: Duration) {
+        self.inner.storage_load.record(duration);
+    }
+
+    pub(crate) fn record_storage_store(&self, duration: Duration) {
+        self.inner.storage_store.record(duration);
+    }
+
+    pub(crate) fn record_generation(&self, duration: Duration) {
+        self.inner.generation.record(duration);
+    }
+
+    pub(crate) fn record_provider_tick(&self, duration: Duration) {
+        self.inner.provider_tick.record(duration);
+    }
+}
+
+impl fmt::Display for ServerMetrics {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = &self.inner;
+        write_value(
+            formatter,
+            "luanti_connected_players",
+            "gauge",
+            "Number of players being in-game",
+            self.connected_players(),
+        )?;
+        write_value(
+            formatter,
+            "luanti_blocks_sent_total",
+            "counter",
+            "Number of map blocks sent to all players",
+            self.blocks_sent(),
+        )?;
+        write_value(
+            formatter,
+            "luanti_peer_retransmits_total",
+            "counter",
+            "Number of reliable packets sent again to any client",
+            self.retransmits(),
+        )?;
+        write_value(
+            formatter,
+            "luanti_mapgen_queue_depth",
+            "gauge",
+            "Number of map block requests waiting to be loaded or generated",
+            self.mapgen_queue_depth(),
+        )?;
+        write_value(
+            formatter,
+            "luanti_mapgen_coalesced_requests_total",
+            "counter",
+            "Number of map block requests merged with a waiting request for the same map block",
+            self.coalesced_requests(),
+        )?;
+        write_unhandled_commands(formatter, &self.unhandled_commands())?;
+        write_timing(
+            formatter,
+            "luanti_storage_load_seconds",
+            "Time spent loading map blocks from the storage",
+            &metrics.storage_load,
+        )?;
+        write_timing(
+            formatter,
+            "luanti_storage_store_seconds",
+            "Time spent writing map blocks to the storage",
+            &metrics.storage_store,
+        )?;
+        write_timing(
+            formatter,
+            "luanti_mapgen_seconds",
+            "Time spent generating map blocks",
+            &metrics.generation,
+        )?;
+        write_timing(
+            formatter,
+            "luanti_provider_tick_seconds",
+            "Duration of the map block provider's ticks which had work to do",
+            &metrics.provider_tick,
+        )?;
+        write_compression(formatter)
+    }
+}
+
+fn write_value(
+    formatter: &mut fmt::Formatter<'_>,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: u64,
+) -> fmt::Result {
+    writeln!(formatter, "# HELP {name} {help}")?;
+    writeln!(formatter, "# TYPE {name} {kind}")?;
+    writeln!(formatter, "{name} {value}")
+}
+
+fn write_unhandled_commands(
+    formatter: &mut fmt::Formatter<'_>,
+    commands: &BTreeMap<&'static str, u64>,
+) -> fmt::Result {
+    let name = "luanti_unhandled_commands_total";
+    writeln!(
+        formatter,
+        "# HELP {name} Number of client commands dropped as they arrived at an unexpected time"
+    )?;
+    writeln!(formatter, "# TYPE {name} counter")?;
+    for (command, count) in commands {
+        writeln!(formatter, "{name}{{command=\"{command}\"}} {count}")?;
+    }
+    Ok(())
+}
+
+fn write_timing(
+    formatter: &mut fmt::Formatter<'_>,
+    name: &str,
+    help: &str,
+    timing: &Timing,
+) -> fmt::Result {
+    writeln!(formatter, "# HELP {name} {help}")?;
+    writeln!(formatter, "# TYPE {name} summary")?;
+    writeln!(formatter, "{name}_sum {}", timing.sum().as_secs_f64())?;
+    writeln!(formatter, "{name}_count {}", timing.count())
+}
+
+/// Writes the statistics of the compressed payloads of all connections, labeled by their type.
+fn write_compression(formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let payloads = compression_stats();
+    write_payloads(
+        formatter,
+        "luanti_compressed_payloads_total",
+        "Number of compressed command payloads",
+        &payloads,
+        |stats| stats.count.to_string(),
+    )?;
+    write_payloads(
+        formatter,
+        "luanti_compression_input_bytes_total",
+        "Size of the command payloads before compression",
+        &payloads,
+        |stats| stats.uncompressed_bytes.to_string(),
+    )?;
+    write_payloads(
+        formatter,
+        "luanti_compression_output_bytes_total",
+        "Size of the command payloads after compression",
+        &payloads,
+        |stats| stats.compressed_bytes.to_string(),
+    )?;
+    write_payloads(
+        formatter,
+        "luanti_compression_seconds_total",
+        "Time spent compressing command payloads",
+        &payloads,
+        |stats| stats.duration.as_secs_f64().to_string(),
+    )
+}
+
+fn write_payloads(
+    formatter: &mut fmt::Formatter<'_>,
+    name: &str,
+    help: &str,
+    payloads: &BTreeMap<&str, CompressionStats>,
+    value: fn(&CompressionStats) -> String,
+) -> fmt::Result {
+    writeln!(formatter, "# HELP {name} {help}")?;
+    writeln!(formatter, "# TYPE {name} counter")?;
+    for (payload, stats) in payloads {
+        writeln!(
+            formatter,
+            "{name}{{payload=\"{payload}\"}} {}",
+            value(stats)
+        )?;
+    }
+    Ok(())
+}
+
+/// Serves the metrics at [`METRICS_PATH`] via HTTP until the listener fails.
+///
+/// This is a minimal HTTP/1.x implementation meant for being scraped by Prometheus only.
+///
+/// # Errors
+///
+/// Fails if the address cannot be bound or accepting connections fails.
+pub async fn serve(metrics: ServerMetrics, bind_addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(bind_addr).await?;
+    info!("serving metrics at http://{bind_addr}{METRICS_PATH}");
+    loop {
+        let (stream, remote_addr) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(error) = respond(stream, &metrics).await {
+                debug!("failed to serve metrics to {remote_addr}: {error}");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &ServerMetrics) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            anyhow::bail!("request is too large");
+        }
+        let length = stream.read(&mut buffer).await?;
+        if length == 0 {
+            anyhow::bail!("connection closed before the request was complete");
+        }
+        request.extend_from_slice(buffer.get(..length).unwrap_or_default());
+    }
+
+    let request_line = request
+        .split(|&byte| byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut parts = request_line.split(|&byte| byte == b' ');
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(path)) if path == METRICS_PATH.as_bytes() => {
+            ("200 OK", metrics.to_string())
+        }
+        (Some(b"GET"), _) => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n{body}",
+        length = body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ServerMetrics;
+
+    #[test]
+    fn metrics_are_rendered() {
+        let metrics = ServerMetrics::default();
+        metrics.player_joined();
+        metrics.block_sent();
+        metrics.block_sent();
+        metrics.record_storage_load(Duration::from_millis(250));
+        metrics.record_storage_load(Duration::from_millis(250));
+        metrics.command_unhandled("RequestMedia");
+        metrics.command_unhandled("RequestMedia");
+        metrics.command_unhandled("Init");
+
+        let text = metrics.to_string();
+        assert!(
+            text.contains("# TYPE luanti_connected_players gauge\nluanti_connected_players 1\n")
+        );
+        assert!(text.contains("\nluanti_blocks_sent_total 2\n"));
+        assert!(text.contains("\nluanti_storage_load_seconds_sum 0.5\n"));
+        assert!(text.contains("\nluanti_storage_load_seconds_count 2\n"));
+        assert!(text.contains("\n# TYPE luanti_compressed_payloads_total counter\n"));
+        assert!(text.contains(
+            "\nluanti_unhandled_commands_total{command=\"Init\"} 1\n\
+             luanti_unhandled_commands_total{command=\"RequestMedia\"} 2\n"
+        ));
+    }
+}
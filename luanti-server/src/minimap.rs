@@ -0,0 +1,127 @@
+//! Helpers for configuring the minimap modes of a player
+//!
+//! Clients cycle through a list of modes when the player presses the minimap key. Without being
+//! told otherwise they use their built-in list, which [`MinimapModes::default`] mirrors; a plugin
+//! replaces it by sending the event returned by [`MinimapModes::to_event`].
+
+use luanti_protocol::commands::server_to_client::MinimapModesSpec;
+use luanti_protocol::types::{InvalidMinimapModes, MinimapMode, MinimapModeList};
+
+use crate::api::FromPluginEvent;
+
+/// The modes of the minimap a player can cycle through
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimapModes {
+    modes: Vec<MinimapMode>,
+    selected: u16,
+}
+
+impl Default for MinimapModes {
+    /// The built-in modes of the C++ client
+    fn default() -> Self {
+        Self {
+            modes: vec![
+                MinimapMode::off(),
+                MinimapMode::surface(256),
+                MinimapMode::surface(128),
+                MinimapMode::surface(64),
+                MinimapMode::radar(512),
+                MinimapMode::radar(256),
+                MinimapMode::radar(128),
+            ],
+            selected: 0,
+        }
+    }
+}
+
+impl MinimapModes {
+    /// Creates an empty list; at least one mode needs to be added before sending it.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            modes: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    /// Creates a list which only allows to hide the minimap.
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self::new().with_mode(MinimapMode::off())
+    }
+
+    /// Appends a mode.
+    #[must_use]
+    pub fn with_mode(mut self, mode: MinimapMode) -> Self {
+        self.modes.push(mode);
+        self
+    }
+
+    /// Selects the mode being shown right after the list has been sent.
+    #[must_use]
+    pub fn with_selected(mut self, index: u16) -> Self {
+        self.selected = index;
+        self
+    }
+
+    /// Returns all modes in the order the player cycles through them.
+    #[must_use]
+    pub fn modes(&self) -> &[MinimapMode] {
+        &self.modes
+    }
+
+    /// Returns the event replacing the modes of a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client couldn't show the list, e.g. because it's empty.
+    pub fn to_event(&self) -> Result<FromPluginEvent, InvalidMinimapModes> {
+        let modes = MinimapModeList::new(self.modes.clone(), self.selected);
+        modes.validate()?;
+        Ok(FromPluginEvent::MinimapModes(MinimapModesSpec { modes }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use luanti_protocol::types::{InvalidMinimapModes, MinimapMode, MinimapType};
+
+    use super::MinimapModes;
+    use crate::api::FromPluginEvent;
+
+    #[test]
+    fn modes_are_sent_after_validation() {
+        let event = MinimapModes::default().to_event().unwrap();
+        let FromPluginEvent::MinimapModes(spec) = &event else {
+            unreachable!("{event:?}");
+        };
+        assert_eq!(spec.modes.vec.len(), 7);
+        assert_eq!(
+            spec.modes.selected().unwrap().minimap_type(),
+            Some(MinimapType::Off)
+        );
+
+        let modes = MinimapModes::disabled()
+            .with_mode(MinimapMode::texture("world_map.png", 256, 2).with_label("Map"))
+            .with_selected(1);
+        assert_eq!(modes.modes().len(), 2);
+        modes.to_event().unwrap();
+
+        assert_eq!(
+            MinimapModes::new().to_event().unwrap_err(),
+            InvalidMinimapModes::Empty
+        );
+        assert_eq!(
+            MinimapModes::disabled()
+                .with_selected(1)
+                .to_event()
+                .unwrap_err(),
+            InvalidMinimapModes::SelectedOutOfRange {
+                selected: 1,
+                count: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Mod channels allow structured communication between the server and client-side mods.
+//!
+//! Clients join channels by name and every message sent to a channel is delivered to all
+//! players who joined it. A channel exists as long as at least one player is a member.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use luanti_protocol::commands::server_to_client::TCModchannelMsgSpec;
+
+use crate::client_connection::ConnectedPlayers;
+
+/// The mod channels and the players who joined them
+#[derive(Debug, Clone)]
+pub struct ModChannels {
+    /// members by channel name
+    channels: Arc<Mutex<HashMap<String, BTreeSet<String>>>>,
+    connected_players: ConnectedPlayers,
+}
+
+impl ModChannels {
+    pub(crate) fn new(connected_players: ConnectedPlayers) -> Self {
+        Self {
+            channels: Arc::default(),
+            connected_players,
+        }
+    }
+
+    /// Returns the names of all channels ordered by name.
+    #[must_use]
+    pub fn channels(&self) -> Vec<String> {
+        let mut channels: Vec<_> = self
+            .channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// Returns the names of the players who joined a channel ordered by name.
+    #[must_use]
+    pub fn members(&self, channel: &str) -> Vec<String> {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(channel)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if at least one player joined the channel.
+    #[must_use]
+    pub fn is_registered(&self, channel: &str) -> bool {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(channel)
+    }
+
+    /// Sends a message of the server to all members of a channel. Returns the number of players
+    /// the message has been delivered to.
+    #[must_use]
+    pub fn send_message(&self, channel: &str, message: &str) -> usize {
+        self.broadcast(
+            &TCModchannelMsgSpec {
+                channel_name: channel.to_owned(),
+                sender: String::new(),
+                channel_msg: message.to_owned(),
+            },
+            None,
+        )
+    }
+
+    /// Delivers a message to all members of its channel except `except`.
+    pub(crate) fn broadcast(&self, message: &TCModchannelMsgSpec, except: Option<&str>) -> usize {
+        self.members(&message.channel_name)
+            .iter()
+            .filter(|member| Some(member.as_str()) != except)
+            .filter(|member| self.connected_players.send(member, message.clone().into()))
+            .count()
+    }
+
+    /// Adds a player to a channel; returns `false` if the player already joined it.
+    pub(crate) fn join(&self, channel: &str, player: &str) -> bool {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(channel.to_owned())
+            .or_default()
+            .insert(player.to_owned())
+    }
+
+    /// Removes a player from a channel; returns `false` if the player wasn't a member.
+    pub(crate) fn leave(&self, channel: &str, player: &str) -> bool {
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(members) = channels.get_mut(channel) else {
+            return false;
+        };
+        let left = members.remove(player);
+        if members.is_empty() {
+            channels.remove(channel);
+        }
+        left
+    }
+
+    /// Removes a player from all channels, e.g. because they disconnected.
+    pub(crate) fn leave_all(&self, player: &str) {
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        channels.retain(|_, members| {
+            members.remove(player);
+            !members.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModChannels;
+    use crate::client_connection::ConnectedPlayers;
+
+    #[test]
+    fn empty_channels_are_removed() {
+        let channels = ModChannels::new(ConnectedPlayers::default());
+        assert!(channels.join("chat", "alice"));
+        assert!(!channels.join("chat", "alice"));
+        assert!(channels.join("chat", "bob"));
+        assert!(channels.join("trade", "bob"));
+        assert_eq!(channels.members("chat"), ["alice", "bob"]);
+
+        assert!(channels.leave("chat", "alice"));
+        assert!(!channels.leave("chat", "alice"));
+        channels.leave_all("bob");
+        assert!(channels.channels().is_empty());
+        assert!(!channels.is_registered("chat"));
+    }
+}
@@ -0,0 +1,385 @@
+//! Server-side validation of player movements
+//!
+//! The validation follows the anti-cheat of the C++ engine: every position update needs to be
+//! paid for with time in which the player could have covered the distance. Short bursts above the
+//! limits are tolerated by allowing players to catch up on some time in advance, which covers lag
+//! spikes.
+//!
+//! Players without the `fly` privilege may only move upwards as fast as they can jump, while
+//! flying players may move upwards as fast as horizontally. The validator has no access to the
+//! map, so collisions can't be checked and `noclip` doesn't relax any limits. Moving downwards
+//! isn't limited either, as falling players may become very fast. Sneaking doesn't lower any
+//! limits, as the crouch speed neither applies right after pressing the key nor in liquids or on
+//! climbable nodes.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use glam::{Vec2, Vec3};
+use luanti_protocol::commands::server_to_client::MovementSpec;
+
+/// positions and speeds are being transferred in tenths of a node
+const PROTOCOL_SCALE: f32 = 10.0;
+
+/// Prevents divisions by zero for players who aren't allowed to move at all
+const MIN_SPEED: f32 = 0.0001;
+
+/// speeds are being transferred with a precision of a thousandth of a node per second
+const SPEED_ROUNDING: f32 = 0.001;
+
+/// The physics a client uses as long as the server didn't send a [`MovementSpec`]
+///
+/// Like the spec itself, all values are in tenths of a node per second (squared).
+pub const DEFAULT_MOVEMENT: MovementSpec = MovementSpec {
+    acceleration_default: 30.0,
+    acceleration_air: 20.0,
+    acceleration_fast: 100.0,
+    speed_walk: 40.0,
+    speed_crouch: 13.5,
+    speed_fast: 200.0,
+    speed_climb: 30.0,
+    speed_jump: 65.0,
+    liquid_fluidity: 10.0,
+    liquid_fluidity_smooth: 5.0,
+    liquid_sink: 100.0,
+    gravity: 98.1,
+};
+
+/// Settings controlling how strict player movements are being validated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovementTolerances {
+    /// factor applied to all speed and acceleration limits
+    pub speed_factor: f32,
+    /// the amount of movement time a player may use up in advance
+    pub lag_pool: Duration,
+    /// positions failing the validation are silently dropped for this long after the player has
+    /// been moved by the server, as updates sent before the move may still be in flight
+    pub teleport_grace: Duration,
+    /// whether the speed reported by the client is checked against the acceleration limits
+    pub check_acceleration: bool,
+}
+
+impl Default for MovementTolerances {
+    fn default() -> Self {
+        Self {
+            // covers the rounding of the transferred values
+            speed_factor: 1.2,
+            lag_pool: Duration::from_secs(5),
+            teleport_grace: Duration::from_secs(2),
+            check_acceleration: true,
+        }
+    }
+}
+
+/// Statistics about the movement validation of a single player
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ViolationStats {
+    /// number of position updates which have been validated
+    pub checks: u64,
+    /// number of updates covering a larger distance than possible
+    pub speed_violations: u64,
+    /// number of updates reporting a larger change in speed than possible
+    pub acceleration_violations: u64,
+    /// number of times the player has been moved back to the last valid position
+    pub rewinds: u64,
+}
+
+/// Collects the [`ViolationStats`] of all players who have been online.
+///
+/// Clones share the same statistics.
+#[derive(Debug, Clone, Default)]
+pub struct MovementMetrics {
+    players: Arc<Mutex<HashMap<String, ViolationStats>>>,
+}
+
+impl MovementMetrics {
+    /// Returns the statistics of a player; `None` if the player has never been validated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the metrics' lock.
+    #[must_use]
+    pub fn player(&self, name: &str) -> Option<ViolationStats> {
+        self.players
+            .lock()
+            .expect("poisoned movement metrics")
+            .get(name)
+            .copied()
+    }
+
+    /// Returns the statistics of all players ordered by name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the metrics' lock.
+    #[must_use]
+    pub fn players(&self) -> Vec<(String, ViolationStats)> {
+        let mut players: Vec<_> = self
+            .players
+            .lock()
+            .expect("poisoned movement metrics")
+            .iter()
+            .map(|(name, stats)| (name.clone(), *stats))
+            .collect();
+        players.sort_unstable_by(|(name_a, _), (name_b, _)| name_a.cmp(name_b));
+        players
+    }
+
+    fn update(&self, name: &str, stats: ViolationStats) {
+        self.players
+            .lock()
+            .expect("poisoned movement metrics")
+            .insert(name.to_owned(), stats);
+    }
+}
+
+/// The result of validating a position update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Verdict {
+    /// the position is plausible
+    Accepted,
+    /// the position is implausible but the player has recently been moved by the server
+    Dropped,
+    /// the position is implausible; the player needs to be moved back to the given position
+    Rewind(Vec3),
+}
+
+/// Validates the position updates of a single player.
+///
+/// Positions are in nodes and speeds in nodes per second.
+pub(crate) struct MovementValidator {
+    name: String,
+    tolerances: MovementTolerances,
+    physics: MovementSpec,
+    metrics: MovementMetrics,
+    stats: ViolationStats,
+    last_valid_position: Vec3,
+    last_speed: Vec3,
+    last_update: Instant,
+    last_teleport: Instant,
+    /// time in seconds which has been used up in advance for covering distances
+    movement_debt: f32,
+    /// time in seconds which has been used up in advance for speeding up
+    acceleration_debt: f32,
+}
+
+impl MovementValidator {
+    pub(crate) fn new(
+        name: &str,
+        position: Vec3,
+        tolerances: MovementTolerances,
+        metrics: MovementMetrics,
+        now: Instant,
+    ) -> Self {
+        let stats = metrics.player(name).unwrap_or_default();
+        Self {
+            name: name.to_owned(),
+            tolerances,
+            physics: DEFAULT_MOVEMENT,
+            metrics,
+            stats,
+            last_valid_position: position,
+            last_speed: Vec3::ZERO,
+            last_update: now,
+            last_teleport: now,
+            movement_debt: 0.0,
+            acceleration_debt: 0.0,
+        }
+    }
+
+    /// Replaces the physics after a new [`MovementSpec`] has been sent to the client.
+    pub(crate) fn set_physics(&mut self, physics: MovementSpec) {
+        self.physics = physics;
+    }
+
+    /// Accepts a position the server moved the player to.
+    pub(crate) fn teleport(&mut self, position: Vec3, now: Instant) {
+        self.last_valid_position = position;
+        self.last_speed = Vec3::ZERO;
+        self.last_teleport = now;
+        self.movement_debt = 0.0;
+        self.acceleration_debt = 0.0;
+    }
+
+    /// Validates a position update of the client.
+    pub(crate) fn check(
+        &mut self,
+        position: Vec3,
+        speed: Vec3,
+        privileges: &[String],
+        now: Instant,
+    ) -> Verdict {
+        let has = |privilege: &str| privileges.iter().any(|granted| granted == privilege);
+        let fast = has("fast");
+        let fly = has("fly");
+
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f32();
+        self.last_update = now;
+        self.movement_debt = (self.movement_debt - elapsed).max(0.0);
+        self.acceleration_debt = (self.acceleration_debt - elapsed).max(0.0);
+        self.stats.checks += 1;
+        let lag_pool = self.tolerances.lag_pool.as_secs_f32();
+
+        let physics = &self.physics;
+        let factor = self.tolerances.speed_factor / PROTOCOL_SCALE;
+        let max_horizontal = (if fast {
+            physics.speed_fast
+        } else {
+            physics.speed_walk
+        } * factor)
+            .max(MIN_SPEED);
+        // the engine doubles the jump speed to cover bouncy nodes
+        let max_jump = (physics.speed_jump * 2.0).max(physics.speed_climb) * factor;
+        let max_upwards = if fly {
+            max_jump.max(max_horizontal)
+        } else {
+            max_jump
+        }
+        .max(MIN_SPEED);
+
+        let distance = position - self.last_valid_position;
+        let movement_time =
+            (distance.x.hypot(distance.z) / max_horizontal).max(distance.y.max(0.0) / max_upwards);
+        let too_fast = self.movement_debt + movement_time > lag_pool;
+
+        let max_acceleration = if fast {
+            physics.acceleration_fast
+        } else {
+            physics.acceleration_default.max(physics.acceleration_air)
+        } * factor;
+        let speed_gain = Vec2::new(speed.x, speed.z).length()
+            - Vec2::new(self.last_speed.x, self.last_speed.z).length();
+        let acceleration_time =
+            (speed_gain - SPEED_ROUNDING).max(0.0) / max_acceleration.max(MIN_SPEED);
+        let too_much_acceleration = self.tolerances.check_acceleration
+            && self.acceleration_debt + acceleration_time > lag_pool;
+
+        if too_fast {
+            self.stats.speed_violations += 1;
+        }
+        if too_much_acceleration {
+            self.stats.acceleration_violations += 1;
+        }
+
+        let verdict = if !too_fast && !too_much_acceleration {
+            self.movement_debt += movement_time;
+            self.acceleration_debt += acceleration_time;
+            self.last_valid_position = position;
+            self.last_speed = speed;
+            Verdict::Accepted
+        } else if now.saturating_duration_since(self.last_teleport) < self.tolerances.teleport_grace
+        {
+            Verdict::Dropped
+        } else {
+            self.stats.rewinds += 1;
+            self.teleport(self.last_valid_position, now);
+            Verdict::Rewind(self.last_valid_position)
+        };
+        self.metrics.update(&self.name, self.stats);
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use glam::Vec3;
+
+    use super::{MovementMetrics, MovementTolerances, MovementValidator, Verdict};
+
+    /// Moves a player with a constant velocity in steps of 100 ms and returns the first verdict
+    /// which isn't `Accepted`.
+    fn travel(
+        validator: &mut MovementValidator,
+        privileges: &[&str],
+        start: Instant,
+        velocity: Vec3,
+        steps: u16,
+    ) -> Option<Verdict> {
+        let privileges: Vec<String> = privileges
+            .iter()
+            .map(|privilege| (*privilege).to_owned())
+            .collect();
+        (1..=steps).find_map(|step| {
+            let time = f32::from(step) / 10.0;
+            let verdict = validator.check(
+                velocity * time,
+                velocity,
+                &privileges,
+                start + Duration::from_secs_f32(time),
+            );
+            (verdict != Verdict::Accepted).then_some(verdict)
+        })
+    }
+
+    fn validator(now: Instant) -> MovementValidator {
+        MovementValidator::new(
+            "player",
+            Vec3::ZERO,
+            MovementTolerances::default(),
+            MovementMetrics::default(),
+            now,
+        )
+    }
+
+    #[test]
+    fn speeding_players_are_rewound() {
+        // start after the grace period of the initial position has passed
+        let created = Instant::now();
+        let start = created + Duration::from_secs(3);
+        let mut validator = validator(created);
+        let walking = Vec3::new(4.0, 0.0, 0.0);
+        assert_eq!(travel(&mut validator, &[], start, walking, 50), None);
+
+        let verdict = validator.check(
+            Vec3::new(120.0, 0.0, 0.0),
+            walking,
+            &[],
+            start + Duration::from_secs_f32(5.1),
+        );
+        assert_eq!(verdict, Verdict::Rewind(Vec3::new(20.0, 0.0, 0.0)));
+
+        let stats = validator.metrics.player("player").unwrap_or_default();
+        assert_eq!(
+            (stats.checks, stats.speed_violations, stats.rewinds),
+            (51, 1, 1)
+        );
+    }
+
+    #[test]
+    fn violations_right_after_a_teleport_are_dropped() {
+        let start = Instant::now();
+        let mut validator = validator(start);
+        let verdict = validator.check(
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::ZERO,
+            &[],
+            start + Duration::from_millis(100),
+        );
+        assert_eq!(verdict, Verdict::Dropped);
+    }
+
+    #[test]
+    fn privileges_raise_the_limits() {
+        let start = Instant::now() + Duration::from_secs(3);
+        let fast = Vec3::new(18.0, 0.0, 0.0);
+        assert!(travel(&mut validator(start), &[], start, fast, 300).is_some());
+        assert_eq!(
+            travel(&mut validator(start), &["fast"], start, fast, 300),
+            None
+        );
+
+        let rising = Vec3::new(0.0, 20.0, 0.0);
+        assert!(travel(&mut validator(start), &["fast"], start, rising, 300).is_some());
+        assert_eq!(
+            travel(&mut validator(start), &["fast", "fly"], start, rising, 300),
+            None
+        );
+    }
+}
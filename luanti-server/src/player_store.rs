@@ -0,0 +1,128 @@
+//! Contains the `PlayerStore` trait and some implementations thereof.
+
+use anyhow::Result;
+use glam::Vec3;
+use luanti_protocol::types::{Inventory, InventoryEntry, InventoryList};
+
+pub mod file;
+
+/// The hit points of a player who hasn't been stored yet
+pub const DEFAULT_HP: u16 = 20;
+/// The breath of a player who hasn't been stored yet
+pub const DEFAULT_BREATH: u16 = 10;
+/// The privileges granted to a player who hasn't been stored yet
+pub const DEFAULT_PRIVILEGES: [&str; 5] = ["fly", "fast", "noclip", "rollback", "debug"];
+
+/// Everything about a player which outlives a single connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerData {
+    /// position of the player's feet in nodes
+    pub position: Vec3,
+    /// vertical look direction in degrees
+    pub pitch: f32,
+    /// horizontal look direction in degrees
+    pub yaw: f32,
+    /// hit points
+    pub hp: u16,
+    /// remaining breath while being under water
+    pub breath: u16,
+    /// all inventory lists of the player (e.g. `main` and `craft`)
+    pub inventory: Vec<InventoryList>,
+    /// names of all privileges granted to the player
+    pub privileges: Vec<String>,
+}
+
+impl Default for PlayerData {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            pitch: 0.0,
+            yaw: 0.0,
+            hp: DEFAULT_HP,
+            breath: DEFAULT_BREATH,
+            inventory: Vec::new(),
+            privileges: DEFAULT_PRIVILEGES.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl PlayerData {
+    /// Returns the player's inventory in the form being sent to the client.
+    #[must_use]
+    pub fn inventory(&self) -> Inventory {
+        Inventory {
+            entries: self
+                .inventory
+                .iter()
+                .cloned()
+                .map(InventoryEntry::Update)
+                .collect(),
+        }
+    }
+
+    /// Applies an inventory update like the client does.
+    ///
+    /// Lists being updated are replaced, lists being kept stay untouched and all other lists are
+    /// removed.
+    pub fn update_inventory(&mut self, update: &Inventory) {
+        let mut previous = std::mem::take(&mut self.inventory);
+        for entry in &update.entries {
+            match entry {
+                InventoryEntry::KeepList(name) => {
+                    if let Some(index) = previous.iter().position(|list| &list.name == name) {
+                        self.inventory.push(previous.swap_remove(index));
+                    }
+                }
+                InventoryEntry::Update(list) => self.inventory.push(list.clone()),
+            }
+        }
+    }
+}
+
+/// This trait needs to be implemented by a storage provider for player data
+pub trait PlayerStore: Send + Sync {
+    /// Loads the data of a player.
+    /// Returns `None`, if the player has never been stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exists but couldn't be read.
+    fn load_player(&self, name: &str) -> Result<Option<PlayerData>>;
+
+    /// Stores the data of a player, replacing any previous version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data couldn't be written.
+    fn save_player(&self, name: &str, player: &PlayerData) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use luanti_protocol::types::{Inventory, InventoryEntry, InventoryList, ItemStackUpdate};
+
+    use super::PlayerData;
+
+    fn list(name: &str, size: usize) -> InventoryList {
+        InventoryList {
+            name: name.into(),
+            width: 0,
+            items: vec![ItemStackUpdate::Empty; size],
+        }
+    }
+
+    #[test]
+    fn inventory_updates_replace_keep_and_remove_lists() {
+        let mut player = PlayerData {
+            inventory: vec![list("main", 32), list("craft", 9), list("craftpreview", 1)],
+            ..PlayerData::default()
+        };
+        player.update_inventory(&Inventory {
+            entries: vec![
+                InventoryEntry::Update(list("main", 8)),
+                InventoryEntry::KeepList("craft".into()),
+            ],
+        });
+        assert_eq!(player.inventory, vec![list("main", 8), list("craft", 9)]);
+    }
+}
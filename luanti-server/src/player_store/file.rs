@@ -0,0 +1,226 @@
+//! Contains the `FilePlayerStore`
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use glam::Vec3;
+use log::trace;
+use luanti_protocol::types::{Inventory, InventoryEntry, ProtocolContext};
+use luanti_protocol::wire::deser::{Deserialize, Deserializer};
+use luanti_protocol::wire::ser::{Serialize, Serializer, VecSerializer};
+
+use super::{PlayerData, PlayerStore};
+
+/// separates the player's attributes from the inventory
+const ARGS_END: &str = "PlayerArgsEnd";
+
+/// the engine stores positions in tenths of a node
+const POSITION_SCALE: f32 = 10.0;
+
+/// A player store which keeps one file per player within a `players`-directory.
+///
+/// The files use the format of the `files` player backend of the C++ engine. The engine keeps
+/// privileges in a separate authentication database, so they're stored as an additional `privs`
+/// attribute which the engine ignores.
+pub struct FilePlayerStore {
+    directory: PathBuf,
+}
+
+impl FilePlayerStore {
+    /// Opens the player directory of a world. The path points to a `world`-directory which will
+    /// contain the `players` directory.
+    ///
+    /// The directory is being created if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new(world_directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = world_directory.as_ref().join("players");
+        fs::create_dir_all(&directory).with_context(|| {
+            format!(
+                "failed to create player directory {directory}",
+                directory = directory.display()
+            )
+        })?;
+        Ok(Self { directory })
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        // the engine enforces the same restriction; this also prevents escaping the directory
+        if name.is_empty()
+            || !name.chars().all(|character| {
+                character.is_ascii_alphanumeric() || character == '_' || character == '-'
+            })
+        {
+            bail!("invalid player name: '{name}'");
+        }
+        Ok(self.directory.join(name))
+    }
+}
+
+impl PlayerStore for FilePlayerStore {
+    fn load_player(&self, name: &str) -> Result<Option<PlayerData>> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            trace!("player '{name}' hasn't been stored yet");
+            return Ok(None);
+        }
+        let data = fs::read(&path)?;
+        let player = decode(&data)
+            .with_context(|| format!("failed to read player file {}", path.display()))?;
+        Ok(Some(player))
+    }
+
+    fn save_player(&self, name: &str, player: &PlayerData) -> Result<()> {
+        let path = self.path(name)?;
+        let data = encode(name, player)?;
+        // write to a temporary file first so a crash cannot leave a truncated file behind
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, data)?;
+        fs::rename(&temporary, &path)?;
+        trace!("stored player '{name}'");
+        Ok(())
+    }
+}
+
+fn encode(name: &str, player: &PlayerData) -> Result<Vec<u8>> {
+    let PlayerData {
+        position,
+        pitch,
+        yaw,
+        hp,
+        breath,
+        inventory: _,
+        privileges,
+    } = player;
+    let Vec3 { x, y, z } = *position * POSITION_SCALE;
+    let args = format!(
+        "name = {name}\npitch = {pitch}\nyaw = {yaw}\nposition = ({x},{y},{z})\nhp = {hp}\nbreath = {breath}\nprivs = {privs}\n{ARGS_END}\n",
+        privs = privileges.join(",")
+    );
+
+    let mut serializer = VecSerializer::new(ProtocolContext::latest_for_send(false), 0x1000);
+    serializer.write_bytes(args.as_bytes())?;
+    Inventory::serialize(&player.inventory(), &mut serializer)?;
+    Ok(serializer.take())
+}
+
+fn decode(data: &[u8]) -> Result<PlayerData> {
+    let mut player = PlayerData::default();
+    let mut deser = Deserializer::new(ProtocolContext::latest_for_receive(false), data);
+    loop {
+        if !deser.has_remaining() {
+            bail!("missing '{ARGS_END}'");
+        }
+        let line = std::str::from_utf8(deser.take_line()?)?.trim();
+        if line == ARGS_END {
+            break;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "pitch" => player.pitch = value.parse()?,
+            "yaw" => player.yaw = value.parse()?,
+            "position" => player.position = parse_vec3(value)? / POSITION_SCALE,
+            "hp" => player.hp = value.parse()?,
+            "breath" => player.breath = value.parse()?,
+            "privs" => {
+                player.privileges = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|privilege| !privilege.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            // everything else is managed by the engine
+            _ => {}
+        }
+    }
+
+    if deser.has_remaining() {
+        let inventory = Inventory::deserialize(&mut deser)?;
+        player.inventory = inventory
+            .entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                InventoryEntry::Update(list) => Some(list),
+                InventoryEntry::KeepList(_) => None,
+            })
+            .collect();
+    }
+    Ok(player)
+}
+
+/// Parses a vector in the format `(x,y,z)`.
+fn parse_vec3(value: &str) -> Result<Vec3> {
+    let components = value
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|component| component.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let &[x, y, z] = components.as_slice() else {
+        bail!("invalid vector: '{value}'");
+    };
+    Ok(Vec3::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::Vec3;
+    use luanti_protocol::types::{InventoryList, ItemStack, ItemStackMetadata, ItemStackUpdate};
+
+    use super::{decode, encode};
+    use crate::player_store::PlayerData;
+
+    #[test]
+    fn players_survive_a_round_trip() {
+        let player = PlayerData {
+            position: Vec3::new(1.5, -20.0, 300.25),
+            pitch: -12.5,
+            yaw: 270.0,
+            hp: 7,
+            breath: 3,
+            inventory: vec![InventoryList {
+                name: "main".into(),
+                width: 0,
+                items: vec![
+                    ItemStackUpdate::Item(ItemStack {
+                        name: "basenodes:dirt".into(),
+                        count: 42,
+                        wear: 0,
+                        metadata: ItemStackMetadata {
+                            string_vars: Vec::new(),
+                        },
+                    }),
+                    ItemStackUpdate::Empty,
+                ],
+            }],
+            privileges: vec!["fly".into(), "interact".into()],
+        };
+
+        let data = encode("singleplayer", &player).unwrap();
+        let text = String::from_utf8(data.clone()).unwrap();
+        assert!(text.starts_with("name = singleplayer\n"));
+        assert!(text.contains("position = (15,-200,3002.5)\n"));
+        assert_eq!(decode(&data).unwrap(), player);
+    }
+
+    #[test]
+    fn missing_attributes_use_defaults() {
+        let player = decode(b"name = foo\nhp = 3\nPlayerArgsEnd\n").unwrap();
+        assert_eq!(
+            player,
+            PlayerData {
+                hp: 3,
+                ..PlayerData::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Throttling of frequent updates sent to each player
+//!
+//! Updates which are likely to be superseded soon (the messages of active objects, the time of
+//! day and changes of the player list) aren't sent right away but collected and sent once per
+//! [`SendRateConfig::interval`]. Positions of the same object and times of day replace their
+//! predecessors, so the bandwidth stays stable no matter how often they change. The interval is
+//! also sent to clients as `recommended_send_interval`, telling them how often to report their own
+//! position.
+
+use std::time::Duration;
+
+use luanti_protocol::commands::server_to_client::{
+    ActiveObjectMessage, ActiveObjectMessagesCommand, TimeOfDaySpec, ToClientCommand,
+    UpdatePlayerListSpec,
+};
+use luanti_protocol::types::ActiveObjectCommand;
+
+/// The default interval; the `recommended_send_interval` this server used to announce
+pub const DEFAULT_SEND_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The `typ` of an [`UpdatePlayerListSpec`] replacing the entire list
+const PLAYER_LIST_INIT: u8 = 0;
+
+/// Settings of the throttling of updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendRateConfig {
+    /// time between two batches of updates
+    pub interval: Duration,
+}
+
+impl Default for SendRateConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_SEND_INTERVAL,
+        }
+    }
+}
+
+impl SendRateConfig {
+    /// Returns the interval in seconds as being announced to clients.
+    #[must_use]
+    pub fn recommended_send_interval(&self) -> f32 {
+        self.interval.as_secs_f32()
+    }
+}
+
+/// The throttled updates of a single player which haven't been sent yet
+#[derive(Debug, Default)]
+pub(crate) struct UpdateBatch {
+    object_messages: Vec<ActiveObjectMessage>,
+    time_of_day: Option<TimeOfDaySpec>,
+    player_list: Vec<UpdatePlayerListSpec>,
+}
+
+impl UpdateBatch {
+    /// Takes a command which is about to be sent to the player. Returns the command if it isn't
+    /// being throttled and has to be sent right away.
+    pub(crate) fn push(&mut self, command: ToClientCommand) -> Option<ToClientCommand> {
+        match command {
+            ToClientCommand::ActiveObjectMessages(command) => {
+                for message in command.objects {
+                    self.push_object_message(message);
+                }
+            }
+            ToClientCommand::TimeOfDay(spec) => self.time_of_day = Some(*spec),
+            ToClientCommand::UpdatePlayerList(spec) => self.push_player_list(*spec),
+            other => return Some(other),
+        }
+        None
+    }
+
+    fn push_object_message(&mut self, message: ActiveObjectMessage) {
+        if matches!(message.data, ActiveObjectCommand::UpdatePosition(_)) {
+            // only the most recent position of an object is of interest
+            self.object_messages.retain(|pending| {
+                pending.id != message.id
+                    || !matches!(pending.data, ActiveObjectCommand::UpdatePosition(_))
+            });
+        }
+        self.object_messages.push(message);
+    }
+
+    fn push_player_list(&mut self, spec: UpdatePlayerListSpec) {
+        if spec.typ == PLAYER_LIST_INIT {
+            self.player_list.clear();
+        }
+        match self.player_list.last_mut() {
+            Some(pending) if pending.typ == spec.typ && spec.typ != PLAYER_LIST_INIT => {
+                pending.players.extend(spec.players);
+            }
+            _ => self.player_list.push(spec),
+        }
+    }
+
+    /// Returns the commands collected since the previous call.
+    pub(crate) fn flush(&mut self) -> Vec<ToClientCommand> {
+        let mut commands: Vec<ToClientCommand> = Vec::new();
+        if let Some(spec) = self.time_of_day.take() {
+            commands.push(spec.into());
+        }
+        commands.extend(self.player_list.drain(..).map(ToClientCommand::from));
+        if !self.object_messages.is_empty() {
+            commands.push(
+                ActiveObjectMessagesCommand {
+                    objects: std::mem::take(&mut self.object_messages),
+                }
+                .into(),
+            );
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use luanti_protocol::commands::server_to_client::{
+        ActiveObjectMessage, ActiveObjectMessagesCommand, HpSpec, TimeOfDaySpec, ToClientCommand,
+        UpdatePlayerListSpec,
+    };
+    use luanti_protocol::types::{
+        AOCPunched, AOCUpdatePosition, ActiveObjectCommand, TrailingBytes,
+    };
+
+    use super::UpdateBatch;
+
+    fn position(id: u16, x: f32) -> ActiveObjectMessage {
+        ActiveObjectMessage {
+            id,
+            data: ActiveObjectCommand::UpdatePosition(AOCUpdatePosition {
+                position: Vec3::new(x, 0.0, 0.0),
+                velocity: Vec3::ZERO,
+                acceleration: Vec3::ZERO,
+                rotation: Vec3::ZERO,
+                do_interpolate: true,
+                is_end_position: false,
+                update_interval: 0.1,
+            }),
+        }
+    }
+
+    #[test]
+    fn updates_are_batched_and_superseded() {
+        let mut batch = UpdateBatch::default();
+        let hp = ToClientCommand::from(HpSpec {
+            hp: 20,
+            damage_effect: None,
+            extra: TrailingBytes::default(),
+        });
+        assert_eq!(batch.push(hp.clone()), Some(hp));
+
+        let punched = ActiveObjectMessage {
+            id: 1,
+            data: ActiveObjectCommand::Punched(AOCPunched { hp: 3 }),
+        };
+        for x in [1.0, 2.0] {
+            let command = ActiveObjectMessagesCommand {
+                objects: vec![position(1, x), position(2, x)],
+            };
+            assert_eq!(batch.push(command.into()), None);
+        }
+        let command = ActiveObjectMessagesCommand {
+            objects: vec![punched.clone()],
+        };
+        assert_eq!(batch.push(command.into()), None);
+        for time_of_day in [100, 200] {
+            let spec = TimeOfDaySpec {
+                time_of_day,
+                time_speed: Some(72.0),
+                extra: TrailingBytes::default(),
+            };
+            assert_eq!(batch.push(spec.into()), None);
+        }
+        for (typ, player) in [(1, "alice"), (1, "bob"), (2, "carol")] {
+            let spec = UpdatePlayerListSpec {
+                typ,
+                players: vec![player.to_owned()],
+            };
+            assert_eq!(batch.push(spec.into()), None);
+        }
+
+        let flushed = batch.flush();
+        assert_eq!(
+            flushed,
+            [
+                TimeOfDaySpec {
+                    time_of_day: 200,
+                    time_speed: Some(72.0),
+                    extra: TrailingBytes::default(),
+                }
+                .into(),
+                UpdatePlayerListSpec {
+                    typ: 1,
+                    players: vec!["alice".to_owned(), "bob".to_owned()],
+                }
+                .into(),
+                UpdatePlayerListSpec {
+                    typ: 2,
+                    players: vec!["carol".to_owned()],
+                }
+                .into(),
+                ActiveObjectMessagesCommand {
+                    objects: vec![position(1, 2.0), position(2, 2.0), punched],
+                }
+                .into(),
+            ]
+        );
+        assert!(batch.flush().is_empty());
+    }
+}
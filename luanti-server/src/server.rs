@@ -3,24 +3,99 @@
 use crate::MediaRegistry;
 use crate::api::{FromPluginEvent, ToPluginEvent};
 use crate::authentication::Authenticator;
-use crate::client_connection::ClientConnection;
+use crate::client_connection::{
+    ClientConnection, ConnectedPlayers, ConnectionConfig, PluginEvents, ServerContext,
+};
+use crate::clock::WorldClock;
+use crate::connections::Connections;
+use crate::flood::FloodConfig;
+use crate::formspec::node_forms::NodeForms;
+use crate::health::{FixedSpawnPoint, HealthConfig, HealthRules, NodeHazards, SpawnPointProvider};
+use crate::inventory::InventoryManager;
+use crate::load_budget::LoadBudget;
+use crate::metrics::ServerMetrics;
+use crate::mod_channels::ModChannels;
+use crate::movement::{MovementMetrics, MovementTolerances};
+use crate::player_store::PlayerStore;
+use crate::send_rate::SendRateConfig;
+use crate::status::{ServerInfo, ServerStatusProvider};
+use crate::world::content_id_mapper::ContentIdMapper;
 use crate::world::map_block_router::ToRouterMessage;
-use log::info;
+use crate::world::unknown_nodes::{UnknownNodeFilter, UnknownNodes};
+use crate::world::view_tracker::{InterestSnapshots, ViewConfig};
+use flexstr::SharedStr;
+use glam::Vec3;
+use log::{info, warn};
+use luanti_core::ContentId;
 use luanti_protocol::LuantiServer;
+use luanti_protocol::commands::server_to_client::{CsmRestrictions, DisconnectReason};
 use luanti_protocol::types::NodeDefManager;
+use luanti_protocol::versions::{
+    MAX_PROTOCOL_VERSION, ZSTD_PROTOCOL_VERSION, clamp_protocol_versions,
+};
+use luanti_protocol::wire::compression::CompressionConfig;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
 
+/// The protocol versions being accepted unless configured otherwise; all of the ones implemented
+/// by [`luanti_protocol`] back to Luanti 5.5.0
+///
+/// Older clients can't read the map blocks the server sends, see
+/// [`luanti_protocol::versions::ZSTD_PROTOCOL_VERSION`].
+pub const DEFAULT_PROTOCOL_VERSIONS: RangeInclusive<u16> =
+    ZSTD_PROTOCOL_VERSION..=MAX_PROTOCOL_VERSION;
+
+/// The number of players being online at the same time unless configured otherwise; the default
+/// of the C++ engine
+pub const DEFAULT_MAX_CLIENTS: usize = 15;
+
 /// A server providing access to a single Luanti world
 pub struct LuantiWorldServer {
     /// used to accept connection from clients
     bind_addr: SocketAddr,
+    /// the protocol versions clients may use
+    protocol_versions: RangeInclusive<u16>,
     verbosity: u8,
     runner: Option<JoinHandle<()>>,
+    /// keeps sending the time of day to all players while the server is running
+    clock_broadcasts: Option<JoinHandle<()>>,
     node_def: Arc<NodeDefManager>,
     media: Arc<MediaRegistry>,
+    load_budget: Arc<LoadBudget>,
+    player_store: Option<Arc<dyn PlayerStore>>,
+    movement_tolerances: Option<MovementTolerances>,
+    movement_metrics: MovementMetrics,
+    view_config: ViewConfig,
+    send_rate: SendRateConfig,
+    flood_config: FloodConfig,
+    health_config: HealthConfig,
+    /// replaces nodes without a definition in map blocks being sent
+    unknown_node: ContentId,
+    /// used to name the nodes without a definition
+    content_ids: Option<Arc<ContentIdMapper>>,
+    unknown_nodes: UnknownNodes,
+    spawn_points: Arc<dyn SpawnPointProvider>,
+    node_forms: NodeForms,
+    max_clients: usize,
+    motd: SharedStr,
+    server_info: ServerInfo,
+    /// set by [`Self::start`]
+    started: Arc<OnceLock<Instant>>,
+    csm_restrictions: CsmRestrictions,
+    /// the seed of the world; sent to clients which use it for client-side effects
+    map_seed: u64,
+    compression: CompressionConfig,
+    connected_players: ConnectedPlayers,
+    connections: Connections,
+    mod_channels: ModChannels,
+    clock: WorldClock,
+    inventories: InventoryManager,
+    interest_snapshots: InterestSnapshots,
+    metrics: ServerMetrics,
     plugin_event_sender: UnboundedSender<ToPluginEvent>,
     plugin_event_receiver: Option<UnboundedReceiver<FromPluginEvent>>,
 }
@@ -36,17 +111,281 @@ impl LuantiWorldServer {
         plugin_event_sender: UnboundedSender<ToPluginEvent>,
         plugin_event_receiver: UnboundedReceiver<FromPluginEvent>,
     ) -> Self {
+        let connected_players = ConnectedPlayers::default();
         Self {
             bind_addr,
+            protocol_versions: DEFAULT_PROTOCOL_VERSIONS,
             verbosity,
             runner: None,
+            clock_broadcasts: None,
             node_def,
             media,
+            load_budget: Arc::new(LoadBudget::default()),
+            player_store: None,
+            movement_tolerances: Some(MovementTolerances::default()),
+            movement_metrics: MovementMetrics::default(),
+            view_config: ViewConfig::default(),
+            send_rate: SendRateConfig::default(),
+            flood_config: FloodConfig::default(),
+            health_config: HealthConfig::default(),
+            unknown_node: ContentId::UNKNOWN,
+            content_ids: None,
+            unknown_nodes: UnknownNodes::default(),
+            spawn_points: Arc::new(FixedSpawnPoint(Vec3::ZERO)),
+            node_forms: NodeForms::new(),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            motd: SharedStr::empty(),
+            server_info: ServerInfo::default(),
+            started: Arc::new(OnceLock::new()),
+            csm_restrictions: CsmRestrictions::default(),
+            map_seed: 0,
+            compression: CompressionConfig::default(),
+            mod_channels: ModChannels::new(connected_players.clone()),
+            clock: WorldClock::new(connected_players.clone()),
+            inventories: InventoryManager::new(connected_players.clone()),
+            connected_players,
+            connections: Connections::default(),
+            interest_snapshots: InterestSnapshots::default(),
+            metrics: ServerMetrics::default(),
             plugin_event_sender,
             plugin_event_receiver: Some(plugin_event_receiver),
         }
     }
 
+    /// Sets the range of protocol versions clients may connect with; the highest version
+    /// supported by both sides is used for each connection. Defaults to
+    /// [`DEFAULT_PROTOCOL_VERSIONS`].
+    ///
+    /// Versions the server can't talk are dropped from the range, see
+    /// [`DEFAULT_PROTOCOL_VERSIONS`].
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range doesn't contain any supported version.
+    pub fn set_protocol_versions(&mut self, protocol_versions: RangeInclusive<u16>) {
+        let supported = clamp_protocol_versions(&protocol_versions)
+            .map(|supported| (*supported.start()).max(ZSTD_PROTOCOL_VERSION)..=*supported.end())
+            .filter(|supported| !supported.is_empty());
+        assert!(
+            supported.is_some(),
+            "the range of protocol versions {protocol_versions:?} doesn't contain any of {DEFAULT_PROTOCOL_VERSIONS:?}"
+        );
+        if let Some(supported) = supported {
+            if supported != protocol_versions {
+                warn!("protocol versions {protocol_versions:?} are limited to {supported:?}");
+            }
+            self.protocol_versions = supported;
+        }
+    }
+
+    /// Replaces the byte budget shared by all clients while loading definitions and media.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_load_budget(&mut self, load_budget: LoadBudget) {
+        self.load_budget = Arc::new(load_budget);
+    }
+
+    /// Sets the storage used to restore players when they join and to save them periodically and
+    /// when they leave. Without a store every player starts from scratch.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_player_store(&mut self, player_store: Arc<dyn PlayerStore>) {
+        self.player_store = Some(player_store);
+    }
+
+    /// Sets how strict the movements of players are being validated. Players moving faster than
+    /// allowed are moved back to their last valid position. `None` disables the validation.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_movement_tolerances(&mut self, movement_tolerances: Option<MovementTolerances>) {
+        self.movement_tolerances = movement_tolerances;
+    }
+
+    /// Sets the view range and the number of map blocks being sent to each player at once.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_view_config(&mut self, view_config: ViewConfig) {
+        self.view_config = view_config;
+    }
+
+    /// Sets how often frequent updates like the positions of objects are being sent to each
+    /// player.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_send_rate_config(&mut self, send_rate: SendRateConfig) {
+        self.send_rate = send_rate;
+    }
+
+    /// Sets how many chat messages and interactions players may send before being muted or kicked.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_flood_config(&mut self, flood_config: FloodConfig) {
+        self.flood_config = flood_config;
+    }
+
+    /// Sets whether and how much players can be hurt.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_health_config(&mut self, health_config: HealthConfig) {
+        self.health_config = health_config;
+    }
+
+    /// Sets the node which replaces all nodes without a definition in the map blocks being sent,
+    /// e.g. nodes of removed mods. Defaults to [`ContentId::UNKNOWN`] which is built into the
+    /// client.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_unknown_node(&mut self, unknown_node: ContentId) {
+        self.unknown_node = unknown_node;
+    }
+
+    /// Sets the content ids of the world, which are used to name the nodes without a definition
+    /// in the log and in [`Self::unknown_nodes`].
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_content_ids(&mut self, content_ids: Arc<ContentIdMapper>) {
+        self.content_ids = Some(content_ids);
+    }
+
+    /// Sets where players respawn after dying. Defaults to the origin of the world.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_spawn_point_provider(&mut self, spawn_points: Arc<dyn SpawnPointProvider>) {
+        self.spawn_points = spawn_points;
+    }
+
+    /// Sets the forms being shown when players right-click nodes.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_node_forms(&mut self, node_forms: NodeForms) {
+        self.node_forms = node_forms;
+    }
+
+    /// Sets the number of players being online at the same time. Further clients are being
+    /// rejected. Defaults to [`DEFAULT_MAX_CLIENTS`].
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_max_clients(&mut self, max_clients: usize) {
+        self.max_clients = max_clients;
+    }
+
+    /// Sets the message of the day being shown to every player who joins. An empty message isn't
+    /// being shown at all.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_motd(&mut self, motd: impl Into<SharedStr>) {
+        self.motd = motd.into();
+    }
+
+    /// Sets the name and description of the server as shown by the server list.
+    ///
+    /// Must be called before [`Self::status_provider`] to take effect.
+    pub fn set_server_info(&mut self, server_info: ServerInfo) {
+        self.server_info = server_info;
+    }
+
+    /// Sets which features client-side mods of the players may use. Defaults to the restrictions
+    /// of the C++ engine, which allow loading client mods but nothing else.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_csm_restrictions(&mut self, csm_restrictions: CsmRestrictions) {
+        self.csm_restrictions = csm_restrictions;
+    }
+
+    /// Sets the seed of the world which is announced to the players; this should be the seed the
+    /// map generator uses, see [`crate::world::map_meta::world_seed`]. Defaults to `0`.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_map_seed(&mut self, map_seed: u64) {
+        self.map_seed = map_seed;
+    }
+
+    /// Returns the seed of the world which is announced to the players.
+    #[must_use]
+    pub fn map_seed(&self) -> u64 {
+        self.map_seed
+    }
+
+    /// Sets how compressed payloads like the node definitions and map blocks are being compressed
+    /// for all clients, e.g. [`CompressionConfig::ADAPTIVE`]. Defaults to the fixed levels of the
+    /// C++ engine.
+    ///
+    /// Must be called before [`Self::start`] to take effect.
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = compression;
+    }
+
+    /// Returns the nodes without a definition which have been found in the map blocks sent so far.
+    #[must_use]
+    pub fn unknown_nodes(&self) -> UnknownNodes {
+        self.unknown_nodes.clone()
+    }
+
+    /// Returns the statistics about the movement validation of all players.
+    #[must_use]
+    pub fn movement_metrics(&self) -> MovementMetrics {
+        self.movement_metrics.clone()
+    }
+
+    /// Disconnects a player, telling the client why. Returns `false` if the player isn't online.
+    ///
+    /// The client is asked to offer reconnecting if `reconnect` is set.
+    #[must_use]
+    pub fn kick(&self, player: &str, reason: DisconnectReason, reconnect: bool) -> bool {
+        self.connected_players.kick(player, reason, reconnect)
+    }
+
+    /// Returns the state of all client connections, e.g. for admin tools.
+    #[must_use]
+    pub fn connections(&self) -> Connections {
+        self.connections.clone()
+    }
+
+    /// Returns the registry of mod channels, e.g. for sending messages to their members.
+    #[must_use]
+    pub fn mod_channels(&self) -> ModChannels {
+        self.mod_channels.clone()
+    }
+
+    /// Returns the clock of the world, e.g. for changing the time of day.
+    #[must_use]
+    pub fn clock(&self) -> WorldClock {
+        self.clock.clone()
+    }
+
+    /// Returns the detached inventories, e.g. for creating chests or trade interfaces.
+    #[must_use]
+    pub fn inventories(&self) -> InventoryManager {
+        self.inventories.clone()
+    }
+
+    /// Returns a provider of the current state of the server, e.g. for announcing it to the server
+    /// list or answering the `/status` command. It reflects the configuration at the time of this
+    /// call.
+    #[must_use]
+    pub fn status_provider(&self) -> ServerStatusProvider {
+        ServerStatusProvider::new(
+            self.server_info.clone(),
+            Arc::clone(&self.started),
+            self.protocol_versions.clone(),
+            self.max_clients,
+            self.health_config.enable_damage,
+            self.connected_players.clone(),
+            self.connections.clone(),
+        )
+    }
+
+    /// Returns the statistics of the server, e.g. for serving them via
+    /// [`metrics::serve`](crate::metrics::serve). Pass them to the
+    /// [`MapBlockProvider`](crate::world::map_block_provider::MapBlockProvider) to include the
+    /// statistics about the world.
+    #[must_use]
+    pub fn metrics(&self) -> ServerMetrics {
+        self.metrics.clone()
+    }
+
     /// Starts a runner task for the server which listens on the configured socket for incoming
     /// connections and then return immediately.
     ///
@@ -59,20 +398,56 @@ impl LuantiWorldServer {
         block_interest_sender: UnboundedSender<ToRouterMessage>,
     ) {
         assert!(self.runner.is_none(), "server is already running");
+        self.started.get_or_init(Instant::now);
 
-        let bind_addr = self.bind_addr;
-        let verbosity = self.verbosity;
-        let node_def_clone = Arc::clone(&self.node_def);
-        let media_clone = Arc::clone(&self.media);
+        let config = Arc::new(ConnectionConfig {
+            protocol_versions: self.protocol_versions.clone(),
+            verbosity: self.verbosity,
+            movement_tolerances: self.movement_tolerances,
+            view_config: self.view_config,
+            send_rate: self.send_rate,
+            flood_config: self.flood_config,
+            max_clients: self.max_clients,
+            motd: self.motd.clone(),
+            csm_restrictions: self.csm_restrictions,
+            map_seed: self.map_seed,
+            compression: self.compression,
+        });
+        let context = ServerContext {
+            block_interest_sender,
+            node_def: Arc::clone(&self.node_def),
+            media: Arc::clone(&self.media),
+            load_budget: Arc::clone(&self.load_budget),
+            player_store: self.player_store.clone(),
+            movement_metrics: self.movement_metrics.clone(),
+            health_rules: HealthRules {
+                config: self.health_config,
+                spawn_points: Arc::clone(&self.spawn_points),
+                hazards: Arc::new(NodeHazards::new(&self.node_def)),
+            },
+            node_forms: Arc::new(std::mem::take(&mut self.node_forms).resolve(&self.node_def)),
+            unknown_node_filter: Arc::new(UnknownNodeFilter::new(
+                &self.node_def,
+                self.unknown_node,
+                self.content_ids.clone(),
+                self.unknown_nodes.clone(),
+            )),
+            plugin_event_sender: self.plugin_event_sender.clone(),
+            plugin_events: PluginEvents::new(self.plugin_event_receiver.take().unwrap()),
+            connected_players: self.connected_players.clone(),
+            connections: self.connections.clone(),
+            mod_channels: self.mod_channels.clone(),
+            clock: self.clock.clone(),
+            inventories: self.inventories.clone(),
+            interest_snapshots: self.interest_snapshots.clone(),
+            metrics: self.metrics.clone(),
+        };
+        self.clock_broadcasts = Some(context.clock.spawn_broadcasts());
         let runner = tokio::spawn(Self::accept_connections(
-            bind_addr,
+            self.bind_addr,
             authenticator,
-            verbosity,
-            block_interest_sender,
-            node_def_clone,
-            media_clone,
-            self.plugin_event_sender.clone(),
-            self.plugin_event_receiver.take().unwrap(),
+            config,
+            context,
         ));
         self.runner.replace(runner);
     }
@@ -80,12 +455,8 @@ impl LuantiWorldServer {
     async fn accept_connections<Auth: Authenticator + 'static>(
         bind_addr: SocketAddr,
         authenticator: Auth,
-        verbosity: u8,
-        block_interest_sender: UnboundedSender<ToRouterMessage>,
-        node_def: Arc<NodeDefManager>,
-        media: Arc<MediaRegistry>,
-        plugin_event_sender: UnboundedSender<ToPluginEvent>,
-        from_plugin_event_receiver: UnboundedReceiver<FromPluginEvent>,
+        config: Arc<ConnectionConfig>,
+        context: ServerContext,
     ) {
         let mut server = LuantiServer::new(bind_addr);
         let mut connection_id = 1;
@@ -101,20 +472,94 @@ impl LuantiWorldServer {
                 id,
                 connection.remote_addr()
             );
+            connection.set_compression(config.compression);
 
             ClientConnection::spawn(
                 id,
                 connection,
                 authenticator.clone(),
-                verbosity,
-                block_interest_sender.clone(),
-                Arc::clone(&node_def),
-                Arc::clone(&media),
-                plugin_event_sender.clone(),
-                from_plugin_event_receiver,
+                Arc::clone(&config),
+                &context,
             );
+        }
+    }
+}
 
-            break;
+impl Drop for LuantiWorldServer {
+    fn drop(&mut self) {
+        if let Some(clock_broadcasts) = self.clock_broadcasts.take() {
+            clock_broadcasts.abort();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::net::SocketAddr;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+
+    use luanti_protocol::LuantiClient;
+    use luanti_protocol::commands::client_to_server::InitSpec;
+    use luanti_protocol::commands::server_to_client::ToClientCommand;
+    use luanti_protocol::types::NodeDefManager;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::{DEFAULT_PROTOCOL_VERSIONS, LuantiWorldServer};
+    use crate::MediaRegistry;
+    use crate::authentication::dummy::DummyAuthenticator;
+
+    /// Connects a client and lets it introduce itself to the server.
+    async fn connect(addr: SocketAddr, user_name: &str) -> LuantiClient {
+        let mut client = LuantiClient::connect(addr).await.unwrap();
+        client
+            .send(
+                InitSpec {
+                    serialization_ver_max: 29,
+                    supp_compr_modes: 0,
+                    min_net_proto_version: *DEFAULT_PROTOCOL_VERSIONS.start(),
+                    max_net_proto_version: *DEFAULT_PROTOCOL_VERSIONS.end(),
+                    user_name: user_name.into(),
+                }
+                .into(),
+            )
+            .unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn every_client_is_being_served() {
+        // the server doesn't tell which port it's listening on, so a free one is picked upfront
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (plugin_event_sender, _to_plugins) = unbounded_channel();
+        let (_from_plugins, plugin_event_receiver) = unbounded_channel();
+        let mut server = LuantiWorldServer::new(
+            addr,
+            0,
+            Arc::new(NodeDefManager {
+                content_features: Vec::new(),
+            }),
+            Arc::new(MediaRegistry::default()),
+            plugin_event_sender,
+            plugin_event_receiver,
+        );
+        let (block_interest_sender, _block_interests) = unbounded_channel();
+        server.start(DummyAuthenticator, block_interest_sender);
+
+        let mut alice = connect(addr, "alice").await;
+        let mut bob = connect(addr, "bob").await;
+        assert!(matches!(
+            alice.recv().await.unwrap(),
+            ToClientCommand::Hello(_)
+        ));
+        assert!(matches!(
+            bob.recv().await.unwrap(),
+            ToClientCommand::Hello(_)
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+//! Announcements of the server to the public server list
+//!
+//! Like the C++ engine, the server announces itself when it starts and updates its entry
+//! periodically. The server list drops entries which haven't been updated for a while; stopping
+//! the announcements sends [`AnnounceAction::Delete`], which removes the entry right away.
+//!
+//! Sending the announcements requires the `serverlist` feature.
+
+use std::time::Duration;
+
+#[cfg(feature = "serverlist")]
+use tokio::{sync::oneshot, task::JoinHandle};
+
+#[cfg(feature = "serverlist")]
+use crate::status::ServerStatusProvider;
+use crate::status::{JsonObject, ServerStatus};
+
+/// The server list of the C++ engine; `serverlist_url`
+pub const DEFAULT_SERVERLIST_URL: &str = "https://servers.luanti.org";
+
+/// What the server list is being told
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceAction {
+    /// the server has been started
+    Start,
+    /// the server is still running
+    Update,
+    /// the server is shutting down
+    Delete,
+}
+
+impl AnnounceAction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// Settings of the announcements
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceConfig {
+    /// the server list without the `/announce` path
+    pub url: String,
+    /// the address clients shall connect to; the server list uses the address the announcement
+    /// came from if this is `None`
+    pub address: Option<String>,
+    /// the port clients shall connect to
+    pub port: u16,
+    /// time between two updates
+    pub interval: Duration,
+}
+
+impl AnnounceConfig {
+    /// The default time between two updates; the one of the C++ engine
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    /// Creates settings for announcing the given port to the [`DEFAULT_SERVERLIST_URL`].
+    #[must_use]
+    pub fn new(port: u16) -> Self {
+        Self {
+            url: DEFAULT_SERVERLIST_URL.to_owned(),
+            address: None,
+            port,
+            interval: Self::DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Renders the announcement in the format of the C++ engine. The status is left out of
+    /// [`AnnounceAction::Delete`].
+    #[must_use]
+    pub fn announcement(&self, action: AnnounceAction, status: &ServerStatus) -> String {
+        let mut json = JsonObject::default();
+        json.string("action", action.name());
+        json.raw("port", self.port);
+        if let Some(address) = &self.address {
+            json.string("address", address);
+        }
+        if action != AnnounceAction::Delete {
+            status.write_json_fields(&mut json);
+        }
+        json.finish()
+    }
+
+    /// Returns the URL the announcements are being posted to.
+    #[must_use]
+    pub fn announce_url(&self) -> String {
+        format!("{}/announce", self.url.trim_end_matches('/'))
+    }
+}
+
+/// The task announcing the server; see [`spawn_announcements`]
+///
+/// Dropping this stops the announcements as well, but doesn't wait for the server list to remove
+/// the entry.
+#[cfg(feature = "serverlist")]
+#[derive(Debug)]
+pub struct Announcements {
+    /// tells the task to stop; dropping it does the same
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+#[cfg(feature = "serverlist")]
+impl Announcements {
+    /// Stops updating the entry and removes it from the server list. Completes once the server
+    /// list has been told or failed to be reached.
+    pub async fn stop(self) {
+        // the task has only finished already if it panicked, which is reported below
+        if self.stop.send(()).is_err() {
+            log::debug!("the announcements have already stopped");
+        }
+        if let Err(error) = self.task.await {
+            log::warn!("the announcements failed: {error}");
+        }
+    }
+}
+
+/// Starts a task which announces the server right away and updates its entry every
+/// [`AnnounceConfig::interval`]. Failed announcements are logged and retried with the next update.
+///
+/// Once stopped, the entry is removed from the server list if the server has been announced.
+#[cfg(feature = "serverlist")]
+#[must_use]
+pub fn spawn_announcements(config: AnnounceConfig, status: ServerStatusProvider) -> Announcements {
+    let (stop, mut stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut action = AnnounceAction::Start;
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                _ = interval.tick() => {}
+            }
+            match announce(&client, &config, action, &status.status()).await {
+                Ok(()) => {
+                    log::debug!("announced the server to {}", config.url);
+                    action = AnnounceAction::Update;
+                }
+                Err(error) => {
+                    log::warn!("failed to announce the server to {}: {error}", config.url);
+                }
+            }
+        }
+
+        // there's no entry to remove if the server has never been announced
+        if action == AnnounceAction::Update {
+            match announce(&client, &config, AnnounceAction::Delete, &status.status()).await {
+                Ok(()) => log::debug!("removed the server from {}", config.url),
+                Err(error) => {
+                    log::warn!("failed to remove the server from {}: {error}", config.url);
+                }
+            }
+        }
+    });
+    Announcements { stop, task }
+}
+
+/// Sends a single announcement to the server list.
+///
+/// # Errors
+///
+/// Returns an error if the server list cannot be reached or rejects the announcement.
+#[cfg(feature = "serverlist")]
+pub async fn announce(
+    client: &reqwest::Client,
+    config: &AnnounceConfig,
+    action: AnnounceAction,
+    status: &ServerStatus,
+) -> anyhow::Result<()> {
+    // the C++ engine sends the announcement as a multipart form
+    let form = reqwest::multipart::Form::new().text("json", config.announcement(action, status));
+    client
+        .post(config.announce_url())
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{AnnounceAction, AnnounceConfig};
+    use crate::status::{SERVER_VERSION, ServerInfo, ServerStatus};
+    #[cfg(feature = "serverlist")]
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    /// Answers every request with `200 OK` and passes on the bodies of the requests.
+    #[cfg(feature = "serverlist")]
+    async fn serve(
+        listener: TcpListener,
+        bodies: mpsc::UnboundedSender<String>,
+    ) -> std::io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            // the announcements are small enough to be complete once the closing boundary arrived
+            while !String::from_utf8_lossy(&request).trim_end().ends_with("--") {
+                let len = stream.read(&mut buf).await?;
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(buf.get(..len).unwrap_or_default());
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .await?;
+            if bodies
+                .send(String::from_utf8_lossy(&request).into_owned())
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    #[cfg(feature = "serverlist")]
+    #[tokio::test]
+    async fn stopping_removes_the_entry() {
+        #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+        use std::sync::{Arc, OnceLock};
+
+        use super::spawn_announcements;
+        use crate::status::ServerStatusProvider;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = AnnounceConfig::new(30000);
+        config.url = format!("http://{}", listener.local_addr().unwrap());
+        let (bodies_sender, mut bodies) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, bodies_sender));
+
+        let status = ServerStatusProvider::new(
+            ServerInfo::default(),
+            Arc::new(OnceLock::new()),
+            47..=47,
+            15,
+            false,
+            crate::client_connection::ConnectedPlayers::default(),
+            crate::connections::Connections::default(),
+        );
+        let announcements = spawn_announcements(config, status);
+        assert!(
+            bodies
+                .recv()
+                .await
+                .unwrap()
+                .contains(r#"{"action":"start","#)
+        );
+        announcements.stop().await;
+        assert!(
+            bodies
+                .recv()
+                .await
+                .unwrap()
+                .contains(r#"{"action":"delete","#)
+        );
+    }
+
+    #[test]
+    fn announcements_contain_the_status() {
+        let mut config = AnnounceConfig::new(30000);
+        config.url = "https://example.com/".to_owned();
+        config.address = Some("luanti.example.com".to_owned());
+        assert_eq!(config.announce_url(), "https://example.com/announce");
+
+        let status = ServerStatus {
+            info: ServerInfo {
+                name: "Rusty".to_owned(),
+                ..ServerInfo::default()
+            },
+            version: SERVER_VERSION,
+            protocol_versions: 47..=47,
+            uptime: Duration::from_secs(60),
+            players: Vec::new(),
+            max_clients: 15,
+            damage: false,
+        };
+        let start = config.announcement(AnnounceAction::Start, &status);
+        assert!(start.starts_with(
+            r#"{"action":"start","port":30000,"address":"luanti.example.com","name":"Rusty","#
+        ));
+        assert!(start.contains(r#""proto_min":47,"proto_max":47,"#));
+        assert_eq!(
+            config.announcement(AnnounceAction::Delete, &status),
+            r#"{"action":"delete","port":30000,"address":"luanti.example.com"}"#
+        );
+    }
+}
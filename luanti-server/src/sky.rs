@@ -0,0 +1,346 @@
+//! Configuration of the sky, sun, moon, stars, clouds and lighting
+//!
+//! [`SkyController`] keeps a global [`Ambiance`] along with per-player overrides and creates the
+//! commands required to bring a client up to date. [`weather::Weather`] changes the global
+//! ambiance gradually over time.
+
+pub mod weather;
+
+use std::collections::HashMap;
+
+use flexstr::SharedStr;
+use glam::Vec2;
+use luanti_protocol::commands::server_to_client::{
+    CloudParamsSpec, SetLightingSpec, SetMoonSpec, SetSkyCommand, SetStarsSpec, SetSunSpec,
+    SkyboxData, SkyboxParams,
+};
+use luanti_protocol::types::{
+    AutoExposure, Lighting, MoonParams, SColor, SkyColor, StarParams, SunParams,
+};
+
+use crate::api::FromPluginEvent;
+
+/// Everything defining the look of the sky for a player
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ambiance {
+    /// The sky box and fog
+    pub sky: SkyboxParams,
+    /// The sun
+    pub sun: SunParams,
+    /// The moon
+    pub moon: MoonParams,
+    /// The stars
+    pub stars: StarParams,
+    /// The clouds
+    pub clouds: CloudParamsSpec,
+    /// Shadows, exposure, bloom and volumetric light
+    pub lighting: Lighting,
+}
+
+impl Default for Ambiance {
+    /// The defaults used by the C++ engine
+    fn default() -> Self {
+        Self {
+            sky: default_sky(),
+            sun: SunParams {
+                visible: true,
+                texture: "sun.png".into(),
+                tonemap: "sun_tonemap.png".into(),
+                sunrise: "sunrisebg.png".into(),
+                sunrise_visible: true,
+                scale: 1.0,
+            },
+            moon: MoonParams {
+                visible: true,
+                texture: "moon.png".into(),
+                tonemap: "moon_tonemap.png".into(),
+                scale: 1.0,
+            },
+            stars: StarParams {
+                visible: true,
+                count: 1000,
+                starcolor: SColor::new(105, 235, 235, 255),
+                scale: 1.0,
+                day_opacity: Some(0.0),
+            },
+            clouds: CloudParamsSpec {
+                density: 0.4,
+                color_bright: SColor::new(229, 240, 240, 255),
+                color_ambient: SColor::new(255, 0, 0, 0),
+                height: 120.0,
+                thickness: 16.0,
+                speed: Vec2::new(0.0, -2.0),
+                color_shadow: SColor::new(255, 204, 204, 204),
+            },
+            lighting: Lighting {
+                shadow_intensity: 0.0,
+                saturation: 1.0,
+                exposure: AutoExposure {
+                    luminance_min: -3.0,
+                    luminance_max: -3.0,
+                    exposure_correction: 0.0,
+                    speed_dark_bright: 1000.0,
+                    speed_bright_dark: 1000.0,
+                    center_weight_power: 1.0,
+                },
+                volumetric_light_strength: 0.0,
+                shadow_tint: SColor::new(255, 0, 0, 0),
+                bloom_intensity: 0.05,
+                bloom_strength_factor: 1.0,
+                bloom_radius: 1.0,
+            },
+        }
+    }
+}
+
+impl Ambiance {
+    /// Returns the commands required to send the full ambiance to a client.
+    #[must_use]
+    pub fn commands(&self) -> Vec<FromPluginEvent> {
+        vec![
+            FromPluginEvent::SetSky(SetSkyCommand {
+                params: self.sky.clone(),
+            }),
+            FromPluginEvent::SetSun(SetSunSpec {
+                sun: self.sun.clone(),
+            }),
+            FromPluginEvent::SetMoon(SetMoonSpec {
+                moon: self.moon.clone(),
+            }),
+            FromPluginEvent::SetStars(SetStarsSpec {
+                stars: self.stars.clone(),
+            }),
+            FromPluginEvent::CloudParams(self.clouds.clone()),
+            FromPluginEvent::SetLighting(SetLightingSpec {
+                lighting: self.lighting.clone(),
+            }),
+        ]
+    }
+
+    /// Returns the commands required to change a client's ambiance from `previous` to `self`.
+    ///
+    /// Only parts which actually changed will be sent.
+    #[must_use]
+    pub fn diff(&self, previous: &Self) -> Vec<FromPluginEvent> {
+        let mut result = Vec::new();
+        if self.sky != previous.sky {
+            result.push(FromPluginEvent::SetSky(SetSkyCommand {
+                params: self.sky.clone(),
+            }));
+        }
+        if self.sun != previous.sun {
+            result.push(FromPluginEvent::SetSun(SetSunSpec {
+                sun: self.sun.clone(),
+            }));
+        }
+        if self.moon != previous.moon {
+            result.push(FromPluginEvent::SetMoon(SetMoonSpec {
+                moon: self.moon.clone(),
+            }));
+        }
+        if self.stars != previous.stars {
+            result.push(FromPluginEvent::SetStars(SetStarsSpec {
+                stars: self.stars.clone(),
+            }));
+        }
+        if self.clouds != previous.clouds {
+            result.push(FromPluginEvent::CloudParams(self.clouds.clone()));
+        }
+        if self.lighting != previous.lighting {
+            result.push(FromPluginEvent::SetLighting(SetLightingSpec {
+                lighting: self.lighting.clone(),
+            }));
+        }
+        result
+    }
+}
+
+/// The default sky as defined by the C++ engine
+fn default_sky() -> SkyboxParams {
+    let data = SkyboxData::Color(SkyColor {
+        day_sky: SColor::new(255, 97, 181, 245),
+        day_horizon: SColor::new(255, 144, 211, 246),
+        dawn_sky: SColor::new(255, 180, 186, 250),
+        dawn_horizon: SColor::new(255, 186, 193, 240),
+        night_sky: SColor::new(255, 0, 107, 255),
+        night_horizon: SColor::new(255, 64, 144, 255),
+        indoors: SColor::new(255, 100, 100, 100),
+    });
+    SkyboxParams {
+        bgcolor: SColor::new(255, 255, 255, 255),
+        r#type: "regular".into(),
+        clouds: true,
+        fog_sun_tint: SColor::new(255, 244, 125, 29),
+        fog_moon_tint: SColor::new(255, 127, 153, 204),
+        fog_tint_type: "default".into(),
+        data,
+        body_orbit_tilt: 0.0,
+        fog_distance: -1,
+        fog_start: -1.0,
+        fog_color: SColor::new(0, 0, 0, 0),
+    }
+}
+
+/// Manages the ambiance shown to all players.
+///
+/// Players without an override see the global ambiance. All modifying methods return the commands
+/// that need to be sent to the affected player(s).
+#[derive(Debug, Default)]
+pub struct SkyController {
+    global: Ambiance,
+    overrides: HashMap<SharedStr, Ambiance>,
+}
+
+impl SkyController {
+    /// Create a controller using the engine's default ambiance for everyone.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the ambiance shown to players without an override.
+    #[must_use]
+    pub fn global(&self) -> &Ambiance {
+        &self.global
+    }
+
+    /// Returns the ambiance the given player currently sees.
+    #[must_use]
+    pub fn effective(&self, player: &str) -> &Ambiance {
+        self.overrides.get(player).unwrap_or(&self.global)
+    }
+
+    /// Returns `true` if the given player has an individual ambiance.
+    #[must_use]
+    pub fn has_override(&self, player: &str) -> bool {
+        self.overrides.contains_key(player)
+    }
+
+    /// Returns the commands to be sent to a player who just joined.
+    #[must_use]
+    pub fn join(&self, player: &str) -> Vec<FromPluginEvent> {
+        self.effective(player).commands()
+    }
+
+    /// Modify the global ambiance.
+    ///
+    /// The returned commands must be sent to all players without an override.
+    pub fn modify_global(&mut self, modify: impl FnOnce(&mut Ambiance)) -> Vec<FromPluginEvent> {
+        let previous = self.global.clone();
+        modify(&mut self.global);
+        self.global.diff(&previous)
+    }
+
+    /// Modify the ambiance of a single player, creating an override based on the global ambiance
+    /// if there isn't one yet.
+    pub fn modify_player(
+        &mut self,
+        player: SharedStr,
+        modify: impl FnOnce(&mut Ambiance),
+    ) -> Vec<FromPluginEvent> {
+        let ambiance = self
+            .overrides
+            .entry(player)
+            .or_insert_with(|| self.global.clone());
+        let previous = ambiance.clone();
+        modify(ambiance);
+        ambiance.diff(&previous)
+    }
+
+    /// Remove the override of a player so they'll see the global ambiance again.
+    pub fn reset_player(&mut self, player: &str) -> Vec<FromPluginEvent> {
+        match self.overrides.remove(player) {
+            Some(previous) => self.global.diff(&previous),
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget about a player who left the game.
+    pub fn leave(&mut self, player: &str) {
+        self.overrides.remove(player);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flexstr::SharedStr;
+    use luanti_protocol::commands::server_to_client::{
+        SetMoonSpec, SetSkyCommand, SetStarsSpec, SetSunSpec, SkyboxData,
+    };
+    use luanti_protocol::types::SColor;
+
+    use super::{Ambiance, SkyController};
+    use crate::api::FromPluginEvent;
+
+    #[test]
+    fn defaults_match_the_engine() {
+        let ambiance = Ambiance::default();
+        let SkyboxData::Color(colors) = &ambiance.sky.data else {
+            unreachable!("the default sky is a regular one");
+        };
+        let argb = |color: SColor| color.argb();
+        assert_eq!(argb(colors.day_sky), 0xff61_b5f5);
+        assert_eq!(argb(colors.day_horizon), 0xff90_d3f6);
+        assert_eq!(argb(colors.dawn_sky), 0xffb4_bafa);
+        assert_eq!(argb(colors.dawn_horizon), 0xffba_c1f0);
+        assert_eq!(argb(colors.night_sky), 0xff00_6bff);
+        assert_eq!(argb(colors.night_horizon), 0xff40_90ff);
+        assert_eq!(argb(colors.indoors), 0xff64_6464);
+        assert_eq!(argb(ambiance.sky.fog_sun_tint), 0xfff4_7d1d);
+        assert_eq!(argb(ambiance.sky.fog_moon_tint), 0xff7f_99cc);
+        assert_eq!(argb(ambiance.stars.starcolor), 0x69eb_ebff);
+        assert_eq!(argb(ambiance.clouds.color_bright), 0xe5f0_f0ff);
+        assert_eq!(argb(ambiance.clouds.color_shadow), 0xffcc_cccc);
+        assert_eq!(ambiance.commands().len(), 6);
+    }
+
+    #[test]
+    fn changes_produce_the_commands_of_the_changed_parts() {
+        let mut sky = SkyController::new();
+        assert!(sky.modify_global(|_| {}).is_empty());
+
+        let sun = sky.modify_global(|ambiance| ambiance.sun.visible = false);
+        assert!(matches!(
+            sun.as_slice(),
+            [FromPluginEvent::SetSun(SetSunSpec { sun })] if !sun.visible
+        ));
+
+        let others = sky.modify_global(|ambiance| {
+            ambiance.sky.clouds = false;
+            ambiance.moon.scale = 2.0;
+            ambiance.stars.count = 500;
+        });
+        assert!(matches!(
+            others.as_slice(),
+            [
+                FromPluginEvent::SetSky(SetSkyCommand { params }),
+                FromPluginEvent::SetMoon(SetMoonSpec { moon }),
+                FromPluginEvent::SetStars(SetStarsSpec { stars }),
+            ] if !params.clouds && (moon.scale - 2.0).abs() < f32::EPSILON && stars.count == 500
+        ));
+    }
+
+    #[test]
+    fn players_see_their_override_until_it_is_reset() {
+        let mut sky = SkyController::new();
+        let player = SharedStr::from_borrowed("player");
+
+        let modified = sky.modify_player(player.clone(), |ambiance| ambiance.stars.visible = false);
+        assert!(matches!(
+            modified.as_slice(),
+            [FromPluginEvent::SetStars(SetStarsSpec { stars })] if !stars.visible
+        ));
+        assert!(sky.has_override(&player));
+        assert!(!sky.effective(&player).stars.visible);
+        assert!(sky.global().stars.visible);
+
+        // resetting sends what differs from the global ambiance
+        let reset = sky.reset_player(&player);
+        assert!(matches!(
+            reset.as_slice(),
+            [FromPluginEvent::SetStars(SetStarsSpec { stars })] if stars.visible
+        ));
+        assert!(!sky.has_override(&player));
+        assert!(sky.reset_player(&player).is_empty());
+    }
+}
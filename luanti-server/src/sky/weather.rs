@@ -0,0 +1,266 @@
+//! Gradual changes of the global ambiance
+//!
+//! [`Weather`] queues [`WeatherChange`]s and blends the global [`Ambiance`] of a [`SkyController`]
+//! towards each of them. Embedders call [`Weather::step`] regularly and send the returned commands
+//! to all players without an override, just like those of [`SkyController::modify_global`].
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use luanti_protocol::commands::server_to_client::SkyboxData;
+use luanti_protocol::types::{SColor, SkyColor};
+
+use super::{Ambiance, SkyController};
+use crate::api::FromPluginEvent;
+
+/// The parts of the ambiance a weather change affects; `None` keeps the current value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeatherChange {
+    /// the colors of a regular sky
+    pub sky_colors: Option<SkyColor>,
+    /// how much of the sky is covered by clouds, from `0.0` to `1.0`
+    pub cloud_density: Option<f32>,
+    /// the color of the lit side of the clouds
+    pub cloud_color: Option<SColor>,
+    /// the strength of the volumetric light, which makes the air look hazy, from `0.0` to `1.0`
+    pub fog: Option<f32>,
+}
+
+/// A change waiting for its turn
+#[derive(Debug, Clone)]
+struct ScheduledChange {
+    /// time to wait after the previous change has been completed
+    delay: Duration,
+    /// time to blend from the previous ambiance to the new one
+    duration: Duration,
+    change: WeatherChange,
+}
+
+/// The change currently being blended in
+#[derive(Debug, Clone)]
+struct Transition {
+    /// the ambiance when the transition started; only the fields of `change` are being read
+    from: Ambiance,
+    change: WeatherChange,
+    duration: Duration,
+    elapsed: Duration,
+}
+
+/// A queue of ambiance changes being applied one after another
+#[derive(Debug, Clone, Default)]
+pub struct Weather {
+    queue: VecDeque<ScheduledChange>,
+    transition: Option<Transition>,
+    /// time spent waiting for the next change
+    waited: Duration,
+}
+
+impl Weather {
+    /// Creates an empty queue.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a change which starts `delay` after all previously scheduled changes have been
+    /// completed and takes `duration` to blend in.
+    pub fn schedule(&mut self, delay: Duration, duration: Duration, change: WeatherChange) {
+        self.queue.push_back(ScheduledChange {
+            delay,
+            duration,
+            change,
+        });
+    }
+
+    /// Drops all changes which didn't start yet. A change already being blended in will be
+    /// completed.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.waited = Duration::ZERO;
+    }
+
+    /// Returns `true` if there is nothing left to do.
+    #[must_use]
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty() && self.transition.is_none()
+    }
+
+    /// Advances the weather by the given time and updates the global ambiance of `sky`.
+    ///
+    /// Only the fields controlled by the current change are being updated, so other changes of
+    /// the global ambiance made in the meantime are kept.
+    ///
+    /// The returned commands must be sent to all players without an override.
+    pub fn step(&mut self, sky: &mut SkyController, elapsed: Duration) -> Vec<FromPluginEvent> {
+        sky.modify_global(|ambiance| self.advance(ambiance, elapsed))
+    }
+
+    fn advance(&mut self, ambiance: &mut Ambiance, elapsed: Duration) {
+        let mut remaining = elapsed;
+        loop {
+            if let Some(transition) = &mut self.transition {
+                let step = remaining.min(transition.duration.saturating_sub(transition.elapsed));
+                transition.elapsed += step;
+                remaining = remaining.saturating_sub(step);
+                transition.apply(ambiance);
+                if transition.elapsed < transition.duration {
+                    break;
+                }
+                self.transition = None;
+            }
+
+            let Some(upcoming) = self.queue.front() else {
+                break;
+            };
+            let wait = upcoming.delay.saturating_sub(self.waited).min(remaining);
+            self.waited += wait;
+            remaining = remaining.saturating_sub(wait);
+            if self.waited < upcoming.delay {
+                break;
+            }
+            let Some(next) = self.queue.pop_front() else {
+                break;
+            };
+            self.waited = Duration::ZERO;
+            self.transition = Some(Transition {
+                from: ambiance.clone(),
+                change: next.change,
+                duration: next.duration,
+                elapsed: Duration::ZERO,
+            });
+        }
+    }
+}
+
+impl Transition {
+    /// Sets the fields controlled by the change to their values at the current point of the
+    /// transition.
+    fn apply(&self, ambiance: &mut Ambiance) {
+        let progress = if self.elapsed >= self.duration {
+            1.0
+        } else {
+            self.elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        };
+        let change = &self.change;
+        if let Some(to) = &change.sky_colors {
+            // sky colors only exist for a regular sky
+            ambiance.sky.r#type = "regular".into();
+            ambiance.sky.data = SkyboxData::Color(match &self.from.sky.data {
+                SkyboxData::Color(from) => SkyColor {
+                    day_sky: from.day_sky.lerp(to.day_sky, progress),
+                    day_horizon: from.day_horizon.lerp(to.day_horizon, progress),
+                    dawn_sky: from.dawn_sky.lerp(to.dawn_sky, progress),
+                    dawn_horizon: from.dawn_horizon.lerp(to.dawn_horizon, progress),
+                    night_sky: from.night_sky.lerp(to.night_sky, progress),
+                    night_horizon: from.night_horizon.lerp(to.night_horizon, progress),
+                    indoors: from.indoors.lerp(to.indoors, progress),
+                },
+                // there's nothing to blend from
+                _ => to.clone(),
+            });
+        }
+        if let Some(density) = change.cloud_density {
+            ambiance.clouds.density =
+                blend(self.from.clouds.density, density.clamp(0.0, 1.0), progress);
+        }
+        if let Some(color) = change.cloud_color {
+            ambiance.clouds.color_bright = self.from.clouds.color_bright.lerp(color, progress);
+        }
+        if let Some(fog) = change.fog {
+            ambiance.lighting.volumetric_light_strength = blend(
+                self.from.lighting.volumetric_light_strength,
+                fog.clamp(0.0, 1.0),
+                progress,
+            );
+        }
+    }
+}
+
+fn blend(from: f32, to: f32, progress: f32) -> f32 {
+    from + (to - from) * progress
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use luanti_protocol::types::SColor;
+
+    use super::{Weather, WeatherChange};
+    use crate::{api::FromPluginEvent, sky::SkyController};
+
+    #[test]
+    fn changes_are_blended_in_one_after_another() {
+        let mut sky = SkyController::new();
+        let mut weather = Weather::new();
+        let rain = WeatherChange {
+            cloud_density: Some(0.9),
+            cloud_color: Some(SColor::new(255, 100, 100, 100)),
+            fog: Some(0.5),
+            ..WeatherChange::default()
+        };
+        weather.schedule(Duration::from_secs(10), Duration::from_secs(20), rain);
+        weather.schedule(
+            Duration::ZERO,
+            Duration::ZERO,
+            WeatherChange {
+                cloud_density: Some(0.2),
+                ..WeatherChange::default()
+            },
+        );
+
+        assert!(weather.step(&mut sky, Duration::from_secs(5)).is_empty());
+        // 5 seconds into the transition from 0.4 to 0.9
+        let commands = weather.step(&mut sky, Duration::from_secs(10));
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                FromPluginEvent::CloudParams(_),
+                FromPluginEvent::SetLighting(_)
+            ]
+        ));
+        assert!((sky.global().clouds.density - 0.525).abs() < 0.001);
+        assert!((sky.global().lighting.volumetric_light_strength - 0.125).abs() < 0.001);
+
+        // completes the rain and applies the follow-up change right away
+        weather.step(&mut sky, Duration::from_secs(20));
+        assert!((sky.global().clouds.density - 0.2).abs() < 0.001);
+        assert_eq!(
+            sky.global().clouds.color_bright,
+            SColor::new(255, 100, 100, 100)
+        );
+        assert!(weather.is_idle());
+    }
+
+    #[test]
+    fn other_changes_are_kept_during_a_transition() {
+        let mut sky = SkyController::new();
+        let mut weather = Weather::new();
+        weather.schedule(
+            Duration::ZERO,
+            Duration::from_secs(10),
+            WeatherChange {
+                cloud_density: Some(1.0),
+                ..WeatherChange::default()
+            },
+        );
+        weather.step(&mut sky, Duration::from_secs(2));
+
+        // a plugin changes the clouds while the weather is changing
+        sky.modify_global(|ambiance| {
+            ambiance.clouds.height = 200.0;
+            ambiance.lighting.volumetric_light_strength = 0.7;
+        });
+        let commands = weather.step(&mut sky, Duration::from_secs(2));
+        assert!(matches!(
+            commands.as_slice(),
+            [FromPluginEvent::CloudParams(_)]
+        ));
+        assert!((sky.global().clouds.height - 200.0).abs() < 0.001);
+        assert!((sky.global().lighting.volumetric_light_strength - 0.7).abs() < 0.001);
+
+        weather.step(&mut sky, Duration::from_secs(10));
+        assert!((sky.global().clouds.density - 1.0).abs() < 0.001);
+        assert!((sky.global().clouds.height - 200.0).abs() < 0.001);
+    }
+}
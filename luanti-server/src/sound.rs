@@ -0,0 +1,378 @@
+//! Playback of sounds on clients
+//!
+//! [`SoundManager`] assigns the ids sounds are being referred to by server and client, keeps track
+//! of which players are playing which sound and forgets about sounds once they ended.
+
+use std::collections::{HashMap, HashSet};
+
+use flexstr::SharedStr;
+use glam::Vec3;
+use luanti_protocol::commands::server_to_client::{
+    EPHEMERAL_SOUND_ID, FadeSoundSpec, PlaySoundSpec, SoundLocationType, StopSoundSpec,
+};
+
+use crate::api::FromPluginEvent;
+
+/// Where a sound is being played
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[expect(variant_size_differences, reason = "all variants are small enough")]
+pub enum SoundLocation {
+    /// The sound can be heard everywhere at the same volume
+    Global,
+    /// The sound is emitted at a fixed position (in node coordinates)
+    Position(Vec3),
+    /// The sound follows an active object
+    Object(u16),
+}
+
+impl SoundLocation {
+    /// Returns the `typ`, `pos` and `object_id` fields of `PlaySoundSpec`
+    fn to_wire(self) -> (SoundLocationType, Vec3, u16) {
+        match self {
+            Self::Global => (SoundLocationType::Local, Vec3::ZERO, 0),
+            // the client expects positions in world units (10 per node)
+            Self::Position(pos) => (SoundLocationType::Position, pos * 10.0, 0),
+            // clients which know the object use its position instead
+            Self::Object(object_id) => (SoundLocationType::Object, Vec3::ZERO, object_id),
+        }
+    }
+}
+
+/// Parameters of a sound to be played
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundParams {
+    /// Name of the sound file without extension and variant number
+    pub name: String,
+    /// Volume; `1.0` is the original volume
+    pub gain: f32,
+    /// Playback speed; `1.0` is the original speed
+    pub pitch: f32,
+    /// Fade-in speed in gain per second; `0.0` disables fading
+    pub fade: f32,
+    /// Repeat the sound until it is being stopped
+    pub looped: bool,
+    /// Offset in seconds to start the playback at
+    pub start_time: f32,
+    /// Ephemeral sounds cannot be stopped or faded and don't occupy an id
+    pub ephemeral: bool,
+}
+
+impl SoundParams {
+    /// Create parameters for playing the sound once at its original volume and speed.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            gain: 1.0,
+            pitch: 1.0,
+            fade: 0.0,
+            looped: false,
+            start_time: 0.0,
+            ephemeral: false,
+        }
+    }
+
+    /// Make this sound ephemeral.
+    #[must_use]
+    pub fn ephemeral(mut self) -> Self {
+        self.ephemeral = true;
+        self
+    }
+
+    /// Repeat this sound until it is being stopped.
+    #[must_use]
+    pub fn looped(mut self) -> Self {
+        self.looped = true;
+        self
+    }
+
+    /// Set the volume of this sound.
+    #[must_use]
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+}
+
+/// Refers to a (non-ephemeral) sound played through a [`SoundManager`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle(i32);
+
+impl SoundHandle {
+    /// Returns the id as transmitted to the client.
+    #[must_use]
+    pub fn server_id(self) -> i32 {
+        self.0
+    }
+}
+
+/// Keeps track of all sounds being played.
+///
+/// All methods return the commands to be sent along with the name of the player they need to be
+/// sent to.
+#[derive(Debug)]
+pub struct SoundManager {
+    next_id: i32,
+    playing: HashMap<SoundHandle, HashSet<SharedStr>>,
+}
+
+impl Default for SoundManager {
+    fn default() -> Self {
+        Self {
+            next_id: EPHEMERAL_SOUND_ID + 1,
+            playing: HashMap::new(),
+        }
+    }
+}
+
+impl SoundManager {
+    /// Create a manager without any sounds.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Play a sound for all the given players.
+    ///
+    /// Returns `None` as handle for ephemeral sounds.
+    pub fn play(
+        &mut self,
+        players: impl IntoIterator<Item = SharedStr>,
+        params: &SoundParams,
+        location: SoundLocation,
+    ) -> (Option<SoundHandle>, Vec<(SharedStr, FromPluginEvent)>) {
+        let players: HashSet<SharedStr> = players.into_iter().collect();
+        let handle = (!params.ephemeral).then(|| self.allocate_handle());
+        let server_id = handle.map_or(EPHEMERAL_SOUND_ID, SoundHandle::server_id);
+
+        let (typ, pos, object_id) = location.to_wire();
+        let spec = PlaySoundSpec {
+            server_id,
+            spec_name: params.name.clone(),
+            spec_gain: params.gain,
+            typ,
+            pos,
+            object_id,
+            spec_loop: params.looped,
+            spec_fade: params.fade,
+            spec_pitch: params.pitch,
+            ephemeral: params.ephemeral,
+            start_time: params.start_time,
+        };
+
+        let events = players
+            .iter()
+            .map(|player| (player.clone(), FromPluginEvent::PlaySound(spec.clone())))
+            .collect();
+
+        if let Some(handle) = handle {
+            if !players.is_empty() {
+                self.playing.insert(handle, players);
+            }
+        }
+
+        (handle, events)
+    }
+
+    /// Stop a sound for all players.
+    pub fn stop(&mut self, handle: SoundHandle) -> Vec<(SharedStr, FromPluginEvent)> {
+        let Some(players) = self.playing.remove(&handle) else {
+            return Vec::new();
+        };
+        players
+            .into_iter()
+            .map(|player| {
+                let event = FromPluginEvent::StopSound(StopSoundSpec {
+                    server_id: handle.0,
+                });
+                (player, event)
+            })
+            .collect()
+    }
+
+    /// Fade a sound to the given `gain` changing by `step` per second.
+    ///
+    /// Fading to a gain of `0.0` stops the sound.
+    pub fn fade(
+        &mut self,
+        handle: SoundHandle,
+        step: f32,
+        gain: f32,
+    ) -> Vec<(SharedStr, FromPluginEvent)> {
+        let spec = FadeSoundSpec {
+            sound_id: handle.0,
+            step,
+            gain,
+        };
+        let players = if gain <= 0.0 {
+            // the client will remove the sound by itself once it has been faded out
+            self.playing.remove(&handle).unwrap_or_default()
+        } else {
+            self.playing.get(&handle).cloned().unwrap_or_default()
+        };
+        players
+            .into_iter()
+            .map(|player| (player, FromPluginEvent::FadeSound(spec.clone())))
+            .collect()
+    }
+
+    /// Returns `true` if the sound is still being played by at least one player.
+    #[must_use]
+    pub fn is_playing(&self, handle: SoundHandle) -> bool {
+        self.playing.contains_key(&handle)
+    }
+
+    /// Handle the client's notification about sounds which ended (`RemovedSounds`).
+    pub fn removed_by_client(&mut self, player: &str, ids: &[i32]) {
+        for &id in ids {
+            let handle = SoundHandle(id);
+            if let Some(players) = self.playing.get_mut(&handle) {
+                players.remove(player);
+                if players.is_empty() {
+                    self.playing.remove(&handle);
+                }
+            }
+        }
+    }
+
+    /// Forget about all sounds of a player who disconnected.
+    pub fn player_left(&mut self, player: &str) {
+        self.playing.retain(|_, players| {
+            players.remove(player);
+            !players.is_empty()
+        });
+    }
+
+    fn allocate_handle(&mut self) -> SoundHandle {
+        loop {
+            let handle = SoundHandle(self.next_id);
+            self.next_id = self
+                .next_id
+                .checked_add(1)
+                .unwrap_or(EPHEMERAL_SOUND_ID + 1);
+            if handle.0 != EPHEMERAL_SOUND_ID && !self.playing.contains_key(&handle) {
+                return handle;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flexstr::SharedStr;
+    use glam::Vec3;
+    use luanti_protocol::commands::server_to_client::{
+        EPHEMERAL_SOUND_ID, FadeSoundSpec, PlaySoundSpec, SoundLocationType, StopSoundSpec,
+    };
+
+    use super::{SoundLocation, SoundManager, SoundParams};
+    use crate::api::FromPluginEvent;
+
+    fn players() -> [SharedStr; 2] {
+        [
+            SharedStr::from_borrowed("alice"),
+            SharedStr::from_borrowed("bob"),
+        ]
+    }
+
+    #[test]
+    fn sounds_are_played_until_all_clients_removed_them() {
+        let mut sounds = SoundManager::new();
+        let params = SoundParams::new("default_dig").looped();
+        let location = SoundLocation::Position(Vec3::new(1.0, 2.0, 3.0));
+        let (handle, events) = sounds.play(players(), &params, location);
+
+        let handle = handle.expect("a handle for a non-ephemeral sound");
+        assert_ne!(handle.server_id(), EPHEMERAL_SOUND_ID);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|(_, event)| matches!(
+            event,
+            FromPluginEvent::PlaySound(PlaySoundSpec {
+                server_id,
+                typ: SoundLocationType::Position,
+                pos,
+                spec_loop: true,
+                ephemeral: false,
+                ..
+            }) if *server_id == handle.server_id() && *pos == Vec3::new(10.0, 20.0, 30.0)
+        )));
+
+        assert!(sounds.is_playing(handle));
+        sounds.removed_by_client("alice", &[handle.server_id()]);
+        assert!(sounds.is_playing(handle));
+        sounds.removed_by_client("bob", &[handle.server_id()]);
+        assert!(!sounds.is_playing(handle));
+
+        // ids aren't being reused right away
+        let (other, _) = sounds.play(players(), &params, location);
+        assert_ne!(other, Some(handle));
+    }
+
+    #[test]
+    fn ephemeral_sounds_are_not_tracked() {
+        let mut sounds = SoundManager::new();
+        let params = SoundParams::new("default_place_node").ephemeral();
+        let (handle, events) = sounds.play(players(), &params, SoundLocation::Global);
+
+        assert_eq!(handle, None);
+        assert!(events.iter().all(|(_, event)| matches!(
+            event,
+            FromPluginEvent::PlaySound(PlaySoundSpec {
+                server_id: EPHEMERAL_SOUND_ID,
+                ephemeral: true,
+                ..
+            })
+        )));
+    }
+
+    #[test]
+    fn stopped_sounds_are_forgotten() {
+        let mut sounds = SoundManager::new();
+        let params = SoundParams::new("default_dig");
+        let (handle, _) = sounds.play(players(), &params, SoundLocation::Object(7));
+        let handle = handle.expect("a handle for a non-ephemeral sound");
+
+        let events = sounds.stop(handle);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|(_, event)| matches!(
+            event,
+            FromPluginEvent::StopSound(StopSoundSpec { server_id }) if *server_id == handle.server_id()
+        )));
+        assert!(!sounds.is_playing(handle));
+        assert!(sounds.stop(handle).is_empty());
+    }
+
+    #[test]
+    fn sounds_faded_out_are_forgotten() {
+        let mut sounds = SoundManager::new();
+        let params = SoundParams::new("ambience").looped();
+        let (handle, _) = sounds.play(players(), &params, SoundLocation::Global);
+        let handle = handle.expect("a handle for a non-ephemeral sound");
+
+        let events = sounds.fade(handle, 0.5, 0.25);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|(_, event)| matches!(
+            event,
+            FromPluginEvent::FadeSound(FadeSoundSpec { sound_id, .. }) if *sound_id == handle.server_id()
+        )));
+        assert!(sounds.is_playing(handle));
+
+        assert_eq!(sounds.fade(handle, 0.5, 0.0).len(), 2);
+        assert!(!sounds.is_playing(handle));
+        assert!(sounds.fade(handle, 0.5, 0.0).is_empty());
+    }
+
+    #[test]
+    fn sounds_are_forgotten_when_their_players_left() {
+        let mut sounds = SoundManager::new();
+        let params = SoundParams::new("ambience").looped();
+        let (handle, _) = sounds.play(players(), &params, SoundLocation::Global);
+        let handle = handle.expect("a handle for a non-ephemeral sound");
+
+        sounds.player_left("alice");
+        assert!(sounds.is_playing(handle));
+        sounds.player_left("bob");
+        assert!(!sounds.is_playing(handle));
+        assert!(sounds.stop(handle).is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+//! The public description of a server as shown by the server list and the `/status` command
+//!
+//! The server list measures the latency of a server by opening a regular connection, which is
+//! being answered by the handshake of [`luanti_protocol`]. Everything else it shows is taken from
+//! the [`ServerStatus`] a server announces; see [`ServerStatus::to_json`].
+
+use std::fmt::{self, Display, Write as _};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::client_connection::ConnectedPlayers;
+use crate::connections::{ConnectionInfo, Connections};
+
+/// The version being reported to clients and the server list
+pub const SERVER_VERSION: &str = concat!("luanti-rs ", env!("CARGO_PKG_VERSION"));
+
+/// The static description of a server
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ServerInfo {
+    /// shown as the title in the server list
+    pub name: String,
+    /// shown below the name in the server list
+    pub description: String,
+    /// a website about the server; may be empty
+    pub url: String,
+    /// the identifier of the game being played, e.g. `minetest_game`
+    pub game_id: String,
+    /// the names of the installed mods
+    pub mods: Vec<String>,
+    /// whether players have unlimited resources
+    pub creative: bool,
+    /// whether players can hurt each other
+    pub pvp: bool,
+    /// whether players need a password to join
+    pub password: bool,
+}
+
+/// A snapshot of the state of a server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    /// the description as configured by
+    /// [`LuantiWorldServer::set_server_info`](crate::server::LuantiWorldServer::set_server_info)
+    pub info: ServerInfo,
+    /// see [`SERVER_VERSION`]
+    pub version: &'static str,
+    /// the protocol versions clients may connect with
+    pub protocol_versions: RangeInclusive<u16>,
+    /// the time since the server has been started
+    pub uptime: Duration,
+    /// the names of the players being online, sorted alphabetically
+    pub players: Vec<String>,
+    /// the number of players being online at the same time
+    pub max_clients: usize,
+    /// whether players can be hurt
+    pub damage: bool,
+}
+
+impl ServerStatus {
+    /// Renders the fields of the server list's announcement (except `action`, `address` and
+    /// `port`) as a JSON object.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut json = JsonObject::default();
+        self.write_json_fields(&mut json);
+        json.finish()
+    }
+
+    pub(crate) fn write_json_fields(&self, json: &mut JsonObject) {
+        json.string("name", &self.info.name);
+        json.string("description", &self.info.description);
+        json.string("url", &self.info.url);
+        json.string("version", self.version);
+        json.raw("proto_min", self.protocol_versions.start());
+        json.raw("proto_max", self.protocol_versions.end());
+        json.string("gameid", &self.info.game_id);
+        json.strings("mods", &self.info.mods);
+        json.raw("uptime", self.uptime.as_secs());
+        json.raw("clients", self.players.len());
+        json.raw("clients_max", self.max_clients);
+        json.strings("clients_list", &self.players);
+        json.raw("creative", self.info.creative);
+        json.raw("damage", self.damage);
+        json.raw("pvp", self.info.pvp);
+        json.raw("password", self.info.password);
+        json.raw("dedicated", true);
+    }
+}
+
+impl Display for ServerStatus {
+    /// Renders the answer of the engine's `/status` command.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "# Server: version: {}", self.version)?;
+        if !self.info.game_id.is_empty() {
+            write!(formatter, " | game: {}", self.info.game_id)?;
+        }
+        write!(
+            formatter,
+            " | uptime: {} | clients: {}",
+            FormattedDuration(self.uptime),
+            self.players.join(", ")
+        )
+    }
+}
+
+/// Provides the current [`ServerStatus`]; cloning yields a handle to the same server.
+#[derive(Debug, Clone)]
+pub struct ServerStatusProvider {
+    info: Arc<ServerInfo>,
+    /// set when the server is being started
+    started: Arc<OnceLock<Instant>>,
+    protocol_versions: RangeInclusive<u16>,
+    max_clients: usize,
+    damage: bool,
+    connected_players: ConnectedPlayers,
+    connections: Connections,
+}
+
+impl ServerStatusProvider {
+    pub(crate) fn new(
+        info: ServerInfo,
+        started: Arc<OnceLock<Instant>>,
+        protocol_versions: RangeInclusive<u16>,
+        max_clients: usize,
+        damage: bool,
+        connected_players: ConnectedPlayers,
+        connections: Connections,
+    ) -> Self {
+        Self {
+            info: Arc::new(info),
+            started,
+            protocol_versions,
+            max_clients,
+            damage,
+            connected_players,
+            connections,
+        }
+    }
+
+    /// Returns the current state of the server. The uptime is zero until the server has been
+    /// started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the lock of the connected players.
+    #[must_use]
+    pub fn status(&self) -> ServerStatus {
+        let mut players = self.connected_players.names();
+        players.sort_unstable();
+        ServerStatus {
+            info: ServerInfo::clone(&self.info),
+            version: SERVER_VERSION,
+            protocol_versions: self.protocol_versions.clone(),
+            uptime: self.started.get().map_or(Duration::ZERO, Instant::elapsed),
+            players,
+            max_clients: self.max_clients,
+            damage: self.damage,
+        }
+    }
+
+    /// Returns the state of every client connection ordered by id, e.g. for showing details to
+    /// admins invoking the `/status` command.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the lock of the connections.
+    #[must_use]
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.connections.list()
+    }
+}
+
+/// Formats a duration like `2d 3h 0min 5s`, leaving out leading zeros.
+struct FormattedDuration(Duration);
+
+impl Display for FormattedDuration {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        let parts = [
+            (seconds / 86400, "d"),
+            (seconds / 3600 % 24, "h"),
+            (seconds / 60 % 60, "min"),
+        ];
+        let mut started = false;
+        for (value, unit) in parts {
+            started |= value > 0;
+            if started {
+                write!(formatter, "{value}{unit} ")?;
+            }
+        }
+        write!(formatter, "{}s", seconds % 60)
+    }
+}
+
+/// Renders a JSON object field by field.
+#[derive(Debug, Default)]
+pub(crate) struct JsonObject {
+    json: String,
+}
+
+impl JsonObject {
+    /// Adds a field whose value is a number or a boolean.
+    pub(crate) fn raw(&mut self, name: &str, value: impl Display) {
+        self.key(name);
+        write!(self.json, "{value}").expect("writing to a `String` doesn't fail");
+    }
+
+    pub(crate) fn string(&mut self, name: &str, value: &str) {
+        self.key(name);
+        self.json.push_str(&json_string(value));
+    }
+
+    pub(crate) fn strings(&mut self, name: &str, values: &[String]) {
+        self.key(name);
+        let items: Vec<_> = values.iter().map(|value| json_string(value)).collect();
+        self.json.push('[');
+        self.json.push_str(&items.join(","));
+        self.json.push(']');
+    }
+
+    fn key(&mut self, name: &str) {
+        self.json.push(if self.json.is_empty() { '{' } else { ',' });
+        self.json.push_str(&json_string(name));
+        self.json.push(':');
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        if self.json.is_empty() {
+            self.json.push('{');
+        }
+        self.json.push('}');
+        self.json
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => json.push_str(r#"\""#),
+            '\\' => json.push_str(r"\\"),
+            '\n' => json.push_str(r"\n"),
+            '\r' => json.push_str(r"\r"),
+            '\t' => json.push_str(r"\t"),
+            control if control < ' ' => {
+                write!(json, r"\u{:04x}", u32::from(control))
+                    .expect("writing to a `String` doesn't fail");
+            }
+            other => json.push(other),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{SERVER_VERSION, ServerInfo, ServerStatus};
+
+    fn status() -> ServerStatus {
+        ServerStatus {
+            info: ServerInfo {
+                name: "Rusty \"Server\"".to_owned(),
+                description: "line\nbreak".to_owned(),
+                url: String::new(),
+                game_id: "minetest_game".to_owned(),
+                mods: vec!["default".to_owned()],
+                creative: false,
+                pvp: true,
+                password: false,
+            },
+            version: SERVER_VERSION,
+            protocol_versions: 46..=47,
+            uptime: Duration::from_secs(3 * 3600 + 5),
+            players: vec!["alice".to_owned(), "bob".to_owned()],
+            max_clients: 15,
+            damage: true,
+        }
+    }
+
+    #[test]
+    fn status_is_rendered_like_the_engine() {
+        assert_eq!(
+            status().to_string(),
+            format!(
+                "# Server: version: {SERVER_VERSION} | game: minetest_game | uptime: 3h 0min 5s | clients: alice, bob"
+            )
+        );
+    }
+
+    #[test]
+    fn status_is_rendered_as_json() {
+        assert_eq!(
+            status().to_json(),
+            format!(
+                r#"{{"name":"Rusty \"Server\"","description":"line\nbreak","url":"","version":"{SERVER_VERSION}","proto_min":46,"proto_max":47,"gameid":"minetest_game","mods":["default"],"uptime":10805,"clients":2,"clients_max":15,"clients_list":["alice","bob"],"creative":false,"damage":true,"pvp":true,"password":false,"dedicated":true}}"#
+            )
+        );
+    }
+}
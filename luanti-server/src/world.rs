@@ -1,17 +1,28 @@
 //! Contains types related to the configuration and state of an entire world.
 //! Everything in here should be kept decoupled from the server types if possible.
 
+pub mod backup;
+pub mod block_cache;
 pub mod content_id_map;
+pub mod content_id_mapper;
+pub mod editor;
+pub mod emerge_queue;
+pub mod far_blocks;
 pub mod generation;
 pub mod map_block_provider;
 pub mod map_block_router;
+pub mod map_meta;
 pub mod media_registry;
 pub(crate) mod priority;
 pub mod storage;
-pub(crate) mod view_tracker;
+pub mod unknown_nodes;
+pub mod view_tracker;
+pub mod voxel_buffer;
+pub mod worldedit;
 
-use luanti_core::{MapBlockNodes, MapBlockPos, MapNodeIndex};
-use luanti_protocol::types::NodeMetadata;
+use glam::Vec3;
+use luanti_core::{MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex, MapNodePos};
+use luanti_protocol::types::{MapNodesBulk, NodeMetadata, NodeMetadataList, TransferrableMapBlock};
 
 // /// A single Luanti world with all items, nodes, media, etc.
 // struct World {
@@ -72,20 +83,157 @@ pub struct WorldBlock {
     pub(crate) metadata: Vec<(MapNodeIndex, NodeMetadata)>,
 }
 
+impl WorldBlock {
+    /// Returns `true` if this block consists of [`luanti_core::ContentId::IGNORE`] only, which
+    /// means that it has never been generated.
+    pub(crate) fn is_ungenerated(&self) -> bool {
+        self.nodes.0.iter().all(|node| node.content_id.is_ignore())
+    }
+
+    /// Returns the metadata of a node if it has any.
+    pub(crate) fn metadata(&self, index: MapNodeIndex) -> Option<&NodeMetadata> {
+        self.metadata
+            .iter()
+            .find_map(|(existing, metadata)| (*existing == index).then_some(metadata))
+    }
+
+    /// Replaces the metadata of a node; `None` removes it.
+    pub(crate) fn set_metadata(&mut self, index: MapNodeIndex, metadata: Option<NodeMetadata>) {
+        self.metadata.retain(|(existing, _)| *existing != index);
+        if let Some(metadata) = metadata {
+            self.metadata.push((index, metadata));
+        }
+    }
+
+    /// Returns `true` if both blocks are at the same position and have the same content,
+    /// regardless of how many updates they've received.
+    #[must_use]
+    pub fn same_content(&self, other: &Self) -> bool {
+        let Self {
+            version: _,
+            pos,
+            is_underground,
+            day_night_differs,
+            lighting_complete,
+            nodes,
+            metadata,
+        } = self;
+        *pos == other.pos
+            && *is_underground == other.is_underground
+            && *day_night_differs == other.day_night_differs
+            && *lighting_complete == other.lighting_complete
+            && *nodes == other.nodes
+            && *metadata == other.metadata
+    }
+}
+
+impl From<WorldBlock> for TransferrableMapBlock {
+    fn from(world_block: WorldBlock) -> Self {
+        let WorldBlock {
+            version: _,
+            pos: _,
+            is_underground,
+            day_night_differs,
+            lighting_complete,
+            nodes,
+            metadata,
+        } = world_block;
+
+        Self {
+            is_underground,
+            day_night_differs,
+            generated: true,
+            lighting_complete: Some(lighting_complete),
+            nodes: MapNodesBulk { nodes: nodes.0 },
+            node_metadata: NodeMetadataList { metadata },
+        }
+    }
+}
+
 /// A value of this type describes a change to the world.
 #[derive(Clone)]
+#[expect(
+    clippy::large_enum_variant,
+    reason = "map blocks are the most common updates and would need an extra allocation if boxed"
+)]
 pub enum WorldUpdate {
     /// A new map block was made available. This usually means that this block has just been
     /// generated or loaded from storage.
     ///
     /// This may also be created for an existing map block that is _new_ to a certain player.
     NewMapBlock(WorldBlock),
+    /// A single node of a loaded map block has been replaced. Its metadata is kept; changes of
+    /// the metadata are reported by [`Self::NodeMetaChanged`].
+    NodeChanged {
+        /// position of the node
+        pos: MapNodePos,
+        /// the new node
+        node: MapNode,
+    },
+    /// The metadata of a node has been replaced.
+    NodeMetaChanged {
+        /// position of the node
+        pos: MapNodePos,
+        /// the new metadata; `None` if it has been removed
+        metadata: Option<NodeMetadata>,
+    },
+    /// A map block is no longer kept in memory. Copies of it remain valid, as changes are still
+    /// being reported.
+    BlockUnloaded(MapBlockPos),
+    /// An entity changed its position.
+    EntityMoved {
+        /// the id of the active object representing the entity
+        id: u16,
+        /// position in nodes
+        position: Vec3,
+        /// velocity in nodes per second
+        velocity: Vec3,
+        /// rotation in degrees
+        rotation: Vec3,
+    },
+    /// Several updates being delivered at once, in the order they happened
+    Batch(Vec<WorldUpdate>),
+}
+
+impl WorldUpdate {
+    /// Combines updates into a single one; `None` if there are none.
+    #[must_use]
+    pub fn batch(mut updates: Vec<Self>) -> Option<Self> {
+        match updates.len() {
+            0 => None,
+            1 => updates.pop(),
+            _ => Some(Self::Batch(updates)),
+        }
+    }
+
+    /// Splits batches into the updates they contain.
+    #[must_use]
+    pub fn into_updates(self) -> Vec<Self> {
+        match self {
+            Self::Batch(updates) => updates.into_iter().flat_map(Self::into_updates).collect(),
+            update => vec![update],
+        }
+    }
 }
 
 impl std::fmt::Debug for WorldUpdate {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NewMapBlock(world_block) => write!(formatter, "NewMapBlock: {}", world_block.pos),
+            Self::NodeChanged { pos, node } => {
+                write!(formatter, "NodeChanged: {:?} {}", pos.0, node.content_id.0)
+            }
+            Self::NodeMetaChanged { pos, metadata } => write!(
+                formatter,
+                "NodeMetaChanged: {:?} {}",
+                pos.0,
+                if metadata.is_some() { "set" } else { "removed" }
+            ),
+            Self::BlockUnloaded(pos) => write!(formatter, "BlockUnloaded: {pos}"),
+            Self::EntityMoved { id, position, .. } => {
+                write!(formatter, "EntityMoved: #{id} {position}")
+            }
+            Self::Batch(updates) => write!(formatter, "Batch: {} updates", updates.len()),
         }
     }
 }
@@ -0,0 +1,186 @@
+//! Backups of the map of a running server
+//!
+//! A backup is a snapshot of the storage of a
+//! [`MapBlockProvider`](super::map_block_provider::MapBlockProvider) taken after all modified map
+//! blocks have been written, so no restart is needed to obtain a consistent copy of the world.
+//! Only the map is being copied; files like `world.mt` rarely change and need to be saved
+//! separately.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use log::{debug, error, info};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use super::map_block_provider::ToProviderMessage;
+
+/// Every backup is stored in a subdirectory with this prefix followed by the creation time
+const BACKUP_PREFIX: &str = "backup-";
+
+/// Settings of periodic backups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupConfig {
+    /// directory which will contain one subdirectory per backup
+    pub directory: PathBuf,
+    /// time between two backups
+    pub interval: Duration,
+    /// number of backups being kept; older ones are removed
+    pub keep: usize,
+}
+
+impl BackupConfig {
+    /// The default time between two backups
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    /// The default number of backups being kept
+    pub const DEFAULT_KEEP: usize = 24;
+
+    /// Creates settings for hourly backups into the given directory.
+    #[must_use]
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            interval: Self::DEFAULT_INTERVAL,
+            keep: Self::DEFAULT_KEEP,
+        }
+    }
+}
+
+/// Asks the [`MapBlockProvider`](super::map_block_provider::MapBlockProvider) to write all
+/// modified map blocks and to create a snapshot of its storage in `directory`. Completes when the
+/// snapshot has been created.
+///
+/// # Errors
+///
+/// Returns an error if the provider isn't running anymore or if the snapshot failed.
+pub async fn snapshot(
+    provider_sender: &mpsc::UnboundedSender<ToProviderMessage>,
+    directory: PathBuf,
+) -> Result<()> {
+    let (result_sender, result_receiver) = oneshot::channel();
+    if provider_sender
+        .send(ToProviderMessage::Snapshot(directory, result_sender))
+        .is_err()
+    {
+        bail!("the map block provider isn't running anymore");
+    }
+    result_receiver
+        .await
+        .context("the map block provider stopped before the snapshot was complete")?
+}
+
+/// Starts a task which creates a backup every [`BackupConfig::interval`] and removes the oldest
+/// ones beyond [`BackupConfig::keep`]. The task ends when the provider stops.
+#[must_use]
+pub fn spawn_backups(
+    provider_sender: mpsc::UnboundedSender<ToProviderMessage>,
+    config: BackupConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        // the first tick completes immediately, but there's nothing worth saving at startup
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if provider_sender.is_closed() {
+                debug!("stopping backups as the map block provider isn't running anymore");
+                return;
+            }
+            let directory = config.directory.join(backup_name(SystemTime::now()));
+            if let Err(error) = snapshot(&provider_sender, directory.clone()).await {
+                error!("failed to create backup {}: {error:?}", directory.display());
+                // keep the older backups as long as no new one is available
+                continue;
+            }
+            info!("created backup {}", directory.display());
+            if let Err(error) = remove_old_backups(&config.directory, config.keep) {
+                error!("failed to remove old backups: {error:?}");
+            }
+        }
+    })
+}
+
+/// Returns the name of the subdirectory of a backup created at the given time.
+fn backup_name(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    // the padding keeps the lexicographic order chronological
+    format!("{BACKUP_PREFIX}{seconds:012}")
+}
+
+/// Removes all but the `keep` most recent backups.
+fn remove_old_backups(directory: &Path, keep: usize) -> Result<()> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir()
+            && entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(BACKUP_PREFIX))
+        {
+            backups.push(entry.path());
+        }
+    }
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for backup in backups.drain(..excess) {
+        debug!("removing old backup {}", backup.display());
+        fs::remove_dir_all(&backup)
+            .with_context(|| format!("failed to remove {}", backup.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{
+        env, fs,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{backup_name, remove_old_backups};
+
+    #[test]
+    fn backup_names_sort_chronologically() {
+        let early = backup_name(UNIX_EPOCH + Duration::from_secs(999));
+        let late = backup_name(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(early, "backup-000000000999");
+        assert!(early < late);
+    }
+
+    #[test]
+    fn only_the_most_recent_backups_are_kept() {
+        let directory = env::temp_dir().join(format!("luanti-rs-backups-{}", std::process::id()));
+        for seconds in [3, 1, 4, 2] {
+            fs::create_dir_all(
+                directory.join(backup_name(UNIX_EPOCH + Duration::from_secs(seconds))),
+            )
+            .unwrap();
+        }
+        fs::create_dir_all(directory.join("unrelated")).unwrap();
+
+        remove_old_backups(&directory, 2).unwrap();
+
+        let mut remaining: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            ["backup-000000000003", "backup-000000000004", "unrelated"]
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! Contains `BlockCache`
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::warn;
+use luanti_core::MapBlockPos;
+
+use super::WorldBlock;
+
+/// Settings of the block cache of a `MapBlockProvider`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCacheConfig {
+    /// maximum number of blocks being kept in memory
+    pub capacity: usize,
+    /// modified blocks are written to the storage at least this often
+    pub flush_interval: Duration,
+}
+
+impl Default for BlockCacheConfig {
+    fn default() -> Self {
+        Self {
+            // about 16 MiB of node data
+            capacity: 1024,
+            flush_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Statistics of a block cache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// number of requested blocks which were found in the cache
+    pub hits: u64,
+    /// number of requested blocks which had to be loaded or generated
+    pub misses: u64,
+    /// number of blocks which have been removed to make room for others
+    pub evictions: u64,
+    /// number of modified blocks which have been written to the storage
+    pub flushed: u64,
+    /// number of blocks currently in the cache
+    pub len: usize,
+    /// number of blocks in the cache which haven't been written to the storage yet
+    pub dirty: usize,
+}
+
+impl CacheStats {
+    /// The share of requests which could be served from the cache; `0.0` if there were none.
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "the result is an approximation anyway"
+    )]
+    #[must_use]
+    pub fn hit_rate(&self) -> f64 {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / requests as f64
+    }
+}
+
+struct CacheEntry {
+    block: WorldBlock,
+    /// whether the block has been modified since it has been written to the storage
+    dirty: bool,
+    /// the tick of the last access
+    last_used: u64,
+}
+
+/// A least recently used cache of map blocks which keeps track of modifications
+pub(crate) struct BlockCache {
+    capacity: usize,
+    entries: HashMap<MapBlockPos, CacheEntry>,
+    /// positions of all cached blocks ordered by their last access
+    recency: BTreeMap<u64, MapBlockPos>,
+    tick: u64,
+    stats: CacheStats,
+    /// positions of the blocks which have been evicted since the last call of `take_evicted`
+    evicted: Vec<MapBlockPos>,
+}
+
+impl BlockCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+            evicted: Vec::new(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            len: self.entries.len(),
+            dirty: self.entries.values().filter(|entry| entry.dirty).count(),
+            ..self.stats
+        }
+    }
+
+    /// Returns the block at the given position and marks it as recently used.
+    pub(crate) fn get(&mut self, pos: MapBlockPos) -> Option<&WorldBlock> {
+        let tick = self.next_tick();
+        let Some(entry) = self.entries.get_mut(&pos) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, pos);
+        entry.last_used = tick;
+        Some(&entry.block)
+    }
+
+    /// Returns the block at the given position without counting it as a request.
+    pub(crate) fn peek(&self, pos: MapBlockPos) -> Option<&WorldBlock> {
+        self.entries.get(&pos).map(|entry| &entry.block)
+    }
+
+    /// Adds or replaces a block.
+    ///
+    /// A replaced block stays dirty even if the new one isn't. If the cache is full, the least
+    /// recently used block is removed and returned if it needs to be written to the storage.
+    pub(crate) fn insert(&mut self, block: WorldBlock, mut dirty: bool) -> Option<WorldBlock> {
+        let tick = self.next_tick();
+        let pos = block.pos;
+        if let Some(previous) = self.entries.remove(&pos) {
+            self.recency.remove(&previous.last_used);
+            dirty |= previous.dirty;
+        }
+
+        let evicted = if self.entries.len() >= self.capacity {
+            self.evict()
+        } else {
+            None
+        };
+
+        self.recency.insert(tick, pos);
+        self.entries.insert(
+            pos,
+            CacheEntry {
+                block,
+                dirty,
+                last_used: tick,
+            },
+        );
+        evicted
+    }
+
+    /// Passes all modified blocks to `store` and marks them as clean.
+    ///
+    /// Returns the number of blocks written. Blocks which couldn't be stored are logged and remain
+    /// dirty, so they are retried by the next flush.
+    pub(crate) fn flush(&mut self, mut store: impl FnMut(&WorldBlock) -> Result<()>) -> usize {
+        let mut count = 0;
+        for entry in self.entries.values_mut().filter(|entry| entry.dirty) {
+            if let Err(error) = store(&entry.block) {
+                warn!("failed to store map block {}: {error:#}", entry.block.pos);
+                continue;
+            }
+            entry.dirty = false;
+            count += 1;
+            self.stats.flushed += 1;
+        }
+        count
+    }
+
+    fn evict(&mut self) -> Option<WorldBlock> {
+        let (_, pos) = self.recency.pop_first()?;
+        let entry = self.entries.remove(&pos)?;
+        self.stats.evictions += 1;
+        self.evicted.push(pos);
+        entry.dirty.then_some(entry.block)
+    }
+
+    /// Returns the positions of all blocks which have been evicted since the previous call.
+    pub(crate) fn take_evicted(&mut self) -> Vec<MapBlockPos> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::I16Vec3;
+    use luanti_core::MapBlockPos;
+
+    use super::BlockCache;
+    use crate::world::WorldBlock;
+    use crate::world::generation::mapgen::MapgenBlock;
+
+    fn block(x: i16) -> WorldBlock {
+        MapgenBlock::new(0, MapBlockPos::new(I16Vec3::new(x, 0, 0)).unwrap()).into_world_block()
+    }
+
+    #[test]
+    fn least_recently_used_blocks_are_evicted() {
+        let mut cache = BlockCache::new(2);
+        assert!(cache.insert(block(0), true).is_none());
+        assert!(cache.insert(block(1), false).is_none());
+
+        // make block 1 the least recently used one
+        assert!(cache.get(block(0).pos).is_some());
+        assert!(cache.insert(block(2), false).is_none());
+        assert!(cache.get(block(1).pos).is_none());
+
+        // the evicted block is dirty and needs to be written
+        assert!(cache.get(block(2).pos).is_some());
+        let evicted = cache.insert(block(3), false).unwrap();
+        assert_eq!(evicted.pos, block(0).pos);
+
+        assert_eq!(cache.take_evicted(), [block(1).pos, block(0).pos]);
+        assert!(cache.take_evicted().is_empty());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 2));
+        assert_eq!((stats.len, stats.dirty), (2, 0));
+    }
+
+    #[test]
+    fn flushing_cleans_dirty_blocks() {
+        let mut cache = BlockCache::new(8);
+        cache.insert(block(0), true);
+        cache.insert(block(1), false);
+        cache.insert(block(2), true);
+        // replacing a dirty block with a clean one keeps it dirty
+        cache.insert(block(2), false);
+        assert_eq!(cache.stats().dirty, 2);
+
+        let mut stored = Vec::new();
+        let count = cache.flush(|block| {
+            stored.push(block.pos);
+            Ok(())
+        });
+        assert_eq!(count, 2);
+        assert_eq!(stored.len(), 2);
+        assert_eq!(cache.stats().dirty, 0);
+        assert_eq!(cache.flush(|_| Ok(())), 0);
+    }
+
+    #[test]
+    fn failed_blocks_are_retried() {
+        let mut cache = BlockCache::new(8);
+        cache.insert(block(0), true);
+        cache.insert(block(1), true);
+
+        let failing = block(0).pos;
+        let count = cache.flush(|block| {
+            if block.pos == failing {
+                anyhow::bail!("disk full");
+            }
+            Ok(())
+        });
+        assert_eq!(count, 1);
+        assert_eq!(cache.stats().dirty, 1);
+
+        let mut stored = Vec::new();
+        assert_eq!(
+            cache.flush(|block| {
+                stored.push(block.pos);
+                Ok(())
+            }),
+            1
+        );
+        assert_eq!(stored, [failing]);
+        assert_eq!(cache.stats().dirty, 0);
+    }
+}
@@ -1,8 +1,8 @@
 //! Contains `ContentIdMap`
 
-use std::{borrow::Borrow, collections::HashMap, hash::Hash, ops::Index};
+use std::{borrow::Borrow, collections::HashMap, fmt, hash::Hash, ops::Index};
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use flexstr::SharedStr;
 use luanti_core::ContentId;
 
@@ -58,6 +58,66 @@ impl ContentIdMap {
         Ok(id)
     }
 
+    /// Returns `true` if there's an id for the given name.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.to_id.contains_key(name.as_bytes())
+    }
+
+    /// Returns the id of the given name or `None` if there's none.
+    ///
+    /// Unlike indexing this tells unknown names apart from the name `unknown`.
+    #[must_use]
+    pub fn get(&self, name: &[u8]) -> Option<ContentId> {
+        self.to_id.get(name).copied()
+    }
+
+    /// Returns all ids along with their names in ascending order of the ids.
+    pub fn iter(&self) -> impl Iterator<Item = (ContentId, &SharedStr)> {
+        (0..=u16::MAX)
+            .map(ContentId)
+            .zip(&self.to_name)
+            .filter(|(_, name)| !name.is_empty())
+    }
+
+    /// Parses a mapping written by the [`Display`](fmt::Display) implementation: one id followed
+    /// by its name per line. The default mappings of [`Self::new`] are always present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is malformed or assigns an id or a name twice.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut result = Self::new();
+        for (line_number, line) in (1_usize..).zip(text.lines()) {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((id, name)) = line.split_once(' ') else {
+                bail!("line {line_number} doesn't contain an id and a name");
+            };
+            let id = ContentId(
+                id.parse()
+                    .with_context(|| format!("invalid content id in line {line_number}"))?,
+            );
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                bail!("invalid node name `{name}` in line {line_number}");
+            }
+            match (result.get(name.as_bytes()), &result[id]) {
+                (None, existing) if existing.is_empty() => {
+                    result.insert(id, SharedStr::from(name.to_owned()));
+                }
+                (Some(existing), _) if existing == id => {}
+                _ => bail!(
+                    "content id {} or `{name}` in line {line_number} is assigned twice",
+                    id.0
+                ),
+            }
+        }
+        Ok(result)
+    }
+
     fn find_free_id(&self) -> Option<ContentId> {
         self.to_name
             .iter()
@@ -75,13 +135,21 @@ impl ContentIdMap {
         if let Some(entry) = self.to_name.get_mut(usize::from(id)) {
             *entry = name;
         } else {
-            self.to_name
-                .resize(usize::from(id).saturating_sub(1), SharedStr::empty());
+            self.to_name.resize(usize::from(id), SharedStr::empty());
             self.to_name.push(name);
         }
     }
 }
 
+impl fmt::Display for ContentIdMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, name) in self.iter() {
+            writeln!(f, "{} {name}", id.0)?;
+        }
+        Ok(())
+    }
+}
+
 impl Index<ContentId> for ContentIdMap {
     type Output = SharedStr;
 
@@ -0,0 +1,285 @@
+//! Contains `ContentIdMapper` and `ContentIdRemap`
+//!
+//! Worlds store node names while clients address nodes by content ids which are assigned by the
+//! server. The [`ContentIdMapper`] assigns an id to every name on first use and persists the
+//! mapping with the world, so the ids stay the same across restarts. Map blocks which have been
+//! stored using a different mapping are translated by a [`ContentIdRemap`].
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        PoisonError, RwLock, RwLockReadGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::{Context, Result};
+use flexstr::SharedStr;
+use log::{debug, info};
+use luanti_core::{ContentId, MapBlockNodes};
+
+use super::content_id_map::ContentIdMap;
+
+/// The name of the file within a world directory which contains the mapping
+const FILE_NAME: &str = "content_ids.txt";
+
+/// A [`ContentIdMap`] which assigns ids on first use and can be shared between threads
+pub struct ContentIdMapper {
+    map: RwLock<ContentIdMap>,
+    /// the world directory the mapping is persisted in; `None` keeps it in memory only
+    directory: Option<PathBuf>,
+    /// whether ids have been assigned since the mapping has been loaded or saved
+    modified: AtomicBool,
+}
+
+impl ContentIdMapper {
+    /// Creates a mapper starting with the given mapping which is kept in memory only.
+    #[must_use]
+    pub fn new(map: ContentIdMap) -> Self {
+        Self {
+            map: RwLock::new(map),
+            directory: None,
+            modified: AtomicBool::new(false),
+        }
+    }
+
+    /// Opens the mapping persisted in a world directory. A world without a mapping starts with
+    /// the default mappings of [`ContentIdMap::new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mapping exists but can't be read.
+    pub fn open(world_directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = world_directory.as_ref();
+        let path = directory.join(FILE_NAME);
+        let map = match fs::read_to_string(&path) {
+            Ok(text) => ContentIdMap::parse(&text)
+                .with_context(|| format!("failed to parse {}", path.display()))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!("{} doesn't exist yet", path.display());
+                ContentIdMap::new()
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        Ok(Self {
+            directory: Some(directory.to_path_buf()),
+            ..Self::new(map)
+        })
+    }
+
+    /// Returns the id of the given name. Names without an id get the next free one.
+    ///
+    /// Invalid UTF-8 within a name is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no free ids left.
+    pub fn id(&self, name: &[u8]) -> Result<ContentId> {
+        if let Some(id) = self.get(name) {
+            return Ok(id);
+        }
+        let name = String::from_utf8_lossy(name);
+        let mut map = self.map.write().unwrap_or_else(PoisonError::into_inner);
+        // another thread might have been faster
+        if let Some(id) = map.get(name.as_bytes()) {
+            return Ok(id);
+        }
+        let id = map.push(SharedStr::from(name.into_owned()))?;
+        self.modified.store(true, Ordering::Release);
+        Ok(id)
+    }
+
+    /// Returns the id of the given name without assigning one.
+    #[must_use]
+    pub fn get(&self, name: &[u8]) -> Option<ContentId> {
+        self.map().get(name)
+    }
+
+    /// Returns the name of the given id or an empty string if it has none.
+    #[must_use]
+    pub fn name(&self, id: ContentId) -> SharedStr {
+        self.map()[id].clone()
+    }
+
+    /// Grants access to the whole mapping. Assigning ids is blocked until the guard is dropped.
+    pub fn map(&self) -> RwLockReadGuard<'_, ContentIdMap> {
+        self.map.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Creates the translation of ids from the given mapping into this one. Names without an id
+    /// get the next free one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no free ids left.
+    pub fn remap(&self, stored: &ContentIdMap) -> Result<ContentIdRemap> {
+        let mut remap = ContentIdRemap::default();
+        for (stored_id, name) in stored.iter() {
+            remap.insert(stored_id, self.id(name.as_bytes())?);
+        }
+        Ok(remap)
+    }
+
+    /// Writes the mapping into its world directory if ids have been assigned since it has been
+    /// opened. Mappings being kept in memory only are never written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mapping could not be written.
+    pub fn save(&self) -> Result<()> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+        if self.modified.swap(false, Ordering::AcqRel) {
+            if let Err(error) = self.save_to(directory) {
+                self.modified.store(true, Ordering::Release);
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the mapping into the given directory, e.g. along with a snapshot of a world.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mapping could not be written.
+    pub fn save_to(&self, directory: &Path) -> Result<()> {
+        let path = directory.join(FILE_NAME);
+        let text = self.map().to_string();
+        // a crash while writing must not destroy the previous mapping
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, text)
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, &path)
+            .with_context(|| format!("failed to replace {}", path.display()))?;
+        info!("saved content ids into {}", path.display());
+        Ok(())
+    }
+}
+
+/// Translates the content ids of a stored mapping into those of a [`ContentIdMapper`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ContentIdRemap {
+    /// the current ids indexed by the stored ids
+    ids: Vec<ContentId>,
+}
+
+impl ContentIdRemap {
+    /// Sets the current id of a stored id.
+    pub fn insert(&mut self, stored: ContentId, current: ContentId) {
+        let index = usize::from(stored.0);
+        if index >= self.ids.len() {
+            self.ids.resize(index + 1, ContentId::UNKNOWN);
+        }
+        if let Some(slot) = self.ids.get_mut(index) {
+            *slot = current;
+        }
+    }
+
+    /// Returns the current id of a stored id; [`ContentId::UNKNOWN`] if the stored id has no name.
+    #[must_use]
+    pub fn get(&self, stored: ContentId) -> ContentId {
+        self.ids
+            .get(usize::from(stored.0))
+            .copied()
+            .unwrap_or(ContentId::UNKNOWN)
+    }
+
+    /// Returns `true` if all ids stay the same.
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        (0..=u16::MAX)
+            .map(ContentId)
+            .zip(&self.ids)
+            .all(|(stored, current)| stored == *current || *current == ContentId::UNKNOWN)
+    }
+
+    /// Translates the ids of all nodes of a map block.
+    pub fn apply(&self, nodes: &mut MapBlockNodes) {
+        for node in &mut nodes.0 {
+            node.content_id = self.get(node.content_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{env, fs};
+
+    use flexstr::SharedStr;
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode};
+
+    use super::ContentIdMapper;
+    use crate::ContentIdMap;
+
+    #[test]
+    fn ids_are_assigned_on_first_use_and_persisted() {
+        let directory =
+            env::temp_dir().join(format!("luanti-rs-content-ids-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let mapper = ContentIdMapper::open(&directory).unwrap();
+        assert_eq!(mapper.id(b"air").unwrap(), ContentId::AIR);
+        let stone = mapper.id(b"default:stone").unwrap();
+        let dirt = mapper.id(b"default:dirt").unwrap();
+        assert_ne!(stone, dirt);
+        assert_eq!(mapper.id(b"default:stone").unwrap(), stone);
+        assert_eq!(mapper.name(dirt), "default:dirt");
+        assert_eq!(mapper.get(b"default:sand"), None);
+        mapper.save().unwrap();
+
+        let reopened = ContentIdMapper::open(&directory).unwrap();
+        assert_eq!(reopened.get(b"default:stone"), Some(stone));
+        assert_eq!(reopened.get(b"default:dirt"), Some(dirt));
+        assert_eq!(reopened.get(b"ignore"), Some(ContentId::IGNORE));
+
+        fs::remove_dir_all(directory).unwrap();
+
+        ContentIdMap::parse("0 default:stone\n0 default:dirt")
+            .map(drop)
+            .unwrap_err();
+        ContentIdMap::parse("1 air").map(drop).unwrap_err();
+        ContentIdMap::parse("1\n").map(drop).unwrap_err();
+    }
+
+    #[test]
+    fn stored_ids_are_remapped() {
+        let mapper = ContentIdMapper::new(ContentIdMap::new());
+        let stone = mapper.id(b"default:stone").unwrap();
+
+        // a storage which assigned the ids in a different order
+        let mut stored = ContentIdMap::new();
+        let stored_dirt = stored
+            .push(SharedStr::from_borrowed("default:dirt"))
+            .unwrap();
+        let stored_stone = stored
+            .push(SharedStr::from_borrowed("default:stone"))
+            .unwrap();
+        let remap = mapper.remap(&stored).unwrap();
+        assert!(!remap.is_identity());
+        assert_eq!(remap.get(stored_stone), stone);
+        assert_eq!(remap.get(stored_dirt), mapper.get(b"default:dirt").unwrap());
+        assert_eq!(remap.get(ContentId::AIR), ContentId::AIR);
+
+        let mut nodes = MapBlockNodes(
+            [MapNode {
+                content_id: stored_stone,
+                param1: 0,
+                param2: 0,
+            }; MapBlockPos::NODE_COUNT as usize],
+        );
+        remap.apply(&mut nodes);
+        assert!(nodes.0.iter().all(|node| node.content_id == stone));
+
+        // the mapper itself needs no translation
+        let current = ContentIdMap::parse(&mapper.map().to_string()).unwrap();
+        assert!(mapper.remap(&current).unwrap().is_identity());
+    }
+}
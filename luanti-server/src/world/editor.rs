@@ -0,0 +1,210 @@
+//! Contains the `WorldEditor`
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodePos};
+use luanti_protocol::types::NodeMetadata;
+
+use super::{WorldBlock, content_id_mapper::ContentIdMapper, storage::WorldStorage};
+
+/// Reads and modifies individual nodes of a stored world.
+///
+/// Map blocks are loaded on first access and kept in memory until [`WorldEditor::flush`] writes
+/// the modified ones back. This is meant for tools working on a world which isn't being served at
+/// the same time.
+pub struct WorldEditor<'storage> {
+    storage: &'storage mut dyn WorldStorage,
+    content_ids: Arc<ContentIdMapper>,
+    /// all map blocks accessed so far; `None` if the storage doesn't contain the block
+    blocks: HashMap<MapBlockPos, Option<WorldBlock>>,
+    modified: HashSet<MapBlockPos>,
+}
+
+impl<'storage> WorldEditor<'storage> {
+    /// Creates an editor for the given storage. `content_ids` must be the mapper the storage has
+    /// been opened with.
+    #[must_use]
+    pub fn new(storage: &'storage mut dyn WorldStorage, content_ids: Arc<ContentIdMapper>) -> Self {
+        Self {
+            storage,
+            content_ids,
+            blocks: HashMap::new(),
+            modified: HashSet::new(),
+        }
+    }
+
+    /// Returns the mapping between the content ids and the node names of the edited world.
+    #[must_use]
+    pub fn content_ids(&self) -> &ContentIdMapper {
+        &self.content_ids
+    }
+
+    /// Returns the node at the given position or `None` if its map block doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map block could not be loaded.
+    pub fn node(&mut self, pos: MapNodePos) -> Result<Option<MapNode>> {
+        let (block_pos, index) = pos.split_index();
+        Ok(self.block(block_pos)?.map(|block| block.nodes[index]))
+    }
+
+    /// Replaces the node at the given position. Missing map blocks are being created and filled
+    /// with air, so they won't be generated by a map generator later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map block could not be loaded.
+    pub fn set_node(&mut self, pos: MapNodePos, node: MapNode) -> Result<()> {
+        let (block_pos, index) = pos.split_index();
+        let block = self.block_mut(block_pos)?;
+        block.nodes[index] = node;
+        block.day_night_differs |= node.day_night_differs();
+        Ok(())
+    }
+
+    /// Returns the metadata of the node at the given position if there is any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map block could not be loaded.
+    pub fn metadata(&mut self, pos: MapNodePos) -> Result<Option<&NodeMetadata>> {
+        let (block_pos, index) = pos.split_index();
+        Ok(self.block(block_pos)?.and_then(|block| {
+            block
+                .metadata
+                .iter()
+                .find_map(|(meta_index, metadata)| (*meta_index == index).then_some(metadata))
+        }))
+    }
+
+    /// Replaces or removes the metadata of the node at the given position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map block could not be loaded.
+    pub fn set_metadata(&mut self, pos: MapNodePos, metadata: Option<NodeMetadata>) -> Result<()> {
+        let (block_pos, index) = pos.split_index();
+        let block = self.block_mut(block_pos)?;
+        block
+            .metadata
+            .retain(|(meta_index, _)| *meta_index != index);
+        if let Some(metadata) = metadata {
+            block.metadata.push((index, metadata));
+        }
+        Ok(())
+    }
+
+    /// Writes all modified map blocks to the storage and returns their number.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a map block could not be stored.
+    pub fn flush(&mut self) -> Result<usize> {
+        let mut count = 0;
+        for pos in self.modified.drain() {
+            if let Some(Some(block)) = self.blocks.get(&pos) {
+                self.storage.store_block(block)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn block(&mut self, pos: MapBlockPos) -> Result<Option<&WorldBlock>> {
+        if !self.blocks.contains_key(&pos) {
+            let block = self.storage.load_block(pos)?;
+            self.blocks.insert(pos, block);
+        }
+        Ok(self.blocks.get(&pos).and_then(Option::as_ref))
+    }
+
+    /// Returns a map block for modification, creating it if necessary.
+    fn block_mut(&mut self, pos: MapBlockPos) -> Result<&mut WorldBlock> {
+        self.block(pos)?;
+        self.modified.insert(pos);
+        let block = self
+            .blocks
+            .entry(pos)
+            .or_default()
+            .get_or_insert_with(|| empty_block(pos));
+        // the light has to be recalculated by whoever loads the block next
+        block.lighting_complete = 0;
+        Ok(block)
+    }
+}
+
+/// Creates a map block consisting of unlit air.
+pub(super) fn empty_block(pos: MapBlockPos) -> WorldBlock {
+    let air = MapNode {
+        content_id: ContentId::AIR,
+        param1: 0,
+        param2: 0,
+    };
+    WorldBlock {
+        version: 0,
+        pos,
+        is_underground: false,
+        day_night_differs: false,
+        lighting_complete: 0,
+        nodes: MapBlockNodes([air; MapBlockPos::NODE_COUNT as usize]),
+        metadata: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::sync::Arc;
+
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapNode, MapNodePos};
+    use luanti_protocol::types::{Inventory, NodeMetadata};
+
+    use super::WorldEditor;
+    use crate::world::{
+        content_id_map::ContentIdMap, content_id_mapper::ContentIdMapper,
+        storage::dummy::DummyStorage,
+    };
+
+    #[test]
+    fn missing_blocks_are_created_on_write() {
+        let mut storage = DummyStorage;
+        let mut editor = WorldEditor::new(
+            &mut storage,
+            Arc::new(ContentIdMapper::new(ContentIdMap::new())),
+        );
+        let pos = MapNodePos(I16Vec3::new(-1, 20, 33));
+        let stone = MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2: 3,
+        };
+
+        assert_eq!(editor.node(pos).unwrap(), None);
+        editor.set_node(pos, stone).unwrap();
+        assert_eq!(editor.node(pos).unwrap(), Some(stone));
+        let neighbor = MapNodePos(I16Vec3::new(-2, 20, 33));
+        assert_eq!(
+            editor.node(neighbor).unwrap().unwrap().content_id,
+            ContentId::AIR
+        );
+
+        let metadata = NodeMetadata {
+            stringvars: vec![],
+            inventory: Inventory { entries: vec![] },
+        };
+        editor.set_metadata(pos, Some(metadata.clone())).unwrap();
+        assert_eq!(editor.metadata(pos).unwrap(), Some(&metadata));
+        editor.set_metadata(pos, None).unwrap();
+        assert_eq!(editor.metadata(pos).unwrap(), None);
+
+        assert_eq!(editor.flush().unwrap(), 1);
+        assert_eq!(editor.flush().unwrap(), 0);
+    }
+}
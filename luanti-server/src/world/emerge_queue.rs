@@ -0,0 +1,159 @@
+//! Contains the `EmergeQueue`
+//!
+//! Loading or generating a map block ("emerging" it) takes much longer than routing a request
+//! for it. Requests therefore wait in an [`EmergeQueue`] which merges repeated requests for the
+//! same map block and hands out the most important map blocks first.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+use luanti_core::MapBlockPos;
+
+use super::priority::Priority;
+
+/// Settings of the emerge queue of a `MapBlockProvider`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmergeConfig {
+    /// maximum number of map blocks being loaded or generated before new requests are being
+    /// accepted again; this keeps the priorities up to date while the queue is long
+    pub max_per_tick: usize,
+}
+
+impl Default for EmergeConfig {
+    fn default() -> Self {
+        Self { max_per_tick: 16 }
+    }
+}
+
+/// A queue of map blocks waiting to be loaded or generated
+///
+/// Every map block is queued at most once. Requesting a queued map block again only updates its
+/// priority, and requesting it with [`Priority::NONE`] removes it from the queue.
+#[derive(Debug, Default)]
+pub(crate) struct EmergeQueue {
+    /// the current priority of every queued map block
+    pending: HashMap<MapBlockPos, Priority>,
+    /// the queued map blocks by priority; entries whose priority doesn't match `pending` anymore
+    /// are outdated and skipped
+    entries: BinaryHeap<QueueEntry>,
+    /// increases with every entry to hand out map blocks of the same priority in request order
+    sequence: u64,
+}
+
+impl EmergeQueue {
+    /// Adds a map block to the queue or updates the priority of a queued one. Returns `true` if
+    /// the map block has already been queued.
+    pub(crate) fn push(&mut self, pos: MapBlockPos, priority: Priority) -> bool {
+        if priority.is_none() {
+            return self.pending.remove(&pos).is_some();
+        }
+        let previous = self.pending.insert(pos, priority);
+        if previous != Some(priority) {
+            self.entries.push(QueueEntry {
+                priority,
+                sequence: Reverse(self.sequence),
+                pos,
+            });
+            self.sequence += 1;
+            self.discard_outdated();
+        }
+        previous.is_some()
+    }
+
+    /// Removes the map block with the highest priority from the queue.
+    pub(crate) fn pop(&mut self) -> Option<MapBlockPos> {
+        while let Some(entry) = self.entries.pop() {
+            if self.pending.get(&entry.pos) == Some(&entry.priority) {
+                self.pending.remove(&entry.pos);
+                return Some(entry.pos);
+            }
+        }
+        None
+    }
+
+    /// Returns the number of queued map blocks.
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Removes outdated entries once they outnumber the queued map blocks.
+    fn discard_outdated(&mut self) {
+        if self.entries.len() <= 2 * self.pending.len() + 64 {
+            return;
+        }
+        let pending = &self.pending;
+        self.entries
+            .retain(|entry| pending.get(&entry.pos) == Some(&entry.priority));
+    }
+}
+
+#[derive(Debug)]
+struct QueueEntry {
+    priority: Priority,
+    sequence: Reverse<u64>,
+    pos: MapBlockPos,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I16Vec3;
+    use luanti_core::MapBlockPos;
+
+    use super::EmergeQueue;
+    use crate::world::priority::Priority;
+
+    #[test]
+    fn requests_are_merged_and_prioritized() {
+        let block = |x| MapBlockPos::new(I16Vec3::new(x, 0, 0)).unwrap_or(MapBlockPos::ZERO);
+        let mut queue = EmergeQueue::default();
+        assert!(!queue.push(block(1), Priority::MIN));
+        assert!(!queue.push(block(2), Priority::MIN));
+        assert!(!queue.push(block(3), Priority::MIN));
+        assert!(!queue.push(block(4), Priority::MAX));
+        // other players request the same blocks
+        assert!(queue.push(block(1), Priority::MIN));
+        assert!(queue.push(block(3), Priority::MAX));
+        assert_eq!(queue.len(), 4);
+
+        // cancelled requests are dropped
+        assert!(queue.push(block(2), Priority::NONE));
+        assert!(!queue.push(block(5), Priority::NONE));
+        assert_eq!(queue.len(), 3);
+
+        assert_eq!(queue.pop(), Some(block(4)));
+        assert_eq!(queue.pop(), Some(block(3)));
+        assert_eq!(queue.pop(), Some(block(1)));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.len(), 0);
+
+        // many priority changes don't pile up
+        for round in 0..1000_u16 {
+            queue.push(block(1), Priority::from(f32::from(round % 7) / 7.0));
+        }
+        assert!(queue.entries.len() <= 2 + 64);
+        assert_eq!(queue.pop(), Some(block(1)));
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! Contains the downsampling of map blocks into low-detail far blocks
+//!
+//! A far block has the same size as a map block, but each of its nodes represents a cube of
+//! `2^level` nodes along each axis. This allows clients to show distant regions of the world
+//! without having to transfer every single map block.
+
+use anyhow::{Result, bail};
+use glam::{I16Vec3, UVec3};
+use luanti_core::{MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex, MapNodePos, Region};
+use luanti_protocol::commands::server_to_client::FarBlocksSpec;
+
+use super::WorldBlock;
+
+/// The highest supported level; a far block of this level covers 16 map blocks along each axis.
+pub const MAX_LEVEL: u8 = 4;
+
+/// The position of a far block of a certain level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FarBlockPos {
+    level: u8,
+    /// the covered map block with the lowest coordinates
+    origin: MapBlockPos,
+}
+
+impl FarBlockPos {
+    /// Returns the position of the far block of the given level which covers a map block.
+    ///
+    /// Returns `None` if the level exceeds [`MAX_LEVEL`].
+    #[must_use]
+    pub fn containing(pos: MapBlockPos, level: u8) -> Option<Self> {
+        if level > MAX_LEVEL {
+            return None;
+        }
+        let mask = !((1_i16 << level) - 1);
+        // aligning towards the lower end never leaves the world as its boundaries are aligned
+        let origin = MapBlockPos::new(I16Vec3::from_array(
+            pos.vec().to_array().map(|coord| coord & mask),
+        ))?;
+        Some(Self { level, origin })
+    }
+
+    /// Returns the level of detail. Each node of the far block covers `2^level` nodes along each
+    /// axis.
+    #[must_use]
+    pub fn level(self) -> u8 {
+        self.level
+    }
+
+    /// Returns the covered map block with the lowest coordinates.
+    #[must_use]
+    pub fn origin(self) -> MapBlockPos {
+        self.origin
+    }
+
+    /// Returns the number of covered map blocks along each axis.
+    #[must_use]
+    pub fn span(self) -> i16 {
+        1 << self.level
+    }
+
+    /// Returns `true` if the given map block is covered by this far block.
+    #[must_use]
+    pub fn covers(self, pos: MapBlockPos) -> bool {
+        let offset = pos.vec() - self.origin.vec();
+        offset.cmpge(I16Vec3::ZERO).all() && offset.cmplt(I16Vec3::splat(self.span())).all()
+    }
+
+    /// Returns all map blocks covered by this far block.
+    pub fn map_blocks(self) -> impl Iterator<Item = MapBlockPos> {
+        let origin = self.origin.vec();
+        MapBlockPos::blocks_within(Region::new(
+            origin,
+            origin.saturating_add(I16Vec3::splat(self.span() - 1)),
+        ))
+    }
+}
+
+/// A low-detail block which has been created by a [`FarBlockBuilder`]
+pub struct FarBlock {
+    pub(crate) pos: FarBlockPos,
+    /// the downsampled nodes; the position is the origin of the far block
+    pub(crate) block: WorldBlock,
+}
+
+impl FarBlock {
+    /// Returns the position of this far block.
+    #[must_use]
+    pub fn pos(&self) -> FarBlockPos {
+        self.pos
+    }
+}
+
+impl From<FarBlock> for FarBlocksSpec {
+    fn from(far_block: FarBlock) -> Self {
+        Self {
+            pos: far_block.pos.origin.vec(),
+            level: far_block.pos.level,
+            block: far_block.block.into(),
+        }
+    }
+}
+
+/// Combines the map blocks covered by a far block into a single block of reduced resolution.
+///
+/// Each node of the far block becomes the content which occurs most often within the cube it
+/// represents. Solid content wins over air if both are equally frequent, so thin surfaces don't
+/// disappear. Nodes of map blocks which haven't been added remain
+/// [`luanti_core::ContentId::IGNORE`].
+pub struct FarBlockBuilder {
+    pos: FarBlockPos,
+    /// occurrences of each content for every node of the far block; the first node found for a
+    /// content provides its parameters
+    votes: Vec<Vec<(MapNode, u32)>>,
+    is_underground: bool,
+    day_night_differs: bool,
+}
+
+impl FarBlockBuilder {
+    /// Creates a builder for the given far block without any map blocks.
+    #[must_use]
+    pub fn new(pos: FarBlockPos) -> Self {
+        Self {
+            pos,
+            votes: vec![Vec::new(); usize::from(MapBlockPos::NODE_COUNT)],
+            is_underground: true,
+            day_night_differs: false,
+        }
+    }
+
+    /// Adds the nodes of a map block.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map block isn't covered by the far block.
+    pub fn add(&mut self, block: &WorldBlock) -> Result<()> {
+        if !self.pos.covers(block.pos) {
+            bail!(
+                "map block {} isn't covered by the far block at {} (level {})",
+                block.pos,
+                self.pos.origin,
+                self.pos.level
+            );
+        }
+        self.is_underground &= block.is_underground;
+        self.day_night_differs |= block.day_night_differs;
+
+        let block_offset = (block.pos.vec() - self.pos.origin.vec()).as_uvec3()
+            * UVec3::splat(MapBlockPos::SIZE.into());
+        let shift = UVec3::splat(self.pos.level.into());
+        for (index, node) in block.nodes.0.iter().enumerate() {
+            if node.content_id.is_ignore() {
+                continue;
+            }
+            let cell = (block_offset + UVec3::from(MapNodeIndex::from(index))) >> shift;
+            let cell_index = MapNodeIndex::for_node(MapNodePos(cell.as_i16vec3()));
+            let Some(votes) = self.votes.get_mut(usize::from(cell_index)) else {
+                continue;
+            };
+            if let Some((_, count)) = votes
+                .iter_mut()
+                .find(|(voted, _)| voted.content_id == node.content_id)
+            {
+                *count += 1;
+            } else {
+                votes.push((*node, 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the far block from all map blocks which have been added.
+    #[must_use]
+    pub fn build(self) -> FarBlock {
+        let mut nodes = MapBlockNodes([MapNode::default(); MapBlockPos::NODE_COUNT as usize]);
+        for (node, votes) in nodes.0.iter_mut().zip(self.votes) {
+            if let Some((winner, _)) = votes
+                .into_iter()
+                .max_by_key(|&(voted, count)| (count, !voted.content_id.is_air()))
+            {
+                *node = winner;
+            }
+        }
+
+        FarBlock {
+            pos: self.pos,
+            block: WorldBlock {
+                version: 0,
+                pos: self.pos.origin,
+                is_underground: self.is_underground,
+                day_night_differs: self.day_night_differs,
+                // the client cannot compute any lighting for blocks of reduced resolution
+                lighting_complete: 0xffff,
+                nodes,
+                metadata: Vec::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::{I16Vec3, U16Vec3};
+    use luanti_core::{ContentId, MapBlockPos, MapNodeIndex, MapNodePos};
+
+    use super::{FarBlockBuilder, FarBlockPos};
+    use crate::world::WorldBlock;
+    use crate::world::generation::mapgen::MapgenBlock;
+
+    const STONE: ContentId = ContentId(1);
+
+    fn pos(x: i16, y: i16, z: i16) -> MapBlockPos {
+        MapBlockPos::new(I16Vec3::new(x, y, z)).unwrap()
+    }
+
+    /// Creates a map block consisting of stone below the given height and air above.
+    fn block(pos: MapBlockPos, height: u16) -> WorldBlock {
+        let mut block = MapgenBlock::new(0, pos).into_world_block();
+        for (index, node) in block.nodes.0.iter_mut().enumerate() {
+            let y = U16Vec3::from(MapNodeIndex::from(index)).y;
+            node.content_id = if y < height { STONE } else { ContentId::AIR };
+        }
+        block
+    }
+
+    #[test]
+    fn far_blocks_are_aligned_to_their_level() {
+        let far_pos = FarBlockPos::containing(pos(-3, 5, 2), 2).unwrap();
+        assert_eq!(far_pos.origin(), pos(-4, 4, 0));
+        assert_eq!(far_pos.map_blocks().count(), 64);
+        assert!(far_pos.map_blocks().all(|covered| far_pos.covers(covered)));
+        assert!(!far_pos.covers(pos(0, 4, 0)));
+        assert!(FarBlockPos::containing(pos(0, 0, 0), 5).is_none());
+    }
+
+    #[test]
+    fn nodes_become_the_most_frequent_content() {
+        let far_pos = FarBlockPos::containing(pos(0, 0, 0), 1).unwrap();
+        let mut builder = FarBlockBuilder::new(far_pos);
+        builder.add(&block(pos(0, 0, 0), 3)).unwrap();
+        builder.add(&block(pos(1, 0, 1), 16)).unwrap();
+        assert!(builder.add(&block(pos(2, 0, 0), 16)).is_err());
+        let far_block = builder.build();
+
+        let content = |x: i16, y: i16, z: i16| {
+            let index = MapNodeIndex::for_node(MapNodePos(I16Vec3::new(x, y, z)));
+            far_block.block.nodes[index].content_id
+        };
+        // the lowest three nodes are stone; the second cell is tied and prefers stone
+        assert_eq!(content(0, 0, 0), STONE);
+        assert_eq!(content(0, 1, 0), STONE);
+        assert_eq!(content(0, 2, 0), ContentId::AIR);
+        // the block at (1, 0, 1) is solid
+        assert_eq!(content(8, 7, 8), STONE);
+        // the block at (1, 0, 0) hasn't been added
+        assert!(content(8, 0, 0).is_ignore());
+    }
+}
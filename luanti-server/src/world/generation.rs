@@ -1,12 +1,20 @@
 //! Contains the `WorldGenerator` trait and some implementations thereof.
 
+pub mod decoration;
 pub mod flat;
+pub mod mapgen;
+pub mod noise;
+pub mod ore;
+pub mod random;
+pub mod v7;
 
 use luanti_core::MapBlockPos;
 
 use super::WorldBlock;
 
 /// This trait is implemented by map generators.
+///
+/// It is implemented automatically for every [`mapgen::Mapgen`].
 pub trait WorldGenerator: Send + Sync {
     /// generate and return a new `WorldBlock` for the given position.
     fn generate_block(&self, pos: MapBlockPos) -> WorldBlock;
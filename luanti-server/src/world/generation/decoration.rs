@@ -0,0 +1,290 @@
+//! Placement of plants, trees and other structures on top of the terrain
+//!
+//! All decorations of a [`DecorationRegistry`] are placed during [`StageKind::Decoration`] on
+//! the surface given by [`MapgenBlock::heightmap`] and [`MapgenBlock::surface`]. Whether a
+//! decoration appears at a position only depends on the world seed and the position itself, so
+//! structures continue seamlessly into the blocks above. Structures reaching into horizontally
+//! neighboring blocks are cut off at the block border.
+
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use glam::{I16Vec3, U16Vec3};
+use luanti_core::{ContentId, MapNode, MapNodePos};
+
+use super::mapgen::{BiomeId, MapgenBlock, MapgenStage, StageKind, node_seed, stage_seed};
+use super::random::PcgRandom;
+
+/// A box of nodes to be placed as a whole
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    size: U16Vec3,
+    /// `None` leaves the existing node untouched
+    nodes: Vec<Option<MapNode>>,
+}
+
+impl Schematic {
+    /// Creates a schematic from nodes ordered by x, then y, then z.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of nodes doesn't match the size.
+    pub fn new(size: U16Vec3, nodes: Vec<Option<MapNode>>) -> Result<Self> {
+        let volume = usize::from(size.x) * usize::from(size.y) * usize::from(size.z);
+        if nodes.len() != volume {
+            bail!(
+                "schematic of size {size} requires {volume} nodes but got {count}",
+                count = nodes.len()
+            );
+        }
+        Ok(Self { size, nodes })
+    }
+
+    /// Creates a simple tree with a trunk and a cube of leaves around its top.
+    ///
+    /// The trunk is placed at the horizontal center of the schematic.
+    #[must_use]
+    pub fn tree(trunk: ContentId, leaves: ContentId, trunk_height: u16, radius: u16) -> Self {
+        let width = 2 * radius + 1;
+        let height = trunk_height + radius;
+        let leaves_from = trunk_height.saturating_sub(radius + 1);
+        let nodes = (0..width)
+            .flat_map(|z| (0..height).map(move |y| (y, z)))
+            .flat_map(|(y, z)| (0..width).map(move |x| U16Vec3::new(x, y, z)))
+            .map(|pos| {
+                let content_id = if pos.x == radius && pos.z == radius && pos.y < trunk_height {
+                    trunk
+                } else if pos.y >= leaves_from {
+                    leaves
+                } else {
+                    return None;
+                };
+                Some(MapNode {
+                    content_id,
+                    ..MapNode::default()
+                })
+            })
+            .collect();
+        Self {
+            size: U16Vec3::new(width, height, width),
+            nodes,
+        }
+    }
+
+    /// The size of this schematic
+    #[must_use]
+    pub fn size(&self) -> U16Vec3 {
+        self.size
+    }
+
+    /// Returns all nodes to be placed along with their offsets.
+    pub fn nodes(&self) -> impl Iterator<Item = (I16Vec3, MapNode)> + '_ {
+        let size = self.size.as_i16vec3();
+        (0..size.z)
+            .flat_map(move |z| (0..size.y).map(move |y| (y, z)))
+            .flat_map(move |(y, z)| (0..size.x).map(move |x| I16Vec3::new(x, y, z)))
+            .zip(&self.nodes)
+            .filter_map(|(offset, node)| node.map(|node| (offset, node)))
+    }
+}
+
+/// What a decoration consists of
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecorationKind {
+    /// A column of a single node, e.g. grass, flowers or cacti
+    Simple {
+        /// the node to be placed
+        node: ContentId,
+        /// the minimum height of the column
+        height: u16,
+        /// the maximum height of the column
+        height_max: u16,
+    },
+    /// A schematic, e.g. a tree; horizontally centered above the surface node
+    Schematic(Arc<Schematic>),
+}
+
+/// A single type of decoration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    /// the decoration is only placed on top of these nodes
+    pub place_on: Vec<ContentId>,
+    /// the probability of the decoration being placed on each suitable surface node
+    pub fill_ratio: f32,
+    /// the lowest height of the surface node
+    pub y_min: i16,
+    /// the greatest height of the surface node
+    pub y_max: i16,
+    /// restricts the decoration to these biomes; all biomes if empty
+    pub biomes: Vec<BiomeId>,
+    /// the content
+    pub kind: DecorationKind,
+}
+
+/// A collection of decorations to be placed on the terrain
+///
+/// Each column receives at most one decoration, tried in order of registration. Decorations
+/// never replace anything but air, so overlapping structures keep the parts placed first.
+pub struct DecorationRegistry {
+    world_seed: u64,
+    decorations: Vec<(Decoration, u64)>,
+}
+
+impl DecorationRegistry {
+    /// Creates an empty registry for a world with the given seed.
+    #[must_use]
+    pub fn new(world_seed: u64) -> Self {
+        Self {
+            world_seed,
+            decorations: Vec::new(),
+        }
+    }
+
+    /// Adds a decoration.
+    pub fn register(&mut self, decoration: Decoration) {
+        let seed = stage_seed(
+            self.world_seed,
+            &format!("decoration{}", self.decorations.len()),
+        );
+        self.decorations.push((decoration, seed));
+    }
+
+    /// Returns `true` if no decoration has been registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.decorations.is_empty()
+    }
+}
+
+impl MapgenStage for DecorationRegistry {
+    fn kind(&self) -> StageKind {
+        StageKind::Decoration
+    }
+
+    fn apply(&self, block: &mut MapgenBlock) {
+        let origin = MapNodePos::from(block.pos()).0;
+        for (column, (x, z)) in MapgenBlock::column_offsets().enumerate() {
+            let (Some(&surface), Some(&ground)) =
+                (block.heightmap.get(column), block.surface.get(column))
+            else {
+                continue;
+            };
+            if surface == i16::MIN {
+                continue;
+            }
+            let surface_pos = MapNodePos(I16Vec3::new(origin.x + x, surface, origin.z + z));
+            let base = surface_pos.0 + I16Vec3::Y;
+
+            for (decoration, seed) in &self.decorations {
+                if !(decoration.y_min..=decoration.y_max).contains(&surface)
+                    || !decoration.place_on.contains(&ground)
+                    || !block.in_biomes(&decoration.biomes, column)
+                {
+                    continue;
+                }
+                let mut random = PcgRandom::new(node_seed(*seed, surface_pos));
+                if !random.chance(decoration.fill_ratio) {
+                    continue;
+                }
+                match &decoration.kind {
+                    DecorationKind::Simple {
+                        node,
+                        height,
+                        height_max,
+                    } => {
+                        let height = random.range(i32::from(*height), i32::from(*height_max));
+                        let node = MapNode {
+                            content_id: *node,
+                            ..MapNode::default()
+                        };
+                        for step in 0..i16::try_from(height).unwrap_or_default() {
+                            place(block, base + I16Vec3::Y * step, node);
+                        }
+                    }
+                    DecorationKind::Schematic(schematic) => {
+                        let size = schematic.size().as_i16vec3();
+                        let corner = base - I16Vec3::new(size.x / 2, 0, size.z / 2);
+                        for (offset, node) in schematic.nodes() {
+                            place(block, corner + offset, node);
+                        }
+                    }
+                }
+                // only a single decoration per column
+                break;
+            }
+        }
+    }
+}
+
+/// Returns `true` if the node at the given position is within the block and not occupied.
+fn is_free(block: &MapgenBlock, pos: I16Vec3) -> bool {
+    let pos = MapNodePos(pos);
+    if !block.pos().contains(pos) {
+        return false;
+    }
+    let content_id = block.nodes[pos.index()].content_id;
+    content_id.is_air() || content_id.is_ignore()
+}
+
+/// Places a node if its position is free.
+fn place(block: &mut MapgenBlock, pos: I16Vec3, node: MapNode) {
+    if is_free(block, pos) {
+        block.nodes[MapNodePos(pos).index()] = node;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use glam::{I16Vec3, U16Vec3};
+    use luanti_core::{ContentId, MapBlockPos};
+
+    use super::{Decoration, DecorationKind, DecorationRegistry, Schematic};
+    use crate::world::generation::flat::MapgenFlat;
+    use crate::world::generation::mapgen::{Mapgen, MapgenBlock, MapgenStage};
+
+    const GRASS: ContentId = ContentId(1);
+    const TRUNK: ContentId = ContentId(2);
+    const LEAVES: ContentId = ContentId(3);
+
+    #[test]
+    fn trees_grow_on_grass() {
+        let tree = Schematic::tree(TRUNK, LEAVES, 4, 1);
+        assert_eq!(tree.size(), U16Vec3::new(3, 5, 3));
+        let trunk = tree.nodes().filter(|(_, node)| node.content_id == TRUNK);
+        assert_eq!(trunk.count(), 4);
+
+        let mut registry = DecorationRegistry::new(5);
+        registry.register(Decoration {
+            place_on: vec![GRASS],
+            fill_ratio: 0.1,
+            y_min: i16::MIN,
+            y_max: i16::MAX,
+            biomes: vec![],
+            kind: DecorationKind::Schematic(Arc::new(tree)),
+        });
+        let count = |block: &MapgenBlock, content_id| {
+            block
+                .nodes
+                .0
+                .iter()
+                .filter(|node| node.content_id == content_id)
+                .count()
+        };
+
+        // the surface of the flat map generator is located in the block below
+        let mut block = MapgenBlock::new(5, MapBlockPos::ZERO);
+        MapgenFlat::new(GRASS).terrain(&mut block);
+        registry.apply(&mut block);
+        assert!(count(&block, TRUNK) > 0);
+        assert!(count(&block, LEAVES) > count(&block, TRUNK));
+
+        // nothing grows underground
+        let below = MapBlockPos::for_vec(I16Vec3::NEG_Y);
+        let mut underground = MapgenBlock::new(5, below);
+        MapgenFlat::new(GRASS).terrain(&mut underground);
+        registry.apply(&mut underground);
+        assert_eq!(count(&underground, TRUNK) + count(&underground, LEAVES), 0);
+    }
+}
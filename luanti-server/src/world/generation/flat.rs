@@ -1,8 +1,7 @@
 //! contains `MapgenFlat`
 
-use super::WorldGenerator;
-use crate::world::WorldBlock;
-use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex, MapNodePos};
+use super::mapgen::{Mapgen, MapgenBlock, MapgenStage, StageKind};
+use luanti_core::{ContentId, MapNodeIndex};
 
 /// Generates a world where all nodes below z=0 are of a given type, while everything above is air.
 pub struct MapgenFlat {
@@ -17,29 +16,30 @@ impl MapgenFlat {
     }
 }
 
-impl WorldGenerator for MapgenFlat {
-    fn generate_block(&self, map_block_pos: MapBlockPos) -> WorldBlock {
-        let nodes = std::array::from_fn(|index| {
-            let node_pos = map_block_pos.node_pos(MapNodeIndex::from(index));
-            let content_id = match node_pos.0.y {
-                i16::MIN..0 => self.node,
-                _ => ContentId::AIR,
-            };
-            MapNode {
-                content_id,
-                param1: 255,
-                param2: 255,
-            }
-        });
+impl Mapgen for MapgenFlat {
+    fn seed(&self) -> u64 {
+        0
+    }
 
-        WorldBlock {
-            version: 0,
-            pos: map_block_pos,
-            is_underground: MapNodePos::from(map_block_pos).0.y < 0,
-            day_night_differs: false,
-            lighting_complete: 0xffff,
-            nodes: MapBlockNodes(nodes),
-            metadata: vec![],
+    fn terrain(&self, block: &mut MapgenBlock) {
+        block.heightmap.fill(-1);
+        block.surface.fill(self.node);
+        for index in 0..block.nodes.0.len() {
+            let index = MapNodeIndex::from(index);
+            if block.node_pos(index).0.y < 0 {
+                block.nodes[index].content_id = self.node;
+            }
         }
     }
 }
+
+/// Allows using this generator as the terrain stage of a `MapgenPipeline`.
+impl MapgenStage for MapgenFlat {
+    fn kind(&self) -> StageKind {
+        StageKind::Terrain
+    }
+
+    fn apply(&self, block: &mut MapgenBlock) {
+        self.terrain(block);
+    }
+}
@@ -0,0 +1,414 @@
+//! A map generator framework composed of individual stages
+//!
+//! A [`Mapgen`] fills a [`MapgenBlock`] in four passes: biomes, terrain, caves and decorations.
+//! Every type implementing [`Mapgen`] automatically is a [`WorldGenerator`].
+//!
+//! [`MapgenPipeline`] implements [`Mapgen`] by running a list of [`MapgenStage`]s, which may be
+//! looked up by name from a [`StageRegistry`].
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use glam::{I16Vec3, U16Vec3};
+use luanti_core::{
+    ContentId, LIGHT_SUN, MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex, MapNodePos,
+};
+
+use super::WorldGenerator;
+use crate::world::WorldBlock;
+
+/// number of node columns within a map block
+pub const COLUMN_COUNT: usize = MapBlockPos::SIZE as usize * MapBlockPos::SIZE as usize;
+
+/// Identifies a biome; the meaning is up to the stages of a map generator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BiomeId(pub u16);
+
+/// The passes a map block goes through during generation, in order of execution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StageKind {
+    /// Decides about the biome of each column.
+    Biome,
+    /// Creates the base terrain and fills the height map and the surface nodes.
+    Terrain,
+    /// Carves caves out of the terrain.
+    Caves,
+    /// Places ores, plants, trees, etc.
+    Decoration,
+}
+
+/// A map block being generated
+pub struct MapgenBlock {
+    pos: MapBlockPos,
+    seed: u64,
+    /// The nodes of the block; initially filled with [`ContentId::IGNORE`] to mark them as not
+    /// being set yet
+    pub nodes: MapBlockNodes,
+    /// The biome of each column, indexed by [`Self::column_index`]
+    pub biomes: [BiomeId; COLUMN_COUNT],
+    /// The y-coordinate of the topmost solid node of each column, indexed by
+    /// [`Self::column_index`]; `i16::MIN` if unknown
+    pub heightmap: [i16; COLUMN_COUNT],
+    /// The topmost solid node of each column, indexed by [`Self::column_index`]; this may lie
+    /// outside of the block
+    pub surface: [ContentId; COLUMN_COUNT],
+}
+
+impl MapgenBlock {
+    /// Creates an empty block to be filled by a map generator with the given world seed.
+    #[must_use]
+    pub fn new(world_seed: u64, pos: MapBlockPos) -> Self {
+        Self {
+            pos,
+            seed: block_seed(world_seed, pos),
+            nodes: MapBlockNodes([MapNode::default(); MapBlockPos::NODE_COUNT as usize]),
+            biomes: [BiomeId::default(); COLUMN_COUNT],
+            heightmap: [i16::MIN; COLUMN_COUNT],
+            surface: [ContentId::IGNORE; COLUMN_COUNT],
+        }
+    }
+
+    /// Position of the block being generated
+    #[must_use]
+    pub fn pos(&self) -> MapBlockPos {
+        self.pos
+    }
+
+    /// A seed unique to this block and world which should be used for all random decisions in
+    /// order to keep the generation reproducible.
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the world position of the node with the given index.
+    #[must_use]
+    pub fn node_pos(&self, index: MapNodeIndex) -> MapNodePos {
+        self.pos.node_pos(index)
+    }
+
+    /// Returns the index into [`Self::biomes`] and [`Self::heightmap`] for the column containing
+    /// the given node.
+    #[must_use]
+    pub fn column_index(index: MapNodeIndex) -> usize {
+        let local = U16Vec3::from(index);
+        usize::from(local.z * MapBlockPos::SIZE + local.x)
+    }
+
+    /// Returns the horizontal offsets of all columns within a block, in the order used by
+    /// [`Self::column_index`].
+    pub fn column_offsets() -> impl Iterator<Item = (i16, i16)> {
+        let size = i16::try_from(MapBlockPos::SIZE).unwrap_or_default();
+        (0..size).flat_map(move |z| (0..size).map(move |x| (x, z)))
+    }
+
+    /// Returns `true` if the column with the given index belongs to any of the given biomes or if
+    /// `biomes` is empty.
+    #[must_use]
+    pub fn in_biomes(&self, biomes: &[BiomeId], column: usize) -> bool {
+        biomes.is_empty()
+            || self
+                .biomes
+                .get(column)
+                .is_some_and(|biome| biomes.contains(biome))
+    }
+
+    /// Converts the generated data into a world block.
+    ///
+    /// Nodes which haven't been set by any stage become sunlit air.
+    #[must_use]
+    pub fn into_world_block(mut self) -> WorldBlock {
+        let air = MapNode {
+            content_id: ContentId::AIR,
+            param1: MapNode::pack_light(LIGHT_SUN, LIGHT_SUN),
+            param2: 0,
+        };
+        for node in &mut self.nodes.0 {
+            if node.content_id.is_ignore() {
+                *node = air;
+            }
+        }
+        let top = self.pos.node_pos(MapNodeIndex::MAX).0.y;
+        let is_underground = self.heightmap.iter().all(|&surface| surface >= top);
+        let day_night_differs = self.nodes.0.iter().any(MapNode::day_night_differs);
+        WorldBlock {
+            version: 0,
+            pos: self.pos,
+            is_underground,
+            day_night_differs,
+            lighting_complete: 0xffff,
+            nodes: self.nodes,
+            metadata: vec![],
+        }
+    }
+}
+
+/// A map generator made of four consecutive passes.
+///
+/// Each pass receives the same [`MapgenBlock`] and may modify it freely.
+pub trait Mapgen: Send + Sync {
+    /// The seed of the world being generated
+    fn seed(&self) -> u64;
+
+    /// Decides about the biome of each column.
+    fn biomes(&self, _block: &mut MapgenBlock) {}
+
+    /// Creates the base terrain and fills the height map and the surface nodes.
+    fn terrain(&self, block: &mut MapgenBlock);
+
+    /// Carves caves out of the terrain.
+    fn caves(&self, _block: &mut MapgenBlock) {}
+
+    /// Places ores, plants, trees, etc.
+    fn decorations(&self, _block: &mut MapgenBlock) {}
+}
+
+impl<T: Mapgen> WorldGenerator for T {
+    fn generate_block(&self, pos: MapBlockPos) -> WorldBlock {
+        let mut block = MapgenBlock::new(self.seed(), pos);
+        self.biomes(&mut block);
+        self.terrain(&mut block);
+        self.caves(&mut block);
+        self.decorations(&mut block);
+        block.into_world_block()
+    }
+}
+
+/// A single pass of a [`MapgenPipeline`]
+pub trait MapgenStage: Send + Sync {
+    /// The pass this stage belongs to
+    fn kind(&self) -> StageKind;
+
+    /// Applies this stage to a block.
+    fn apply(&self, block: &mut MapgenBlock);
+}
+
+/// Creates a stage given the seed derived for this stage.
+pub type StageFactory = Box<dyn Fn(u64) -> Box<dyn MapgenStage> + Send + Sync>;
+
+/// A collection of named stages which may be used to assemble a [`MapgenPipeline`].
+#[derive(Default)]
+pub struct StageRegistry {
+    factories: HashMap<String, StageFactory>,
+}
+
+impl StageRegistry {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a stage under the given name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if another stage has already been registered under this name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(u64) -> Box<dyn MapgenStage> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let name = name.into();
+        if self.factories.contains_key(&name) {
+            bail!("mapgen stage '{name}' has already been registered");
+        }
+        self.factories.insert(name, Box::new(factory));
+        Ok(())
+    }
+
+    /// Returns the names of all registered stages.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    /// Creates the stage registered under the given name for a world with the given seed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no stage has been registered under this name.
+    pub fn create(&self, name: &str, world_seed: u64) -> Result<Box<dyn MapgenStage>> {
+        let Some(factory) = self.factories.get(name) else {
+            bail!("unknown mapgen stage '{name}'");
+        };
+        Ok(factory(stage_seed(world_seed, name)))
+    }
+
+    /// Creates a pipeline from the stages with the given names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the stages is unknown.
+    pub fn build(
+        &self,
+        world_seed: u64,
+        names: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<MapgenPipeline> {
+        let mut pipeline = MapgenPipeline::new(world_seed);
+        for name in names {
+            pipeline.push(self.create(name.as_ref(), world_seed)?);
+        }
+        Ok(pipeline)
+    }
+}
+
+/// A [`Mapgen`] running a list of stages.
+///
+/// Stages are executed by [`StageKind`] and then in the order they've been added.
+pub struct MapgenPipeline {
+    seed: u64,
+    stages: Vec<Box<dyn MapgenStage>>,
+}
+
+impl MapgenPipeline {
+    /// Creates a pipeline without any stages.
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Appends a stage.
+    pub fn push(&mut self, stage: Box<dyn MapgenStage>) {
+        self.stages.push(stage);
+        // stable sort keeps the insertion order within each kind
+        self.stages.sort_by_key(|added| added.kind());
+    }
+
+    /// Appends a stage; builder-style variant of [`Self::push`].
+    #[must_use]
+    pub fn with_stage(mut self, stage: impl MapgenStage + 'static) -> Self {
+        self.push(Box::new(stage));
+        self
+    }
+
+    fn run(&self, kind: StageKind, block: &mut MapgenBlock) {
+        for stage in self.stages.iter().filter(|stage| stage.kind() == kind) {
+            stage.apply(block);
+        }
+    }
+}
+
+impl Mapgen for MapgenPipeline {
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn biomes(&self, block: &mut MapgenBlock) {
+        self.run(StageKind::Biome, block);
+    }
+
+    fn terrain(&self, block: &mut MapgenBlock) {
+        self.run(StageKind::Terrain, block);
+    }
+
+    fn caves(&self, block: &mut MapgenBlock) {
+        self.run(StageKind::Caves, block);
+    }
+
+    fn decorations(&self, block: &mut MapgenBlock) {
+        self.run(StageKind::Decoration, block);
+    }
+}
+
+/// Derives the seed of a single block from the world seed.
+///
+/// The result only depends on its inputs and is stable across platforms and releases.
+#[must_use]
+pub fn block_seed(world_seed: u64, pos: MapBlockPos) -> u64 {
+    position_seed(world_seed, pos.vec())
+}
+
+/// Derives the seed of a single node from another seed.
+///
+/// This is useful for decisions which must not depend on the block being generated.
+#[must_use]
+pub fn node_seed(seed: u64, pos: MapNodePos) -> u64 {
+    position_seed(seed, pos.0)
+}
+
+fn position_seed(seed: u64, vec: I16Vec3) -> u64 {
+    [vec.x, vec.y, vec.z]
+        .into_iter()
+        .fold(mix(seed), |seed, coord| {
+            mix(seed ^ u64::from(u16::from_le_bytes(coord.to_le_bytes())))
+        })
+}
+
+/// Derives the seed of a stage from the world seed, so that stages using the same algorithm
+/// don't produce correlated results.
+#[must_use]
+pub fn stage_seed(world_seed: u64, name: &str) -> u64 {
+    // FNV-1a
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    mix(world_seed ^ hash)
+}
+
+/// The finalizer of `SplitMix64`
+fn mix(value: u64) -> u64 {
+    let value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapBlockPos, MapNodeIndex};
+
+    use super::{MapgenBlock, MapgenStage, StageKind, StageRegistry, block_seed};
+    use crate::world::generation::WorldGenerator;
+
+    struct Fill(StageKind, ContentId);
+
+    impl MapgenStage for Fill {
+        fn kind(&self) -> StageKind {
+            self.0
+        }
+
+        fn apply(&self, block: &mut MapgenBlock) {
+            block.nodes[MapNodeIndex::MIN].content_id = self.1;
+        }
+    }
+
+    #[test]
+    fn seeds_are_deterministic() {
+        let pos = MapBlockPos::new(I16Vec3::new(1, -2, 3)).unwrap();
+        let other = MapBlockPos::new(I16Vec3::new(3, -2, 1)).unwrap();
+        assert_eq!(block_seed(42, pos), block_seed(42, pos));
+        assert_ne!(block_seed(42, pos), block_seed(42, other));
+        assert_ne!(block_seed(42, pos), block_seed(43, pos));
+    }
+
+    #[test]
+    fn stages_run_in_order_of_kind() {
+        let mut registry = StageRegistry::new();
+        registry
+            .register("decoration", |_seed| {
+                Box::new(Fill(StageKind::Decoration, ContentId(1)))
+            })
+            .unwrap();
+        registry
+            .register("terrain", |_seed| {
+                Box::new(Fill(StageKind::Terrain, ContentId(2)))
+            })
+            .unwrap();
+        assert!(
+            registry
+                .register("terrain", |_seed| unreachable!())
+                .is_err()
+        );
+        assert!(registry.build(0, ["caves"]).is_err());
+
+        let pipeline = registry.build(0, ["decoration", "terrain"]).unwrap();
+        let block = pipeline.generate_block(MapBlockPos::ZERO);
+        assert_eq!(block.nodes[MapNodeIndex::MIN].content_id, ContentId(1));
+    }
+}
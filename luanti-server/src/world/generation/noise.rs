@@ -0,0 +1,238 @@
+//! Fractal value noise compatible with the noise functions of the C++ engine
+
+use glam::Vec3;
+
+const NOISE_MAGIC_X: i32 = 1619;
+const NOISE_MAGIC_Y: i32 = 31337;
+const NOISE_MAGIC_Z: i32 = 52591;
+const NOISE_MAGIC_SEED: i32 = 1013;
+
+/// Parameters of a fractal noise, equivalent to `NoiseParams` of the C++ engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseParams {
+    /// added to the result
+    pub offset: f32,
+    /// multiplied with the sum of all octaves
+    pub scale: f32,
+    /// size of the largest structures in nodes for each axis
+    pub spread: Vec3,
+    /// combined with the world seed to make different noises independent of each other
+    pub seed: i32,
+    /// number of layers of noise to be added
+    pub octaves: u16,
+    /// change of the amplitude from one octave to the next
+    pub persistence: f32,
+    /// change of the frequency from one octave to the next
+    pub lacunarity: f32,
+    /// whether to use a quintic curve instead of linear interpolation
+    pub eased: bool,
+}
+
+impl NoiseParams {
+    /// Creates parameters with default values for `lacunarity` and `eased`.
+    #[must_use]
+    pub const fn new(
+        offset: f32,
+        scale: f32,
+        spread: Vec3,
+        seed: i32,
+        octaves: u16,
+        persistence: f32,
+    ) -> Self {
+        Self {
+            offset,
+            scale,
+            spread,
+            seed,
+            octaves,
+            persistence,
+            lacunarity: 2.0,
+            eased: false,
+        }
+    }
+}
+
+/// A fractal noise bound to a world seed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Noise {
+    params: NoiseParams,
+    seed: i32,
+}
+
+impl Noise {
+    /// Creates a noise for the world with the given seed.
+    #[must_use]
+    pub fn new(params: NoiseParams, world_seed: u64) -> Self {
+        // like the engine, only the lower 32 bits of the world seed are being used
+        let [b0, b1, b2, b3, ..] = world_seed.to_le_bytes();
+        Self {
+            params,
+            seed: params
+                .seed
+                .wrapping_add(i32::from_le_bytes([b0, b1, b2, b3])),
+        }
+    }
+
+    /// The parameters of this noise
+    #[must_use]
+    pub fn params(&self) -> &NoiseParams {
+        &self.params
+    }
+
+    /// Samples the noise at the given horizontal position.
+    #[must_use]
+    pub fn get_2d(&self, x: f32, y: f32) -> f32 {
+        let params = &self.params;
+        let x = x / params.spread.x;
+        let y = y / params.spread.y;
+        let mut sum = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut seed = self.seed;
+        for _ in 0..params.octaves {
+            sum += amplitude * gradient_2d(x * frequency, y * frequency, seed, params.eased);
+            frequency *= params.lacunarity;
+            amplitude *= params.persistence;
+            seed = seed.wrapping_add(1);
+        }
+        params.offset + params.scale * sum
+    }
+
+    /// Samples the noise at the given position.
+    #[must_use]
+    pub fn get_3d(&self, pos: Vec3) -> f32 {
+        let params = &self.params;
+        let pos = pos / params.spread;
+        let mut sum = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut seed = self.seed;
+        for _ in 0..params.octaves {
+            sum += amplitude * gradient_3d(pos * frequency, seed, params.eased);
+            frequency *= params.lacunarity;
+            amplitude *= params.persistence;
+            seed = seed.wrapping_add(1);
+        }
+        params.offset + params.scale * sum
+    }
+}
+
+/// Pseudo-random value in the range `-1.0..=1.0` for an integer lattice point
+fn hash(n: i32) -> f32 {
+    let n = n & 0x7fff_ffff;
+    let n = (n >> 13) ^ n;
+    let n = n
+        .wrapping_mul(
+            n.wrapping_mul(n)
+                .wrapping_mul(60493)
+                .wrapping_add(19_990_303),
+        )
+        .wrapping_add(1_376_312_589)
+        & 0x7fff_ffff;
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "the engine performs the same lossy conversion"
+    )]
+    let value = n as f32;
+    1.0 - value / 2.0_f32.powi(30)
+}
+
+fn lattice_2d(x: i32, y: i32, seed: i32) -> f32 {
+    hash(
+        NOISE_MAGIC_X
+            .wrapping_mul(x)
+            .wrapping_add(NOISE_MAGIC_Y.wrapping_mul(y))
+            .wrapping_add(NOISE_MAGIC_SEED.wrapping_mul(seed)),
+    )
+}
+
+fn lattice_3d(x: i32, y: i32, z: i32, seed: i32) -> f32 {
+    hash(
+        NOISE_MAGIC_X
+            .wrapping_mul(x)
+            .wrapping_add(NOISE_MAGIC_Y.wrapping_mul(y))
+            .wrapping_add(NOISE_MAGIC_Z.wrapping_mul(z))
+            .wrapping_add(NOISE_MAGIC_SEED.wrapping_mul(seed)),
+    )
+}
+
+/// Splits a coordinate into its lattice cell and the interpolation weight within that cell.
+fn cell(value: f32, eased: bool) -> (i32, f32) {
+    let floor = value.floor();
+    let fraction = value - floor;
+    let weight = if eased {
+        fraction * fraction * fraction * (fraction * (fraction * 6.0 - 15.0) + 10.0)
+    } else {
+        fraction
+    };
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "coordinates are limited to the size of the world"
+    )]
+    let floor = floor as i32;
+    (floor, weight)
+}
+
+fn lerp(from: f32, to: f32, weight: f32) -> f32 {
+    from + (to - from) * weight
+}
+
+fn gradient_2d(x: f32, y: f32, seed: i32, eased: bool) -> f32 {
+    let (x0, wx) = cell(x, eased);
+    let (y0, wy) = cell(y, eased);
+    let bottom = lerp(lattice_2d(x0, y0, seed), lattice_2d(x0 + 1, y0, seed), wx);
+    let top = lerp(
+        lattice_2d(x0, y0 + 1, seed),
+        lattice_2d(x0 + 1, y0 + 1, seed),
+        wx,
+    );
+    lerp(bottom, top, wy)
+}
+
+fn gradient_3d(pos: Vec3, seed: i32, eased: bool) -> f32 {
+    let (x0, wx) = cell(pos.x, eased);
+    let (y0, wy) = cell(pos.y, eased);
+    let (z0, wz) = cell(pos.z, eased);
+    let plane = |z| {
+        let bottom = lerp(
+            lattice_3d(x0, y0, z, seed),
+            lattice_3d(x0 + 1, y0, z, seed),
+            wx,
+        );
+        let top = lerp(
+            lattice_3d(x0, y0 + 1, z, seed),
+            lattice_3d(x0 + 1, y0 + 1, z, seed),
+            wx,
+        );
+        lerp(bottom, top, wy)
+    };
+    lerp(plane(z0), plane(z0 + 1), wz)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{Noise, NoiseParams};
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        let params = NoiseParams::new(0.0, 1.0, Vec3::splat(100.0), 5, 3, 0.5);
+        let noise = Noise::new(params, 1234);
+        let other = Noise::new(params, 1235);
+        let mut differs = false;
+        for step in 0..100_u16 {
+            let pos = f32::from(step) * 7.3;
+            let value = noise.get_2d(pos, -pos);
+            assert_eq!(
+                value.to_bits(),
+                Noise::new(params, 1234).get_2d(pos, -pos).to_bits()
+            );
+            // the sum of all amplitudes is 1.75
+            assert!(value.abs() <= 1.75);
+            differs |= value.to_bits() != other.get_2d(pos, -pos).to_bits();
+            assert!(noise.get_3d(Vec3::splat(pos)).abs() <= 1.75);
+        }
+        assert!(differs);
+    }
+}
@@ -0,0 +1,323 @@
+//! Placement of ores within the terrain
+//!
+//! All ores of an [`OreRegistry`] are placed during [`StageKind::Decoration`] by replacing nodes
+//! of the terrain. Clusters never cross the border of the block they've been generated for.
+
+use glam::I16Vec3;
+use luanti_core::{ContentId, MapBlockPos, MapNodeIndex, MapNodePos};
+
+use super::mapgen::{BiomeId, MapgenBlock, MapgenStage, StageKind, stage_seed};
+use super::noise::{Noise, NoiseParams};
+use super::random::PcgRandom;
+
+/// The shape and distribution of an ore
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OreKind {
+    /// Cube-shaped clusters of randomly scattered nodes
+    Scatter {
+        /// one cluster per this many nodes
+        scarcity: u32,
+        /// the average number of ore nodes per cluster
+        ores_per_cluster: u16,
+        /// the edge length of the cube containing a cluster
+        cluster_size: u16,
+    },
+    /// Roundish clusters shaped by a 3D noise
+    Blob {
+        /// one cluster per this many nodes
+        scarcity: u32,
+        /// the diameter of a cluster
+        cluster_size: u16,
+        /// shapes the surface of each blob
+        noise: NoiseParams,
+    },
+    /// Horizontal layers undulating along a 2D noise
+    Sheet {
+        /// the layer is only present where the noise exceeds this value
+        threshold: f32,
+        /// the height of the layer
+        thickness: u16,
+        /// the vertical offset of the layer relative to the center of the ore's height range
+        noise: NoiseParams,
+    },
+}
+
+/// A single type of ore
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ore {
+    /// the node to be placed
+    pub ore: ContentId,
+    /// only these nodes will be replaced by the ore
+    pub wherein: Vec<ContentId>,
+    /// the lowest height at which the ore may be placed
+    pub y_min: i16,
+    /// the greatest height at which the ore may be placed
+    pub y_max: i16,
+    /// restricts the ore to these biomes; all biomes if empty
+    pub biomes: Vec<BiomeId>,
+    /// the shape and distribution
+    pub kind: OreKind,
+}
+
+impl Ore {
+    fn allows(&self, block: &MapgenBlock, index: MapNodeIndex) -> bool {
+        let y = block.node_pos(index).0.y;
+        (self.y_min..=self.y_max).contains(&y)
+            && block.in_biomes(&self.biomes, MapgenBlock::column_index(index))
+            && self.wherein.contains(&block.nodes[index].content_id)
+    }
+
+    fn place(&self, block: &mut MapgenBlock, pos: I16Vec3) {
+        let pos = MapNodePos(pos);
+        if !block.pos().contains(pos) {
+            return;
+        }
+        let index = pos.index();
+        if self.allows(block, index) {
+            block.nodes[index].content_id = self.ore;
+        }
+    }
+}
+
+struct RegisteredOre {
+    ore: Ore,
+    seed: u64,
+    noise: Option<Noise>,
+}
+
+/// A collection of ores to be placed into the terrain
+///
+/// Ores are placed in order of registration, so later ores may replace earlier ones if they list
+/// them in [`Ore::wherein`].
+pub struct OreRegistry {
+    world_seed: u64,
+    ores: Vec<RegisteredOre>,
+}
+
+impl OreRegistry {
+    /// Creates an empty registry for a world with the given seed.
+    #[must_use]
+    pub fn new(world_seed: u64) -> Self {
+        Self {
+            world_seed,
+            ores: Vec::new(),
+        }
+    }
+
+    /// Adds an ore.
+    pub fn register(&mut self, ore: Ore) {
+        let seed = stage_seed(self.world_seed, &format!("ore{}", self.ores.len()));
+        let noise = match ore.kind {
+            OreKind::Scatter { .. } => None,
+            OreKind::Blob { noise, .. } | OreKind::Sheet { noise, .. } => {
+                Some(Noise::new(noise, seed))
+            }
+        };
+        self.ores.push(RegisteredOre { ore, seed, noise });
+    }
+
+    /// Returns `true` if no ore has been registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ores.is_empty()
+    }
+}
+
+impl MapgenStage for OreRegistry {
+    fn kind(&self) -> StageKind {
+        StageKind::Decoration
+    }
+
+    fn apply(&self, block: &mut MapgenBlock) {
+        let top = block.node_pos(MapNodeIndex::MAX).0.y;
+        let bottom = block.node_pos(MapNodeIndex::MIN).0.y;
+        for registered in &self.ores {
+            let ore = &registered.ore;
+            if ore.y_min > top || ore.y_max < bottom {
+                continue;
+            }
+            let mut random = PcgRandom::new(block.seed() ^ registered.seed);
+            match (ore.kind, &registered.noise) {
+                (
+                    OreKind::Scatter {
+                        scarcity,
+                        ores_per_cluster,
+                        cluster_size,
+                    },
+                    _,
+                ) => {
+                    let cluster_volume = u32::from(cluster_size).pow(3);
+                    let ores_per_cluster = u32::from(ores_per_cluster).max(1);
+                    let chance = f64::from(ores_per_cluster) / f64::from(cluster_volume.max(1));
+                    for origin in cluster_origins(block, &mut random, scarcity, cluster_size) {
+                        for offset in cube(cluster_size) {
+                            if f64::from(random.fraction()) < chance {
+                                ore.place(block, origin + offset);
+                            }
+                        }
+                    }
+                }
+                (
+                    OreKind::Blob {
+                        scarcity,
+                        cluster_size,
+                        ..
+                    },
+                    Some(noise),
+                ) => {
+                    let radius = f32::from(cluster_size) / 2.0;
+                    for origin in cluster_origins(block, &mut random, scarcity, cluster_size) {
+                        for offset in cube(cluster_size) {
+                            let pos = origin + offset;
+                            let distance = (offset.as_vec3() + 0.5 - radius).length() / radius;
+                            if noise.get_3d(pos.as_vec3()) - distance >= 0.0 {
+                                ore.place(block, pos);
+                            }
+                        }
+                    }
+                }
+                (
+                    OreKind::Sheet {
+                        threshold,
+                        thickness,
+                        ..
+                    },
+                    Some(noise),
+                ) => place_sheet(block, ore, noise, threshold, thickness),
+                (OreKind::Blob { .. } | OreKind::Sheet { .. }, None) => {}
+            }
+        }
+    }
+}
+
+fn place_sheet(block: &mut MapgenBlock, ore: &Ore, noise: &Noise, threshold: f32, thickness: u16) {
+    let center = f32::from(ore.y_min) + (f32::from(ore.y_max) - f32::from(ore.y_min)) / 2.0;
+    let origin = MapNodePos::from(block.pos()).0;
+    for (x, z) in MapgenBlock::column_offsets() {
+        let x = origin.x + x;
+        let z = origin.z + z;
+        let value = noise.get_2d(f32::from(x), f32::from(z));
+        if value < threshold {
+            continue;
+        }
+        let bottom = center + value - noise.params().offset;
+        for step in 0..thickness {
+            let y = (bottom + f32::from(step)).round();
+            if (f32::from(i16::MIN)..=f32::from(i16::MAX)).contains(&y) {
+                #[expect(
+                    clippy::cast_possible_truncation,
+                    reason = "the range has been checked before"
+                )]
+                let y = y as i16;
+                ore.place(block, I16Vec3::new(x, y, z));
+            }
+        }
+    }
+}
+
+/// Picks the positions of the clusters to be placed within a block.
+///
+/// The clusters are fully contained within the block.
+fn cluster_origins(
+    block: &MapgenBlock,
+    random: &mut PcgRandom,
+    scarcity: u32,
+    cluster_size: u16,
+) -> Vec<I16Vec3> {
+    let expected = f32::from(MapBlockPos::NODE_COUNT) / scarcity_as_f32(scarcity);
+    let mut count = expected.floor();
+    if random.fraction() < expected - count {
+        count += 1.0;
+    }
+    let origin = MapNodePos::from(block.pos()).0;
+    let max = i32::from(MapBlockPos::SIZE) - i32::from(cluster_size.max(1));
+    let mut result = Vec::new();
+    while count >= 1.0 {
+        count -= 1.0;
+        let mut offset = || i16::try_from(random.range(0, max)).unwrap_or_default();
+        result.push(origin + I16Vec3::new(offset(), offset(), offset()));
+    }
+    result
+}
+
+/// Converts the number of nodes per cluster; `0` is treated like `1`.
+#[expect(
+    clippy::cast_precision_loss,
+    reason = "scarcities above 2^24 are rounded, which doesn't matter for an expected count"
+)]
+fn scarcity_as_f32(scarcity: u32) -> f32 {
+    scarcity.max(1) as f32
+}
+
+/// All offsets within a cube of the given edge length
+fn cube(size: u16) -> impl Iterator<Item = I16Vec3> {
+    let size = i16::try_from(size).unwrap_or(i16::MAX);
+    (0..size).flat_map(move |z| {
+        (0..size).flat_map(move |y| (0..size).map(move |x| I16Vec3::new(x, y, z)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use luanti_core::{ContentId, MapBlockPos};
+
+    use super::{Ore, OreKind, OreRegistry};
+    use crate::world::generation::flat::MapgenFlat;
+    use crate::world::generation::mapgen::{Mapgen, MapgenBlock, MapgenStage};
+    use crate::world::generation::noise::NoiseParams;
+
+    const STONE: ContentId = ContentId(1);
+    const COAL: ContentId = ContentId(2);
+    const IRON: ContentId = ContentId(3);
+
+    fn count(block: &MapgenBlock, content_id: ContentId) -> usize {
+        block
+            .nodes
+            .0
+            .iter()
+            .filter(|node| node.content_id == content_id)
+            .count()
+    }
+
+    #[test]
+    fn ores_only_replace_their_host() {
+        let mut registry = OreRegistry::new(3);
+        registry.register(Ore {
+            ore: COAL,
+            wherein: vec![STONE],
+            y_min: i16::MIN,
+            y_max: i16::MAX,
+            biomes: vec![],
+            kind: OreKind::Scatter {
+                scarcity: 64,
+                ores_per_cluster: 8,
+                cluster_size: 3,
+            },
+        });
+        registry.register(Ore {
+            ore: IRON,
+            wherein: vec![STONE],
+            y_min: i16::MIN,
+            y_max: i16::MAX,
+            biomes: vec![],
+            kind: OreKind::Blob {
+                scarcity: 512,
+                cluster_size: 6,
+                noise: NoiseParams::new(0.5, 0.5, Vec3::splat(4.0), 17, 2, 0.5),
+            },
+        });
+
+        let below = MapBlockPos::for_vec(glam::I16Vec3::NEG_Y);
+        let mut block = MapgenBlock::new(3, below);
+        MapgenFlat::new(STONE).terrain(&mut block);
+        registry.apply(&mut block);
+        assert!(count(&block, COAL) > 0);
+        assert!(count(&block, IRON) > 0);
+
+        // nothing to replace above ground
+        let mut above = MapgenBlock::new(3, MapBlockPos::ZERO);
+        registry.apply(&mut above);
+        assert_eq!(count(&above, COAL) + count(&above, IRON), 0);
+    }
+}
@@ -0,0 +1,90 @@
+//! A small deterministic random number generator for map generation
+
+/// A permuted congruential generator (`PCG32`), like the one used by the C++ engine
+///
+/// Unlike generic random number generators, its output is guaranteed to never change, which keeps
+/// generated worlds reproducible.
+#[derive(Debug, Clone)]
+pub struct PcgRandom {
+    state: u64,
+    increment: u64,
+}
+
+impl PcgRandom {
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+    const DEFAULT_SEQUENCE: u64 = 0xda3e_39cb_94b9_5bdb;
+
+    /// Creates a new generator from the given seed.
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        let mut result = Self {
+            state: 0,
+            increment: (Self::DEFAULT_SEQUENCE << 1) | 1,
+        };
+        result.next_u32();
+        result.state = result.state.wrapping_add(seed);
+        result.next_u32();
+        result
+    }
+
+    /// Returns the next random number.
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let [b0, b1, b2, b3, ..] = ((old_state ^ (old_state >> 18)) >> 27).to_le_bytes();
+        let xor_shifted = u32::from_le_bytes([b0, b1, b2, b3]);
+        let rotation = u32::try_from(old_state >> 59).unwrap_or_default();
+        xor_shifted.rotate_right(rotation)
+    }
+
+    /// Returns a random number within `min..=max`.
+    ///
+    /// Returns `min` if the range is empty.
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        let Ok(span) = u64::try_from(i64::from(max) - i64::from(min)) else {
+            return min;
+        };
+        let offset = u64::from(self.next_u32()) % (span + 1);
+        i64::try_from(offset)
+            .ok()
+            .and_then(|offset| i32::try_from(i64::from(min) + offset).ok())
+            .unwrap_or(min)
+    }
+
+    /// Returns a random number within `0.0..1.0`.
+    pub fn fraction(&mut self) -> f32 {
+        let [_, _, b2, b3] = self.next_u32().to_le_bytes();
+        f32::from(u16::from_le_bytes([b2, b3])) / 65536.0
+    }
+
+    /// Returns `true` with the given probability.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.fraction() < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PcgRandom;
+
+    #[test]
+    fn range_is_inclusive_and_reproducible() {
+        let mut random = PcgRandom::new(7);
+        let mut copy = PcgRandom::new(7);
+        let mut seen = [false; 3];
+        for _ in 0..100 {
+            let value = random.range(-1, 1);
+            assert_eq!(value, copy.range(-1, 1));
+            if let Some(slot) = usize::try_from(value + 1)
+                .ok()
+                .and_then(|index| seen.get_mut(index))
+            {
+                *slot = true;
+            }
+        }
+        assert_eq!(seen, [true; 3]);
+        assert_eq!(random.range(5, 4), 5);
+    }
+}
@@ -0,0 +1,220 @@
+//! contains `MapgenV7`
+
+use anyhow::{Result, bail};
+use glam::{U16Vec3, Vec3};
+use luanti_core::{ContentId, LIGHT_MAX, MapBlockPos, MapNode, MapNodeIndex};
+
+use super::mapgen::{Mapgen, MapgenBlock, MapgenStage, StageKind};
+use super::noise::{Noise, NoiseParams};
+use crate::ContentIdMap;
+
+/// The nodes a [`MapgenV7`] builds its terrain from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapgenV7Nodes {
+    /// the bulk of the terrain
+    pub stone: ContentId,
+    /// a thin layer between the surface and the stone
+    pub filler: ContentId,
+    /// the surface above water level
+    pub top: ContentId,
+    /// surface and filler close to the water level
+    pub shore: ContentId,
+    /// fills everything below the water level
+    pub water: ContentId,
+}
+
+impl MapgenV7Nodes {
+    /// Looks up the nodes of the `basenodes` mod which is part of the development test game.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the nodes hasn't been registered.
+    pub fn basenodes(content_id_map: &ContentIdMap) -> Result<Self> {
+        let lookup = |name: &str| {
+            let content_id = content_id_map[name];
+            if content_id.is_unknown() {
+                bail!("node '{name}' required by the map generator is not registered");
+            }
+            Ok(content_id)
+        };
+        Ok(Self {
+            stone: lookup("basenodes:stone")?,
+            filler: lookup("basenodes:dirt")?,
+            top: lookup("basenodes:dirt_with_grass")?,
+            shore: lookup("basenodes:sand")?,
+            water: lookup("basenodes:water_source")?,
+        })
+    }
+}
+
+/// Settings of a [`MapgenV7`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapgenV7Params {
+    /// everything below this height which isn't part of the terrain will be water
+    pub water_level: i16,
+    /// thickness of the layer of filler nodes including the surface
+    pub filler_depth: i16,
+    /// distance from the water level within which the shore nodes are being used
+    pub shore_height: i16,
+    /// the part of the river noise being considered as river bed
+    pub river_width: f32,
+    /// depth of rivers below the water level at their center
+    pub river_depth: f32,
+    /// the terrain height of the plains
+    pub terrain_base: NoiseParams,
+    /// the terrain height of the hills
+    pub terrain_alt: NoiseParams,
+    /// selects between `terrain_base` and `terrain_alt`
+    pub height_select: NoiseParams,
+    /// the course of rivers
+    pub rivers: NoiseParams,
+}
+
+impl Default for MapgenV7Params {
+    /// The defaults used by the C++ engine
+    fn default() -> Self {
+        Self {
+            water_level: 1,
+            filler_depth: 3,
+            shore_height: 1,
+            river_width: 0.2,
+            river_depth: 4.0,
+            terrain_base: NoiseParams::new(4.0, 70.0, Vec3::splat(600.0), 82341, 5, 0.6),
+            terrain_alt: NoiseParams::new(4.0, 25.0, Vec3::splat(600.0), 5934, 5, 0.6),
+            height_select: NoiseParams::new(-8.0, 16.0, Vec3::splat(500.0), 4213, 6, 0.7),
+            rivers: NoiseParams::new(0.0, 1.0, Vec3::splat(1000.0), 85039, 5, 0.6),
+        }
+    }
+}
+
+/// A map generator modeled after the `v7` map generator of the C++ engine.
+///
+/// The terrain alternates between flat plains and rolling hills, cut by rivers and surrounded
+/// by seas.
+pub struct MapgenV7 {
+    seed: u64,
+    nodes: MapgenV7Nodes,
+    params: MapgenV7Params,
+    terrain_base: Noise,
+    terrain_alt: Noise,
+    height_select: Noise,
+    rivers: Noise,
+}
+
+impl MapgenV7 {
+    /// Creates a new generator for a world with the given seed.
+    #[must_use]
+    pub fn new(seed: u64, nodes: MapgenV7Nodes, params: MapgenV7Params) -> Self {
+        Self {
+            seed,
+            nodes,
+            params,
+            terrain_base: Noise::new(params.terrain_base, seed),
+            terrain_alt: Noise::new(params.terrain_alt, seed),
+            height_select: Noise::new(params.height_select, seed),
+            rivers: Noise::new(params.rivers, seed),
+        }
+    }
+
+    /// Returns the y-coordinate of the topmost terrain node of the given column.
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the value is being clamped to the valid range"
+    )]
+    #[must_use]
+    pub fn surface_height(&self, x: i16, z: i16) -> i16 {
+        let (x, z) = (f32::from(x), f32::from(z));
+        let base = self.terrain_base.get_2d(x, z);
+        let alt = self.terrain_alt.get_2d(x, z);
+        let mut height = if alt > base {
+            alt
+        } else {
+            let select = self.height_select.get_2d(x, z).clamp(0.0, 1.0);
+            alt + (base - alt) * select
+        };
+
+        let river = self.rivers.get_2d(x, z).abs();
+        if river <= self.params.river_width {
+            // carve the river bed; deepest at the center of the river
+            let depth = (1.0 - river / self.params.river_width) * self.params.river_depth;
+            height = height.min(f32::from(self.params.water_level) - 1.0 - depth);
+        }
+
+        height
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+    }
+
+    /// Returns the node at height `y` of a column with the given surface height.
+    fn node(&self, y: i16, surface: i16) -> Option<MapNode> {
+        let params = &self.params;
+        let content_id = if y > surface {
+            if y > params.water_level {
+                return None;
+            }
+            // light gets weaker the deeper it reaches
+            let depth = u8::try_from(params.water_level.saturating_sub(y)).unwrap_or(u8::MAX);
+            return Some(MapNode {
+                content_id: self.nodes.water,
+                param1: MapNode::pack_light(LIGHT_MAX.saturating_sub(depth), 0),
+                param2: 0,
+            });
+        } else if y <= surface.saturating_sub(params.filler_depth) {
+            self.nodes.stone
+        } else if surface.saturating_sub(params.water_level).abs() <= params.shore_height {
+            self.nodes.shore
+        } else if y == surface && surface > params.water_level {
+            self.nodes.top
+        } else {
+            self.nodes.filler
+        };
+        Some(MapNode {
+            content_id,
+            ..MapNode::default()
+        })
+    }
+}
+
+impl Mapgen for MapgenV7 {
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn terrain(&self, block: &mut MapgenBlock) {
+        let bottom_layer = (0..MapBlockPos::NODE_COUNT)
+            .map(MapNodeIndex::from)
+            .filter(|index| U16Vec3::from(*index).y == 0);
+        for index in bottom_layer {
+            let pos = block.node_pos(index).0;
+            let height = self.surface_height(pos.x, pos.z);
+            let column = MapgenBlock::column_index(index);
+            if let Some(slot) = block.heightmap.get_mut(column) {
+                *slot = height;
+            }
+            if let Some((slot, node)) = block.surface.get_mut(column).zip(self.node(height, height))
+            {
+                *slot = node.content_id;
+            }
+        }
+
+        for index in (0..MapBlockPos::NODE_COUNT).map(MapNodeIndex::from) {
+            let Some(&surface) = block.heightmap.get(MapgenBlock::column_index(index)) else {
+                continue;
+            };
+            if let Some(node) = self.node(block.node_pos(index).0.y, surface) {
+                block.nodes[index] = node;
+            }
+        }
+    }
+}
+
+/// Allows using this generator as the terrain stage of a `MapgenPipeline`.
+impl MapgenStage for MapgenV7 {
+    fn kind(&self) -> StageKind {
+        StageKind::Terrain
+    }
+
+    fn apply(&self, block: &mut MapgenBlock) {
+        self.terrain(block);
+    }
+}
@@ -1,71 +1,388 @@
 //! Contains `MapBlockProvider`
 
 use super::{
-    WorldUpdate, generation::WorldGenerator, storage::WorldStorage, view_tracker::BlockInterest,
+    WorldBlock, WorldUpdate,
+    block_cache::{BlockCache, BlockCacheConfig, CacheStats},
+    emerge_queue::{EmergeConfig, EmergeQueue},
+    generation::WorldGenerator,
+    storage::WorldStorage,
+    view_tracker::BlockInterest,
 };
-use anyhow::Result;
-use log::{error, trace};
-use std::thread::{self, JoinHandle};
-use tokio::sync::mpsc;
+use crate::metrics::ServerMetrics;
+use anyhow::{Result, anyhow};
+use log::{debug, error, trace};
+use luanti_core::{MapBlockPos, MapNodePos};
+use std::{
+    path::PathBuf,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError},
+    oneshot, watch,
+};
+
+/// Messages being accepted by the [`MapBlockProvider`]
+pub enum ToProviderMessage {
+    /// Requests a map block to be loaded or generated. Requests with a higher priority are served
+    /// first and requests without a priority cancel a waiting request.
+    BlockInterest(BlockInterest),
+    /// Replaces a map block with a modified version which will be written to the storage
+    /// eventually.
+    BlockModified(Box<WorldBlock>),
+    /// Writes all modified map blocks to the storage immediately.
+    Flush,
+    /// Writes all modified map blocks to the storage and then creates a snapshot of the storage
+    /// in the given directory; see [`WorldStorage::snapshot`]. The outcome is sent back through
+    /// the channel.
+    Snapshot(PathBuf, oneshot::Sender<Result<()>>),
+}
 
 /// Implements a runner which provides map blocks in request.
 /// Possible sources are map storage and map generators.
+///
+/// Requests wait in an [`EmergeQueue`] which merges requests for the same map block. Recently used
+/// map blocks are being cached. Modified and generated map blocks are written to
+/// the storage periodically, when they're evicted from the cache and when the provider shuts down.
 pub struct MapBlockProvider {
+    cache_stats: watch::Receiver<CacheStats>,
     _runner: JoinHandle<Result<()>>,
 }
 
 impl MapBlockProvider {
     /// Creates a new [`MapBlockProvider`].
     ///
-    /// - `request_receiver` is being used to accept requests for map blocks
+    /// - `request_receiver` is being used to accept requests for map blocks and modifications.
+    ///   The provider shuts down when all senders have been dropped.
     /// - `block_sender` is being used to forward map blocks that have been loaded or generated
     /// - `storage` is being used first to load existing generated map blocks
     /// - `generator` is being used second to generate map block that could not be loaded
+    /// - `cache_config` controls caching and the frequency of writes to the storage
+    /// - `emerge_config` limits the work being done between accepting new requests
+    /// - `metrics` receives the timings of the storage and the generator
     #[must_use]
     pub fn new(
-        request_receiver: mpsc::UnboundedReceiver<BlockInterest>,
+        request_receiver: mpsc::UnboundedReceiver<ToProviderMessage>,
         block_sender: mpsc::UnboundedSender<WorldUpdate>,
         storage: Option<Box<dyn WorldStorage>>,
         generator: Option<Box<dyn WorldGenerator>>,
+        cache_config: BlockCacheConfig,
+        emerge_config: EmergeConfig,
+        metrics: ServerMetrics,
     ) -> Self {
+        let (cache_stats_sender, cache_stats) = watch::channel(CacheStats::default());
         let runner = thread::spawn(move || {
-            Self::run(request_receiver, &block_sender, storage, generator).inspect_err(|error| {
-                error!("map block provider exited with error: {error}");
-            })
+            let mut runner = ProviderRunner {
+                block_sender,
+                storage,
+                generator,
+                cache: BlockCache::new(cache_config.capacity),
+                emerge_queue: EmergeQueue::default(),
+                cache_stats: cache_stats_sender,
+                metrics,
+            };
+            runner
+                .run(
+                    request_receiver,
+                    cache_config.flush_interval,
+                    emerge_config.max_per_tick,
+                )
+                .inspect_err(|error| {
+                    error!("map block provider exited with error: {error}");
+                })
         });
 
-        Self { _runner: runner }
+        Self {
+            cache_stats,
+            _runner: runner,
+        }
     }
 
+    /// Returns the current statistics of the block cache.
+    #[must_use]
+    pub fn cache_stats(&self) -> CacheStats {
+        *self.cache_stats.borrow()
+    }
+}
+
+struct ProviderRunner {
+    block_sender: mpsc::UnboundedSender<WorldUpdate>,
+    storage: Option<Box<dyn WorldStorage>>,
+    generator: Option<Box<dyn WorldGenerator>>,
+    cache: BlockCache,
+    emerge_queue: EmergeQueue,
+    cache_stats: watch::Sender<CacheStats>,
+    metrics: ServerMetrics,
+}
+
+impl ProviderRunner {
     fn run(
-        mut request_receiver: mpsc::UnboundedReceiver<BlockInterest>,
-        block_sender: &mpsc::UnboundedSender<WorldUpdate>,
-        mut storage: Option<Box<dyn WorldStorage>>,
-        mut generator: Option<Box<dyn WorldGenerator>>,
+        &mut self,
+        mut request_receiver: mpsc::UnboundedReceiver<ToProviderMessage>,
+        flush_interval: Duration,
+        max_emerges_per_tick: usize,
     ) -> Result<()> {
-        'next_request: while let Some(message) = request_receiver.blocking_recv() {
-            let BlockInterest {
-                player_key: _,
-                pos,
-                priority: _,
-            } = message;
-
-            if let Some(storage) = &mut storage {
-                if let Some(block) = storage.load_block(pos)? {
-                    block_sender.send(WorldUpdate::NewMapBlock(block))?;
-                    continue 'next_request;
+        let mut last_flush = Instant::now();
+        'thread_loop: loop {
+            // used to measure activity
+            let mut event_count = 0;
+            let tick_start = Instant::now();
+
+            while let Some(message) = match request_receiver.try_recv() {
+                Ok(message) => {
+                    event_count += 1;
+                    Some(message)
                 }
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    debug!("no more block request senders exist");
+                    break 'thread_loop;
+                }
+            } {
+                match message {
+                    ToProviderMessage::BlockInterest(BlockInterest {
+                        player_key: _,
+                        pos,
+                        priority,
+                    }) => {
+                        if self.emerge_queue.push(pos, priority) {
+                            self.metrics.request_coalesced();
+                        }
+                    }
+                    ToProviderMessage::BlockModified(block) => self.modify_block(*block)?,
+                    ToProviderMessage::Flush => {
+                        self.flush();
+                        last_flush = Instant::now();
+                    }
+                    ToProviderMessage::Snapshot(directory, result_sender) => {
+                        self.flush();
+                        last_flush = Instant::now();
+                        let result = match &self.storage {
+                            Some(storage) => storage.snapshot(&directory),
+                            None => Err(anyhow!("there's no storage to create a snapshot of")),
+                        };
+                        if result_sender.send(result).is_err() {
+                            debug!("nobody is waiting for the snapshot anymore");
+                        }
+                    }
+                }
+            }
+
+            // serve the most important requests; the others wait until the priorities have been
+            // updated by new requests
+            for _ in 0..max_emerges_per_tick {
+                let Some(pos) = self.emerge_queue.pop() else {
+                    break;
+                };
+                event_count += 1;
+                self.provide(pos)?;
+            }
+            self.metrics.set_mapgen_queue_depth(self.emerge_queue.len());
+
+            if last_flush.elapsed() >= flush_interval {
+                self.flush();
+                last_flush = Instant::now();
             }
+            self.cache_stats.send_replace(self.cache.stats());
 
-            if let Some(generator) = &mut generator {
-                let block = generator.generate_block(pos);
-                block_sender.send(WorldUpdate::NewMapBlock(block))?;
-                continue 'next_request;
+            // slow down event polling if there was nothing to do in the recent iteration
+            if event_count == 0 {
+                thread::sleep(Duration::from_millis(50));
+            } else {
+                self.metrics.record_provider_tick(tick_start.elapsed());
             }
+        }
+
+        // don't lose any modifications when shutting down
+        self.flush();
+        let stats = self.cache.stats();
+        if stats.dirty > 0 {
+            error!("{} modified map blocks couldn't be stored", stats.dirty);
+        }
+        self.cache_stats.send_replace(stats);
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "block", level = "debug", skip_all, fields(%pos))
+    )]
+    fn provide(&mut self, pos: MapBlockPos) -> Result<()> {
+        if let Some(block) = self.cache.get(pos) {
+            self.block_sender
+                .send(WorldUpdate::NewMapBlock(block.clone()))?;
+            return Ok(());
+        }
+
+        if let Some(storage) = &self.storage {
+            let load_start = Instant::now();
+            let loaded = storage.load_block(pos)?;
+            self.metrics.record_storage_load(load_start.elapsed());
+            match loaded {
+                // let the generator replace placeholders if there is one
+                Some(block) if block.is_ungenerated() && self.generator.is_some() => {
+                    trace!("map block {pos} hasn't been generated yet");
+                }
+                Some(block) => {
+                    self.block_sender
+                        .send(WorldUpdate::NewMapBlock(block.clone()))?;
+                    return self.cache_block(block, false);
+                }
+                None => {}
+            }
+        }
 
-            trace!("map block {pos} couldn't be obtained from any source");
+        if let Some(generator) = &mut self.generator {
+            let generation_start = Instant::now();
+            let block = generator.generate_block(pos);
+            self.metrics.record_generation(generation_start.elapsed());
+            self.block_sender
+                .send(WorldUpdate::NewMapBlock(block.clone()))?;
+            // generated blocks need to be stored to keep them stable across generator changes
+            return self.cache_block(block, true);
         }
 
+        trace!("map block {pos} couldn't be obtained from any source");
         Ok(())
     }
+
+    /// Replaces a block with a modified version and reports the nodes which have changed.
+    fn modify_block(&mut self, block: WorldBlock) -> Result<()> {
+        let changes = if let Some(previous) = self.cache.peek(block.pos) {
+            block_changes(previous, &block)
+        } else if let Some(storage) = &self.storage {
+            // clients may still have a copy of blocks which are no longer cached
+            let load_start = Instant::now();
+            let previous = storage.load_block(block.pos)?;
+            self.metrics.record_storage_load(load_start.elapsed());
+            previous
+                .map(|previous| block_changes(&previous, &block))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if let Some(update) = WorldUpdate::batch(changes) {
+            self.block_sender.send(update)?;
+        }
+        self.cache_block(block, true)
+    }
+
+    /// Adds a block to the cache and writes the block being evicted to make room for it.
+    fn cache_block(&mut self, block: WorldBlock, dirty: bool) -> Result<()> {
+        if let (Some(evicted), Some(storage)) = (self.cache.insert(block, dirty), &mut self.storage)
+        {
+            Self::store_block(storage.as_mut(), &evicted, &self.metrics)?;
+        }
+        for pos in self.cache.take_evicted() {
+            self.block_sender.send(WorldUpdate::BlockUnloaded(pos))?;
+        }
+        Ok(())
+    }
+
+    fn store_block(
+        storage: &mut dyn WorldStorage,
+        block: &WorldBlock,
+        metrics: &ServerMetrics,
+    ) -> Result<()> {
+        let store_start = Instant::now();
+        storage.store_block(block)?;
+        metrics.record_storage_store(store_start.elapsed());
+        Ok(())
+    }
+
+    /// Writes all modified blocks; the ones which couldn't be stored are retried next time.
+    fn flush(&mut self) {
+        let count = if let Some(storage) = &mut self.storage {
+            self.cache
+                .flush(|block| Self::store_block(storage.as_mut(), block, &self.metrics))
+        } else {
+            self.cache.flush(|_| Ok(()))
+        };
+        if count > 0 {
+            debug!("flushed {count} modified map blocks");
+        }
+    }
+}
+
+/// Lists the changes of the nodes and their metadata between two versions of a block.
+fn block_changes(previous: &WorldBlock, current: &WorldBlock) -> Vec<WorldUpdate> {
+    let block_pos = current.pos;
+    let mut changes: Vec<_> = previous
+        .nodes
+        .0
+        .iter()
+        .zip(&current.nodes.0)
+        .zip(block_pos.nodes())
+        .filter(|((previous_node, current_node), _)| previous_node != current_node)
+        .map(|((_, &node), pos)| WorldUpdate::NodeChanged {
+            pos: MapNodePos(pos),
+            node,
+        })
+        .collect();
+
+    for &(index, _) in &previous.metadata {
+        if current.metadata(index).is_none() {
+            changes.push(WorldUpdate::NodeMetaChanged {
+                pos: block_pos.node_pos(index),
+                metadata: None,
+            });
+        }
+    }
+    for (index, metadata) in &current.metadata {
+        if previous.metadata(*index) != Some(metadata) {
+            changes.push(WorldUpdate::NodeMetaChanged {
+                pos: block_pos.node_pos(*index),
+                metadata: Some(metadata.clone()),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapBlockPos, MapNode, MapNodeIndex, MapNodePos};
+    use luanti_protocol::types::{Inventory, NodeMetadata};
+
+    use super::block_changes;
+    use crate::world::{WorldUpdate, editor::empty_block};
+
+    #[test]
+    fn changes_of_blocks_are_listed() {
+        let block_pos = MapBlockPos::new(I16Vec3::new(1, 0, 0)).unwrap_or(MapBlockPos::ZERO);
+        let metadata = NodeMetadata {
+            stringvars: vec![],
+            inventory: Inventory { entries: vec![] },
+        };
+        let mut previous = empty_block(block_pos);
+        previous.set_metadata(MapNodeIndex::from(5_u16), Some(metadata.clone()));
+        let mut current = previous.clone();
+        assert!(block_changes(&previous, &current).is_empty());
+
+        let stone = MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2: 0,
+        };
+        current.nodes[MapNodeIndex::from(1_u16)] = stone;
+        current.set_metadata(MapNodeIndex::from(5_u16), None);
+        current.set_metadata(MapNodeIndex::from(16_u16), Some(metadata));
+
+        let changes = block_changes(&previous, &current);
+        let pos = |x: i16, y| MapNodePos(I16Vec3::new(16 + x, y, 0));
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(
+            changes.first(),
+            Some(&WorldUpdate::NodeChanged { pos: node_pos, node }) if node_pos == pos(1, 0) && node == stone
+        ));
+        assert!(matches!(
+            changes.get(1),
+            Some(&WorldUpdate::NodeMetaChanged { pos: node_pos, metadata: None }) if node_pos == pos(5, 0)
+        ));
+        assert!(matches!(
+            changes.get(2),
+            Some(&WorldUpdate::NodeMetaChanged { pos: node_pos, metadata: Some(_) }) if node_pos == pos(0, 1)
+        ));
+    }
 }
@@ -11,9 +11,15 @@ use anyhow::Result;
 use flexstr::SharedStr;
 use log::{debug, error, trace, warn};
 use luanti_core::MapBlockPos;
-use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError},
+    oneshot,
+};
 
-use super::{WorldBlock, WorldUpdate, priority::Priority, view_tracker::BlockInterest};
+use super::{
+    WorldBlock, WorldUpdate, map_block_provider::ToProviderMessage, priority::Priority,
+    view_tracker::BlockInterest,
+};
 
 /// Handles map block requests from multiple players and combines them according to their priority.
 /// The requests will be forwarded to a `MapBlockProvider` which will load or generate those blocks.
@@ -26,7 +32,7 @@ impl MapBlockRouter {
     /// Creates a new [`MapBlockRouter`].
     #[must_use]
     pub fn new(
-        block_request_sender: mpsc::UnboundedSender<BlockInterest>,
+        block_request_sender: mpsc::UnboundedSender<ToProviderMessage>,
         world_update_receiver: mpsc::UnboundedReceiver<WorldUpdate>,
         block_interest_receiver: mpsc::UnboundedReceiver<ToRouterMessage>,
     ) -> Self {
@@ -50,10 +56,9 @@ impl MapBlockRouter {
     pub(crate) fn run(
         mut block_interest_receiver: mpsc::UnboundedReceiver<ToRouterMessage>,
         mut world_update_receiver: mpsc::UnboundedReceiver<WorldUpdate>,
-        block_request_sender: &mpsc::UnboundedSender<BlockInterest>,
+        block_request_sender: &mpsc::UnboundedSender<ToProviderMessage>,
     ) -> Result<()> {
-        let mut players = HashMap::new();
-        let mut block_subscriptions: HashMap<MapBlockPos, EffectiveBlockInterest> = HashMap::new();
+        let mut state = RouterState::default();
         'thread_loop: loop {
             // used to measure activity
             let mut event_count = 0;
@@ -70,31 +75,7 @@ impl MapBlockRouter {
                     break 'thread_loop;
                 }
             } {
-                match message {
-                    ToRouterMessage::Register { player_key, sender } => {
-                        if players.insert(player_key.clone(), sender).is_some() {
-                            warn!("player '{player_key}' is already subscribed");
-                        }
-                    }
-                    ToRouterMessage::Unregister(player_key) => {
-                        if players.remove(&player_key).is_none() {
-                            warn!("player '{player_key}' never subscribed");
-                        }
-                    }
-                    ToRouterMessage::BlockInterest(BlockInterest {
-                        player_key,
-                        pos,
-                        priority,
-                    }) => {
-                        if block_subscriptions
-                            .entry(pos)
-                            .or_default()
-                            .update_player(&player_key, priority)
-                        {
-                            subscription_change_count += 1;
-                        }
-                    }
-                }
+                subscription_change_count += state.handle_message(message);
             }
 
             while let Some(message) = match world_update_receiver.try_recv() {
@@ -105,44 +86,13 @@ impl MapBlockRouter {
                 Err(TryRecvError::Empty) => None,
                 Err(TryRecvError::Disconnected) => break 'thread_loop,
             } {
-                // FIXME(kawogi) until the player has received this block, it might continue to send interests for that block which will eventually result in multiple map block messages
-                #[expect(irrefutable_let_patterns, reason = "more variants will be added")]
-                if let &WorldUpdate::NewMapBlock(WorldBlock { pos, .. }) = &message {
-                    match block_subscriptions.entry(pos) {
-                        Entry::Occupied(occupied_entry) => {
-                            let interest = occupied_entry.remove();
-
-                            for (player_key, _priority) in interest.player_priorities {
-                                if let Some(to_player) = players.get(&player_key) {
-                                    // TODO(kawogi) cloning is mad expensive. There should be a way to use an Arc internally
-                                    to_player.send(message.clone())?;
-                                } else {
-                                    warn!("cannot forward block {pos} to player '{player_key}'");
-                                }
-                            }
-                        }
-                        Entry::Vacant(_vacant_entry) => {
-                            trace!(
-                                "generated block {pos} is unknown to the router and will be ignored"
-                            );
-                        }
-                    }
-                }
+                state.forward(message)?;
             }
+            state.broadcast()?;
 
-            if subscription_change_count > 0 {
-                for (pos, priority) in
-                    block_subscriptions
-                        .iter_mut()
-                        .filter_map(|(&pos, interest)| {
-                            interest.ack_max().map(|priority| (pos, priority))
-                        })
-                {
-                    block_request_sender.send(BlockInterest {
-                        player_key: SharedStr::empty(),
-                        pos,
-                        priority,
-                    })?;
+            if subscription_change_count > 0 || !state.resync.is_empty() {
+                for interest in state.provider_requests() {
+                    block_request_sender.send(ToProviderMessage::BlockInterest(interest))?;
                 }
             }
 
@@ -156,6 +106,251 @@ impl MapBlockRouter {
     }
 }
 
+/// The interests of a single player in map blocks, whether they have been delivered already or
+/// not
+///
+/// Taken by [`ToRouterMessage::SnapshotInterests`] and passed back by
+/// [`ToRouterMessage::RestoreInterests`], e.g. to keep the requests of a player across a
+/// reconnect.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerInterests {
+    blocks: Vec<(MapBlockPos, Priority)>,
+}
+
+impl PlayerInterests {
+    /// Returns the number of map blocks the player is interested in.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns `true` if the player isn't interested in any map block.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Iterates over the positions of all map blocks the player is interested in.
+    pub fn positions(&self) -> impl Iterator<Item = MapBlockPos> {
+        self.blocks.iter().map(|&(pos, _)| pos)
+    }
+
+    /// Iterates over all map blocks the player is interested in along with their priority.
+    pub(crate) fn blocks(&self) -> impl Iterator<Item = (MapBlockPos, Priority)> {
+        self.blocks.iter().copied()
+    }
+}
+
+impl FromIterator<(MapBlockPos, Priority)> for PlayerInterests {
+    fn from_iter<T: IntoIterator<Item = (MapBlockPos, Priority)>>(iter: T) -> Self {
+        Self {
+            blocks: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Combines the interests of all players and forwards the map blocks to them.
+#[derive(Default)]
+struct RouterState {
+    /// the channels of all registered players
+    players: HashMap<SharedStr, mpsc::UnboundedSender<WorldUpdate>>,
+    /// the interests of all players in map blocks which haven't been delivered yet
+    block_subscriptions: HashMap<MapBlockPos, EffectiveBlockInterest>,
+    /// the map blocks delivered to each player along with the priority they had been requested
+    /// with; kept until the player changes its interest, so they can be delivered again
+    delivered: HashMap<SharedStr, HashMap<MapBlockPos, Priority>>,
+    /// map blocks which have to be requested again even though their priority didn't change
+    resync: Vec<MapBlockPos>,
+    /// updates of the world which will be sent to all players by the end of the tick
+    pending_updates: Vec<WorldUpdate>,
+}
+
+impl RouterState {
+    /// Applies a message of a player and returns the number of map blocks whose effective
+    /// priority has changed.
+    fn handle_message(&mut self, message: ToRouterMessage) -> usize {
+        match message {
+            ToRouterMessage::Register { player_key, sender } => {
+                if self.players.insert(player_key.clone(), sender).is_some() {
+                    warn!("player '{player_key}' is already subscribed");
+                }
+                0
+            }
+            ToRouterMessage::Unregister(player_key) => {
+                if self.players.remove(&player_key).is_none() {
+                    warn!("player '{player_key}' never subscribed");
+                }
+                // nobody would receive the blocks anymore
+                let pending = self.pending_interests(&player_key);
+                self.delivered.remove(&player_key);
+                self.update_player(
+                    &player_key,
+                    pending.into_iter().map(|(pos, _)| (pos, Priority::NONE)),
+                )
+            }
+            ToRouterMessage::BlockInterest(BlockInterest {
+                player_key,
+                pos,
+                priority,
+            }) => self.update_player(&player_key, [(pos, priority)]),
+            ToRouterMessage::SnapshotInterests { player_key, sender } => {
+                if sender.send(self.interests(&player_key)).is_err() {
+                    debug!("nobody is waiting for the interests of player '{player_key}' anymore");
+                }
+                0
+            }
+            ToRouterMessage::RestoreInterests {
+                player_key,
+                interests,
+            } => self.update_player(&player_key, interests.blocks),
+            ToRouterMessage::Resync(player_key) => {
+                let pending = self.pending_interests(&player_key);
+                let delivered = self.delivered.remove(&player_key).unwrap_or_default();
+                trace!(
+                    "requesting {} map blocks of player '{player_key}' again",
+                    pending.len() + delivered.len()
+                );
+                // unchanged priorities wouldn't be requested again
+                self.resync.extend(pending.into_iter().map(|(pos, _)| pos));
+                self.resync.extend(delivered.keys());
+                self.update_player(&player_key, delivered)
+            }
+        }
+    }
+
+    /// Updates the priorities of a player for some map blocks and returns the number of map
+    /// blocks whose effective priority has changed.
+    fn update_player(
+        &mut self,
+        player_key: &SharedStr,
+        priorities: impl IntoIterator<Item = (MapBlockPos, Priority)>,
+    ) -> usize {
+        let mut delivered = self.delivered.get_mut(player_key);
+        priorities
+            .into_iter()
+            .filter(|&(pos, priority)| {
+                // the player either wants the block again or doesn't want it anymore
+                if let Some(delivered) = &mut delivered {
+                    delivered.remove(&pos);
+                }
+                self.block_subscriptions
+                    .entry(pos)
+                    .or_default()
+                    .update_player(player_key, priority)
+            })
+            .count()
+    }
+
+    /// Collects the interests of a player in map blocks, including the delivered ones.
+    fn interests(&self, player_key: &SharedStr) -> PlayerInterests {
+        let delivered = self
+            .delivered
+            .get(player_key)
+            .into_iter()
+            .flatten()
+            .map(|(&pos, &priority)| (pos, priority));
+        self.pending_interests(player_key)
+            .into_iter()
+            .chain(delivered)
+            .collect()
+    }
+
+    /// Collects the interests of a player in map blocks which haven't been delivered yet.
+    fn pending_interests(&self, player_key: &SharedStr) -> Vec<(MapBlockPos, Priority)> {
+        self.block_subscriptions
+            .iter()
+            .filter_map(|(&pos, interest)| {
+                interest
+                    .player_priorities
+                    .iter()
+                    .find_map(|(key, priority)| (key == player_key).then_some((pos, *priority)))
+            })
+            .collect()
+    }
+
+    /// Forwards a new map block to all players being interested in it. All other updates are
+    /// collected for [`Self::broadcast`].
+    fn forward(&mut self, message: WorldUpdate) -> Result<()> {
+        let pos = match &message {
+            WorldUpdate::NewMapBlock(WorldBlock { pos, .. }) => *pos,
+            WorldUpdate::Batch(_) => {
+                for update in message.into_updates() {
+                    self.forward(update)?;
+                }
+                return Ok(());
+            }
+            WorldUpdate::NodeChanged { .. }
+            | WorldUpdate::NodeMetaChanged { .. }
+            | WorldUpdate::BlockUnloaded(_)
+            | WorldUpdate::EntityMoved { .. } => {
+                self.pending_updates.push(message);
+                return Ok(());
+            }
+        };
+        // FIXME(kawogi) until the player has received this block, it might continue to send interests for that block which will eventually result in multiple map block messages
+        match self.block_subscriptions.entry(pos) {
+            Entry::Occupied(occupied_entry) => {
+                let interest = occupied_entry.remove();
+
+                for (player_key, priority) in interest.player_priorities {
+                    if let Some(to_player) = self.players.get(&player_key) {
+                        // TODO(kawogi) cloning is mad expensive. There should be a way to use an Arc internally
+                        to_player.send(message.clone())?;
+                        self.delivered
+                            .entry(player_key)
+                            .or_default()
+                            .insert(pos, priority);
+                    } else {
+                        warn!("cannot forward block {pos} to player '{player_key}'");
+                    }
+                }
+            }
+            Entry::Vacant(_vacant_entry) => {
+                trace!("generated block {pos} is unknown to the router and will be ignored");
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the updates collected by [`Self::forward`] to all players in a single batch. The
+    /// view trackers decide which of them are relevant to their player.
+    fn broadcast(&mut self) -> Result<()> {
+        let Some(batch) = WorldUpdate::batch(mem::take(&mut self.pending_updates)) else {
+            return Ok(());
+        };
+        for to_player in self.players.values() {
+            to_player.send(batch.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the requests for the provider: all map blocks whose effective priority has
+    /// changed and all map blocks to be resynchronized.
+    fn provider_requests(&mut self) -> Vec<BlockInterest> {
+        let mut requests: Vec<_> = self
+            .block_subscriptions
+            .iter_mut()
+            .filter_map(|(&pos, interest)| interest.ack_max().map(|priority| (pos, priority)))
+            .collect();
+        for pos in self.resync.drain(..) {
+            let Some(interest) = self.block_subscriptions.get(&pos) else {
+                continue;
+            };
+            if !requests.iter().any(|&(requested, _)| requested == pos) {
+                requests.push((pos, interest.max_priority));
+            }
+        }
+        requests
+            .into_iter()
+            .map(|(pos, priority)| BlockInterest {
+                player_key: SharedStr::empty(),
+                pos,
+                priority,
+            })
+            .collect()
+    }
+}
+
 #[derive(Default)]
 struct EffectiveBlockInterest {
     max_priority: Priority,
@@ -268,4 +463,206 @@ pub enum ToRouterMessage {
     /// Tells the router about which blocks a player is interested in and how important that block
     /// is to the player.
     BlockInterest(BlockInterest),
+    /// Sends the interests of a player in map blocks, including the delivered ones, through the
+    /// channel.
+    SnapshotInterests {
+        /// Name of the player
+        player_key: SharedStr,
+        /// The channel to send the interests to
+        sender: oneshot::Sender<PlayerInterests>,
+    },
+    /// Adds interests which have been taken by [`Self::SnapshotInterests`] to a player, e.g.
+    /// after the player reconnected. All of these map blocks are being delivered again.
+    RestoreInterests {
+        /// Name of the player
+        player_key: SharedStr,
+        /// The interests to be restored
+        interests: PlayerInterests,
+    },
+    /// Requests all map blocks the player is interested in again, including the ones which
+    /// have been delivered already, so the loaded ones are being sent right away.
+    Resync(SharedStr),
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use flexstr::SharedStr;
+    use glam::I16Vec3;
+    use luanti_core::MapBlockPos;
+    use tokio::sync::{mpsc, oneshot};
+
+    use super::{RouterState, ToRouterMessage};
+    use crate::world::{
+        WorldBlock, WorldUpdate, generation::mapgen::MapgenBlock, priority::Priority,
+        view_tracker::BlockInterest,
+    };
+
+    fn pos(x: i16) -> MapBlockPos {
+        MapBlockPos::new(I16Vec3::new(x, 0, 0)).unwrap()
+    }
+
+    fn requested(state: &mut RouterState) -> Vec<(MapBlockPos, Priority)> {
+        let mut requests: Vec<_> = state
+            .provider_requests()
+            .into_iter()
+            .map(|interest| (interest.pos, interest.priority))
+            .collect();
+        requests.sort_by_key(|&(pos, _)| pos.vec().x);
+        requests
+    }
+
+    #[test]
+    fn interests_survive_a_reconnect() {
+        let player = SharedStr::from_borrowed("player");
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut state = RouterState::default();
+        state.handle_message(ToRouterMessage::Register {
+            player_key: player.clone(),
+            sender,
+        });
+        for (x, priority) in [(1, Priority::MAX), (2, Priority::MIN)] {
+            state.handle_message(ToRouterMessage::BlockInterest(BlockInterest {
+                player_key: player.clone(),
+                pos: pos(x),
+                priority,
+            }));
+        }
+        assert_eq!(
+            requested(&mut state),
+            [(pos(1), Priority::MAX), (pos(2), Priority::MIN)]
+        );
+
+        let (snapshot_sender, mut snapshot_receiver) = oneshot::channel();
+        state.handle_message(ToRouterMessage::SnapshotInterests {
+            player_key: player.clone(),
+            sender: snapshot_sender,
+        });
+        let interests = snapshot_receiver.try_recv().unwrap();
+        assert_eq!(interests.len(), 2);
+
+        // disconnecting cancels the requests
+        assert_eq!(
+            state.handle_message(ToRouterMessage::Unregister(player.clone())),
+            2
+        );
+        assert_eq!(
+            requested(&mut state),
+            [(pos(1), Priority::NONE), (pos(2), Priority::NONE)]
+        );
+
+        let (reconnected_sender, mut receiver) = mpsc::unbounded_channel();
+        state.handle_message(ToRouterMessage::Register {
+            player_key: player.clone(),
+            sender: reconnected_sender,
+        });
+        assert_eq!(
+            state.handle_message(ToRouterMessage::RestoreInterests {
+                player_key: player.clone(),
+                interests,
+            }),
+            2
+        );
+        assert_eq!(
+            requested(&mut state),
+            [(pos(1), Priority::MAX), (pos(2), Priority::MIN)]
+        );
+        assert!(requested(&mut state).is_empty());
+
+        // unchanged interests are requested again
+        state.handle_message(ToRouterMessage::Resync(player.clone()));
+        assert_eq!(
+            requested(&mut state),
+            [(pos(1), Priority::MAX), (pos(2), Priority::MIN)]
+        );
+
+        let block = MapgenBlock::new(0, pos(1)).into_world_block();
+        state.forward(WorldUpdate::NewMapBlock(block)).unwrap();
+        let Ok(WorldUpdate::NewMapBlock(forwarded)) = receiver.try_recv() else {
+            unreachable!("the block must have been forwarded");
+        };
+        assert_eq!(forwarded.pos, pos(1));
+
+        // delivered blocks are still of interest to the player and are delivered again
+        let (delivered_sender, mut delivered_receiver) = oneshot::channel();
+        state.handle_message(ToRouterMessage::SnapshotInterests {
+            player_key: player.clone(),
+            sender: delivered_sender,
+        });
+        assert_eq!(delivered_receiver.try_recv().unwrap().len(), 2);
+        state.handle_message(ToRouterMessage::Resync(player.clone()));
+        assert_eq!(
+            requested(&mut state),
+            [(pos(1), Priority::MAX), (pos(2), Priority::MIN)]
+        );
+        let loaded = MapgenBlock::new(0, pos(1)).into_world_block();
+        state.forward(WorldUpdate::NewMapBlock(loaded)).unwrap();
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(WorldUpdate::NewMapBlock(WorldBlock { pos: resent, .. })) if resent == pos(1)
+        ));
+
+        // losing interest in a delivered block forgets about it
+        state.handle_message(ToRouterMessage::BlockInterest(BlockInterest {
+            player_key: player.clone(),
+            pos: pos(1),
+            priority: Priority::NONE,
+        }));
+        state.handle_message(ToRouterMessage::Resync(player));
+        assert_eq!(requested(&mut state), [(pos(2), Priority::MIN)]);
+    }
+
+    #[test]
+    fn leaving_players_forget_their_delivered_blocks() {
+        let player = SharedStr::from_borrowed("player");
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut state = RouterState::default();
+        state.handle_message(ToRouterMessage::Register {
+            player_key: player.clone(),
+            sender,
+        });
+        state.handle_message(ToRouterMessage::BlockInterest(BlockInterest {
+            player_key: player.clone(),
+            pos: pos(1),
+            priority: Priority::MAX,
+        }));
+        let block = MapgenBlock::new(0, pos(1)).into_world_block();
+        state.forward(WorldUpdate::NewMapBlock(block)).unwrap();
+        requested(&mut state);
+
+        state.handle_message(ToRouterMessage::Unregister(player.clone()));
+        let (snapshot_sender, mut snapshot_receiver) = oneshot::channel();
+        state.handle_message(ToRouterMessage::SnapshotInterests {
+            player_key: player,
+            sender: snapshot_sender,
+        });
+        assert!(snapshot_receiver.try_recv().unwrap().is_empty());
+    }
+
+    #[test]
+    fn world_changes_are_batched() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut state = RouterState::default();
+        state.handle_message(ToRouterMessage::Register {
+            player_key: SharedStr::from_borrowed("player"),
+            sender,
+        });
+        state.broadcast().unwrap();
+        receiver.try_recv().unwrap_err();
+
+        state.forward(WorldUpdate::BlockUnloaded(pos(1))).unwrap();
+        state
+            .forward(WorldUpdate::Batch(vec![
+                WorldUpdate::BlockUnloaded(pos(2)),
+                WorldUpdate::BlockUnloaded(pos(3)),
+            ]))
+            .unwrap();
+        state.broadcast().unwrap();
+        let Ok(WorldUpdate::Batch(updates)) = receiver.try_recv() else {
+            unreachable!("the updates must have been sent as a batch");
+        };
+        assert_eq!(updates.len(), 3);
+        receiver.try_recv().unwrap_err();
+    }
 }
@@ -0,0 +1,133 @@
+//! Reads and writes the seed of a world in its `map_meta.txt`
+//!
+//! The C++ engine stores the settings of the map generator in this file, most importantly the
+//! seed which makes the generated terrain reproducible. Other settings within the file are kept.
+
+use std::{fs, io::ErrorKind, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+
+use super::generation::mapgen::stage_seed;
+
+/// The name of the file within a world directory which contains the seed
+const FILE_NAME: &str = "map_meta.txt";
+
+/// The line terminating the settings
+const END_OF_PARAMS: &str = "[end_of_params]";
+
+/// Returns the seed of the world in the given directory.
+///
+/// A world keeps the seed it has been created with. New worlds use `fixed_seed` or a random seed
+/// if there is none, which is then written to the world directory.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or written or if it contains an invalid seed.
+pub fn world_seed(world_directory: impl AsRef<Path>, fixed_seed: Option<u64>) -> Result<u64> {
+    let directory = world_directory.as_ref();
+    let path = directory.join(FILE_NAME);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+    if let Some(seed) =
+        read_seed(&text).with_context(|| format!("invalid seed in {}", path.display()))?
+    {
+        if fixed_seed.is_some_and(|fixed_seed| fixed_seed != seed) {
+            warn!("ignoring the fixed map seed as the world already uses the seed {seed}");
+        }
+        return Ok(seed);
+    }
+
+    let seed = fixed_seed.unwrap_or_else(rand::random);
+    fs::create_dir_all(directory)
+        .with_context(|| format!("failed to create {}", directory.display()))?;
+    fs::write(&path, with_seed(&text, seed))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    info!("saved the seed {seed} into {}", path.display());
+    Ok(seed)
+}
+
+/// Interprets the value of the `fixed_map_seed` setting like the C++ engine: numbers are used as
+/// they are, any other text is hashed.
+#[must_use]
+pub fn parse_seed(value: &str) -> u64 {
+    let value = value.trim();
+    value
+        .parse()
+        .or_else(|_| {
+            value
+                .parse::<i64>()
+                .map(|seed| u64::from_le_bytes(seed.to_le_bytes()))
+        })
+        // any stable hash will do
+        .unwrap_or_else(|_| stage_seed(0, value))
+}
+
+/// Extracts the seed from the contents of a `map_meta.txt`.
+fn read_seed(text: &str) -> Result<Option<u64>> {
+    text.lines()
+        .take_while(|line| line.trim() != END_OF_PARAMS)
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "seed")
+        .map(|(_, value)| {
+            let value = value.trim();
+            value
+                .parse()
+                .map_err(|error| anyhow!("invalid value `{value}` for `seed`: {error}"))
+        })
+        .transpose()
+}
+
+/// Adds the seed to the contents of a `map_meta.txt` which doesn't contain one.
+fn with_seed(text: &str, seed: u64) -> String {
+    let mut lines: Vec<_> = text
+        .lines()
+        .take_while(|line| line.trim() != END_OF_PARAMS)
+        .filter(|line| !line.trim().is_empty())
+        .map(ToOwned::to_owned)
+        .collect();
+    lines.push(format!("seed = {seed}"));
+    lines.push(END_OF_PARAMS.to_owned());
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{env, fs};
+
+    use super::{parse_seed, read_seed, with_seed, world_seed};
+
+    #[test]
+    fn seeds_are_kept_with_the_world() {
+        let directory = env::temp_dir().join(format!("luanti-rs-map-meta-{}", std::process::id()));
+
+        assert_eq!(world_seed(&directory, Some(42)).unwrap(), 42);
+        // the world keeps its seed
+        assert_eq!(world_seed(&directory, Some(43)).unwrap(), 42);
+        assert_eq!(world_seed(&directory, None).unwrap(), 42);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let text = "mg_name = v7\nwater_level = 1\n[end_of_params]\n";
+        assert_eq!(read_seed(text).unwrap(), None);
+        let text = with_seed(text, 1234);
+        assert_eq!(
+            text,
+            "mg_name = v7\nwater_level = 1\nseed = 1234\n[end_of_params]\n"
+        );
+        assert_eq!(read_seed(&text).unwrap(), Some(1234));
+        read_seed("seed = soup").unwrap_err();
+
+        assert_eq!(parse_seed("1234"), 1234);
+        assert_eq!(parse_seed("-1"), u64::MAX);
+        assert_eq!(parse_seed("luanti"), parse_seed(" luanti "));
+        assert_ne!(parse_seed("luanti"), parse_seed("minetest"));
+    }
+}
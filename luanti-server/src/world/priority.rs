@@ -1,6 +1,3 @@
-use glam::{I16Vec3, Vec3};
-use luanti_core::{MapBlockPos, MapNodePos};
-
 /// Represents a value describing how important something (e.g. a map block) is to the player.
 ///
 /// This value may be used as key for a priority queue, where higher values mean higher priority.
@@ -31,44 +28,6 @@ impl Priority {
     pub(crate) fn is_some(self) -> bool {
         !self.is_none()
     }
-
-    /// Uses the euclidean distance between two positions as priority.
-    /// Distances exceeding `max_distance` will be mapped to `Priority::NONE`. Set the limit to
-    /// `u32::MAX` to disable this.
-    /// Distances exceeding the limit of `u16` will be clamped to a priority of `Priority::MIN`.
-    pub(crate) fn from_vec_distance(pos_a: I16Vec3, pos_b: I16Vec3, max_distance: u32) -> Self {
-        // TODO(kawogi) find a more performant solution; a very coarse approximation would be sufficient
-        // note: do not use `distance_squared` because the decimation for lower distances will cause
-        // all low distances to be mapped to `Priority::MAX`
-        let distance = Vec3::distance(pos_a.as_vec3(), pos_b.as_vec3());
-        #[expect(
-            clippy::cast_precision_loss,
-            reason = "the expected range is precise enough"
-        )]
-        if distance < max_distance as f32 {
-            #[expect(clippy::cast_possible_truncation, reason = "truncation is on purpose")]
-            #[expect(clippy::cast_sign_loss, reason = "distance is always positive")]
-            Priority(Self::MAX.0.saturating_sub(distance as u16).max(Self::MIN.0))
-        } else {
-            Priority::NONE
-        }
-    }
-
-    pub(crate) fn from_node_distance(
-        pos_a: MapNodePos,
-        pos_b: MapNodePos,
-        max_distance: u32,
-    ) -> Self {
-        Self::from_vec_distance(pos_a.into(), pos_b.into(), max_distance)
-    }
-
-    pub(crate) fn from_block_distance(
-        pos_a: MapBlockPos,
-        pos_b: MapBlockPos,
-        max_distance: u32,
-    ) -> Self {
-        Self::from_node_distance(pos_a.into(), pos_b.into(), max_distance)
-    }
 }
 
 impl Default for Priority {
@@ -1,11 +1,17 @@
 //! Contains the `WorldStorage` trait and some implementations thereof.
 
+use std::{collections::BTreeSet, path::Path};
+
 use super::WorldBlock;
-use anyhow::Result;
+use anyhow::{Result, bail};
+use flexstr::SharedStr;
 use luanti_core::MapBlockPos;
 
+pub mod blob;
 pub mod dummy;
 pub mod minetestworld;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 /// This trait needs to be implemented by a storage provider for map data
 pub trait WorldStorage: Send + Sync {
@@ -22,4 +28,56 @@ pub trait WorldStorage: Send + Sync {
     ///
     /// Returns an error if the block could be retrieved for other reasons.
     fn load_block(&self, pos: MapBlockPos) -> Result<Option<WorldBlock>>;
+    /// Returns the positions of all stored blocks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage could not be read.
+    fn block_positions(&self) -> Result<Vec<MapBlockPos>>;
+    /// Loads a block in the serialization format of the C++ engine; see [`blob`]. Unlike
+    /// [`Self::load_block`] this includes the parts of a block which have no representation in
+    /// [`WorldBlock`]. Returns `None`, if the requested block doesn't exist.
+    ///
+    /// Storages which use another format fail by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block could be retrieved for other reasons.
+    fn load_serialized_block(&self, pos: MapBlockPos) -> Result<Option<Vec<u8>>> {
+        bail!("this storage cannot load map block {pos} in the engine's format");
+    }
+    /// Stores a block in the serialization format of the C++ engine as-is; see [`blob`].
+    ///
+    /// Storages which use another format fail by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block could not be stored.
+    fn store_serialized_block(&mut self, pos: MapBlockPos, _data: &[u8]) -> Result<()> {
+        bail!("this storage cannot store map block {pos} in the engine's format");
+    }
+    /// Returns the names of all nodes occurring in the stored blocks.
+    ///
+    /// Every block is being read, so this is meant for tools inspecting the content of a whole
+    /// world.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage could not be read.
+    fn node_names(&self) -> Result<BTreeSet<SharedStr>>;
+    /// Writes a consistent copy of all stored blocks into another directory which can be opened
+    /// by a storage of the same kind afterwards. The storage stays usable while the copy is being
+    /// made.
+    ///
+    /// Storages which don't support this fail by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the copy could not be created.
+    fn snapshot(&self, directory: &Path) -> Result<()> {
+        bail!(
+            "this storage cannot create snapshots in {}",
+            directory.display()
+        );
+    }
 }
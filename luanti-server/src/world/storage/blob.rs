@@ -0,0 +1,513 @@
+//! Encoding of map blocks as stored in the map databases of the C++ engine
+//!
+//! Blocks are always written using serialization version 29 which compresses the whole block
+//! using zstd. Version 28 which compresses nodes and metadata separately using zlib can be read as
+//! well.
+//!
+//! Static objects, node timers and the timestamp aren't represented by [`WorldBlock`] (yet), so
+//! they can be carried over from a previously stored version of a block using [`RetainedData`].
+
+use anyhow::{Result, bail};
+use flexstr::SharedStr;
+use luanti_core::{ContentId, MapBlockNodes, MapBlockPos};
+use luanti_protocol::types::{CommandDirection, MapNodesBulk, NodeMetadataList, ProtocolContext};
+use luanti_protocol::wire::compression::CompressionConfig;
+use luanti_protocol::wire::deser::{Deserialize, Deserializer};
+use luanti_protocol::wire::packet::LATEST_PROTOCOL_VERSION;
+use luanti_protocol::wire::ser::{Serialize, Serializer, VecSerializer};
+use luanti_protocol::wire::util::{decompress_zlib, zstd_compress, zstd_decompress};
+
+use crate::ContentIdMapper;
+use crate::world::WorldBlock;
+use crate::world::content_id_mapper::ContentIdRemap;
+
+/// the serialization version used for writing
+const WRITE_VERSION: u8 = 29;
+/// the oldest serialization version which can be read
+const MIN_READ_VERSION: u8 = 28;
+
+const FLAG_IS_UNDERGROUND: u8 = 0x01;
+const FLAG_DAY_NIGHT_DIFFERS: u8 = 0x02;
+const FLAG_NOT_GENERATED: u8 = 0x08;
+
+/// the size of the serialized nodes of a block; content id, `param1` and `param2` of each node
+const NODES_LEN: usize = 4 * MapBlockPos::NODE_COUNT as usize;
+
+/// the timestamp of blocks which haven't been active yet
+const TIMESTAMP_UNDEFINED: u32 = u32::MAX;
+
+/// Parts of a stored block which have no representation in [`WorldBlock`]
+///
+/// These are kept in their serialized form which is the same for all supported versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetainedData {
+    /// the game time at which the block was last active
+    pub timestamp: u32,
+    /// the serialized list of static objects including its version
+    pub static_objects: Vec<u8>,
+    /// the serialized list of node timers including the size of a timer
+    pub node_timers: Vec<u8>,
+}
+
+impl Default for RetainedData {
+    /// Data of a block which has never been active
+    fn default() -> Self {
+        Self {
+            timestamp: TIMESTAMP_UNDEFINED,
+            // version 0, no objects
+            static_objects: vec![0, 0, 0],
+            // 10 bytes per timer, no timers
+            node_timers: vec![10, 0, 0],
+        }
+    }
+}
+
+/// Decodes a stored block.
+///
+/// The block-local ids are remapped to those of `content_ids`; node names without an id get the
+/// next free one.
+///
+/// # Errors
+///
+/// Returns an error if the data is corrupt or uses an unsupported serialization version.
+pub fn deserialize_block(
+    pos: MapBlockPos,
+    data: &[u8],
+    content_ids: &ContentIdMapper,
+) -> Result<(WorldBlock, RetainedData)> {
+    decode_block(pos, data, &mut |name| content_ids.id(name))
+}
+
+/// Returns the names of all nodes occurring in a stored block.
+///
+/// # Errors
+///
+/// Returns an error if the data is corrupt or uses an unsupported serialization version.
+pub fn node_names(pos: MapBlockPos, data: &[u8]) -> Result<Vec<SharedStr>> {
+    let mut names = Vec::new();
+    decode_block(pos, data, &mut |name| {
+        names.push(String::from_utf8_lossy(name).into_owned().into());
+        Ok(ContentId::UNKNOWN)
+    })?;
+    Ok(names)
+}
+
+/// Returns the parts of a stored block which have no representation in [`WorldBlock`].
+///
+/// Neither the nodes nor their names are being decoded, so no content ids are assigned.
+///
+/// # Errors
+///
+/// Returns an error if the data is corrupt or uses an unsupported serialization version.
+pub fn retained_data(pos: MapBlockPos, data: &[u8]) -> Result<RetainedData> {
+    let Some((&version, data)) = data.split_first() else {
+        bail!("map block {pos} is empty");
+    };
+    if !(MIN_READ_VERSION..=WRITE_VERSION).contains(&version) {
+        bail!("map block {pos} uses unsupported serialization version {version}");
+    }
+    let skip_name = &mut |_: &[u8]| Ok(ContentId::UNKNOWN);
+
+    if version >= 29 {
+        let mut decompressed = Vec::new();
+        zstd_decompress(data, |chunk| {
+            decompressed.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        let deser = &mut Deserializer::new(context(version), &decompressed);
+        deserialize_flags(deser)?;
+        let timestamp = u32::deserialize(deser)?;
+        deserialize_name_id_mapping(deser, skip_name)?;
+        deserialize_widths(deser)?;
+        deser.take(NODES_LEN)?;
+        NodeMetadataList::deserialize(deser)?;
+        let static_objects = take_static_objects(deser)?;
+        let node_timers = take_node_timers(deser)?;
+        Ok(RetainedData {
+            timestamp,
+            static_objects,
+            node_timers,
+        })
+    } else {
+        let deser = &mut Deserializer::new(context(version), data);
+        deserialize_flags(deser)?;
+        deserialize_widths(deser)?;
+        // nodes and metadata
+        for _ in 0..2 {
+            let (compressed_len, _) = decompress_zlib(deser.peek_all())?;
+            deser.take(compressed_len)?;
+        }
+        let static_objects = take_static_objects(deser)?;
+        let timestamp = u32::deserialize(deser)?;
+        deserialize_name_id_mapping(deser, skip_name)?;
+        let node_timers = take_node_timers(deser)?;
+        Ok(RetainedData {
+            timestamp,
+            static_objects,
+            node_timers,
+        })
+    }
+}
+
+/// Decodes a stored block using `resolve` to translate node names into content ids.
+fn decode_block(
+    pos: MapBlockPos,
+    data: &[u8],
+    resolve: &mut impl FnMut(&[u8]) -> Result<ContentId>,
+) -> Result<(WorldBlock, RetainedData)> {
+    let Some((&version, data)) = data.split_first() else {
+        bail!("map block {pos} is empty");
+    };
+    if !(MIN_READ_VERSION..=WRITE_VERSION).contains(&version) {
+        bail!("map block {pos} uses unsupported serialization version {version}");
+    }
+
+    if version >= 29 {
+        let mut decompressed = Vec::new();
+        zstd_decompress(data, |chunk| {
+            decompressed.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        let deser = &mut Deserializer::new(context(version), &decompressed);
+        let flags = deserialize_flags(deser)?;
+        let timestamp = u32::deserialize(deser)?;
+        let remap = deserialize_name_id_mapping(deser, resolve)?;
+        deserialize_widths(deser)?;
+        let nodes = MapNodesBulk::deserialize(deser)?;
+        let metadata = NodeMetadataList::deserialize(deser)?;
+        let static_objects = take_static_objects(deser)?;
+        let node_timers = take_node_timers(deser)?;
+        Ok((
+            flags.into_block(pos, &nodes, &remap, metadata),
+            RetainedData {
+                timestamp,
+                static_objects,
+                node_timers,
+            },
+        ))
+    } else {
+        let deser = &mut Deserializer::new(context(version), data);
+        let flags = deserialize_flags(deser)?;
+        deserialize_widths(deser)?;
+        let (nodes_len, nodes_raw) = decompress_zlib(deser.peek_all())?;
+        deser.take(nodes_len)?;
+        let nodes = MapNodesBulk::deserialize(&mut deser.nested(&nodes_raw))?;
+        let (metadata_len, metadata_raw) = decompress_zlib(deser.peek_all())?;
+        deser.take(metadata_len)?;
+        let metadata = NodeMetadataList::deserialize(&mut deser.nested(&metadata_raw))?;
+        let static_objects = take_static_objects(deser)?;
+        let timestamp = u32::deserialize(deser)?;
+        let remap = deserialize_name_id_mapping(deser, resolve)?;
+        let node_timers = take_node_timers(deser)?;
+        Ok((
+            flags.into_block(pos, &nodes, &remap, metadata),
+            RetainedData {
+                timestamp,
+                static_objects,
+                node_timers,
+            },
+        ))
+    }
+}
+
+/// Encodes a block for storage.
+///
+/// # Errors
+///
+/// Returns an error if the block contains content ids without a name.
+pub fn serialize_block(
+    block: &WorldBlock,
+    retained: &RetainedData,
+    content_ids: &ContentIdMapper,
+) -> Result<Vec<u8>> {
+    // block-local ids are assigned in order of first appearance
+    let mut names: Vec<ContentId> = Vec::new();
+    let mut nodes = block.nodes.0;
+    for node in &mut nodes {
+        let local_id = if let Some(local_id) = names.iter().position(|id| *id == node.content_id) {
+            local_id
+        } else {
+            names.push(node.content_id);
+            names.len() - 1
+        };
+        node.content_id = ContentId(u16::try_from(local_id)?);
+    }
+
+    let mut flags = 0;
+    if block.is_underground {
+        flags |= FLAG_IS_UNDERGROUND;
+    }
+    if block.day_night_differs {
+        flags |= FLAG_DAY_NIGHT_DIFFERS;
+    }
+    if block.is_ungenerated() {
+        flags |= FLAG_NOT_GENERATED;
+    }
+
+    let mut serializer = VecSerializer::new(context(WRITE_VERSION), 0x4000);
+    let ser = &mut serializer;
+    u8::serialize(&flags, ser)?;
+    u16::serialize(&block.lighting_complete, ser)?;
+    u32::serialize(&retained.timestamp, ser)?;
+    // name-id mapping version
+    u8::serialize(&0, ser)?;
+    u16::serialize(&u16::try_from(names.len())?, ser)?;
+    let content_id_map = content_ids.map();
+    for (local_id, content_id) in (0_u16..).zip(&names) {
+        let name = &content_id_map[*content_id];
+        if name.is_empty() {
+            bail!(
+                "content id {content_id:?} in map block {pos} has no name",
+                pos = block.pos
+            );
+        }
+        u16::serialize(&local_id, ser)?;
+        u16::serialize(&u16::try_from(name.len())?, ser)?;
+        ser.write_bytes(name.as_bytes())?;
+    }
+    drop(content_id_map);
+    // content width and params width
+    u8::serialize(&2, ser)?;
+    u8::serialize(&2, ser)?;
+    MapNodesBulk::serialize(&MapNodesBulk { nodes }, ser)?;
+    NodeMetadataList::serialize(
+        &NodeMetadataList {
+            metadata: block.metadata.clone(),
+        },
+        ser,
+    )?;
+    ser.write_bytes(&retained.static_objects)?;
+    ser.write_bytes(&retained.node_timers)?;
+
+    let uncompressed = serializer.take();
+    let mut result = Vec::with_capacity(uncompressed.len() / 4);
+    result.push(WRITE_VERSION);
+    zstd_compress(&uncompressed, |chunk| {
+        result.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+fn context(version: u8) -> ProtocolContext {
+    ProtocolContext {
+        dir: CommandDirection::ToClient,
+        protocol_version: LATEST_PROTOCOL_VERSION,
+        ser_fmt: version,
+        audit: false,
+        compression: CompressionConfig::default(),
+        strict_inventory: false,
+    }
+}
+
+struct Flags {
+    is_underground: bool,
+    day_night_differs: bool,
+    lighting_complete: u16,
+}
+
+impl Flags {
+    fn into_block(
+        self,
+        pos: MapBlockPos,
+        nodes: &MapNodesBulk,
+        remap: &ContentIdRemap,
+        metadata: NodeMetadataList,
+    ) -> WorldBlock {
+        let mut nodes = MapBlockNodes(nodes.nodes);
+        remap.apply(&mut nodes);
+        WorldBlock {
+            version: 0,
+            pos,
+            is_underground: self.is_underground,
+            day_night_differs: self.day_night_differs,
+            lighting_complete: self.lighting_complete,
+            nodes,
+            metadata: metadata.metadata,
+        }
+    }
+}
+
+fn deserialize_flags(deser: &mut Deserializer<'_>) -> Result<Flags> {
+    let flags = u8::deserialize(deser)?;
+    let lighting_complete = u16::deserialize(deser)?;
+    Ok(Flags {
+        is_underground: flags & FLAG_IS_UNDERGROUND != 0,
+        day_night_differs: flags & FLAG_DAY_NIGHT_DIFFERS != 0,
+        lighting_complete,
+    })
+}
+
+fn deserialize_widths(deser: &mut Deserializer<'_>) -> Result<()> {
+    let content_width = u8::deserialize(deser)?;
+    let params_width = u8::deserialize(deser)?;
+    if content_width != 2 || params_width != 2 {
+        bail!("unsupported content width {content_width} or params width {params_width}");
+    }
+    Ok(())
+}
+
+/// Returns the translation of the block-local ids into global ones.
+fn deserialize_name_id_mapping(
+    deser: &mut Deserializer<'_>,
+    resolve: &mut impl FnMut(&[u8]) -> Result<ContentId>,
+) -> Result<ContentIdRemap> {
+    let version = u8::deserialize(deser)?;
+    if version != 0 {
+        bail!("unsupported name-id mapping version {version}");
+    }
+    let count = u16::deserialize(deser)?;
+    let mut remap = ContentIdRemap::default();
+    for _ in 0..count {
+        let local_id = ContentId(u16::deserialize(deser)?);
+        let name_len = usize::from(u16::deserialize(deser)?);
+        remap.insert(local_id, resolve(deser.take(name_len)?)?);
+    }
+    Ok(remap)
+}
+
+/// Takes the serialized static objects without interpreting them.
+fn take_static_objects(deser: &mut Deserializer<'_>) -> Result<Vec<u8>> {
+    let start = deser.peek_all();
+    let version = u8::deserialize(deser)?;
+    if version != 0 {
+        bail!("unsupported static object list version {version}");
+    }
+    let count = u16::deserialize(deser)?;
+    for _ in 0..count {
+        // type and position (3 × s32)
+        deser.take(1 + 3 * 4)?;
+        let data_len = usize::from(u16::deserialize(deser)?);
+        deser.take(data_len)?;
+    }
+    Ok(start
+        .get(..start.len() - deser.remaining())
+        .unwrap_or_default()
+        .to_vec())
+}
+
+/// Takes the serialized node timers without interpreting them.
+fn take_node_timers(deser: &mut Deserializer<'_>) -> Result<Vec<u8>> {
+    let start = deser.peek_all();
+    let timer_len = usize::from(u8::deserialize(deser)?);
+    let count = usize::from(u16::deserialize(deser)?);
+    deser.take(timer_len * count)?;
+    Ok(start
+        .get(..start.len() - deser.remaining())
+        .unwrap_or_default()
+        .to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex};
+    use luanti_protocol::wire::util::compress_zlib;
+
+    use super::{RetainedData, deserialize_block, node_names, retained_data, serialize_block};
+    use crate::world::WorldBlock;
+    use crate::{ContentIdMap, ContentIdMapper};
+
+    fn content_ids() -> (ContentIdMapper, ContentId) {
+        let content_ids = ContentIdMapper::new(ContentIdMap::new());
+        let stone = content_ids.id(b"test:stone").unwrap();
+        (content_ids, stone)
+    }
+
+    #[test]
+    fn blocks_survive_a_round_trip() {
+        let (content_ids, stone) = content_ids();
+        let pos = MapBlockPos::ZERO;
+        let mut nodes = MapBlockNodes(
+            [MapNode {
+                content_id: ContentId::AIR,
+                param1: 0xf0,
+                param2: 0,
+            }; MapBlockPos::NODE_COUNT as usize],
+        );
+        nodes[MapNodeIndex::from(7_u16)] = MapNode {
+            content_id: stone,
+            param1: 0,
+            param2: 3,
+        };
+        let block = WorldBlock {
+            version: 0,
+            pos,
+            is_underground: true,
+            day_night_differs: false,
+            lighting_complete: 0xfffe,
+            nodes,
+            metadata: vec![],
+        };
+        let retained = RetainedData {
+            timestamp: 1234,
+            ..RetainedData::default()
+        };
+
+        let data = serialize_block(&block, &retained, &content_ids).unwrap();
+        let (loaded, loaded_retained) = deserialize_block(pos, &data, &content_ids).unwrap();
+        assert_eq!(loaded.nodes.0, block.nodes.0);
+        assert!(loaded.is_underground);
+        assert!(!loaded.day_night_differs);
+        assert_eq!(loaded.lighting_complete, 0xfffe);
+        assert_eq!(loaded_retained, retained);
+        assert_eq!(retained_data(pos, &data).unwrap(), retained);
+
+        let names = node_names(pos, &data).unwrap();
+        assert_eq!(
+            names.iter().map(|name| &**name).collect::<Vec<_>>(),
+            ["air", "test:stone"]
+        );
+
+        // a server which assigned its ids in a different order
+        let other = ContentIdMapper::new(ContentIdMap::new());
+        let dirt = other.id(b"test:dirt").unwrap();
+        let (remapped, _) = deserialize_block(pos, &data, &other).unwrap();
+        let other_stone = remapped.nodes[MapNodeIndex::from(7_u16)].content_id;
+        assert_ne!(other_stone, dirt);
+        assert_eq!(other.name(other_stone), "test:stone");
+    }
+
+    #[test]
+    fn zlib_blocks_can_be_read() {
+        let (content_ids, stone) = content_ids();
+        let node_count = usize::from(MapBlockPos::NODE_COUNT);
+
+        // all nodes use the block-local id 0 which is mapped to `test:stone`
+        let mut nodes = vec![0; 2 * node_count];
+        nodes.extend(vec![0x0f; node_count]);
+        nodes.extend(vec![0; node_count]);
+
+        let mut data = vec![28, 0x01, 0xff, 0xff, 2, 2];
+        data.extend(compress_zlib(&nodes));
+        data.extend(compress_zlib(&[0]));
+        // static objects
+        data.extend([0, 0, 0]);
+        // timestamp
+        data.extend([0, 0, 0, 42]);
+        // name-id mapping
+        data.extend([0, 0, 1, 0, 0, 0, 10]);
+        data.extend(b"test:stone");
+        // node timers
+        data.extend([10, 0, 0]);
+
+        let (block, retained) = deserialize_block(MapBlockPos::ZERO, &data, &content_ids).unwrap();
+        assert!(block.is_underground);
+        assert!(
+            block
+                .nodes
+                .0
+                .iter()
+                .all(|node| node.content_id == stone && node.param1 == 0x0f)
+        );
+        assert_eq!(retained.timestamp, 42);
+        assert_eq!(
+            retained_data(MapBlockPos::ZERO, &data).unwrap(),
+            RetainedData {
+                timestamp: 42,
+                ..RetainedData::default()
+            }
+        );
+    }
+}
@@ -1,12 +1,18 @@
 //! contains the `DummyStorage`
 
+use std::collections::BTreeSet;
+
 use super::WorldStorage;
 use crate::world::WorldBlock;
 use anyhow::Result;
+use flexstr::SharedStr;
 use luanti_core::MapBlockPos;
 
 /// A world storage provider which actually never stores or loads anything.
 /// This is useful for temporary throwaway worlds and for mapgen tests.
+///
+/// As there's nothing to copy, creating a snapshot fails rather than pretending to have saved the
+/// world.
 pub struct DummyStorage;
 
 impl WorldStorage for DummyStorage {
@@ -17,4 +23,20 @@ impl WorldStorage for DummyStorage {
     fn load_block(&self, _pos: MapBlockPos) -> Result<Option<WorldBlock>> {
         Ok(None)
     }
+
+    fn block_positions(&self) -> Result<Vec<MapBlockPos>> {
+        Ok(Vec::new())
+    }
+
+    fn load_serialized_block(&self, _pos: MapBlockPos) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn store_serialized_block(&mut self, _pos: MapBlockPos, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn node_names(&self) -> Result<BTreeSet<SharedStr>> {
+        Ok(BTreeSet::new())
+    }
 }
@@ -1,18 +1,22 @@
 //! Contains the `MinetestworldStorage`
 
-use std::{path::Path, sync::Arc};
+use std::{collections::BTreeSet, path::Path, sync::Arc};
 
 use super::WorldStorage;
-use crate::{ContentIdMap, world::WorldBlock};
+use super::blob::deserialize_block;
+use crate::{ContentIdMapper, world::WorldBlock};
 use anyhow::{Result, anyhow};
+use flexstr::SharedStr;
+use futures::TryStreamExt;
+use glam::I16Vec3;
 use log::{debug, info, trace};
-use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodePos};
+use luanti_core::MapBlockPos;
 use minetestworld::{MapDataError, Position};
 
 /// A world storage provider which uses the `minetestworld` crate.
 pub struct MinetestworldStorage {
     map_data: minetestworld::MapData,
-    content_id_map: Arc<ContentIdMap>,
+    content_ids: Arc<ContentIdMapper>,
     runtime: tokio::runtime::Runtime,
 }
 
@@ -25,7 +29,7 @@ impl MinetestworldStorage {
     /// Returns an error if the given path doesn't contain a valid luanti world.
     pub async fn new(
         world_directory: impl AsRef<Path>,
-        content_id_map: Arc<ContentIdMap>,
+        content_ids: Arc<ContentIdMapper>,
     ) -> Result<Self> {
         info!(
             "loading world from {path}",
@@ -42,7 +46,7 @@ impl MinetestworldStorage {
 
         Ok(MinetestworldStorage {
             map_data: world.get_map_data().await?,
-            content_id_map,
+            content_ids,
             runtime,
         })
     }
@@ -54,50 +58,61 @@ impl WorldStorage for MinetestworldStorage {
     }
 
     fn load_block(&self, map_block_pos: MapBlockPos) -> Result<Option<WorldBlock>> {
+        let Some(data) = self.load_serialized_block(map_block_pos)? else {
+            return Ok(None);
+        };
+        // the blocks are stored in the engine's format, including their metadata
+        let (block, _) = deserialize_block(map_block_pos, &data, &self.content_ids)?;
+        Ok(Some(block))
+    }
+
+    fn load_serialized_block(&self, map_block_pos: MapBlockPos) -> Result<Option<Vec<u8>>> {
         let (x, y, z) = map_block_pos.vec().into();
-        let map_block = self
+        let data = self
             .runtime
-            .block_on(async { self.map_data.get_mapblock(Position::new(x, y, z)).await });
+            .block_on(async { self.map_data.get_block_data(Position::new(x, y, z)).await });
 
-        let map_block = match map_block {
-            Ok(map_block) => map_block,
+        match data {
+            Ok(data) => Ok(Some(data)),
             Err(MapDataError::MapBlockNonexistent(_position)) => {
                 trace!("map block {map_block_pos} doesn't exist in map store");
-                return Ok(None);
-            }
-            Err(error) => return Err(anyhow!(error)),
-        };
-
-        let mut id_map = Vec::with_capacity(map_block.name_id_mappings.len());
-        for (id, name) in map_block.name_id_mappings {
-            let global_id = self.content_id_map[name.as_slice()];
-            let index = usize::from(id);
-            if let Some(slot) = id_map.get_mut(index) {
-                *slot = global_id;
-            } else {
-                id_map.resize(index, ContentId::UNKNOWN);
-                id_map.push(global_id);
+                Ok(None)
             }
+            Err(error) => Err(anyhow!(error)),
         }
+    }
 
-        #[expect(
-            clippy::indexing_slicing,
-            reason = "block size is known at compile-time"
-        )]
-        let nodes = std::array::from_fn(|index| MapNode {
-            content_id: id_map[usize::from(map_block.param0[index])],
-            param1: map_block.param1[index],
-            param2: map_block.param2[index],
-        });
+    fn block_positions(&self) -> Result<Vec<MapBlockPos>> {
+        let positions: Vec<Position> = self.runtime.block_on(async {
+            self.map_data
+                .all_mapblock_positions()
+                .await
+                .try_collect()
+                .await
+        })?;
+        positions
+            .into_iter()
+            .map(|Position { x, y, z }| {
+                let vec = I16Vec3::new(x, y, z);
+                MapBlockPos::new(vec).ok_or_else(|| anyhow!("invalid map block position {vec}"))
+            })
+            .collect()
+    }
 
-        Ok(Some(WorldBlock {
-            version: 0,
-            pos: map_block_pos,
-            is_underground: MapNodePos::from(map_block_pos).0.y < 0,
-            day_night_differs: false,
-            lighting_complete: 0xffff,
-            nodes: MapBlockNodes(nodes),
-            metadata: vec![],
-        }))
+    fn node_names(&self) -> Result<BTreeSet<SharedStr>> {
+        let mut names = BTreeSet::new();
+        for pos in self.block_positions()? {
+            let (x, y, z) = pos.vec().into();
+            let map_block = self
+                .runtime
+                .block_on(async { self.map_data.get_mapblock(Position::new(x, y, z)).await })?;
+            names.extend(
+                map_block
+                    .name_id_mappings
+                    .into_values()
+                    .map(|name| SharedStr::from(String::from_utf8_lossy(&name).into_owned())),
+            );
+        }
+        Ok(names)
     }
 }
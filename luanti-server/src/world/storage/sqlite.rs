@@ -0,0 +1,464 @@
+//! Contains the `SqliteStorage`
+
+use std::{collections::BTreeSet, fs, path::Path, sync::Arc};
+
+use super::WorldStorage;
+use super::blob::{RetainedData, deserialize_block, node_names, retained_data, serialize_block};
+use crate::{ContentIdMapper, world::WorldBlock};
+use anyhow::{Context, Result, anyhow, bail};
+use flexstr::SharedStr;
+use glam::I16Vec3;
+use log::{info, trace};
+use luanti_core::MapBlockPos;
+use sqlx::Sqlite;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+
+/// The name of the map database within a world directory
+const MAP_FILE_NAME: &str = "map.sqlite";
+
+/// The layout of the `blocks` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Schema {
+    /// blocks are identified by a single integer; see [`block_key`]
+    Legacy,
+    /// blocks are identified by separate `x`, `y` and `z` columns
+    Coordinates,
+}
+
+/// A world storage provider which reads and writes the `map.sqlite` of a world directly.
+///
+/// Worlds created by the C++ engine can be served and modified in place. Both the legacy layout
+/// of the `blocks` table and the newer one using separate coordinate columns are supported. A new
+/// database uses the legacy layout.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    schema: Schema,
+    content_ids: Arc<ContentIdMapper>,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl SqliteStorage {
+    /// Opens the map database of a world. The path points to a `world`-directory which will
+    /// contain the `map.sqlite` file.
+    ///
+    /// Every stored block contains the names of its nodes, so blocks can be loaded with any
+    /// `content_ids`. Ids assigned in the meantime are saved whenever a block is stored.
+    ///
+    /// The database is being created if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or has an unknown layout.
+    pub async fn new(
+        world_directory: impl AsRef<Path>,
+        content_ids: Arc<ContentIdMapper>,
+    ) -> Result<Self> {
+        let path = world_directory.as_ref().join(MAP_FILE_NAME);
+        info!("opening map database {path}", path = path.display());
+
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        // a single connection avoids locking issues with the C++ engine
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        let columns: Vec<String> =
+            sqlx::query_scalar::<Sqlite, String>("SELECT name FROM pragma_table_info('blocks')")
+                .fetch_all(&pool)
+                .await?;
+        let has_column = |name: &str| columns.iter().any(|column| column == name);
+        let schema = if columns.is_empty() {
+            sqlx::query("CREATE TABLE IF NOT EXISTS blocks (pos INT PRIMARY KEY, data BLOB)")
+                .execute(&pool)
+                .await?;
+            Schema::Legacy
+        } else if has_column("pos") && has_column("data") {
+            Schema::Legacy
+        } else if ["x", "y", "z", "data"]
+            .iter()
+            .all(|column| has_column(column))
+        {
+            Schema::Coordinates
+        } else {
+            bail!(
+                "map database {path} has an unknown layout: {columns:?}",
+                path = path.display()
+            );
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+
+        Ok(Self {
+            pool,
+            schema,
+            content_ids,
+            runtime,
+        })
+    }
+
+    /// Loads the raw data of a block.
+    fn load_data(&self, pos: MapBlockPos) -> Result<Option<Vec<u8>>> {
+        let data = self.runtime.block_on(async {
+            match self.schema {
+                Schema::Legacy => {
+                    sqlx::query_scalar::<Sqlite, Vec<u8>>("SELECT data FROM blocks WHERE pos = ?")
+                        .bind(block_key(pos))
+                        .fetch_optional(&self.pool)
+                        .await
+                }
+                Schema::Coordinates => {
+                    let I16Vec3 { x, y, z } = pos.vec();
+                    sqlx::query_scalar::<Sqlite, Vec<u8>>(
+                        "SELECT data FROM blocks WHERE x = ? AND y = ? AND z = ?",
+                    )
+                    .bind(x)
+                    .bind(y)
+                    .bind(z)
+                    .fetch_optional(&self.pool)
+                    .await
+                }
+            }
+        })?;
+        Ok(data)
+    }
+
+    /// Stores the raw data of a block.
+    fn store_data(&self, pos: MapBlockPos, data: Vec<u8>) -> Result<()> {
+        self.runtime.block_on(async {
+            match self.schema {
+                Schema::Legacy => {
+                    sqlx::query("INSERT OR REPLACE INTO blocks (pos, data) VALUES (?, ?)")
+                        .bind(block_key(pos))
+                        .bind(data)
+                        .execute(&self.pool)
+                        .await
+                }
+                Schema::Coordinates => {
+                    let I16Vec3 { x, y, z } = pos.vec();
+                    sqlx::query("INSERT OR REPLACE INTO blocks (x, y, z, data) VALUES (?, ?, ?, ?)")
+                        .bind(x)
+                        .bind(y)
+                        .bind(z)
+                        .bind(data)
+                        .execute(&self.pool)
+                        .await
+                }
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl WorldStorage for SqliteStorage {
+    fn store_block(&mut self, map_block: &WorldBlock) -> Result<()> {
+        let pos = map_block.pos;
+        // keep everything the engine stored which isn't known to the `WorldBlock`
+        let retained = match self.load_data(pos)? {
+            Some(data) => retained_data(pos, &data).with_context(|| {
+                format!("failed to keep static objects and node timers of map block {pos}")
+            })?,
+            None => RetainedData::default(),
+        };
+        let data = serialize_block(map_block, &retained, &self.content_ids)?;
+        self.store_data(pos, data)?;
+        trace!("stored map block {pos}");
+        // ids assigned while loading blocks need to be kept along with the stored blocks
+        self.content_ids.save()
+    }
+
+    fn load_block(&self, pos: MapBlockPos) -> Result<Option<WorldBlock>> {
+        let Some(data) = self.load_data(pos)? else {
+            trace!("map block {pos} doesn't exist in map database");
+            return Ok(None);
+        };
+        let (block, _) = deserialize_block(pos, &data, &self.content_ids)?;
+        Ok(Some(block))
+    }
+
+    fn load_serialized_block(&self, pos: MapBlockPos) -> Result<Option<Vec<u8>>> {
+        self.load_data(pos)
+    }
+
+    fn store_serialized_block(&mut self, pos: MapBlockPos, data: &[u8]) -> Result<()> {
+        self.store_data(pos, data.to_vec())?;
+        trace!("stored serialized map block {pos}");
+        Ok(())
+    }
+
+    fn block_positions(&self) -> Result<Vec<MapBlockPos>> {
+        let coordinates = self.runtime.block_on(async {
+            match self.schema {
+                Schema::Legacy => {
+                    let keys = sqlx::query_scalar::<Sqlite, i64>("SELECT pos FROM blocks")
+                        .fetch_all(&self.pool)
+                        .await?;
+                    Ok::<_, sqlx::Error>(keys.into_iter().map(block_vec).collect::<Vec<_>>())
+                }
+                Schema::Coordinates => {
+                    let rows =
+                        sqlx::query_as::<Sqlite, (i16, i16, i16)>("SELECT x, y, z FROM blocks")
+                            .fetch_all(&self.pool)
+                            .await?;
+                    Ok(rows
+                        .into_iter()
+                        .map(|(x, y, z)| I16Vec3::new(x, y, z))
+                        .collect())
+                }
+            }
+        })?;
+        coordinates
+            .into_iter()
+            .map(|vec| {
+                MapBlockPos::new(vec).ok_or_else(|| anyhow!("invalid map block position {vec}"))
+            })
+            .collect()
+    }
+
+    fn node_names(&self) -> Result<BTreeSet<SharedStr>> {
+        let mut names = BTreeSet::new();
+        for pos in self.block_positions()? {
+            if let Some(data) = self.load_data(pos)? {
+                names.extend(node_names(pos, &data)?);
+            }
+        }
+        Ok(names)
+    }
+
+    fn snapshot(&self, directory: &Path) -> Result<()> {
+        fs::create_dir_all(directory)
+            .with_context(|| format!("failed to create {}", directory.display()))?;
+        let path = directory.join(MAP_FILE_NAME);
+        let Some(path_str) = path.to_str() else {
+            bail!("snapshot path {} isn't valid UTF-8", path.display());
+        };
+        // `VACUUM INTO` reads within a single transaction, so the copy is consistent even while
+        // the C++ engine or another storage is writing
+        self.runtime.block_on(async {
+            sqlx::query("VACUUM INTO ?")
+                .bind(path_str)
+                .execute(&self.pool)
+                .await
+        })?;
+        self.content_ids.save_to(directory)?;
+        info!("created snapshot {}", path.display());
+        Ok(())
+    }
+}
+
+/// Encodes a block position into the key used by the legacy layout of the `blocks` table.
+fn block_key(pos: MapBlockPos) -> i64 {
+    let I16Vec3 { x, y, z } = pos.vec();
+    i64::from(z) * 0x100_0000 + i64::from(y) * 0x1000 + i64::from(x)
+}
+
+/// Decodes a key of the legacy layout of the `blocks` table; the inverse of [`block_key`].
+fn block_vec(key: i64) -> I16Vec3 {
+    /// Splits off the lowest 12 bits as a signed coordinate.
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the coordinate is always within -2048..2048"
+    )]
+    fn split(value: i64) -> (i16, i64) {
+        let coordinate = (value + 0x800).rem_euclid(0x1000) - 0x800;
+        (coordinate as i16, (value - coordinate) / 0x1000)
+    }
+
+    let (x, key) = split(key);
+    let (y, key) = split(key);
+    let (z, _) = split(key);
+    I16Vec3::new(x, y, z)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::{env, fs, sync::Arc};
+
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode};
+
+    use super::{SqliteStorage, block_key, block_vec};
+    use crate::world::WorldBlock;
+    use crate::world::storage::WorldStorage;
+    use crate::world::storage::blob::{RetainedData, deserialize_block, serialize_block};
+    use crate::{ContentIdMap, ContentIdMapper};
+
+    #[test]
+    fn block_keys_match_the_engine() {
+        let key = |x, y, z| block_key(MapBlockPos::new(I16Vec3::new(x, y, z)).unwrap());
+        assert_eq!(key(0, 0, 0), 0);
+        assert_eq!(key(1, 2, 3), 0x300_2001);
+        assert_eq!(key(-1, 0, 0), -1);
+        assert_eq!(key(0, -1, 0), -0x1000);
+        assert_eq!(key(-2048, -2048, -2048), -0x8_0080_0800);
+    }
+
+    #[test]
+    fn block_keys_can_be_decoded() {
+        for vec in [
+            I16Vec3::new(0, 0, 0),
+            I16Vec3::new(1, 2, 3),
+            I16Vec3::new(-1, 0, 0),
+            I16Vec3::new(0, -1, 0),
+            I16Vec3::new(5, -7, -2048),
+            I16Vec3::new(2047, 2047, 2047),
+            I16Vec3::new(-2048, -2048, -2048),
+        ] {
+            assert_eq!(block_vec(block_key(MapBlockPos::new(vec).unwrap())), vec);
+        }
+    }
+
+    #[test]
+    fn snapshots_contain_all_blocks() {
+        let directory = env::temp_dir().join(format!("luanti-rs-snapshot-{}", std::process::id()));
+        fs::create_dir_all(directory.join("world")).unwrap();
+        let content_ids = Arc::new(ContentIdMapper::new(ContentIdMap::new()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut storage = runtime
+            .block_on(SqliteStorage::new(
+                directory.join("world"),
+                Arc::clone(&content_ids),
+            ))
+            .unwrap();
+
+        let block = WorldBlock {
+            version: 0,
+            pos: MapBlockPos::new(I16Vec3::new(1, -2, 3)).unwrap(),
+            is_underground: true,
+            day_night_differs: false,
+            lighting_complete: 0xffff,
+            nodes: MapBlockNodes(
+                [MapNode {
+                    content_id: ContentId::AIR,
+                    param1: 0,
+                    param2: 0,
+                }; MapBlockPos::NODE_COUNT as usize],
+            ),
+            metadata: vec![],
+        };
+        storage.store_block(&block).unwrap();
+        storage.snapshot(&directory.join("backup")).unwrap();
+
+        let snapshot = runtime
+            .block_on(SqliteStorage::new(directory.join("backup"), content_ids))
+            .unwrap();
+        assert_eq!(snapshot.block_positions().unwrap(), [block.pos]);
+        assert!(
+            snapshot
+                .load_block(block.pos)
+                .unwrap()
+                .is_some_and(|loaded| loaded.same_content(&block))
+        );
+
+        drop((storage, snapshot));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn serialized_blocks_are_stored_as_is() {
+        let directory =
+            env::temp_dir().join(format!("luanti-rs-serialized-{}", std::process::id()));
+        let content_ids = Arc::new(ContentIdMapper::new(ContentIdMap::new()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        fs::create_dir_all(&directory).unwrap();
+        let mut storage = runtime
+            .block_on(SqliteStorage::new(&directory, Arc::clone(&content_ids)))
+            .unwrap();
+
+        let block = WorldBlock {
+            version: 0,
+            pos: MapBlockPos::new(I16Vec3::new(4, 5, 6)).unwrap(),
+            is_underground: false,
+            day_night_differs: true,
+            lighting_complete: 0xffff,
+            nodes: MapBlockNodes(
+                [MapNode {
+                    content_id: ContentId::AIR,
+                    param1: 0,
+                    param2: 0,
+                }; MapBlockPos::NODE_COUNT as usize],
+            ),
+            metadata: vec![],
+        };
+        // a block which has been active and has a node timer
+        let retained = RetainedData {
+            timestamp: 1234,
+            static_objects: vec![0, 0, 0],
+            node_timers: vec![10, 0, 1, 0x07, 0xff, 0, 0, 0, 10, 0, 0, 0, 0],
+        };
+        let data = serialize_block(&block, &retained, &content_ids).unwrap();
+        storage.store_serialized_block(block.pos, &data).unwrap();
+
+        let stored = storage.load_serialized_block(block.pos).unwrap().unwrap();
+        assert_eq!(stored, data);
+        let (loaded, loaded_retained) =
+            deserialize_block(block.pos, &stored, &content_ids).unwrap();
+        assert!(loaded.same_content(&block));
+        assert_eq!(loaded_retained, retained);
+
+        drop(storage);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn stored_blocks_keep_static_objects_and_node_timers() {
+        let directory = env::temp_dir().join(format!("luanti-rs-retained-{}", std::process::id()));
+        let content_ids = Arc::new(ContentIdMapper::new(ContentIdMap::new()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        fs::create_dir_all(&directory).unwrap();
+        let mut storage = runtime
+            .block_on(SqliteStorage::new(&directory, Arc::clone(&content_ids)))
+            .unwrap();
+
+        let mut block = WorldBlock {
+            version: 0,
+            pos: MapBlockPos::new(I16Vec3::new(-3, 0, 2)).unwrap(),
+            is_underground: false,
+            day_night_differs: false,
+            lighting_complete: 0xffff,
+            nodes: MapBlockNodes(
+                [MapNode {
+                    content_id: ContentId::AIR,
+                    param1: 0,
+                    param2: 0,
+                }; MapBlockPos::NODE_COUNT as usize],
+            ),
+            metadata: vec![],
+        };
+        // a block written by the engine with a static object and a node timer
+        let retained = RetainedData {
+            timestamp: 99,
+            static_objects: vec![0, 0, 1, 7, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 1, 0xab],
+            node_timers: vec![10, 0, 1, 0x07, 0xff, 0, 0, 0, 10, 0, 0, 0, 0],
+        };
+        let data = serialize_block(&block, &retained, &content_ids).unwrap();
+        storage.store_serialized_block(block.pos, &data).unwrap();
+
+        block.is_underground = true;
+        storage.store_block(&block).unwrap();
+        let stored = storage.load_serialized_block(block.pos).unwrap().unwrap();
+        let (loaded, loaded_retained) =
+            deserialize_block(block.pos, &stored, &content_ids).unwrap();
+        assert!(loaded.is_underground);
+        assert_eq!(loaded_retained, retained);
+
+        // nothing is lost silently if the stored block cannot be read
+        storage
+            .store_serialized_block(block.pos, &[29, 1, 2, 3])
+            .unwrap();
+        assert!(storage.store_block(&block).is_err());
+        assert_eq!(
+            storage.load_serialized_block(block.pos).unwrap().unwrap(),
+            [29, 1, 2, 3]
+        );
+
+        drop(storage);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+//! Handling of nodes which are stored in the world but have no definition
+//!
+//! Storages assign a content id to every node name they encounter, including names of mods which
+//! have been removed from the game. Clients don't know what to draw for ids without a definition,
+//! so the [`UnknownNodeFilter`] replaces them with a placeholder before a map block is sent and
+//! remembers their names in [`UnknownNodes`] for diagnostics.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use flexstr::SharedStr;
+use log::warn;
+use luanti_core::{ContentId, MapBlockNodes, MapNode};
+use luanti_protocol::types::NodeDefManager;
+
+use super::content_id_mapper::ContentIdMapper;
+
+/// Collects the nodes without a definition which have been found in the world.
+///
+/// Clones share the same nodes.
+#[derive(Debug, Clone, Default)]
+pub struct UnknownNodes {
+    nodes: Arc<Mutex<HashMap<ContentId, SharedStr>>>,
+}
+
+impl UnknownNodes {
+    /// Returns the names of all nodes without a definition found so far in alphabetical order.
+    /// Nodes without a known name are listed by their content id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the lock.
+    #[must_use]
+    pub fn names(&self) -> Vec<SharedStr> {
+        let mut names: Vec<_> = self
+            .nodes
+            .lock()
+            .expect("poisoned unknown nodes")
+            .values()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns `true` if no node without a definition has been found so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the lock.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes
+            .lock()
+            .expect("poisoned unknown nodes")
+            .is_empty()
+    }
+
+    /// Adds a node; returns `false` if it has been added before.
+    fn insert(&self, content_id: ContentId, name: impl FnOnce() -> SharedStr) -> bool {
+        let mut nodes = self.nodes.lock().expect("poisoned unknown nodes");
+        if nodes.contains_key(&content_id) {
+            return false;
+        }
+        nodes.insert(content_id, name());
+        true
+    }
+}
+
+/// Replaces the nodes without a definition within map blocks which are about to be sent.
+pub(crate) struct UnknownNodeFilter {
+    /// whether a node has a definition, indexed by the content id
+    defined: Box<[bool]>,
+    placeholder: ContentId,
+    /// used to look up the names of unknown nodes
+    content_ids: Option<Arc<ContentIdMapper>>,
+    found: UnknownNodes,
+}
+
+impl UnknownNodeFilter {
+    /// Creates a filter replacing all nodes which have neither a definition nor are built into
+    /// the client with `placeholder`.
+    pub(crate) fn new(
+        node_def: &NodeDefManager,
+        placeholder: ContentId,
+        content_ids: Option<Arc<ContentIdMapper>>,
+        found: UnknownNodes,
+    ) -> Self {
+        let mut defined = vec![false; usize::from(u16::MAX) + 1].into_boxed_slice();
+        let builtin = [ContentId::UNKNOWN, ContentId::AIR, ContentId::IGNORE];
+        for id in node_def
+            .content_features
+            .iter()
+            .map(|(id, _)| ContentId(*id))
+            .chain(builtin)
+        {
+            if let Some(slot) = defined.get_mut(usize::from(id)) {
+                *slot = true;
+            }
+        }
+        if !defined
+            .get(usize::from(placeholder))
+            .copied()
+            .unwrap_or(false)
+        {
+            warn!(
+                "the placeholder {} for unknown nodes has no definition itself",
+                placeholder.0
+            );
+        }
+        Self {
+            defined,
+            placeholder,
+            content_ids,
+            found,
+        }
+    }
+
+    /// Replaces all nodes without a definition. Every node is logged when it's found the first
+    /// time.
+    pub(crate) fn apply(&self, nodes: &mut MapBlockNodes) {
+        // blocks usually contain a few kinds of nodes only, so this needs no set
+        let mut unknown = Vec::new();
+        for node in &mut nodes.0 {
+            if self.is_defined(node.content_id) {
+                continue;
+            }
+            if !unknown.contains(&node.content_id) {
+                unknown.push(node.content_id);
+            }
+            node.content_id = self.placeholder;
+        }
+        for content_id in unknown {
+            self.report(content_id);
+        }
+    }
+
+    /// Replaces a single node if it has no definition.
+    pub(crate) fn apply_node(&self, node: &mut MapNode) {
+        if !self.is_defined(node.content_id) {
+            self.report(node.content_id);
+            node.content_id = self.placeholder;
+        }
+    }
+
+    fn is_defined(&self, content_id: ContentId) -> bool {
+        self.defined
+            .get(usize::from(content_id))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn report(&self, content_id: ContentId) {
+        let name = || {
+            self.content_ids
+                .as_ref()
+                .map(|content_ids| content_ids.name(content_id))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| SharedStr::from(format!("#{}", content_id.0)))
+        };
+        if self.found.insert(content_id, name) {
+            warn!(
+                "node '{}' has no definition and is replaced by {}",
+                name(),
+                self.placeholder.0
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::sync::Arc;
+
+    use luanti_core::{ContentId, MapBlockNodes, MapBlockPos, MapNode, MapNodeIndex};
+    use luanti_protocol::types::{ContentFeatures, NodeDefManager};
+
+    use super::{UnknownNodeFilter, UnknownNodes};
+    use crate::{ContentIdMap, ContentIdMapper};
+
+    #[test]
+    fn undefined_nodes_are_replaced() {
+        let content_ids = Arc::new(ContentIdMapper::new(ContentIdMap::new()));
+        let stone = content_ids.id(b"default:stone").unwrap();
+        let removed = content_ids.id(b"oldmod:gem").unwrap();
+        let node_def = NodeDefManager {
+            content_features: vec![(
+                stone.0,
+                ContentFeatures::new_unknown("default:stone".into()),
+            )],
+        };
+        let found = UnknownNodes::default();
+        let filter = UnknownNodeFilter::new(
+            &node_def,
+            ContentId::UNKNOWN,
+            Some(content_ids),
+            found.clone(),
+        );
+
+        let node = |content_id| MapNode {
+            content_id,
+            param1: 0,
+            param2: 0,
+        };
+        let mut nodes = MapBlockNodes([node(ContentId::AIR); MapBlockPos::NODE_COUNT as usize]);
+        nodes[MapNodeIndex::from(1_u16)] = node(stone);
+        nodes[MapNodeIndex::from(2_u16)] = node(removed);
+        nodes[MapNodeIndex::from(3_u16)] = node(ContentId(4000));
+        assert!(found.is_empty());
+        filter.apply(&mut nodes);
+
+        assert_eq!(nodes[MapNodeIndex::from(0_u16)].content_id, ContentId::AIR);
+        assert_eq!(nodes[MapNodeIndex::from(1_u16)].content_id, stone);
+        assert_eq!(
+            nodes[MapNodeIndex::from(2_u16)].content_id,
+            ContentId::UNKNOWN
+        );
+        assert_eq!(
+            nodes[MapNodeIndex::from(3_u16)].content_id,
+            ContentId::UNKNOWN
+        );
+        assert_eq!(found.names(), ["#4000", "oldmod:gem"]);
+    }
+}
@@ -2,35 +2,117 @@
 //! sent to them.
 
 use std::{
-    collections::{HashMap, hash_map::Entry},
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, hash_map::Entry},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
 use flexstr::SharedStr;
 use glam::{I16Vec3, Vec3};
 use log::{debug, error, trace, warn};
-use luanti_core::MapBlockPos;
+use luanti_core::{MapBlockPos, MapNodePos};
 use luanti_protocol::commands::client_to_server::{DeletedblocksSpec, GotBlocksSpec};
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender, error::TryRecvError};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender, error::TryRecvError},
+    oneshot,
+};
+
+use crate::world::{WorldBlock, WorldUpdate};
+
+use super::{
+    map_block_router::{PlayerInterests, ToRouterMessage},
+    priority::Priority,
+};
+
+/// distance from the center of a map block to its corners in nodes
+const BLOCK_RADIUS: f32 = 13.9;
+
+/// blocks within this distance (in nodes) are considered to be visible regardless of the
+/// viewing direction
+const NEAR_DISTANCE: f32 = 32.0;
+
+/// the view is being recomputed if the viewing direction changed by more than this angle
+/// (in radians) since the last computation
+const DIRECTION_THRESHOLD: f32 = 0.25;
+
+/// Settings of the view trackers of all players
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewConfig {
+    /// upper limit of the view range in map blocks, regardless of what the client asks for
+    pub max_range: u8,
+    /// maximum number of map blocks a client may have received without confirming them
+    pub max_blocks_in_flight: usize,
+    /// map blocks which haven't been confirmed within this time no longer count as being in
+    /// flight
+    pub confirmation_timeout: Duration,
+    /// map blocks the client has a copy of are sent again as a whole once more than this many of
+    /// their nodes changed at once, rather than each changed node on its own; `None` always
+    /// sends the changed nodes
+    pub max_node_changes: Option<usize>,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        Self {
+            max_range: 10,
+            // the default of the C++ engine
+            max_blocks_in_flight: 40,
+            confirmation_timeout: Duration::from_secs(10),
+            // a compressed map block takes about as many bytes as this many changed nodes
+            max_node_changes: Some(64),
+        }
+    }
+}
+
+/// The interests of players who left, kept until they join again
+///
+/// A player joining again gets the map blocks which have been of interest before right away,
+/// without waiting for the first position to be reported.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InterestSnapshots {
+    players: Arc<Mutex<HashMap<SharedStr, PlayerInterests>>>,
+}
+
+impl InterestSnapshots {
+    /// Locks the snapshots; a panic while holding the lock leaves the map consistent, as every
+    /// change is a single insertion or removal.
+    fn lock(&self) -> MutexGuard<'_, HashMap<SharedStr, PlayerInterests>> {
+        self.players.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 
-use crate::world::WorldUpdate;
+    fn store(&self, player_key: SharedStr, interests: PlayerInterests) {
+        self.lock().insert(player_key, interests);
+    }
 
-use super::{map_block_router::ToRouterMessage, priority::Priority};
+    fn take(&self, player_key: &SharedStr) -> Option<PlayerInterests> {
+        self.lock().remove(player_key)
+    }
+}
 
 /// Keeps track of the map blocks a single player is and shall be aware of.
 pub(crate) struct ViewTracker {
     _player_key: SharedStr,
     _runner: JoinHandle<Result<()>>,
     player_view_sender: UnboundedSender<PlayerViewEvent>,
+    /// published by the runner after every iteration; see [`Self::pending_blocks`]
+    pending_blocks: Arc<AtomicUsize>,
 }
 
 impl ViewTracker {
+    /// Registers the player with the router. The interests the player had when leaving the last
+    /// time are restored from `snapshots`.
     pub(crate) fn new(
         player_key: SharedStr,
+        config: ViewConfig,
         block_interest_sender: UnboundedSender<ToRouterMessage>,
         world_update_sender: UnboundedSender<WorldUpdate>,
+        snapshots: InterestSnapshots,
     ) -> Result<Self> {
         let (player_view_sender, player_view_receiver) = mpsc::unbounded_channel();
         let (external_world_update_sender, world_update_receiver) = mpsc::unbounded_channel();
@@ -39,17 +121,33 @@ impl ViewTracker {
             player_key: player_key.clone(),
             sender: external_world_update_sender,
         })?;
+        let mut state = ViewState::new(player_key.clone(), config);
+        if let Some(interests) = snapshots.take(&player_key) {
+            debug!(
+                "restoring the interest of player '{player_key}' in {} map blocks",
+                interests.len()
+            );
+            state.restore(&interests);
+            block_interest_sender.send(ToRouterMessage::RestoreInterests {
+                player_key: player_key.clone(),
+                interests,
+            })?;
+        }
 
         // the implementation is expected to be compute intensive, so a dedicated thread should be
         // more appropriate than an async task
         let player_key_clone = player_key.clone();
+        let pending_blocks = Arc::new(AtomicUsize::new(0));
+        let pending_blocks_clone = Arc::clone(&pending_blocks);
         let runner = thread::spawn(move || {
             Self::run_inner(
-                &player_key_clone,
+                state,
                 player_view_receiver,
                 &block_interest_sender,
                 world_update_receiver,
                 &world_update_sender,
+                &pending_blocks_clone,
+                &snapshots,
             )
             .inspect_err(|error| {
                 error!("view tracker for player '{player_key_clone}' exited with error: {error}");
@@ -60,6 +158,7 @@ impl ViewTracker {
             _player_key: player_key,
             _runner: runner,
             player_view_sender,
+            pending_blocks,
         })
     }
 
@@ -68,22 +167,36 @@ impl ViewTracker {
         Ok(())
     }
 
+    /// Returns the number of map blocks which are waiting to be sent or to be confirmed by the
+    /// client.
+    pub(crate) fn pending_blocks(&self) -> usize {
+        self.pending_blocks.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Unregisters the player from the router after keeping their interests for the next time
+    /// they join.
+    pub(crate) fn leave(&self) -> Result<()> {
+        self.player_view_sender.send(PlayerViewEvent::Leave)?;
+        Ok(())
+    }
+
+    /// - `state`: state of all map blocks the player is interested in
     /// - `player_view_receiver`: informs this tracker about player movements
     /// - `block_interest_sender`: reports which map blocks this player is interested in
     /// - `world_update_receiver`: informs this tracker about world updates (new blocks, changed nodes, etc.)
     /// - `world_update_sender`: used to forward changes of the world to the player
-    /// - `map_block_states`: state of all map blocks the player is interested in
-    #[expect(clippy::too_many_lines, reason = "//TODO(kawogi) split this up")]
+    /// - `pending_blocks`: receives the number of map blocks not yet confirmed by the client
+    /// - `snapshots`: receives the interests of the player when they leave
     fn run_inner(
-        player_key: &SharedStr,
+        mut state: ViewState,
         mut player_view_receiver: UnboundedReceiver<PlayerViewEvent>,
         block_interest_sender: &UnboundedSender<ToRouterMessage>,
         mut world_update_receiver: UnboundedReceiver<WorldUpdate>,
         world_update_sender: &UnboundedSender<WorldUpdate>,
+        pending_blocks: &AtomicUsize,
+        snapshots: &InterestSnapshots,
     ) -> Result<()> {
-        let mut map_block_states = HashMap::with_capacity(1024);
-        let mut recent_player_block_pos = None;
-
+        let player_key = state.player_key.clone();
         'thread_loop: loop {
             // used to measure activity
             let mut event_count = 0;
@@ -100,79 +213,27 @@ impl ViewTracker {
                 }
                 Err(TryRecvError::Empty) => None,
             } {
-                match event {
-                    PlayerViewEvent::PlayerPos { position } => {
-                        // TODO(kawogi) this entire implementation is a placeholder and shall be replaced
-
-                        // find the block containing the player
-                        let current_block_pos = MapBlockPos::for_vec(position.round().as_i16vec3());
-
-                        // only recompute if the player moved into a different block …
-                        // this will always evaluate to `true` for the first iteration
-                        if Some(current_block_pos) != recent_player_block_pos {
-                            if let Some(recent_block_pos) = recent_player_block_pos {
-                                trace!(
-                                    "player '{player_key}' moved from block {recent_block_pos} to {current_block_pos}",
-                                );
-                            } else {
-                                trace!("player '{player_key}' starts at block {current_block_pos}");
-                            }
-                            recent_player_block_pos = Some(current_block_pos);
-
-                            // make sure that all surrounding blocks have an entry in the state table
-                            let radius = 1;
-                            let range = -radius..=radius;
-                            for dz in range.clone() {
-                                for dy in range.clone() {
-                                    for dx in range.clone() {
-                                        if let Some(block_pos) =
-                                            current_block_pos.checked_add(I16Vec3::new(dx, dy, dz))
-                                        {
-                                            map_block_states
-                                                .entry(block_pos)
-                                                .or_insert_with(MapBlockState::default);
-                                        }
-                                    }
-                                }
-                            }
-
-                            #[expect(
-                                unused_variables,
-                                reason = "// TODO(kawogi) this implementation is likely still incomplete"
-                            )]
-                            for (&block_pos, state) in &mut map_block_states {
-                                let priority = Priority::from_block_distance(
-                                    current_block_pos,
-                                    block_pos,
-                                    100,
-                                );
-
-                                let interest = BlockInterest::subscribe(
-                                    player_key.clone(),
-                                    block_pos,
-                                    priority,
-                                );
-
-                                block_interest_sender
-                                    .send(ToRouterMessage::BlockInterest(interest))?;
-                            }
-                        }
-                    }
+                let interests = match event {
+                    PlayerViewEvent::PlayerPos(view) => state.update_view(view),
                     PlayerViewEvent::GotMapBlocks(GotBlocksSpec { blocks }) => {
-                        Self::handle_got_map_blocks(player_key, &mut map_block_states, blocks);
+                        state.got_map_blocks(blocks);
+                        Vec::new()
                     }
                     PlayerViewEvent::DroppedBlocks(DeletedblocksSpec { blocks }) => {
-                        Self::handle_deleted_map_blocks(
-                            player_key,
-                            &mut map_block_states,
-                            blocks,
-                            block_interest_sender,
-                        )?;
+                        state.deleted_map_blocks(blocks)
                     }
+                    PlayerViewEvent::Leave => {
+                        Self::leave_router(&player_key, block_interest_sender, snapshots)?;
+                        break 'thread_loop;
+                    }
+                };
+                for interest in interests {
+                    block_interest_sender.send(ToRouterMessage::BlockInterest(interest))?;
                 }
             }
 
             // process bursts of world updates
+            let mut forwarded = Vec::new();
             while let Some(event) = match world_update_receiver.try_recv() {
                 Ok(event) => {
                     event_count += 1;
@@ -186,39 +247,25 @@ impl ViewTracker {
                 }
                 Err(TryRecvError::Empty) => None,
             } {
-                match event {
-                    WorldUpdate::NewMapBlock(world_block) => {
-                        let block_pos = world_block.pos;
-
-                        match map_block_states.entry(block_pos) {
-                            #[expect(
-                                unused_variables,
-                                reason = "// TODO(kawogi) this implementation is likely still incomplete"
-                            )]
-                            Entry::Occupied(occupied_entry) => {
-                                // let mut state = occupied_entry.get_mut();
-                                // if state.sent_to_client {
-                                //     warn!(
-                                //         "player '{player_key}' already received a copy of map block {block_pos}"
-                                //     );
-                                // }
-                            }
-                            Entry::Vacant(_vacant_entry) => {
-                                // trace!(
-                                //     "player '{player_key}' has no interest in map block {block_pos}"
-                                // );
-                            }
-                        }
+                forwarded.extend(state.world_updated(event));
+            }
+            let (forwarded, interests) = state.resend_changed_blocks(forwarded);
+            for interest in interests {
+                block_interest_sender.send(ToRouterMessage::BlockInterest(interest))?;
+            }
+            if let Some(update) = WorldUpdate::batch(forwarded) {
+                world_update_sender.send(update)?;
+            }
 
-                        // just forward this block to the player
-                        trace!(
-                            "forwarding map block {pos} to player '{player_key}'",
-                            pos = world_block.pos
-                        );
-                        world_update_sender.send(WorldUpdate::NewMapBlock(world_block))?;
-                    }
-                }
+            for world_block in state.next_map_blocks(Instant::now()) {
+                trace!(
+                    "forwarding map block {pos} to player '{player_key}'",
+                    pos = world_block.pos
+                );
+                event_count += 1;
+                world_update_sender.send(WorldUpdate::NewMapBlock(world_block))?;
             }
+            pending_blocks.store(state.pending_blocks(), AtomicOrdering::Relaxed);
 
             // slow down event polling if there was nothing to do in the recent iteration
             if event_count == 0 {
@@ -229,13 +276,447 @@ impl ViewTracker {
         Ok(())
     }
 
-    fn handle_got_map_blocks(
+    /// Takes the interests of the player from the router and unregisters them.
+    fn leave_router(
         player_key: &SharedStr,
-        map_block_states: &mut HashMap<MapBlockPos, MapBlockState>,
-        mut blocks: Vec<I16Vec3>,
-    ) {
+        block_interest_sender: &UnboundedSender<ToRouterMessage>,
+        snapshots: &InterestSnapshots,
+    ) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        block_interest_sender.send(ToRouterMessage::SnapshotInterests {
+            player_key: player_key.clone(),
+            sender,
+        })?;
+        // this is a dedicated thread, so blocking doesn't stall any async task
+        match receiver.blocking_recv() {
+            Ok(interests) => snapshots.store(player_key.clone(), interests),
+            Err(_) => warn!("the router didn't report the interests of player '{player_key}'"),
+        }
+        block_interest_sender.send(ToRouterMessage::Unregister(player_key.clone()))?;
+        Ok(())
+    }
+}
+
+/// The position and viewing direction of a player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PlayerView {
+    /// position of the player in nodes
+    pub(crate) position: Vec3,
+    /// vertical look direction in degrees
+    pub(crate) pitch: f32,
+    /// horizontal look direction in degrees
+    pub(crate) yaw: f32,
+    /// the field of view in radians
+    pub(crate) fov: f32,
+    /// the view range requested by the client in map blocks
+    pub(crate) wanted_range: u8,
+}
+
+impl PlayerView {
+    /// Returns the normalized viewing direction.
+    fn direction(&self) -> Vec3 {
+        let (pitch, yaw) = (self.pitch.to_radians(), self.yaw.to_radians());
+        Vec3::new(
+            -yaw.sin() * pitch.cos(),
+            -pitch.sin(),
+            yaw.cos() * pitch.cos(),
+        )
+    }
+}
+
+pub(crate) enum PlayerViewEvent {
+    /// The player has changed its position or viewing direction
+    PlayerPos(PlayerView),
+    /// The player confirmed to have received some blocks
+    GotMapBlocks(GotBlocksSpec),
+    /// The player reports to have removed some map blocks from its cache
+    DroppedBlocks(DeletedblocksSpec),
+    /// The player left; see [`ViewTracker::leave`]
+    Leave,
+}
+
+/// Describes how much a player wants to see a certain map block
+pub struct BlockInterest {
+    pub(crate) player_key: SharedStr,
+    /// position of the block
+    pub(crate) pos: MapBlockPos,
+    /// a value of _how much_ the player wants to see this block
+    pub(crate) priority: Priority,
+}
+
+impl BlockInterest {
+    fn subscribe(player_key: SharedStr, pos: MapBlockPos, priority: Priority) -> Self {
+        Self {
+            player_key,
+            pos,
+            priority,
+        }
+    }
+
+    fn unsubscribe(player_key: SharedStr, pos: MapBlockPos) -> Self {
+        Self {
+            player_key,
+            pos,
+            priority: Priority::NONE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct MapBlockState {
+    /// How important it is that the player sees this map block
+    priority: Priority,
+    /// whether this block has been sent to the client
+    sent_to_client: bool,
+    /// whether the client confirmed to have a copy of this map block
+    cached_by_client: bool,
+    /// when the block has been sent, as long as the client didn't confirm it
+    in_flight_since: Option<Instant>,
+}
+
+/// An entry of the send queue. Entries become stale if the priority of their block changes.
+#[derive(PartialEq, Eq)]
+struct QueueEntry {
+    priority: Priority,
+    pos: MapBlockPos,
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| self.pos.vec().to_array().cmp(&other.pos.vec().to_array()))
+    }
+}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Decides which map blocks a player needs and in which order they're being sent.
+struct ViewState {
+    player_key: SharedStr,
+    config: ViewConfig,
+    /// the view the priorities have been computed for
+    view: Option<PlayerView>,
+    /// state of all map blocks the player is interested in or has a copy of
+    map_block_states: HashMap<MapBlockPos, MapBlockState>,
+    /// map blocks which are ready to be sent
+    ready: HashMap<MapBlockPos, WorldBlock>,
+    /// the order in which the map blocks in `ready` will be sent
+    send_queue: BinaryHeap<QueueEntry>,
+    /// number of map blocks which have been sent but not confirmed
+    in_flight: usize,
+}
+
+impl ViewState {
+    fn new(player_key: SharedStr, config: ViewConfig) -> Self {
+        Self {
+            player_key,
+            config,
+            view: None,
+            map_block_states: HashMap::with_capacity(1024),
+            ready: HashMap::new(),
+            send_queue: BinaryHeap::new(),
+            in_flight: 0,
+        }
+    }
+
+    /// Adopts interests which have been restored with the router, so the map blocks are being
+    /// sent once they arrive. They are replaced by the first view of the player.
+    fn restore(&mut self, interests: &PlayerInterests) {
+        for (pos, priority) in interests.blocks() {
+            self.map_block_states.insert(
+                pos,
+                MapBlockState {
+                    priority,
+                    ..MapBlockState::default()
+                },
+            );
+        }
+    }
+
+    /// Returns the view range in map blocks.
+    fn range(&self, view: &PlayerView) -> u8 {
+        view.wanted_range.clamp(1, self.config.max_range.max(1))
+    }
+
+    /// Updates the priorities of all map blocks within the view range and returns the changes of
+    /// interest to be reported to the router.
+    fn update_view(&mut self, view: PlayerView) -> Vec<BlockInterest> {
+        let player_block_pos = MapBlockPos::for_vec(view.position.round().as_i16vec3());
+        if let Some(recent) = self.view {
+            let recent_block_pos = MapBlockPos::for_vec(recent.position.round().as_i16vec3());
+            if recent_block_pos == player_block_pos
+                && self.range(&recent) == self.range(&view)
+                && recent.direction().angle_between(view.direction()) < DIRECTION_THRESHOLD
+            {
+                return Vec::new();
+            }
+            if recent_block_pos != player_block_pos {
+                trace!(
+                    "player '{}' moved from block {recent_block_pos} to {player_block_pos}",
+                    self.player_key
+                );
+            }
+        } else {
+            trace!(
+                "player '{}' starts at block {player_block_pos}",
+                self.player_key
+            );
+        }
+        self.view = Some(view);
+
+        let range = self.range(&view);
+        let max_distance = f32::from(range) * f32::from(MapBlockPos::SIZE) + BLOCK_RADIUS;
+        let mut priorities = HashMap::new();
+        for block_pos in player_block_pos.blocks_in_radius(range.into()) {
+            let priority = block_priority(&view, block_pos, max_distance);
+            if !priority.is_none() {
+                priorities.insert(block_pos, priority);
+            }
+        }
+
+        let mut interests = Vec::new();
+        for (&block_pos, &priority) in &priorities {
+            let state = self.map_block_states.entry(block_pos).or_default();
+            if state.priority == priority {
+                continue;
+            }
+            state.priority = priority;
+            if self.ready.contains_key(&block_pos) {
+                self.send_queue.push(QueueEntry {
+                    priority,
+                    pos: block_pos,
+                });
+            } else if !state.sent_to_client {
+                interests.push(BlockInterest::subscribe(
+                    self.player_key.clone(),
+                    block_pos,
+                    priority,
+                ));
+            }
+        }
+
+        // forget about blocks which went out of range, unless the client has a copy
+        let player_key = &self.player_key;
+        let ready = &mut self.ready;
+        self.map_block_states.retain(|&block_pos, state| {
+            if priorities.contains_key(&block_pos) || state.priority.is_none() {
+                return true;
+            }
+            state.priority = Priority::NONE;
+            if state.sent_to_client {
+                return true;
+            }
+            if ready.remove(&block_pos).is_none() {
+                interests.push(BlockInterest::unsubscribe(player_key.clone(), block_pos));
+            }
+            false
+        });
+
+        interests
+    }
+
+    /// Applies an update of the world and returns the part of it the client has to be told
+    /// about.
+    ///
+    /// Changes of map blocks which are waiting to be sent are applied to them instead.
+    fn world_updated(&mut self, update: WorldUpdate) -> Option<WorldUpdate> {
+        let block_pos = match update {
+            WorldUpdate::NewMapBlock(world_block) => {
+                self.map_block_arrived(world_block);
+                return None;
+            }
+            WorldUpdate::Batch(updates) => {
+                let forwarded = updates
+                    .into_iter()
+                    .filter_map(|batched| self.world_updated(batched))
+                    .collect();
+                return WorldUpdate::batch(forwarded);
+            }
+            // the client keeps its copy anyway
+            WorldUpdate::BlockUnloaded(_) => return None,
+            WorldUpdate::NodeChanged { pos, .. } | WorldUpdate::NodeMetaChanged { pos, .. } => {
+                pos.block_pos()
+            }
+            WorldUpdate::EntityMoved { position, .. } => {
+                MapBlockPos::for_vec(position.round().as_i16vec3())
+            }
+        };
+        if self
+            .map_block_states
+            .get(&block_pos)
+            .is_some_and(|state| state.sent_to_client)
+        {
+            return Some(update);
+        }
+        if let Some(world_block) = self.ready.get_mut(&block_pos) {
+            match update {
+                WorldUpdate::NodeChanged { pos, node } => world_block.nodes[pos.index()] = node,
+                WorldUpdate::NodeMetaChanged { pos, metadata } => {
+                    world_block.set_metadata(pos.index(), metadata);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Replaces the changes of map blocks with many changed nodes by sending these map blocks
+    /// again as a whole. Returns the remaining changes and the interests to be reported to the
+    /// router.
+    ///
+    /// Only map blocks the client confirmed to have are sent again, as confirmations of blocks in
+    /// flight couldn't be told apart.
+    fn resend_changed_blocks(
+        &mut self,
+        updates: Vec<WorldUpdate>,
+    ) -> (Vec<WorldUpdate>, Vec<BlockInterest>) {
+        let Some(max_node_changes) = self.config.max_node_changes else {
+            return (updates, Vec::new());
+        };
+        let updates: Vec<_> = updates
+            .into_iter()
+            .flat_map(WorldUpdate::into_updates)
+            .collect();
+        let mut node_changes = HashMap::<MapBlockPos, usize>::new();
+        for update in &updates {
+            if let WorldUpdate::NodeChanged { pos, .. } = update {
+                *node_changes.entry(pos.block_pos()).or_default() += 1;
+            }
+        }
+
+        let mut interests = Vec::new();
+        for (block_pos, count) in node_changes {
+            if count <= max_node_changes {
+                continue;
+            }
+            let Some(state) = self.map_block_states.get_mut(&block_pos) else {
+                continue;
+            };
+            if !state.cached_by_client || state.priority.is_none() {
+                continue;
+            }
+            trace!(
+                "sending map block {block_pos} with {count} changed nodes to player '{}' again",
+                self.player_key
+            );
+            state.sent_to_client = false;
+            state.cached_by_client = false;
+            interests.push(BlockInterest::subscribe(
+                self.player_key.clone(),
+                block_pos,
+                state.priority,
+            ));
+        }
+        if interests.is_empty() {
+            return (updates, interests);
+        }
+
+        // the map block being sent again contains these changes
+        let updates = updates
+            .into_iter()
+            .filter(|update| match update {
+                WorldUpdate::NodeChanged { pos, .. } | WorldUpdate::NodeMetaChanged { pos, .. } => {
+                    let block_pos = pos.block_pos();
+                    !interests.iter().any(|interest| interest.pos == block_pos)
+                }
+                _ => true,
+            })
+            .collect();
+        (updates, interests)
+    }
+
+    /// Queues a map block which has been loaded or generated for being sent.
+    fn map_block_arrived(&mut self, world_block: WorldBlock) {
+        let block_pos = world_block.pos;
+        let Some(state) = self.map_block_states.get(&block_pos) else {
+            trace!(
+                "player '{}' has no interest in map block {block_pos}",
+                self.player_key
+            );
+            return;
+        };
+        if state.priority.is_none() {
+            trace!(
+                "player '{}' lost interest in map block {block_pos}",
+                self.player_key
+            );
+            return;
+        }
+        if state.sent_to_client {
+            trace!(
+                "player '{}' already received a copy of map block {block_pos}",
+                self.player_key
+            );
+            return;
+        }
+        self.send_queue.push(QueueEntry {
+            priority: state.priority,
+            pos: block_pos,
+        });
+        self.ready.insert(block_pos, world_block);
+    }
+
+    /// Returns the map blocks to be sent next, ordered by their priority, without exceeding the
+    /// number of map blocks in flight.
+    fn next_map_blocks(&mut self, now: Instant) -> Vec<WorldBlock> {
+        if self.in_flight > 0 {
+            self.expire_in_flight(now);
+        }
+
+        let mut result = Vec::new();
+        while self.in_flight < self.config.max_blocks_in_flight {
+            let Some(QueueEntry { priority, pos }) = self.send_queue.pop() else {
+                break;
+            };
+            let Some(state) = self.map_block_states.get_mut(&pos) else {
+                continue;
+            };
+            if state.priority != priority {
+                // the priority has changed and a more recent entry exists
+                continue;
+            }
+            let Some(world_block) = self.ready.remove(&pos) else {
+                continue;
+            };
+            state.sent_to_client = true;
+            state.in_flight_since = Some(now);
+            self.in_flight += 1;
+            result.push(world_block);
+        }
+        result
+    }
+
+    /// Returns the number of map blocks which are waiting to be sent or to be confirmed by the
+    /// client.
+    fn pending_blocks(&self) -> usize {
+        self.ready.len() + self.in_flight
+    }
+
+    /// Stops waiting for confirmations which take too long.
+    fn expire_in_flight(&mut self, now: Instant) {
+        for (block_pos, state) in &mut self.map_block_states {
+            if state.in_flight_since.is_some_and(|since| {
+                now.saturating_duration_since(since) > self.config.confirmation_timeout
+            }) {
+                debug!(
+                    "player '{}' didn't confirm the reception of map block {block_pos}",
+                    self.player_key
+                );
+                state.in_flight_since = None;
+                self.in_flight -= 1;
+            }
+        }
+    }
+
+    fn got_map_blocks(&mut self, mut blocks: Vec<I16Vec3>) {
+        let player_key = &self.player_key;
         for block_pos in blocks.drain(..).filter_map(MapBlockPos::new) {
-            match map_block_states.entry(block_pos) {
+            match self.map_block_states.entry(block_pos) {
                 Entry::Occupied(mut occupied_entry) => match occupied_entry.get_mut() {
                     MapBlockState {
                         sent_to_client: false,
@@ -258,6 +739,9 @@ impl ViewTracker {
                             "player '{player_key}' confirmed reception of map block {block_pos}"
                         );
                         state.cached_by_client = true;
+                        if state.in_flight_since.take().is_some() {
+                            self.in_flight -= 1;
+                        }
                     }
                 },
                 Entry::Vacant(_vacant_entry) => {
@@ -269,14 +753,15 @@ impl ViewTracker {
         }
     }
 
-    fn handle_deleted_map_blocks(
-        player_key: &SharedStr,
-        map_block_states: &mut HashMap<MapBlockPos, MapBlockState>,
-        mut blocks: Vec<I16Vec3>,
-        block_interest_sender: &UnboundedSender<ToRouterMessage>,
-    ) -> Result<(), anyhow::Error> {
+    /// Forgets about map blocks the client dropped from its cache and returns the interests to be
+    /// revoked.
+    ///
+    /// Blocks which are still in view will be requested again with the next view update.
+    fn deleted_map_blocks(&mut self, mut blocks: Vec<I16Vec3>) -> Vec<BlockInterest> {
+        let player_key = &self.player_key;
+        let mut interests = Vec::with_capacity(blocks.len());
         for block_pos in blocks.drain(..).filter_map(MapBlockPos::new) {
-            match map_block_states.entry(block_pos) {
+            match self.map_block_states.entry(block_pos) {
                 // remove state for this block
                 Entry::Occupied(occupied_entry) => match occupied_entry.remove() {
                     MapBlockState {
@@ -289,11 +774,15 @@ impl ViewTracker {
                     }
                     MapBlockState {
                         cached_by_client: false,
+                        in_flight_since,
                         ..
                     } => {
                         warn!(
                             "player '{player_key}' reported dropping of map block {block_pos}, which was never confirmed to be received"
                         );
+                        if in_flight_since.is_some() {
+                            self.in_flight -= 1;
+                        }
                     }
                     _ => trace!("player '{player_key}' removed map block {block_pos}"),
                 },
@@ -303,72 +792,305 @@ impl ViewTracker {
                     );
                 }
             }
+            self.ready.remove(&block_pos);
 
             // report, that we're no longer interested in updates for this map block
-            block_interest_sender.send(ToRouterMessage::BlockInterest(
-                BlockInterest::unsubscribe(player_key.clone(), block_pos),
-            ))?;
+            interests.push(BlockInterest::unsubscribe(player_key.clone(), block_pos));
         }
-        Ok(())
+
+        // make sure that the next view update brings back the blocks which are still in view
+        self.view = None;
+        interests
     }
 }
 
-pub(crate) enum PlayerViewEvent {
-    /// The player has changed its position or viewing direction
-    PlayerPos {
-        position: Vec3,
-        // TODO(kawogi) add more information as needed
-    },
-    /// The player confirmed to have received some blocks
-    #[expect(
-        dead_code,
-        reason = "// TODO(kawogi) this implementation is likely still incomplete"
-    )]
-    GotMapBlocks(GotBlocksSpec),
-    /// The player reports to have removed some map blocks from its cache
-    #[expect(
-        dead_code,
-        reason = "// TODO(kawogi) this implementation is likely still incomplete"
-    )]
-    DroppedBlocks(DeletedblocksSpec),
+/// Computes how important a map block is to a player.
+///
+/// Closer blocks are more important. Blocks outside the field of view count as being twice as
+/// far away unless they're very close. Blocks farther away than `max_distance` (in nodes) aren't
+/// of any interest.
+fn block_priority(view: &PlayerView, block_pos: MapBlockPos, max_distance: f32) -> Priority {
+    let center =
+        MapNodePos::from(block_pos).0.as_vec3() + Vec3::splat(f32::from(MapBlockPos::SIZE) / 2.0);
+    let offset = center - view.position;
+    let distance = offset.length();
+    if distance > max_distance {
+        return Priority::NONE;
+    }
+    // widen the field of view by the size of a block to include partially visible blocks
+    let visible = distance <= NEAR_DISTANCE
+        || view.direction().angle_between(offset)
+            <= view.fov / 2.0 + (BLOCK_RADIUS / distance).atan();
+    let weighted_distance = if visible { distance } else { distance * 2.0 };
+    Priority::from(1.0 - weighted_distance / (max_distance * 2.0))
 }
 
-pub struct BlockInterest {
-    pub(crate) player_key: SharedStr,
-    /// position of the block
-    pub(crate) pos: MapBlockPos,
-    /// a value of _how much_ the player wants to see this block
-    pub(crate) priority: Priority,
-}
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
 
-impl BlockInterest {
-    fn subscribe(player_key: SharedStr, pos: MapBlockPos, priority: Priority) -> Self {
-        Self {
-            player_key,
-            pos,
-            priority,
-        }
+    use std::time::Instant;
+
+    use flexstr::SharedStr;
+    use glam::{I16Vec3, Vec3};
+    use luanti_core::{ContentId, MapBlockPos, MapNode, MapNodePos};
+    use tokio::sync::mpsc;
+
+    use super::{
+        InterestSnapshots, PlayerView, ViewConfig, ViewState, ViewTracker, block_priority,
+    };
+    use crate::world::generation::mapgen::MapgenBlock;
+    use crate::world::map_block_router::ToRouterMessage;
+    use crate::world::priority::Priority;
+    use crate::world::{WorldBlock, WorldUpdate};
+
+    fn pos(x: i16, y: i16, z: i16) -> MapBlockPos {
+        MapBlockPos::new(I16Vec3::new(x, y, z)).unwrap()
     }
 
-    fn unsubscribe(player_key: SharedStr, pos: MapBlockPos) -> Self {
-        Self {
-            player_key,
-            pos,
-            priority: Priority::NONE,
+    fn block(pos: MapBlockPos) -> WorldBlock {
+        MapgenBlock::new(0, pos).into_world_block()
+    }
+
+    /// A player at the origin looking along the positive Z-axis
+    fn view(wanted_range: u8) -> PlayerView {
+        PlayerView {
+            position: Vec3::ZERO,
+            pitch: 0.0,
+            yaw: 0.0,
+            fov: 1.2,
+            wanted_range,
         }
     }
-}
 
-#[derive(Clone, Copy, Default)]
-struct MapBlockState {
-    /// How important it is that the player sees this map block
-    #[expect(
-        dead_code,
-        reason = "// TODO(kawogi) this implementation is likely still incomplete"
-    )]
-    priority: Priority,
-    /// whether this block has been sent to the client
-    sent_to_client: bool,
-    /// whether the client confirmed to have a copy of this map block
-    cached_by_client: bool,
+    #[test]
+    fn blocks_in_view_are_preferred() {
+        let view = view(8);
+        let ahead = block_priority(&view, pos(0, 0, 4), 200.0);
+        let behind = block_priority(&view, pos(0, 0, -5), 200.0);
+        let far_ahead = block_priority(&view, pos(0, 0, 6), 200.0);
+        assert!(ahead > far_ahead);
+        assert!(far_ahead > behind);
+        assert!(block_priority(&view, pos(0, 0, 20), 200.0).is_none());
+    }
+
+    #[test]
+    fn wanted_range_is_honored() {
+        let mut state = ViewState::new(SharedStr::from_borrowed("player"), ViewConfig::default());
+        let interests = state.update_view(view(2));
+        assert!(!interests.is_empty());
+        assert!(
+            interests
+                .iter()
+                .all(|interest| interest.pos.vec().abs().max_element() <= 2)
+        );
+
+        // moving within the same block doesn't change anything
+        let moved = PlayerView {
+            position: Vec3::new(2.0, 0.0, 0.0),
+            ..view(2)
+        };
+        assert!(state.update_view(moved).is_empty());
+
+        // the range is limited by the configuration
+        let limited = state.update_view(view(100));
+        assert!(
+            limited
+                .iter()
+                .all(|interest| interest.pos.vec().abs().max_element() <= 10)
+        );
+        assert!(
+            limited
+                .iter()
+                .any(|interest| interest.pos.vec().abs().max_element() > 2)
+        );
+    }
+
+    #[test]
+    fn blocks_in_flight_are_limited() {
+        let config = ViewConfig {
+            max_blocks_in_flight: 2,
+            ..ViewConfig::default()
+        };
+        let mut state = ViewState::new(SharedStr::from_borrowed("player"), config);
+        state.update_view(view(2));
+
+        // behind the player
+        state.map_block_arrived(block(pos(1, 1, -2)));
+        state.map_block_arrived(block(pos(0, 0, 2)));
+        state.map_block_arrived(block(pos(0, 0, 1)));
+        // blocks nobody asked for are being ignored
+        state.map_block_arrived(block(pos(0, 0, 9)));
+        assert_eq!(state.pending_blocks(), 3);
+
+        let now = Instant::now();
+        let sent: Vec<_> = state
+            .next_map_blocks(now)
+            .iter()
+            .map(|block| block.pos)
+            .collect();
+        assert_eq!(sent, vec![pos(0, 0, 1), pos(0, 0, 2)]);
+        assert!(state.next_map_blocks(now).is_empty());
+
+        state.got_map_blocks(vec![I16Vec3::new(0, 0, 1)]);
+        let refilled: Vec<_> = state
+            .next_map_blocks(now)
+            .iter()
+            .map(|block| block.pos)
+            .collect();
+        assert_eq!(refilled, vec![pos(1, 1, -2)]);
+        assert_eq!(state.pending_blocks(), 2);
+
+        // blocks the client already has aren't sent again
+        state.map_block_arrived(block(pos(0, 0, 1)));
+        state.got_map_blocks(vec![I16Vec3::new(0, 0, 2), I16Vec3::new(1, 1, -2)]);
+        assert!(state.next_map_blocks(now).is_empty());
+    }
+
+    #[test]
+    fn changes_reach_blocks_known_to_the_client() {
+        let mut state = ViewState::new(SharedStr::from_borrowed("player"), ViewConfig::default());
+        state.update_view(view(2));
+        let stone = MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2: 0,
+        };
+        let node_changed = |z| WorldUpdate::NodeChanged {
+            pos: MapNodePos(I16Vec3::new(0, 0, z)),
+            node: stone,
+        };
+
+        // blocks waiting to be sent are updated in place
+        state.world_updated(WorldUpdate::NewMapBlock(block(pos(0, 0, 1))));
+        assert!(state.world_updated(node_changed(16)).is_none());
+        let sent = state.next_map_blocks(Instant::now());
+        assert_eq!(sent.first().unwrap().nodes.0.first(), Some(&stone));
+
+        // the client is told about changes of the blocks it has
+        let update = state.world_updated(WorldUpdate::Batch(vec![
+            node_changed(16),
+            node_changed(48),
+            WorldUpdate::BlockUnloaded(pos(0, 0, 1)),
+        ]));
+        assert!(matches!(
+            update,
+            Some(WorldUpdate::NodeChanged { pos, .. }) if pos.0.z == 16
+        ));
+        let entity_moved = |z| WorldUpdate::EntityMoved {
+            id: 1,
+            position: Vec3::new(0.0, 0.0, z),
+            velocity: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+        };
+        assert!(state.world_updated(entity_moved(20.0)).is_some());
+        assert!(state.world_updated(entity_moved(40.0)).is_none());
+    }
+
+    #[test]
+    fn blocks_with_many_changes_are_sent_again() {
+        let config = ViewConfig {
+            max_node_changes: Some(2),
+            ..ViewConfig::default()
+        };
+        let mut state = ViewState::new(SharedStr::from_borrowed("player"), config);
+        state.update_view(view(2));
+        let node_changed = |z| WorldUpdate::NodeChanged {
+            pos: MapNodePos(I16Vec3::new(0, 0, z)),
+            node: MapNode {
+                content_id: ContentId(10),
+                param1: 0,
+                param2: 0,
+            },
+        };
+        state.map_block_arrived(block(pos(0, 0, 1)));
+        assert_eq!(state.next_map_blocks(Instant::now()).len(), 1);
+        // returns the number of forwarded changes and the map blocks being sent again
+        let changed = |view_state: &mut ViewState, nodes: &[i16]| {
+            let forwarded = nodes
+                .iter()
+                .filter_map(|&z| view_state.world_updated(node_changed(z)))
+                .collect();
+            let (forwarded, interests) = view_state.resend_changed_blocks(forwarded);
+            let resent: Vec<_> = interests.iter().map(|interest| interest.pos).collect();
+            (forwarded.len(), resent)
+        };
+
+        // blocks in flight receive all changes
+        assert_eq!(changed(&mut state, &[16, 17, 18]), (3, vec![]));
+        state.got_map_blocks(vec![I16Vec3::new(0, 0, 1)]);
+        assert_eq!(changed(&mut state, &[16, 17]), (2, vec![]));
+        assert_eq!(changed(&mut state, &[16, 17, 18]), (0, vec![pos(0, 0, 1)]));
+
+        // the block is sent again once the router delivers it
+        assert_eq!(changed(&mut state, &[19]), (0, vec![]));
+        state.map_block_arrived(block(pos(0, 0, 1)));
+        assert_eq!(state.next_map_blocks(Instant::now()).len(), 1);
+    }
+
+    #[test]
+    fn interests_are_kept_until_players_join_again() {
+        let player = SharedStr::from_borrowed("player");
+        let snapshots = InterestSnapshots::default();
+        let (router_sender, mut router) = mpsc::unbounded_channel();
+        let (world_update_sender, mut world_updates) = mpsc::unbounded_channel();
+        let tracker = ViewTracker::new(
+            player.clone(),
+            ViewConfig::default(),
+            router_sender.clone(),
+            world_update_sender.clone(),
+            snapshots.clone(),
+        )
+        .unwrap();
+        let Some(ToRouterMessage::Register { .. }) = router.blocking_recv() else {
+            unreachable!("the player must be registered first");
+        };
+
+        tracker.leave().unwrap();
+        let Some(ToRouterMessage::SnapshotInterests { player_key, sender }) =
+            router.blocking_recv()
+        else {
+            unreachable!("the interests must be taken when leaving");
+        };
+        assert_eq!(player_key, player);
+        sender
+            .send([(pos(0, 0, 1), Priority::MAX)].into_iter().collect())
+            .unwrap();
+        assert!(matches!(
+            router.blocking_recv(),
+            Some(ToRouterMessage::Unregister(left)) if left == player
+        ));
+        drop(tracker);
+
+        let rejoined = ViewTracker::new(
+            player.clone(),
+            ViewConfig::default(),
+            router_sender,
+            world_update_sender,
+            snapshots.clone(),
+        )
+        .unwrap();
+        let Some(ToRouterMessage::Register {
+            sender: to_tracker, ..
+        }) = router.blocking_recv()
+        else {
+            unreachable!("the player must be registered first");
+        };
+        let Some(ToRouterMessage::RestoreInterests { interests, .. }) = router.blocking_recv()
+        else {
+            unreachable!("the interests must be restored when joining again");
+        };
+        assert_eq!(interests.positions().collect::<Vec<_>>(), [pos(0, 0, 1)]);
+        assert!(snapshots.take(&player).is_none());
+
+        // the block is sent without waiting for the first view of the player
+        to_tracker
+            .send(WorldUpdate::NewMapBlock(block(pos(0, 0, 1))))
+            .unwrap();
+        let Some(WorldUpdate::NewMapBlock(sent)) = world_updates.blocking_recv() else {
+            unreachable!("the restored block must be sent");
+        };
+        assert_eq!(sent.pos, pos(0, 0, 1));
+        drop(rejoined);
+    }
 }
@@ -0,0 +1,300 @@
+//! Contains the `VoxelBuffer`
+//!
+//! Editing a world node by node costs a hash lookup of the map block per access. Map generators
+//! and other tools touching many nodes at once rather load whole map blocks into a
+//! [`VoxelBuffer`], modify the nodes in place and write the changed map blocks back in one go,
+//! just like the `VoxelManip` of the Lua API.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use luanti_core::{ContentId, MapBlockPos, MapNode, MapNodePos, Region};
+use tokio::sync::mpsc;
+
+use super::{
+    WorldBlock, editor::empty_block, map_block_provider::ToProviderMessage, storage::WorldStorage,
+};
+
+/// Buffers must not contain more nodes than this (64 MiB worth of nodes)
+const MAX_NODES: u64 = 1 << 24;
+
+/// The nodes of a region of whole map blocks within a single array
+///
+/// Nodes of map blocks which don't exist are [`ContentId::IGNORE`]. Writing a buffer back only
+/// writes the map blocks whose nodes have been changed since they've been loaded or written
+/// the last time.
+pub struct VoxelBuffer {
+    /// the region covered by the buffer; always aligned to map blocks
+    region: Region,
+    /// all nodes of the region in the order of [`Region::iter`]
+    nodes: Vec<MapNode>,
+    /// the map blocks as they have been loaded or written the last time; `None` if they don't
+    /// exist
+    blocks: HashMap<MapBlockPos, Option<WorldBlock>>,
+}
+
+impl VoxelBuffer {
+    /// Loads all map blocks containing nodes of the given region from a storage.
+    ///
+    /// Modifications which are still held in the cache of a running
+    /// [`MapBlockProvider`](super::map_block_provider::MapBlockProvider) are not visible; send
+    /// [`ToProviderMessage::Flush`] first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region is too large or if a map block could not be loaded.
+    pub fn load(storage: &dyn WorldStorage, region: Region) -> Result<Self> {
+        Self::load_with(region, |pos| storage.load_block(pos))
+    }
+
+    /// Loads all map blocks containing nodes of the given region using a custom source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region is too large or if `load` failed.
+    pub fn load_with(
+        region: Region,
+        mut load: impl FnMut(MapBlockPos) -> Result<Option<WorldBlock>>,
+    ) -> Result<Self> {
+        let region = Region::new(
+            MapBlockPos::for_vec(region.min()).nodes().min(),
+            MapBlockPos::for_vec(region.max()).nodes().max(),
+        );
+        let volume = region.volume();
+        if volume > MAX_NODES {
+            bail!("the region {region:?} contains {volume} nodes which exceeds {MAX_NODES}");
+        }
+        let ignore = MapNode {
+            content_id: ContentId::IGNORE,
+            param1: 0,
+            param2: 0,
+        };
+        let mut buffer = Self {
+            region,
+            nodes: vec![ignore; usize::try_from(volume)?],
+            blocks: HashMap::new(),
+        };
+        for block_pos in MapBlockPos::blocks_in_region(region) {
+            let block = load(block_pos)?;
+            if let Some(block) = &block {
+                for (node, pos) in block.nodes.0.iter().zip(block_pos.nodes()) {
+                    if let Some(slot) = buffer.node_mut(MapNodePos(pos)) {
+                        *slot = *node;
+                    }
+                }
+            }
+            buffer.blocks.insert(block_pos, block);
+        }
+        Ok(buffer)
+    }
+
+    /// Returns the region covered by this buffer. It contains the region the buffer has been
+    /// loaded for, extended to whole map blocks.
+    #[must_use]
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Returns the node at the given position or `None` if it lies outside of the buffer.
+    #[must_use]
+    pub fn node(&self, pos: MapNodePos) -> Option<MapNode> {
+        self.nodes.get(self.region.index(pos.0)?).copied()
+    }
+
+    /// Returns the node at the given position for modification or `None` if it lies outside of
+    /// the buffer.
+    pub fn node_mut(&mut self, pos: MapNodePos) -> Option<&mut MapNode> {
+        self.nodes.get_mut(self.region.index(pos.0)?)
+    }
+
+    /// Replaces the node at the given position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the position lies outside of the buffer.
+    pub fn set_node(&mut self, pos: MapNodePos, node: MapNode) -> Result<()> {
+        let region = self.region;
+        let slot = self
+            .node_mut(pos)
+            .ok_or_else(|| anyhow!("node {:?} lies outside of {region:?}", pos.0))?;
+        *slot = node;
+        Ok(())
+    }
+
+    /// Grants access to all nodes at once. They are ordered like [`Region::iter`] of
+    /// [`Self::region`], so [`Region::index`] returns the index of a position.
+    #[must_use]
+    pub fn nodes(&self) -> &[MapNode] {
+        &self.nodes
+    }
+
+    /// Grants mutable access to all nodes at once; see [`Self::nodes`].
+    pub fn nodes_mut(&mut self) -> &mut [MapNode] {
+        &mut self.nodes
+    }
+
+    /// Writes all changed map blocks into a storage and returns their number.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a map block could not be stored.
+    pub fn write_to(&mut self, storage: &mut dyn WorldStorage) -> Result<usize> {
+        self.write_with(|block| storage.store_block(&block))
+    }
+
+    /// Hands all changed map blocks to a running
+    /// [`MapBlockProvider`](super::map_block_provider::MapBlockProvider) and returns their
+    /// number.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider has shut down.
+    pub fn send_to(
+        &mut self,
+        provider: &mpsc::UnboundedSender<ToProviderMessage>,
+    ) -> Result<usize> {
+        self.write_with(|block| {
+            if provider
+                .send(ToProviderMessage::BlockModified(Box::new(block)))
+                .is_err()
+            {
+                bail!("the map block provider isn't running anymore");
+            }
+            Ok(())
+        })
+    }
+
+    /// Passes all changed map blocks to `write` and returns their number.
+    ///
+    /// Changing a map block invalidates its lighting, so it will be recalculated by whoever loads
+    /// it next. The metadata of nodes which have been replaced by another kind of node is
+    /// removed. Map blocks which didn't exist before are filled up with air.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `write` failed. The map blocks which have been written before remain
+    /// written.
+    pub fn write_with(&mut self, mut write: impl FnMut(WorldBlock) -> Result<()>) -> Result<usize> {
+        let mut count = 0;
+        for block_pos in MapBlockPos::blocks_in_region(self.region) {
+            let Some(block) = self.changed_block(block_pos) else {
+                continue;
+            };
+            write(block.clone())?;
+            self.blocks.insert(block_pos, Some(block));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the map block at the given position if its nodes have been changed.
+    fn changed_block(&self, block_pos: MapBlockPos) -> Option<WorldBlock> {
+        let original = self.blocks.get(&block_pos).and_then(Option::as_ref);
+        let mut block = original.cloned().unwrap_or_else(|| empty_block(block_pos));
+        for (slot, pos) in block.nodes.0.iter_mut().zip(block_pos.nodes()) {
+            match self.node(MapNodePos(pos)) {
+                // keep the air of new blocks
+                Some(node) if original.is_none() && node.content_id.is_ignore() => {}
+                Some(node) => *slot = node,
+                None => {}
+            }
+        }
+
+        if let Some(original) = original {
+            if block.nodes == original.nodes {
+                return None;
+            }
+            block.metadata.retain(|(index, _)| {
+                block.nodes[*index].content_id == original.nodes[*index].content_id
+            });
+        } else if block.nodes == empty_block(block_pos).nodes {
+            return None;
+        }
+        block.lighting_complete = 0;
+        block.day_night_differs = block.nodes.0.iter().any(MapNode::day_night_differs);
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapBlockPos, MapNode, MapNodeIndex, MapNodePos, Region};
+    use luanti_protocol::types::{Inventory, NodeMetadata};
+    use tokio::sync::mpsc;
+
+    use super::VoxelBuffer;
+    use crate::world::{editor::empty_block, map_block_provider::ToProviderMessage};
+
+    #[test]
+    fn changed_blocks_are_written_back() {
+        let stone = MapNode {
+            content_id: ContentId(10),
+            param1: 0,
+            param2: 0,
+        };
+        let origin = MapBlockPos::ZERO;
+        let mut existing = empty_block(origin);
+        existing.lighting_complete = 0xFFFF;
+        existing.metadata.push((
+            MapNodeIndex::from(1_u16),
+            NodeMetadata {
+                stringvars: vec![],
+                inventory: Inventory { entries: vec![] },
+            },
+        ));
+
+        let region = Region::new(I16Vec3::new(-1, 0, 0), I16Vec3::new(1, 0, 0));
+        let mut buffer =
+            VoxelBuffer::load_with(region, |pos| Ok((pos == origin).then(|| existing.clone())))
+                .unwrap();
+        assert_eq!(
+            buffer.region(),
+            Region::new(I16Vec3::new(-16, 0, 0), I16Vec3::new(15, 15, 15))
+        );
+        let inside = MapNodePos(I16Vec3::new(1, 0, 0));
+        let outside = MapNodePos(I16Vec3::new(-1, 0, 0));
+        assert_eq!(buffer.node(inside).unwrap().content_id, ContentId::AIR);
+        assert!(buffer.node(outside).unwrap().content_id.is_ignore());
+        assert_eq!(buffer.node(MapNodePos(I16Vec3::new(16, 0, 0))), None);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        assert_eq!(buffer.send_to(&sender).unwrap(), 0);
+
+        buffer.set_node(inside, stone).unwrap();
+        buffer
+            .set_node(MapNodePos(I16Vec3::new(16, 0, 0)), stone)
+            .unwrap_err();
+        assert_eq!(buffer.send_to(&sender).unwrap(), 1);
+        let Ok(ToProviderMessage::BlockModified(block)) = receiver.try_recv() else {
+            unreachable!("the block must have been sent");
+        };
+        assert_eq!(block.pos, origin);
+        assert_eq!(block.lighting_complete, 0);
+        assert!(block.metadata.is_empty());
+        assert_eq!(block.nodes[MapNodeIndex::from(1_u16)], stone);
+        // nothing changed since the last write
+        assert_eq!(buffer.send_to(&sender).unwrap(), 0);
+
+        // missing blocks are created
+        buffer.set_node(outside, stone).unwrap();
+        let mut written = Vec::new();
+        assert_eq!(
+            buffer
+                .write_with(|new_block| {
+                    written.push(new_block);
+                    Ok(())
+                })
+                .unwrap(),
+            1
+        );
+        let created = written.first().unwrap();
+        assert_eq!(created.nodes[MapNodeIndex::from(15_u16)], stone);
+        assert_eq!(
+            created.nodes[MapNodeIndex::from(14_u16)].content_id,
+            ContentId::AIR
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! Reads and writes regions of a world in the `.we` schematic format of the `WorldEdit` mod
+//!
+//! Only version 4 and 5 of the format are supported. Both contain the output of `core.serialize`
+//! which is a Lua table listing every node that isn't air along with its position relative to
+//! the origin of the region, its parameters and its metadata. Version 5 prepends the header `5:`.
+
+mod lua;
+
+use std::{collections::BTreeSet, fmt::Display};
+
+use anyhow::{Context, Result, anyhow, bail};
+use flexstr::SharedStr;
+use glam::I16Vec3;
+use log::warn;
+use luanti_core::{ContentId, MapNode, MapNodePos, Region};
+use luanti_protocol::types::{
+    Inventory, InventoryEntry, InventoryList, ItemStack, ItemStackMetadata, ItemStackUpdate,
+    NodeMetadata, StringVar,
+};
+
+use super::editor::WorldEditor;
+use lua::{LuaTable, LuaValue};
+
+/// The version being written
+const VERSION: u32 = 5;
+
+/// A single node of a [`Schematic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchematicNode {
+    /// position relative to the origin of the schematic
+    pub offset: I16Vec3,
+    /// name of the node; names are stored instead of content ids so the schematic can be placed
+    /// into another world
+    pub name: SharedStr,
+    /// see [`MapNode::param1`]
+    pub param1: u8,
+    /// see [`MapNode::param2`]
+    pub param2: u8,
+    /// fields and inventory of the node, if there are any
+    pub metadata: Option<NodeMetadata>,
+}
+
+/// A region of a world which can be written to and read from `.we` files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schematic {
+    /// all nodes which aren't air
+    pub nodes: Vec<SchematicNode>,
+}
+
+impl Schematic {
+    /// Copies the cuboid between `min` and `max` (both inclusive) out of a world. Air and nodes
+    /// of missing map blocks are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a map block could not be loaded.
+    pub fn read_region(
+        editor: &mut WorldEditor<'_>,
+        min: MapNodePos,
+        max: MapNodePos,
+    ) -> Result<Self> {
+        let region = Region::new(min.0, max.0);
+        let mut nodes = Vec::new();
+        for pos in region.iter().map(MapNodePos) {
+            let Some(node) = editor.node(pos)? else {
+                continue;
+            };
+            if node.content_id == ContentId::AIR || node.content_id.is_ignore() {
+                continue;
+            }
+            let name = editor.content_ids().name(node.content_id);
+            let metadata = editor.metadata(pos)?.cloned();
+            nodes.push(SchematicNode {
+                offset: pos.0 - region.min(),
+                name,
+                param1: node.param1,
+                param2: node.param2,
+                metadata,
+            });
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Places all nodes relative to `origin` into a world. Existing nodes at positions without a
+    /// node in the schematic are kept.
+    ///
+    /// Node names without a content id get the next free one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a node lies outside of the world, if there are no free content ids left
+    /// or if a map block could not be loaded.
+    pub fn write_region(&self, editor: &mut WorldEditor<'_>, origin: MapNodePos) -> Result<()> {
+        for node in &self.nodes {
+            let content_id = editor.content_ids().id(node.name.as_bytes())?;
+            let pos = origin
+                .0
+                .checked_add(node.offset)
+                .map(MapNodePos)
+                .ok_or_else(|| anyhow!("node at {} lies outside of the world", node.offset))?;
+            editor.set_node(
+                pos,
+                MapNode {
+                    content_id,
+                    param1: node.param1,
+                    param2: node.param2,
+                },
+            )?;
+            editor.set_metadata(pos, node.metadata.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the names of all nodes being used.
+    #[must_use]
+    pub fn node_names(&self) -> BTreeSet<SharedStr> {
+        self.nodes.iter().map(|node| node.name.clone()).collect()
+    }
+
+    /// Parses the content of a `.we` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format version isn't supported or the content is malformed.
+    pub fn parse(text: &str) -> Result<Self> {
+        let data = match text.split_once(':') {
+            Some((version, data)) if version.bytes().all(|byte| byte.is_ascii_digit()) => {
+                let version: u32 = version.parse()?;
+                if version != 4 && version != VERSION {
+                    bail!("unsupported WorldEdit schematic version {version}");
+                }
+                data
+            }
+            // version 4 didn't have a header
+            _ => text,
+        };
+
+        let value = lua::parse_return(data)?;
+        let list = value
+            .as_table()
+            .ok_or_else(|| anyhow!("the schematic doesn't contain a list"))?;
+        let nodes = list
+            .array
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                parse_node(entry).with_context(|| format!("invalid node #{}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { nodes })
+    }
+}
+
+impl Display for Schematic {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = format!("{VERSION}:return {{");
+        for (index, node) in self.nodes.iter().enumerate() {
+            if index > 0 {
+                output.push_str(", ");
+            }
+            write_node(&mut output, node);
+        }
+        output.push('}');
+        formatter.write_str(&output)
+    }
+}
+
+fn parse_node(entry: &LuaValue) -> Result<SchematicNode> {
+    let table = entry
+        .as_table()
+        .ok_or_else(|| anyhow!("expected a table"))?;
+    let coordinate = |key: &str| -> Result<i16> {
+        let number = table
+            .get(key)
+            .and_then(LuaValue::as_number)
+            .ok_or_else(|| anyhow!("missing coordinate `{key}`"))?;
+        integer(number).with_context(|| format!("invalid coordinate `{key}`"))
+    };
+    let param = |key: &str| -> Result<u8> {
+        table
+            .get(key)
+            .and_then(LuaValue::as_number)
+            .map_or(Ok(0), |number| {
+                integer(number).with_context(|| format!("invalid `{key}`"))
+            })
+    };
+    let name = table
+        .get("name")
+        .and_then(LuaValue::as_bytes)
+        .ok_or_else(|| anyhow!("missing `name`"))?;
+    let name = String::from_utf8(name.to_vec()).context("the name isn't valid UTF-8")?;
+
+    Ok(SchematicNode {
+        offset: I16Vec3::new(coordinate("x")?, coordinate("y")?, coordinate("z")?),
+        name: SharedStr::from(name),
+        param1: param("param1")?,
+        param2: param("param2")?,
+        metadata: table
+            .get("meta")
+            .and_then(LuaValue::as_table)
+            .map(parse_metadata)
+            .transpose()?
+            .filter(|metadata| {
+                !metadata.stringvars.is_empty() || !metadata.inventory.entries.is_empty()
+            }),
+    })
+}
+
+/// Converts a Lua number into an integer type if it's a whole number within its range.
+fn integer<T: TryFrom<i64>>(number: f64) -> Result<T> {
+    if number.fract() != 0.0 || !(-9e15..=9e15).contains(&number) {
+        bail!("{number} isn't an integer");
+    }
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the number has been checked to be a whole number within range"
+    )]
+    let integer = number as i64;
+    T::try_from(integer)
+        .ok()
+        .with_context(|| format!("{integer} is out of range"))
+}
+
+fn parse_metadata(meta: &LuaTable) -> Result<NodeMetadata> {
+    let mut stringvars = Vec::new();
+    if let Some(fields) = meta.get("fields").and_then(LuaValue::as_table) {
+        for (name, value) in &fields.fields {
+            let value = match value {
+                LuaValue::String(bytes) => bytes.clone(),
+                // numbers are being stored as strings by the engine anyway
+                LuaValue::Number(number) => number.to_string().into_bytes(),
+                _ => bail!("unsupported value of field `{name}`"),
+            };
+            stringvars.push(StringVar {
+                name: name.clone(),
+                value,
+                is_private: false,
+            });
+        }
+    }
+
+    let mut entries = Vec::new();
+    if let Some(inventory) = meta.get("inventory").and_then(LuaValue::as_table) {
+        for (name, list) in &inventory.fields {
+            let list = list
+                .as_table()
+                .ok_or_else(|| anyhow!("inventory list `{name}` isn't a list"))?;
+            let items = list
+                .array
+                .iter()
+                .map(|item| {
+                    let item = item
+                        .as_bytes()
+                        .ok_or_else(|| anyhow!("inventory list `{name}` contains a non-string"))?;
+                    parse_item(&String::from_utf8_lossy(item))
+                })
+                .collect::<Result<_>>()?;
+            entries.push(InventoryEntry::Update(InventoryList {
+                name: name.clone(),
+                // the width isn't part of the schematic
+                width: 0,
+                items,
+            }));
+        }
+    }
+
+    Ok(NodeMetadata {
+        stringvars,
+        inventory: Inventory { entries },
+    })
+}
+
+/// Parses an item string like `default:dirt 99 0`.
+fn parse_item(item: &str) -> Result<ItemStackUpdate> {
+    let mut parts = item.split_whitespace();
+    let Some(name) = parts.next() else {
+        return Ok(ItemStackUpdate::Empty);
+    };
+    let count = parts.next().map_or(Ok(1), str::parse)?;
+    let wear = parts.next().map_or(Ok(0), str::parse)?;
+    if parts.next().is_some() {
+        warn!("dropping the metadata of `{name}`");
+    }
+    Ok(ItemStackUpdate::Item(ItemStack {
+        name: name.to_owned(),
+        count,
+        wear,
+        metadata: ItemStackMetadata {
+            string_vars: vec![],
+        },
+    }))
+}
+
+/// Formats an item the way `ItemStack:to_string()` does, omitting default values.
+fn item_string(item: &ItemStackUpdate) -> String {
+    let ItemStackUpdate::Item(stack) = item else {
+        return String::new();
+    };
+    if !stack.metadata.string_vars.is_empty() {
+        warn!("dropping the metadata of `{}`", stack.name);
+    }
+    match (stack.count, stack.wear) {
+        (1, 0) => stack.name.clone(),
+        (count, 0) => format!("{} {count}", stack.name),
+        (count, wear) => format!("{} {count} {wear}", stack.name),
+    }
+}
+
+fn write_node(output: &mut String, node: &SchematicNode) {
+    let SchematicNode {
+        offset,
+        name,
+        param1,
+        param2,
+        metadata,
+    } = node;
+    output.push('{');
+    for (key, value) in [("x", offset.x), ("y", offset.y), ("z", offset.z)] {
+        lua::write_key(output, key);
+        output.push_str(&value.to_string());
+        output.push_str(", ");
+    }
+    lua::write_key(output, "name");
+    lua::write_string(output, name);
+    for (key, value) in [("param1", *param1), ("param2", *param2)] {
+        if value != 0 {
+            output.push_str(", ");
+            lua::write_key(output, key);
+            output.push_str(&value.to_string());
+        }
+    }
+    if let Some(metadata) = metadata {
+        output.push_str(", ");
+        lua::write_key(output, "meta");
+        write_metadata(output, metadata);
+    }
+    output.push('}');
+}
+
+fn write_metadata(output: &mut String, metadata: &NodeMetadata) {
+    output.push('{');
+    lua::write_key(output, "fields");
+    output.push('{');
+    for (index, var) in metadata.stringvars.iter().enumerate() {
+        if index > 0 {
+            output.push_str(", ");
+        }
+        lua::write_key(output, &var.name);
+        lua::write_string(output, &String::from_utf8_lossy(&var.value));
+    }
+    output.push_str("}, ");
+    lua::write_key(output, "inventory");
+    output.push('{');
+    let lists = metadata
+        .inventory
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            InventoryEntry::Update(list) => Some(list),
+            InventoryEntry::KeepList(_) => None,
+        });
+    for (index, list) in lists.enumerate() {
+        if index > 0 {
+            output.push_str(", ");
+        }
+        lua::write_key(output, &list.name);
+        output.push('{');
+        for (item_index, item) in list.items.iter().enumerate() {
+            if item_index > 0 {
+                output.push_str(", ");
+            }
+            lua::write_string(output, &item_string(item));
+        }
+        output.push('}');
+    }
+    output.push_str("}}");
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::sync::Arc;
+
+    use flexstr::SharedStr;
+    use glam::I16Vec3;
+    use luanti_core::{ContentId, MapNodePos};
+    use luanti_protocol::types::{
+        Inventory, InventoryEntry, InventoryList, ItemStack, ItemStackMetadata, ItemStackUpdate,
+        NodeMetadata, StringVar,
+    };
+
+    use super::{Schematic, SchematicNode};
+    use crate::world::{
+        content_id_map::ContentIdMap, content_id_mapper::ContentIdMapper, editor::WorldEditor,
+        storage::dummy::DummyStorage,
+    };
+
+    fn chest() -> SchematicNode {
+        SchematicNode {
+            offset: I16Vec3::new(1, 0, 2),
+            name: SharedStr::from_borrowed("default:chest"),
+            param1: 0,
+            param2: 3,
+            metadata: Some(NodeMetadata {
+                stringvars: vec![StringVar {
+                    name: "infotext".to_owned(),
+                    value: b"Chest \"1\"".to_vec(),
+                    is_private: false,
+                }],
+                inventory: Inventory {
+                    entries: vec![InventoryEntry::Update(InventoryList {
+                        name: "main".to_owned(),
+                        width: 0,
+                        items: vec![
+                            ItemStackUpdate::Item(ItemStack {
+                                name: "default:dirt".to_owned(),
+                                count: 99,
+                                wear: 0,
+                                metadata: ItemStackMetadata {
+                                    string_vars: vec![],
+                                },
+                            }),
+                            ItemStackUpdate::Empty,
+                        ],
+                    })],
+                },
+            }),
+        }
+    }
+
+    #[test]
+    fn schematics_round_trip() {
+        let schematic = Schematic {
+            nodes: vec![
+                SchematicNode {
+                    offset: I16Vec3::ZERO,
+                    name: SharedStr::from_borrowed("default:stone"),
+                    param1: 0,
+                    param2: 0,
+                    metadata: None,
+                },
+                chest(),
+            ],
+        };
+        let text = schematic.to_string();
+        assert!(text.starts_with("5:return {{x = 0, y = 0, z = 0, name = \"default:stone\"}"));
+        assert_eq!(Schematic::parse(&text).unwrap(), schematic);
+    }
+
+    #[test]
+    fn files_written_by_worldedit_can_be_read() {
+        let text = "5:return {{[\"y\"] = 0, [\"x\"] = 0, [\"name\"] = \"default:dirt\", \
+                    [\"z\"] = -1, [\"param1\"] = 15}, {[\"y\"] = 2, [\"x\"] = 1, \
+                    [\"name\"] = \"default:torch\", [\"z\"] = 0, [\"param2\"] = 1, \
+                    [\"meta\"] = {[\"fields\"] = {}, [\"inventory\"] = {}}}}";
+        let schematic = Schematic::parse(text).unwrap();
+        assert_eq!(schematic.nodes.len(), 2);
+        let dirt = schematic.nodes.first().unwrap();
+        let torch = schematic.nodes.last().unwrap();
+        assert_eq!(dirt.offset, I16Vec3::new(0, 0, -1));
+        assert_eq!(dirt.param1, 15);
+        assert_eq!(torch.name, "default:torch");
+        assert_eq!(torch.param2, 1);
+        assert_eq!(torch.metadata, None);
+
+        Schematic::parse("3:0 0 0 default:dirt 0 0").unwrap_err();
+    }
+
+    #[test]
+    fn regions_are_copied_between_worlds() {
+        let content_ids = Arc::new(ContentIdMapper::new(ContentIdMap::new()));
+        let chest_id = content_ids.id(b"default:chest").unwrap();
+        let mut storage = DummyStorage;
+        let mut editor = WorldEditor::new(&mut storage, Arc::clone(&content_ids));
+
+        let origin = MapNodePos(I16Vec3::new(30, -5, 12));
+        let schematic = Schematic {
+            nodes: vec![chest()],
+        };
+        schematic.write_region(&mut editor, origin).unwrap();
+        let chest_pos = MapNodePos(I16Vec3::new(31, -5, 14));
+        assert_eq!(
+            editor.node(chest_pos).unwrap().unwrap().content_id,
+            chest_id
+        );
+
+        let copy =
+            Schematic::read_region(&mut editor, MapNodePos(I16Vec3::new(33, -5, 15)), origin)
+                .unwrap();
+        assert_eq!(copy, schematic);
+        assert_eq!(
+            editor.node(origin).unwrap().map(|node| node.content_id),
+            Some(ContentId::AIR)
+        );
+
+        // names without an id get one
+        let unknown = Schematic {
+            nodes: vec![SchematicNode {
+                name: SharedStr::from_borrowed("default:mese"),
+                ..chest()
+            }],
+        };
+        unknown.write_region(&mut editor, origin).unwrap();
+        let mese_id = editor.node(chest_pos).unwrap().unwrap().content_id;
+        assert_ne!(mese_id, chest_id);
+        assert_eq!(content_ids.name(mese_id), "default:mese");
+    }
+}
@@ -0,0 +1,398 @@
+//! Just enough Lua to read and write the data produced by `core.serialize`
+
+use anyhow::{Result, anyhow, bail};
+
+/// Tables nested deeper than this are rejected instead of overflowing the stack; schematics
+/// nest only a few levels for the metadata and inventories of nodes.
+const MAX_DEPTH: usize = 64;
+
+/// A Lua value as it may appear in serialized data
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum LuaValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    /// Lua strings are byte strings without any encoding
+    String(Vec<u8>),
+    Table(LuaTable),
+}
+
+impl LuaValue {
+    pub(super) fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub(super) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::String(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub(super) fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            Self::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+/// A table constructor split into its positional and its named entries
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct LuaTable {
+    pub(super) array: Vec<LuaValue>,
+    pub(super) fields: Vec<(String, LuaValue)>,
+}
+
+impl LuaTable {
+    /// Returns the value of a named entry; the last one wins just like in Lua.
+    pub(super) fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.fields
+            .iter()
+            .rev()
+            .find_map(|(name, value)| (name == key).then_some(value))
+    }
+}
+
+/// Parses the output of `core.serialize` which is a single `return` statement.
+pub(super) fn parse_return(text: &str) -> Result<LuaValue> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        offset: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    if !parser.eat_keyword("return") {
+        bail!("expected `return` at the beginning of the data");
+    }
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.offset < parser.bytes.len() {
+        bail!("unexpected data at offset {}", parser.offset);
+    }
+    Ok(value)
+}
+
+struct Parser<'text> {
+    bytes: &'text [u8],
+    offset: usize,
+    /// the number of tables the parser is currently in
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.offset).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<()> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(byte) if byte == expected => Ok(()),
+            _ => bail!(
+                "expected `{}` at offset {}",
+                char::from(expected),
+                self.offset
+            ),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(byte) if byte.is_ascii_whitespace() => self.offset += 1,
+                Some(b'-') if self.bytes.get(self.offset + 1) == Some(&b'-') => {
+                    while self.next().is_some_and(|byte| byte != b'\n') {}
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Option<&str> {
+        let start = self.offset;
+        while self
+            .peek()
+            .is_some_and(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+        {
+            self.offset += 1;
+        }
+        let identifier = self.bytes.get(start..self.offset)?;
+        if identifier.first().is_none_or(u8::is_ascii_digit) {
+            self.offset = start;
+            return None;
+        }
+        std::str::from_utf8(identifier).ok()
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.offset;
+        if self.identifier() == Some(keyword) {
+            true
+        } else {
+            self.offset = start;
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<LuaValue> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                if self.depth == MAX_DEPTH {
+                    bail!(
+                        "tables are nested deeper than {MAX_DEPTH} levels at offset {}",
+                        self.offset
+                    );
+                }
+                self.depth += 1;
+                let table = self.table();
+                self.depth -= 1;
+                table.map(LuaValue::Table)
+            }
+            Some(quote @ (b'"' | b'\'')) => self.string(quote).map(LuaValue::String),
+            Some(b'-' | b'.' | b'0'..=b'9') => self.number().map(LuaValue::Number),
+            _ => match self.identifier() {
+                Some("nil") => Ok(LuaValue::Nil),
+                Some("true") => Ok(LuaValue::Boolean(true)),
+                Some("false") => Ok(LuaValue::Boolean(false)),
+                _ => bail!("unsupported value at offset {}", self.offset),
+            },
+        }
+    }
+
+    fn table(&mut self) -> Result<LuaTable> {
+        self.expect(b'{')?;
+        let mut table = LuaTable::default();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(b'}') {
+                self.offset += 1;
+                return Ok(table);
+            }
+
+            if self.peek() == Some(b'[') {
+                self.offset += 1;
+                let key = self.value()?;
+                self.expect(b']')?;
+                self.expect(b'=')?;
+                let value = self.value()?;
+                // `core.serialize` writes lists without keys, so only string keys are expected
+                let LuaValue::String(key) = key else {
+                    bail!("unsupported table key {key:?}");
+                };
+                table
+                    .fields
+                    .push((String::from_utf8_lossy(&key).into_owned(), value));
+            } else {
+                let start = self.offset;
+                let key = self.identifier().map(ToOwned::to_owned);
+                self.skip_whitespace();
+                match key {
+                    Some(key) if self.peek() == Some(b'=') => {
+                        self.offset += 1;
+                        let value = self.value()?;
+                        table.fields.push((key, value));
+                    }
+                    _ => {
+                        // not a named entry, so the identifier is a value like `true`
+                        self.offset = start;
+                        let value = self.value()?;
+                        table.array.push(value);
+                    }
+                }
+            }
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',' | b';') => {}
+                Some(b'}') => return Ok(table),
+                _ => bail!("expected `,` or `}}` at offset {}", self.offset),
+            }
+        }
+    }
+
+    fn string(&mut self, quote: u8) -> Result<Vec<u8>> {
+        self.offset += 1;
+        let mut result = Vec::new();
+        loop {
+            let byte = self.next().ok_or_else(|| anyhow!("unterminated string"))?;
+            if byte == quote {
+                return Ok(result);
+            }
+            if byte != b'\\' {
+                result.push(byte);
+                continue;
+            }
+            let escaped = self.next().ok_or_else(|| anyhow!("unterminated string"))?;
+            match escaped {
+                b'n' | b'\n' => result.push(b'\n'),
+                b'r' => result.push(b'\r'),
+                b't' => result.push(b'\t'),
+                b'a' => result.push(0x07),
+                b'b' => result.push(0x08),
+                b'f' => result.push(0x0c),
+                b'v' => result.push(0x0b),
+                b'x' => {
+                    let digits = self
+                        .bytes
+                        .get(self.offset..self.offset + 2)
+                        .and_then(|digits| std::str::from_utf8(digits).ok())
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or_else(|| anyhow!("invalid `\\x` escape at offset {}", self.offset))?;
+                    self.offset += 2;
+                    result.push(digits);
+                }
+                b'z' => {
+                    while self.peek().is_some_and(|next| next.is_ascii_whitespace()) {
+                        self.offset += 1;
+                    }
+                }
+                b'0'..=b'9' => {
+                    let mut code = u32::from(escaped - b'0');
+                    for _ in 0..2 {
+                        match self.peek() {
+                            Some(digit @ b'0'..=b'9') => {
+                                code = code * 10 + u32::from(digit - b'0');
+                                self.offset += 1;
+                            }
+                            _ => break,
+                        }
+                    }
+                    result.push(
+                        u8::try_from(code)
+                            .map_err(|error| anyhow!("invalid escape `\\{code}`: {error}"))?,
+                    );
+                }
+                other => result.push(other),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        let start = self.offset;
+        while self
+            .peek()
+            .is_some_and(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'+' | b'.'))
+        {
+            self.offset += 1;
+        }
+        let text = self
+            .bytes
+            .get(start..self.offset)
+            .and_then(|text| std::str::from_utf8(text).ok())
+            .unwrap_or_default();
+        text.parse()
+            .map_err(|error| anyhow!("invalid number `{text}` at offset {start}: {error}"))
+    }
+}
+
+/// Appends a string literal which reads back as the given string.
+pub(super) fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            // three digits keep following digits from being part of the escape sequence
+            control if control.is_ascii_control() => {
+                let code = u8::try_from(control).unwrap_or_default();
+                output.push('\\');
+                for digit in [code / 100, code / 10 % 10, code % 10] {
+                    output.push(char::from(b'0' + digit));
+                }
+            }
+            other => output.push(other),
+        }
+    }
+    output.push('"');
+}
+
+/// Returns `true` if the key can be written without brackets and quotes.
+fn is_identifier(key: &str) -> bool {
+    key.bytes()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == b'_')
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+        && !KEYWORDS.contains(&key)
+}
+
+/// Reserved words which cannot be used as a bare table key
+const KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Appends a table key followed by ` = `.
+pub(super) fn write_key(output: &mut String, key: &str) {
+    if is_identifier(key) {
+        output.push_str(key);
+    } else {
+        output.push('[');
+        write_string(output, key);
+        output.push(']');
+    }
+    output.push_str(" = ");
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use super::{LuaValue, parse_return, write_string};
+
+    #[test]
+    fn strings_round_trip() {
+        let original = "a \"quoted\"\\ line\nwith\tcontrol\u{1}1 and ünïcödé";
+        let mut literal = String::new();
+        write_string(&mut literal, original);
+        let parsed = parse_return(&format!("return {literal}")).unwrap();
+        assert_eq!(parsed, LuaValue::String(original.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn tables_in_all_notations() {
+        let parsed =
+            parse_return("return {[\"x\"] = -1, y = 2.5, 'three', true, nil; [\"and\"] = {}}")
+                .unwrap();
+        let table = parsed.as_table().unwrap();
+        assert_eq!(table.get("x").unwrap().as_number(), Some(-1.0));
+        assert_eq!(table.get("y").unwrap().as_number(), Some(2.5));
+        assert_eq!(
+            table.array,
+            [
+                LuaValue::String(b"three".to_vec()),
+                LuaValue::Boolean(true),
+                LuaValue::Nil
+            ]
+        );
+        assert!(table.get("and").unwrap().as_table().is_some());
+    }
+
+    #[test]
+    fn deeply_nested_tables_are_rejected() {
+        let nested = |depth| format!("return {}{}", "{".repeat(depth), "}".repeat(depth));
+        parse_return(&nested(64)).unwrap();
+        parse_return(&nested(65)).unwrap_err();
+        parse_return(&nested(100_000)).unwrap_err();
+    }
+
+    #[test]
+    fn trailing_garbage_is_rejected() {
+        parse_return("return {} {}").unwrap_err();
+        parse_return("{}").unwrap_err();
+    }
+}
@@ -0,0 +1,871 @@
+//!
+//! Offline decoding of captured traffic
+//!
+//! Reads a pcap or pcapng file (e.g. written by `tcpdump -w` or Wireshark) and
+//! prints the commands found in its UDP datagrams the same way the proxy does.
+//! Files which contain a single raw UDP payload are supported as well.
+//!
+//! The direction of every packet is derived from its sender's peer id, so the
+//! capture doesn't need to be filtered for the server's port in advance.
+//! Split packets are reassembled per UDP flow and channel. Retransmitted
+//! reliable packets are shown each time they appear in the capture.
+//!
+//! Commands are decoded with the protocol version and serialization format the
+//! server announced in its `Hello`, so captures of older clients can be read
+//! as well. Commands preceding the `Hello` are decoded with the latest version.
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use log::debug;
+use log::info;
+use log::trace;
+use log::warn;
+use luanti_protocol::commands::Command;
+use luanti_protocol::commands::server_to_client::ToClientCommand;
+use luanti_protocol::types::ProtocolContext;
+use luanti_protocol::wire::channel_id::ChannelId;
+use luanti_protocol::wire::deser::Deserialize;
+use luanti_protocol::wire::deser::Deserializer;
+use luanti_protocol::wire::packet::InnerBody;
+use luanti_protocol::wire::packet::OriginalBody;
+use luanti_protocol::wire::packet::PROTOCOL_ID;
+use luanti_protocol::wire::packet::Packet;
+use luanti_protocol::wire::packet::PacketBody;
+use luanti_protocol::wire::packet::ReliableBody;
+use luanti_protocol::wire::packet::SplitBody;
+use luanti_protocol::wire::peer_id::PeerId;
+use luanti_protocol::wire::sequence_number::WrappingSequenceNumber;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::output::CommandInfo;
+use crate::output::Output;
+use crate::output::OutputFormat;
+use crate::stats::Stats;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPTION_END: u16 = 0;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// A UDP datagram found in a capture
+#[derive(Debug)]
+struct Datagram {
+    /// time since the Unix epoch
+    timestamp: Duration,
+    source: SocketAddr,
+    destination: SocketAddr,
+    payload: Vec<u8>,
+}
+
+/// Reads a capture file and prints all commands it contains.
+pub(crate) fn decode_capture(path: &Path, output: Output, audit: bool) -> Result<()> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let datagrams = read_capture(&data)
+        .with_context(|| format!("failed to parse capture {}", path.display()))?;
+    info!(
+        "read {} UDP datagrams from {}",
+        datagrams.len(),
+        path.display()
+    );
+
+    let mut decoder = CaptureDecoder::new(output, audit);
+    for datagram in &datagrams {
+        decoder.decode(datagram);
+    }
+    if !decoder.splits.is_empty() {
+        warn!(
+            "{} split packets are incomplete at the end of the capture",
+            decoder.splits.len()
+        );
+    }
+    if let Some(stats) = &decoder.output.stats {
+        Stats::lock(stats).report();
+    }
+    Ok(())
+}
+
+/// Extracts all UDP datagrams from a capture.
+fn read_capture(data: &[u8]) -> Result<Vec<Datagram>> {
+    let Some(magic) = data.first_chunk::<4>() else {
+        bail!("file is too short");
+    };
+    if magic == &PROTOCOL_ID.to_be_bytes() {
+        // the file contains a single raw UDP payload
+        let unknown = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+        return Ok(vec![Datagram {
+            timestamp: Duration::ZERO,
+            source: unknown,
+            destination: unknown,
+            payload: data.to_vec(),
+        }]);
+    }
+    let number = u32::from_le_bytes(*magic);
+    let pcap_magics = [PCAP_MAGIC_MICROS, PCAP_MAGIC_NANOS];
+    if number == PCAPNG_SECTION_HEADER {
+        read_pcapng(data)
+    } else if pcap_magics.contains(&number) {
+        read_pcap(data, false)
+    } else if pcap_magics.contains(&number.swap_bytes()) {
+        read_pcap(data, true)
+    } else {
+        bail!("unsupported file format (magic number 0x{number:08x})");
+    }
+}
+
+/// Reads the legacy pcap format.
+fn read_pcap(data: &[u8], big_endian: bool) -> Result<Vec<Datagram>> {
+    let mut reader = Reader::new(data, big_endian);
+    let nanos = reader.u32()? == PCAP_MAGIC_NANOS;
+    // version, time zone, accuracy and snapshot length
+    reader.take(16)?;
+    let link_type = reader.u32()?;
+
+    let mut datagrams = Vec::new();
+    while !reader.is_empty() {
+        let seconds = u64::from(reader.u32()?);
+        let fraction = u64::from(reader.u32()?);
+        let captured_length = reader.u32()?;
+        // original length
+        reader.take(4)?;
+        let frame = reader.take(usize::try_from(captured_length)?)?;
+
+        let timestamp = Duration::from_secs(seconds)
+            + if nanos {
+                Duration::from_nanos(fraction)
+            } else {
+                Duration::from_micros(fraction)
+            };
+        if let Some(datagram) = parse_frame(link_type, timestamp, frame)? {
+            datagrams.push(datagram);
+        }
+    }
+    Ok(datagrams)
+}
+
+/// An interface of a pcapng section
+struct Interface {
+    link_type: u32,
+    /// resolution of the timestamps
+    units_per_second: u64,
+}
+
+/// Reads the pcapng format.
+fn read_pcapng(data: &[u8]) -> Result<Vec<Datagram>> {
+    let mut reader = Reader::new(data, false);
+    let mut interfaces = Vec::new();
+    let mut datagrams = Vec::new();
+    while !reader.is_empty() {
+        let block_type = reader.u32()?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            // every section defines its own byte order
+            let Some(byte_order) = reader.data.get(4..8) else {
+                bail!("truncated section header");
+            };
+            reader.big_endian = if byte_order == PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes() {
+                true
+            } else if byte_order == PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes() {
+                false
+            } else {
+                bail!("invalid byte order magic in section header");
+            };
+            interfaces.clear();
+        }
+        let block_length = usize::try_from(reader.u32()?)?;
+        let Some(body_length) = block_length.checked_sub(12) else {
+            bail!("invalid block length {block_length}");
+        };
+        let mut body = Reader::new(reader.take(body_length)?, reader.big_endian);
+        // trailing copy of the block length
+        reader.take(4)?;
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => interfaces.push(read_interface(&mut body)?),
+            PCAPNG_ENHANCED_PACKET => {
+                let interface_id = usize::try_from(body.u32()?)?;
+                let Some(interface) = interfaces.get(interface_id) else {
+                    bail!("packet refers to unknown interface {interface_id}");
+                };
+                let high = u64::from(body.u32()?);
+                let low = u64::from(body.u32()?);
+                let captured_length = body.u32()?;
+                // original length
+                body.take(4)?;
+                let frame = body.take(usize::try_from(captured_length)?)?;
+
+                let nanos = u128::from((high << 32) | low) * NANOS_PER_SECOND
+                    / u128::from(interface.units_per_second);
+                let timestamp = Duration::from_nanos(u64::try_from(nanos)?);
+                if let Some(datagram) = parse_frame(interface.link_type, timestamp, frame)? {
+                    datagrams.push(datagram);
+                }
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                // simple packets don't carry a timestamp and always refer to the first interface
+                let Some(interface) = interfaces.first() else {
+                    bail!("packet refers to unknown interface 0");
+                };
+                let original_length = usize::try_from(body.u32()?)?;
+                let frame = body.data.get(..original_length).unwrap_or(body.data);
+                if let Some(datagram) = parse_frame(interface.link_type, Duration::ZERO, frame)? {
+                    datagrams.push(datagram);
+                }
+            }
+            _ => trace!("skipping pcapng block of type {block_type}"),
+        }
+    }
+    Ok(datagrams)
+}
+
+/// Reads the body of an interface description block.
+fn read_interface(body: &mut Reader<'_>) -> Result<Interface> {
+    let link_type = u32::from(body.u16()?);
+    // reserved and snapshot length
+    body.take(6)?;
+
+    let mut units_per_second = 1_000_000;
+    while !body.is_empty() {
+        let code = body.u16()?;
+        let length = body.u16()?;
+        if code == PCAPNG_OPTION_END {
+            break;
+        }
+        let value = body.take(usize::from(length).next_multiple_of(4))?;
+        if code == PCAPNG_OPTION_TSRESOL {
+            let Some(&resolution) = value.first() else {
+                bail!("empty timestamp resolution option");
+            };
+            let exponent = u32::from(resolution & 0x7f);
+            let base: u64 = if resolution & 0x80 == 0 { 10 } else { 2 };
+            units_per_second = base
+                .checked_pow(exponent)
+                .with_context(|| format!("unsupported timestamp resolution {resolution}"))?;
+        }
+    }
+    Ok(Interface {
+        link_type,
+        units_per_second,
+    })
+}
+
+/// Extracts a UDP datagram from a link layer frame.
+///
+/// Returns `None` for frames which don't contain a complete UDP datagram.
+fn parse_frame(link_type: u32, timestamp: Duration, frame: &[u8]) -> Result<Option<Datagram>> {
+    let mut reader = Reader::new(frame, true);
+    let ether_type = match link_type {
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            // the address family is stored in the capturing host's byte order
+            reader.take(4)?;
+            None
+        }
+        LINKTYPE_ETHERNET => {
+            // destination and source MAC address
+            reader.take(12)?;
+            let mut ether_type = reader.u16()?;
+            while ether_type == ETHERTYPE_VLAN || ether_type == ETHERTYPE_QINQ {
+                reader.take(2)?;
+                ether_type = reader.u16()?;
+            }
+            Some(ether_type)
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => None,
+        LINKTYPE_LINUX_SLL => {
+            reader.take(14)?;
+            Some(reader.u16()?)
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            let protocol = reader.u16()?;
+            reader.take(18)?;
+            Some(protocol)
+        }
+        _ => bail!("unsupported link type {link_type}"),
+    };
+    if ether_type.is_some_and(|protocol| protocol != ETHERTYPE_IPV4 && protocol != ETHERTYPE_IPV6) {
+        return Ok(None);
+    }
+    parse_ip(timestamp, reader.data)
+}
+
+/// Extracts a UDP datagram from an IPv4 or IPv6 packet.
+fn parse_ip(timestamp: Duration, packet: &[u8]) -> Result<Option<Datagram>> {
+    let mut reader = Reader::new(packet, true);
+    let version_and_length = reader.u8()?;
+    let (source, destination, udp) = match version_and_length >> 4 {
+        4 => {
+            let header_length = usize::from(version_and_length & 0x0f) * 4;
+            // type of service
+            reader.take(1)?;
+            let total_length = usize::from(reader.u16()?);
+            // identification
+            reader.take(2)?;
+            if reader.u16()? & 0x3fff != 0 {
+                // Luanti packets are small enough to never be fragmented
+                debug!("skipping fragmented IPv4 packet");
+                return Ok(None);
+            }
+            // time to live
+            reader.take(1)?;
+            if reader.u8()? != IP_PROTOCOL_UDP {
+                return Ok(None);
+            }
+            // checksum
+            reader.take(2)?;
+            let source = Ipv4Addr::from(reader.array::<4>()?);
+            let destination = Ipv4Addr::from(reader.array::<4>()?);
+            let Some(udp) = packet.get(header_length..total_length) else {
+                bail!("truncated IPv4 packet");
+            };
+            (IpAddr::V4(source), IpAddr::V4(destination), udp)
+        }
+        6 => {
+            // traffic class and flow label
+            reader.take(3)?;
+            let payload_length = usize::from(reader.u16()?);
+            if reader.u8()? != IP_PROTOCOL_UDP {
+                // extension headers are not supported
+                return Ok(None);
+            }
+            // hop limit
+            reader.take(1)?;
+            let source = Ipv6Addr::from(reader.array::<16>()?);
+            let destination = Ipv6Addr::from(reader.array::<16>()?);
+            let udp = reader.take(payload_length)?;
+            (IpAddr::V6(source), IpAddr::V6(destination), udp)
+        }
+        version => {
+            debug!("skipping packet of IP version {version}");
+            return Ok(None);
+        }
+    };
+
+    let mut udp_reader = Reader::new(udp, true);
+    let source_port = udp_reader.u16()?;
+    let destination_port = udp_reader.u16()?;
+    let length = usize::from(udp_reader.u16()?);
+    let Some(payload) = udp.get(8..length) else {
+        bail!("truncated UDP datagram");
+    };
+    Ok(Some(Datagram {
+        timestamp,
+        source: SocketAddr::new(source, source_port),
+        destination: SocketAddr::new(destination, destination_port),
+        payload: payload.to_vec(),
+    }))
+}
+
+/// Identifies the chunks of a split packet
+type SplitKey = (SocketAddr, SocketAddr, ChannelId, WrappingSequenceNumber);
+
+/// The client's and the server's address of a connection
+type Connection = (SocketAddr, SocketAddr);
+
+/// The chunks of a split packet which have been received so far
+struct IncompleteSplit {
+    chunk_count: u16,
+    chunks: BTreeMap<u16, Vec<u8>>,
+}
+
+/// Decodes datagrams into commands and prints them.
+struct CaptureDecoder {
+    output: Output,
+    /// timestamp of the first datagram; all times are shown relative to this one
+    start: Option<Duration>,
+    splits: HashMap<SplitKey, IncompleteSplit>,
+    /// the protocol version and serialization format the server announced per connection
+    versions: HashMap<Connection, (u16, u8)>,
+    /// whether decoded commands are audited
+    audit: bool,
+}
+
+impl CaptureDecoder {
+    fn new(output: Output, audit: bool) -> Self {
+        Self {
+            output,
+            start: None,
+            splits: HashMap::new(),
+            versions: HashMap::new(),
+            audit,
+        }
+    }
+
+    fn decode(&mut self, datagram: &Datagram) {
+        if let Some(stats) = &self.output.stats {
+            Stats::lock(stats).advance(datagram.timestamp);
+        }
+        let start = *self.start.get_or_insert(datagram.timestamp);
+        let elapsed = datagram.timestamp.saturating_sub(start);
+        let context = format!(
+            "{:.6} {} -> {}",
+            elapsed.as_secs_f64(),
+            datagram.source,
+            datagram.destination
+        );
+        match self.decode_command(&context, datagram) {
+            Ok(Some((info, command))) => self.output.show(&info, &command),
+            Ok(None) => (),
+            Err(error) => warn!("[{context}] failed to decode packet: {error:?}"),
+        }
+    }
+
+    /// Returns the command contained in a datagram, if any.
+    fn decode_command(
+        &mut self,
+        context: &str,
+        datagram: &Datagram,
+    ) -> Result<Option<(CommandInfo, Command)>> {
+        let payload = datagram.payload.as_slice();
+        if !payload.starts_with(&PROTOCOL_ID.to_be_bytes()) {
+            trace!("[{context}] skipping non-Luanti datagram");
+            return Ok(None);
+        }
+
+        // commands can only be deserialized if their direction is known
+        let mut header = Deserializer::new(ProtocolContext::latest_for_receive(false), payload);
+        u32::deserialize(&mut header)?;
+        let sender = PeerId::deserialize(&mut header)?;
+        let connection = if sender.is_server() {
+            (datagram.destination, datagram.source)
+        } else {
+            (datagram.source, datagram.destination)
+        };
+        let recv_context = self.receive_context(&connection, sender.is_server());
+
+        let packet = Packet::deserialize(&mut Deserializer::new(recv_context, payload))?;
+        let channel = packet.channel;
+        let (inner, reliable_seqnum) = match packet.body {
+            PacketBody::Reliable(ReliableBody { seqnum, inner }) => (inner, Some(seqnum)),
+            PacketBody::Inner(inner) => (inner, None),
+        };
+        if let Some(stats) = &self.output.stats {
+            Stats::lock(stats).record_packet(
+                datagram.source,
+                datagram.destination,
+                channel,
+                reliable_seqnum,
+            );
+        }
+        let command = match inner {
+            InnerBody::Control(control) => {
+                if self.output.format == OutputFormat::Text && self.output.verbosity >= 3 {
+                    trace!("[{context}] {control:?}");
+                }
+                return Ok(None);
+            }
+            InnerBody::Original(OriginalBody { command }) => command,
+            InnerBody::Split(split) => {
+                let key = (datagram.source, datagram.destination, channel, split.seqnum);
+                let data = self.push_split(key, split)?;
+                if let Some(stats) = &self.output.stats {
+                    Stats::lock(stats).record_split_chunk(data.is_some());
+                }
+                let Some(data) = data else {
+                    return Ok(None);
+                };
+                Command::deserialize(&mut Deserializer::new(recv_context, &data))?
+            }
+        };
+        let Some(command) = command else {
+            return Ok(None);
+        };
+        if let Command::ToClient(ToClientCommand::Hello(spec)) = &command {
+            debug!(
+                "[{context}] protocol version {} / serialization version {}",
+                spec.protocol_version, spec.serialization_version
+            );
+            self.versions.insert(
+                connection,
+                (spec.protocol_version, spec.serialization_version),
+            );
+        }
+        let info = CommandInfo {
+            label: context.to_owned(),
+            timestamp: datagram.timestamp,
+            peer: connection.0.to_string(),
+            channel,
+            reliable: reliable_seqnum.is_some(),
+        };
+        Ok(Some((info, command)))
+    }
+
+    /// Returns the context for deserializing what the client or the server of a connection sent.
+    fn receive_context(&self, connection: &Connection, from_server: bool) -> ProtocolContext {
+        let mut context = ProtocolContext::latest_for_receive(from_server).with_audit(self.audit);
+        if let Some(&(protocol_version, ser_fmt)) = self.versions.get(connection) {
+            context.protocol_version = protocol_version;
+            context.ser_fmt = ser_fmt;
+        }
+        context
+    }
+
+    /// Stores a chunk of a split packet and returns the complete payload once all chunks have
+    /// been received.
+    fn push_split(&mut self, key: SplitKey, split: SplitBody) -> Result<Option<Vec<u8>>> {
+        let SplitBody {
+            seqnum: _,
+            chunk_count,
+            chunk_num,
+            chunk_data,
+        } = split;
+        let incomplete = self.splits.entry(key).or_insert_with(|| IncompleteSplit {
+            chunk_count,
+            chunks: BTreeMap::new(),
+        });
+        if chunk_count != incomplete.chunk_count || chunk_num >= chunk_count {
+            self.splits.remove(&key);
+            bail!("split packet corrupt: chunk {chunk_num} of {chunk_count}");
+        }
+        incomplete.chunks.insert(chunk_num, chunk_data);
+        if incomplete.chunks.len() < usize::from(chunk_count) {
+            return Ok(None);
+        }
+        Ok(self
+            .splits
+            .remove(&key)
+            .map(|complete| complete.chunks.into_values().flatten().collect()))
+    }
+}
+
+/// Reads the fields of binary headers
+struct Reader<'data> {
+    data: &'data [u8],
+    big_endian: bool,
+}
+
+impl<'data> Reader<'data> {
+    fn new(data: &'data [u8], big_endian: bool) -> Self {
+        Self { data, big_endian }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'data [u8]> {
+        let Some((head, tail)) = self.data.split_at_checked(length) else {
+            bail!(
+                "unexpected end of data (expected {length} bytes, got {})",
+                self.data.len()
+            );
+        };
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const LENGTH: usize>(&mut self) -> Result<[u8; LENGTH]> {
+        Ok(self.take(LENGTH)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let [byte] = self.array()?;
+        Ok(byte)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.array()?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.array()?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::net::SocketAddr;
+    use std::net::SocketAddrV4;
+    use std::time::Duration;
+
+    use glam::I16Vec3;
+    use luanti_core::ContentId;
+    use luanti_core::MapNode;
+    use luanti_protocol::commands::Command;
+    use luanti_protocol::commands::server_to_client::BlockdataSpec;
+    use luanti_protocol::commands::server_to_client::HelloSpec;
+    use luanti_protocol::commands::server_to_client::ToClientCommand;
+    use luanti_protocol::types::AuthMechsBitset;
+    use luanti_protocol::types::MapNodesBulk;
+    use luanti_protocol::types::NodeMetadataList;
+    use luanti_protocol::types::ProtocolContext;
+    use luanti_protocol::types::TransferrableMapBlock;
+    use luanti_protocol::wire::channel_id::ChannelId;
+    use luanti_protocol::wire::packet::InnerBody;
+    use luanti_protocol::wire::packet::OriginalBody;
+    use luanti_protocol::wire::packet::PROTOCOL_ID;
+    use luanti_protocol::wire::packet::PacketBody;
+    use luanti_protocol::wire::packet::SplitBody;
+    use luanti_protocol::wire::ser::Serialize;
+    use luanti_protocol::wire::ser::VecSerializer;
+
+    use super::CaptureDecoder;
+    use super::Datagram;
+    use super::read_capture;
+    use crate::output::Output;
+    use crate::output::OutputFormat;
+
+    const CLIENT: &str = "10.0.0.2:51000";
+    const SERVER: &str = "10.0.0.1:30000";
+    const SERVER_PEER_ID: u16 = 1;
+    const CLIENT_PEER_ID: u16 = 2;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    /// Builds the UDP payload of an unreliable packet containing the given command.
+    fn payload(sender: u16, command: &Command, context: ProtocolContext) -> Vec<u8> {
+        let body = InnerBody::Original(OriginalBody {
+            command: Some(command.clone()),
+        })
+        .into_unreliable();
+        let mut ser = VecSerializer::new(context, 64);
+        u32::serialize(&PROTOCOL_ID, &mut ser).unwrap();
+        u16::serialize(&sender, &mut ser).unwrap();
+        ChannelId::serialize(&ChannelId::Default, &mut ser).unwrap();
+        PacketBody::serialize(&body, &mut ser).unwrap();
+        ser.take()
+    }
+
+    fn from_server(payload: Vec<u8>) -> Datagram {
+        Datagram {
+            timestamp: Duration::ZERO,
+            source: addr(SERVER),
+            destination: addr(CLIENT),
+            payload,
+        }
+    }
+
+    /// Wraps a UDP payload into an IPv4 packet.
+    fn ipv4_packet(source: SocketAddrV4, destination: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+        let udp_length = u16::try_from(8 + payload.len()).unwrap();
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(20 + udp_length).to_be_bytes());
+        // identification, flags, time to live, protocol and checksum
+        packet.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0]);
+        packet.extend_from_slice(&source.ip().octets());
+        packet.extend_from_slice(&destination.ip().octets());
+        packet.extend_from_slice(&source.port().to_be_bytes());
+        packet.extend_from_slice(&destination.port().to_be_bytes());
+        packet.extend_from_slice(&udp_length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn hello(protocol_version: u16, serialization_version: u8) -> Command {
+        Command::ToClient(ToClientCommand::Hello(Box::new(HelloSpec {
+            serialization_version,
+            compression_mode: 0,
+            protocol_version,
+            auth_mechs: AuthMechsBitset::default(),
+            username_legacy: String::new(),
+        })))
+    }
+
+    fn blockdata() -> Command {
+        Command::ToClient(ToClientCommand::Blockdata(Box::new(BlockdataSpec {
+            pos: I16Vec3::new(1, -2, 3),
+            block: TransferrableMapBlock {
+                is_underground: true,
+                day_night_differs: false,
+                generated: true,
+                lighting_complete: Some(0xffff),
+                nodes: MapNodesBulk {
+                    nodes: [MapNode {
+                        content_id: ContentId::AIR,
+                        param1: 0,
+                        param2: 0,
+                    }; 4096],
+                },
+                node_metadata: NodeMetadataList {
+                    metadata: Vec::new(),
+                },
+            },
+            network_specific_version: 2,
+        })))
+    }
+
+    fn decoder() -> CaptureDecoder {
+        let output = Output {
+            format: OutputFormat::Text,
+            verbosity: 1,
+            filters: Vec::new(),
+            stats: None,
+        };
+        CaptureDecoder::new(output, false)
+    }
+
+    #[test]
+    fn datagrams_are_read_from_pcap_files() {
+        let payload = payload(
+            SERVER_PEER_ID,
+            &hello(47, 29),
+            ProtocolContext::latest_for_send(false),
+        );
+        let packet = ipv4_packet(SERVER.parse().unwrap(), CLIENT.parse().unwrap(), &payload);
+
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&0xa1b2_c3d4_u32.to_le_bytes());
+        // version 2.4, time zone, accuracy and snapshot length
+        capture.extend_from_slice(&[2, 0, 4, 0]);
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&0xffff_u32.to_le_bytes());
+        // raw IP packets
+        capture.extend_from_slice(&101_u32.to_le_bytes());
+        capture.extend_from_slice(&1_700_000_000_u32.to_le_bytes());
+        capture.extend_from_slice(&250_000_u32.to_le_bytes());
+        for _ in 0..2 {
+            capture.extend_from_slice(&u32::try_from(packet.len()).unwrap().to_le_bytes());
+        }
+        capture.extend_from_slice(&packet);
+
+        let [datagram] = <[Datagram; 1]>::try_from(read_capture(&capture).unwrap()).unwrap();
+        assert_eq!(
+            datagram.timestamp,
+            Duration::from_secs(1_700_000_000) + Duration::from_millis(250)
+        );
+        assert_eq!(datagram.source, addr(SERVER));
+        assert_eq!(datagram.destination, addr(CLIENT));
+        assert_eq!(datagram.payload, payload);
+    }
+
+    #[test]
+    fn datagrams_are_read_from_pcapng_files() {
+        let payload = payload(
+            SERVER_PEER_ID,
+            &hello(47, 29),
+            ProtocolContext::latest_for_send(false),
+        );
+        // Ethernet header with the MAC addresses left empty
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&0x0800_u16.to_be_bytes());
+        frame.extend_from_slice(&ipv4_packet(
+            SERVER.parse().unwrap(),
+            CLIENT.parse().unwrap(),
+            &payload,
+        ));
+
+        let mut capture = Vec::new();
+        let mut push_block = |block_type: u32, body: &[u8]| {
+            let length = u32::try_from(12 + body.len().next_multiple_of(4)).unwrap();
+            capture.extend_from_slice(&block_type.to_le_bytes());
+            capture.extend_from_slice(&length.to_le_bytes());
+            capture.extend_from_slice(body);
+            capture.resize(capture.len().next_multiple_of(4), 0);
+            capture.extend_from_slice(&length.to_le_bytes());
+        };
+        // byte order magic, version 1.0 and unspecified section length
+        let mut section = 0x1a2b_3c4d_u32.to_le_bytes().to_vec();
+        section.extend_from_slice(&[1, 0, 0, 0]);
+        section.extend_from_slice(&[0xff; 8]);
+        push_block(0x0a0d_0d0a, &section);
+        // Ethernet, reserved, snapshot length, millisecond timestamps and end of options
+        let mut interface = vec![1, 0, 0, 0];
+        interface.extend_from_slice(&0xffff_u32.to_le_bytes());
+        interface.extend_from_slice(&[9, 0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+        push_block(1, &interface);
+        let mut packet = 0_u32.to_le_bytes().to_vec();
+        packet.extend_from_slice(&0_u32.to_le_bytes());
+        packet.extend_from_slice(&1_500_u32.to_le_bytes());
+        for _ in 0..2 {
+            packet.extend_from_slice(&u32::try_from(frame.len()).unwrap().to_le_bytes());
+        }
+        packet.extend_from_slice(&frame);
+        push_block(6, &packet);
+
+        let [datagram] = <[Datagram; 1]>::try_from(read_capture(&capture).unwrap()).unwrap();
+        assert_eq!(datagram.timestamp, Duration::from_millis(1_500));
+        assert_eq!(datagram.source, addr(SERVER));
+        assert_eq!(datagram.payload, payload);
+    }
+
+    #[test]
+    fn raw_payloads_are_read_as_a_single_datagram() {
+        let payload = payload(
+            CLIENT_PEER_ID,
+            &hello(47, 29),
+            ProtocolContext::latest_for_send(true),
+        );
+        let [datagram] = <[Datagram; 1]>::try_from(read_capture(&payload).unwrap()).unwrap();
+        assert_eq!(datagram.payload, payload);
+    }
+
+    #[test]
+    fn split_packets_are_reassembled() {
+        let mut decoder = decoder();
+        let key = (addr(SERVER), addr(CLIENT), ChannelId::Default, 7.into());
+        let chunk = |chunk_num: u16, chunk_data: &[u8]| SplitBody {
+            seqnum: 7.into(),
+            chunk_count: 3,
+            chunk_num,
+            chunk_data: chunk_data.to_vec(),
+        };
+        assert_eq!(decoder.push_split(key, chunk(2, b"ghi")).unwrap(), None);
+        assert_eq!(decoder.push_split(key, chunk(0, b"abc")).unwrap(), None);
+        assert_eq!(
+            decoder.push_split(key, chunk(1, b"def")).unwrap(),
+            Some(b"abcdefghi".to_vec())
+        );
+        assert!(decoder.splits.is_empty());
+    }
+
+    #[test]
+    fn commands_are_decoded_with_the_announced_version() {
+        let mut context = ProtocolContext::latest_for_send(false);
+        context.protocol_version = 37;
+        context.ser_fmt = 28;
+        let blockdata = from_server(payload(SERVER_PEER_ID, &blockdata(), context));
+
+        // the latest serialization format compresses map blocks differently
+        decoder().decode_command("", &blockdata).unwrap_err();
+
+        let mut decoder = decoder();
+        let hello = from_server(payload(SERVER_PEER_ID, &hello(37, 28), context));
+        decoder.decode_command("", &hello).unwrap();
+        let (info, command) = decoder.decode_command("", &blockdata).unwrap().unwrap();
+        assert_eq!(info.peer, CLIENT);
+        assert_eq!(command, self::blockdata());
+    }
+}
@@ -0,0 +1,191 @@
+//!
+//! Filter expressions for selecting the commands to be shown
+//!
+//! An expression consists of comma separated conditions which all need to
+//! match, e.g. `command=Blockdata,direction=to_client`. Supported conditions:
+//! - `command=<name>`: the command's name (case insensitive)
+//! - `direction=<to_server|to_client>`
+//! - `peer=<address>`: the client's address
+//!
+//! Names and addresses may contain `*` as a wildcard and conditions can be
+//! negated using `!=` instead of `=`.
+use anyhow::Error;
+use anyhow::Result;
+use anyhow::bail;
+use luanti_protocol::CommandDirection;
+use luanti_protocol::CommandRef;
+use std::str::FromStr;
+
+/// A property of a command being compared by a [`Condition`]
+#[derive(Debug, Clone)]
+enum Subject {
+    Command(String),
+    Direction(CommandDirection),
+    Peer(String),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    subject: Subject,
+    negated: bool,
+}
+
+impl Condition {
+    fn matches<Cmd: CommandRef>(&self, peer: &str, command: &Cmd) -> bool {
+        let matches = match &self.subject {
+            Subject::Command(pattern) => {
+                wildcard_match(pattern, &command.command_name().to_lowercase())
+            }
+            Subject::Direction(direction) => command.direction() == *direction,
+            Subject::Peer(pattern) => wildcard_match(pattern, peer),
+        };
+        matches != self.negated
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(condition: &str) -> Result<Self> {
+        let (key, value, negated) = if let Some((key, value)) = condition.split_once("!=") {
+            (key, value, true)
+        } else if let Some((key, value)) = condition.split_once('=') {
+            (key, value, false)
+        } else {
+            bail!("invalid condition '{condition}'; expected '<key>=<value>' or '<key>!=<value>'");
+        };
+        let subject = match key.trim() {
+            "command" => Subject::Command(value.trim().to_lowercase()),
+            "direction" => Subject::Direction(match value.trim() {
+                "to_server" => CommandDirection::ToServer,
+                "to_client" => CommandDirection::ToClient,
+                other => bail!("invalid direction '{other}'; expected 'to_server' or 'to_client'"),
+            }),
+            "peer" => Subject::Peer(value.trim().to_owned()),
+            other => {
+                bail!("unknown filter key '{other}'; expected 'command', 'direction' or 'peer'")
+            }
+        };
+        Ok(Self { subject, negated })
+    }
+}
+
+/// A set of conditions which all need to match
+#[derive(Debug, Clone)]
+pub(crate) struct CommandFilter {
+    conditions: Vec<Condition>,
+}
+
+impl CommandFilter {
+    /// Returns `true` if the command sent from or to the given peer matches all conditions.
+    pub(crate) fn matches<Cmd: CommandRef>(&self, peer: &str, command: &Cmd) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(peer, command))
+    }
+}
+
+impl FromStr for CommandFilter {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let conditions = expression
+            .split(',')
+            .map(Condition::from_str)
+            .collect::<Result<_>>()?;
+        Ok(Self { conditions })
+    }
+}
+
+/// Matches a text against a pattern where `*` matches any sequence of characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return text.is_empty();
+    };
+    let Some(mut remaining) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut last = None;
+    for part in parts {
+        if let Some(previous) = last.replace(part) {
+            let Some(position) = remaining.find(previous) else {
+                return false;
+            };
+            remaining = remaining
+                .get(position + previous.len()..)
+                .unwrap_or_default();
+        }
+    }
+    match last {
+        // the pattern didn't contain any wildcard
+        None => remaining.is_empty(),
+        Some(suffix) => remaining.ends_with(suffix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use luanti_protocol::commands::Command;
+    use luanti_protocol::commands::client_to_server::Init2Spec;
+    use luanti_protocol::commands::client_to_server::ToServerCommand;
+
+    use super::CommandFilter;
+    use super::wildcard_match;
+
+    const PEER: &str = "127.0.0.1:51000";
+
+    fn init2() -> Command {
+        Command::ToServer(ToServerCommand::Init2(Box::new(Init2Spec { lang: None })))
+    }
+
+    fn matches(expression: &str) -> bool {
+        expression
+            .parse::<CommandFilter>()
+            .unwrap()
+            .matches(PEER, &init2())
+    }
+
+    #[test]
+    fn wildcards_match_any_sequence() {
+        assert!(wildcard_match("blockdata", "blockdata"));
+        assert!(!wildcard_match("block", "blockdata"));
+        assert!(wildcard_match("block*", "blockdata"));
+        assert!(wildcard_match("*data", "blockdata"));
+        assert!(wildcard_match("b*k*a", "blockdata"));
+        assert!(!wildcard_match("b*x*a", "blockdata"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "blockdata"));
+        // the parts must not overlap
+        assert!(!wildcard_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn all_conditions_need_to_match() {
+        assert!(matches("command=Init2"));
+        assert!(matches("command=init*"));
+        assert!(!matches("command=Init"));
+        assert!(matches("command!=Init"));
+        assert!(matches("direction=to_server"));
+        assert!(!matches("direction=to_client"));
+        assert!(matches("peer=127.0.0.1:*"));
+        assert!(matches(" command = init2 , peer != 10.* "));
+        assert!(!matches("command=init2,direction=to_client"));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "",
+            "command",
+            "name=Init2",
+            "direction=up",
+            "command=Init2,",
+        ] {
+            assert!(expression.parse::<CommandFilter>().is_err(), "{expression}");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//!
+//! Hooks for rewriting the proxied traffic
+//!
+//! Every command passing the proxy is handed to the registered hooks, which may
+//! forward, drop or replace it. This allows simulating misbehaving servers or
+//! clients, e.g. for testing how robust a client is against missing or
+//! malformed commands.
+//!
+//! To add a custom hook, implement [`CommandHook`] and register it in `main`.
+use log::debug;
+use luanti_protocol::CommandDirection;
+use luanti_protocol::commands::Command;
+use luanti_protocol::commands::CommandProperties;
+use luanti_protocol::commands::server_to_client::TimeOfDaySpec;
+use luanti_protocol::commands::server_to_client::ToClientCommand;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+
+use crate::filter::CommandFilter;
+
+/// What to do with a command
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Action {
+    /// Pass the command on unchanged
+    Forward,
+    /// Discard the command
+    Drop,
+    /// Pass on a different command instead; it needs to have the same direction
+    Replace(Command),
+}
+
+/// Inspects and rewrites the commands passing the proxy
+///
+/// Hooks are shared by all connections, so state needs to be kept using interior mutability.
+pub(crate) trait CommandHook: Debug + Send + Sync {
+    /// Decides what to do with a command sent by or to the client at `peer`.
+    fn on_command(
+        &self,
+        peer: SocketAddr,
+        direction: CommandDirection,
+        command: &Command,
+    ) -> Action;
+}
+
+/// Applies multiple hooks in the order of registration
+#[derive(Debug, Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<Box<dyn CommandHook>>,
+}
+
+impl Hooks {
+    pub(crate) fn register(&mut self, hook: impl CommandHook + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Runs the command through all hooks and returns the command to be forwarded, if any.
+    ///
+    /// Each hook sees the command as it has been left by the previous ones.
+    pub(crate) fn apply(&self, peer: SocketAddr, mut command: Command) -> Option<Command> {
+        let direction = command.direction();
+        for hook in &self.hooks {
+            match hook.on_command(peer, direction, &command) {
+                Action::Forward => (),
+                Action::Drop => {
+                    debug!("{hook:?} dropped {}", command.command_name());
+                    return None;
+                }
+                Action::Replace(replacement) => {
+                    if replacement.direction() != direction {
+                        debug!(
+                            "{hook:?} tried to replace {} by {} which has the wrong direction",
+                            command.command_name(),
+                            replacement.command_name()
+                        );
+                        return None;
+                    }
+                    debug!(
+                        "{hook:?} replaced {} by {}",
+                        command.command_name(),
+                        replacement.command_name()
+                    );
+                    command = replacement;
+                }
+            }
+        }
+        Some(command)
+    }
+}
+
+/// Drops all commands matching any of the filters
+#[derive(Debug)]
+pub(crate) struct DropHook {
+    filters: Vec<CommandFilter>,
+}
+
+impl DropHook {
+    pub(crate) fn new(filters: Vec<CommandFilter>) -> Self {
+        Self { filters }
+    }
+}
+
+impl CommandHook for DropHook {
+    fn on_command(
+        &self,
+        peer: SocketAddr,
+        _direction: CommandDirection,
+        command: &Command,
+    ) -> Action {
+        let peer = peer.to_string();
+        if self
+            .filters
+            .iter()
+            .any(|filter| filter.matches(&peer, command))
+        {
+            Action::Drop
+        } else {
+            Action::Forward
+        }
+    }
+}
+
+/// Freezes the client's time of day by replacing the time updates sent by the server
+#[derive(Debug)]
+pub(crate) struct FreezeTimeHook {
+    time_of_day: u16,
+}
+
+impl FreezeTimeHook {
+    pub(crate) fn new(time_of_day: u16) -> Self {
+        Self { time_of_day }
+    }
+}
+
+impl CommandHook for FreezeTimeHook {
+    fn on_command(
+        &self,
+        _peer: SocketAddr,
+        _direction: CommandDirection,
+        command: &Command,
+    ) -> Action {
+        let Command::ToClient(ToClientCommand::TimeOfDay(spec)) = command else {
+            return Action::Forward;
+        };
+        Action::Replace(Command::ToClient(ToClientCommand::from(TimeOfDaySpec {
+            time_of_day: self.time_of_day,
+            time_speed: Some(0.0),
+            extra: spec.extra.clone(),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use std::net::SocketAddr;
+
+    use luanti_protocol::CommandDirection;
+    use luanti_protocol::commands::Command;
+    use luanti_protocol::commands::client_to_server::Init2Spec;
+    use luanti_protocol::commands::client_to_server::ToServerCommand;
+    use luanti_protocol::commands::server_to_client::TimeOfDaySpec;
+    use luanti_protocol::commands::server_to_client::ToClientCommand;
+    use luanti_protocol::types::TrailingBytes;
+
+    use super::Action;
+    use super::CommandHook;
+    use super::DropHook;
+    use super::FreezeTimeHook;
+    use super::Hooks;
+
+    const PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 51000);
+
+    /// Always returns the same action
+    #[derive(Debug)]
+    struct FixedHook(Action);
+
+    impl CommandHook for FixedHook {
+        fn on_command(
+            &self,
+            _peer: SocketAddr,
+            _direction: CommandDirection,
+            _command: &Command,
+        ) -> Action {
+            self.0.clone()
+        }
+    }
+
+    fn time_of_day(time_of_day: u16) -> Command {
+        Command::ToClient(ToClientCommand::from(TimeOfDaySpec {
+            time_of_day,
+            time_speed: Some(72.0),
+            extra: TrailingBytes::default(),
+        }))
+    }
+
+    fn init2() -> Command {
+        Command::ToServer(ToServerCommand::Init2(Box::new(Init2Spec { lang: None })))
+    }
+
+    #[test]
+    fn commands_are_forwarded_without_hooks() {
+        assert_eq!(Hooks::default().apply(PEER, init2()), Some(init2()));
+    }
+
+    #[test]
+    fn time_updates_are_replaced() {
+        let mut hooks = Hooks::default();
+        hooks.register(FreezeTimeHook::new(6000));
+        hooks.register(DropHook::new(vec!["command=Init2".parse().unwrap()]));
+
+        assert_eq!(
+            hooks.apply(PEER, time_of_day(18_000)),
+            Some(Command::ToClient(ToClientCommand::from(TimeOfDaySpec {
+                time_of_day: 6000,
+                time_speed: Some(0.0),
+                extra: TrailingBytes::default(),
+            })))
+        );
+        assert_eq!(hooks.apply(PEER, init2()), None);
+    }
+
+    #[test]
+    fn later_hooks_see_the_replacement() {
+        let mut hooks = Hooks::default();
+        hooks.register(FixedHook(Action::Replace(time_of_day(1))));
+        hooks.register(DropHook::new(vec!["command=TimeOfDay".parse().unwrap()]));
+        assert_eq!(hooks.apply(PEER, time_of_day(2)), None);
+    }
+
+    #[test]
+    fn replacements_keep_the_direction() {
+        let mut hooks = Hooks::default();
+        hooks.register(FixedHook(Action::Replace(init2())));
+        assert_eq!(hooks.apply(PEER, time_of_day(0)), None);
+        assert_eq!(hooks.apply(PEER, init2()), Some(init2()));
+    }
+}
@@ -1,21 +1,36 @@
 //! Luanti protocol implemented in Rust
 #![expect(clippy::expect_used, reason = "//TODO improve error handling")]
 
+mod capture;
+mod filter;
+mod hooks;
+mod netsim;
+mod output;
 mod proxy;
+mod stats;
 
 use anyhow::bail;
 use clap::ArgGroup;
 use clap::Parser;
+use filter::CommandFilter;
+use hooks::DropHook;
+use hooks::FreezeTimeHook;
+use hooks::Hooks;
 use log::info;
-use luanti_protocol::audit_on;
+use netsim::ImpairmentOption;
+use netsim::NetworkConditions;
+use output::Output;
+use output::OutputFormat;
 use proxy::LuantiProxy;
+use stats::Stats;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 /// luanti-shark - Luanti proxy that gives detailed inspection of protocol
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-#[command(group(ArgGroup::new("source").required(true).args(["listen", "bind"])))]
+#[command(group(ArgGroup::new("source").required(true).args(["listen", "bind", "read"])))]
 struct Args {
     /// Listen on port
     #[arg(group = "source", short, long)]
@@ -25,14 +40,47 @@ struct Args {
     #[arg(group = "source", short, long)]
     bind: Option<SocketAddr>,
 
+    /// Decode a pcap/pcapng capture or a raw UDP payload instead of proxying
+    #[arg(group = "source", short, long)]
+    read: Option<PathBuf>,
+
     /// Target server (address:port)
-    #[arg(short, long, required = true)]
-    target: SocketAddr,
+    #[arg(short, long, required_unless_present = "read")]
+    target: Option<SocketAddr>,
 
     /// Verbosity level (up to -vvv)
     #[arg(short, long, default_value_t = 0, action = clap::ArgAction::Count)]
     verbose: u8,
 
+    /// Output format of the decoded commands
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Only show commands matching the expression, e.g. `command=Blockdata,direction=to_client`;
+    /// may be given multiple times
+    #[arg(long, value_name = "EXPRESSION")]
+    filter: Vec<CommandFilter>,
+
+    /// Drop proxied commands matching the expression (same syntax as `--filter`); may be given
+    /// multiple times
+    #[arg(long, value_name = "EXPRESSION")]
+    drop: Vec<CommandFilter>,
+
+    /// Freeze the client's time of day at TIME (0 to 23999, 6000 being noon) by rewriting the
+    /// time updates sent by the server
+    #[arg(long, value_name = "TIME", value_parser = clap::value_parser!(u16).range(0..24_000))]
+    freeze_time: Option<u16>,
+
+    /// Show aggregated statistics every SECONDS instead of individual commands
+    #[arg(long, value_name = "SECONDS")]
+    stats: Option<u64>,
+
+    /// Impair the proxied traffic, e.g. `both:latency=100,jitter=20` or
+    /// `to_client:loss=5,reorder=1,duplicate=1` (milliseconds and percent); may be given multiple
+    /// times
+    #[arg(long, value_name = "DIRECTION:SETTINGS")]
+    impair: Vec<ImpairmentOption>,
+
     /// Enable audit mode
     #[arg(short, long, default_value_t = false)]
     audit: bool,
@@ -54,14 +102,30 @@ async fn real_main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     if args.audit {
-        audit_on();
         info!("Auditing is ON.");
         info!("Proxy will terminate if an invalid packet is received,");
         info!("or if serialization/deserialization do not match exactly.");
     }
 
+    let stats = args
+        .stats
+        .map(|seconds| Stats::shared(Duration::from_secs(seconds.max(1))));
+    if let Some(path) = args.read {
+        let output = Output {
+            format: args.format,
+            // offline decoding is pointless without showing any commands
+            verbosity: args.verbose.max(1),
+            filters: args.filter,
+            stats,
+        };
+        return capture::decode_capture(&path, output, args.audit);
+    }
+
+    let Some(target) = args.target else {
+        bail!("--target must be specified");
+    };
     let bind_addr: SocketAddr = if let Some(listen_port) = args.listen {
-        if args.target.is_ipv4() {
+        if target.is_ipv4() {
             format!("0.0.0.0:{listen_port}").parse()?
         } else {
             format!("[::]:{listen_port}").parse()?
@@ -72,12 +136,40 @@ async fn real_main() -> anyhow::Result<()> {
         bail!("One of --listen or --bind must be specified");
     };
 
-    let _proxy = LuantiProxy::new(bind_addr, args.target, args.verbose);
+    let output = Output {
+        format: args.format,
+        verbosity: args.verbose,
+        filters: args.filter,
+        stats: stats.clone(),
+    };
+    let mut hooks = Hooks::default();
+    if !args.drop.is_empty() {
+        hooks.register(DropHook::new(args.drop));
+    }
+    if let Some(time_of_day) = args.freeze_time {
+        hooks.register(FreezeTimeHook::new(time_of_day));
+    }
+    let mut conditions = NetworkConditions::default();
+    for option in &args.impair {
+        conditions.apply(option)?;
+    }
+    let forwarding_addr = if conditions.is_perfect() {
+        target
+    } else {
+        netsim::spawn_relay(target, conditions).await?
+    };
+    let _proxy = LuantiProxy::new(bind_addr, forwarding_addr, output, hooks, args.audit);
     #[expect(
         clippy::infinite_loop,
         reason = "// TODO implement a cancellation mechanism"
     )]
     loop {
-        tokio::time::sleep(Duration::from_secs(3600)).await;
+        if let Some(stats) = &stats {
+            let interval = Stats::lock(stats).interval();
+            tokio::time::sleep(interval).await;
+            Stats::lock(stats).report();
+        } else {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        }
     }
 }
@@ -0,0 +1,477 @@
+//!
+//! Simulation of adverse network conditions
+//!
+//! When any impairment is configured, the proxy doesn't talk to the server
+//! directly but through a UDP relay, which delays, drops, reorders and
+//! duplicates the datagrams passing it. This exercises the reliable transport
+//! of both the proxy's client side and the server.
+//!
+//! Impairments are configured per direction, e.g.
+//! `both:latency=100,jitter=20` or `to_client:loss=5,duplicate=1`.
+//! Times are given in milliseconds and probabilities in percent.
+//!
+//! Every client gets its own socket towards the server. It is closed once the
+//! server hasn't sent anything for [`IDLE_TIMEOUT`].
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use anyhow::bail;
+use log::debug;
+use log::error;
+use log::trace;
+use log::warn;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::unbounded_channel;
+
+const MAX_DATAGRAM_SIZE: usize = 0x0001_0000;
+
+/// additional delay of reordered datagrams, so subsequent ones overtake them
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+/// time after which the socket of a client is closed if the server didn't send anything
+///
+/// Luanti pings every few seconds, so this only happens if the connection is gone.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How datagrams sent in one direction are being impaired
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Impairment {
+    /// constant delay of every datagram
+    latency: Duration,
+    /// maximum random deviation from the latency
+    jitter: Duration,
+    /// probability of a datagram getting lost
+    loss: f64,
+    /// probability of a datagram being delayed so later ones overtake it
+    reorder: f64,
+    /// probability of a datagram being delivered twice
+    duplicate: f64,
+}
+
+impl Impairment {
+    fn is_none(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the delays after which copies of a datagram are to be delivered; empty if the
+    /// datagram gets lost.
+    fn schedule(&self) -> Vec<Duration> {
+        if rand::random_bool(self.loss) {
+            return Vec::new();
+        }
+        let mut delays = vec![self.delay()];
+        if rand::random_bool(self.duplicate) {
+            delays.push(self.delay());
+        }
+        delays
+    }
+
+    fn delay(&self) -> Duration {
+        let deviation = self.jitter.as_secs_f64() * rand::random_range(-1.0..=1.0);
+        let mut delay = Duration::from_secs_f64((self.latency.as_secs_f64() + deviation).max(0.0));
+        if rand::random_bool(self.reorder) {
+            delay += REORDER_DELAY;
+        }
+        delay
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "latency" => self.latency = parse_millis(value)?,
+            "jitter" => self.jitter = parse_millis(value)?,
+            "loss" => self.loss = parse_percentage(value)?,
+            "reorder" => self.reorder = parse_percentage(value)?,
+            "duplicate" => self.duplicate = parse_percentage(value)?,
+            other => bail!(
+                "unknown impairment '{other}'; expected 'latency', 'jitter', 'loss', 'reorder' or 'duplicate'"
+            ),
+        }
+        Ok(())
+    }
+}
+
+fn parse_millis(value: &str) -> Result<Duration> {
+    let millis = value.strip_suffix("ms").unwrap_or(value);
+    Ok(Duration::from_millis(millis.parse().with_context(
+        || format!("invalid time '{value}'; expected milliseconds"),
+    )?))
+}
+
+fn parse_percentage(value: &str) -> Result<f64> {
+    let percent = value.strip_suffix('%').unwrap_or(value);
+    let percent: f64 = percent
+        .parse()
+        .with_context(|| format!("invalid percentage '{value}'"))?;
+    if !(0.0..=100.0).contains(&percent) {
+        bail!("percentage '{value}' is out of range");
+    }
+    Ok(percent / 100.0)
+}
+
+/// Impairments of both directions
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct NetworkConditions {
+    to_server: Impairment,
+    to_client: Impairment,
+}
+
+impl NetworkConditions {
+    /// Returns `true` if datagrams are passed on unchanged in both directions.
+    pub(crate) fn is_perfect(&self) -> bool {
+        self.to_server.is_none() && self.to_client.is_none()
+    }
+
+    pub(crate) fn apply(&mut self, option: &ImpairmentOption) -> Result<()> {
+        for (key, value) in &option.settings {
+            if option.to_server {
+                self.to_server.set(key, value)?;
+            }
+            if option.to_client {
+                self.to_client.set(key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A command line option like `to_client:loss=5,latency=100`
+#[derive(Debug, Clone)]
+pub(crate) struct ImpairmentOption {
+    to_server: bool,
+    to_client: bool,
+    settings: Vec<(String, String)>,
+}
+
+impl FromStr for ImpairmentOption {
+    type Err = Error;
+
+    fn from_str(option: &str) -> Result<Self> {
+        let Some((direction, settings)) = option.split_once(':') else {
+            bail!("invalid impairment '{option}'; expected '<direction>:<key>=<value>,...'");
+        };
+        let (to_server, to_client) = match direction.trim() {
+            "to_server" => (true, false),
+            "to_client" => (false, true),
+            "both" => (true, true),
+            other => {
+                bail!("invalid direction '{other}'; expected 'to_server', 'to_client' or 'both'")
+            }
+        };
+        let settings = settings
+            .split(',')
+            .map(|setting| {
+                let Some((key, value)) = setting.split_once('=') else {
+                    bail!("invalid impairment '{setting}'; expected '<key>=<value>'");
+                };
+                Ok((key.trim().to_owned(), value.trim().to_owned()))
+            })
+            .collect::<Result<_>>()?;
+        let impairment = Self {
+            to_server,
+            to_client,
+            settings,
+        };
+        // validate the settings right away
+        NetworkConditions::default().apply(&impairment)?;
+        Ok(impairment)
+    }
+}
+
+/// Starts a local relay forwarding datagrams to and from `target` under the given conditions.
+///
+/// Returns the address of the relay to connect to instead of `target`.
+pub(crate) async fn spawn_relay(
+    target: SocketAddr,
+    conditions: NetworkConditions,
+) -> Result<SocketAddr> {
+    spawn_relay_with_timeout(target, conditions, IDLE_TIMEOUT).await
+}
+
+async fn spawn_relay_with_timeout(
+    target: SocketAddr,
+    conditions: NetworkConditions,
+    idle_timeout: Duration,
+) -> Result<SocketAddr> {
+    let bind_addr = if target.is_ipv4() {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 0))
+    } else {
+        SocketAddr::from((Ipv6Addr::LOCALHOST, 0))
+    };
+    let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+    let relay_addr = socket.local_addr()?;
+    debug!("relaying {relay_addr} to {target} with {conditions:?}");
+    tokio::spawn(run_relay(socket, target, conditions, idle_timeout));
+    Ok(relay_addr)
+}
+
+async fn run_relay(
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    conditions: NetworkConditions,
+    idle_timeout: Duration,
+) {
+    // every client gets its own socket, so the server can tell them apart
+    let mut upstreams: HashMap<SocketAddr, Arc<UdpSocket>> = HashMap::new();
+    // clients whose socket has been idle for too long
+    let (closed_tx, mut closed_rx) = unbounded_channel();
+    let mut buf = vec![0_u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let received = tokio::select! {
+            received = socket.recv_from(&mut buf) => received,
+            Some(client) = closed_rx.recv() => {
+                debug!("relay closes the idle socket of {client}");
+                upstreams.remove(&client);
+                continue;
+            }
+        };
+        let (length, client) = match received {
+            Ok(received) => received,
+            Err(error) => {
+                error!("relay failed to receive: {error:?}");
+                return;
+            }
+        };
+        let upstream = if let Some(upstream) = upstreams.get(&client) {
+            Arc::clone(upstream)
+        } else {
+            let bind_addr = if target.is_ipv4() {
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+            } else {
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+            };
+            let upstream = match UdpSocket::bind(bind_addr).await {
+                Ok(upstream) => Arc::new(upstream),
+                Err(error) => {
+                    error!("relay failed to bind upstream socket: {error:?}");
+                    continue;
+                }
+            };
+            tokio::spawn(run_downstream(
+                Arc::clone(&upstream),
+                Arc::clone(&socket),
+                target,
+                client,
+                conditions.to_client,
+                idle_timeout,
+                closed_tx.clone(),
+            ));
+            upstreams.insert(client, Arc::clone(&upstream));
+            upstream
+        };
+        let data = buf.get(..length).unwrap_or_default().to_vec();
+        send_impaired(conditions.to_server, upstream, target, data).await;
+    }
+}
+
+/// Forwards the datagrams of the server back to the client.
+///
+/// Reports the client to `closed` once the server stopped sending.
+async fn run_downstream(
+    upstream: Arc<UdpSocket>,
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    client: SocketAddr,
+    impairment: Impairment,
+    idle_timeout: Duration,
+    closed: UnboundedSender<SocketAddr>,
+) {
+    let mut buf = vec![0_u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let received = tokio::time::timeout(idle_timeout, upstream.recv_from(&mut buf)).await;
+        let (length, source) = match received {
+            Ok(Ok(received)) => received,
+            Ok(Err(error)) => {
+                error!("relay failed to receive from {target}: {error:?}");
+                break;
+            }
+            Err(_) => break,
+        };
+        if source != target {
+            trace!("relay ignores datagram from {source}");
+            continue;
+        }
+        let data = buf.get(..length).unwrap_or_default().to_vec();
+        send_impaired(impairment, Arc::clone(&socket), client, data).await;
+    }
+    // the relay is gone if this fails
+    closed.send(client).ok();
+}
+
+/// Sends a datagram, possibly delayed, duplicated or not at all.
+async fn send_impaired(
+    impairment: Impairment,
+    socket: Arc<UdpSocket>,
+    destination: SocketAddr,
+    data: Vec<u8>,
+) {
+    let delays = impairment.schedule();
+    match delays.len() {
+        0 => trace!("dropping datagram to {destination}"),
+        1 => (),
+        _ => trace!("duplicating datagram to {destination}"),
+    }
+    for delay in delays {
+        if delay.is_zero() {
+            if let Err(error) = socket.send_to(&data, destination).await {
+                warn!("relay failed to send to {destination}: {error:?}");
+            }
+            continue;
+        }
+        let socket = Arc::clone(&socket);
+        let data = data.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            if let Err(error) = socket.send_to(&data, destination).await {
+                warn!("relay failed to send to {destination}: {error:?}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::net::Ipv4Addr;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use tokio::net::UdpSocket;
+
+    use super::ImpairmentOption;
+    use super::NetworkConditions;
+    use super::spawn_relay;
+    use super::spawn_relay_with_timeout;
+
+    /// how long to wait for datagrams which are expected to arrive
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn bind() -> UdpSocket {
+        UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .unwrap()
+    }
+
+    fn conditions(options: &[&str]) -> NetworkConditions {
+        let mut conditions = NetworkConditions::default();
+        for option in options {
+            conditions
+                .apply(&option.parse::<ImpairmentOption>().unwrap())
+                .unwrap();
+        }
+        conditions
+    }
+
+    /// Receives a datagram, failing after [`TIMEOUT`].
+    async fn recv(socket: &UdpSocket) -> (Vec<u8>, SocketAddr) {
+        let mut buf = [0; 16];
+        let (length, source) = tokio::time::timeout(TIMEOUT, socket.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        (buf.get(..length).unwrap().to_vec(), source)
+    }
+
+    #[test]
+    fn options_are_validated() {
+        assert!(conditions(&[]).is_perfect());
+        assert!(!conditions(&["to_client:latency=100ms,jitter=20"]).is_perfect());
+        for option in [
+            "loss=5",
+            "sideways:loss=5",
+            "both:loss",
+            "both:loss=101",
+            "both:latency=-1",
+            "both:bandwidth=5",
+        ] {
+            assert!(option.parse::<ImpairmentOption>().is_err(), "{option}");
+        }
+    }
+
+    #[test]
+    fn impairments_are_scheduled() {
+        let lossy = conditions(&["to_server:loss=100%"]);
+        assert!(lossy.to_server.schedule().is_empty());
+        assert_eq!(lossy.to_client.schedule(), [Duration::ZERO]);
+
+        let duplicating = conditions(&["both:duplicate=100,latency=30"]);
+        assert_eq!(
+            duplicating.to_client.schedule(),
+            [Duration::from_millis(30); 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn datagrams_are_relayed_with_latency() {
+        let server = bind().await;
+        let client = bind().await;
+        let relay = spawn_relay(
+            server.local_addr().unwrap(),
+            conditions(&["to_client:latency=100"]),
+        )
+        .await
+        .unwrap();
+
+        client.send_to(b"ping", relay).await.unwrap();
+        let (data, upstream) = recv(&server).await;
+        assert_eq!(data, b"ping");
+
+        let sent = Instant::now();
+        server.send_to(b"pong", upstream).await.unwrap();
+        assert_eq!(recv(&client).await, (b"pong".to_vec(), relay));
+        assert!(sent.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn lost_datagrams_are_not_relayed() {
+        let server = bind().await;
+        let client = bind().await;
+        let relay = spawn_relay(
+            server.local_addr().unwrap(),
+            conditions(&["to_server:loss=100"]),
+        )
+        .await
+        .unwrap();
+
+        client.send_to(b"ping", relay).await.unwrap();
+        let mut buf = [0; 16];
+        tokio::time::timeout(Duration::from_millis(200), server.recv_from(&mut buf))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn idle_sockets_are_closed() {
+        let server = bind().await;
+        let client = bind().await;
+        let relay = spawn_relay_with_timeout(
+            server.local_addr().unwrap(),
+            conditions(&["both:latency=1"]),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+
+        client.send_to(b"first", relay).await.unwrap();
+        let (_, first_upstream) = recv(&server).await;
+        client.send_to(b"second", relay).await.unwrap();
+        let (_, second_upstream) = recv(&server).await;
+        assert_eq!(first_upstream, second_upstream);
+
+        // the server doesn't answer, so the socket gets replaced by a new one
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        client.send_to(b"third", relay).await.unwrap();
+        let (data, third_upstream) = recv(&server).await;
+        assert_eq!(data, b"third");
+        assert_ne!(third_upstream, first_upstream);
+    }
+}
@@ -0,0 +1,248 @@
+//!
+//! Presentation of decoded commands
+//!
+//! Commands are either logged in a human-readable form or printed to stdout as
+//! NDJSON, i.e. one JSON object per line, for post-processing with tools like
+//! `jq`.
+//!
+//! The JSON representation of a command follows the protocol's schema (see
+//! [`ToJson`]):
+//! - structs with named fields become objects with the schema's field names
+//! - newtypes become their value, other tuple structs become arrays
+//! - unit variants become strings, variants with data become `{"Name": value}`
+//! - `None` becomes `null` and `Some` is omitted
+//! - lists and tuples become arrays
+//!
+//! Commands with an unknown id are emitted as their id and raw payload.
+use clap::ValueEnum;
+use log::trace;
+use luanti_protocol::CommandDirection;
+use luanti_protocol::CommandRef;
+use luanti_protocol::commands::serialize_commandref;
+use luanti_protocol::commands::server_to_client::ToClientCommand;
+use luanti_protocol::schema::ToJson;
+use luanti_protocol::schema::write_json_string;
+use luanti_protocol::types::ProtocolContext;
+use luanti_protocol::wire::channel_id::ChannelId;
+use luanti_protocol::wire::ser::MockSerializer;
+use std::time::Duration;
+
+use crate::filter::CommandFilter;
+use crate::stats::SharedStats;
+use crate::stats::Stats;
+
+/// How decoded commands are presented
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Human-readable log messages
+    #[default]
+    Text,
+    /// One JSON object per command on stdout
+    Json,
+}
+
+/// Where and how a command has been transferred
+#[derive(Debug)]
+pub(crate) struct CommandInfo {
+    /// shown in front of every command in text mode
+    pub(crate) label: String,
+    /// time since the Unix epoch
+    pub(crate) timestamp: Duration,
+    /// the client's side of the connection
+    pub(crate) peer: String,
+    pub(crate) channel: ChannelId,
+    pub(crate) reliable: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Output {
+    pub(crate) format: OutputFormat,
+    pub(crate) verbosity: u8,
+    /// only commands matching any of the filters are shown; all commands if empty
+    pub(crate) filters: Vec<CommandFilter>,
+    /// if present, commands are only counted instead of being shown
+    pub(crate) stats: Option<SharedStats>,
+}
+
+impl Output {
+    pub(crate) fn show<Cmd: CommandRef>(&self, info: &CommandInfo, command: &Cmd) {
+        if !self.filters.is_empty()
+            && !self
+                .filters
+                .iter()
+                .any(|filter| filter.matches(&info.peer, command))
+        {
+            return;
+        }
+        if let Some(stats) = &self.stats {
+            Stats::lock(stats).record_command(command.command_name(), serialized_size(command));
+            return;
+        }
+        match self.format {
+            OutputFormat::Text => self.show_text(info, command),
+            OutputFormat::Json => show_json(info, command),
+        }
+    }
+
+    /// Logs a command with the level of detail requested by `verbosity`.
+    fn show_text<Cmd: CommandRef>(&self, info: &CommandInfo, command: &Cmd) {
+        let prefix = format!("[{}] {} ", info.label, direction_arrow(command.direction()));
+        let mut verbosity = self.verbosity;
+        if verbosity == 2 && is_bulk_command(command) {
+            // Show the contents of smaller commands, but skip the huge ones
+            verbosity = 1;
+        }
+        match verbosity {
+            0 => (),
+            1 => trace!("{} {}", prefix, command.command_name()),
+            2.. => trace!("{prefix} {command:#?}"),
+        }
+    }
+}
+
+pub(crate) fn is_bulk_command<Cmd: CommandRef>(command: &Cmd) -> bool {
+    matches!(
+        command.toclient_ref(),
+        Some(
+            ToClientCommand::Blockdata(_)
+                | ToClientCommand::FarBlocks(_)
+                | ToClientCommand::Media(_)
+        )
+    )
+}
+
+/// Returns the number of bytes the command occupies on the wire; `0` if it can't be serialized.
+fn serialized_size<Cmd: CommandRef>(command: &Cmd) -> usize {
+    let remote_is_server = command.direction() == CommandDirection::ToServer;
+    let mut serializer = MockSerializer::new(ProtocolContext::latest_for_send(remote_is_server));
+    match serialize_commandref(command, &mut serializer) {
+        Ok(()) => serializer.len(),
+        Err(_) => 0,
+    }
+}
+
+fn direction_arrow(direction: CommandDirection) -> &'static str {
+    match direction {
+        CommandDirection::ToClient => "S->C",
+        CommandDirection::ToServer => "C->S",
+    }
+}
+
+/// Prints a command as a single line of JSON to stdout.
+#[expect(
+    clippy::print_stdout,
+    reason = "the JSON output is meant to be piped into other tools"
+)]
+fn show_json<Cmd: CommandRef>(info: &CommandInfo, command: &Cmd) {
+    println!("{}", json_line(info, command));
+}
+
+/// Renders a command along with its transfer details as a JSON object.
+fn json_line<Cmd: CommandRef>(info: &CommandInfo, command: &Cmd) -> String {
+    let direction = match command.direction() {
+        CommandDirection::ToClient => "to_client",
+        CommandDirection::ToServer => "to_server",
+    };
+    let mut peer = String::new();
+    write_json_string(&mut peer, &info.peer);
+    // the name of the command is stored separately, so only its fields are rendered
+    let mut content = String::new();
+    if let Some(command) = command.toclient_ref() {
+        command.write_json(&mut content);
+    } else if let Some(command) = command.toserver_ref() {
+        command.write_json(&mut content);
+    } else if let Some(command) = command.raw_ref() {
+        command.write_json(&mut content);
+    } else {
+        content.push_str("null");
+    }
+
+    format!(
+        r#"{{"timestamp":{timestamp:.6},"direction":"{direction}","peer":{peer},"channel":{channel},"reliable":{reliable},"command":"{name}","content":{content}}}"#,
+        timestamp = info.timestamp.as_secs_f64(),
+        channel = usize::from(info.channel),
+        reliable = info.reliable,
+        name = command.command_name(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used, reason = "ok for tests")]
+
+    use std::time::Duration;
+
+    use glam::I16Vec3;
+    use luanti_protocol::CommandDirection;
+    use luanti_protocol::commands::Command;
+    use luanti_protocol::commands::RawCommand;
+    use luanti_protocol::commands::client_to_server::Init2Spec;
+    use luanti_protocol::commands::client_to_server::NodemetaFieldsSpec;
+    use luanti_protocol::commands::client_to_server::ToServerCommand;
+    use luanti_protocol::wire::channel_id::ChannelId;
+
+    use super::CommandInfo;
+    use super::json_line;
+
+    fn info() -> CommandInfo {
+        CommandInfo {
+            label: "1".to_owned(),
+            timestamp: Duration::from_millis(1_500),
+            peer: "127.0.0.1:51000".to_owned(),
+            channel: ChannelId::Init,
+            reliable: true,
+        }
+    }
+
+    /// Returns the JSON rendering of a command's content.
+    fn content(command: &Command) -> String {
+        let line = json_line(&info(), command);
+        let (_, content) = line.split_once(r#""content":"#).unwrap();
+        content.strip_suffix('}').unwrap().to_owned()
+    }
+
+    #[test]
+    fn lines_contain_the_transfer_details() {
+        let command = Command::ToServer(ToServerCommand::Init2(Box::new(Init2Spec { lang: None })));
+        assert_eq!(
+            json_line(&info(), &command),
+            r#"{"timestamp":1.500000,"direction":"to_server","peer":"127.0.0.1:51000","channel":1,"reliable":true,"command":"Init2","content":{"lang":null}}"#
+        );
+    }
+
+    #[test]
+    fn optional_values_are_kept() {
+        let command = Command::ToServer(ToServerCommand::Init2(Box::new(Init2Spec {
+            lang: Some("de \"DE\"".to_owned()),
+        })));
+        assert_eq!(content(&command), r#"{"lang":"de \"DE\""}"#);
+    }
+
+    #[test]
+    fn content_follows_the_schema() {
+        let command = Command::ToServer(ToServerCommand::NodemetaFields(Box::new(
+            NodemetaFieldsSpec {
+                p: I16Vec3::new(1, -2, 3),
+                form_name: "chest".to_owned(),
+                fields: vec![("quit".to_owned(), "true".to_owned())],
+            },
+        )));
+        assert_eq!(
+            content(&command),
+            r#"{"p":[1,-2,3],"form_name":"chest","fields":[["quit","true"]]}"#
+        );
+    }
+
+    #[test]
+    fn unknown_commands_show_their_payload() {
+        let command = Command::Unknown(RawCommand {
+            direction: CommandDirection::ToClient,
+            id: 0x99,
+            payload: vec![1, 2],
+        });
+        assert_eq!(
+            content(&command),
+            r#"{"direction":"ToClient","id":153,"payload":[1,2]}"#
+        );
+    }
+}
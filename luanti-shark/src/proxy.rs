@@ -23,23 +23,38 @@ use log::debug;
 use log::error;
 use log::info;
 use log::trace;
-use luanti_protocol::CommandDirection;
 use luanti_protocol::CommandRef;
 use luanti_protocol::LuantiClient;
 use luanti_protocol::LuantiConnection;
 use luanti_protocol::LuantiServer;
-use luanti_protocol::commands::server_to_client::ToClientCommand;
-use luanti_protocol::peer::PeerError;
+use luanti_protocol::commands::Command;
+use luanti_protocol::peer::Disconnect;
+use luanti_protocol::peer::Transfer;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::hooks::Hooks;
+use crate::output::CommandInfo;
+use crate::output::Output;
 
 pub(crate) struct LuantiProxy;
 
 impl LuantiProxy {
-    pub(crate) fn new(bind_addr: SocketAddr, forwarding_addr: SocketAddr, verbosity: u8) -> Self {
+    pub(crate) fn new(
+        bind_addr: SocketAddr,
+        forwarding_addr: SocketAddr,
+        output: Output,
+        hooks: Hooks,
+        audit: bool,
+    ) -> Self {
         let runner = LuantiProxyRunner {
             bind_addr,
             forwarding_addr,
-            verbosity,
+            output,
+            hooks: Arc::new(hooks),
+            audit,
         };
         tokio::spawn(runner.run());
         LuantiProxy {}
@@ -51,7 +66,10 @@ struct LuantiProxyRunner {
     bind_addr: SocketAddr,
     /// used to connect to the server
     forwarding_addr: SocketAddr,
-    verbosity: u8,
+    output: Output,
+    hooks: Arc<Hooks>,
+    /// whether commands received on both sides of each connection are audited
+    audit: bool,
 }
 
 impl LuantiProxyRunner {
@@ -59,10 +77,13 @@ impl LuantiProxyRunner {
         let Self {
             bind_addr,
             forwarding_addr,
-            verbosity,
+            output,
+            hooks,
+            audit,
         } = self;
 
         let mut server = LuantiServer::new(bind_addr);
+        server.set_audit(audit);
         let mut next_id: u64 = 1;
         loop {
             tokio::select! {
@@ -72,9 +93,15 @@ impl LuantiProxyRunner {
                     info!("[P{}] New client connected from {:?}", id, conn.remote_addr());
                     debug!("forwarding connection to {forwarding_addr}");
                     // TODO(kawogi) this outgoing connection attempt blocks accepting new incoming connections
-                    let client = LuantiClient::connect(forwarding_addr).await.expect("Connect failed");
+                    let client = LuantiClient::connect_with_audit(forwarding_addr, audit).await.expect("Connect failed");
                     debug!("successfully connected to {forwarding_addr}");
-                    ProxyAdapterRunner::spawn(id, conn, client, verbosity);
+                    ProxyAdapterRunner::spawn(
+                        id,
+                        conn,
+                        client,
+                        output.clone(),
+                        Arc::clone(&hooks),
+                    );
                 },
             }
         }
@@ -85,16 +112,24 @@ pub(crate) struct ProxyAdapterRunner {
     id: u64,
     conn: LuantiConnection,
     client: LuantiClient,
-    verbosity: u8,
+    output: Output,
+    hooks: Arc<Hooks>,
 }
 
 impl ProxyAdapterRunner {
-    pub(crate) fn spawn(id: u64, conn: LuantiConnection, client: LuantiClient, verbosity: u8) {
+    pub(crate) fn spawn(
+        id: u64,
+        conn: LuantiConnection,
+        client: LuantiClient,
+        output: Output,
+        hooks: Arc<Hooks>,
+    ) {
         let runner = ProxyAdapterRunner {
             id,
             conn,
             client,
-            verbosity,
+            output,
+            hooks,
         };
         tokio::spawn(runner.run());
     }
@@ -104,15 +139,10 @@ impl ProxyAdapterRunner {
         match self.run_inner().await {
             Ok(()) => (),
             Err(err) => {
-                let show_err = if let Some(err) = err.downcast_ref::<PeerError>() {
-                    !matches!(err, PeerError::PeerSentDisconnect)
+                if let Some(disconnect) = err.downcast_ref::<Disconnect>() {
+                    info!("[{}] {disconnect}", self.id);
                 } else {
-                    true
-                };
-                if show_err {
                     error!("[{}] Disconnected: {:?}", self.id, err);
-                } else {
-                    info!("[{}] Disconnected", self.id);
                 }
             }
         }
@@ -121,44 +151,51 @@ impl ProxyAdapterRunner {
     pub(crate) async fn run_inner(&mut self) -> Result<()> {
         loop {
             tokio::select! {
-                command = self.conn.recv() => {
+                command = self.conn.recv_command() => {
                     trace!("conn.recv: {command:?}");
                     let command = command?;
-                    self.maybe_show(&command);
-                    self.client.send(command)?;
+                    self.maybe_show(&command, self.conn.peer().last_transfer());
+                    let peer = self.conn.remote_addr();
+                    // hooks never change the direction of a command
+                    match self.hooks.apply(peer, command) {
+                        Some(Command::ToServer(command)) => self.client.send(command)?,
+                        Some(Command::Unknown(command)) => {
+                            self.client.send_raw_command(command.id, command.payload)?;
+                        }
+                        Some(Command::ToClient(_)) | None => (),
+                    }
                 },
-                command = self.client.recv() => {
+                command = self.client.recv_command() => {
                     trace!("client.recv: {command:?}");
                     let command = command?;
-                    self.maybe_show(&command);
-                    self.conn.send(command)?;
+                    self.maybe_show(&command, self.client.peer().last_transfer());
+                    let peer = self.conn.remote_addr();
+                    match self.hooks.apply(peer, command) {
+                        Some(Command::ToClient(command)) => self.conn.send(command)?,
+                        Some(Command::Unknown(command)) => {
+                            self.conn.send_raw_command(command.id, command.payload)?;
+                        }
+                        Some(Command::ToServer(_)) | None => (),
+                    }
                 }
             }
         }
     }
 
-    pub(crate) fn is_bulk_command<Cmd: CommandRef>(command: &Cmd) -> bool {
-        matches!(
-            command.toclient_ref(),
-            Some(ToClientCommand::Blockdata(_) | ToClientCommand::Media(_))
-        )
-    }
-
-    pub(crate) fn maybe_show<Cmd: CommandRef>(&self, command: &Cmd) {
-        let dir = match command.direction() {
-            CommandDirection::ToClient => "S->C",
-            CommandDirection::ToServer => "C->S",
+    pub(crate) fn maybe_show<Cmd: CommandRef>(&self, command: &Cmd, transfer: Option<Transfer>) {
+        let transfer = transfer.unwrap_or_else(|| Transfer {
+            channel: command.default_channel(),
+            reliable: command.default_reliability(),
+        });
+        let info = CommandInfo {
+            label: self.id.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            peer: self.conn.remote_addr().to_string(),
+            channel: transfer.channel,
+            reliable: transfer.reliable,
         };
-        let prefix = format!("[{}] {} ", self.id, dir);
-        let mut verbosity = self.verbosity;
-        if verbosity == 2 && Self::is_bulk_command(command) {
-            // Show the contents of smaller commands, but skip the huge ones
-            verbosity = 1;
-        }
-        match verbosity {
-            0 => (),
-            1 => trace!("{} {}", prefix, command.command_name()),
-            2.. => trace!("{prefix} {command:#?}"),
-        }
+        self.output.show(&info, command);
     }
 }
@@ -0,0 +1,266 @@
+//!
+//! Aggregated statistics of the observed traffic
+//!
+//! Instead of showing every command, the statistics collect the number and
+//! size of the commands and report them once per interval. When decoding a
+//! capture, the packets are accounted for as well: how many have been sent
+//! reliably, how many reliable packets have been retransmitted and how many
+//! commands had to be split into multiple packets.
+//!
+//! A connection doesn't expose its packets, so the proxy can only report the
+//! command statistics.
+use log::info;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use luanti_protocol::wire::channel_id::ChannelId;
+use luanti_protocol::wire::sequence_number::WrappingSequenceNumber;
+
+/// Statistics being shared by all connections of the proxy
+pub(crate) type SharedStats = Arc<Mutex<Stats>>;
+
+/// Identifies a reliable packet
+type ReliableKey = (SocketAddr, SocketAddr, ChannelId, WrappingSequenceNumber);
+
+/// Number of reliable packets being remembered for detecting retransmissions
+///
+/// This is less than half the sequence number space, so a packet has been forgotten before its
+/// sequence number wraps around.
+const SEEN_LIMIT: usize = 0x4000;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct CommandStats {
+    count: u64,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Stats {
+    interval: Duration,
+    /// start of the current interval; `None` until the first event has been recorded
+    interval_start: Option<Duration>,
+    commands: BTreeMap<&'static str, CommandStats>,
+    packets: u64,
+    reliable_packets: u64,
+    retransmitted_packets: u64,
+    split_chunks: u64,
+    split_commands: u64,
+    /// reliable packets which have been seen recently
+    seen: HashSet<ReliableKey>,
+    /// the packets of `seen` in the order they have been seen
+    seen_order: VecDeque<ReliableKey>,
+}
+
+impl Stats {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            interval_start: None,
+            commands: BTreeMap::new(),
+            packets: 0,
+            reliable_packets: 0,
+            retransmitted_packets: 0,
+            split_chunks: 0,
+            split_commands: 0,
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn shared(interval: Duration) -> SharedStats {
+        Arc::new(Mutex::new(Self::new(interval)))
+    }
+
+    /// Locks shared statistics; statistics are still useful if another thread panicked.
+    pub(crate) fn lock(stats: &SharedStats) -> MutexGuard<'_, Self> {
+        stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Reports and resets the statistics if the given time is past the current interval.
+    ///
+    /// `now` may be the time of day or the timestamp of a captured packet.
+    pub(crate) fn advance(&mut self, now: Duration) {
+        let start = *self.interval_start.get_or_insert(now);
+        if now.saturating_sub(start) >= self.interval {
+            self.report();
+            self.interval_start = Some(now);
+        }
+    }
+
+    pub(crate) fn record_command(&mut self, name: &'static str, bytes: usize) {
+        let stats = self.commands.entry(name).or_default();
+        stats.count += 1;
+        stats.bytes += u64::try_from(bytes).unwrap_or(u64::MAX);
+    }
+
+    /// Records a packet of a capture.
+    pub(crate) fn record_packet(
+        &mut self,
+        source: SocketAddr,
+        destination: SocketAddr,
+        channel: ChannelId,
+        reliable_seqnum: Option<WrappingSequenceNumber>,
+    ) {
+        self.packets += 1;
+        let Some(seqnum) = reliable_seqnum else {
+            return;
+        };
+        self.reliable_packets += 1;
+        let key = (source, destination, channel, seqnum);
+        if !self.seen.insert(key) {
+            self.retransmitted_packets += 1;
+            return;
+        }
+        self.seen_order.push_back(key);
+        if self.seen_order.len() > SEEN_LIMIT {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+    }
+
+    /// Records a chunk of a split command; `completed` is `true` for the chunk completing it.
+    pub(crate) fn record_split_chunk(&mut self, completed: bool) {
+        self.split_chunks += 1;
+        if completed {
+            self.split_commands += 1;
+        }
+    }
+
+    /// Logs the statistics of the current interval and starts a new one.
+    pub(crate) fn report(&mut self) {
+        let (count, bytes) = self
+            .commands
+            .values()
+            .fold((0, 0), |(count, bytes), stats| {
+                (count + stats.count, bytes + stats.bytes)
+            });
+        info!(
+            "[stats] {count} commands, {bytes} bytes within {interval:?}",
+            interval = self.interval
+        );
+        if self.packets > 0 {
+            #[expect(
+                clippy::cast_precision_loss,
+                reason = "the percentage doesn't need to be exact"
+            )]
+            let retransmit_rate = if self.reliable_packets == 0 {
+                0.0
+            } else {
+                100.0 * self.retransmitted_packets as f64 / self.reliable_packets as f64
+            };
+            info!(
+                "[stats] {packets} packets, {reliable} reliable, {retransmitted} retransmitted ({retransmit_rate:.1}%), {chunks} split chunks forming {splits} commands",
+                packets = self.packets,
+                reliable = self.reliable_packets,
+                retransmitted = self.retransmitted_packets,
+                chunks = self.split_chunks,
+                splits = self.split_commands,
+            );
+        }
+
+        let mut by_volume: Vec<_> = self.commands.iter().collect();
+        by_volume.sort_by_key(|(_, stats)| Reverse(stats.bytes));
+        for (name, stats) in by_volume {
+            info!(
+                "[stats]   {name:<24} {count:>8} commands {bytes:>10} bytes",
+                count = stats.count,
+                bytes = stats.bytes
+            );
+        }
+
+        self.commands.clear();
+        self.packets = 0;
+        self.reliable_packets = 0;
+        self.retransmitted_packets = 0;
+        self.split_chunks = 0;
+        self.split_commands = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use luanti_protocol::wire::channel_id::ChannelId;
+    use luanti_protocol::wire::sequence_number::WrappingSequenceNumber;
+
+    use super::SEEN_LIMIT;
+    use super::Stats;
+
+    const SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 30000);
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 51000);
+
+    fn record_reliable(stats: &mut Stats, seqnum: WrappingSequenceNumber) {
+        stats.record_packet(SERVER, CLIENT, ChannelId::Default, Some(seqnum));
+    }
+
+    #[test]
+    fn retransmissions_are_counted() {
+        let mut stats = Stats::new(Duration::from_secs(1));
+        let seqnum = WrappingSequenceNumber::from(0xffdc);
+        record_reliable(&mut stats, seqnum);
+        record_reliable(&mut stats, seqnum + 1);
+        record_reliable(&mut stats, seqnum);
+        // the same sequence number in the other direction isn't a retransmission
+        stats.record_packet(CLIENT, SERVER, ChannelId::Default, Some(seqnum));
+        stats.record_packet(SERVER, CLIENT, ChannelId::Default, None);
+
+        assert_eq!(stats.packets, 5);
+        assert_eq!(stats.reliable_packets, 4);
+        assert_eq!(stats.retransmitted_packets, 1);
+    }
+
+    #[test]
+    fn seen_packets_are_bounded() {
+        let mut stats = Stats::new(Duration::from_secs(1));
+        let first = WrappingSequenceNumber::from(0xffdc);
+        let mut seqnum = first;
+        for _ in 0..0x1_0000 {
+            record_reliable(&mut stats, seqnum);
+            seqnum = seqnum + 1;
+        }
+        assert_eq!(stats.seen.len(), SEEN_LIMIT);
+        assert_eq!(stats.seen_order.len(), SEEN_LIMIT);
+        // sequence numbers have wrapped around without being mistaken for retransmissions
+        assert_eq!(seqnum, first);
+        record_reliable(&mut stats, seqnum);
+        assert_eq!(stats.retransmitted_packets, 0);
+    }
+
+    #[test]
+    fn intervals_reset_the_counters() {
+        let mut stats = Stats::new(Duration::from_secs(10));
+        stats.advance(Duration::from_secs(100));
+        stats.record_command("Blockdata", 1000);
+        stats.record_command("Blockdata", 500);
+        stats.record_command("Hello", 10);
+        stats.record_split_chunk(false);
+        stats.record_split_chunk(true);
+
+        stats.advance(Duration::from_secs(105));
+        let blockdata = stats.commands.get("Blockdata").copied().unwrap_or_default();
+        assert_eq!((blockdata.count, blockdata.bytes), (2, 1500));
+        assert_eq!((stats.split_chunks, stats.split_commands), (2, 1));
+
+        stats.advance(Duration::from_secs(110));
+        assert!(stats.commands.is_empty());
+        assert_eq!((stats.split_chunks, stats.split_commands), (0, 0));
+        assert_eq!(stats.interval_start, Some(Duration::from_secs(110)));
+    }
+}